@@ -0,0 +1,67 @@
+//! Runs the standardized workloads against any connector.
+//!
+//! The connector is selected through environment variables:
+//! - `MEMFLOW_BENCH_CONNECTOR`: name of the connector, defaults to `dummy`
+//! - `MEMFLOW_BENCH_CONNECTOR_ARGS`: connector arguments in the `target:args` format,
+//!   defaults to a 64 mb dummy connector
+//! - `MEMFLOW_BENCH_OS`: name of the os plugin used for the translation workload,
+//!   defaults to `dummy`
+extern crate memflow_bench;
+use memflow_bench::{util, workload};
+
+use criterion::*;
+
+use memflow::prelude::v1::*;
+
+fn connector_name() -> String {
+    std::env::var("MEMFLOW_BENCH_CONNECTOR").unwrap_or_else(|_| "dummy".into())
+}
+
+fn os_name() -> String {
+    std::env::var("MEMFLOW_BENCH_OS").unwrap_or_else(|_| "dummy".into())
+}
+
+fn create_connector(middleware_args: ConnectorMiddlewareArgs) -> Result<impl PhysicalMemory> {
+    let mut args: ConnectorArgs = std::env::var("MEMFLOW_BENCH_CONNECTOR_ARGS")
+        .unwrap_or_else(|_| ":size=64m".into())
+        .parse()?;
+    args.middleware_args = middleware_args;
+
+    // this workaround is to prevent loaded libraries
+    // from spitting out to much log information and skewing benchmarks
+    let filter = log::max_level();
+    log::set_max_level(log::Level::Error.to_level_filter());
+
+    let result = Inventory::scan().create_connector(&connector_name(), None, Some(&args));
+
+    log::set_max_level(filter);
+    result
+}
+
+fn initialize_virt_ctx(cache_size: usize, use_tlb: bool) -> Result<OsInstanceArcBox<'static>> {
+    let os = os_name();
+    // the dummy os brings its own memory
+    let connector = if os == "dummy" {
+        String::new()
+    } else {
+        connector_name()
+    };
+    util::build_os(&connector, cache_size, &os, use_tlb)
+}
+
+fn workload_group(c: &mut Criterion) {
+    let name = connector_name();
+    workload::seq_read(c, &name, &create_connector);
+    workload::random_read(c, &name, &create_connector);
+    workload::translation_walk(c, &name, &initialize_virt_ctx);
+}
+
+criterion_group! {
+    name = workloads;
+    config = Criterion::default()
+        .warm_up_time(std::time::Duration::from_millis(1000))
+        .measurement_time(std::time::Duration::from_millis(5000));
+    targets = workload_group
+}
+
+criterion_main!(workloads);
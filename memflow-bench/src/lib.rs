@@ -2,3 +2,4 @@ pub mod phys;
 pub mod util;
 pub mod vat;
 pub mod virt;
+pub mod workload;
@@ -0,0 +1,231 @@
+//! Standardized workloads for comparing connectors and middleware configurations.
+//!
+//! Unlike the other benchmarks of this crate, all access patterns of these workloads are derived
+//! from a fixed seed. Every connector and every middleware configuration performs exactly the
+//! same accesses, so results of different hardware setups or middleware stacks can be compared
+//! directly.
+//!
+//! The following workloads are provided:
+//! - `seq_read`: sequential reads of increasing block sizes through physical memory
+//! - `random_read`: batches of random 8 byte reads scattered over physical memory
+//! - `translation_walk`: batches of 8 byte reads on distinct pages of a process module, so
+//!   nearly every read needs its own virtual address translation
+
+use criterion::*;
+
+use memflow::prelude::v1::*;
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng as CurRng;
+
+use std::convert::TryInto;
+
+/// Seed all access patterns are derived from.
+pub const WORKLOAD_SEED: u64 = 0x6d65_6d66_6c6f_7762;
+
+/// Upper bound of the physical memory range the physical workloads access.
+pub const MAX_PHYS_RANGE: umem = size::mb(64) as umem;
+
+/// Number of distinct batches cycled through by the random workloads.
+const ROUNDS: usize = 64;
+
+/// Middleware configurations the physical workloads are run with.
+pub fn middleware_configs() -> Vec<(&'static str, ConnectorMiddlewareArgs)> {
+    vec![
+        ("plain", ConnectorMiddlewareArgs::new()),
+        (
+            "cache",
+            ConnectorMiddlewareArgs::new().cache_size(size::mb(2)),
+        ),
+        (
+            "cache16m",
+            ConnectorMiddlewareArgs::new().cache_size(size::mb(16)),
+        ),
+    ]
+}
+
+/// Returns the physical range accessed by the physical workloads on `mem`.
+fn phys_range(mem: &impl PhysicalMemory) -> (Address, Address) {
+    let end = std::cmp::min(mem.metadata().max_address.to_umem(), MAX_PHYS_RANGE);
+    (Address::null(), Address::from(end))
+}
+
+/// Generates `rounds` batches of `batch` addresses of 8 byte reads in the range.
+///
+/// If `page_size` is not zero every address of a batch lies in a different page.
+fn random_batches(
+    (start, end): (Address, Address),
+    rounds: usize,
+    batch: usize,
+    page_size: usize,
+) -> Vec<Vec<Address>> {
+    let mut rng = CurRng::seed_from_u64(WORKLOAD_SEED);
+    let len = (end - start) as umem - 8;
+
+    (0..rounds)
+        .map(|_| {
+            let mut addrs: Vec<Address> = vec![];
+            while addrs.len() < batch {
+                let addr = start + rng.gen_range(0..len);
+                let distinct = page_size == 0
+                    || !addrs
+                        .iter()
+                        .any(|a| a.as_page_aligned(page_size) == addr.as_page_aligned(page_size));
+                if distinct || addrs.len() as umem * page_size as umem >= len {
+                    addrs.push(addr);
+                }
+            }
+            addrs
+        })
+        .collect()
+}
+
+fn read_batches(bench: &mut Bencher, mem: &mut impl MemoryView, batches: &[Vec<Address>]) {
+    let mut bufs = vec![[0u8; 8]; batches[0].len()];
+    let mut round = 0;
+
+    bench.iter(|| {
+        let mut reads = batches[round]
+            .iter()
+            .zip(bufs.iter_mut())
+            .map(|(&addr, buf)| CTup2(addr, buf.as_mut().into()))
+            .collect::<Vec<_>>();
+        let _ = black_box(mem.read_raw_list(&mut reads));
+        round = (round + 1) % batches.len();
+    });
+}
+
+fn seq_read_params<T: PhysicalMemory>(
+    group: &mut BenchmarkGroup<'_, measurement::WallTime>,
+    func_name: &str,
+    mut mem: T,
+) {
+    let (start, end) = phys_range(&mem);
+    let len = (end - start) as umem;
+
+    for &block in [0x1000, 0x10000, 0x100000].iter() {
+        group.throughput(Throughput::Bytes(block));
+        group.bench_with_input(BenchmarkId::new(func_name, block), &block, |b, &block| {
+            let mut buf = vec![0u8; block.try_into().unwrap()];
+            let mut offset = 0;
+            let mut view = mem.phys_view();
+
+            b.iter(|| {
+                if offset + block > len {
+                    offset = 0;
+                }
+                let _ = black_box(view.read_raw_into(start + offset, &mut buf));
+                offset += block;
+            });
+        });
+    }
+}
+
+fn random_read_params<T: PhysicalMemory>(
+    group: &mut BenchmarkGroup<'_, measurement::WallTime>,
+    func_name: &str,
+    mut mem: T,
+) {
+    let range = phys_range(&mem);
+
+    for &batch in [1, 64, 1024].iter() {
+        let batches = random_batches(range, ROUNDS, batch, 0);
+
+        group.throughput(Throughput::Elements(batch as u64));
+        group.bench_with_input(BenchmarkId::new(func_name, batch), &batch, |b, _| {
+            read_batches(b, &mut mem.phys_view(), &batches)
+        });
+    }
+}
+
+fn translation_walk_params(
+    group: &mut BenchmarkGroup<'_, measurement::WallTime>,
+    func_name: &str,
+    os: &mut OsInstanceArcBox<'static>,
+) {
+    let (mut process, module) = crate::util::find_proc(os).unwrap();
+    let range = (module.base, module.base + module.size);
+    let page_size = process.info().proc_arch.into_obj().page_size();
+
+    for &batch in [1, 16, 64].iter() {
+        let batches = random_batches(range, ROUNDS, batch, page_size);
+
+        group.throughput(Throughput::Elements(batch as u64));
+        group.bench_with_input(BenchmarkId::new(func_name, batch), &batch, |b, _| {
+            read_batches(b, &mut process, &batches)
+        });
+    }
+}
+
+/// Runs the sequential read workload on the connector with all middleware configurations.
+pub fn seq_read<T: PhysicalMemory>(
+    c: &mut Criterion,
+    backend_name: &str,
+    initialize_ctx: &dyn Fn(ConnectorMiddlewareArgs) -> Result<T>,
+) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+
+    let group_name = format!("{backend_name}_workload_seq_read");
+
+    let mut group = c.benchmark_group(group_name.clone());
+    group.plot_config(plot_config);
+
+    for (config, args) in middleware_configs() {
+        seq_read_params(
+            &mut group,
+            &format!("{group_name}_{config}"),
+            initialize_ctx(args).unwrap(),
+        );
+    }
+}
+
+/// Runs the random 8 byte read workload on the connector with all middleware configurations.
+pub fn random_read<T: PhysicalMemory>(
+    c: &mut Criterion,
+    backend_name: &str,
+    initialize_ctx: &dyn Fn(ConnectorMiddlewareArgs) -> Result<T>,
+) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+
+    let group_name = format!("{backend_name}_workload_random_read");
+
+    let mut group = c.benchmark_group(group_name.clone());
+    group.plot_config(plot_config);
+
+    for (config, args) in middleware_configs() {
+        random_read_params(
+            &mut group,
+            &format!("{group_name}_{config}"),
+            initialize_ctx(args).unwrap(),
+        );
+    }
+}
+
+/// Runs the translation heavy workload with and without page and translation caches.
+pub fn translation_walk(
+    c: &mut Criterion,
+    backend_name: &str,
+    initialize_ctx: &dyn Fn(usize, bool) -> Result<OsInstanceArcBox<'static>>,
+) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+
+    let group_name = format!("{backend_name}_workload_translation_walk");
+
+    let mut group = c.benchmark_group(group_name.clone());
+    group.plot_config(plot_config);
+
+    for &(config, cache_size, use_tlb) in [
+        ("nocache", 0, false),
+        ("tlb_nocache", 0, true),
+        ("cache", size::mb(2), false),
+        ("tlb_cache", size::mb(2), true),
+    ]
+    .iter()
+    {
+        translation_walk_params(
+            &mut group,
+            &format!("{group_name}_{config}"),
+            &mut initialize_ctx(cache_size, use_tlb).unwrap(),
+        );
+    }
+}
@@ -249,6 +249,64 @@ enum LevelFilter
 typedef uintptr_t LevelFilter;
 #endif // __cplusplus
 
+/**
+ * Registers of a virtual cpu.
+ *
+ * Registers that do not exist on the architecture of the target are rejected by the connector.
+ */
+enum CpuRegister
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+    CpuRegister_Rax,
+    CpuRegister_Rbx,
+    CpuRegister_Rcx,
+    CpuRegister_Rdx,
+    CpuRegister_Rsi,
+    CpuRegister_Rdi,
+    CpuRegister_Rbp,
+    CpuRegister_Rsp,
+    CpuRegister_R8,
+    CpuRegister_R9,
+    CpuRegister_R10,
+    CpuRegister_R11,
+    CpuRegister_R12,
+    CpuRegister_R13,
+    CpuRegister_R14,
+    CpuRegister_R15,
+    CpuRegister_Rip,
+    CpuRegister_Rflags,
+    CpuRegister_Cr0,
+    CpuRegister_Cr2,
+    CpuRegister_Cr3,
+    CpuRegister_Cr4,
+    CpuRegister_Cr8,
+    CpuRegister_Efer,
+    CpuRegister_FsBase,
+    CpuRegister_GsBase,
+    CpuRegister_KernelGsBase,
+    /**
+     * `IA32_RTIT_CTL`, the control register of Intel Processor Trace
+     */
+    CpuRegister_RtitCtl,
+    /**
+     * `IA32_RTIT_STATUS`
+     */
+    CpuRegister_RtitStatus,
+    /**
+     * `IA32_RTIT_OUTPUT_BASE`, the physical base of the trace output
+     */
+    CpuRegister_RtitOutputBase,
+    /**
+     * `IA32_RTIT_OUTPUT_MASK_PTRS`, the current position in the trace output
+     */
+    CpuRegister_RtitOutputMaskPtrs,
+};
+#ifndef __cplusplus
+typedef uint32_t CpuRegister;
+#endif // __cplusplus
+
 typedef struct ArchitectureObj ArchitectureObj;
 
 /**
@@ -347,7 +405,7 @@ typedef umem Address;
 /**
  * Describes the type of a page using a bitflag.
  */
-typedef uint8_t PageType;
+typedef uint16_t PageType;
 /**
  * The page explicitly has no flags.
  */
@@ -372,6 +430,23 @@ typedef uint8_t PageType;
  * The page is not executable.
  */
 #define PageType_NOEXEC 16
+/**
+ * The page is owned by a secure kernel (e.g. VTL1 under Windows VBS) and its contents
+ * can not be accessed from the normal world.
+ */
+#define PageType_SECURE 32
+/**
+ * The page is accessible from user mode.
+ */
+#define PageType_USER 64
+/**
+ * The page is only accessible from supervisor (kernel) mode.
+ */
+#define PageType_SUPERVISOR 128
+/**
+ * The page is executable.
+ */
+#define PageType_EXECUTABLE 256
 
 /**
  * This type represents a wrapper over a [address](address/index.html)
@@ -646,6 +721,17 @@ typedef struct PhysicalMemoryMetadata {
     umem real_size;
     bool readonly;
     uint32_t ideal_batch_size;
+    /**
+     * Maximum number of bytes a single read may request, `u32::MAX` if reads are unlimited
+     */
+    uint32_t max_read_size;
+    /**
+     * Alignment required for the address and size of reads, `1` if reads may be unaligned
+     *
+     * See [`ConstrainedPhysicalMemory`](crate::mem::ConstrainedPhysicalMemory) for how these
+     * constraints are enforced.
+     */
+    uint32_t read_alignment;
 } PhysicalMemoryMetadata;
 
 typedef struct PhysicalMemoryMapping {
@@ -996,6 +1082,9 @@ typedef struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_
 typedef struct CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void {
     void (*pause)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void *cont);
     void (*resume)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void *cont);
+    uintptr_t (*cpu_count)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void *cont);
+    int32_t (*read_register)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void *cont, uintptr_t cpu, CpuRegister reg, uint64_t *ok_out);
+    int32_t (*write_register)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void *cont, uintptr_t cpu, CpuRegister reg, uint64_t value);
 } CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void;
 /**
  * Simple CGlue trait object.
@@ -1036,6 +1125,9 @@ typedef struct CloneVtbl_IntoCpuStateContainer_CBox_c_void_____CArc_c_void {
 typedef struct CpuStateVtbl_IntoCpuStateContainer_CBox_c_void_____CArc_c_void {
     void (*pause)(struct IntoCpuStateContainer_CBox_c_void_____CArc_c_void *cont);
     void (*resume)(struct IntoCpuStateContainer_CBox_c_void_____CArc_c_void *cont);
+    uintptr_t (*cpu_count)(struct IntoCpuStateContainer_CBox_c_void_____CArc_c_void *cont);
+    int32_t (*read_register)(struct IntoCpuStateContainer_CBox_c_void_____CArc_c_void *cont, uintptr_t cpu, CpuRegister reg, uint64_t *ok_out);
+    int32_t (*write_register)(struct IntoCpuStateContainer_CBox_c_void_____CArc_c_void *cont, uintptr_t cpu, CpuRegister reg, uint64_t value);
 } CpuStateVtbl_IntoCpuStateContainer_CBox_c_void_____CArc_c_void;
 /**
  * Trait group potentially implementing `:: cglue :: ext :: core :: clone :: Clone < > + CpuState < >` traits.
@@ -1569,6 +1661,124 @@ typedef struct COption_Address {
     };
 } COption_Address;
 
+/**
+ * Thread executing on a single CPU at the time of the query
+ */
+typedef struct CpuThreadInfo {
+    /**
+     * Number of the CPU
+     */
+    uint32_t cpu;
+    /**
+     * Address of the thread structure (e.g. `ETHREAD` on Windows, `task_struct` on Linux)
+     */
+    Address thread;
+    /**
+     * ID of the thread
+     */
+    uint32_t tid;
+    /**
+     * Address of the owning process structure, usable with [`Os::process_info_by_address`]
+     *
+     * This is [`Address::INVALID`] if the CPU is idle and no process owns the thread.
+     */
+    Address process;
+    /**
+     * ID of the owning process
+     */
+    Pid pid;
+} CpuThreadInfo;
+
+typedef struct Callback_c_void__CpuThreadInfo {
+    void *context;
+    bool (*func)(void*, struct CpuThreadInfo);
+} Callback_c_void__CpuThreadInfo;
+
+typedef struct Callback_c_void__CpuThreadInfo OpaqueCallback_CpuThreadInfo;
+
+typedef OpaqueCallback_CpuThreadInfo CpuThreadInfoCallback;
+
+/**
+ * Time information of the target
+ */
+typedef struct OsTime {
+    /**
+     * Wall-clock time in nanoseconds since the unix epoch (UTC)
+     */
+    uint64_t time_ns;
+    /**
+     * Time since boot in nanoseconds
+     */
+    uint64_t uptime_ns;
+    /**
+     * Offset of the local time zone to UTC in seconds, positive east of UTC
+     */
+    int32_t utc_offset;
+} OsTime;
+
+/**
+ * Security features of the OS.
+ *
+ * Features that are not set are either disabled or could not be determined by the OS layer.
+ */
+typedef uint32_t OsSecurityFeatures;
+/**
+ * No security features were detected.
+ */
+#define OsSecurityFeatures_NONE 0
+/**
+ * The system was booted with secure boot.
+ */
+#define OsSecurityFeatures_SECURE_BOOT 1
+/**
+ * Virtualization based security is running, parts of memory are owned by a secure kernel.
+ */
+#define OsSecurityFeatures_VBS 2
+/**
+ * Hypervisor protected code integrity (memory integrity) is enforced.
+ */
+#define OsSecurityFeatures_HVCI 4
+/**
+ * The kernel is loaded at a randomized address.
+ */
+#define OsSecurityFeatures_KASLR 8
+/**
+ * Hotpatches can be applied to the running kernel (e.g. livepatch on Linux).
+ */
+#define OsSecurityFeatures_HOTPATCH 16
+
+/**
+ * Detailed version information about the OS
+ */
+typedef struct OsVersion {
+    /**
+     * Major version (e.g. 10 on Windows 10/11, 6 on Linux 6.1)
+     */
+    uint32_t major;
+    /**
+     * Minor version
+     */
+    uint32_t minor;
+    /**
+     * Build number (e.g. 22621 on Windows), or the patch level on Linux
+     */
+    uint32_t build;
+    /**
+     * Update revision of the build (e.g. the UBR on Windows)
+     *
+     * This changes with every cumulative update or hotpatch that is installed.
+     */
+    uint32_t revision;
+    /**
+     * Edition or distribution name (e.g. `Professional`, `ServerDatacenter`), may be empty
+     */
+    ReprCString edition;
+    /**
+     * Security features detected as enabled
+     */
+    OsSecurityFeatures security;
+} OsVersion;
+
 /**
  * Information block about OS
  *
@@ -1589,6 +1799,10 @@ typedef struct OsInfo {
      * System architecture
      */
     struct ArchitectureIdent arch;
+    /**
+     * Detailed version information, all fields are zero/empty if unknown
+     */
+    struct OsVersion version;
 } OsInfo;
 
 /**
@@ -1622,6 +1836,8 @@ typedef struct OsVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void {
     int32_t (*module_import_by_name)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ImportInfo *ok_out);
     int32_t (*module_export_by_name)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ExportInfo *ok_out);
     int32_t (*module_section_by_name)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct SectionInfo *ok_out);
+    int32_t (*current_thread_list_callback)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, CpuThreadInfoCallback callback);
+    int32_t (*system_time)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct OsTime *ok_out);
     const struct OsInfo *(*info)(const struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } OsVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void;
 
@@ -1634,6 +1850,7 @@ typedef struct MemoryViewVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void {
     int32_t (*read_raw_iter)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, ReadRawMemOps data);
     int32_t (*write_raw_iter)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, WriteRawMemOps data);
     struct MemoryViewMetadata (*metadata)(const struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
+    void (*prefetch)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, umem len);
     int32_t (*read_iter)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CIterator_ReadData inp, ReadCallback *out, ReadCallback *out_fail);
     int32_t (*read_raw_list)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceMut_ReadData data);
     int32_t (*read_raw_into)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, struct CSliceMut_u8 out);
@@ -1794,6 +2011,50 @@ typedef struct OsKeyboardVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void {
     int32_t (*into_keyboard)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void cont, struct IntoKeyboard_CBox_c_void_____CArc_c_void *ok_out);
 } OsKeyboardVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void;
 
+/**
+ * FFI-safe 2 element tuple.
+ */
+typedef struct CTup2_PhysicalAddress__umem {
+    struct PhysicalAddress _0;
+    umem _1;
+} CTup2_PhysicalAddress__umem;
+
+/**
+ * Wrapper around const slices.
+ *
+ * This is meant as a safe type to pass across the FFI boundary with similar semantics as regular
+ * slice. However, not all functionality is present, use the slice conversion functions.
+ *
+ * # Examples
+ *
+ * Simple conversion:
+ *
+ * ```
+ * use cglue::slice::CSliceRef;
+ *
+ * let arr = [0, 5, 3, 2];
+ *
+ * let cslice = CSliceRef::from(&arr[..]);
+ *
+ * let slice = cslice.as_slice();
+ *
+ * assert_eq!(&arr, slice);
+ * ```
+ */
+typedef struct CSliceRef_CTup2_PhysicalAddress__umem {
+    const struct CTup2_PhysicalAddress__umem *data;
+    uintptr_t len;
+} CSliceRef_CTup2_PhysicalAddress__umem;
+
+typedef struct Callback_c_void__PhysicalMemoryMapping {
+    void *context;
+    bool (*func)(void*, struct PhysicalMemoryMapping);
+} Callback_c_void__PhysicalMemoryMapping;
+
+typedef struct Callback_c_void__PhysicalMemoryMapping OpaqueCallback_PhysicalMemoryMapping;
+
+typedef OpaqueCallback_PhysicalMemoryMapping PhysicalMemoryMappingCallback;
+
 /**
  * CGlue vtable for trait PhysicalMemory.
  *
@@ -1804,6 +2065,10 @@ typedef struct PhysicalMemoryVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_voi
     int32_t (*phys_write_raw_iter)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalWriteMemOps data);
     struct PhysicalMemoryMetadata (*metadata)(const struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*set_mem_map)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalMemoryMapping _mem_map);
+    void (*phys_mem_map)(const struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalMemoryMappingCallback out);
+    void (*phys_prefetch)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_CTup2_PhysicalAddress__umem ranges);
+    int32_t (*phys_cas_raw)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct PhysicalAddress addr, struct CSliceRef_u8 expected, struct CSliceRef_u8 new_, bool *ok_out);
+    int32_t (*health_check)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*into_phys_view)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*phys_view)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } PhysicalMemoryVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void;
@@ -1878,6 +2143,7 @@ typedef struct MemoryViewVtbl_ProcessInstanceContainer_CBox_c_void_____CArc_c_vo
     int32_t (*read_raw_iter)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, ReadRawMemOps data);
     int32_t (*write_raw_iter)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, WriteRawMemOps data);
     struct MemoryViewMetadata (*metadata)(const struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont);
+    void (*prefetch)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, umem len);
     int32_t (*read_iter)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CIterator_ReadData inp, ReadCallback *out, ReadCallback *out_fail);
     int32_t (*read_raw_list)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceMut_ReadData data);
     int32_t (*read_raw_into)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, struct CSliceMut_u8 out);
@@ -1886,6 +2152,26 @@ typedef struct MemoryViewVtbl_ProcessInstanceContainer_CBox_c_void_____CArc_c_vo
     int32_t (*write_raw)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, struct CSliceRef_u8 data);
 } MemoryViewVtbl_ProcessInstanceContainer_CBox_c_void_____CArc_c_void;
 
+/**
+ * Location of the environment variables of a process
+ *
+ * The block consists of null terminated `NAME=VALUE` entries, followed by an empty entry.
+ */
+typedef struct EnvironmentBlock {
+    /**
+     * Address of the first entry
+     */
+    Address address;
+    /**
+     * Size of the block in bytes
+     */
+    umem size;
+    /**
+     * True if the entries are encoded as UTF-16, false for UTF-8
+     */
+    bool wide;
+} EnvironmentBlock;
+
 /**
  * CGlue vtable for trait Process.
  *
@@ -1906,6 +2192,7 @@ typedef struct ProcessVtbl_ProcessInstanceContainer_CBox_c_void_____CArc_c_void
     int32_t (*module_import_by_name)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ImportInfo *ok_out);
     int32_t (*module_export_by_name)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ExportInfo *ok_out);
     int32_t (*module_section_by_name)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct SectionInfo *ok_out);
+    int32_t (*environment_block)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct EnvironmentBlock *ok_out);
     const struct ProcessInfo *(*info)(const struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*mapped_mem_range)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, imem gap_size, Address start, Address end, MemoryRangeCallback out);
     void (*mapped_mem)(struct ProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, imem gap_size, MemoryRangeCallback out);
@@ -1985,6 +2272,7 @@ typedef struct MemoryViewVtbl_IntoProcessInstanceContainer_CBox_c_void_____CArc_
     int32_t (*read_raw_iter)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, ReadRawMemOps data);
     int32_t (*write_raw_iter)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, WriteRawMemOps data);
     struct MemoryViewMetadata (*metadata)(const struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont);
+    void (*prefetch)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, umem len);
     int32_t (*read_iter)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CIterator_ReadData inp, ReadCallback *out, ReadCallback *out_fail);
     int32_t (*read_raw_list)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceMut_ReadData data);
     int32_t (*read_raw_into)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, Address addr, struct CSliceMut_u8 out);
@@ -2013,6 +2301,7 @@ typedef struct ProcessVtbl_IntoProcessInstanceContainer_CBox_c_void_____CArc_c_v
     int32_t (*module_import_by_name)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ImportInfo *ok_out);
     int32_t (*module_export_by_name)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct ExportInfo *ok_out);
     int32_t (*module_section_by_name)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, const struct ModuleInfo *info, struct CSliceRef_u8 name, struct SectionInfo *ok_out);
+    int32_t (*environment_block)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct EnvironmentBlock *ok_out);
     const struct ProcessInfo *(*info)(const struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*mapped_mem_range)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, imem gap_size, Address start, Address end, MemoryRangeCallback out);
     void (*mapped_mem)(struct IntoProcessInstanceContainer_CBox_c_void_____CArc_c_void *cont, imem gap_size, MemoryRangeCallback out);
@@ -2099,6 +2388,7 @@ typedef struct MemoryViewVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____
     int32_t (*read_raw_iter)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, ReadRawMemOps data);
     int32_t (*write_raw_iter)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, WriteRawMemOps data);
     struct MemoryViewMetadata (*metadata)(const struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont);
+    void (*prefetch)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, Address addr, umem len);
     int32_t (*read_iter)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, struct CIterator_ReadData inp, ReadCallback *out, ReadCallback *out_fail);
     int32_t (*read_raw_list)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, struct CSliceMut_ReadData data);
     int32_t (*read_raw_into)(struct CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void *cont, Address addr, struct CSliceMut_u8 out);
@@ -2131,6 +2421,10 @@ typedef struct PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CAr
     int32_t (*phys_write_raw_iter)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalWriteMemOps data);
     struct PhysicalMemoryMetadata (*metadata)(const struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*set_mem_map)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalMemoryMapping _mem_map);
+    void (*phys_mem_map)(const struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalMemoryMappingCallback out);
+    void (*phys_prefetch)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_CTup2_PhysicalAddress__umem ranges);
+    int32_t (*phys_cas_raw)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct PhysicalAddress addr, struct CSliceRef_u8 expected, struct CSliceRef_u8 new_, bool *ok_out);
+    int32_t (*health_check)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*into_phys_view)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*phys_view)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CArc_c_void;
@@ -2400,6 +2694,21 @@ static inline void mf_resume(void *self)  {
 
 }
 
+static inline uintptr_t mf_cpu_count(void *self)  {
+    uintptr_t __ret = (((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->vtbl)->cpu_count(&((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->container);
+    return __ret;
+}
+
+static inline int32_t mf_read_register(void *self, uintptr_t cpu, CpuRegister reg, uint64_t * ok_out)  {
+    int32_t __ret = (((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->vtbl)->read_register(&((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->container, cpu, reg, ok_out);
+    return __ret;
+}
+
+static inline int32_t mf_write_register(void *self, uintptr_t cpu, CpuRegister reg, uint64_t value)  {
+    int32_t __ret = (((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->vtbl)->write_register(&((struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void *)self)->container, cpu, reg, value);
+    return __ret;
+}
+
 static inline void mf_cpustate_drop(struct CGlueTraitObj_CBox_c_void_____CpuStateVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____CpuStateRetTmp_CArc_c_void______________CArc_c_void_____CpuStateRetTmp_CArc_c_void self)  {
     cont_box_drop(&self.container.instance);
     ctx_arc_drop(&self.container.context);
@@ -2453,6 +2762,11 @@ static inline struct MemoryViewMetadata mf_metadata(const void *self)  {
     return __ret;
 }
 
+static inline void mf_prefetch(void *self, Address addr, umem len)  {
+(((struct CGlueTraitObj_CBox_c_void_____MemoryViewVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void______________CArc_c_void_____MemoryViewRetTmp_CArc_c_void *)self)->vtbl)->prefetch(&((struct CGlueTraitObj_CBox_c_void_____MemoryViewVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void______________CArc_c_void_____MemoryViewRetTmp_CArc_c_void *)self)->container, addr, len);
+
+}
+
 static inline int32_t mf_read_iter(void *self, struct CIterator_ReadData inp, ReadCallback * out, ReadCallback * out_fail)  {
     int32_t __ret = (((struct CGlueTraitObj_CBox_c_void_____MemoryViewVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void______________CArc_c_void_____MemoryViewRetTmp_CArc_c_void *)self)->vtbl)->read_iter(&((struct CGlueTraitObj_CBox_c_void_____MemoryViewVtbl_CGlueObjContainer_CBox_c_void_____CArc_c_void_____MemoryViewRetTmp_CArc_c_void______________CArc_c_void_____MemoryViewRetTmp_CArc_c_void *)self)->container, inp, out, out_fail);
     return __ret;
@@ -2523,6 +2837,21 @@ static inline void mf_intocpustate_resume(void *self)  {
 
 }
 
+static inline uintptr_t mf_intocpustate_cpu_count(void *self)  {
+    uintptr_t __ret = (((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->vtbl_cpustate)->cpu_count(&((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->container);
+    return __ret;
+}
+
+static inline int32_t mf_intocpustate_read_register(void *self, uintptr_t cpu, CpuRegister reg, uint64_t * ok_out)  {
+    int32_t __ret = (((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->vtbl_cpustate)->read_register(&((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->container, cpu, reg, ok_out);
+    return __ret;
+}
+
+static inline int32_t mf_intocpustate_write_register(void *self, uintptr_t cpu, CpuRegister reg, uint64_t value)  {
+    int32_t __ret = (((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->vtbl_cpustate)->write_register(&((struct IntoCpuState_CBox_c_void_____CArc_c_void *)self)->container, cpu, reg, value);
+    return __ret;
+}
+
 static inline int32_t mf_connectorinstance_cpu_state(void *self, CpuStateBase_CBox_c_void_____CArc_c_void * ok_out)  {
     int32_t __ret = (((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_connectorcpustate)->cpu_state(&((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container, ok_out);
     return __ret;
@@ -2680,6 +3009,16 @@ static inline int32_t mf_osinstance_module_section_by_name(void *self, const str
     return __ret;
 }
 
+static inline int32_t mf_osinstance_current_thread_list_callback(void *self, CpuThreadInfoCallback callback)  {
+    int32_t __ret = (((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_os)->current_thread_list_callback(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, callback);
+    return __ret;
+}
+
+static inline int32_t mf_osinstance_system_time(void *self, struct OsTime * ok_out)  {
+    int32_t __ret = (((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_os)->system_time(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, ok_out);
+    return __ret;
+}
+
 static inline const struct OsInfo * mf_osinstance_info(const void *self)  {
     const struct OsInfo * __ret = (((const struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_os)->info(&((const struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container);
     return __ret;
@@ -2700,6 +3039,11 @@ static inline struct MemoryViewMetadata mf_osinstance_metadata(const void *self)
     return __ret;
 }
 
+static inline void mf_osinstance_prefetch(void *self, Address addr, umem len)  {
+(((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->prefetch(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, addr, len);
+
+}
+
 static inline int32_t mf_osinstance_read_iter(void *self, struct CIterator_ReadData inp, ReadCallback * out, ReadCallback * out_fail)  {
     int32_t __ret = (((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->read_iter(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, inp, out, out_fail);
     return __ret;
@@ -2784,6 +3128,26 @@ static inline void mf_osinstance_set_mem_map(void *self, struct CSliceRef_Physic
 
 }
 
+static inline void mf_osinstance_phys_mem_map(const void *self, PhysicalMemoryMappingCallback out)  {
+(((const struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_mem_map(&((const struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, out);
+
+}
+
+static inline void mf_osinstance_phys_prefetch(void *self, struct CSliceRef_CTup2_PhysicalAddress__umem ranges)  {
+(((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_prefetch(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, ranges);
+
+}
+
+static inline int32_t mf_osinstance_phys_cas_raw(void *self, struct PhysicalAddress addr, struct CSliceRef_u8 expected, struct CSliceRef_u8 new_, bool * ok_out)  {
+    int32_t __ret = (((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_cas_raw(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, addr, expected, new_, ok_out);
+    return __ret;
+}
+
+static inline int32_t mf_osinstance_health_check(void *self)  {
+    int32_t __ret = (((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->health_check(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container);
+    return __ret;
+}
+
 static inline MemoryViewBase_CBox_c_void_____CArc_c_void mf_osinstance_into_phys_view(struct OsInstance_CBox_c_void_____CArc_c_void self)  {
     CArc_c_void ___ctx = ctx_arc_clone(&self.container.context);
     MemoryViewBase_CBox_c_void_____CArc_c_void __ret = (self.vtbl_physicalmemory)->into_phys_view(self.container);
@@ -2856,6 +3220,11 @@ static inline struct MemoryViewMetadata mf_processinstance_metadata(const void *
     return __ret;
 }
 
+static inline void mf_processinstance_prefetch(void *self, Address addr, umem len)  {
+(((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->prefetch(&((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, addr, len);
+
+}
+
 static inline int32_t mf_processinstance_read_iter(void *self, struct CIterator_ReadData inp, ReadCallback * out, ReadCallback * out_fail)  {
     int32_t __ret = (((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->read_iter(&((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, inp, out, out_fail);
     return __ret;
@@ -2962,6 +3331,11 @@ static inline int32_t mf_processinstance_module_section_by_name(void *self, cons
     return __ret;
 }
 
+static inline int32_t mf_processinstance_environment_block(void *self, struct EnvironmentBlock * ok_out)  {
+    int32_t __ret = (((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_process)->environment_block(&((struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, ok_out);
+    return __ret;
+}
+
 static inline const struct ProcessInfo * mf_processinstance_info(const void *self)  {
     const struct ProcessInfo * __ret = (((const struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_process)->info(&((const struct ProcessInstance_CBox_c_void_____CArc_c_void *)self)->container);
     return __ret;
@@ -3049,6 +3423,11 @@ static inline struct MemoryViewMetadata mf_intoprocessinstance_metadata(const vo
     return __ret;
 }
 
+static inline void mf_intoprocessinstance_prefetch(void *self, Address addr, umem len)  {
+(((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->prefetch(&((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, addr, len);
+
+}
+
 static inline int32_t mf_intoprocessinstance_read_iter(void *self, struct CIterator_ReadData inp, ReadCallback * out, ReadCallback * out_fail)  {
     int32_t __ret = (((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_memoryview)->read_iter(&((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, inp, out, out_fail);
     return __ret;
@@ -3149,6 +3528,11 @@ static inline int32_t mf_intoprocessinstance_module_section_by_name(void *self,
     return __ret;
 }
 
+static inline int32_t mf_intoprocessinstance_environment_block(void *self, struct EnvironmentBlock * ok_out)  {
+    int32_t __ret = (((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_process)->environment_block(&((struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->container, ok_out);
+    return __ret;
+}
+
 static inline const struct ProcessInfo * mf_intoprocessinstance_info(const void *self)  {
     const struct ProcessInfo * __ret = (((const struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_process)->info(&((const struct IntoProcessInstance_CBox_c_void_____CArc_c_void *)self)->container);
     return __ret;
@@ -3229,6 +3613,26 @@ static inline void mf_connectorinstance_set_mem_map(void *self, struct CSliceRef
 
 }
 
+static inline void mf_connectorinstance_phys_mem_map(const void *self, PhysicalMemoryMappingCallback out)  {
+(((const struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_mem_map(&((const struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container, out);
+
+}
+
+static inline void mf_connectorinstance_phys_prefetch(void *self, struct CSliceRef_CTup2_PhysicalAddress__umem ranges)  {
+(((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_prefetch(&((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container, ranges);
+
+}
+
+static inline int32_t mf_connectorinstance_phys_cas_raw(void *self, struct PhysicalAddress addr, struct CSliceRef_u8 expected, struct CSliceRef_u8 new_, bool * ok_out)  {
+    int32_t __ret = (((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->phys_cas_raw(&((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container, addr, expected, new_, ok_out);
+    return __ret;
+}
+
+static inline int32_t mf_connectorinstance_health_check(void *self)  {
+    int32_t __ret = (((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->health_check(&((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container);
+    return __ret;
+}
+
 static inline MemoryViewBase_CBox_c_void_____CArc_c_void mf_connectorinstance_into_phys_view(struct ConnectorInstance_CBox_c_void_____CArc_c_void self)  {
     CArc_c_void ___ctx = ctx_arc_clone(&self.container.context);
     MemoryViewBase_CBox_c_void_____CArc_c_void __ret = (self.vtbl_physicalmemory)->into_phys_view(self.container);
@@ -3321,6 +3725,18 @@ static inline bool cb_count_WriteData(size_t *cnt, WriteData info) {
     return ++(*cnt);
 }
 
+static inline bool cb_collect_static_PhysicalMemoryMapping(struct CollectBase *ctx, PhysicalMemoryMapping info) {
+    return cb_collect_static_base(ctx, sizeof(PhysicalMemoryMapping), &info);
+}
+
+static inline bool cb_collect_dynamic_PhysicalMemoryMapping(struct CollectBase *ctx, PhysicalMemoryMapping info) {
+    return cb_collect_dynamic_base(ctx, sizeof(PhysicalMemoryMapping), &info);
+}
+
+static inline bool cb_count_PhysicalMemoryMapping(size_t *cnt, PhysicalMemoryMapping info) {
+    return ++(*cnt);
+}
+
 static inline bool cb_collect_static_Address(struct CollectBase *ctx, Address info) {
     return cb_collect_static_base(ctx, sizeof(Address), &info);
 }
@@ -3405,6 +3821,18 @@ static inline bool cb_count_SectionInfo(size_t *cnt, SectionInfo info) {
     return ++(*cnt);
 }
 
+static inline bool cb_collect_static_CpuThreadInfo(struct CollectBase *ctx, CpuThreadInfo info) {
+    return cb_collect_static_base(ctx, sizeof(CpuThreadInfo), &info);
+}
+
+static inline bool cb_collect_dynamic_CpuThreadInfo(struct CollectBase *ctx, CpuThreadInfo info) {
+    return cb_collect_dynamic_base(ctx, sizeof(CpuThreadInfo), &info);
+}
+
+static inline bool cb_count_CpuThreadInfo(size_t *cnt, CpuThreadInfo info) {
+    return ++(*cnt);
+}
+
 static inline bool cb_collect_static_MemoryRange(struct CollectBase *ctx, MemoryRange info) {
     return cb_collect_static_base(ctx, sizeof(MemoryRange), &info);
 }
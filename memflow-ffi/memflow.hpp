@@ -106,6 +106,57 @@ enum class LevelFilter : uintptr_t {
     LevelFilter_Trace,
 };
 
+/**
+ * Registers of a virtual cpu.
+ *
+ * Registers that do not exist on the architecture of the target are rejected by the connector.
+ */
+enum class CpuRegister : uint32_t {
+    CpuRegister_Rax,
+    CpuRegister_Rbx,
+    CpuRegister_Rcx,
+    CpuRegister_Rdx,
+    CpuRegister_Rsi,
+    CpuRegister_Rdi,
+    CpuRegister_Rbp,
+    CpuRegister_Rsp,
+    CpuRegister_R8,
+    CpuRegister_R9,
+    CpuRegister_R10,
+    CpuRegister_R11,
+    CpuRegister_R12,
+    CpuRegister_R13,
+    CpuRegister_R14,
+    CpuRegister_R15,
+    CpuRegister_Rip,
+    CpuRegister_Rflags,
+    CpuRegister_Cr0,
+    CpuRegister_Cr2,
+    CpuRegister_Cr3,
+    CpuRegister_Cr4,
+    CpuRegister_Cr8,
+    CpuRegister_Efer,
+    CpuRegister_FsBase,
+    CpuRegister_GsBase,
+    CpuRegister_KernelGsBase,
+    /**
+     * `IA32_RTIT_CTL`, the control register of Intel Processor Trace
+     */
+    CpuRegister_RtitCtl,
+    /**
+     * `IA32_RTIT_STATUS`
+     */
+    CpuRegister_RtitStatus,
+    /**
+     * `IA32_RTIT_OUTPUT_BASE`, the physical base of the trace output
+     */
+    CpuRegister_RtitOutputBase,
+    /**
+     * `IA32_RTIT_OUTPUT_MASK_PTRS`, the current position in the trace output
+     */
+    CpuRegister_RtitOutputMaskPtrs,
+};
+
 struct ArchitectureObj;
 
 
@@ -296,7 +347,7 @@ constexpr static const Address Address_INVALID = ~0;
 /**
  * Describes the type of a page using a bitflag.
  */
-using PageType = uint8_t;
+using PageType = uint16_t;
 /**
  * The page explicitly has no flags.
  */
@@ -321,6 +372,23 @@ constexpr static const PageType PageType_READ_ONLY = 8;
  * The page is not executable.
  */
 constexpr static const PageType PageType_NOEXEC = 16;
+/**
+ * The page is owned by a secure kernel (e.g. VTL1 under Windows VBS) and its contents
+ * can not be accessed from the normal world.
+ */
+constexpr static const PageType PageType_SECURE = 32;
+/**
+ * The page is accessible from user mode.
+ */
+constexpr static const PageType PageType_USER = 64;
+/**
+ * The page is only accessible from supervisor (kernel) mode.
+ */
+constexpr static const PageType PageType_SUPERVISOR = 128;
+/**
+ * The page is executable.
+ */
+constexpr static const PageType PageType_EXECUTABLE = 256;
 
 /**
  * This type represents a wrapper over a [address](address/index.html)
@@ -823,6 +891,17 @@ struct PhysicalMemoryMetadata {
     umem real_size;
     bool readonly;
     uint32_t ideal_batch_size;
+    /**
+     * Maximum number of bytes a single read may request, `u32::MAX` if reads are unlimited
+     */
+    uint32_t max_read_size;
+    /**
+     * Alignment required for the address and size of reads, `1` if reads may be unaligned
+     *
+     * See [`ConstrainedPhysicalMemory`](crate::mem::ConstrainedPhysicalMemory) for how these
+     * constraints are enforced.
+     */
+    uint32_t read_alignment;
 };
 
 struct PhysicalMemoryMapping {
@@ -959,6 +1038,7 @@ struct MemoryViewVtbl {
     int32_t (*read_raw_iter)(CGlueC *cont, ReadRawMemOps data);
     int32_t (*write_raw_iter)(CGlueC *cont, WriteRawMemOps data);
     MemoryViewMetadata (*metadata)(const CGlueC *cont);
+    void (*prefetch)(CGlueC *cont, Address addr, umem len);
     int32_t (*read_iter)(CGlueC *cont, CIterator<ReadData> inp, ReadCallback *out, ReadCallback *out_fail);
     int32_t (*read_raw_list)(CGlueC *cont, CSliceMut<ReadData> data);
     int32_t (*read_raw_into)(CGlueC *cont, Address addr, CSliceMut<uint8_t> out);
@@ -974,6 +1054,7 @@ constexpr MemoryViewVtblImpl() :
         &Impl::read_raw_iter,
         &Impl::write_raw_iter,
         &Impl::metadata,
+        &Impl::prefetch,
         &Impl::read_iter,
         &Impl::read_raw_list,
         &Impl::read_raw_into,
@@ -1003,6 +1084,8 @@ struct CGlueTraitObj {
 template<typename CGlueInst = CBox<void>, typename CGlueCtx = CArc<void>>
 using MemoryViewBase = CGlueTraitObj<CGlueInst, MemoryViewVtbl<CGlueObjContainer<CGlueInst, CGlueCtx, MemoryViewRetTmp<CGlueCtx>>>, CGlueCtx, MemoryViewRetTmp<CGlueCtx>>;
 
+using PhysicalMemoryMappingCallback = OpaqueCallback<PhysicalMemoryMapping>;
+
 /**
  * CGlue vtable for trait PhysicalMemory.
  *
@@ -1015,6 +1098,10 @@ struct PhysicalMemoryVtbl {
     int32_t (*phys_write_raw_iter)(CGlueC *cont, PhysicalWriteMemOps data);
     PhysicalMemoryMetadata (*metadata)(const CGlueC *cont);
     void (*set_mem_map)(CGlueC *cont, CSliceRef<PhysicalMemoryMapping> _mem_map);
+    void (*phys_mem_map)(const CGlueC *cont, PhysicalMemoryMappingCallback out);
+    void (*phys_prefetch)(CGlueC *cont, CSliceRef<CTup2<PhysicalAddress, umem>> ranges);
+    int32_t (*phys_cas_raw)(CGlueC *cont, PhysicalAddress addr, CSliceRef<uint8_t> expected, CSliceRef<uint8_t> new_, bool *ok_out);
+    int32_t (*health_check)(CGlueC *cont);
     MemoryViewBase<CBox<void>, Context> (*into_phys_view)(CGlueC cont);
     MemoryViewBase<CBox<void>, Context> (*phys_view)(CGlueC *cont);
 };
@@ -1027,6 +1114,10 @@ constexpr PhysicalMemoryVtblImpl() :
         &Impl::phys_write_raw_iter,
         &Impl::metadata,
         &Impl::set_mem_map,
+        &Impl::phys_mem_map,
+        &Impl::phys_prefetch,
+        &Impl::phys_cas_raw,
+        &Impl::health_check,
         &Impl::into_phys_view,
         &Impl::phys_view
     } {}
@@ -1042,6 +1133,9 @@ struct CpuStateVtbl {
     typedef typename CGlueC::Context Context;
     void (*pause)(CGlueC *cont);
     void (*resume)(CGlueC *cont);
+    uintptr_t (*cpu_count)(CGlueC *cont);
+    int32_t (*read_register)(CGlueC *cont, uintptr_t cpu, CpuRegister reg, uint64_t *ok_out);
+    int32_t (*write_register)(CGlueC *cont, uintptr_t cpu, CpuRegister reg, uint64_t value);
 };
 
 template<typename Impl>
@@ -1049,7 +1143,10 @@ struct CpuStateVtblImpl : CpuStateVtbl<typename Impl::Parent> {
 constexpr CpuStateVtblImpl() :
     CpuStateVtbl<typename Impl::Parent> {
         &Impl::pause,
-        &Impl::resume
+        &Impl::resume,
+        &Impl::cpu_count,
+        &Impl::read_register,
+        &Impl::write_register
     } {}
 };
 
@@ -1146,6 +1243,21 @@ struct IntoCpuState {
 
     }
 
+    inline uintptr_t cpu_count() noexcept {
+        uintptr_t __ret = (this->vtbl_cpustate)->cpu_count(&this->container);
+        return __ret;
+    }
+
+    inline int32_t read_register(uintptr_t cpu, CpuRegister reg, uint64_t * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_cpustate)->read_register(&this->container, cpu, reg, ok_out);
+        return __ret;
+    }
+
+    inline int32_t write_register(uintptr_t cpu, CpuRegister reg, uint64_t value) noexcept {
+        int32_t __ret = (this->vtbl_cpustate)->write_register(&this->container, cpu, reg, value);
+        return __ret;
+    }
+
 };
 
 /**
@@ -1231,6 +1343,26 @@ struct ConnectorInstance {
 
     }
 
+    inline void phys_mem_map(PhysicalMemoryMappingCallback out) const noexcept {
+    (this->vtbl_physicalmemory)->phys_mem_map(&this->container, out);
+
+    }
+
+    inline void phys_prefetch(CSliceRef<CTup2<PhysicalAddress, umem>> ranges) noexcept {
+    (this->vtbl_physicalmemory)->phys_prefetch(&this->container, ranges);
+
+    }
+
+    inline int32_t phys_cas_raw(PhysicalAddress addr, CSliceRef<uint8_t> expected, CSliceRef<uint8_t> new_, bool * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_physicalmemory)->phys_cas_raw(&this->container, addr, expected, new_, ok_out);
+        return __ret;
+    }
+
+    inline int32_t health_check() noexcept {
+        int32_t __ret = (this->vtbl_physicalmemory)->health_check(&this->container);
+        return __ret;
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl_physicalmemory)->into_phys_view(this->container);
@@ -1618,6 +1750,26 @@ using MemoryRange = CTup3<Address, umem, PageType>;
 
 using MemoryRangeCallback = OpaqueCallback<MemoryRange>;
 
+/**
+ * Location of the environment variables of a process
+ *
+ * The block consists of null terminated `NAME=VALUE` entries, followed by an empty entry.
+ */
+struct EnvironmentBlock {
+    /**
+     * Address of the first entry
+     */
+    Address address;
+    /**
+     * Size of the block in bytes
+     */
+    umem size;
+    /**
+     * True if the entries are encoded as UTF-16, false for UTF-8
+     */
+    bool wide;
+};
+
 /**
  * CGlue vtable for trait Process.
  *
@@ -1640,6 +1792,7 @@ struct ProcessVtbl {
     int32_t (*module_import_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, ImportInfo *ok_out);
     int32_t (*module_export_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, ExportInfo *ok_out);
     int32_t (*module_section_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, SectionInfo *ok_out);
+    int32_t (*environment_block)(CGlueC *cont, EnvironmentBlock *ok_out);
     const ProcessInfo *(*info)(const CGlueC *cont);
     void (*mapped_mem_range)(CGlueC *cont, imem gap_size, Address start, Address end, MemoryRangeCallback out);
     void (*mapped_mem)(CGlueC *cont, imem gap_size, MemoryRangeCallback out);
@@ -1663,6 +1816,7 @@ constexpr ProcessVtblImpl() :
         &Impl::module_import_by_name,
         &Impl::module_export_by_name,
         &Impl::module_section_by_name,
+        &Impl::environment_block,
         &Impl::info,
         &Impl::mapped_mem_range,
         &Impl::mapped_mem
@@ -1821,6 +1975,11 @@ struct ProcessInstance {
         return __ret;
     }
 
+    inline void prefetch(Address addr, umem len) noexcept {
+    (this->vtbl_memoryview)->prefetch(&this->container, addr, len);
+
+    }
+
     inline int32_t read_iter(CIterator<ReadData> inp, ReadCallback * out, ReadCallback * out_fail) noexcept {
         int32_t __ret = (this->vtbl_memoryview)->read_iter(&this->container, inp, out, out_fail);
         return __ret;
@@ -1921,6 +2080,11 @@ struct ProcessInstance {
         return __ret;
     }
 
+    inline int32_t environment_block(EnvironmentBlock * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_process)->environment_block(&this->container, ok_out);
+        return __ret;
+    }
+
     inline const ProcessInfo * info() const noexcept {
         const ProcessInfo * __ret = (this->vtbl_process)->info(&this->container);
         return __ret;
@@ -2079,6 +2243,11 @@ struct IntoProcessInstance {
         return __ret;
     }
 
+    inline void prefetch(Address addr, umem len) noexcept {
+    (this->vtbl_memoryview)->prefetch(&this->container, addr, len);
+
+    }
+
     inline int32_t read_iter(CIterator<ReadData> inp, ReadCallback * out, ReadCallback * out_fail) noexcept {
         int32_t __ret = (this->vtbl_memoryview)->read_iter(&this->container, inp, out, out_fail);
         return __ret;
@@ -2179,6 +2348,11 @@ struct IntoProcessInstance {
         return __ret;
     }
 
+    inline int32_t environment_block(EnvironmentBlock * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_process)->environment_block(&this->container, ok_out);
+        return __ret;
+    }
+
     inline const ProcessInfo * info() const noexcept {
         const ProcessInfo * __ret = (this->vtbl_process)->info(&this->container);
         return __ret;
@@ -2241,6 +2415,117 @@ struct IntoProcessInstance {
 
 };
 
+/**
+ * Thread executing on a single CPU at the time of the query
+ */
+struct CpuThreadInfo {
+    /**
+     * Number of the CPU
+     */
+    uint32_t cpu;
+    /**
+     * Address of the thread structure (e.g. `ETHREAD` on Windows, `task_struct` on Linux)
+     */
+    Address thread;
+    /**
+     * ID of the thread
+     */
+    uint32_t tid;
+    /**
+     * Address of the owning process structure, usable with [`Os::process_info_by_address`]
+     *
+     * This is [`Address::INVALID`] if the CPU is idle and no process owns the thread.
+     */
+    Address process;
+    /**
+     * ID of the owning process
+     */
+    Pid pid;
+};
+
+using CpuThreadInfoCallback = OpaqueCallback<CpuThreadInfo>;
+
+/**
+ * Time information of the target
+ */
+struct OsTime {
+    /**
+     * Wall-clock time in nanoseconds since the unix epoch (UTC)
+     */
+    uint64_t time_ns;
+    /**
+     * Time since boot in nanoseconds
+     */
+    uint64_t uptime_ns;
+    /**
+     * Offset of the local time zone to UTC in seconds, positive east of UTC
+     */
+    int32_t utc_offset;
+};
+
+/**
+ * Security features of the OS.
+ *
+ * Features that are not set are either disabled or could not be determined by the OS layer.
+ */
+using OsSecurityFeatures = uint32_t;
+/**
+ * No security features were detected.
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_NONE = 0;
+/**
+ * The system was booted with secure boot.
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_SECURE_BOOT = 1;
+/**
+ * Virtualization based security is running, parts of memory are owned by a secure kernel.
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_VBS = 2;
+/**
+ * Hypervisor protected code integrity (memory integrity) is enforced.
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_HVCI = 4;
+/**
+ * The kernel is loaded at a randomized address.
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_KASLR = 8;
+/**
+ * Hotpatches can be applied to the running kernel (e.g. livepatch on Linux).
+ */
+constexpr static const OsSecurityFeatures OsSecurityFeatures_HOTPATCH = 16;
+
+/**
+ * Detailed version information about the OS
+ */
+struct OsVersion {
+    /**
+     * Major version (e.g. 10 on Windows 10/11, 6 on Linux 6.1)
+     */
+    uint32_t major;
+    /**
+     * Minor version
+     */
+    uint32_t minor;
+    /**
+     * Build number (e.g. 22621 on Windows), or the patch level on Linux
+     */
+    uint32_t build;
+    /**
+     * Update revision of the build (e.g. the UBR on Windows)
+     *
+     * This changes with every cumulative update or hotpatch that is installed.
+     */
+    uint32_t revision;
+    /**
+     * Edition or distribution name (e.g. `Professional`, `ServerDatacenter`), may be empty
+     */
+    ReprCString edition;
+    /**
+     * Security features detected as enabled
+     */
+    OsSecurityFeatures security;
+};
+
 /**
  * Information block about OS
  *
@@ -2261,6 +2546,10 @@ struct OsInfo {
      * System architecture
      */
     ArchitectureIdent arch;
+    /**
+     * Detailed version information, all fields are zero/empty if unknown
+     */
+    OsVersion version;
 };
 
 /**
@@ -2296,6 +2585,8 @@ struct OsVtbl {
     int32_t (*module_import_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, ImportInfo *ok_out);
     int32_t (*module_export_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, ExportInfo *ok_out);
     int32_t (*module_section_by_name)(CGlueC *cont, const ModuleInfo *info, CSliceRef<uint8_t> name, SectionInfo *ok_out);
+    int32_t (*current_thread_list_callback)(CGlueC *cont, CpuThreadInfoCallback callback);
+    int32_t (*system_time)(CGlueC *cont, OsTime *ok_out);
     const OsInfo *(*info)(const CGlueC *cont);
 };
 
@@ -2328,6 +2619,8 @@ constexpr OsVtblImpl() :
         &Impl::module_import_by_name,
         &Impl::module_export_by_name,
         &Impl::module_section_by_name,
+        &Impl::current_thread_list_callback,
+        &Impl::system_time,
         &Impl::info
     } {}
 };
@@ -2682,6 +2975,16 @@ struct OsInstance {
         return __ret;
     }
 
+    inline int32_t current_thread_list_callback(CpuThreadInfoCallback callback) noexcept {
+        int32_t __ret = (this->vtbl_os)->current_thread_list_callback(&this->container, callback);
+        return __ret;
+    }
+
+    inline int32_t system_time(OsTime * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_os)->system_time(&this->container, ok_out);
+        return __ret;
+    }
+
     inline const OsInfo * info() const noexcept {
         const OsInfo * __ret = (this->vtbl_os)->info(&this->container);
         return __ret;
@@ -2702,6 +3005,11 @@ struct OsInstance {
         return __ret;
     }
 
+    inline void prefetch(Address addr, umem len) noexcept {
+    (this->vtbl_memoryview)->prefetch(&this->container, addr, len);
+
+    }
+
     inline int32_t read_iter(CIterator<ReadData> inp, ReadCallback * out, ReadCallback * out_fail) noexcept {
         int32_t __ret = (this->vtbl_memoryview)->read_iter(&this->container, inp, out, out_fail);
         return __ret;
@@ -2764,6 +3072,26 @@ struct OsInstance {
 
     }
 
+    inline void phys_mem_map(PhysicalMemoryMappingCallback out) const noexcept {
+    (this->vtbl_physicalmemory)->phys_mem_map(&this->container, out);
+
+    }
+
+    inline void phys_prefetch(CSliceRef<CTup2<PhysicalAddress, umem>> ranges) noexcept {
+    (this->vtbl_physicalmemory)->phys_prefetch(&this->container, ranges);
+
+    }
+
+    inline int32_t phys_cas_raw(PhysicalAddress addr, CSliceRef<uint8_t> expected, CSliceRef<uint8_t> new_, bool * ok_out) noexcept {
+        int32_t __ret = (this->vtbl_physicalmemory)->phys_cas_raw(&this->container, addr, expected, new_, ok_out);
+        return __ret;
+    }
+
+    inline int32_t health_check() noexcept {
+        int32_t __ret = (this->vtbl_physicalmemory)->health_check(&this->container);
+        return __ret;
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl_physicalmemory)->into_phys_view(this->container);
@@ -3173,6 +3501,11 @@ struct CGlueTraitObj<T, MemoryViewVtbl<CGlueObjContainer<T, C, R>>, C, R> {
         return __ret;
     }
 
+    inline void prefetch(Address addr, umem len) noexcept {
+    (this->vtbl)->prefetch(&this->container, addr, len);
+
+    }
+
     inline int32_t read_iter(CIterator<ReadData> inp, ReadCallback * out, ReadCallback * out_fail) noexcept {
         int32_t __ret = (this->vtbl)->read_iter(&this->container, inp, out, out_fail);
         return __ret;
@@ -3238,6 +3571,26 @@ struct CGlueTraitObj<T, PhysicalMemoryVtbl<CGlueObjContainer<T, C, R>>, C, R> {
 
     }
 
+    inline void phys_mem_map(PhysicalMemoryMappingCallback out) const noexcept {
+    (this->vtbl)->phys_mem_map(&this->container, out);
+
+    }
+
+    inline void phys_prefetch(CSliceRef<CTup2<PhysicalAddress, umem>> ranges) noexcept {
+    (this->vtbl)->phys_prefetch(&this->container, ranges);
+
+    }
+
+    inline int32_t phys_cas_raw(PhysicalAddress addr, CSliceRef<uint8_t> expected, CSliceRef<uint8_t> new_, bool * ok_out) noexcept {
+        int32_t __ret = (this->vtbl)->phys_cas_raw(&this->container, addr, expected, new_, ok_out);
+        return __ret;
+    }
+
+    inline int32_t health_check() noexcept {
+        int32_t __ret = (this->vtbl)->health_check(&this->container);
+        return __ret;
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl)->into_phys_view(this->container);
@@ -3275,6 +3628,21 @@ struct CGlueTraitObj<T, CpuStateVtbl<CGlueObjContainer<T, C, R>>, C, R> {
 
     }
 
+    inline uintptr_t cpu_count() noexcept {
+        uintptr_t __ret = (this->vtbl)->cpu_count(&this->container);
+        return __ret;
+    }
+
+    inline int32_t read_register(uintptr_t cpu, CpuRegister reg, uint64_t * ok_out) noexcept {
+        int32_t __ret = (this->vtbl)->read_register(&this->container, cpu, reg, ok_out);
+        return __ret;
+    }
+
+    inline int32_t write_register(uintptr_t cpu, CpuRegister reg, uint64_t value) noexcept {
+        int32_t __ret = (this->vtbl)->write_register(&this->container, cpu, reg, value);
+        return __ret;
+    }
+
 };
 
 template<typename T, typename C, typename R>
@@ -3387,6 +3755,11 @@ struct CGlueTraitObj<T, ProcessVtbl<CGlueObjContainer<T, C, R>>, C, R> {
         return __ret;
     }
 
+    inline int32_t environment_block(EnvironmentBlock * ok_out) noexcept {
+        int32_t __ret = (this->vtbl)->environment_block(&this->container, ok_out);
+        return __ret;
+    }
+
     inline const ProcessInfo * info() const noexcept {
         const ProcessInfo * __ret = (this->vtbl)->info(&this->container);
         return __ret;
@@ -3610,6 +3983,16 @@ struct CGlueTraitObj<T, OsVtbl<CGlueObjContainer<T, C, R>>, C, R> {
         return __ret;
     }
 
+    inline int32_t current_thread_list_callback(CpuThreadInfoCallback callback) noexcept {
+        int32_t __ret = (this->vtbl)->current_thread_list_callback(&this->container, callback);
+        return __ret;
+    }
+
+    inline int32_t system_time(OsTime * ok_out) noexcept {
+        int32_t __ret = (this->vtbl)->system_time(&this->container, ok_out);
+        return __ret;
+    }
+
     inline const OsInfo * info() const noexcept {
         const OsInfo * __ret = (this->vtbl)->info(&this->container);
         return __ret;
@@ -0,0 +1,348 @@
+//! Interactive console over a target.
+//!
+//! The [`Console`] executes simple text commands against an [`Os`]: listing and attaching to
+//! processes, hexdumping memory, scanning for byte patterns and displaying structures. With the
+//! `rustyline` feature enabled [`run_editor`](Console::run_editor) reads commands through a
+//! `rustyline` editor with line editing and a persistent history. Without it commands can be fed
+//! from any [`BufRead`] through [`run`](Console::run), or line by line through
+//! [`execute`](Console::execute) from other frontends.
+//!
+//! Addresses can be given as decimal or `0x` prefixed hexadecimal numbers, or relative to a
+//! module of the attached process, e.g. `kernel32.dll+0x1000`.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::prelude::v1::*;
+//! use memflow_inspect::console::Console;
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+//! let pid = os.alloc_process(size::mb(1), &[0x4d, 0x5a]);
+//!
+//! let mut console = Console::new(os);
+//! let mut out = vec![];
+//! console.execute(&format!("attach {}", pid), &mut out).unwrap();
+//! console.execute("modules", &mut out).unwrap();
+//! ```
+
+use std::io::{BufRead, Write};
+#[cfg(feature = "rustyline")]
+use std::path::Path;
+
+use memflow::prelude::v1::*;
+
+use crate::hexdump::hexdump;
+use crate::parse_num;
+use crate::scan::{scan, Pattern};
+
+const HELP: &str = "\
+commands:
+  ps                               lists all processes
+  attach <pid|name>                attaches to a process
+  detach                           detaches from the current process
+  modules                          lists all modules of the attached process
+  read <address> <length>          hexdumps memory of the attached process
+  scan <pattern> [module]          scans the process or a module for a pattern, e.g. `48 8b ?? 05`
+  struct <address> <type>...       reads naturally aligned fields of the types
+                                   u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 ptr
+  help                             shows this text
+  quit                             exits the console";
+
+/// Interactive console executing commands against an [`Os`].
+pub struct Console<T: Os> {
+    os: T,
+    process: Option<T::IntoProcessType>,
+}
+
+impl<T: Os + Clone> Console<T> {
+    /// Creates a new console that is not attached to any process.
+    pub fn new(os: T) -> Self {
+        Self { os, process: None }
+    }
+
+    /// Returns the currently attached process.
+    pub fn process(&mut self) -> Option<&mut T::IntoProcessType> {
+        self.process.as_mut()
+    }
+
+    /// Returns the prompt to display, containing the name of the attached process.
+    pub fn prompt(&self) -> String {
+        match &self.process {
+            Some(p) => format!("{}> ", p.info().name),
+            None => "> ".to_string(),
+        }
+    }
+
+    /// Reads commands from `input` and executes them until the input ends or `quit` is entered.
+    ///
+    /// Errors of individual commands are printed and do not stop the console.
+    pub fn run(&mut self, mut input: impl BufRead, out: &mut impl Write) -> Result<()> {
+        let mut line = String::new();
+
+        loop {
+            write!(out, "{}", self.prompt()).map_err(write_error)?;
+            out.flush().map_err(write_error)?;
+
+            line.clear();
+            if input.read_line(&mut line).map_err(|err| {
+                Error(ErrorOrigin::Other, ErrorKind::UnableToReadFile).log_error(err)
+            })? == 0
+            {
+                return Ok(());
+            }
+
+            match self.execute(&line, out) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(err) => writeln!(out, "error: {}", err).map_err(write_error)?,
+            }
+        }
+    }
+
+    /// Reads commands through a `rustyline` editor and executes them until the input ends or
+    /// `quit` is entered.
+    ///
+    /// The history of entered commands is loaded from and saved to `history` if given, a missing
+    /// history file is created. Errors of individual commands are printed and do not stop the
+    /// console, Ctrl-C discards the current line.
+    #[cfg(feature = "rustyline")]
+    pub fn run_editor(&mut self, history: Option<&Path>, out: &mut impl Write) -> Result<()> {
+        use rustyline::error::ReadlineError;
+
+        let mut editor = rustyline::Editor::<()>::new().map_err(read_error)?;
+        if let Some(history) = history {
+            // the history does not exist yet on the first start
+            let _ = editor.load_history(history);
+        }
+
+        let ret = loop {
+            let line = match editor.readline(&self.prompt()) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break Ok(()),
+                Err(err) => break Err(read_error(err)),
+            };
+            editor.add_history_entry(line.as_str());
+
+            match self.execute(&line, out) {
+                Ok(true) => {}
+                Ok(false) => break Ok(()),
+                Err(err) => {
+                    if let Err(err) = writeln!(out, "error: {}", err) {
+                        break Err(write_error(err));
+                    }
+                }
+            }
+        };
+
+        if let Some(history) = history {
+            editor.save_history(history).map_err(|err| {
+                Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err)
+            })?;
+        }
+
+        ret
+    }
+
+    /// Executes a single command and writes its output to `out`.
+    ///
+    /// Returns `false` if the console should exit.
+    pub fn execute(&mut self, line: &str, out: &mut impl Write) -> Result<bool> {
+        let args = line.split_whitespace().collect::<Vec<_>>();
+
+        match args.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => return Ok(false),
+            ["help"] => writeln!(out, "{}", HELP).map_err(write_error)?,
+            ["ps"] => {
+                for p in self.os.process_info_list()? {
+                    writeln!(out, "{:>5} {:^10} {}", p.pid, p.proc_arch, p.name)
+                        .map_err(write_error)?;
+                }
+            }
+            ["attach", process] => {
+                let os = self.os.clone();
+                let process = match process.parse::<Pid>() {
+                    Ok(pid) => os.into_process_by_pid(pid)?,
+                    Err(_) => os.into_process_by_name(process)?,
+                };
+                writeln!(
+                    out,
+                    "attached to {} ({})",
+                    process.info().name,
+                    process.info().pid
+                )
+                .map_err(write_error)?;
+                self.process = Some(process);
+            }
+            ["detach"] => self.process = None,
+            ["modules"] => {
+                for m in self.attached()?.module_list()? {
+                    writeln!(out, "{:x} {:>10x} {}", m.base, m.size, m.name)
+                        .map_err(write_error)?;
+                }
+            }
+            ["read", addr, len] => {
+                let addr = self.parse_address(addr)?;
+                let len = parse_num(len)?;
+
+                let data = self.attached()?.read_raw(addr, len as usize).data_part()?;
+                hexdump(out, addr, &data).map_err(write_error)?;
+            }
+            ["scan", pattern @ ..] => {
+                let (pattern, module) = match pattern.last() {
+                    Some(m) if Pattern::parse(m).is_none() => {
+                        (&pattern[..pattern.len() - 1], Some(*m))
+                    }
+                    _ => (pattern, None),
+                };
+                let pattern = Pattern::parse(&pattern.join(" ")).ok_or_else(|| {
+                    Error(ErrorOrigin::Other, ErrorKind::ArgValidation).log_error("invalid pattern")
+                })?;
+
+                let process = self.attached()?;
+                let ranges = match module {
+                    Some(name) => {
+                        let m = process.module_by_name(name)?;
+                        vec![CTup3(m.base, m.size, PageType::default())]
+                    }
+                    None => process.mapped_mem_vec(0),
+                };
+
+                for CTup3(base, size, _) in ranges {
+                    for addr in scan(process, base, size, &pattern)? {
+                        writeln!(out, "{:x}", addr).map_err(write_error)?;
+                    }
+                }
+            }
+            ["struct", addr, types @ ..] if !types.is_empty() => {
+                let addr = self.parse_address(addr)?;
+                self.display_struct(addr, types, out)?;
+            }
+            _ => writeln!(out, "unknown command, type `help` for a list of commands")
+                .map_err(write_error)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Reads and displays naturally aligned fields of the given types starting at `addr`.
+    fn display_struct(
+        &mut self,
+        addr: Address,
+        types: &[&str],
+        out: &mut impl Write,
+    ) -> Result<()> {
+        let process = self.attached()?;
+        let ptr_size = process.info().proc_arch.into_obj().size_addr();
+
+        let mut offset = 0;
+        for ty in types {
+            let size = match *ty {
+                "u8" | "i8" => 1,
+                "u16" | "i16" => 2,
+                "u32" | "i32" | "f32" => 4,
+                "u64" | "i64" | "f64" => 8,
+                "ptr" => ptr_size,
+                _ => {
+                    return Err(Error(ErrorOrigin::Other, ErrorKind::ArgValidation)
+                        .log_error(format!("unknown type: {}", ty)))
+                }
+            };
+            offset = (offset + size - 1) / size * size;
+
+            let mut buf = [0u8; 8];
+            process
+                .read_raw_into(addr + offset, &mut buf[..size])
+                .data_part()?;
+
+            let value = match *ty {
+                "u8" => buf[0].to_string(),
+                "i8" => (buf[0] as i8).to_string(),
+                "u16" => u16::from_le_bytes([buf[0], buf[1]]).to_string(),
+                "i16" => i16::from_le_bytes([buf[0], buf[1]]).to_string(),
+                "u32" => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]).to_string(),
+                "i32" => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]).to_string(),
+                "f32" => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]).to_string(),
+                "u64" => u64::from_le_bytes(buf).to_string(),
+                "i64" => i64::from_le_bytes(buf).to_string(),
+                "f64" => f64::from_le_bytes(buf).to_string(),
+                _ => format!("0x{:x}", u64::from_le_bytes(buf)),
+            };
+
+            writeln!(out, "+{:<4x} {:<4} {}", offset, ty, value).map_err(write_error)?;
+            offset += size;
+        }
+
+        Ok(())
+    }
+
+    /// Parses a number or a `module+offset` expression.
+    fn parse_address(&mut self, addr: &str) -> Result<Address> {
+        match addr.rsplit_once('+') {
+            Some((module, offset)) => {
+                let base = self.attached()?.module_by_name(module)?.base;
+                Ok(base + parse_num(offset)?)
+            }
+            None => parse_num(addr).map(Address::from),
+        }
+    }
+
+    fn attached(&mut self) -> Result<&mut T::IntoProcessType> {
+        self.process.as_mut().ok_or_else(|| {
+            Error(ErrorOrigin::Other, ErrorKind::ProcessNotFound)
+                .log_error("not attached to a process")
+        })
+    }
+}
+
+fn write_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err)
+}
+
+#[cfg(feature = "rustyline")]
+fn read_error(err: rustyline::error::ReadlineError) -> Error {
+    Error(ErrorOrigin::Other, ErrorKind::UnableToReadFile).log_error(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memflow::dummy::{DummyMemory, DummyOs};
+
+    fn console() -> (Console<DummyOs>, Pid) {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+        let pid = os.alloc_process(size::mb(1), &[0x4d, 0x5a, 0x90, 0x00, 0x03, 0, 0, 0]);
+        (Console::new(os), pid)
+    }
+
+    fn execute(console: &mut Console<DummyOs>, line: &str) -> String {
+        let mut out = vec![];
+        assert!(console.execute(line, &mut out).unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn read_struct() {
+        let (mut console, pid) = console();
+        assert!(console.execute("read 0 4", &mut vec![]).is_err());
+
+        execute(&mut console, &format!("attach {}", pid));
+        let base = console.process().unwrap().info().address;
+
+        let out = execute(&mut console, &format!("struct 0x{:x} u16 u8 u32", base));
+        assert_eq!(out, "+0    u16  23117\n+2    u8   144\n+4    u32  3\n");
+
+        let out = execute(&mut console, "scan 5a 90 ?? 03");
+        assert!(out.lines().any(|l| l == format!("{:x}", base + 1)));
+    }
+
+    #[test]
+    fn quit() {
+        let (mut console, _) = console();
+        let mut out = vec![];
+        console.run(&b"help\nquit\nps\n"[..], &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("> "));
+    }
+}
@@ -0,0 +1,285 @@
+//! Filesystem view of a target.
+//!
+//! [`TargetFs`] exposes the processes and the physical memory of a target as a tree of files,
+//! similar to `/proc` on Linux:
+//!
+//! ```text
+//! /physical               physical memory, seekable
+//! /processes/<pid>/maps   mapped memory ranges of the process
+//! /processes/<pid>/mem    virtual memory of the process, seekable
+//! ```
+//!
+//! The tree itself is independent of any filesystem driver. Nodes map one to one onto inode
+//! numbers through [`Node::ino`], which makes it straightforward to serve through FUSE (see the
+//! `fuse` feature) or any other virtual filesystem interface.
+//!
+//! Like on `/proc` the `mem` files report a size of zero, they have to be read at the virtual
+//! address of interest. Unreadable parts of a partially readable request are zero filled.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::mem::phys_mem::PhysicalMemoryView;
+//! use memflow::prelude::v1::*;
+//! use memflow_inspect::fs::{Node, TargetFs};
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+//! let pid = os.alloc_process(size::mb(1), &[0x4d, 0x5a]);
+//! let mut proc = os.clone().into_process_by_pid(pid).unwrap();
+//! let base = proc.info().address;
+//!
+//! let mut fs = TargetFs::new(os, None::<PhysicalMemoryView<DummyMemory>>);
+//! let mem = fs.resolve(&format!("/processes/{}/mem", pid)).unwrap();
+//!
+//! let mut buf = [0u8; 2];
+//! fs.read(mem, base.to_umem(), &mut buf).unwrap();
+//! assert_eq!(buf, [0x4d, 0x5a]);
+//! ```
+
+use std::collections::HashMap;
+
+use memflow::prelude::v1::*;
+
+/// Node of the filesystem tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Node {
+    /// The root directory
+    Root,
+    /// The `/processes` directory
+    Processes,
+    /// The `/physical` file
+    Physical,
+    /// The `/processes/<pid>` directory
+    Process(Pid),
+    /// The `/processes/<pid>/maps` file
+    Maps(Pid),
+    /// The `/processes/<pid>/mem` file
+    Mem(Pid),
+}
+
+impl Node {
+    /// Returns the unique inode number of this node.
+    ///
+    /// The root directory always has inode number `1`.
+    pub fn ino(&self) -> u64 {
+        match *self {
+            Node::Root => 1,
+            Node::Processes => 2,
+            Node::Physical => 3,
+            Node::Process(pid) => ((pid as u64) << 8) | 0x10,
+            Node::Maps(pid) => ((pid as u64) << 8) | 0x11,
+            Node::Mem(pid) => ((pid as u64) << 8) | 0x12,
+        }
+    }
+
+    /// Returns the node with the given inode number.
+    pub fn from_ino(ino: u64) -> Option<Self> {
+        let pid = (ino >> 8) as Pid;
+        match (ino, ino & 0xff) {
+            (1, _) => Some(Node::Root),
+            (2, _) => Some(Node::Processes),
+            (3, _) => Some(Node::Physical),
+            (_, 0x10) => Some(Node::Process(pid)),
+            (_, 0x11) => Some(Node::Maps(pid)),
+            (_, 0x12) => Some(Node::Mem(pid)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the node is a directory.
+    pub fn is_dir(&self) -> bool {
+        matches!(self, Node::Root | Node::Processes | Node::Process(_))
+    }
+}
+
+/// Filesystem tree over the processes and physical memory of a target.
+pub struct TargetFs<T: Os, P> {
+    os: T,
+    physical: Option<P>,
+    processes: HashMap<Pid, T::IntoProcessType>,
+}
+
+impl<T: Os + Clone, P: MemoryView> TargetFs<T, P> {
+    /// Creates a new tree over `os`, exposing `physical` as `/physical` if given.
+    pub fn new(os: T, physical: Option<P>) -> Self {
+        Self {
+            os,
+            physical,
+            processes: HashMap::new(),
+        }
+    }
+
+    /// Returns the entry called `name` in the directory `parent`.
+    pub fn lookup(&mut self, parent: Node, name: &str) -> Option<Node> {
+        match (parent, name) {
+            (Node::Root, "processes") => Some(Node::Processes),
+            (Node::Root, "physical") if self.physical.is_some() => Some(Node::Physical),
+            (Node::Processes, pid) => {
+                let pid = pid.parse::<Pid>().ok()?;
+                self.os.process_info_by_pid(pid).ok()?;
+                Some(Node::Process(pid))
+            }
+            (Node::Process(pid), "maps") => Some(Node::Maps(pid)),
+            (Node::Process(pid), "mem") => Some(Node::Mem(pid)),
+            _ => None,
+        }
+    }
+
+    /// Resolves an absolute path like `/processes/4/mem`.
+    pub fn resolve(&mut self, path: &str) -> Option<Node> {
+        path.split('/')
+            .filter(|c| !c.is_empty())
+            .try_fold(Node::Root, |node, name| self.lookup(node, name))
+    }
+
+    /// Returns the names and nodes of all entries in the directory `node`.
+    pub fn read_dir(&mut self, node: Node) -> Result<Vec<(String, Node)>> {
+        match node {
+            Node::Root => {
+                let mut ret = vec![("processes".to_string(), Node::Processes)];
+                if self.physical.is_some() {
+                    ret.push(("physical".to_string(), Node::Physical));
+                }
+                Ok(ret)
+            }
+            Node::Processes => Ok(self
+                .os
+                .process_info_list()?
+                .into_iter()
+                .filter(|p| !p.state.is_dead())
+                .map(|p| (p.pid.to_string(), Node::Process(p.pid)))
+                .collect()),
+            Node::Process(pid) => Ok(vec![
+                ("maps".to_string(), Node::Maps(pid)),
+                ("mem".to_string(), Node::Mem(pid)),
+            ]),
+            _ => Err(Error(ErrorOrigin::Other, ErrorKind::InvalidPath)),
+        }
+    }
+
+    /// Returns the size of the file `node` in bytes.
+    pub fn size(&mut self, node: Node) -> Result<umem> {
+        match node {
+            Node::Physical => Ok(self
+                .physical
+                .as_ref()
+                .map(|p| p.metadata().max_address.to_umem() + 1)
+                .unwrap_or_default()),
+            Node::Maps(pid) => Ok(self.maps(pid)?.len() as umem),
+            _ => Ok(0),
+        }
+    }
+
+    /// Reads the file `node` starting at `offset` into `buf`.
+    ///
+    /// Returns the number of bytes read, which is only smaller than the buffer at the end of a
+    /// file.
+    pub fn read(&mut self, node: Node, offset: umem, buf: &mut [u8]) -> Result<usize> {
+        match node {
+            Node::Physical => {
+                let size = self.size(node)?;
+                let len = std::cmp::min(buf.len() as umem, size.saturating_sub(offset)) as usize;
+                let physical = self
+                    .physical
+                    .as_mut()
+                    .ok_or(Error(ErrorOrigin::Other, ErrorKind::InvalidPath))?;
+                read_zero_filled(physical, offset, &mut buf[..len])
+            }
+            Node::Mem(pid) => read_zero_filled(self.process(pid)?, offset, buf),
+            Node::Maps(pid) => {
+                let maps = self.maps(pid)?;
+                let start = std::cmp::min(offset as usize, maps.len());
+                let len = std::cmp::min(buf.len(), maps.len() - start);
+                buf[..len].copy_from_slice(&maps.as_bytes()[start..start + len]);
+                Ok(len)
+            }
+            _ => Err(Error(ErrorOrigin::Other, ErrorKind::InvalidPath)),
+        }
+    }
+
+    /// Returns the cached process with the given pid, opening it if necessary.
+    fn process(&mut self, pid: Pid) -> Result<&mut T::IntoProcessType> {
+        if !self.processes.contains_key(&pid) {
+            let info = self.os.process_info_by_pid(pid)?;
+            let process = self.os.clone().into_process_by_info(info)?;
+            self.processes.insert(pid, process);
+        }
+
+        Ok(self.processes.get_mut(&pid).unwrap())
+    }
+
+    /// Formats the mapped memory ranges of a process like `/proc/<pid>/maps`.
+    fn maps(&mut self, pid: Pid) -> Result<String> {
+        let process = self.process(pid)?;
+        let modules = process.module_list().unwrap_or_default();
+
+        Ok(process
+            .mapped_mem_vec(0)
+            .into_iter()
+            .map(|CTup3(base, size, page_type)| {
+                let module = modules
+                    .iter()
+                    .find(|m| base >= m.base && base < m.base + m.size)
+                    .map(|m| m.name.as_ref())
+                    .unwrap_or("");
+
+                format!(
+                    "{:016x}-{:016x} r{}{} {}\n",
+                    base.to_umem(),
+                    (base + size).to_umem(),
+                    if page_type.contains(PageType::WRITEABLE) {
+                        'w'
+                    } else {
+                        '-'
+                    },
+                    if page_type.contains(PageType::NOEXEC) {
+                        '-'
+                    } else {
+                        'x'
+                    },
+                    module
+                )
+            })
+            .collect())
+    }
+}
+
+/// Reads `buf` at `offset`, zero filling unreadable parts.
+fn read_zero_filled(mem: &mut impl MemoryView, offset: umem, buf: &mut [u8]) -> Result<usize> {
+    buf.iter_mut().for_each(|b| *b = 0);
+    mem.read_raw_into(offset.into(), buf).data_part()?;
+    Ok(buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memflow::dummy::{DummyMemory, DummyOs};
+
+    #[test]
+    fn tree() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+        let pid = os.alloc_process(size::mb(1), &[]);
+
+        let mut physical = DummyMemory::new(size::mb(1));
+        physical.phys_write(0x1000.into(), &0x1234u32).unwrap();
+
+        let mut fs = TargetFs::new(os, Some(physical.into_phys_view()));
+
+        let processes = fs.read_dir(Node::Processes).unwrap();
+        assert!(processes.contains(&(pid.to_string(), Node::Process(pid))));
+        assert!(fs.resolve("/processes/abc").is_none());
+
+        let maps = fs.resolve(&format!("/processes/{}/maps", pid)).unwrap();
+        assert_eq!(Node::from_ino(maps.ino()), Some(maps));
+        assert!(fs.size(maps).unwrap() > 0);
+
+        let physical = fs.resolve("/physical").unwrap();
+        assert_eq!(fs.size(physical).unwrap(), size::mb(1) as umem);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(fs.read(physical, 0x1000, &mut buf).unwrap(), 4);
+        assert_eq!(u32::from_le_bytes(buf), 0x1234);
+    }
+}
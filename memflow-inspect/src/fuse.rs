@@ -0,0 +1,155 @@
+//! FUSE driver serving a [`TargetFs`].
+//!
+//! The filesystem is mounted read-only. All files are opened with direct I/O, so reads of the
+//! `mem` files are not limited by their reported size and always reach the target.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    ReplyOpen, Request,
+};
+use log::warn;
+
+use memflow::prelude::v1::*;
+
+use crate::fs::{Node, TargetFs};
+
+/// Time the kernel may cache attributes and lookups.
+const TTL: Duration = Duration::from_secs(1);
+
+/// Adapter implementing [`Filesystem`] for a [`TargetFs`].
+pub struct FuseFs<T: Os, P> {
+    fs: TargetFs<T, P>,
+}
+
+impl<T: Os + Clone, P: MemoryView> FuseFs<T, P> {
+    /// Wraps `fs` to be served through FUSE.
+    pub fn new(fs: TargetFs<T, P>) -> Self {
+        Self { fs }
+    }
+
+    /// Mounts the filesystem at `mountpoint` and serves it until it is unmounted.
+    pub fn mount(self, mountpoint: impl AsRef<Path>) -> Result<()> {
+        let options = [MountOption::RO, MountOption::FSName("memflow".to_string())];
+
+        fuser::mount2(self, mountpoint, &options)
+            .map_err(|err| Error(ErrorOrigin::Other, ErrorKind::Unknown).log_error(err))
+    }
+
+    fn attr(&mut self, node: Node) -> Option<FileAttr> {
+        let (kind, perm, size) = if node.is_dir() {
+            (FileType::Directory, 0o555, 0)
+        } else {
+            (
+                FileType::RegularFile,
+                0o444,
+                self.fs.size(node).ok()? as u64,
+            )
+        };
+
+        Some(FileAttr {
+            ino: node.ino(),
+            size,
+            blocks: (size + 511) / 512,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink: if node.is_dir() { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+impl<T: Os + Clone, P: MemoryView> Filesystem for FuseFs<T, P> {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let node = Node::from_ino(parent)
+            .zip(name.to_str())
+            .and_then(|(parent, name)| self.fs.lookup(parent, name))
+            .and_then(|node| self.attr(node));
+
+        match node {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match Node::from_ino(ino).and_then(|node| self.attr(node)) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request, _ino: u64, _flags: i32, reply: ReplyOpen) {
+        reply.opened(0, fuser::consts::FOPEN_DIRECT_IO);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let node = match Node::from_ino(ino) {
+            Some(node) => node,
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let mut buf = vec![0u8; size as usize];
+        match self.fs.read(node, offset as umem, &mut buf) {
+            Ok(len) => reply.data(&buf[..len]),
+            Err(err) => {
+                warn!("unable to read {:?} at {:x}: {}", node, offset, err);
+                reply.error(libc::EIO)
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let node = match Node::from_ino(ino) {
+            Some(node) => node,
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let entries = match self.fs.read_dir(node) {
+            Ok(entries) => entries,
+            Err(_) => return reply.error(libc::ENOTDIR),
+        };
+
+        for (i, (name, entry)) in entries.iter().enumerate().skip(offset as usize) {
+            let kind = if entry.is_dir() {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            };
+
+            if reply.add(entry.ino(), (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+}
@@ -0,0 +1,30 @@
+//! Hexdump formatting.
+
+use std::io::{self, Write};
+
+use memflow::types::Address;
+
+/// Writes `data` as a classic hexdump with 16 bytes per line.
+pub fn hexdump(out: &mut impl Write, base: Address, data: &[u8]) -> io::Result<()> {
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex = line
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect::<String>();
+
+        writeln!(
+            out,
+            "{:016x}  {:<47}  |{}|",
+            (base + i * 16).to_umem(),
+            hex,
+            ascii
+        )?;
+    }
+
+    Ok(())
+}
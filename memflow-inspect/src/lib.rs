@@ -0,0 +1,25 @@
+//! Building blocks of the `memflow-inspect` tool.
+//!
+//! Besides the command line tool this crate exposes its interactive [`Console`](console::Console)
+//! so it can be embedded into downstream applications.
+
+pub mod console;
+pub mod fs;
+#[cfg(all(unix, feature = "fuse"))]
+pub mod fuse;
+pub mod hexdump;
+pub mod scan;
+
+use memflow::prelude::v1::*;
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+pub fn parse_num(s: &str) -> Result<umem> {
+    match s.strip_prefix("0x") {
+        Some(hex) => umem::from_str_radix(hex, 16),
+        None => s.parse::<umem>(),
+    }
+    .map_err(|_| {
+        Error(ErrorOrigin::Other, ErrorKind::ArgValidation)
+            .log_error(format!("invalid number: {}", s))
+    })
+}
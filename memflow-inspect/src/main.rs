@@ -0,0 +1,304 @@
+/// Command line tool to inspect targets using memflow
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::*;
+use log::Level;
+
+use memflow::prelude::v1::*;
+use memflow_inspect::console::Console;
+use memflow_inspect::hexdump::hexdump;
+use memflow_inspect::parse_num;
+
+/// Chunk size used when reading large ranges of memory.
+const CHUNK_SIZE: usize = 0x10000;
+
+fn main() -> Result<()> {
+    let matches = parse_args();
+    init_logger(&matches);
+
+    let inventory = Inventory::scan();
+
+    match matches.subcommand() {
+        Some(("connectors", _)) => {
+            println!("Connectors:");
+            inventory
+                .available_connectors()
+                .iter()
+                .for_each(|c| println!("- {}", c));
+
+            println!("OS plugins:");
+            inventory
+                .available_os()
+                .iter()
+                .for_each(|o| println!("- {}", o));
+        }
+        Some(("targets", args)) => {
+            let connector = args.get_one::<String>("connector").unwrap();
+
+            inventory
+                .connector_target_list(connector)?
+                .iter()
+                .for_each(|t| println!("- {}", t.name));
+        }
+        Some(("ps", args)) => {
+            let mut os = inventory.builder().os_chain(os_chain(args)?).build()?;
+
+            println!(
+                "{:>5} {:>10} {:>10} {:<}",
+                "PID", "SYS ARCH", "PROC ARCH", "NAME"
+            );
+
+            for p in os.process_info_list()? {
+                println!(
+                    "{:>5} {:^10} {:^10} {} ({}) ({:?})",
+                    p.pid, p.sys_arch, p.proc_arch, p.name, p.command_line, p.state
+                );
+            }
+        }
+        Some(("modules", args)) => {
+            let os = inventory.builder().os_chain(os_chain(args)?).build()?;
+            let mut process = open_process(os, args)?;
+
+            println!(
+                "{:>11} {:>11} {:>11} {:>11} {:<}",
+                "BASE", "SIZE", "MOD ARCH", "NAME", "PATH"
+            );
+
+            for m in process.module_list()? {
+                println!(
+                    "0x{:0>8x} 0x{:0>8x} {:^10} {} ({})",
+                    m.base, m.size, m.arch, m.name, m.path
+                );
+            }
+        }
+        Some(("read", args)) => {
+            let os = inventory.builder().os_chain(os_chain(args)?).build()?;
+            let mut process = open_process(os, args)?;
+
+            let addr = parse_num(args.get_one::<String>("address").unwrap())?;
+            let len = parse_num(args.get_one::<String>("length").unwrap())?;
+
+            for chunk in process
+                .read_stream(addr.into(), len, CHUNK_SIZE)
+                .zero_fill_gaps()
+            {
+                let chunk = chunk?;
+                hexdump(&mut io::stdout().lock(), chunk.address, &chunk.data).map_err(|err| {
+                    Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err)
+                })?;
+            }
+        }
+        Some(("dump", args)) => {
+            let os = inventory.builder().os_chain(os_chain(args)?).build()?;
+            let mut process = open_process(os, args)?;
+
+            let out = Path::new(args.get_one::<String>("output").unwrap());
+            dump_process(&mut process, out)?;
+        }
+        Some(("console", args)) => {
+            let os = inventory.builder().os_chain(os_chain(args)?).build()?;
+
+            #[cfg(feature = "rustyline")]
+            Console::new(os).run_editor(
+                args.get_one::<String>("history").map(Path::new),
+                &mut io::stdout(),
+            )?;
+            #[cfg(not(feature = "rustyline"))]
+            Console::new(os).run(io::stdin().lock(), &mut io::stdout())?;
+        }
+        #[cfg(all(unix, feature = "fuse"))]
+        Some(("mount", args)) => {
+            use memflow_inspect::fs::TargetFs;
+            use memflow_inspect::fuse::FuseFs;
+
+            let os = inventory.builder().os_chain(os_chain(args)?).build()?;
+            let physical = into!(os.clone() impl PhysicalMemory).map(|p| p.into_phys_view());
+
+            let mountpoint = args.get_one::<String>("mountpoint").unwrap();
+            FuseFs::new(TargetFs::new(os, physical)).mount(mountpoint)?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Writes every mapped range of `process` into a separate file in the `out` directory.
+///
+/// Files are named after the address and size of the range. Unreadable parts are zero filled.
+fn dump_process(process: &mut (impl Process + MemoryView), out: &Path) -> Result<()> {
+    fs::create_dir_all(out).map_err(|err| {
+        Error(ErrorOrigin::Other, ErrorKind::UnableToCreateDirectory).log_error(err)
+    })?;
+
+    let ranges = process.mapped_mem_vec(0);
+    let total = ranges.iter().map(|r| r.1).sum::<umem>();
+    let mut done = 0;
+
+    for CTup3(base, size, _) in ranges {
+        let path = out.join(format!("{:x}-{:x}.bin", base, size));
+        let mut file = File::create(&path).map_err(|err| {
+            Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+
+        for chunk in process.read_stream(base, size, CHUNK_SIZE).zero_fill_gaps() {
+            let chunk = chunk?;
+            file.write_all(&chunk.data).map_err(|err| {
+                Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err)
+            })?;
+
+            done += chunk.data.len() as umem;
+            eprint!(
+                "\rdumped {:x} of {:x} bytes ({:.1}%)",
+                done,
+                total,
+                Progress::new(done, total).fraction() * 100.0
+            );
+        }
+    }
+
+    eprintln!();
+    Ok(())
+}
+
+/// Opens the process given either by pid or by name.
+fn open_process(
+    os: OsInstanceArcBox<'static>,
+    args: &ArgMatches,
+) -> Result<impl Process + MemoryView> {
+    let process = args.get_one::<String>("process").unwrap();
+
+    match process.parse::<Pid>() {
+        Ok(pid) => os.into_process_by_pid(pid),
+        Err(_) => os.into_process_by_name(process),
+    }
+}
+
+fn os_chain(matches: &ArgMatches) -> Result<OsChain<'_>> {
+    let conn_iter = matches
+        .indices_of("connector")
+        .zip(matches.get_many::<String>("connector"))
+        .map(|(a, b)| a.zip(b.map(String::as_str)))
+        .into_iter()
+        .flatten();
+
+    let os_iter = matches
+        .indices_of("os")
+        .zip(matches.get_many::<String>("os"))
+        .map(|(a, b)| a.zip(b.map(String::as_str)))
+        .into_iter()
+        .flatten();
+
+    OsChain::new(conn_iter, os_iter)
+}
+
+/// Arguments selecting the connector and os chain.
+fn chain_args() -> [Arg; 2] {
+    [
+        Arg::new("connector")
+            .long("connector")
+            .short('c')
+            .action(ArgAction::Append)
+            .required(false),
+        Arg::new("os")
+            .long("os")
+            .short('o')
+            .action(ArgAction::Append)
+            .required(true),
+    ]
+}
+
+fn parse_args() -> ArgMatches {
+    let process_arg = Arg::new("process")
+        .long("process")
+        .short('p')
+        .help("name or pid of the process")
+        .action(ArgAction::Set)
+        .required(true);
+
+    let console = Command::new("console")
+        .about("starts an interactive console")
+        .args(chain_args());
+
+    #[cfg(feature = "rustyline")]
+    let console = console.arg(
+        Arg::new("history")
+            .long("history")
+            .help("file the command history is kept in")
+            .action(ArgAction::Set),
+    );
+
+    let cmd = Command::new("memflow-inspect")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .subcommand_required(true)
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .global(true)
+                .action(ArgAction::Count),
+        )
+        .subcommand(Command::new("connectors").about("lists all available plugins"))
+        .subcommand(
+            Command::new("targets")
+                .about("lists all targets of a connector")
+                .arg(Arg::new("connector").required(true)),
+        )
+        .subcommand(
+            Command::new("ps")
+                .about("lists all processes")
+                .args(chain_args()),
+        )
+        .subcommand(
+            Command::new("modules")
+                .about("lists all modules of a process")
+                .args(chain_args())
+                .arg(process_arg.clone()),
+        )
+        .subcommand(
+            Command::new("read")
+                .about("hexdumps memory of a process")
+                .args(chain_args())
+                .arg(process_arg.clone())
+                .arg(Arg::new("address").required(true))
+                .arg(Arg::new("length").required(true)),
+        )
+        .subcommand(console)
+        .subcommand(
+            Command::new("dump")
+                .about("dumps all mapped memory of a process into a directory")
+                .args(chain_args())
+                .arg(process_arg)
+                .arg(Arg::new("output").required(true)),
+        );
+
+    #[cfg(all(unix, feature = "fuse"))]
+    let cmd = cmd.subcommand(
+        Command::new("mount")
+            .about("mounts the processes and physical memory of the target as a filesystem")
+            .args(chain_args())
+            .arg(Arg::new("mountpoint").required(true)),
+    );
+
+    cmd.get_matches()
+}
+
+fn init_logger(matches: &ArgMatches) {
+    let log_level = match matches.get_count("verbose") {
+        0 => Level::Error,
+        1 => Level::Warn,
+        2 => Level::Info,
+        3 => Level::Debug,
+        4 => Level::Trace,
+        _ => Level::Trace,
+    };
+    simplelog::TermLogger::init(
+        log_level.to_level_filter(),
+        simplelog::Config::default(),
+        simplelog::TerminalMode::Stderr,
+        simplelog::ColorChoice::Auto,
+    )
+    .unwrap();
+}
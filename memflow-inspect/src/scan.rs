@@ -0,0 +1,103 @@
+//! Byte pattern scanning.
+//!
+//! Patterns are written as space separated hex bytes where `?` or `??` matches any byte, e.g.
+//! `48 8b 05 ?? ?? ?? ??`.
+
+use memflow::prelude::v1::*;
+
+/// Chunk size used when scanning memory.
+const SCAN_CHUNK_SIZE: usize = 0x10000;
+
+/// Byte pattern with wildcards.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl Pattern {
+    /// Parses a pattern of space separated hex bytes and `??` wildcards.
+    ///
+    /// Returns `None` if the pattern is empty or contains invalid bytes.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let bytes = pattern
+            .split_whitespace()
+            .map(|b| match b {
+                "?" | "??" => Some(None),
+                _ => u8::from_str_radix(b, 16).ok().map(Some),
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        if bytes.is_empty() {
+            None
+        } else {
+            Some(Self { bytes })
+        }
+    }
+
+    /// Returns the length of the pattern in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns true if the pattern is empty.
+    ///
+    /// Parsed patterns are never empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns true if the pattern matches the start of `data`.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(data)
+                .all(|(p, b)| p.map_or(true, |p| p == *b))
+    }
+
+    /// Returns the offsets of all matches within `data`.
+    pub fn find_all<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..(data.len() + 1).saturating_sub(self.len())).filter(move |&i| self.matches(&data[i..]))
+    }
+}
+
+/// Scans `len` bytes starting at `addr` for `pattern`.
+///
+/// Chunks that can not be read at all are skipped, partially readable chunks are scanned with
+/// their unreadable parts zeroed. Matches spanning two chunks are found as well.
+pub fn scan(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    pattern: &Pattern,
+) -> Result<Vec<Address>> {
+    let mut ret = vec![];
+    let mut tail: Vec<u8> = vec![];
+    let mut tail_addr = addr;
+
+    for chunk in mem.read_stream(addr, len, SCAN_CHUNK_SIZE).zero_fill_gaps() {
+        let chunk = chunk?;
+        if chunk.gaps.iter().map(|g| g.1).sum::<umem>() == chunk.data.len() as umem {
+            tail.clear();
+            continue;
+        }
+
+        // prepend the end of the previous chunk to find matches crossing the boundary
+        let (data_addr, data) = if tail.is_empty() {
+            (chunk.address, chunk.data)
+        } else {
+            let mut data = std::mem::take(&mut tail);
+            data.extend_from_slice(&chunk.data);
+            (tail_addr, data)
+        };
+
+        ret.extend(pattern.find_all(&data).map(|off| data_addr + off));
+
+        let keep = std::cmp::min(pattern.len() - 1, data.len());
+        tail = data[data.len() - keep..].to_vec();
+        tail_addr = data_addr + (data.len() - keep);
+    }
+
+    Ok(ret)
+}
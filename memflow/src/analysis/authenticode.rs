@@ -0,0 +1,433 @@
+//! Authenticode hashes of loaded PE images.
+//!
+//! The authenticode hash of a PE file covers the headers and the raw data of all sections, but
+//! leaves out the checksum, the certificate table directory entry and the certificate table
+//! itself. Since the excluded parts are exactly the ones that change when a file is signed, the
+//! hash can be compared against the hashes stored in catalog files or embedded signatures.
+//!
+//! The functions in this module reconstruct the file layout from the loaded image: the headers
+//! are taken from the start of the image, the raw data of each section from its RVA, and
+//! relocations applied by the loader are reverted. The resulting hash matches the hash of the
+//! file on disk as long as
+//! - the sections covered by the hash were not modified at runtime (writable data sections
+//!   usually are, so a mismatch there is not necessarily suspicious),
+//! - all pages of the image are resident, see [`AuthenticodeHash::missing`],
+//! - the file contains no data after the last section other than the certificate table.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::authenticode::authenticode_hash;
+//! use memflow::analysis::hash::HashAlgorithm;
+//!
+//! fn print_hash(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<()> {
+//!     let hash = authenticode_hash(mem, module.base, HashAlgorithm::Sha256)?;
+//!     if hash.is_complete() {
+//!         println!("{}: {}", module.name, hash.digest);
+//!     } else {
+//!         println!("{}: {} bytes not resident", module.name, hash.missing_bytes());
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let module = proc.primary_module().unwrap();
+//! # assert!(print_hash(&mut proc, &module).is_err());
+//! ```
+
+use std::convert::TryInto;
+use std::prelude::v1::*;
+
+use super::hash::{Digest, HashAlgorithm, Hasher};
+use super::pe_map::{apply_relocations, parse_relocations};
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{SectionData, SectionInfo};
+use crate::types::{size, umem, Address};
+
+/// Maximum size of the image headers.
+const MAX_HEADERS_SIZE: usize = size::kb(64);
+
+/// Maximum size of the relocation directory.
+const MAX_RELOCATIONS_SIZE: usize = size::mb(1);
+
+/// Location of the certificate table in the image file.
+///
+/// The certificate table is not mapped when the image is loaded, its location can be used to
+/// extract the signature from a copy of the file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CertificateTable {
+    /// File offset of the table
+    pub offset: u32,
+    /// Size of the table in bytes
+    pub size: u32,
+}
+
+/// Authenticode hash computed from a loaded image.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AuthenticodeHash {
+    /// The computed hash
+    pub digest: Digest,
+    /// Location of the certificate table, `None` for unsigned images
+    pub certificate_table: Option<CertificateTable>,
+    /// Address and length of all ranges that could not be read and were hashed as zeroes
+    pub missing: Vec<(Address, umem)>,
+}
+
+impl AuthenticodeHash {
+    /// Returns true if the whole image could be read.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Returns the number of bytes that could not be read.
+    pub fn missing_bytes(&self) -> umem {
+        self.missing.iter().map(|(_, len)| *len).sum()
+    }
+
+    /// Returns true if the hash is complete and equal to `expected`.
+    pub fn matches(&self, expected: &[u8]) -> bool {
+        self.is_complete() && self.digest.to_vec() == expected
+    }
+}
+
+/// Reads the location of the certificate table of the image loaded at `base`.
+pub fn read_certificate_table(
+    mem: &mut impl MemoryView,
+    base: Address,
+) -> Result<Option<CertificateTable>> {
+    Ok(PeHeaders::read(mem, base)?.certificate_table())
+}
+
+/// Computes the authenticode hash of the image loaded at `base`.
+///
+/// The base address the image was linked for is taken from the loaded headers. Loaders that
+/// update the `ImageBase` field of relocated images have to be handled with
+/// [`authenticode_hash_with_base`] instead.
+pub fn authenticode_hash(
+    mem: &mut impl MemoryView,
+    base: Address,
+    algo: HashAlgorithm,
+) -> Result<AuthenticodeHash> {
+    let headers = PeHeaders::read(mem, base)?;
+    let preferred_base = headers.image_base();
+    hash_image(mem, base, headers, preferred_base, algo)
+}
+
+/// Computes the authenticode hash of the image loaded at `base` that was linked for
+/// `preferred_base`.
+pub fn authenticode_hash_with_base(
+    mem: &mut impl MemoryView,
+    base: Address,
+    preferred_base: u64,
+    algo: HashAlgorithm,
+) -> Result<AuthenticodeHash> {
+    let headers = PeHeaders::read(mem, base)?;
+    hash_image(mem, base, headers, preferred_base, algo)
+}
+
+fn hash_image(
+    mem: &mut impl MemoryView,
+    base: Address,
+    mut headers: PeHeaders,
+    preferred_base: u64,
+    algo: HashAlgorithm,
+) -> Result<AuthenticodeHash> {
+    let delta = (base.to_umem() as u64).wrapping_sub(preferred_base);
+    headers.set_image_base(preferred_base);
+
+    let relocs = if delta != 0 {
+        let (rva, size) = headers.directory(5);
+        if rva != 0 && (size as usize) <= MAX_RELOCATIONS_SIZE {
+            let data = mem
+                .read_raw(base + rva as umem, size as usize)
+                .data_part()?;
+            parse_relocations(&data)
+        } else {
+            vec![]
+        }
+    } else {
+        vec![]
+    };
+
+    let mut hasher = Hasher::new(algo);
+
+    // the headers without the checksum and the certificate table directory entry
+    let data = &headers.data[..headers.size_of_headers];
+    let cert_dir = headers.directory_offset(4);
+    hasher.update(&data[..headers.checksum]);
+    hasher.update(&data[headers.checksum + 4..cert_dir]);
+    hasher.update(&data[cert_dir + 8..]);
+
+    let mut missing = vec![];
+
+    let mut sections = headers.sections.clone();
+    sections.sort_by_key(|s| s.raw_offset);
+    for section in sections.into_iter().filter(|s| s.raw_size != 0) {
+        let mut section_data = SectionData::read(
+            mem,
+            SectionInfo {
+                name: section.name.as_str().into(),
+                base: base + section.rva as umem,
+                size: section.raw_size as umem,
+            },
+        )?;
+
+        apply_relocations(
+            &mut section_data.data,
+            section.rva,
+            &relocs,
+            delta.wrapping_neg(),
+        );
+
+        hasher.update(&section_data.data);
+        missing.append(&mut section_data.missing);
+    }
+
+    Ok(AuthenticodeHash {
+        digest: hasher.finalize(),
+        certificate_table: headers.certificate_table(),
+        missing,
+    })
+}
+
+#[derive(Clone)]
+struct RawSection {
+    name: String,
+    rva: u32,
+    raw_size: u32,
+    raw_offset: u32,
+}
+
+/// Headers of a loaded PE image.
+struct PeHeaders {
+    data: Vec<u8>,
+    /// Offset of the optional header
+    optional: usize,
+    /// Offset of the data directories
+    directories: usize,
+    /// Offset of the checksum
+    checksum: usize,
+    is_64: bool,
+    size_of_headers: usize,
+    sections: Vec<RawSection>,
+}
+
+impl PeHeaders {
+    fn read(mem: &mut impl MemoryView, base: Address) -> Result<Self> {
+        let invalid =
+            |msg: &str| Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile).log_debug(msg);
+
+        let mut data = mem.read_raw(base, size::kb(4)).data()?;
+
+        if data[..2] != *b"MZ" {
+            return Err(invalid("image does not start with a dos header"));
+        }
+
+        let nt = u32_at(&data, 0x3c) as usize;
+        if data.get(nt..nt + 4) != Some(b"PE\0\0") {
+            return Err(invalid("invalid pe signature"));
+        }
+
+        let num_sections = u16_at(&data, nt + 6) as usize;
+        let size_of_optional = u16_at(&data, nt + 20) as usize;
+
+        let optional = nt + 24;
+        let (is_64, directories) = match u16_at(&data, optional) {
+            0x10b => (false, optional + 96),
+            0x20b => (true, optional + 112),
+            _ => return Err(invalid("invalid optional header magic")),
+        };
+
+        let size_of_headers = u32_at(&data, optional + 60) as usize;
+        let section_table = optional + size_of_optional;
+        let section_table_end = section_table + num_sections * 40;
+
+        if size_of_headers > MAX_HEADERS_SIZE
+            || size_of_headers < directories + 5 * 8
+            || size_of_headers < section_table_end
+        {
+            return Err(invalid("invalid size of headers"));
+        }
+
+        if size_of_headers > data.len() {
+            data = mem.read_raw(base, size_of_headers).data()?;
+        }
+
+        let sections = data[section_table..section_table_end]
+            .chunks_exact(40)
+            .map(|s| RawSection {
+                name: String::from_utf8_lossy(&s[..8])
+                    .trim_end_matches('\0')
+                    .to_string(),
+                rva: u32_at(s, 12),
+                raw_size: u32_at(s, 16),
+                raw_offset: u32_at(s, 20),
+            })
+            .collect();
+
+        Ok(Self {
+            data,
+            optional,
+            directories,
+            checksum: optional + 64,
+            is_64,
+            size_of_headers,
+            sections,
+        })
+    }
+
+    fn image_base(&self) -> u64 {
+        if self.is_64 {
+            u64::from_le_bytes(
+                self.data[self.optional + 24..self.optional + 32]
+                    .try_into()
+                    .unwrap(),
+            )
+        } else {
+            u32_at(&self.data, self.optional + 28) as u64
+        }
+    }
+
+    fn set_image_base(&mut self, image_base: u64) {
+        if self.is_64 {
+            self.data[self.optional + 24..self.optional + 32]
+                .copy_from_slice(&image_base.to_le_bytes());
+        } else {
+            self.data[self.optional + 28..self.optional + 32]
+                .copy_from_slice(&(image_base as u32).to_le_bytes());
+        }
+    }
+
+    fn directory_offset(&self, index: usize) -> usize {
+        self.directories + index * 8
+    }
+
+    fn directory(&self, index: usize) -> (u32, u32) {
+        let off = self.directory_offset(index);
+        (u32_at(&self.data, off), u32_at(&self.data, off + 4))
+    }
+
+    fn certificate_table(&self) -> Option<CertificateTable> {
+        // the address of the certificate table is a file offset, not an rva
+        match self.directory(4) {
+            (0, _) | (_, 0) => None,
+            (offset, size) => Some(CertificateTable { offset, size }),
+        }
+    }
+}
+
+fn u16_at(data: &[u8], off: usize) -> u16 {
+    data.get(off..off + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .unwrap_or_default()
+}
+
+fn u32_at(data: &[u8], off: usize) -> u32 {
+    data.get(off..off + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    const PREFERRED_BASE: u64 = 0x1_4000_0000;
+
+    /// Builds the on-disk file of a PE32+ image linked at `PREFERRED_BASE` with a single
+    /// `.text` section at RVA 0x1000, file offset 0x200, containing one relocated pointer at
+    /// RVA 0x1010, and a relocation directory in a `.reloc` section at RVA 0x2000.
+    fn file() -> Vec<u8> {
+        let mut file = vec![0u8; 0x600];
+        let mut put = |off: usize, data: &[u8]| file[off..off + data.len()].copy_from_slice(data);
+
+        put(0, b"MZ");
+        put(0x3c, &0x80u32.to_le_bytes());
+        put(0x80, b"PE\0\0");
+        put(0x86, &2u16.to_le_bytes());
+        put(0x94, &240u16.to_le_bytes());
+
+        let opt = 0x98;
+        put(opt, &0x20bu16.to_le_bytes());
+        put(opt + 24, &PREFERRED_BASE.to_le_bytes());
+        put(opt + 60, &0x200u32.to_le_bytes());
+        put(opt + 64, &0xdeadbeefu32.to_le_bytes());
+        // certificate table and relocation directory
+        put(opt + 112 + 4 * 8, &0x600u32.to_le_bytes());
+        put(opt + 112 + 4 * 8 + 4, &0x80u32.to_le_bytes());
+        put(opt + 112 + 5 * 8, &0x2000u32.to_le_bytes());
+        put(opt + 112 + 5 * 8 + 4, &12u32.to_le_bytes());
+
+        let sections = opt + 240;
+        put(sections, b".text\0\0\0");
+        put(sections + 12, &0x1000u32.to_le_bytes());
+        put(sections + 16, &0x200u32.to_le_bytes());
+        put(sections + 20, &0x200u32.to_le_bytes());
+        put(sections + 40, b".reloc\0\0");
+        put(sections + 40 + 12, &0x2000u32.to_le_bytes());
+        put(sections + 40 + 16, &0x200u32.to_le_bytes());
+        put(sections + 40 + 20, &0x400u32.to_le_bytes());
+
+        put(0x200, &[0x90; 0x10]);
+        put(0x210, &(PREFERRED_BASE + 0x1234).to_le_bytes());
+
+        put(0x400, &0x1000u32.to_le_bytes());
+        put(0x404, &12u32.to_le_bytes());
+        put(0x408, &((10u16 << 12) | 0x10).to_le_bytes());
+
+        file
+    }
+
+    /// Maps `file` as it would be loaded at `base`.
+    fn map(file: &[u8], base: u64) -> Vec<u8> {
+        let mut image = vec![0u8; 0x3000];
+        image[..0x200].copy_from_slice(&file[..0x200]);
+        image[0x1000..0x1200].copy_from_slice(&file[0x200..0x400]);
+        image[0x2000..0x2200].copy_from_slice(&file[0x400..0x600]);
+
+        let ptr = (PREFERRED_BASE + 0x1234).wrapping_add(base.wrapping_sub(PREFERRED_BASE));
+        image[0x1010..0x1018].copy_from_slice(&ptr.to_le_bytes());
+        image
+    }
+
+    fn expected(file: &[u8]) -> Vec<u8> {
+        let mut hasher = Hasher::new(HashAlgorithm::Sha256);
+        hasher.update(&file[..0x98 + 64]);
+        hasher.update(&file[0x98 + 68..0x98 + 112 + 32]);
+        hasher.update(&file[0x98 + 112 + 40..0x600]);
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    fn relocated_image() {
+        let file = file();
+
+        // the image is mapped at a random address, which always differs from the preferred one
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+        proc.write_raw(base, &map(&file, base.to_umem() as u64))
+            .unwrap();
+
+        let hash = authenticode_hash(&mut proc, base, HashAlgorithm::Sha256).unwrap();
+        assert!(hash.is_complete());
+        assert_eq!(
+            hash.certificate_table,
+            Some(CertificateTable {
+                offset: 0x600,
+                size: 0x80
+            })
+        );
+        assert!(hash.matches(&expected(&file)));
+
+        // a modified byte in the code changes the hash
+        proc.write(base + 0x1000_usize, &0xccu8).unwrap();
+        let hash = authenticode_hash(&mut proc, base, HashAlgorithm::Sha256).unwrap();
+        assert!(!hash.matches(&expected(&file)));
+    }
+}
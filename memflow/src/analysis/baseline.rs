@@ -0,0 +1,543 @@
+//! Comparison of a target against a known-good baseline.
+//!
+//! Many individual detections boil down to the question "is this different from a clean
+//! system?". A [`BaselineProfile`] records the parts of a target that are stable across reboots
+//! of the same installation:
+//!
+//! * the names of the running processes and the modules loaded into them
+//! * hashes of the module headers
+//! * the list of loaded drivers
+//! * the system call table, as module and offset of every handler
+//!
+//! Base addresses are not recorded, as they change with every boot. A live target is recorded
+//! the same way and compared with [`BaselineProfile::compare`], which emits structured
+//! [`Drift`] findings. Profiles can be persisted by wrapping them in a
+//! [`Report`](crate::os::report::Report).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::baseline::{BaselineConfig, BaselineProfile};
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//!
+//! fn check(os: &mut impl Os, baseline: &BaselineProfile) -> Result<()> {
+//!     let live = BaselineProfile::record(os, &BaselineConfig::default())?;
+//!     for drift in baseline.compare(&live) {
+//!         println!("{:?}: {}", drift.severity(), drift);
+//!     }
+//!     Ok(())
+//! }
+//! # let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! # os.alloc_process(size::mb(1), &[]);
+//! # let baseline = BaselineProfile::record(&mut os, &BaselineConfig::default()).unwrap();
+//! # check(&mut os, &baseline).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::hash::{hash_range, Digest, HashAlgorithm};
+use crate::error::Result;
+use crate::mem::MemoryView;
+use crate::os::report::Severity;
+use crate::os::syscalls::SyscallTable;
+use crate::os::{ModuleInfo, Os, Process};
+use crate::types::{size, umem};
+
+/// Parts of a target recorded in a [`BaselineProfile`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BaselineConfig {
+    /// Record the processes and their modules
+    pub processes: bool,
+    /// Record the loaded drivers
+    pub drivers: bool,
+    /// Number of bytes hashed at the start of every process module, 0 disables hashing
+    pub hash_size: umem,
+    /// Algorithm used for hashing modules
+    pub hash_algorithm: HashAlgorithm,
+}
+
+impl Default for BaselineConfig {
+    fn default() -> Self {
+        Self {
+            processes: true,
+            drivers: true,
+            hash_size: size::kb(4) as umem,
+            hash_algorithm: HashAlgorithm::Sha256,
+        }
+    }
+}
+
+/// Recorded state of a single module or driver.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ModuleBaseline {
+    /// Name of the module
+    pub name: String,
+    /// Path of the module
+    pub path: String,
+    /// Size of the module
+    pub size: umem,
+    /// Hash of the start of the module, if hashing was enabled
+    pub hash: Option<Digest>,
+}
+
+impl ModuleBaseline {
+    fn new(module: &ModuleInfo, hash: Option<Digest>) -> Self {
+        Self {
+            name: module.name.to_string(),
+            path: module.path.to_string(),
+            size: module.size,
+            hash,
+        }
+    }
+}
+
+/// Recorded location of a system call handler.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SyscallBaseline {
+    /// Name of the system call
+    pub name: String,
+    /// Name of the driver containing the handler, empty if it is not part of any driver
+    pub module: String,
+    /// Offset of the handler from the start of the driver, its address if it is not part of any
+    /// driver
+    pub offset: umem,
+}
+
+impl fmt::Display for SyscallBaseline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.module.is_empty() {
+            write!(f, "{} at {:#x}", self.name, self.offset)
+        } else {
+            write!(f, "{} at {}+{:#x}", self.name, self.module, self.offset)
+        }
+    }
+}
+
+/// Recorded state of a target.
+///
+/// Processes, modules and drivers are keyed by their lowercase name. The modules of all
+/// instances of a process are merged.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BaselineProfile {
+    /// Processes and the modules loaded into them
+    pub processes: BTreeMap<String, BTreeMap<String, ModuleBaseline>>,
+    /// Loaded drivers
+    pub drivers: BTreeMap<String, ModuleBaseline>,
+    /// System call handlers by system call number
+    pub syscalls: BTreeMap<u32, SyscallBaseline>,
+}
+
+impl BaselineProfile {
+    /// Records the profile of `os`.
+    ///
+    /// Processes whose module list can not be read are recorded without modules.
+    pub fn record(os: &mut impl Os, config: &BaselineConfig) -> Result<Self> {
+        let mut profile = Self::default();
+
+        if config.processes {
+            for info in os.process_info_list()? {
+                let modules = profile
+                    .processes
+                    .entry(info.name.to_lowercase())
+                    .or_default();
+
+                let mut proc = match os.process_by_info(info) {
+                    Ok(proc) => proc,
+                    Err(_) => continue,
+                };
+                for module in proc.module_list().unwrap_or_default() {
+                    let key = module.name.to_lowercase();
+                    if modules.contains_key(&key) {
+                        continue;
+                    }
+                    let hash = hash_module(&mut proc, &module, config);
+                    modules.insert(key, ModuleBaseline::new(&module, hash));
+                }
+            }
+        }
+
+        if config.drivers {
+            profile.drivers = os
+                .module_list()?
+                .iter()
+                .map(|m| (m.name.to_lowercase(), ModuleBaseline::new(m, None)))
+                .collect();
+        }
+
+        Ok(profile)
+    }
+
+    /// Records the handlers of a system call table, e.g. read with
+    /// [`SyscallTable::from_service_table`].
+    ///
+    /// Handlers are recorded relative to the driver containing them, which makes them
+    /// comparable across reboots.
+    pub fn record_syscalls(&mut self, table: &SyscallTable, drivers: &[ModuleInfo]) {
+        self.syscalls = table
+            .entries()
+            .map(|entry| {
+                let (module, offset) = drivers
+                    .iter()
+                    .find(|m| m.contains(entry.address))
+                    .map(|m| (m.name.to_string(), (entry.address - m.base) as umem))
+                    .unwrap_or_else(|| (String::new(), entry.address.to_umem()));
+                (
+                    entry.number,
+                    SyscallBaseline {
+                        name: entry.name.clone(),
+                        module,
+                        offset,
+                    },
+                )
+            })
+            .collect();
+    }
+
+    /// Compares the `live` profile of a target against this baseline.
+    ///
+    /// Only parts recorded in both profiles are compared, e.g. drivers are not reported as
+    /// removed if the live profile was recorded without them.
+    pub fn compare(&self, live: &BaselineProfile) -> Vec<Drift> {
+        let mut drifts = vec![];
+
+        if !self.processes.is_empty() && !live.processes.is_empty() {
+            for (name, expected) in self.processes.iter() {
+                match live.processes.get(name) {
+                    Some(found) => compare_modules(
+                        Some(name),
+                        DriftSubject::Module,
+                        expected,
+                        found,
+                        &mut drifts,
+                    ),
+                    None => drifts.push(Drift::new(
+                        DriftKind::Removed,
+                        DriftSubject::Process,
+                        None,
+                        name,
+                    )),
+                }
+            }
+            for name in live.processes.keys() {
+                if !self.processes.contains_key(name) {
+                    drifts.push(Drift::new(
+                        DriftKind::Added,
+                        DriftSubject::Process,
+                        None,
+                        name,
+                    ));
+                }
+            }
+        }
+
+        if !self.drivers.is_empty() && !live.drivers.is_empty() {
+            compare_modules(
+                None,
+                DriftSubject::Driver,
+                &self.drivers,
+                &live.drivers,
+                &mut drifts,
+            );
+        }
+
+        if !self.syscalls.is_empty() && !live.syscalls.is_empty() {
+            for (number, expected) in self.syscalls.iter() {
+                let found = live.syscalls.get(number);
+                if found != Some(expected) {
+                    drifts.push(Drift {
+                        kind: if found.is_some() {
+                            DriftKind::Changed
+                        } else {
+                            DriftKind::Removed
+                        },
+                        subject: DriftSubject::Syscall,
+                        process: None,
+                        name: format!("{:#x}", number),
+                        expected: Some(expected.to_string()),
+                        found: found.map(ToString::to_string),
+                    });
+                }
+            }
+            for (number, found) in live.syscalls.iter() {
+                if !self.syscalls.contains_key(number) {
+                    drifts.push(Drift {
+                        kind: DriftKind::Added,
+                        subject: DriftSubject::Syscall,
+                        process: None,
+                        name: format!("{:#x}", number),
+                        expected: None,
+                        found: Some(found.to_string()),
+                    });
+                }
+            }
+        }
+
+        drifts
+    }
+}
+
+/// Kind of a deviation from the baseline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum DriftKind {
+    /// The object is not part of the baseline
+    Added,
+    /// The object of the baseline is missing
+    Removed,
+    /// The object differs from the baseline
+    Changed,
+}
+
+/// Kind of the object that deviates from the baseline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum DriftSubject {
+    Process,
+    Module,
+    Driver,
+    Syscall,
+}
+
+/// A deviation of a live target from its baseline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Drift {
+    /// Kind of the deviation
+    pub kind: DriftKind,
+    /// Kind of the deviating object
+    pub subject: DriftSubject,
+    /// Name of the process the module belongs to
+    pub process: Option<String>,
+    /// Name of the deviating object, the system call number for system calls
+    pub name: String,
+    /// Description of the baseline state, for changed and removed objects
+    pub expected: Option<String>,
+    /// Description of the live state, for changed and added objects
+    pub found: Option<String>,
+}
+
+impl Drift {
+    fn new(kind: DriftKind, subject: DriftSubject, process: Option<&String>, name: &str) -> Self {
+        Self {
+            kind,
+            subject,
+            process: process.cloned(),
+            name: name.to_string(),
+            expected: None,
+            found: None,
+        }
+    }
+
+    /// Returns the severity of the deviation.
+    ///
+    /// New drivers, modified modules and changed system call handlers are suspicious, other
+    /// deviations are usually caused by regular usage of the system.
+    pub fn severity(&self) -> Severity {
+        match (self.subject, self.kind) {
+            (DriftSubject::Driver, DriftKind::Added)
+            | (DriftSubject::Driver, DriftKind::Changed)
+            | (DriftSubject::Module, DriftKind::Changed)
+            | (DriftSubject::Syscall, _) => Severity::Suspicious,
+            _ => Severity::Info,
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            DriftKind::Added => "added",
+            DriftKind::Removed => "removed",
+            DriftKind::Changed => "changed",
+        };
+        write!(f, "{:?} {} {}", self.subject, self.name, kind)?;
+        if let Some(process) = &self.process {
+            write!(f, " in {}", process)?;
+        }
+        match (&self.expected, &self.found) {
+            (Some(expected), Some(found)) => write!(f, ": expected {}, found {}", expected, found),
+            (None, Some(found)) => write!(f, ": {}", found),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn hash_module(
+    mem: &mut impl MemoryView,
+    module: &ModuleInfo,
+    config: &BaselineConfig,
+) -> Option<Digest> {
+    if config.hash_size == 0 {
+        return None;
+    }
+    let len = std::cmp::min(config.hash_size, module.size);
+    hash_range(mem, module.base, len, config.hash_algorithm).ok()
+}
+
+fn compare_modules(
+    process: Option<&String>,
+    subject: DriftSubject,
+    expected: &BTreeMap<String, ModuleBaseline>,
+    found: &BTreeMap<String, ModuleBaseline>,
+    drifts: &mut Vec<Drift>,
+) {
+    for (name, module) in expected.iter() {
+        let live = match found.get(name) {
+            Some(live) => live,
+            None => {
+                drifts.push(Drift::new(DriftKind::Removed, subject, process, name));
+                continue;
+            }
+        };
+
+        let mut changed = |expected: String, found: String| {
+            let mut drift = Drift::new(DriftKind::Changed, subject, process, name);
+            drift.expected = Some(expected);
+            drift.found = Some(found);
+            drifts.push(drift);
+        };
+
+        if !module.path.eq_ignore_ascii_case(&live.path) {
+            changed(
+                format!("path {}", module.path),
+                format!("path {}", live.path),
+            );
+        }
+        if module.size != live.size {
+            changed(
+                format!("size {:#x}", module.size),
+                format!("size {:#x}", live.size),
+            );
+        }
+        if let (Some(a), Some(b)) = (module.hash, live.hash) {
+            if a != b {
+                changed(format!("hash {}", a), format!("hash {}", b));
+            }
+        }
+    }
+
+    for (name, module) in found.iter() {
+        if !expected.contains_key(name) {
+            let mut drift = Drift::new(DriftKind::Added, subject, process, name);
+            drift.found = Some(module.path.clone());
+            drifts.push(drift);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::ArchitectureIdent;
+    use crate::os::syscalls::SyscallEntry;
+    use crate::types::Address;
+
+    fn module(name: &str, size: umem, hash: u32) -> (String, ModuleBaseline) {
+        (
+            name.to_lowercase(),
+            ModuleBaseline {
+                name: name.to_string(),
+                path: format!("C:\\Windows\\System32\\{}", name),
+                size,
+                hash: Some(Digest::Crc32(hash)),
+            },
+        )
+    }
+
+    fn profile() -> BaselineProfile {
+        let mut profile = BaselineProfile::default();
+        profile.processes.insert(
+            "explorer.exe".to_string(),
+            vec![
+                module("ntdll.dll", 0x1000, 1),
+                module("kernel32.dll", 0x2000, 2),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        profile.drivers = vec![module("ntoskrnl.exe", 0x100000, 3)]
+            .into_iter()
+            .collect();
+        profile
+    }
+
+    #[test]
+    fn identical_profiles() {
+        assert!(profile().compare(&profile()).is_empty());
+    }
+
+    #[test]
+    fn detect_drift() {
+        let baseline = profile();
+        let mut live = profile();
+
+        let explorer = live.processes.get_mut("explorer.exe").unwrap();
+        explorer.get_mut("ntdll.dll").unwrap().hash = Some(Digest::Crc32(5));
+        explorer.remove("kernel32.dll");
+        live.drivers.extend(vec![module("evil.sys", 0x1000, 4)]);
+        live.processes
+            .insert("cmd.exe".to_string(), BTreeMap::new());
+
+        let drifts = baseline.compare(&live);
+        assert_eq!(drifts.len(), 4);
+
+        let hash = drifts
+            .iter()
+            .find(|d| d.name == "ntdll.dll" && d.kind == DriftKind::Changed)
+            .unwrap();
+        assert_eq!(hash.process.as_deref(), Some("explorer.exe"));
+        assert_eq!(hash.severity(), Severity::Suspicious);
+
+        let driver = drifts
+            .iter()
+            .find(|d| d.subject == DriftSubject::Driver)
+            .unwrap();
+        assert_eq!(driver.kind, DriftKind::Added);
+        assert_eq!(driver.severity(), Severity::Suspicious);
+
+        assert!(drifts
+            .iter()
+            .any(|d| d.subject == DriftSubject::Process && d.name == "cmd.exe"));
+    }
+
+    #[test]
+    fn detect_syscall_hook() {
+        let drivers = vec![ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: Address::from(0x100000u64),
+            size: 0x100000,
+            name: "ntoskrnl.exe".into(),
+            path: "ntoskrnl.exe".into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }];
+        let table = |handler: u64| {
+            SyscallTable::new(vec![SyscallEntry {
+                number: 0x55,
+                name: "NtCreateFile".to_string(),
+                address: Address::from(handler),
+            }])
+        };
+
+        let mut baseline = BaselineProfile::default();
+        baseline.record_syscalls(&table(0x101000), &drivers);
+        let mut live = BaselineProfile::default();
+        live.record_syscalls(&table(0x900000), &drivers);
+
+        let drifts = baseline.compare(&live);
+        assert_eq!(drifts.len(), 1);
+        assert_eq!(drifts[0].subject, DriftSubject::Syscall);
+        assert_eq!(
+            drifts[0].expected.as_deref(),
+            Some("NtCreateFile at ntoskrnl.exe+0x1000")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Carving of console screen buffers and command history.
+//!
+//! Console host processes (`conhost.exe`) keep the screen buffer of every attached console in
+//! memory as rows of fixed width UTF-16 cells, while PowerShell (through PSReadLine) keeps its
+//! command history as managed `System.String` objects on the .NET heap. Both survive long after
+//! the commands finished and are a primary source during triage of a live system.
+//!
+//! Since the exact structure layouts differ between Windows builds, this module does not rely on
+//! them and carves the data heuristically instead:
+//!
+//! - [`carve_screen_buffers`] finds runs of printable UTF-16 text spanning multiple rows of a
+//!   given console width.
+//! - [`carve_dotnet_strings`] finds .NET string objects, identified by their length prefix
+//!   matching the printable text that follows it.
+//! - [`carve_utf16_strings`] finds all printable UTF-16 strings.
+//!
+//! Only printable ASCII characters and tabs are considered text, which keeps the number of false
+//! positives on random data low.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::console::triage_consoles;
+//!
+//! fn print_consoles(os: &mut (impl Os + Clone)) -> Result<()> {
+//!     for console in triage_consoles(os)? {
+//!         println!("{} ({}):", console.process.name, console.process.pid);
+//!         for buffer in console.screen_buffers.iter() {
+//!             buffer.lines.iter().for_each(|l| println!("  {}", l));
+//!         }
+//!         for string in console.strings.iter() {
+//!             println!("  {}: {}", string.address, string.text);
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # print_consoles(&mut DummyOs::new(DummyMemory::new(size::mb(4)))).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{Os, Process, ProcessInfo};
+use crate::types::{umem, Address};
+
+/// Console widths tried by [`triage_consoles`].
+pub const DEFAULT_CONSOLE_WIDTHS: [usize; 2] = [80, 120];
+
+/// Minimum length of strings carved by [`triage_consoles`].
+pub const DEFAULT_MIN_STRING_LEN: usize = 4;
+
+/// Chunk size used when scanning memory.
+const SCAN_CHUNK_SIZE: usize = 0x100000;
+
+/// String carved from memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CarvedString {
+    /// Address of the first character
+    pub address: Address,
+    /// Contents of the string
+    pub text: String,
+}
+
+/// Console screen buffer carved from memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScreenBuffer {
+    /// Address of the first row
+    pub address: Address,
+    /// Width of the console in characters
+    pub width: usize,
+    /// Contents of all rows, with trailing spaces removed
+    pub lines: Vec<String>,
+}
+
+/// Console related data carved from a single process.
+#[derive(Clone, Debug)]
+pub struct ConsoleTriage {
+    /// The process the data was carved from
+    pub process: ProcessInfo,
+    /// Screen buffers found in console host processes
+    pub screen_buffers: Vec<ScreenBuffer>,
+    /// .NET strings found in PowerShell processes, including the command history
+    pub strings: Vec<CarvedString>,
+}
+
+/// Carves all printable UTF-16 strings of at least `min_len` characters.
+///
+/// Strings are expected to be 2 byte aligned.
+pub fn carve_utf16_strings(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    min_len: usize,
+) -> Result<Vec<CarvedString>> {
+    Ok(utf16_runs(mem, addr, len, std::cmp::max(min_len, 1))?
+        .into_iter()
+        .map(|(address, chars)| CarvedString {
+            address,
+            text: String::from_utf16_lossy(&chars),
+        })
+        .collect())
+}
+
+/// Carves all .NET `System.String` objects of at least `min_len` printable characters.
+///
+/// The returned addresses point to the first character of each string. A string is only
+/// reported if the 32-bit length field preceding it matches the length of its printable text.
+pub fn carve_dotnet_strings(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    min_len: usize,
+) -> Result<Vec<CarvedString>> {
+    let mut ret = vec![];
+
+    // the length prefix always contains a null character, so strings start at the start of a run
+    for (address, chars) in utf16_runs(mem, addr, len, std::cmp::max(min_len, 1))? {
+        if let Ok(str_len) = mem.read::<u32>(address - 4usize).data_part() {
+            if str_len as usize == chars.len() {
+                ret.push(CarvedString {
+                    address,
+                    text: String::from_utf16_lossy(&chars),
+                });
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Carves screen buffers of consoles that are `width` characters wide.
+///
+/// A screen buffer is a run of printable text spanning at least two full rows. Partial rows at
+/// the start or end of a run are not included.
+pub fn carve_screen_buffers(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    width: usize,
+) -> Result<Vec<ScreenBuffer>> {
+    Ok(utf16_runs(mem, addr, len, width * 2)?
+        .into_iter()
+        .filter_map(|(address, chars)| {
+            let lines = chars
+                .chunks_exact(width)
+                .map(|row| String::from_utf16_lossy(row).trim_end().to_string())
+                .collect::<Vec<_>>();
+
+            // consoles pad every row with spaces, require at least one padded row
+            if chars
+                .chunks_exact(width)
+                .any(|row| row[width - 1] == b' ' as u16)
+            {
+                Some(ScreenBuffer {
+                    address,
+                    width,
+                    lines,
+                })
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+/// Carves screen buffers and .NET strings from all console host and PowerShell processes.
+///
+/// Screen buffers are carved from `conhost.exe` processes for all [`DEFAULT_CONSOLE_WIDTHS`],
+/// strings are carved from `powershell.exe` and `pwsh.exe` processes.
+pub fn triage_consoles(os: &mut (impl Os + Clone)) -> Result<Vec<ConsoleTriage>> {
+    let mut ret = vec![];
+
+    for info in os.process_info_list()? {
+        let name = info.name.as_ref().to_ascii_lowercase();
+        let (conhost, powershell) = (
+            name == "conhost.exe",
+            name == "powershell.exe" || name == "pwsh.exe",
+        );
+        if !conhost && !powershell {
+            continue;
+        }
+
+        let mut proc = os.clone().into_process_by_info(info.clone())?;
+        let mut screen_buffers = vec![];
+        let mut strings = vec![];
+
+        for range in proc.mapped_mem_vec(0) {
+            if conhost {
+                for width in DEFAULT_CONSOLE_WIDTHS.iter() {
+                    screen_buffers
+                        .extend(carve_screen_buffers(&mut proc, range.0, range.1, *width)?);
+                }
+            } else {
+                strings.extend(carve_dotnet_strings(
+                    &mut proc,
+                    range.0,
+                    range.1,
+                    DEFAULT_MIN_STRING_LEN,
+                )?);
+            }
+        }
+
+        ret.push(ConsoleTriage {
+            process: info,
+            screen_buffers,
+            strings,
+        });
+    }
+
+    Ok(ret)
+}
+
+/// Returns all runs of at least `min_len` printable UTF-16 characters.
+fn utf16_runs(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    min_len: usize,
+) -> Result<Vec<(Address, Vec<u16>)>> {
+    let mut ret = vec![];
+    let mut run: Vec<u16> = vec![];
+    let mut run_addr = Address::null();
+
+    // strings are 2 byte aligned
+    let start = addr + (addr.to_umem() & 1);
+    let len = len.saturating_sub(addr.to_umem() & 1) & !1;
+
+    for chunk in mem
+        .read_stream(start, len, SCAN_CHUNK_SIZE)
+        .zero_fill_gaps()
+    {
+        let chunk = chunk?;
+
+        for (i, c) in chunk.data.chunks_exact(2).enumerate() {
+            let c = u16::from_le_bytes([c[0], c[1]]);
+
+            if c == b'\t' as u16 || (0x20..0x7f).contains(&c) {
+                if run.is_empty() {
+                    run_addr = chunk.address + i * 2;
+                }
+                run.push(c);
+            } else if !run.is_empty() {
+                if run.len() >= min_len {
+                    ret.push((run_addr, std::mem::take(&mut run)));
+                }
+                run.clear();
+            }
+        }
+    }
+
+    if run.len() >= min_len {
+        ret.push((run_addr, run));
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn dotnet_strings() {
+        let mut buf = vec![0u8; 8];
+        // method table pointer, length, characters, null terminator
+        buf.extend_from_slice(&0x7ff8_1234_5678u64.to_le_bytes());
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend(utf16("whoami /a"));
+        buf.extend_from_slice(&[0, 0]);
+        // plain string without a matching length prefix
+        buf.extend(utf16("not a dotnet string"));
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let addr = proc.info().address;
+
+        let strings = carve_dotnet_strings(&mut proc, addr, buf.len() as umem, 4).unwrap();
+        assert_eq!(
+            strings,
+            vec![CarvedString {
+                address: addr + 20,
+                text: "whoami /a".to_string(),
+            }]
+        );
+
+        let all = carve_utf16_strings(&mut proc, addr, buf.len() as umem, 4).unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn screen_buffer() {
+        let mut text = format!("{:<10}{:<10}", "C:\\> dir", "file.txt");
+        text.push_str("abc");
+        let mut buf = vec![0u8; 2];
+        buf.extend(utf16(&text));
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let addr = proc.info().address;
+
+        let buffers = carve_screen_buffers(&mut proc, addr, buf.len() as umem, 10).unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(buffers[0].address, addr + 2);
+        assert_eq!(buffers[0].lines, vec!["C:\\> dir", "file.txt"]);
+    }
+}
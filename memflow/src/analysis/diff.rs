@@ -0,0 +1,330 @@
+//! Comparison of two address spaces.
+//!
+//! Compares two views of the same target, e.g. a live process and a snapshot of it taken before
+//! running a sample, and produces a structured report of what changed. Changes are reported at
+//! three levels of granularity:
+//!
+//! * modules that were loaded, unloaded or relocated
+//! * pages that were mapped, unmapped or modified
+//! * byte ranges that differ inside of modified pages
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::diff::{diff_processes, PageChangeKind};
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn print_changes(before: &mut (impl Process + MemoryView), after: &mut (impl Process + MemoryView)) -> Result<()> {
+//!     let diff = diff_processes(before, after)?;
+//!
+//!     for module in diff.modules.added.iter() {
+//!         println!("loaded {} at {}", module.name, module.base);
+//!     }
+//!
+//!     for page in diff.pages.iter().filter(|p| p.kind == PageChangeKind::Modified) {
+//!         println!("{}: {} modified ranges", page.address, page.bytes.len());
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let mut proc2 = DummyOs::quick_process(size::mb(2), &[]);
+//! # print_changes(&mut proc, &mut proc2).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::CTup2;
+use crate::error::Result;
+use crate::mem::{progress, MemoryView, Progress, ProgressCallback};
+use crate::os::{ModuleInfo, Process};
+use crate::types::{size, umem, Address};
+
+/// Module level differences between two address spaces.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ModuleDiff {
+    /// Modules only present in the second address space
+    pub added: Vec<ModuleInfo>,
+    /// Modules only present in the first address space
+    pub removed: Vec<ModuleInfo>,
+    /// Modules present in both address spaces, but with a different base address or size
+    pub changed: Vec<(ModuleInfo, ModuleInfo)>,
+}
+
+impl ModuleDiff {
+    /// Returns true if the module lists are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Type of change of a single page.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum PageChangeKind {
+    /// The page is only readable in the second address space
+    Mapped,
+    /// The page is only readable in the first address space
+    Unmapped,
+    /// The page is readable in both address spaces, but its contents differ
+    Modified,
+}
+
+/// Contiguous range of bytes that differs between two address spaces.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ByteChange {
+    /// Address of the first differing byte
+    pub address: Address,
+    /// Bytes in the first address space
+    pub old: Vec<u8>,
+    /// Bytes in the second address space
+    pub new: Vec<u8>,
+}
+
+/// Change of a single page.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageChange {
+    /// Address of the page
+    pub address: Address,
+    /// Type of change
+    pub kind: PageChangeKind,
+    /// Differing byte ranges, only filled in for [`PageChangeKind::Modified`] pages
+    pub bytes: Vec<ByteChange>,
+}
+
+/// Report of all differences between two address spaces.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AddressSpaceDiff {
+    /// Module level differences
+    pub modules: ModuleDiff,
+    /// Page level differences in ascending order
+    pub pages: Vec<PageChange>,
+}
+
+/// Compares two module lists.
+///
+/// Modules are matched by their name and architecture.
+pub fn diff_modules(old: &[ModuleInfo], new: &[ModuleInfo]) -> ModuleDiff {
+    let key = |m: &ModuleInfo| (m.name.to_string(), m.arch);
+    let find =
+        |list: &[ModuleInfo], m: &ModuleInfo| list.iter().find(|o| key(o) == key(m)).cloned();
+
+    let mut ret = ModuleDiff::default();
+
+    for m in old {
+        match find(new, m) {
+            None => ret.removed.push(m.clone()),
+            Some(n) if n.base != m.base || n.size != m.size => ret.changed.push((m.clone(), n)),
+            _ => {}
+        }
+    }
+
+    ret.added = new
+        .iter()
+        .filter(|m| find(old, m).is_none())
+        .cloned()
+        .collect();
+
+    ret
+}
+
+/// Compares the given ranges of two memory views page by page.
+///
+/// Ranges are extended to page boundaries. Pages that can not be read in either view are not
+/// reported.
+pub fn diff_memory(
+    old: &mut impl MemoryView,
+    new: &mut impl MemoryView,
+    ranges: &[CTup2<Address, umem>],
+) -> Result<Vec<PageChange>> {
+    diff_memory_with_progress(old, new, ranges, None)
+}
+
+/// Compares the given ranges of two memory views page by page, reporting the progress to
+/// `progress`.
+///
+/// The progress counts the bytes of the page aligned ranges. See
+/// [`progress`](crate::mem::progress) for details.
+pub fn diff_memory_with_progress(
+    old: &mut impl MemoryView,
+    new: &mut impl MemoryView,
+    ranges: &[CTup2<Address, umem>],
+    mut progress: Option<ProgressCallback>,
+) -> Result<Vec<PageChange>> {
+    let page_size = size::kb(4);
+    let mut ret = vec![];
+
+    let ranges = merge_ranges(ranges, page_size);
+    let total = ranges.iter().map(|r| r.1).sum::<umem>();
+    let mut done = 0;
+
+    for CTup2(addr, len) in ranges {
+        let old_pages = old.read_stream(addr, len, page_size).zero_fill_gaps();
+        let new_pages = new.read_stream(addr, len, page_size).zero_fill_gaps();
+
+        for (a, b) in old_pages.zip(new_pages) {
+            let (a, b) = (a?, b?);
+
+            done += a.data.len() as umem;
+            progress::report(&mut progress, Progress::new(done, total))?;
+
+            let kind = match (is_unreadable(&a), is_unreadable(&b)) {
+                (true, true) => continue,
+                (true, false) => PageChangeKind::Mapped,
+                (false, true) => PageChangeKind::Unmapped,
+                (false, false) if a.data != b.data => PageChangeKind::Modified,
+                _ => continue,
+            };
+
+            let bytes = if kind == PageChangeKind::Modified {
+                diff_bytes(a.address, &a.data, &b.data)
+            } else {
+                vec![]
+            };
+
+            ret.push(PageChange {
+                address: a.address,
+                kind,
+                bytes,
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Compares the module lists and all mapped memory of two processes.
+pub fn diff_processes<A, B>(old: &mut A, new: &mut B) -> Result<AddressSpaceDiff>
+where
+    A: Process + MemoryView,
+    B: Process + MemoryView,
+{
+    let modules = diff_modules(&old.module_list()?, &new.module_list()?);
+
+    let ranges = old
+        .mapped_mem_vec(0)
+        .into_iter()
+        .chain(new.mapped_mem_vec(0).into_iter())
+        .map(|r| CTup2(r.0, r.1))
+        .collect::<Vec<_>>();
+
+    let pages = diff_memory(old, new, &ranges)?;
+
+    Ok(AddressSpaceDiff { modules, pages })
+}
+
+fn is_unreadable(chunk: &crate::mem::memory_view::StreamChunk) -> bool {
+    chunk.gaps.iter().map(|g| g.1).sum::<umem>() == chunk.data.len() as umem
+}
+
+/// Aligns all ranges to page boundaries and merges overlapping ones.
+fn merge_ranges(ranges: &[CTup2<Address, umem>], page_size: usize) -> Vec<CTup2<Address, umem>> {
+    let mut aligned = ranges
+        .iter()
+        .filter(|r| r.1 > 0)
+        .map(|&CTup2(addr, len)| {
+            let start = addr.as_page_aligned(page_size);
+            let end = (addr + len + page_size - 1usize).as_page_aligned(page_size);
+            (start, end)
+        })
+        .collect::<Vec<_>>();
+    aligned.sort_unstable();
+
+    let mut merged: Vec<(Address, Address)> = vec![];
+    for (start, end) in aligned {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = std::cmp::max(last.1, end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(start, end)| CTup2(start, (end - start) as umem))
+        .collect()
+}
+
+fn diff_bytes(base: Address, old: &[u8], new: &[u8]) -> Vec<ByteChange> {
+    let mut ret: Vec<ByteChange> = vec![];
+    let mut prev = None;
+
+    for (i, (&a, &b)) in old.iter().zip(new.iter()).enumerate() {
+        if a == b {
+            continue;
+        }
+
+        match ret.last_mut() {
+            Some(change) if prev.map_or(false, |p| p + 1 == i) => {
+                change.old.push(a);
+                change.new.push(b);
+            }
+            _ => ret.push(ByteChange {
+                address: base + i,
+                old: vec![a],
+                new: vec![b],
+            }),
+        }
+
+        prev = Some(i);
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::prelude::v1::*;
+
+    #[test]
+    fn byte_ranges() {
+        let changes = diff_bytes(Address::null(), &[0, 1, 2, 3, 4], &[0, 9, 9, 3, 9]);
+
+        assert_eq!(
+            changes,
+            vec![
+                ByteChange {
+                    address: 1u64.into(),
+                    old: vec![1, 2],
+                    new: vec![9, 9],
+                },
+                ByteChange {
+                    address: 4u64.into(),
+                    old: vec![4],
+                    new: vec![9],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn modified_page() {
+        // identically seeded OS instances produce identical address spaces
+        let process = || {
+            let mut os = DummyOs::with_seed(DummyMemory::new(size::mb(4)), 1);
+            let pid = os.alloc_process(size::mb(2), &[0; 0x2000]);
+            os.into_process_by_pid(pid).unwrap()
+        };
+
+        let mut old = process();
+        let mut new = process();
+        let addr = old.info().address;
+        let ranges = [CTup2(addr, 0x2000)];
+
+        assert!(diff_memory(&mut old, &mut new, &ranges).unwrap().is_empty());
+
+        new.write(addr + 0x1008usize, &0xffu8).unwrap();
+
+        let changes = diff_memory(&mut old, &mut new, &ranges).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].address, addr + 0x1000usize);
+        assert_eq!(changes[0].kind, PageChangeKind::Modified);
+        assert_eq!(changes[0].bytes[0].address, addr + 0x1008usize);
+    }
+}
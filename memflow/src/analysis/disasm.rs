@@ -0,0 +1,253 @@
+//! Disassembly of code residing in a [`MemoryView`].
+//!
+//! The [`Disassembler`] reads code page by page while decoding it, so instructions crossing a
+//! page boundary are decoded correctly and pages that can not be read are reported as
+//! [`Decoded::Unreadable`] gaps instead of aborting the whole pass.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::disasm::{Decoded, Disassembler};
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn print_code(mem: &mut impl MemoryView, addr: Address) -> Result<()> {
+//!     let disasm = Disassembler::new(mem, ArchitectureIdent::X86(64, false), addr)?
+//!         .with_size(3);
+//!
+//!     for decoded in disasm {
+//!         match decoded {
+//!             Decoded::Instruction(instr) => println!("{:x} {}", instr.ip(), instr),
+//!             Decoded::Unreadable { address, size } => println!("{} ?? ({:x})", address, size),
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[0x90, 0x90, 0xc3]);
+//! # let addr = proc.info().address;
+//! # print_code(&mut proc, addr).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use iced_x86::{Decoder, DecoderError, DecoderOptions, Instruction};
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::types::{size, umem, Address};
+
+/// Item yielded by the [`Disassembler`].
+#[derive(Clone, Debug)]
+pub enum Decoded {
+    /// Successfully decoded instruction.
+    ///
+    /// Bytes that do not form a valid instruction are yielded as an invalid instruction
+    /// (see [`Instruction::is_invalid`]).
+    Instruction(Instruction),
+    /// Range of memory that could not be read.
+    ///
+    /// If an instruction is cut off by an unreadable page, the range starts at the instruction
+    /// and spans up to the end of the unreadable page.
+    Unreadable { address: Address, size: umem },
+}
+
+/// Iterator decoding instructions from a [`MemoryView`].
+///
+/// Decoding starts at the given address and continues until the end address is reached, or
+/// indefinitely if no end has been set.
+///
+/// # Remarks
+///
+/// Only x86 code is supported at the moment.
+pub struct Disassembler<'a, T> {
+    mem: &'a mut T,
+    bitness: u32,
+    page_size: usize,
+    ip: Address,
+    end: Option<Address>,
+    buf: Vec<u8>,
+    buf_addr: Address,
+    buf_len: usize,
+}
+
+impl<'a, T: MemoryView> Disassembler<'a, T> {
+    /// Creates a new disassembler decoding code of the given architecture starting at `start`.
+    pub fn new(mem: &'a mut T, arch: ArchitectureIdent, start: Address) -> Result<Self> {
+        let bitness = match arch {
+            ArchitectureIdent::X86(bits, _) => bits as u32,
+            _ => {
+                return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArchitecture)
+                    .log_debug("disassembly is only supported on x86"))
+            }
+        };
+
+        let page_size = size::kb(4);
+
+        Ok(Self {
+            mem,
+            bitness,
+            page_size,
+            ip: start,
+            end: None,
+            buf: vec![0; page_size * 2],
+            buf_addr: Address::null(),
+            buf_len: 0,
+        })
+    }
+
+    /// Stops decoding once `end` has been reached.
+    ///
+    /// The last instruction may extend past `end`.
+    pub fn with_end(mut self, end: Address) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Stops decoding after `size` bytes.
+    ///
+    /// The last instruction may extend past the range.
+    pub fn with_size(self, size: umem) -> Self {
+        let end = self.ip + size;
+        self.with_end(end)
+    }
+
+    /// Returns the address of the next instruction that will be decoded.
+    pub fn ip(&self) -> Address {
+        self.ip
+    }
+
+    /// Continues decoding at `ip`.
+    pub fn set_ip(&mut self, ip: Address) {
+        self.ip = ip;
+    }
+
+    /// Reads the page containing `ip` and the page right after it into the buffer.
+    ///
+    /// Any instruction starting in the first page is guaranteed to fit into the buffer, unless the
+    /// second page is unreadable.
+    fn fill(&mut self) -> bool {
+        let page_size = self.page_size;
+        let page = self.ip.as_page_aligned(page_size);
+
+        self.buf_addr = page;
+        self.buf_len = 0;
+
+        let (first, second) = self.buf.split_at_mut(page_size);
+
+        if self.mem.read_raw_into(page, first).data().is_err() {
+            return false;
+        }
+        self.buf_len = page_size;
+
+        if self
+            .mem
+            .read_raw_into(page + page_size, second)
+            .data()
+            .is_ok()
+        {
+            self.buf_len += page_size;
+        }
+
+        true
+    }
+
+    fn buffered(&self) -> bool {
+        self.buf_len > 0 && self.ip >= self.buf_addr && self.ip < self.buf_addr + self.page_size
+    }
+
+    fn unreadable(&mut self, end: Address) -> Decoded {
+        let address = self.ip;
+        self.ip = end;
+        Decoded::Unreadable {
+            address,
+            size: (end - address) as umem,
+        }
+    }
+}
+
+impl<'a, T: MemoryView> Iterator for Disassembler<'a, T> {
+    type Item = Decoded;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(end) = self.end {
+            if self.ip >= end {
+                return None;
+            }
+        }
+
+        if !self.buffered() && !self.fill() {
+            let next_page = self.buf_addr + self.page_size;
+            return Some(self.unreadable(next_page));
+        }
+
+        let offset = (self.ip - self.buf_addr) as usize;
+        let mut decoder = Decoder::with_ip(
+            self.bitness,
+            &self.buf[offset..self.buf_len],
+            self.ip.to_umem() as u64,
+            DecoderOptions::NONE,
+        );
+
+        let instr = decoder.decode();
+
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            // the instruction continues into an unreadable page
+            let unreadable_end = self.buf_addr + self.buf_len + self.page_size;
+            return Some(self.unreadable(unreadable_end));
+        }
+
+        self.ip += instr.len().max(1);
+
+        Some(Decoded::Instruction(instr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::prelude::v1::*;
+    use iced_x86::Mnemonic;
+
+    #[test]
+    fn decode_range() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0x90, 0x48, 0x89, 0xc8, 0xc3]);
+        let addr = proc.info().address;
+
+        let mnemonics = Disassembler::new(&mut proc, ArchitectureIdent::X86(64, false), addr)
+            .unwrap()
+            .with_size(5)
+            .map(|d| match d {
+                Decoded::Instruction(i) => i.mnemonic(),
+                Decoded::Unreadable { .. } => panic!("unexpected gap"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(mnemonics, [Mnemonic::Nop, Mnemonic::Mov, Mnemonic::Ret]);
+    }
+
+    #[test]
+    fn unreadable_gap() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+
+        let mut disasm = Disassembler::new(
+            &mut proc,
+            ArchitectureIdent::X86(64, false),
+            Address::null(),
+        )
+        .unwrap()
+        .with_size(0x1000);
+
+        match disasm.next() {
+            Some(Decoded::Unreadable { address, size }) => {
+                assert_eq!(address, Address::null());
+                assert_eq!(size, 0x1000);
+            }
+            d => panic!("unexpected item {:?}", d),
+        }
+
+        assert!(disasm.next().is_none());
+    }
+}
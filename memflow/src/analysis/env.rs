@@ -0,0 +1,201 @@
+//! Search of process environment variables.
+//!
+//! Environment variables are a common place for secrets like cloud credentials or API tokens.
+//! [`find_env_var`] reads the [environment block](crate::os::Process::environment_block) of every
+//! process of a target and returns all variables whose name matches a glob pattern, which makes
+//! secret scanning across a fleet of machines a single call per target.
+//!
+//! Each block is fetched with a single read, which the memory layers split into batched page
+//! reads. Processes whose block can not be located or read are skipped.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::env::find_env_var;
+//!
+//! fn aws_secrets(os: &mut (impl Os + Clone)) -> Result<()> {
+//!     for m in find_env_var(os, "AWS_SECRET*")? {
+//!         println!("{} ({}): {}={}", m.process, m.pid, m.var.name, m.var.value);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # aws_secrets(&mut DummyOs::new(DummyMemory::new(size::mb(4)))).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{Os, Pid, Process};
+use crate::types::umem;
+
+/// Upper bound for the size of environment blocks, larger blocks are truncated.
+pub const MAX_ENVIRONMENT_SIZE: umem = 0x100000;
+
+/// Single environment variable.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+/// Environment variable found by [`find_env_var`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct EnvMatch {
+    /// Id of the owning process
+    pub pid: Pid,
+    /// Name of the owning process
+    pub process: String,
+    /// The matching variable
+    pub var: EnvVar,
+}
+
+/// Parses an environment block of null terminated `NAME=VALUE` entries.
+///
+/// Parsing stops at the first empty entry or at the end of `data`. Entries without a `=` are
+/// skipped. Windows specific entries like `=C:=C:\` keep their leading `=` in the name.
+pub fn parse_environment_block(data: &[u8], wide: bool) -> Vec<EnvVar> {
+    let entries = if wide {
+        let chars = data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        chars
+            .split(|&c| c == 0)
+            .take_while(|e| !e.is_empty())
+            .map(String::from_utf16_lossy)
+            .collect::<Vec<_>>()
+    } else {
+        data.split(|&c| c == 0)
+            .take_while(|e| !e.is_empty())
+            .map(|e| String::from_utf8_lossy(e).into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let split = entry.get(1..)?.find('=')? + 1;
+            Some(EnvVar {
+                name: entry[..split].to_string(),
+                value: entry[split + 1..].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads all environment variables of `proc`.
+pub fn read_environment(proc: &mut (impl Process + MemoryView)) -> Result<Vec<EnvVar>> {
+    let block = proc.environment_block()?;
+    if block.address.is_null() {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+    }
+
+    let size = std::cmp::min(block.size, MAX_ENVIRONMENT_SIZE) as usize;
+    let data = proc.read_raw(block.address, size).data_part()?;
+    Ok(parse_environment_block(&data, block.wide))
+}
+
+/// Returns all environment variables of all processes whose name matches `pattern`.
+///
+/// `pattern` is a glob supporting `*` and `?` wildcards and is matched case-insensitively, since
+/// environment variable names are case-insensitive on Windows.
+pub fn find_env_var(os: &mut (impl Os + Clone), pattern: &str) -> Result<Vec<EnvMatch>> {
+    let mut ret = vec![];
+
+    for info in os.process_info_list()? {
+        if info.state.is_dead() {
+            continue;
+        }
+
+        let (pid, process) = (info.pid, info.name.to_string());
+        let vars = match os
+            .clone()
+            .into_process_by_info(info)
+            .and_then(|mut proc| read_environment(&mut proc))
+        {
+            Ok(vars) => vars,
+            Err(_) => continue,
+        };
+
+        ret.extend(
+            vars.into_iter()
+                .filter(|var| glob_match(pattern, &var.name))
+                .map(|var| EnvMatch {
+                    pid,
+                    process: process.clone(),
+                    var,
+                }),
+        );
+    }
+
+    Ok(ret)
+}
+
+/// Matches `name` against a glob `pattern` with `*` and `?` wildcards, ignoring ASCII case.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // position in the pattern after the last `*` and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bn)) => {
+                    backtrack = Some((bp, bn + 1));
+                    p = bp;
+                    n = bn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_wide() {
+        let block = "=C:=C:\\Windows\0AWS_SECRET_ACCESS_KEY=abc=def\0PATH=C:\\\0\0garbage=1\0"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let vars = parse_environment_block(&block, true);
+        assert_eq!(vars.len(), 3);
+        assert_eq!(vars[0].name, "=C:");
+        assert_eq!(vars[0].value, "C:\\Windows");
+        assert_eq!(vars[1].value, "abc=def");
+
+        assert_eq!(parse_environment_block(b"A=1\0B=\0", false).len(), 2);
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("AWS_SECRET*", "aws_secret_access_key"));
+        assert!(glob_match("*TOKEN*", "GITHUB_TOKEN"));
+        assert!(glob_match("?ATH", "PATH"));
+        assert!(!glob_match("AWS_SECRET*", "AWS_REGION"));
+        assert!(!glob_match("PATH", "PATHEXT"));
+    }
+}
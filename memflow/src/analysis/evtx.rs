@@ -0,0 +1,357 @@
+//! Carving of Windows event log (EVTX) data from memory.
+//!
+//! The Windows event log service (`wevtsvc.dll`, hosted in a `svchost.exe` instance started
+//! with `-s EventLog`) keeps the chunks of all open log files mapped in its address space.
+//! Recent log entries can therefore be recovered from memory even after the log files on disk
+//! were cleared or tampered with.
+//!
+//! EVTX files are made up of 64 KiB chunks, each starting with the `ElfChnk\0` signature followed
+//! by a header and a sequence of event records. [`carve_evtx_chunks`] finds these chunks in any
+//! memory view and [`EvtxChunk::records`] yields their records. Records whose chunk header has
+//! already been overwritten can be found individually through [`carve_evtx_records`].
+//!
+//! Records are returned raw: the event data is kept in its binary XML encoding, which can be fed
+//! into any EVTX parser, or written back into a chunk to form a valid EVTX file.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::evtx::{carve_process_evtx, find_eventlog_processes};
+//!
+//! fn recent_records(os: &mut (impl Os + Clone)) -> Result<()> {
+//!     for info in find_eventlog_processes(os)? {
+//!         let mut proc = os.clone().into_process_by_info(info)?;
+//!
+//!         for chunk in carve_process_evtx(&mut proc)? {
+//!             for record in chunk.records() {
+//!                 println!("{} @ {}: {} bytes", record.id, record.timestamp, record.data.len());
+//!             }
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # recent_records(&mut DummyOs::new(DummyMemory::new(size::mb(4)))).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{Os, Process, ProcessInfo};
+use crate::types::{umem, Address};
+
+/// Size of a single EVTX chunk.
+pub const EVTX_CHUNK_SIZE: usize = 0x10000;
+
+/// Size of the EVTX chunk header, including the string and template tables.
+pub const EVTX_CHUNK_HEADER_SIZE: usize = 0x200;
+
+/// Signature at the start of every chunk.
+const CHUNK_SIGNATURE: &[u8; 8] = b"ElfChnk\0";
+
+/// Signature at the start of every event record.
+const RECORD_SIGNATURE: &[u8; 4] = b"**\0\0";
+
+/// Size of the fixed record fields surrounding the event data.
+const RECORD_OVERHEAD: usize = 28;
+
+/// Chunk size used when scanning memory.
+const SCAN_CHUNK_SIZE: usize = 0x100000;
+
+/// EVTX chunk carved from memory.
+#[derive(Clone, Debug)]
+pub struct EvtxChunk {
+    /// Address the chunk was found at
+    pub address: Address,
+    /// Id of the first record in the chunk
+    pub first_record_id: u64,
+    /// Id of the last record in the chunk
+    pub last_record_id: u64,
+    /// True if the header and record checksums match the contents
+    pub valid_checksums: bool,
+    /// Raw contents of the chunk
+    pub data: Vec<u8>,
+}
+
+impl EvtxChunk {
+    /// Parses the chunk header of `data`, which was read at `address`.
+    ///
+    /// Returns `None` if `data` does not start with a chunk signature or is too short.
+    pub fn parse(address: Address, data: Vec<u8>) -> Option<Self> {
+        if data.len() < EVTX_CHUNK_HEADER_SIZE || !data.starts_with(CHUNK_SIGNATURE) {
+            return None;
+        }
+
+        let header_crc = crc32(&[&data[..120], &data[128..EVTX_CHUNK_HEADER_SIZE]]);
+        let records_end = std::cmp::min(read_u32(&data, 48) as usize, data.len());
+        let records_crc =
+            crc32(&[&data[EVTX_CHUNK_HEADER_SIZE..records_end.max(EVTX_CHUNK_HEADER_SIZE)]]);
+
+        Some(Self {
+            address,
+            first_record_id: read_u64(&data, 24),
+            last_record_id: read_u64(&data, 32),
+            valid_checksums: header_crc == read_u32(&data, 124)
+                && records_crc == read_u32(&data, 52),
+            data,
+        })
+    }
+
+    /// Returns all valid records stored in the chunk.
+    pub fn records(&self) -> impl Iterator<Item = EvtxRecord> + '_ {
+        let end = std::cmp::min(read_u32(&self.data, 48) as usize, self.data.len());
+        let mut offset = EVTX_CHUNK_HEADER_SIZE;
+
+        std::iter::from_fn(move || {
+            if offset >= end {
+                return None;
+            }
+
+            let record = EvtxRecord::parse(self.address + offset, &self.data[offset..end])?;
+            offset += record.data.len() + RECORD_OVERHEAD;
+            Some(record)
+        })
+    }
+}
+
+/// Event record carved from memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EvtxRecord {
+    /// Address the record was found at
+    pub address: Address,
+    /// Id of the record
+    pub id: u64,
+    /// Time the record was written, as a Windows `FILETIME`
+    pub timestamp: u64,
+    /// Event data encoded as binary XML
+    pub data: Vec<u8>,
+}
+
+impl EvtxRecord {
+    /// Parses the record at the start of `data`, which was read at `address`.
+    ///
+    /// Returns `None` if `data` does not start with a complete, valid record.
+    pub fn parse(address: Address, data: &[u8]) -> Option<Self> {
+        if data.len() < RECORD_OVERHEAD || !data.starts_with(RECORD_SIGNATURE) {
+            return None;
+        }
+
+        let size = read_u32(data, 4) as usize;
+        if size < RECORD_OVERHEAD || size > data.len() || read_u32(data, size - 4) as usize != size
+        {
+            return None;
+        }
+
+        Some(Self {
+            address,
+            id: read_u64(data, 8),
+            timestamp: read_u64(data, 16),
+            data: data[24..size - 4].to_vec(),
+        })
+    }
+
+    /// Returns the time the record was written in seconds since the unix epoch.
+    pub fn unix_time(&self) -> Option<u64> {
+        (self.timestamp / 10_000_000).checked_sub(11_644_473_600)
+    }
+}
+
+/// Returns all processes that host the event log service, identified by their command line.
+pub fn find_eventlog_processes(os: &mut impl Os) -> Result<Vec<ProcessInfo>> {
+    Ok(os
+        .process_info_list()?
+        .into_iter()
+        .filter(|p| {
+            p.name.as_ref().eq_ignore_ascii_case("svchost.exe")
+                && p.command_line.as_ref().contains("EventLog")
+        })
+        .collect())
+}
+
+/// Carves all EVTX chunks from the mapped memory of `proc`.
+pub fn carve_process_evtx(proc: &mut (impl Process + MemoryView)) -> Result<Vec<EvtxChunk>> {
+    let mut ret = vec![];
+
+    for range in proc.mapped_mem_vec(0) {
+        ret.extend(carve_evtx_chunks(proc, range.0, range.1)?);
+    }
+
+    Ok(ret)
+}
+
+/// Carves all EVTX chunks starting within `len` bytes from `addr`.
+///
+/// Chunks are expected to be 8 byte aligned. Unreadable memory is skipped.
+pub fn carve_evtx_chunks(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+) -> Result<Vec<EvtxChunk>> {
+    let mut ret = vec![];
+
+    for chunk_addr in find_signatures(mem, addr, len, CHUNK_SIGNATURE)? {
+        let mut data = vec![0u8; EVTX_CHUNK_SIZE];
+        if mem.read_raw_into(chunk_addr, &mut data).data_part().is_ok() {
+            ret.extend(EvtxChunk::parse(chunk_addr, data));
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Carves all event records starting within `len` bytes from `addr`, whether they are part of
+/// a valid chunk or not.
+///
+/// Records are expected to be 8 byte aligned. Unreadable memory is skipped.
+pub fn carve_evtx_records(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+) -> Result<Vec<EvtxRecord>> {
+    let mut ret = vec![];
+
+    for record_addr in find_signatures(mem, addr, len, RECORD_SIGNATURE)? {
+        let mut header = [0u8; 8];
+        if mem
+            .read_raw_into(record_addr, &mut header)
+            .data_part()
+            .is_err()
+        {
+            continue;
+        }
+
+        let size = read_u32(&header, 4) as usize;
+        if !(RECORD_OVERHEAD..=EVTX_CHUNK_SIZE - EVTX_CHUNK_HEADER_SIZE).contains(&size) {
+            continue;
+        }
+
+        let mut data = vec![0u8; size];
+        if mem
+            .read_raw_into(record_addr, &mut data)
+            .data_part()
+            .is_ok()
+        {
+            ret.extend(EvtxRecord::parse(record_addr, &data));
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Returns the 8 byte aligned addresses of all occurrences of `signature`.
+fn find_signatures(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    signature: &[u8],
+) -> Result<Vec<Address>> {
+    let mut ret = vec![];
+
+    for chunk in mem.read_stream(addr, len, SCAN_CHUNK_SIZE).zero_fill_gaps() {
+        let chunk = chunk?;
+        let skip = (8 - (chunk.address.to_umem() % 8) as usize) % 8;
+
+        ret.extend(
+            (skip..chunk.data.len())
+                .step_by(8)
+                .filter(|&off| chunk.data[off..].starts_with(signature))
+                .map(|off| chunk.address + off),
+        );
+    }
+
+    Ok(ret)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+/// CRC32 (IEEE) over the concatenation of `parts`, as used by EVTX checksums.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &b in parts.iter().flat_map(|p| p.iter()) {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    fn record(id: u64, payload: &[u8]) -> Vec<u8> {
+        let size = (payload.len() + RECORD_OVERHEAD) as u32;
+        let mut ret = RECORD_SIGNATURE.to_vec();
+        ret.extend_from_slice(&size.to_le_bytes());
+        ret.extend_from_slice(&id.to_le_bytes());
+        ret.extend_from_slice(&132223104000000000u64.to_le_bytes());
+        ret.extend_from_slice(payload);
+        ret.extend_from_slice(&size.to_le_bytes());
+        ret
+    }
+
+    fn chunk(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; EVTX_CHUNK_SIZE];
+        data[..8].copy_from_slice(CHUNK_SIGNATURE);
+        data[24..32].copy_from_slice(&1u64.to_le_bytes());
+        data[32..40].copy_from_slice(&(records.len() as u64).to_le_bytes());
+
+        let mut offset = EVTX_CHUNK_HEADER_SIZE;
+        for r in records {
+            data[offset..offset + r.len()].copy_from_slice(r);
+            offset += r.len();
+        }
+
+        data[48..52].copy_from_slice(&(offset as u32).to_le_bytes());
+        let records_crc = crc32(&[&data[EVTX_CHUNK_HEADER_SIZE..offset]]);
+        data[52..56].copy_from_slice(&records_crc.to_le_bytes());
+        let header_crc = crc32(&[&data[..120], &data[128..EVTX_CHUNK_HEADER_SIZE]]);
+        data[124..128].copy_from_slice(&header_crc.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xcbf43926);
+    }
+
+    #[test]
+    fn carve_chunk() {
+        let records = [record(1, &[0x0f, 0x01, 0x01, 0x00]), record(2, &[0x0f; 12])];
+        let mut buf = vec![0u8; 0x1000];
+        buf.extend(chunk(&records));
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let addr = proc.info().address;
+
+        let chunks = carve_evtx_chunks(&mut proc, addr, buf.len() as umem).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].address, addr + 0x1000);
+        assert!(chunks[0].valid_checksums);
+
+        let parsed = chunks[0].records().collect::<Vec<_>>();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].id, 2);
+        assert_eq!(parsed[1].data, vec![0x0f; 12]);
+        assert_eq!(parsed[0].unix_time(), Some(1577836800));
+
+        let loose = carve_evtx_records(&mut proc, addr, buf.len() as umem).unwrap();
+        assert_eq!(loose, parsed);
+    }
+}
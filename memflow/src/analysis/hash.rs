@@ -0,0 +1,327 @@
+//! Checksums and hashes over memory ranges.
+//!
+//! [`hash_range`] computes a single digest over a range of memory, while [`hash_pages`] produces
+//! one digest per page plus a root digest over all pages. Comparing the root digests of two runs
+//! tells whether anything changed at all, comparing the page digests tells which pages changed.
+//!
+//! All reads are streamed in chunks, so arbitrarily large ranges can be hashed with constant
+//! memory usage. Memory that can not be read is hashed as zeroes.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::hash::{hash_pages, HashAlgorithm};
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn changed_pages(mem: &mut impl MemoryView, addr: Address) -> Result<Vec<Address>> {
+//!     let before = hash_pages(mem, addr, size::kb(16) as umem, HashAlgorithm::Crc32)?;
+//!
+//!     mem.write(addr + size::kb(4), &0xdeadbeefu32).data_part()?;
+//!
+//!     let after = hash_pages(mem, addr, size::kb(16) as umem, HashAlgorithm::Crc32)?;
+//!     Ok(before.diff(&after))
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # assert_eq!(changed_pages(&mut proc, addr).unwrap(), vec![addr + size::kb(4)]);
+//! ```
+
+use std::prelude::v1::*;
+
+use sha2::Digest as _;
+
+use crate::error::Result;
+use crate::mem::{progress, MemoryView, ProgressCallback};
+use crate::types::{size, umem, Address};
+
+/// Size of the chunks read while hashing a range.
+const HASH_CHUNK_SIZE: usize = size::mb(2);
+
+/// Supported hash algorithms.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum HashAlgorithm {
+    /// CRC-32 (IEEE) checksum. Fast, but only suitable for change detection.
+    Crc32,
+    /// SHA-256 digest.
+    Sha256,
+}
+
+/// Digest produced by one of the [`HashAlgorithm`]s.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Digest {
+    Crc32(u32),
+    Sha256([u8; 32]),
+}
+
+impl Digest {
+    /// Returns the raw bytes of the digest.
+    pub fn to_vec(&self) -> Vec<u8> {
+        match self {
+            Digest::Crc32(crc) => crc.to_be_bytes().to_vec(),
+            Digest::Sha256(hash) => hash.to_vec(),
+        }
+    }
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_vec()
+            .iter()
+            .try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+pub(crate) enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(algo: HashAlgorithm) -> Self {
+        match algo {
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Digest {
+        match self {
+            Hasher::Crc32(h) => Digest::Crc32(h.finalize()),
+            Hasher::Sha256(h) => Digest::Sha256(h.finalize().into()),
+        }
+    }
+}
+
+/// Hashes `len` bytes of memory starting at `addr`.
+///
+/// Unreadable memory is hashed as zeroes.
+pub fn hash_range(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    algo: HashAlgorithm,
+) -> Result<Digest> {
+    hash_range_with_progress(mem, addr, len, algo, None)
+}
+
+/// Hashes `len` bytes of memory starting at `addr`, reporting the progress to `progress`.
+///
+/// See [`progress`](crate::mem::progress) for details.
+pub fn hash_range_with_progress(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    algo: HashAlgorithm,
+    mut progress: Option<ProgressCallback>,
+) -> Result<Digest> {
+    let mut hasher = Hasher::new(algo);
+
+    let mut stream = mem.read_stream(addr, len, HASH_CHUNK_SIZE).zero_fill_gaps();
+    while let Some(chunk) = stream.next() {
+        hasher.update(&chunk?.data);
+        progress::report(&mut progress, stream.progress())?;
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// Digest of a single page.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageDigest {
+    /// Address of the page
+    pub address: Address,
+    /// Digest of the page contents, `None` if the page could not be read at all
+    pub digest: Option<Digest>,
+}
+
+/// List of page digests with a root digest over all pages.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageDigests {
+    /// Algorithm used for all digests
+    pub algo: HashAlgorithm,
+    /// Digest over the concatenated page digests
+    pub root: Digest,
+    /// Digests of all pages in ascending order
+    pub pages: Vec<PageDigest>,
+}
+
+impl PageDigests {
+    /// Returns the addresses of all pages whose digest differs between `self` and `other`.
+    ///
+    /// Pages that are only present in one of the lists are considered changed as well.
+    pub fn diff(&self, other: &PageDigests) -> Vec<Address> {
+        if self.root == other.root && self.pages.len() == other.pages.len() {
+            return vec![];
+        }
+
+        let mut ret = vec![];
+        let (mut a, mut b) = (self.pages.iter().peekable(), other.pages.iter().peekable());
+
+        loop {
+            match (a.peek(), b.peek()) {
+                (Some(pa), Some(pb)) if pa.address == pb.address => {
+                    if pa.digest != pb.digest {
+                        ret.push(pa.address);
+                    }
+                    a.next();
+                    b.next();
+                }
+                (Some(pa), Some(pb)) if pa.address < pb.address => {
+                    ret.push(pa.address);
+                    a.next();
+                }
+                (_, Some(pb)) => {
+                    ret.push(pb.address);
+                    b.next();
+                }
+                (Some(pa), None) => {
+                    ret.push(pa.address);
+                    a.next();
+                }
+                (None, None) => break,
+            }
+        }
+
+        ret
+    }
+}
+
+/// Hashes every page in the range of `len` bytes starting at `addr`.
+///
+/// The range is extended to page boundaries. Partially readable pages are hashed with their
+/// unreadable parts zeroed, pages that can not be read at all get no digest.
+pub fn hash_pages(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    algo: HashAlgorithm,
+) -> Result<PageDigests> {
+    hash_pages_with_progress(mem, addr, len, algo, None)
+}
+
+/// Hashes every page in the range of `len` bytes starting at `addr`, reporting the progress to
+/// `progress`.
+///
+/// See [`progress`](crate::mem::progress) for details.
+pub fn hash_pages_with_progress(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    len: umem,
+    algo: HashAlgorithm,
+    mut progress: Option<ProgressCallback>,
+) -> Result<PageDigests> {
+    let page_size = size::kb(4);
+    let start = addr.as_page_aligned(page_size);
+    let end = (addr + len + page_size - 1usize).as_page_aligned(page_size);
+
+    let mut root = Hasher::new(algo);
+    let mut pages = vec![];
+
+    let mut stream = mem
+        .read_stream(start, (end - start) as umem, page_size)
+        .zero_fill_gaps();
+    while let Some(chunk) = stream.next() {
+        let chunk = chunk?;
+        progress::report(&mut progress, stream.progress())?;
+
+        let unreadable = chunk.gaps.iter().map(|g| g.1).sum::<umem>() == chunk.data.len() as umem;
+
+        let digest = if unreadable {
+            root.update(&[0]);
+            None
+        } else {
+            let mut hasher = Hasher::new(algo);
+            hasher.update(&chunk.data);
+            let digest = hasher.finalize();
+
+            root.update(&[1]);
+            root.update(&digest.to_vec());
+            Some(digest)
+        };
+
+        pages.push(PageDigest {
+            address: chunk.address,
+            digest,
+        });
+    }
+
+    Ok(PageDigests {
+        algo,
+        root: root.finalize(),
+        pages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::prelude::v1::*;
+
+    #[test]
+    fn known_digests() {
+        let mut proc = DummyOs::quick_process(size::mb(2), b"123456789");
+        let addr = proc.info().address;
+
+        assert_eq!(
+            hash_range(&mut proc, addr, 9, HashAlgorithm::Crc32).unwrap(),
+            Digest::Crc32(0xcbf43926)
+        );
+        assert_eq!(
+            hash_range(&mut proc, addr, 9, HashAlgorithm::Sha256)
+                .unwrap()
+                .to_string(),
+            "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225"
+        );
+    }
+
+    #[test]
+    fn unreadable_pages() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+
+        let digests = hash_pages(&mut proc, Address::null(), 0x2000, HashAlgorithm::Crc32).unwrap();
+
+        assert_eq!(digests.pages.len(), 2);
+        assert!(digests.pages.iter().all(|p| p.digest.is_none()));
+        assert!(digests.diff(&digests).is_empty());
+    }
+
+    #[test]
+    fn cancel_progress() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let addr = proc.info().address;
+
+        let mut reports = vec![];
+        let mut callback = |p: Progress| {
+            reports.push(p);
+            p.done < 0x2000
+        };
+
+        let err = hash_pages_with_progress(
+            &mut proc,
+            addr,
+            0x4000,
+            HashAlgorithm::Crc32,
+            Some((&mut callback).into()),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.1, ErrorKind::Cancelled);
+        assert_eq!(
+            reports,
+            vec![Progress::new(0x1000, 0x4000), Progress::new(0x2000, 0x4000)]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Inline hook and trampoline detection.
+//!
+//! Detours are usually installed by overwriting the first few instructions of a function with a
+//! branch into a trampoline that lives outside of the hooked module. This pass decodes the
+//! prologue of every export of a module and reports all branches that leave the module,
+//! including absolute jumps through a register loaded with an immediate (`mov rax, imm; jmp rax`).
+
+use std::prelude::v1::*;
+
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction, Mnemonic, OpKind, Register};
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::{ModuleInfo, Process};
+use crate::types::Address;
+
+/// Number of bytes that are decoded at the start of each export by default.
+pub const DEFAULT_PROLOGUE_SIZE: usize = 16;
+
+/// Type of the instruction sequence used to leave the module.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum HookKind {
+    /// Direct `jmp rel`
+    Jmp,
+    /// Direct `call rel`
+    Call,
+    /// Indirect `jmp [mem]`, the target is read from memory
+    IndirectJmp,
+    /// Indirect `call [mem]`, the target is read from memory
+    IndirectCall,
+    /// `push imm; ret` sequence
+    PushRet,
+    /// `mov reg, imm; jmp reg` sequence
+    RegisterJmp,
+}
+
+/// Detour found in the prologue of an exported function.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct InlineHook {
+    /// Name of the hooked export
+    pub export: String,
+    /// Address of the hooked export
+    pub export_address: Address,
+    /// Address of the branch instruction
+    pub address: Address,
+    /// Address the branch leads to
+    pub target: Address,
+    /// Type of branch
+    pub kind: HookKind,
+    /// Name of the module containing `target`, if any
+    pub target_module: Option<String>,
+}
+
+/// Scans the exports of `module` for branches leaving the module.
+///
+/// `prologue_size` bytes are decoded at the start of each export. Decoding of an export stops at
+/// the first return, undecodable instruction or at the end of the prologue. Exports whose
+/// prologue can not be read are skipped.
+///
+/// # Remarks
+///
+/// Only x86 modules are supported at the moment.
+pub fn find_inline_hooks<P: Process + MemoryView>(
+    proc: &mut P,
+    module: &ModuleInfo,
+    prologue_size: usize,
+) -> Result<Vec<InlineHook>> {
+    let bitness = match module.arch {
+        ArchitectureIdent::X86(bits, _) => bits as u32,
+        _ => {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArchitecture)
+                .log_debug("inline hook detection is only supported on x86"))
+        }
+    };
+
+    let modules = proc.module_list().unwrap_or_default();
+    let exports = proc.module_export_list(module)?;
+
+    let mut prologue = vec![0u8; prologue_size];
+    let mut ret = vec![];
+
+    for export in exports.iter() {
+        if export.offset >= module.size {
+            // forwarded exports do not point into the module
+            continue;
+        }
+
+        let export_address = module.base + export.offset;
+
+        if proc
+            .read_raw_into(export_address, &mut prologue)
+            .data()
+            .is_err()
+        {
+            continue;
+        }
+
+        for (address, target, kind) in prologue_branches(proc, bitness, export_address, &prologue) {
+            if module.contains(target) {
+                continue;
+            }
+
+            ret.push(InlineHook {
+                export: export.name.to_string(),
+                export_address,
+                address,
+                target,
+                kind,
+                target_module: modules
+                    .iter()
+                    .find(|m| m.contains(target))
+                    .map(|m| m.name.to_string()),
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Decodes `code` located at `ip` and returns all branches with a resolvable target.
+fn prologue_branches(
+    mem: &mut impl MemoryView,
+    bitness: u32,
+    ip: Address,
+    code: &[u8],
+) -> Vec<(Address, Address, HookKind)> {
+    let mut decoder = Decoder::with_ip(bitness, code, ip.to_umem() as u64, DecoderOptions::NONE);
+    let mut instr = Instruction::default();
+    let mut pushed = None;
+    let mut loaded = None;
+    let mut ret = vec![];
+
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instr);
+
+        if instr.is_invalid() {
+            break;
+        }
+
+        let ip = Address::from(instr.ip());
+
+        let branch = match instr.flow_control() {
+            FlowControl::UnconditionalBranch if is_near_branch(&instr) => {
+                Some((ip, Address::from(instr.near_branch_target()), HookKind::Jmp))
+            }
+            FlowControl::Call if is_near_branch(&instr) => Some((
+                ip,
+                Address::from(instr.near_branch_target()),
+                HookKind::Call,
+            )),
+            FlowControl::IndirectBranch if instr.op0_kind() == OpKind::Register => loaded
+                .filter(|&(reg, _)| reg == instr.op0_register().full_register())
+                .map(|(_, t)| (ip, t, HookKind::RegisterJmp)),
+            FlowControl::IndirectBranch => {
+                indirect_target(mem, bitness, &instr).map(|t| (ip, t, HookKind::IndirectJmp))
+            }
+            FlowControl::IndirectCall => {
+                indirect_target(mem, bitness, &instr).map(|t| (ip, t, HookKind::IndirectCall))
+            }
+            FlowControl::Return => pushed.take(),
+            _ => None,
+        };
+
+        if let Some(branch) = branch {
+            ret.push(branch);
+        }
+
+        match instr.flow_control() {
+            FlowControl::UnconditionalBranch
+            | FlowControl::IndirectBranch
+            | FlowControl::Return
+            | FlowControl::Exception => break,
+            _ => {}
+        }
+
+        pushed = if instr.mnemonic() == Mnemonic::Push
+            && matches!(
+                instr.op0_kind(),
+                OpKind::Immediate32 | OpKind::Immediate32to64 | OpKind::Immediate8to32
+            ) {
+            Some((
+                ip,
+                Address::from(instr.immediate(0) & 0xffff_ffff),
+                HookKind::PushRet,
+            ))
+        } else {
+            None
+        };
+
+        loaded = if instr.mnemonic() == Mnemonic::Mov
+            && instr.op0_kind() == OpKind::Register
+            && matches!(
+                instr.op1_kind(),
+                OpKind::Immediate32 | OpKind::Immediate32to64 | OpKind::Immediate64
+            ) {
+            Some((
+                instr.op0_register().full_register(),
+                Address::from(instr.immediate(1)),
+            ))
+        } else {
+            loaded.filter(|&(reg, _): &(Register, Address)| {
+                // any other write to the register invalidates the loaded value
+                !(instr.op0_kind() == OpKind::Register
+                    && instr.op0_register().full_register() == reg)
+            })
+        };
+    }
+
+    ret
+}
+
+fn is_near_branch(instr: &Instruction) -> bool {
+    matches!(
+        instr.op0_kind(),
+        OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64
+    )
+}
+
+/// Resolves the target of `jmp [mem]`/`call [mem]` if the memory operand is an absolute or rip
+/// relative address.
+fn indirect_target(
+    mem: &mut impl MemoryView,
+    bitness: u32,
+    instr: &Instruction,
+) -> Option<Address> {
+    if instr.op0_kind() != OpKind::Memory {
+        return None;
+    }
+
+    let slot = if instr.is_ip_rel_memory_operand() {
+        instr.ip_rel_memory_address()
+    } else if instr.memory_base() == iced_x86::Register::None
+        && instr.memory_index() == iced_x86::Register::None
+    {
+        instr.memory_displacement64()
+    } else {
+        return None;
+    };
+
+    match bitness {
+        64 => mem.read_addr64(slot.into()).ok(),
+        _ => mem.read_addr32(slot.into()).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[test]
+    fn jmp_prologue() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        // jmp 0x2005; int3
+        let code = [0xe9, 0x00, 0x10, 0x00, 0x00, 0xcc];
+        let branches = prologue_branches(&mut view, 64, Address::from(0x1000u64), &code);
+
+        assert_eq!(
+            branches,
+            vec![(
+                Address::from(0x1000u64),
+                Address::from(0x2005u64),
+                HookKind::Jmp
+            )]
+        );
+    }
+
+    #[test]
+    fn mov_jmp_prologue() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        // mov rax, 0x7ffe12345678; jmp rax
+        let code = [
+            0x48, 0xb8, 0x78, 0x56, 0x34, 0x12, 0xfe, 0x7f, 0x00, 0x00, 0xff, 0xe0,
+        ];
+        let branches = prologue_branches(&mut view, 64, Address::from(0x1000u64), &code);
+
+        assert_eq!(
+            branches,
+            vec![(
+                Address::from(0x100au64),
+                Address::from(0x7ffe12345678u64),
+                HookKind::RegisterJmp
+            )]
+        );
+
+        // mov rax, 0x7ffe12345678; mov rax, [rax]; jmp rax
+        let code = [
+            0x48, 0xb8, 0x78, 0x56, 0x34, 0x12, 0xfe, 0x7f, 0x00, 0x00, 0x48, 0x8b, 0x00, 0xff,
+            0xe0,
+        ];
+        let branches = prologue_branches(&mut view, 64, Address::from(0x1000u64), &code);
+        assert!(branches.is_empty());
+    }
+}
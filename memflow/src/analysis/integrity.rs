@@ -0,0 +1,332 @@
+//! Module integrity verification.
+//!
+//! Compares the executable sections of a loaded module with the contents of its image on disk
+//! and reports all byte ranges that have been modified in memory. Both PE and ELF images are
+//! supported.
+//!
+//! For PE images the base relocations are applied to the on-disk contents before comparing, so
+//! modules that have been rebased by the loader do not produce false positives. ELF code
+//! sections are compared as-is, since position independent code does not contain relocations in
+//! its text sections.
+//!
+//! Pages that are not resident in memory (e.g. paged out code) are skipped.
+
+use std::prelude::v1::*;
+
+use goblin::elf::{program_header::PT_LOAD, section_header::SHT_NOBITS, Elf};
+use goblin::pe::PE;
+use goblin::Object;
+
+use super::pe_map::{apply_relocations, pe_relocations};
+use crate::cglue::CTup2;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::ModuleInfo;
+use crate::types::{size, umem, Address, PeSectionCharacteristics};
+
+/// Contiguous range of a code section that differs from the image on disk.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ModifiedRange {
+    /// Name of the section containing the range
+    pub section: String,
+    /// Address of the first modified byte
+    pub address: Address,
+    /// Bytes as found in the image on disk (after relocation)
+    pub original: Vec<u8>,
+    /// Bytes as found in memory
+    pub current: Vec<u8>,
+}
+
+struct CodeSection {
+    name: String,
+    address: Address,
+    expected: Vec<u8>,
+}
+
+/// Compares the code sections of `module` with the image file located at `path`.
+///
+/// See [`verify_module_image`] for details.
+#[cfg(feature = "std")]
+pub fn verify_module_against_file(
+    mem: &mut impl MemoryView,
+    module: &ModuleInfo,
+    path: impl AsRef<std::path::Path>,
+) -> Result<Vec<ModifiedRange>> {
+    let image = std::fs::read(path)
+        .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(err))?;
+    verify_module_image(mem, module, &image)
+}
+
+/// Compares the code sections of `module` with the unmapped on-disk `image`.
+///
+/// Returns all modified byte ranges. An empty list means the code sections are identical.
+pub fn verify_module_image(
+    mem: &mut impl MemoryView,
+    module: &ModuleInfo,
+    image: &[u8],
+) -> Result<Vec<ModifiedRange>> {
+    let object = Object::parse(image)
+        .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile).log_info(err))?;
+
+    let sections = match object {
+        Object::PE(pe) => pe_code_sections(&pe, image, module),
+        Object::Elf(elf) => elf_code_sections(&elf, image, module),
+        _ => {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_info("unsupported executable format"))
+        }
+    };
+
+    let mut ret = vec![];
+    for section in sections.iter() {
+        compare_section(mem, section, &mut ret)?;
+    }
+    Ok(ret)
+}
+
+fn pe_code_sections(pe: &PE, image: &[u8], module: &ModuleInfo) -> Vec<CodeSection> {
+    let relocs = pe_relocations(pe, image);
+    let delta = module.base.to_umem().wrapping_sub(pe.image_base as umem) as u64;
+
+    pe.sections
+        .iter()
+        .filter(|s| {
+            let flags = PeSectionCharacteristics::from_bits_truncate(s.characteristics);
+            flags.contains(PeSectionCharacteristics::MEM_EXECUTE)
+                && !flags.contains(PeSectionCharacteristics::MEM_WRITE)
+        })
+        .filter_map(|s| {
+            let size = match s.virtual_size {
+                0 => s.size_of_raw_data,
+                vsize => vsize.min(s.size_of_raw_data),
+            } as usize;
+
+            let start = s.pointer_to_raw_data as usize;
+            let mut expected = image.get(start..start + size)?.to_vec();
+
+            apply_relocations(&mut expected, s.virtual_address, &relocs, delta);
+
+            Some(CodeSection {
+                name: s.name().unwrap_or_default().to_string(),
+                address: module.base + s.virtual_address as umem,
+                expected,
+            })
+        })
+        .collect()
+}
+
+fn elf_code_sections(elf: &Elf, image: &[u8], module: &ModuleInfo) -> Vec<CodeSection> {
+    // the module base corresponds to the lowest loaded segment
+    let bias = elf
+        .program_headers
+        .iter()
+        .filter(|p| p.p_type == PT_LOAD)
+        .map(|p| p.p_vaddr & !0xfff)
+        .min()
+        .unwrap_or(0);
+
+    elf.section_headers
+        .iter()
+        .filter(|s| s.is_executable() && s.sh_type != SHT_NOBITS)
+        .filter_map(|s| {
+            let start = s.sh_offset as usize;
+            let expected = image.get(start..start + s.sh_size as usize)?.to_vec();
+
+            Some(CodeSection {
+                name: elf.shdr_strtab.get_at(s.sh_name).unwrap_or("").to_string(),
+                address: module.base + (s.sh_addr.checked_sub(bias)? as umem),
+                expected,
+            })
+        })
+        .collect()
+}
+
+fn compare_section(
+    mem: &mut impl MemoryView,
+    section: &CodeSection,
+    out: &mut Vec<ModifiedRange>,
+) -> Result<()> {
+    let mut current: Option<ModifiedRange> = None;
+
+    let stream = mem
+        .read_stream(section.address, section.expected.len() as umem, size::kb(4))
+        .zero_fill_gaps();
+
+    for chunk in stream {
+        let chunk = chunk?;
+        let offset = (chunk.address - section.address) as usize;
+
+        for (i, (&cur, &orig)) in chunk
+            .data
+            .iter()
+            .zip(&section.expected[offset..])
+            .enumerate()
+        {
+            let address = chunk.address + i;
+            let readable = !chunk
+                .gaps
+                .iter()
+                .any(|&CTup2(gap, len)| address >= gap && ((address - gap) as umem) < len);
+
+            if readable && cur != orig {
+                match current.as_mut() {
+                    Some(range) => {
+                        range.original.push(orig);
+                        range.current.push(cur);
+                    }
+                    None => {
+                        current = Some(ModifiedRange {
+                            section: section.name.clone(),
+                            address,
+                            original: vec![orig],
+                            current: vec![cur],
+                        })
+                    }
+                }
+            } else if let Some(range) = current.take() {
+                out.push(range);
+            }
+        }
+    }
+
+    out.extend(current);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::ArchitectureIdent;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+
+    const BASE: u64 = 0x100000;
+    const IMAGE_BASE: u64 = 0x140000000;
+
+    /// Builds a minimal PE32+ image with a single code section at RVA `0x1000` containing a
+    /// relocated pointer to RVA `0x1020` at RVA `0x1010`.
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x400];
+        let put = |image: &mut Vec<u8>, off: usize, data: &[u8]| {
+            image[off..off + data.len()].copy_from_slice(data)
+        };
+
+        put(&mut image, 0, b"MZ");
+        put(&mut image, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut image, 0x40, b"PE\0\0");
+
+        // file header
+        put(&mut image, 0x44, &0x8664u16.to_le_bytes());
+        put(&mut image, 0x46, &1u16.to_le_bytes());
+        put(&mut image, 0x54, &0xf0u16.to_le_bytes());
+        put(&mut image, 0x56, &0x22u16.to_le_bytes());
+
+        // optional header
+        let opt = 0x58;
+        put(&mut image, opt, &0x20bu16.to_le_bytes());
+        put(&mut image, opt + 24, &IMAGE_BASE.to_le_bytes());
+        put(&mut image, opt + 32, &0x1000u32.to_le_bytes());
+        put(&mut image, opt + 36, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 56, &0x2000u32.to_le_bytes());
+        put(&mut image, opt + 60, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 108, &16u32.to_le_bytes());
+        // base relocation directory
+        put(&mut image, opt + 152, &0x1080u32.to_le_bytes());
+        put(&mut image, opt + 156, &12u32.to_le_bytes());
+
+        // section table, the section is executable and not writeable
+        let sec = opt + 0xf0;
+        put(&mut image, sec, b".text\0\0\0");
+        put(&mut image, sec + 8, &0x100u32.to_le_bytes());
+        put(&mut image, sec + 12, &0x1000u32.to_le_bytes());
+        put(&mut image, sec + 16, &0x200u32.to_le_bytes());
+        put(&mut image, sec + 20, &0x200u32.to_le_bytes());
+        put(&mut image, sec + 36, &0x60000020u32.to_le_bytes());
+
+        // some code, the pointer to RVA 0x1020 and its relocation block
+        put(
+            &mut image,
+            0x200,
+            &[0x48, 0x8b, 0x05, 0x09, 0x00, 0x00, 0x00, 0xc3],
+        );
+        put(&mut image, 0x210, &(IMAGE_BASE + 0x1020).to_le_bytes());
+        put(&mut image, 0x280, &0x1000u32.to_le_bytes());
+        put(&mut image, 0x284, &12u32.to_le_bytes());
+        put(&mut image, 0x288, &((10u16 << 12) | 0x10).to_le_bytes());
+
+        image
+    }
+
+    fn module() -> ModuleInfo {
+        ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: Address::from(BASE),
+            size: 0x2000,
+            name: "test.dll".into(),
+            path: "test.dll".into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    /// Maps the code section of `image` at `BASE` like the loader does.
+    fn load(mem: &mut impl MemoryView, image: &[u8]) {
+        mem.write_raw(Address::from(BASE + 0x1000), &image[0x200..0x300])
+            .unwrap();
+        mem.write(Address::from(BASE + 0x1010), &(BASE + 0x1020))
+            .unwrap();
+    }
+
+    #[test]
+    fn relocated_image() {
+        let image = image();
+        let mut mem = DummyMemory::new(size::mb(2));
+        let mut view = mem.phys_view();
+        load(&mut view, &image);
+
+        let ranges = verify_module_image(&mut view, &module(), &image).unwrap();
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn relocation_only_difference() {
+        let image = image();
+        let mut mem = DummyMemory::new(size::mb(2));
+        let mut view = mem.phys_view();
+        load(&mut view, &image);
+
+        // the pointer differs from the file, but only by the relocation delta
+        let pointer: u64 = view.read(Address::from(BASE + 0x1010)).unwrap();
+        assert_ne!(pointer.to_le_bytes(), image[0x210..0x218]);
+
+        let ranges = verify_module_image(&mut view, &module(), &image).unwrap();
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn patched_byte() {
+        let image = image();
+        let mut mem = DummyMemory::new(size::mb(2));
+        let mut view = mem.phys_view();
+        load(&mut view, &image);
+
+        // replace the ret with an int3 and redirect the relocated pointer
+        view.write(Address::from(BASE + 0x1007), &0xccu8).unwrap();
+        view.write(Address::from(BASE + 0x1010), &(BASE + 0x1030))
+            .unwrap();
+
+        let ranges = verify_module_image(&mut view, &module(), &image).unwrap();
+        assert_eq!(ranges.len(), 2);
+
+        assert_eq!(ranges[0].section, ".text");
+        assert_eq!(ranges[0].address, Address::from(BASE + 0x1007));
+        assert_eq!(ranges[0].original, [0xc3]);
+        assert_eq!(ranges[0].current, [0xcc]);
+
+        assert_eq!(ranges[1].address, Address::from(BASE + 0x1010));
+        assert_eq!(ranges[1].original, [0x20]);
+        assert_eq!(ranges[1].current, [0x30]);
+    }
+}
@@ -0,0 +1,365 @@
+//! Locating and extracting Intel Processor Trace buffers.
+//!
+//! Intel Processor Trace (IPT) records the control flow of a cpu into physical memory. The output
+//! is either a single contiguous range, or a set of regions described by a Table of Physical
+//! Addresses (ToPA). Since the trace is written directly to physical memory, it can be extracted
+//! from outside of the target without the tracing driver noticing, and decoded offline (e.g. with
+//! `libipt`).
+//!
+//! The configuration of the trace is read from the `IA32_RTIT_*` model specific registers of a
+//! cpu through [`CpuState`]. If the registers are not available, e.g. because the trace has
+//! already been stopped, an [`IptBuffer`] can be constructed from the ToPA table referenced by
+//! the structures of the tracing driver through [`IptBuffer::from_topa`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::ipt::{IptBuffer, RtitState};
+//! use memflow::connector::cpu_state::CpuState;
+//!
+//! fn dump_traces(state: &mut impl CpuState, mem: &mut impl PhysicalMemory) -> Result<()> {
+//!     for cpu in 0..state.cpu_count() {
+//!         let rtit = RtitState::read(state, cpu)?;
+//!         if !rtit.is_enabled() {
+//!             continue;
+//!         }
+//!         let buffer = IptBuffer::from_state(mem, &rtit)?;
+//!         let trace = buffer.read_linear(mem)?;
+//!         println!("cpu {}: {} bytes of trace", cpu, trace.len());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use crate::connector::cpu_state::{CpuRegister, CpuState};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+
+/// Maximum number of ToPA entries followed while walking the tables of a buffer.
+pub const MAX_TOPA_ENTRIES: usize = 0x10000;
+
+/// Mask of the physical address of a ToPA entry.
+const TOPA_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Trace output configuration of a single cpu.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RtitState {
+    /// `IA32_RTIT_CTL`
+    pub ctl: u64,
+    /// `IA32_RTIT_STATUS`
+    pub status: u64,
+    /// `IA32_RTIT_OUTPUT_BASE`
+    pub output_base: u64,
+    /// `IA32_RTIT_OUTPUT_MASK_PTRS`
+    pub output_mask_ptrs: u64,
+}
+
+impl RtitState {
+    /// Reads the trace configuration of the cpu with the index `cpu`.
+    pub fn read(state: &mut impl CpuState, cpu: usize) -> Result<Self> {
+        Ok(Self {
+            ctl: state.read_register(cpu, CpuRegister::RtitCtl)?,
+            status: state.read_register(cpu, CpuRegister::RtitStatus)?,
+            output_base: state.read_register(cpu, CpuRegister::RtitOutputBase)?,
+            output_mask_ptrs: state.read_register(cpu, CpuRegister::RtitOutputMaskPtrs)?,
+        })
+    }
+
+    /// Returns true if tracing is enabled (`TraceEn`).
+    pub fn is_enabled(&self) -> bool {
+        self.ctl & 1 != 0
+    }
+
+    /// Returns true if the output is described by a ToPA table instead of a single range.
+    pub fn uses_topa(&self) -> bool {
+        self.ctl & (1 << 8) != 0
+    }
+
+    /// Returns true if the output has been stopped because a ToPA region with the `STOP` bit
+    /// has been filled (`Stopped`).
+    pub fn is_stopped(&self) -> bool {
+        self.status & (1 << 5) != 0
+    }
+
+    /// Returns the physical base address of the output (ToPA mode: of the current table).
+    pub fn output_base(&self) -> Address {
+        Address::from(self.output_base & TOPA_ADDRESS_MASK)
+    }
+
+    /// Returns the offset into the current output region.
+    pub fn output_offset(&self) -> umem {
+        (self.output_mask_ptrs >> 32) as umem
+    }
+
+    /// Returns the `MaskOrTableOffset` field.
+    ///
+    /// In ToPA mode it is the index of the current table entry, in single range mode it is the
+    /// size of the output range minus one.
+    fn mask_or_table_offset(&self) -> u64 {
+        (self.output_mask_ptrs & 0xffff_ffff) >> 7
+    }
+}
+
+/// A single output region of a trace buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TopaRegion {
+    /// Physical base address of the region
+    pub base: Address,
+    /// Size of the region in bytes
+    pub size: umem,
+    /// Tracing stops once the region has been filled
+    pub stop: bool,
+    /// An interrupt is raised once the region has been filled
+    pub interrupt: bool,
+}
+
+/// The output regions of the trace of a cpu together with the current write position.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct IptBuffer {
+    /// All output regions in the order they are filled
+    pub regions: Vec<TopaRegion>,
+    /// Index of the region currently written to
+    pub current_region: usize,
+    /// Offset into the current region
+    pub current_offset: umem,
+}
+
+impl IptBuffer {
+    /// Locates the output of a cpu from its trace configuration.
+    pub fn from_state(mem: &mut impl PhysicalMemory, state: &RtitState) -> Result<Self> {
+        if state.uses_topa() {
+            let table = state.output_base();
+            let mut buffer = Self::from_topa(mem, table)?;
+
+            // the current table may be any of the chained tables, locate the current entry in it
+            let entry = table + state.mask_or_table_offset() as umem * 8;
+            let pte = read_entry(mem, entry)?;
+            let base = Address::from(pte & TOPA_ADDRESS_MASK);
+            buffer.current_region = buffer
+                .regions
+                .iter()
+                .position(|r| r.base == base)
+                .unwrap_or(0);
+            buffer.current_offset = state.output_offset();
+
+            Ok(buffer)
+        } else {
+            let size = ((state.mask_or_table_offset() << 7) | 0x7f) + 1;
+            Ok(Self {
+                regions: vec![TopaRegion {
+                    base: state.output_base(),
+                    size: size as umem,
+                    stop: false,
+                    interrupt: false,
+                }],
+                current_region: 0,
+                current_offset: state.output_offset(),
+            })
+        }
+    }
+
+    /// Walks the ToPA tables starting at the physical address `table`.
+    ///
+    /// Tables are chained through entries with the `END` bit set. The walk stops once a table is
+    /// reached the second time, which is the usual setup of a circular buffer. The write position
+    /// is set to the beginning of the buffer.
+    pub fn from_topa(mem: &mut impl PhysicalMemory, table: Address) -> Result<Self> {
+        let mut regions = vec![];
+        let mut tables = vec![table];
+        let mut entry = table;
+
+        for _ in 0..MAX_TOPA_ENTRIES {
+            let pte = read_entry(mem, entry)?;
+            let base = Address::from(pte & TOPA_ADDRESS_MASK);
+
+            if pte & 1 != 0 {
+                // END: continue in the next table
+                if tables.contains(&base) {
+                    break;
+                }
+                tables.push(base);
+                entry = base;
+                continue;
+            }
+
+            regions.push(TopaRegion {
+                base,
+                size: 0x1000 << ((pte >> 6) & 0xf),
+                stop: pte & (1 << 4) != 0,
+                interrupt: pte & (1 << 2) != 0,
+            });
+            entry += 8usize;
+        }
+
+        if regions.is_empty() {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                .log_debug("topa table does not contain any output regions"));
+        }
+
+        Ok(Self {
+            regions,
+            current_region: 0,
+            current_offset: 0,
+        })
+    }
+
+    /// Returns the total size of all output regions.
+    pub fn size(&self) -> umem {
+        self.regions.iter().map(|r| r.size).sum()
+    }
+
+    /// Reads the trace written since the buffer was set up.
+    ///
+    /// This is the data up to the current write position. If the buffer has already wrapped
+    /// around, older data has been overwritten and [`read_wrapped`](Self::read_wrapped) has to be
+    /// used instead.
+    pub fn read_linear(&self, mem: &mut impl PhysicalMemory) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        for (idx, region) in self
+            .regions
+            .iter()
+            .enumerate()
+            .take(self.current_region + 1)
+        {
+            let size = if idx == self.current_region {
+                std::cmp::min(self.current_offset, region.size)
+            } else {
+                region.size
+            };
+            read_region(mem, region.base, size, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Reads the whole buffer, assuming it has wrapped around at least once.
+    ///
+    /// The oldest data, located directly after the current write position, comes first.
+    pub fn read_wrapped(&self, mem: &mut impl PhysicalMemory) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        let count = self.regions.len();
+        let current = self.regions.get(self.current_region).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds)
+                .log_debug("current region is out of bounds")
+        })?;
+        let offset = std::cmp::min(self.current_offset, current.size);
+
+        read_region(mem, current.base + offset, current.size - offset, &mut out)?;
+        for i in 1..count {
+            let region = &self.regions[(self.current_region + i) % count];
+            read_region(mem, region.base, region.size, &mut out)?;
+        }
+        read_region(mem, current.base, offset, &mut out)?;
+
+        Ok(out)
+    }
+}
+
+fn read_entry(mem: &mut impl PhysicalMemory, entry: Address) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    mem.phys_read_into(entry.into(), &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_region(
+    mem: &mut impl PhysicalMemory,
+    base: Address,
+    size: umem,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let start = out.len();
+    let size: usize = size.try_into().map_err(|_| {
+        Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds).log_debug("trace region is too large")
+    })?;
+    out.resize(start + size, 0);
+    mem.phys_read_into(base.into(), &mut out[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    fn write_topa(mem: &mut DummyMemory) {
+        let entries: [u64; 3] = [
+            // 4kb region
+            0x10000,
+            // 8kb region with interrupt
+            0x20000 | (1 << 6) | (1 << 2),
+            // END, back to the start of the table
+            0x1000 | 1,
+        ];
+        for (i, entry) in entries.iter().enumerate() {
+            mem.phys_write(Address::from(0x1000 + i as u64 * 8).into(), entry)
+                .unwrap();
+        }
+        mem.phys_write(Address::from(0x10000u64).into(), &[0xaau8; 0x1000][..])
+            .unwrap();
+        mem.phys_write(Address::from(0x20000u64).into(), &[0xbbu8; 0x2000][..])
+            .unwrap();
+    }
+
+    #[test]
+    fn topa_walk() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_topa(&mut mem);
+
+        let buffer = IptBuffer::from_topa(&mut mem, Address::from(0x1000u64)).unwrap();
+        assert_eq!(buffer.regions.len(), 2);
+        assert_eq!(buffer.regions[1].size, 0x2000);
+        assert!(buffer.regions[1].interrupt);
+        assert_eq!(buffer.size(), 0x3000);
+    }
+
+    #[test]
+    fn topa_state() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_topa(&mut mem);
+
+        let state = RtitState {
+            ctl: 1 | (1 << 8),
+            status: 0,
+            output_base: 0x1000,
+            // second entry, 0x10 bytes written
+            output_mask_ptrs: (0x10 << 32) | (1 << 7) | 0x7f,
+        };
+        let buffer = IptBuffer::from_state(&mut mem, &state).unwrap();
+        assert_eq!(buffer.current_region, 1);
+        assert_eq!(buffer.current_offset, 0x10);
+
+        let linear = buffer.read_linear(&mut mem).unwrap();
+        assert_eq!(linear.len(), 0x1010);
+        assert_eq!(linear[0xfff], 0xaa);
+        assert_eq!(linear[0x1000], 0xbb);
+
+        let wrapped = buffer.read_wrapped(&mut mem).unwrap();
+        assert_eq!(wrapped.len(), 0x3000);
+        assert_eq!(wrapped[0], 0xbb);
+        assert_eq!(wrapped[0x2000 - 0x10], 0xaa);
+        assert_eq!(wrapped[0x2fff], 0xbb);
+    }
+
+    #[test]
+    fn single_range() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let state = RtitState {
+            ctl: 1,
+            status: 0,
+            output_base: 0x40000,
+            output_mask_ptrs: (0x100 << 32) | 0xfff,
+        };
+        let buffer = IptBuffer::from_state(&mut mem, &state).unwrap();
+        assert_eq!(buffer.regions[0].base, Address::from(0x40000u64));
+        assert_eq!(buffer.regions[0].size, 0x1000);
+        assert_eq!(buffer.read_linear(&mut mem).unwrap().len(), 0x100);
+    }
+}
@@ -0,0 +1,425 @@
+//! Locators for logon sessions and credential material in LSASS.
+//!
+//! The Local Security Authority process (`lsass.exe`) keeps a logon session entry for every
+//! active logon in each of its authentication packages. This module walks the session lists of
+//! the MSV1_0 package (`lsasrv!LogonSessionList`) and the Kerberos package
+//! (`kerberos!KerbGlobalLogonSessionTable`) and reports which sessions still hold credential
+//! material. Defensive teams use this to verify the exposure of privileged accounts on a host,
+//! e.g. after hardening with Credential Guard or protected users.
+//!
+//! The module only locates the credential structures. It never reads or decrypts the credentials
+//! themselves, the reported [`LogonSession::credentials`] is the address of the encrypted blob.
+//!
+//! Since the layout of the session entries changes between Windows builds, all offsets are
+//! supplied by the caller, usually from the type information in the PDBs of `lsasrv.dll` and
+//! `kerberos.dll`. The list heads are resolved by name through [`LsassOffsets::resolve`].
+//!
+//! Walking LSASS requires an explicit opt-in: besides enabling the `lsass` feature, every
+//! [`LsassLocator`] has to be created with
+//! [`allow_credential_access`](LsassLocator::allow_credential_access), otherwise all walks fail
+//! with [`ErrorKind::UnsupportedOptionalFeature`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::lsass::{KerberosLayout, LsassLocator, LsassOffsets, MsvLayout};
+//!
+//! fn exposed_sessions(
+//!     lsass: &mut (impl Process + MemoryView),
+//!     symbols: impl FnMut(&str) -> Option<Address>,
+//!     msv: MsvLayout,
+//!     kerberos: KerberosLayout,
+//! ) -> Result<()> {
+//!     let offsets = LsassOffsets::resolve(msv, kerberos, symbols);
+//!     let locator = LsassLocator::new(offsets)
+//!         .allow_credential_access()
+//!         .redact_identities(true);
+//!
+//!     for session in locator.logon_sessions(lsass)? {
+//!         if session.has_credentials() {
+//!             println!("{:?} {:x}: {}\\{}", session.package, session.luid, session.domain, session.user_name);
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # exposed_sessions(&mut proc, |_| None, MsvLayout::default(), KerberosLayout::default()).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::Process;
+use crate::types::Address;
+
+/// Maximum number of entries walked per list, protecting against corrupted or cyclic lists.
+pub const DEFAULT_MAX_ENTRIES: usize = 0x10000;
+
+/// Placeholder replacing user and domain names when identities are redacted.
+pub const REDACTED: &str = "<redacted>";
+
+/// Offsets within an MSV1_0 logon session entry (`_MSV1_0_LIST`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct MsvLayout {
+    /// Offset of the `LocallyUniqueIdentifier` field
+    pub luid: usize,
+    /// Offset of the `UserName` unicode string
+    pub user_name: usize,
+    /// Offset of the `Domaine` unicode string
+    pub domain: usize,
+    /// Offset of the logon type
+    pub logon_type: usize,
+    /// Offset of the pointer to the credential list
+    pub credentials: usize,
+}
+
+/// Offsets within the user data of a Kerberos logon session table node
+/// (`_KIWI_KERBEROS_LOGON_SESSION`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KerberosLayout {
+    /// Offset of the `LocallyUniqueIdentifier` field
+    pub luid: usize,
+    /// Offset of the `UserName` unicode string
+    pub user_name: usize,
+    /// Offset of the `Domaine` unicode string
+    pub domain: usize,
+    /// Offset of the pointer to the encrypted password
+    pub credentials: usize,
+}
+
+/// Locations of the session lists in LSASS and the layouts of their entries.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct LsassOffsets {
+    /// Address of `lsasrv!LogonSessionList`, an array of list heads
+    pub logon_session_list: Option<Address>,
+    /// Address of `lsasrv!LogonSessionListCount`, the number of list heads
+    pub logon_session_list_count: Option<Address>,
+    /// Layout of the MSV1_0 entries
+    pub msv: MsvLayout,
+    /// Address of `kerberos!KerbGlobalLogonSessionTable`, an `RTL_AVL_TABLE`
+    pub kerberos_table: Option<Address>,
+    /// Layout of the Kerberos entries
+    pub kerberos: KerberosLayout,
+}
+
+impl LsassOffsets {
+    /// Resolves the session lists through `resolve`, which maps symbol names to addresses.
+    ///
+    /// Lists whose symbol can not be resolved are skipped during walks.
+    pub fn resolve(
+        msv: MsvLayout,
+        kerberos: KerberosLayout,
+        mut resolve: impl FnMut(&str) -> Option<Address>,
+    ) -> Self {
+        Self {
+            logon_session_list: resolve("LogonSessionList"),
+            logon_session_list_count: resolve("LogonSessionListCount"),
+            msv,
+            kerberos_table: resolve("KerbGlobalLogonSessionTable"),
+            kerberos,
+        }
+    }
+}
+
+/// Authentication package a logon session belongs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum CredentialPackage {
+    /// The MSV1_0 package, holding NTLM credentials
+    Msv,
+    /// The Kerberos package
+    Kerberos,
+}
+
+/// Logon session found in LSASS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct LogonSession {
+    /// Package the session was found in
+    pub package: CredentialPackage,
+    /// Address of the session entry
+    pub entry: Address,
+    /// Locally unique identifier of the logon session
+    pub luid: u64,
+    /// Name of the user, or [`REDACTED`]
+    pub user_name: String,
+    /// Domain of the user, or [`REDACTED`]
+    pub domain: String,
+    /// Logon type (e.g. `2` for interactive logons), only known for MSV1_0 sessions
+    pub logon_type: Option<u32>,
+    /// Address of the encrypted credential material, if the session holds any
+    pub credentials: Option<Address>,
+}
+
+impl LogonSession {
+    /// Returns true if the session still holds credential material.
+    pub fn has_credentials(&self) -> bool {
+        self.credentials.is_some()
+    }
+}
+
+/// Walks the logon session lists of LSASS.
+#[derive(Clone, Debug)]
+pub struct LsassLocator {
+    offsets: LsassOffsets,
+    allowed: bool,
+    redact: bool,
+    max_entries: usize,
+}
+
+impl LsassLocator {
+    /// Creates a new locator. Walks fail until credential access is allowed.
+    pub fn new(offsets: LsassOffsets) -> Self {
+        Self {
+            offsets,
+            allowed: false,
+            redact: false,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+
+    /// Explicitly allows walking the credential structures of LSASS.
+    pub fn allow_credential_access(mut self) -> Self {
+        self.allowed = true;
+        self
+    }
+
+    /// Replaces user and domain names in all results by [`REDACTED`].
+    pub fn redact_identities(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Sets the maximum number of entries walked per list.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Returns the sessions of all packages whose lists could be resolved.
+    pub fn logon_sessions(
+        &self,
+        lsass: &mut (impl Process + MemoryView),
+    ) -> Result<Vec<LogonSession>> {
+        let mut ret = self.msv_sessions(lsass)?;
+        ret.extend(self.kerberos_sessions(lsass)?);
+        Ok(ret)
+    }
+
+    /// Returns the sessions of the MSV1_0 package.
+    pub fn msv_sessions(
+        &self,
+        lsass: &mut (impl Process + MemoryView),
+    ) -> Result<Vec<LogonSession>> {
+        self.check_allowed()?;
+
+        let list = match self.offsets.logon_session_list {
+            Some(list) => list,
+            None => return Ok(vec![]),
+        };
+        let count = match self.offsets.logon_session_list_count {
+            Some(addr) => lsass.read::<u32>(addr).data_part()? as usize,
+            None => 1,
+        };
+
+        let arch = lsass.info().proc_arch.into_obj();
+        let layout = self.offsets.msv;
+        let mut ret = vec![];
+
+        for i in 0..count {
+            let head = list + i * 2 * arch.size_addr();
+            let mut entry = lsass.read_addr_arch(arch, head).data_part()?;
+            let mut walked = 0;
+
+            while !entry.is_null() && entry != head && walked < self.max_entries {
+                walked += 1;
+                ret.push(LogonSession {
+                    package: CredentialPackage::Msv,
+                    entry,
+                    luid: lsass.read::<u64>(entry + layout.luid).data_part()?,
+                    user_name: self.identity(lsass, arch, entry + layout.user_name)?,
+                    domain: self.identity(lsass, arch, entry + layout.domain)?,
+                    logon_type: Some(lsass.read::<u32>(entry + layout.logon_type).data_part()?),
+                    credentials: non_null(
+                        lsass
+                            .read_addr_arch(arch, entry + layout.credentials)
+                            .data_part()?,
+                    ),
+                });
+
+                entry = lsass.read_addr_arch(arch, entry).data_part()?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the sessions of the Kerberos package.
+    pub fn kerberos_sessions(
+        &self,
+        lsass: &mut (impl Process + MemoryView),
+    ) -> Result<Vec<LogonSession>> {
+        self.check_allowed()?;
+
+        let table = match self.offsets.kerberos_table {
+            Some(table) => table,
+            None => return Ok(vec![]),
+        };
+
+        let arch = lsass.info().proc_arch.into_obj();
+        let ptr = arch.size_addr();
+        let layout = self.offsets.kerberos;
+        let mut ret = vec![];
+
+        // the root of the tree is the right child of the balanced root embedded in the table,
+        // the user data of every node follows its RTL_BALANCED_LINKS
+        let mut stack = vec![lsass.read_addr_arch(arch, table + 2 * ptr).data_part()?];
+        while let Some(node) = stack.pop() {
+            if node.is_null() || ret.len() >= self.max_entries {
+                continue;
+            }
+
+            stack.push(lsass.read_addr_arch(arch, node + ptr).data_part()?);
+            stack.push(lsass.read_addr_arch(arch, node + 2 * ptr).data_part()?);
+
+            let entry = node + 4 * ptr;
+            ret.push(LogonSession {
+                package: CredentialPackage::Kerberos,
+                entry,
+                luid: lsass.read::<u64>(entry + layout.luid).data_part()?,
+                user_name: self.identity(lsass, arch, entry + layout.user_name)?,
+                domain: self.identity(lsass, arch, entry + layout.domain)?,
+                logon_type: None,
+                credentials: non_null(
+                    lsass
+                        .read_addr_arch(arch, entry + layout.credentials)
+                        .data_part()?,
+                ),
+            });
+        }
+
+        Ok(ret)
+    }
+
+    fn check_allowed(&self) -> Result<()> {
+        if self.allowed {
+            Ok(())
+        } else {
+            Err(
+                Error(ErrorOrigin::Other, ErrorKind::UnsupportedOptionalFeature)
+                    .log_warn("credential access was not explicitly allowed"),
+            )
+        }
+    }
+
+    /// Reads a user or domain name, unless identities are redacted.
+    fn identity(
+        &self,
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        addr: Address,
+    ) -> Result<String> {
+        if self.redact {
+            Ok(REDACTED.to_string())
+        } else {
+            read_unicode_string(mem, arch, addr)
+        }
+    }
+}
+
+/// Reads a `UNICODE_STRING` at `addr`.
+fn read_unicode_string(
+    mem: &mut impl MemoryView,
+    arch: ArchitectureObj,
+    addr: Address,
+) -> Result<String> {
+    let len = mem.read::<u16>(addr).data_part()? as usize;
+    // the buffer pointer is aligned to the pointer size
+    let buffer = mem
+        .read_addr_arch(arch, addr + arch.size_addr())
+        .data_part()?;
+
+    if len == 0 || buffer.is_null() {
+        return Ok(String::new());
+    }
+
+    let bytes = mem.read_raw(buffer, len & !1).data_part()?;
+    let chars = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    Ok(String::from_utf16_lossy(&chars))
+}
+
+fn non_null(addr: Address) -> Option<Address> {
+    if addr.is_null() {
+        None
+    } else {
+        Some(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    const MSV: MsvLayout = MsvLayout {
+        luid: 0x10,
+        user_name: 0x20,
+        domain: 0x30,
+        logon_type: 0x40,
+        credentials: 0x48,
+    };
+
+    #[test]
+    fn msv_list() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // list head at base, a single entry at base + 0x100, user name at base + 0x200
+        let (head, entry, name) = (base, base + 0x100usize, base + 0x200usize);
+        proc.write(head, &entry.to_umem()).unwrap();
+        proc.write(entry, &head.to_umem()).unwrap();
+        proc.write(entry + 0x10usize, &0x3e7u64).unwrap();
+        proc.write(entry + 0x20usize, &8u16).unwrap();
+        proc.write(entry + 0x28usize, &name.to_umem()).unwrap();
+        proc.write(entry + 0x40usize, &2u32).unwrap();
+        proc.write(entry + 0x48usize, &0x1000u64).unwrap();
+        proc.write_raw(name, &[b'u', 0, b's', 0, b'e', 0, b'r', 0])
+            .unwrap();
+
+        let offsets = LsassOffsets::resolve(MSV, KerberosLayout::default(), |name| {
+            if name == "LogonSessionList" {
+                Some(head)
+            } else {
+                None
+            }
+        });
+
+        let locator = LsassLocator::new(offsets);
+        assert_eq!(
+            locator.logon_sessions(&mut proc).unwrap_err().1,
+            ErrorKind::UnsupportedOptionalFeature
+        );
+
+        let sessions = locator
+            .allow_credential_access()
+            .logon_sessions(&mut proc)
+            .unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].luid, 0x3e7);
+        assert_eq!(sessions[0].user_name, "user");
+        assert_eq!(sessions[0].logon_type, Some(2));
+        assert!(sessions[0].has_credentials());
+    }
+}
@@ -0,0 +1,121 @@
+//! Analysis passes built on top of the memory and OS abstractions.
+//!
+//! Most passes operate purely on the generic [`MemoryView`](crate::mem::MemoryView) and
+//! [`Process`](crate::os::Process) traits and work with any OS layer. Some passes walk Windows
+//! specific structures instead (e.g. [`pool`], [`objdir`], [`winsta`], [`residue`], [`evtx`],
+//! [`console`] and `lsass`), they document the targets they support.
+//! Passes that need to decode machine code are gated behind the `disasm` feature, hashing
+//! utilities are gated behind the `hash` feature, regex scanning behind `regex_scan` and image
+//! parsing requires `goblin`.
+
+#[cfg(all(feature = "goblin", feature = "hash"))]
+pub mod authenticode;
+#[cfg(all(feature = "goblin", feature = "hash"))]
+pub use authenticode::{
+    authenticode_hash, authenticode_hash_with_base, read_certificate_table, AuthenticodeHash,
+    CertificateTable,
+};
+
+#[cfg(feature = "hash")]
+pub mod baseline;
+#[cfg(feature = "hash")]
+pub use baseline::{
+    BaselineConfig, BaselineProfile, Drift, DriftKind, DriftSubject, ModuleBaseline,
+    SyscallBaseline,
+};
+
+pub mod console;
+pub use console::{
+    carve_dotnet_strings, carve_screen_buffers, carve_utf16_strings, triage_consoles, CarvedString,
+    ConsoleTriage, ScreenBuffer,
+};
+
+pub mod diff;
+pub use diff::{
+    diff_memory, diff_memory_with_progress, diff_modules, diff_processes, AddressSpaceDiff,
+    PageChangeKind,
+};
+
+pub mod env;
+pub use env::{find_env_var, parse_environment_block, read_environment, EnvMatch, EnvVar};
+
+pub mod evtx;
+pub use evtx::{carve_evtx_chunks, carve_evtx_records, EvtxChunk, EvtxRecord};
+
+#[cfg(feature = "disasm")]
+pub mod disasm;
+#[cfg(feature = "disasm")]
+pub use disasm::{Decoded, Disassembler};
+
+#[cfg(feature = "hash")]
+pub mod hash;
+#[cfg(feature = "hash")]
+pub use hash::{
+    hash_pages, hash_pages_with_progress, hash_range, hash_range_with_progress, Digest,
+    HashAlgorithm, PageDigest, PageDigests,
+};
+
+#[cfg(feature = "goblin")]
+pub mod integrity;
+#[cfg(all(feature = "goblin", feature = "std"))]
+pub use integrity::verify_module_against_file;
+#[cfg(feature = "goblin")]
+pub use integrity::{verify_module_image, ModifiedRange};
+
+pub mod ipt;
+pub use ipt::{IptBuffer, RtitState, TopaRegion};
+
+#[cfg(feature = "lsass")]
+pub mod lsass;
+#[cfg(feature = "lsass")]
+pub use lsass::{LogonSession, LsassLocator, LsassOffsets};
+
+#[cfg(feature = "regex_scan")]
+pub mod regex_scan;
+#[cfg(feature = "regex_scan")]
+pub use regex_scan::{RegexMatch, RegexScanner};
+
+pub mod objdir;
+pub use objdir::{NamedObject, ObjectLayout, ObjectNamespace};
+
+#[cfg(feature = "goblin")]
+pub mod pe_map;
+#[cfg(feature = "goblin")]
+pub use pe_map::{PeAddressMap, PeSectionMapping};
+
+pub mod pool;
+pub use pool::{
+    find_pool_allocation, scan_pool_tag, BigPoolEntry, BigPoolTable, PoolAllocation, PoolLayout,
+    PoolTag,
+};
+
+pub mod residue;
+pub use residue::{
+    find_exited_processes, read_unloaded_drivers, unloaded_drivers_from_symbols, ExitedProcess,
+    ProcessResidueLayout, UnloadedDriver,
+};
+
+pub mod shared;
+pub use shared::{find_shared_mappings, SharedMapping};
+
+pub mod strings;
+pub use strings::{FoundString, StringEncoding, StringSearch};
+
+pub mod timeline;
+pub use timeline::{Timeline, TimelineEvent, TimelineEventKind, TimelineSource};
+
+pub mod unwind;
+pub use unwind::{
+    ExceptionDirectory, ExceptionHandler, RuntimeFunction, UnwindCode, UnwindContext, UnwindInfo,
+    UnwindOp,
+};
+
+pub mod winsta;
+pub use winsta::{
+    Desktop, Session, SessionEnumerator, SessionProcess, WindowStation, WinstaLayout,
+};
+
+#[cfg(feature = "disasm")]
+pub mod hooks;
+#[cfg(feature = "disasm")]
+pub use hooks::{find_inline_hooks, HookKind, InlineHook};
@@ -0,0 +1,620 @@
+//! Traversal of the kernel object manager namespace.
+//!
+//! The object manager keeps named kernel objects in a tree of directory objects rooted at
+//! `nt!ObpRootDirectoryObject`. Devices live in `\Device`, drive letters and other DOS device
+//! names are symbolic links in `\GLOBAL??`, and named mutexes, events and sections of all
+//! sessions are found in `\BaseNamedObjects` and `\Sessions\<n>\BaseNamedObjects`.
+//!
+//! [`ObjectNamespace`] walks this tree. It enumerates directories, looks up objects by path
+//! (following symbolic links on the way), resolves symbolic link targets and finds all objects
+//! of a type, e.g. every named `Mutant` for malware triage.
+//!
+//! Every directory is a hash table of 37 buckets, each a singly linked list of
+//! `OBJECT_DIRECTORY_ENTRY`s. The name of an object is stored in the `OBJECT_HEADER_NAME_INFO`
+//! in front of its `OBJECT_HEADER`, its type is resolved through `nt!ObTypeIndexTable`. Since
+//! the offsets of these structures differ between architectures they are supplied through an
+//! [`ObjectLayout`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::objdir::{ObjectLayout, ObjectNamespace};
+//!
+//! fn named_mutexes(
+//!     kernel: &mut impl MemoryView,
+//!     arch: ArchitectureObj,
+//!     symbols: impl FnMut(&str) -> Option<Address>,
+//! ) -> Result<()> {
+//!     let layout = ObjectLayout::x64();
+//!     let mut namespace = ObjectNamespace::from_symbols(kernel, arch, layout, symbols)?;
+//!
+//!     for mutex in namespace.find_by_type(kernel, "Mutant")? {
+//!         println!("{}", mutex.path);
+//!     }
+//!
+//!     let volume = namespace.resolve(kernel, "\\GLOBAL??\\C:")?;
+//!     println!("C: is {}", volume.path);
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # assert!(named_mutexes(&mut proc, x64::ARCH, |_| None).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Number of hash buckets of an object directory.
+pub const DIRECTORY_BUCKETS: usize = 37;
+
+/// Maximum number of entries walked per bucket, protecting against corrupted or cyclic lists.
+pub const DEFAULT_MAX_ENTRIES: usize = 0x10000;
+
+/// Maximum number of symbolic links followed during a single lookup.
+pub const MAX_LINK_DEPTH: usize = 32;
+
+/// Offsets within the object manager structures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ObjectLayout {
+    /// Offset of the `Object` field in an `OBJECT_DIRECTORY_ENTRY`
+    pub entry_object: usize,
+    /// Offset of the object body in its `OBJECT_HEADER`
+    pub header_body: usize,
+    /// Offset of the `TypeIndex` field in an `OBJECT_HEADER`
+    pub header_type_index: usize,
+    /// Offset of the `InfoMask` field in an `OBJECT_HEADER`
+    pub header_info_mask: usize,
+    /// Size of an `OBJECT_HEADER_CREATOR_INFO`
+    pub creator_info_size: usize,
+    /// Size of an `OBJECT_HEADER_NAME_INFO`
+    pub name_info_size: usize,
+    /// Offset of the `Name` field in an `OBJECT_HEADER_NAME_INFO`
+    pub name_info_name: usize,
+    /// Offset of the `Name` field in an `OBJECT_TYPE`
+    pub type_name: usize,
+    /// Offset of the `LinkTarget` field in an `OBJECT_SYMBOLIC_LINK`
+    pub symlink_target: usize,
+}
+
+impl ObjectLayout {
+    /// Layout of 64-bit kernels since Windows 7.
+    pub const fn x64() -> Self {
+        Self {
+            entry_object: 0x8,
+            header_body: 0x30,
+            header_type_index: 0x18,
+            header_info_mask: 0x1a,
+            creator_info_size: 0x20,
+            name_info_size: 0x20,
+            name_info_name: 0x8,
+            type_name: 0x10,
+            symlink_target: 0x8,
+        }
+    }
+
+    /// Layout of 32-bit kernels since Windows 7.
+    pub const fn x86() -> Self {
+        Self {
+            entry_object: 0x4,
+            header_body: 0x18,
+            header_type_index: 0xc,
+            header_info_mask: 0xe,
+            creator_info_size: 0x10,
+            name_info_size: 0x10,
+            name_info_name: 0x4,
+            type_name: 0x8,
+            symlink_target: 0x8,
+        }
+    }
+}
+
+/// A named object of the namespace.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct NamedObject {
+    /// Address of the object body
+    pub address: Address,
+    /// Name of the object
+    pub name: String,
+    /// Name of the object type, e.g. `Directory`, `SymbolicLink`, `Device` or `Mutant`
+    pub type_name: String,
+    /// Full path of the object in the namespace
+    pub path: String,
+}
+
+impl NamedObject {
+    /// Returns true if the object is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.type_name == "Directory"
+    }
+
+    /// Returns true if the object is a symbolic link.
+    pub fn is_symbolic_link(&self) -> bool {
+        self.type_name == "SymbolicLink"
+    }
+}
+
+/// Walker of the object manager namespace.
+pub struct ObjectNamespace {
+    arch: ArchitectureObj,
+    layout: ObjectLayout,
+    root: Address,
+    type_table: Address,
+    header_cookie: Option<u8>,
+    max_entries: usize,
+    types: BTreeMap<u8, String>,
+}
+
+impl ObjectNamespace {
+    /// Creates a walker from the address of the root directory object and of
+    /// `nt!ObTypeIndexTable`.
+    pub fn new(
+        arch: ArchitectureObj,
+        layout: ObjectLayout,
+        root: Address,
+        type_table: Address,
+    ) -> Self {
+        Self {
+            arch,
+            layout,
+            root,
+            type_table,
+            header_cookie: None,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            types: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a walker from the kernel variables `ObpRootDirectoryObject`, `ObTypeIndexTable`
+    /// and, if present, `ObHeaderCookie`, resolved through `resolve`.
+    pub fn from_symbols(
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        layout: ObjectLayout,
+        mut resolve: impl FnMut(&str) -> Option<Address>,
+    ) -> Result<Self> {
+        let (root_ptr, type_table) = resolve("ObpRootDirectoryObject")
+            .zip(resolve("ObTypeIndexTable"))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_info("unable to resolve the object root directory")
+            })?;
+
+        let root = mem.read_addr_arch(arch, root_ptr).data_part()?;
+        let mut namespace = Self::new(arch, layout, root, type_table);
+
+        if let Some(cookie) = resolve("ObHeaderCookie") {
+            namespace.header_cookie = Some(mem.read::<u8>(cookie).data_part()?);
+        }
+
+        Ok(namespace)
+    }
+
+    /// Sets the cookie used to encode the type index of object headers since Windows 10.
+    pub fn header_cookie(mut self, cookie: u8) -> Self {
+        self.header_cookie = Some(cookie);
+        self
+    }
+
+    /// Sets the maximum number of entries walked per directory bucket.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Returns the root directory object.
+    pub fn root(&mut self, mem: &mut impl MemoryView) -> Result<NamedObject> {
+        Ok(NamedObject {
+            address: self.root,
+            name: String::new(),
+            type_name: self.type_name(mem, self.root)?,
+            path: "\\".to_string(),
+        })
+    }
+
+    /// Returns the objects in the directory `dir`.
+    pub fn list_directory(
+        &mut self,
+        mem: &mut impl MemoryView,
+        dir: &NamedObject,
+    ) -> Result<Vec<NamedObject>> {
+        if !dir.is_directory() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("object is not a directory"));
+        }
+
+        let ptr = self.arch.size_addr();
+        let mut ret = vec![];
+
+        for bucket in 0..DIRECTORY_BUCKETS {
+            let mut entry = mem
+                .read_addr_arch(self.arch, dir.address + bucket * ptr)
+                .data_part()?;
+            let mut walked = 0;
+
+            while !entry.is_null() && walked < self.max_entries {
+                walked += 1;
+
+                let object = mem
+                    .read_addr_arch(self.arch, entry + self.layout.entry_object)
+                    .data_part()?;
+                if !object.is_null() {
+                    let name = self.object_name(mem, object)?;
+                    let path = if dir.path.ends_with('\\') {
+                        format!("{}{}", dir.path, name)
+                    } else {
+                        format!("{}\\{}", dir.path, name)
+                    };
+
+                    ret.push(NamedObject {
+                        address: object,
+                        type_name: self.type_name(mem, object)?,
+                        name,
+                        path,
+                    });
+                }
+
+                entry = mem.read_addr_arch(self.arch, entry).data_part()?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the objects in the directory at `path`.
+    pub fn list(&mut self, mem: &mut impl MemoryView, path: &str) -> Result<Vec<NamedObject>> {
+        let dir = self.lookup(mem, path)?;
+        self.list_directory(mem, &dir)
+    }
+
+    /// Returns the object at `path`.
+    ///
+    /// Symbolic links in the middle of the path are followed, a symbolic link at the end of the
+    /// path is returned as is. Names are compared case-insensitively.
+    pub fn lookup(&mut self, mem: &mut impl MemoryView, path: &str) -> Result<NamedObject> {
+        let mut path = path.to_string();
+
+        'restart: for _ in 0..MAX_LINK_DEPTH {
+            let mut cur = self.root(mem)?;
+            let components = path
+                .split('\\')
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+
+            for (i, component) in components.iter().enumerate() {
+                if cur.is_symbolic_link() {
+                    let rest = components[i..].join("\\");
+                    path = format!("{}\\{}", self.symbolic_link_target(mem, &cur)?, rest);
+                    continue 'restart;
+                }
+
+                cur = self
+                    .list_directory(mem, &cur)?
+                    .into_iter()
+                    .find(|o| o.name.to_lowercase() == component.to_lowercase())
+                    .ok_or_else(|| {
+                        Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                            .log_debug("object not found in namespace")
+                    })?;
+            }
+
+            return Ok(cur);
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_warn("too many symbolic links while looking up object"))
+    }
+
+    /// Returns the object at `path`, following all symbolic links including a final one.
+    pub fn resolve(&mut self, mem: &mut impl MemoryView, path: &str) -> Result<NamedObject> {
+        let mut obj = self.lookup(mem, path)?;
+        for _ in 0..MAX_LINK_DEPTH {
+            if !obj.is_symbolic_link() {
+                return Ok(obj);
+            }
+            let target = self.symbolic_link_target(mem, &obj)?;
+            obj = self.lookup(mem, &target)?;
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_warn("too many symbolic links while resolving object"))
+    }
+
+    /// Returns the target path of a symbolic link.
+    pub fn symbolic_link_target(
+        &mut self,
+        mem: &mut impl MemoryView,
+        link: &NamedObject,
+    ) -> Result<String> {
+        if !link.is_symbolic_link() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("object is not a symbolic link"));
+        }
+
+        read_unicode_string(mem, self.arch, link.address + self.layout.symlink_target)
+    }
+
+    /// Walks the entire namespace depth-first and calls `callback` for every object.
+    ///
+    /// The walk stops early once `callback` returns false. Symbolic links are not followed.
+    pub fn walk(
+        &mut self,
+        mem: &mut impl MemoryView,
+        mut callback: impl FnMut(&NamedObject) -> bool,
+    ) -> Result<()> {
+        let mut stack = vec![self.root(mem)?];
+        let mut visited = vec![];
+
+        while let Some(dir) = stack.pop() {
+            // directories can be linked into multiple places
+            if visited.contains(&dir.address) {
+                continue;
+            }
+            visited.push(dir.address);
+
+            for obj in self.list_directory(mem, &dir)? {
+                if !callback(&obj) {
+                    return Ok(());
+                }
+                if obj.is_directory() {
+                    stack.push(obj);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns all objects of the given type, e.g. `Mutant`, `Event`, `Section` or `Device`.
+    pub fn find_by_type(
+        &mut self,
+        mem: &mut impl MemoryView,
+        type_name: &str,
+    ) -> Result<Vec<NamedObject>> {
+        let mut ret = vec![];
+        self.walk(mem, |obj| {
+            if obj.type_name == type_name {
+                ret.push(obj.clone());
+            }
+            true
+        })?;
+        Ok(ret)
+    }
+
+    /// Reads the name of the object with the body at `object`.
+    pub(crate) fn object_name(
+        &mut self,
+        mem: &mut impl MemoryView,
+        object: Address,
+    ) -> Result<String> {
+        let header = object - self.layout.header_body;
+        let info_mask = mem
+            .read::<u8>(header + self.layout.header_info_mask)
+            .data_part()?;
+
+        // the name info is present if bit 1 is set, the creator info (bit 0) lies in between
+        if info_mask & 2 == 0 {
+            return Ok(String::new());
+        }
+        let mut offset = self.layout.name_info_size;
+        if info_mask & 1 != 0 {
+            offset += self.layout.creator_info_size;
+        }
+
+        read_unicode_string(mem, self.arch, header - offset + self.layout.name_info_name)
+    }
+
+    /// Reads the type name of the object with the body at `object`.
+    fn type_name(&mut self, mem: &mut impl MemoryView, object: Address) -> Result<String> {
+        let header = object - self.layout.header_body;
+        let mut index = mem
+            .read::<u8>(header + self.layout.header_type_index)
+            .data_part()?;
+        if let Some(cookie) = self.header_cookie {
+            index ^= cookie ^ (header.to_umem() >> 8) as u8;
+        }
+
+        if let Some(name) = self.types.get(&index) {
+            return Ok(name.clone());
+        }
+
+        let ty = mem
+            .read_addr_arch(
+                self.arch,
+                self.type_table + index as usize * self.arch.size_addr(),
+            )
+            .data_part()?;
+        let name = if ty.is_null() {
+            String::new()
+        } else {
+            read_unicode_string(mem, self.arch, ty + self.layout.type_name)?
+        };
+
+        self.types.insert(index, name.clone());
+        Ok(name)
+    }
+}
+
+/// Reads a `UNICODE_STRING` at `addr`.
+fn read_unicode_string(
+    mem: &mut impl MemoryView,
+    arch: ArchitectureObj,
+    addr: Address,
+) -> Result<String> {
+    let len = mem.read::<u16>(addr).data_part()? as usize;
+    // the buffer pointer is aligned to the pointer size
+    let buffer = mem
+        .read_addr_arch(arch, addr + arch.size_addr())
+        .data_part()?;
+
+    if len == 0 || buffer.is_null() {
+        return Ok(String::new());
+    }
+
+    let bytes = mem.read_raw(buffer, len & !1).data_part()?;
+    let chars = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    Ok(String::from_utf16_lossy(&chars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    /// Lays out object manager structures in a buffer mapped at `base`.
+    struct Builder {
+        base: Address,
+        buf: Vec<u8>,
+        next: usize,
+    }
+
+    impl Builder {
+        fn alloc(&mut self, len: usize) -> usize {
+            let off = self.next;
+            self.next = (self.next + len + 0xf) & !0xf;
+            off
+        }
+
+        fn addr(&self, off: usize) -> u64 {
+            (self.base + off).to_umem() as u64
+        }
+
+        fn write(&mut self, off: usize, data: &[u8]) {
+            self.buf[off..off + data.len()].copy_from_slice(data);
+        }
+
+        fn unicode(&mut self, off: usize, s: &str) {
+            let chars = s
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>();
+            let buffer = self.alloc(chars.len());
+            self.write(buffer, &chars);
+            self.write(off, &(chars.len() as u16).to_le_bytes());
+            let ptr = self.addr(buffer);
+            self.write(off + 8, &ptr.to_le_bytes());
+        }
+
+        /// Creates a named object and returns the offset of its body.
+        fn object(&mut self, type_index: u8, name: &str, body_size: usize) -> usize {
+            let name_info = self.alloc(0x20 + 0x30 + body_size);
+            self.unicode(name_info + 0x8, name);
+            let header = name_info + 0x20;
+            self.write(header + 0x18, &[type_index, 0, 2]);
+            header + 0x30
+        }
+
+        fn directory(&mut self, name: &str) -> usize {
+            self.object(2, name, DIRECTORY_BUCKETS * 8)
+        }
+
+        fn insert(&mut self, dir: usize, bucket: usize, object: usize) {
+            let entry = self.alloc(0x18);
+            let head = self.buf[dir + bucket * 8..dir + bucket * 8 + 8].to_vec();
+            self.write(entry, &head);
+            let object = self.addr(object);
+            self.write(entry + 8, &object.to_le_bytes());
+            let entry = self.addr(entry);
+            self.write(dir + bucket * 8, &entry.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn namespace() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut b = Builder {
+            base,
+            buf: vec![0u8; 0x4000],
+            next: 0x100,
+        };
+
+        // type table with Directory (2), SymbolicLink (3) and Mutant (4)
+        for (i, name) in [(2usize, "Directory"), (3, "SymbolicLink"), (4, "Mutant")] {
+            let ty = b.alloc(0x20);
+            b.unicode(ty + 0x10, name);
+            let ty = b.addr(ty);
+            b.write(i * 8, &ty.to_le_bytes());
+        }
+
+        let root = b.directory("");
+        let device = b.directory("Device");
+        let global = b.directory("GLOBAL??");
+        let named = b.directory("BaseNamedObjects");
+        b.insert(root, 0, device);
+        b.insert(root, 5, global);
+        b.insert(root, 5, named);
+
+        let volume = b.directory("HarddiskVolume1");
+        b.insert(device, 1, volume);
+
+        let link = b.object(3, "C:", 0x18);
+        b.unicode(link + 0x8, "\\Device\\HarddiskVolume1");
+        b.insert(global, 36, link);
+
+        let mutex = b.object(4, "Infected", 0x40);
+        b.insert(named, 3, mutex);
+        let mutex = b.object(4, "Other", 0x40);
+        b.insert(volume, 0, mutex);
+
+        proc.write_raw(base, &b.buf).unwrap();
+
+        let mut ns = ObjectNamespace::new(x64::ARCH, ObjectLayout::x64(), b.base + root, base);
+
+        let mut names = ns
+            .list(&mut proc, "\\")
+            .unwrap()
+            .into_iter()
+            .map(|o| o.path)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["\\BaseNamedObjects", "\\Device", "\\GLOBAL??"]);
+
+        let link = ns.lookup(&mut proc, "\\global??\\c:").unwrap();
+        assert!(link.is_symbolic_link());
+        assert_eq!(
+            ns.symbolic_link_target(&mut proc, &link).unwrap(),
+            "\\Device\\HarddiskVolume1"
+        );
+
+        let resolved = ns.resolve(&mut proc, "\\GLOBAL??\\C:").unwrap();
+        assert_eq!(resolved.address, base + volume);
+        assert_eq!(resolved.path, "\\Device\\HarddiskVolume1");
+
+        // intermediate links are followed
+        let other = ns.lookup(&mut proc, "\\GLOBAL??\\C:\\Other").unwrap();
+        assert_eq!(other.path, "\\Device\\HarddiskVolume1\\Other");
+
+        let mut mutexes = ns
+            .find_by_type(&mut proc, "Mutant")
+            .unwrap()
+            .into_iter()
+            .map(|o| o.name)
+            .collect::<Vec<_>>();
+        mutexes.sort();
+        assert_eq!(mutexes, ["Infected", "Other"]);
+
+        assert_eq!(
+            ns.lookup(&mut proc, "\\Device\\Missing").unwrap_err().1,
+            ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! Address conversions between the on-disk and the loaded form of PE images.
+//!
+//! Comparing the image of a module on disk with its contents in memory requires translating
+//! between three kinds of locations:
+//!
+//! * relative virtual addresses (RVAs), offsets from the start of the loaded image
+//! * file offsets into the unmapped image on disk
+//! * virtual addresses of the loaded module, which differ from the addresses the image was
+//!   linked for if the loader rebased it
+//!
+//! [`PeAddressMap`] performs these conversions and applies the base relocations of the image,
+//! so on-disk bytes can be compared with the bytes in memory directly.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::pe_map::PeAddressMap;
+//!
+//! fn compare(
+//!     mem: &mut impl MemoryView,
+//!     module: &ModuleInfo,
+//!     image: &[u8],
+//!     rva: u32,
+//! ) -> Result<bool> {
+//!     let map = PeAddressMap::new(image, module.base)?;
+//!
+//!     // on-disk bytes as they look after being relocated by the loader
+//!     let expected = map.relocated_bytes(image, rva, 16).ok_or(ErrorKind::OutOfBounds)?;
+//!     let current = mem.read_raw(map.rva_to_va(rva), 16).data_part()?;
+//!
+//!     Ok(expected == current)
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let module = proc.primary_module().unwrap();
+//! # assert!(compare(&mut proc, &module, &[], 0x1000).is_err());
+//! ```
+
+use std::convert::TryInto;
+use std::prelude::v1::*;
+
+use goblin::pe::{section_table::SectionTable, PE};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{umem, Address};
+
+/// Location of a section in the image file and in the loaded image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PeSectionMapping {
+    /// Name of the section
+    pub name: String,
+    /// RVA of the section
+    pub rva: u32,
+    /// Size of the section in memory
+    pub virtual_size: u32,
+    /// Offset of the section data in the image file
+    pub file_offset: u32,
+    /// Size of the section data in the image file
+    pub raw_size: u32,
+}
+
+/// Conversions between RVAs, file offsets and virtual addresses of a PE image.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PeAddressMap {
+    preferred_base: u64,
+    load_base: Address,
+    size_of_headers: u32,
+    sections: Vec<PeSectionMapping>,
+    relocations: Vec<(u32, usize)>,
+}
+
+impl PeAddressMap {
+    /// Parses the unmapped on-disk `image` of a module loaded at `load_base`.
+    pub fn new(image: &[u8], load_base: Address) -> Result<Self> {
+        let pe = PE::parse(image)
+            .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile).log_info(err))?;
+
+        let size_of_headers = pe
+            .header
+            .optional_header
+            .as_ref()
+            .map(|h| h.windows_fields.size_of_headers)
+            .unwrap_or_default();
+
+        Ok(Self {
+            preferred_base: pe.image_base as u64,
+            load_base,
+            size_of_headers,
+            sections: pe
+                .sections
+                .iter()
+                .map(|s| PeSectionMapping {
+                    name: s.name().unwrap_or_default().to_string(),
+                    rva: s.virtual_address,
+                    virtual_size: s.virtual_size,
+                    file_offset: s.pointer_to_raw_data,
+                    raw_size: s.size_of_raw_data,
+                })
+                .collect(),
+            relocations: pe_relocations(&pe, image),
+        })
+    }
+
+    /// Returns the base address the image was linked for.
+    pub fn preferred_base(&self) -> u64 {
+        self.preferred_base
+    }
+
+    /// Returns the base address the image is loaded at.
+    pub fn load_base(&self) -> Address {
+        self.load_base
+    }
+
+    /// Returns the difference between the load base and the preferred base.
+    pub fn delta(&self) -> u64 {
+        (self.load_base.to_umem() as u64).wrapping_sub(self.preferred_base)
+    }
+
+    /// Returns the sections of the image.
+    pub fn sections(&self) -> &[PeSectionMapping] {
+        &self.sections
+    }
+
+    /// Returns the section containing `rva`.
+    pub fn section_by_rva(&self, rva: u32) -> Option<&PeSectionMapping> {
+        self.sections
+            .iter()
+            .find(|s| rva >= s.rva && rva - s.rva < std::cmp::max(s.virtual_size, s.raw_size))
+    }
+
+    /// Returns the `(rva, width)` pairs of all base relocations.
+    pub fn relocations(&self) -> &[(u32, usize)] {
+        &self.relocations
+    }
+
+    /// Converts an RVA to an offset into the image file.
+    ///
+    /// Returns `None` for RVAs without file backing, e.g. uninitialized data.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        if rva < self.size_of_headers {
+            return Some(rva as usize);
+        }
+
+        self.sections
+            .iter()
+            .find(|s| rva >= s.rva && rva - s.rva < s.raw_size)
+            .map(|s| (rva - s.rva) as usize + s.file_offset as usize)
+    }
+
+    /// Converts an offset into the image file to an RVA.
+    pub fn offset_to_rva(&self, offset: usize) -> Option<u32> {
+        let offset: u32 = offset.try_into().ok()?;
+        if offset < self.size_of_headers {
+            return Some(offset);
+        }
+
+        self.sections
+            .iter()
+            .find(|s| offset >= s.file_offset && offset - s.file_offset < s.raw_size)
+            .map(|s| offset - s.file_offset + s.rva)
+    }
+
+    /// Converts an RVA to a virtual address of the loaded image.
+    pub fn rva_to_va(&self, rva: u32) -> Address {
+        self.load_base + rva as umem
+    }
+
+    /// Converts a virtual address of the loaded image to an RVA.
+    pub fn va_to_rva(&self, va: Address) -> Option<u32> {
+        if va < self.load_base {
+            return None;
+        }
+        (va - self.load_base).try_into().ok()
+    }
+
+    /// Converts an offset into the image file to a virtual address of the loaded image.
+    pub fn offset_to_va(&self, offset: usize) -> Option<Address> {
+        self.offset_to_rva(offset).map(|rva| self.rva_to_va(rva))
+    }
+
+    /// Converts a virtual address of the loaded image to an offset into the image file.
+    pub fn va_to_offset(&self, va: Address) -> Option<usize> {
+        self.va_to_rva(va).and_then(|rva| self.rva_to_offset(rva))
+    }
+
+    /// Converts an address relative to the preferred base, e.g. from a disassembly of the file,
+    /// to the corresponding address of the loaded image.
+    pub fn preferred_to_loaded(&self, va: u64) -> Address {
+        Address::from(va.wrapping_add(self.delta()) as umem)
+    }
+
+    /// Converts an address of the loaded image to the corresponding address relative to the
+    /// preferred base.
+    pub fn loaded_to_preferred(&self, va: Address) -> u64 {
+        (va.to_umem() as u64).wrapping_sub(self.delta())
+    }
+
+    /// Returns `len` bytes of the image file starting at `rva` with all base relocations
+    /// applied, i.e. as they are expected to be found in memory.
+    ///
+    /// Returns `None` if the range is not entirely backed by the image file.
+    pub fn relocated_bytes(&self, image: &[u8], rva: u32, len: usize) -> Option<Vec<u8>> {
+        let start = self.rva_to_offset(rva)?;
+        if len > 0 {
+            let last = self.rva_to_offset(rva.checked_add(len as u32 - 1)?)?;
+            if last != start + len - 1 {
+                return None;
+            }
+        }
+        let mut buf = image.get(start..start + len)?.to_vec();
+        apply_relocations(&mut buf, rva, &self.relocations, self.delta());
+        Some(buf)
+    }
+}
+
+/// Applies the relocations to `buf`, which holds the image contents starting at `rva`.
+///
+/// Relocations crossing the bounds of `buf` are applied to the bytes inside of it. If the low
+/// bytes of a relocation are in front of `buf`, the carry into the remaining bytes is only known
+/// if the corresponding bytes of `delta` are zero, which holds for the 64 KiB aligned image bases
+/// of PE files. Otherwise the relocation is skipped.
+pub(crate) fn apply_relocations(buf: &mut [u8], rva: u32, relocs: &[(u32, usize)], delta: u64) {
+    for &(reloc, width) in relocs.iter() {
+        // number of bytes of the relocation in front of buf and its offset into buf
+        let (skipped, off) = match reloc.checked_sub(rva) {
+            Some(off) => (0, off as usize),
+            None => ((rva - reloc) as usize, 0),
+        };
+        if skipped >= width || off >= buf.len() {
+            continue;
+        }
+        if skipped > 0 && delta & ((1u64 << (skipped * 8)) - 1) != 0 {
+            continue;
+        }
+        let len = std::cmp::min(width - skipped, buf.len() - off);
+
+        let mut value = [0u8; 8];
+        value[skipped..skipped + len].copy_from_slice(&buf[off..off + len]);
+        let value = if width == 8 {
+            u64::from_le_bytes(value).wrapping_add(delta)
+        } else {
+            let v = u32::from_le_bytes(value[..4].try_into().unwrap());
+            v.wrapping_add(delta as u32) as u64
+        };
+        buf[off..off + len].copy_from_slice(&value.to_le_bytes()[skipped..skipped + len]);
+    }
+}
+
+/// Parses the base relocation directory into a list of `(rva, width)` pairs.
+pub(crate) fn pe_relocations(pe: &PE, image: &[u8]) -> Vec<(u32, usize)> {
+    let dir = match pe
+        .header
+        .optional_header
+        .as_ref()
+        .and_then(|h| h.data_directories.get_base_relocation_table().as_ref())
+    {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+
+    match rva_to_offset(&pe.sections, dir.virtual_address)
+        .and_then(|off| image.get(off..off + dir.size as usize))
+    {
+        Some(data) => parse_relocations(data),
+        None => vec![],
+    }
+}
+
+/// Parses the contents of a base relocation directory into a list of `(rva, width)` pairs.
+pub(crate) fn parse_relocations(data: &[u8]) -> Vec<(u32, usize)> {
+    let mut ret = vec![];
+
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let page = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        let block_size = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+
+        let entries = match data.get(pos + 8..pos + block_size) {
+            Some(entries) if block_size >= 8 => entries,
+            _ => break,
+        };
+
+        for entry in entries.chunks_exact(2) {
+            let entry = u16::from_le_bytes(entry.try_into().unwrap());
+            let rva = page.wrapping_add((entry & 0xfff) as u32);
+            match entry >> 12 {
+                // IMAGE_REL_BASED_HIGHLOW
+                3 => ret.push((rva, 4)),
+                // IMAGE_REL_BASED_DIR64
+                10 => ret.push((rva, 8)),
+                _ => {}
+            }
+        }
+
+        pos += block_size;
+    }
+
+    ret
+}
+
+pub(crate) fn rva_to_offset(sections: &[SectionTable], rva: u32) -> Option<usize> {
+    sections
+        .iter()
+        .find(|s| rva >= s.virtual_address && rva - s.virtual_address < s.size_of_raw_data)
+        .map(|s| (rva - s.virtual_address) as usize + s.pointer_to_raw_data as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a minimal PE32+ image linked at 0x140000000 with a single `.text` section at
+    /// RVA 0x1000, file offset 0x200, containing one relocated pointer at RVA 0x1010.
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x400];
+        let put = |image: &mut Vec<u8>, off: usize, data: &[u8]| {
+            image[off..off + data.len()].copy_from_slice(data)
+        };
+
+        put(&mut image, 0, b"MZ");
+        put(&mut image, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut image, 0x40, b"PE\0\0");
+
+        // file header
+        put(&mut image, 0x44, &0x8664u16.to_le_bytes());
+        put(&mut image, 0x46, &1u16.to_le_bytes());
+        put(&mut image, 0x54, &0xf0u16.to_le_bytes());
+        put(&mut image, 0x56, &0x22u16.to_le_bytes());
+
+        // optional header
+        let opt = 0x58;
+        put(&mut image, opt, &0x20bu16.to_le_bytes());
+        put(&mut image, opt + 24, &0x140000000u64.to_le_bytes());
+        put(&mut image, opt + 32, &0x1000u32.to_le_bytes());
+        put(&mut image, opt + 36, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 56, &0x2000u32.to_le_bytes());
+        put(&mut image, opt + 60, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 108, &16u32.to_le_bytes());
+        // base relocation directory
+        put(&mut image, opt + 152, &0x1080u32.to_le_bytes());
+        put(&mut image, opt + 156, &12u32.to_le_bytes());
+
+        // section table
+        let sec = opt + 0xf0;
+        put(&mut image, sec, b".text\0\0\0");
+        put(&mut image, sec + 8, &0x100u32.to_le_bytes());
+        put(&mut image, sec + 12, &0x1000u32.to_le_bytes());
+        put(&mut image, sec + 16, &0x200u32.to_le_bytes());
+        put(&mut image, sec + 20, &0x200u32.to_le_bytes());
+        put(&mut image, sec + 36, &0x60000020u32.to_le_bytes());
+
+        // pointer to RVA 0x1020 and its relocation block
+        put(&mut image, 0x210, &0x140001020u64.to_le_bytes());
+        put(&mut image, 0x280, &0x1000u32.to_le_bytes());
+        put(&mut image, 0x284, &12u32.to_le_bytes());
+        put(&mut image, 0x288, &((10u16 << 12) | 0x10).to_le_bytes());
+
+        image
+    }
+
+    #[test]
+    fn conversions() {
+        let image = image();
+        let map = PeAddressMap::new(&image, Address::from(0x7ff600000000u64)).unwrap();
+
+        assert_eq!(map.relocations(), &[(0x1010, 8)]);
+        assert_eq!(map.section_by_rva(0x1010).unwrap().name, ".text");
+
+        assert_eq!(map.rva_to_offset(0x1010), Some(0x210));
+        assert_eq!(map.rva_to_offset(0x40), Some(0x40));
+        assert_eq!(map.rva_to_offset(0x1300), None);
+        assert_eq!(map.offset_to_rva(0x210), Some(0x1010));
+        assert_eq!(
+            map.offset_to_va(0x210),
+            Some(Address::from(0x7ff600001010u64))
+        );
+        assert_eq!(
+            map.va_to_offset(Address::from(0x7ff600001010u64)),
+            Some(0x210)
+        );
+
+        assert_eq!(
+            map.preferred_to_loaded(0x140001020),
+            Address::from(0x7ff600001020u64)
+        );
+        assert_eq!(
+            map.loaded_to_preferred(Address::from(0x7ff600001020u64)),
+            0x140001020
+        );
+
+        let bytes = map.relocated_bytes(&image, 0x1010, 8).unwrap();
+        assert_eq!(
+            u64::from_le_bytes(bytes.try_into().unwrap()),
+            0x7ff600001020
+        );
+
+        // the carry out of the uncovered low half of the relocation is unknown
+        let bytes = map.relocated_bytes(&image, 0x1014, 4).unwrap();
+        assert_eq!(bytes, [1, 0, 0, 0]);
+
+        // relocations partially covered by the range are applied to the covered bytes
+        let bytes = map.relocated_bytes(&image, 0x1008, 12).unwrap();
+        assert_eq!(bytes[8..], 0x1020u32.to_le_bytes());
+    }
+
+    #[test]
+    fn straddling_relocations() {
+        let relocs = [(0x10, 8)];
+
+        // only the low half of the relocation is covered, the carry out of it is dropped
+        let mut buf = 0xfffffff0u32.to_le_bytes();
+        apply_relocations(&mut buf, 0x10, &relocs, 0x20);
+        assert_eq!(buf, 0x10u32.to_le_bytes());
+
+        // the carry out of the uncovered low half is unknown
+        let mut buf = 1u32.to_le_bytes();
+        apply_relocations(&mut buf, 0x14, &relocs, 0x20);
+        assert_eq!(buf, 1u32.to_le_bytes());
+
+        // unless the low half of the delta is zero
+        apply_relocations(&mut buf, 0x14, &relocs, 0x2_0000_0000);
+        assert_eq!(buf, 3u32.to_le_bytes());
+
+        // relocations outside of the buffer are ignored, even close to the end of the rva space
+        let mut buf = [0u8; 4];
+        apply_relocations(&mut buf, 0x20, &[(0x10, 8), (u32::MAX, 8)], 0x20);
+        assert_eq!(buf, [0u8; 4]);
+    }
+}
@@ -0,0 +1,554 @@
+//! Introspection of kernel pool allocations.
+//!
+//! Structures found while scanning kernel memory are much easier to attribute once it is known
+//! which pool allocation they are part of. The pool tag identifies the allocating component and
+//! the allocation size bounds the structure.
+//!
+//! Two kinds of allocations are distinguished:
+//!
+//! * Big allocations of a page or more are tracked in the big pool table
+//!   (`nt!PoolBigPageTable`), an array of `POOL_TRACKER_BIG_PAGES` entries. [`BigPoolTable`]
+//!   reads and queries this table.
+//! * Small allocations are preceded by a `POOL_HEADER` and packed into pages. On kernels using
+//!   the legacy pool allocator the headers of a page can be walked from its start, which is what
+//!   [`find_pool_allocation`] does if the address is not part of a big allocation.
+//!
+//! Pages of the segment heap based pool (Windows 10 19H1 and later) can not be walked, only big
+//! allocations can be found there.
+//!
+//! [`scan_pool_tag`] finds small blocks by their tag without walking the pages, which also
+//! finds freed blocks whose contents are still intact.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::pool::{find_pool_allocation, BigPoolTable, PoolLayout};
+//!
+//! fn describe(
+//!     kernel: &mut impl MemoryView,
+//!     symbols: impl FnMut(&str) -> Option<Address>,
+//!     addr: Address,
+//! ) -> Result<()> {
+//!     let layout = PoolLayout::x64();
+//!     let big_pool = BigPoolTable::from_symbols(kernel, layout, symbols)?;
+//!
+//!     let alloc = find_pool_allocation(kernel, layout, Some(&big_pool), addr)?;
+//!     println!("{:x} is part of a {:#x} byte '{}' allocation", addr, alloc.size, alloc.tag);
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # assert!(describe(&mut proc, |_| None, Address::null()).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{size, umem, Address};
+
+/// Maximum number of big pool table entries read, protecting against corrupted table sizes.
+pub const MAX_BIG_POOL_ENTRIES: usize = 0x100000;
+
+/// Four character tag identifying the owner of a pool allocation.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PoolTag(pub [u8; 4]);
+
+impl PoolTag {
+    /// Creates a tag from its characters.
+    pub const fn new(tag: &[u8; 4]) -> Self {
+        Self(*tag)
+    }
+
+    /// Creates a tag from its in-memory representation.
+    pub fn from_u32(tag: u32) -> Self {
+        Self(tag.to_le_bytes())
+    }
+
+    /// Returns the tag without the protected bit set by older kernels on the last character.
+    pub fn unprotected(self) -> Self {
+        let mut tag = self.0;
+        tag[3] &= 0x7f;
+        Self(tag)
+    }
+}
+
+impl fmt::Display for PoolTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tag = self
+            .0
+            .iter()
+            .map(|&c| {
+                if c.is_ascii_graphic() || c == b' ' {
+                    c as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        f.pad(&tag)
+    }
+}
+
+impl fmt::Debug for PoolTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PoolTag({})", self)
+    }
+}
+
+/// Sizes and offsets of the pool structures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PoolLayout {
+    /// Size of a pointer
+    pub pointer_size: usize,
+    /// Size of a `POOL_TRACKER_BIG_PAGES` entry
+    pub big_entry_size: usize,
+    /// Offset of the `Key` field (the tag) in a big pool entry
+    pub big_key: usize,
+    /// Offset of the field containing the pool type in a big pool entry
+    pub big_pool_type: usize,
+    /// Bit position of the pool type within its field
+    ///
+    /// Newer kernels share the field with the `Pattern` and `SlushSize` bitfields.
+    pub big_pool_type_shift: u32,
+    /// Offset of the `NumberOfBytes` field in a big pool entry
+    pub big_number_of_bytes: usize,
+    /// Size of a `POOL_HEADER`, which is also the granularity of small allocations
+    pub header_size: usize,
+}
+
+impl PoolLayout {
+    /// Layout of 64-bit kernels since Windows 8.
+    pub const fn x64() -> Self {
+        Self {
+            pointer_size: 8,
+            big_entry_size: 0x18,
+            big_key: 0x8,
+            big_pool_type: 0xc,
+            big_pool_type_shift: 8,
+            big_number_of_bytes: 0x10,
+            header_size: 0x10,
+        }
+    }
+
+    /// Layout of 64-bit Windows 7 kernels, where the pool type occupies the entire field.
+    pub const fn x64_win7() -> Self {
+        Self {
+            big_pool_type_shift: 0,
+            ..Self::x64()
+        }
+    }
+
+    /// Layout of 32-bit kernels since Windows 8.
+    pub const fn x86() -> Self {
+        Self {
+            pointer_size: 4,
+            big_entry_size: 0xc,
+            big_key: 0x4,
+            big_pool_type: 0x8,
+            big_pool_type_shift: 8,
+            big_number_of_bytes: 0x8,
+            header_size: 0x8,
+        }
+    }
+
+    fn read_ptr(&self, buf: &[u8]) -> umem {
+        if self.pointer_size == 8 {
+            u64::from_le_bytes(buf[..8].try_into().unwrap()) as umem
+        } else {
+            u32::from_le_bytes(buf[..4].try_into().unwrap()) as umem
+        }
+    }
+}
+
+/// An entry of the big pool table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BigPoolEntry {
+    /// Start of the allocation
+    pub address: Address,
+    /// Tag of the allocation
+    pub tag: PoolTag,
+    /// Size of the allocation in bytes
+    pub size: umem,
+    /// True if the allocation is pageable
+    pub paged: bool,
+}
+
+impl BigPoolEntry {
+    /// Returns true if `address` is part of the allocation.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.address && address.to_umem() - self.address.to_umem() < self.size
+    }
+}
+
+/// Snapshot of the big pool allocation table.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BigPoolTable {
+    /// In-use entries sorted by address
+    entries: Vec<BigPoolEntry>,
+}
+
+impl BigPoolTable {
+    /// Reads the table of `count` entries at `table`.
+    ///
+    /// Free entries are skipped. Parts of the table that can not be read are treated as free.
+    pub fn read(
+        mem: &mut impl MemoryView,
+        layout: PoolLayout,
+        table: Address,
+        count: usize,
+    ) -> Result<Self> {
+        if count > MAX_BIG_POOL_ENTRIES {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_warn("big pool table size exceeds the maximum number of entries"));
+        }
+
+        let mut buf = vec![0u8; count * layout.big_entry_size];
+        mem.read_raw_into(table, &mut buf).data_part()?;
+
+        let mut entries = buf
+            .chunks_exact(layout.big_entry_size)
+            .filter_map(|entry| {
+                let va = layout.read_ptr(entry);
+                // the lowest bit of the address marks free entries
+                if va == 0 || va & 1 != 0 {
+                    return None;
+                }
+
+                let field =
+                    |off: usize| u32::from_le_bytes(entry[off..off + 4].try_into().unwrap());
+                let pool_type = field(layout.big_pool_type) >> layout.big_pool_type_shift;
+
+                Some(BigPoolEntry {
+                    address: Address::from(va),
+                    tag: PoolTag::from_u32(field(layout.big_key)),
+                    size: layout.read_ptr(&entry[layout.big_number_of_bytes..]),
+                    paged: pool_type & 1 != 0,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by_key(|e| e.address);
+
+        Ok(Self { entries })
+    }
+
+    /// Reads the table located through the `PoolBigPageTable` and `PoolBigPageTableSize`
+    /// kernel variables, resolved through `resolve`.
+    pub fn from_symbols(
+        mem: &mut impl MemoryView,
+        layout: PoolLayout,
+        mut resolve: impl FnMut(&str) -> Option<Address>,
+    ) -> Result<Self> {
+        let (table_ptr, size_ptr) = resolve("PoolBigPageTable")
+            .zip(resolve("PoolBigPageTableSize"))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_info("unable to resolve the big pool table")
+            })?;
+
+        let mut buf = [0u8; 8];
+        mem.read_raw_into(table_ptr, &mut buf[..layout.pointer_size])?;
+        let table = Address::from(layout.read_ptr(&buf));
+
+        buf = [0u8; 8];
+        mem.read_raw_into(size_ptr, &mut buf[..layout.pointer_size])?;
+        let count = layout.read_ptr(&buf) as usize;
+
+        Self::read(mem, layout, table, count)
+    }
+
+    /// Returns all in-use entries sorted by address.
+    pub fn entries(&self) -> &[BigPoolEntry] {
+        &self.entries
+    }
+
+    /// Returns the allocation containing `address`.
+    pub fn find(&self, address: Address) -> Option<&BigPoolEntry> {
+        let idx = self.entries.partition_point(|e| e.address <= address);
+        self.entries[..idx]
+            .last()
+            .filter(|entry| entry.contains(address))
+    }
+
+    /// Returns all allocations overlapping the range from `start` to `end`.
+    pub fn entries_in(
+        &self,
+        start: Address,
+        end: Address,
+    ) -> impl Iterator<Item = &BigPoolEntry> + '_ {
+        let first = self
+            .entries
+            .partition_point(|e| e.address.to_umem() + e.size <= start.to_umem());
+        self.entries[first..]
+            .iter()
+            .take_while(move |e| e.address < end)
+            .filter(move |e| e.address.to_umem() + e.size > start.to_umem())
+    }
+
+    /// Returns all allocations with the given tag.
+    pub fn entries_with_tag(&self, tag: PoolTag) -> impl Iterator<Item = &BigPoolEntry> + '_ {
+        self.entries.iter().filter(move |e| e.tag == tag)
+    }
+}
+
+/// A pool allocation containing a queried address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PoolAllocation {
+    /// Start of the allocation, including the pool header of small allocations
+    pub address: Address,
+    /// Start of the data returned to the allocating component
+    pub data: Address,
+    /// Size of the allocation in bytes, including the pool header of small allocations
+    pub size: umem,
+    /// Tag of the allocation
+    pub tag: PoolTag,
+    /// True if the allocation is pageable
+    pub paged: bool,
+    /// True if the allocation was found in the big pool table
+    pub big: bool,
+    /// True if the block was freed, its contents may still be intact
+    pub free: bool,
+}
+
+impl From<BigPoolEntry> for PoolAllocation {
+    fn from(entry: BigPoolEntry) -> Self {
+        Self {
+            address: entry.address,
+            data: entry.address,
+            size: entry.size,
+            tag: entry.tag,
+            paged: entry.paged,
+            big: true,
+            free: false,
+        }
+    }
+}
+
+/// Returns the pool allocation containing `address`.
+///
+/// The big pool table is consulted first, if given. Otherwise the pool headers of the page
+/// containing `address` are walked from the start of the page. Free blocks and addresses that
+/// are not part of a consistent chain of headers are reported as [`ErrorKind::NotFound`].
+pub fn find_pool_allocation(
+    mem: &mut impl MemoryView,
+    layout: PoolLayout,
+    big_pool: Option<&BigPoolTable>,
+    address: Address,
+) -> Result<PoolAllocation> {
+    if let Some(entry) = big_pool.and_then(|t| t.find(address)) {
+        return Ok((*entry).into());
+    }
+
+    let page_size = size::kb(4);
+    let page = address.as_page_aligned(page_size);
+    let target = (address - page) as usize;
+
+    let mut buf = vec![0u8; page_size];
+    mem.read_raw_into(page, &mut buf)?;
+
+    let mut offset = 0;
+    let mut prev_blocks = 0;
+    while offset + layout.header_size <= page_size {
+        let header = &buf[offset..offset + layout.header_size];
+        let (prev, blocks, pool_type) = decode_header(layout, header);
+
+        if blocks == 0 || prev != prev_blocks {
+            break;
+        }
+
+        let size = blocks * layout.header_size;
+        if target < offset + size {
+            // allocated blocks store their pool type incremented by one
+            if pool_type == 0 {
+                break;
+            }
+
+            return Ok(PoolAllocation {
+                address: page + offset,
+                data: page + offset + layout.header_size,
+                size: size as umem,
+                tag: PoolTag::from_u32(u32::from_le_bytes(header[4..8].try_into().unwrap())),
+                paged: (pool_type - 1) & 1 != 0,
+                big: false,
+                free: false,
+            });
+        }
+
+        offset += size;
+        prev_blocks = blocks;
+    }
+
+    Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+        .log_debug("address is not part of a pool allocation"))
+}
+
+/// Scans `len` bytes starting at `start` for small pool blocks tagged with `tag`.
+///
+/// Every header aligned position is checked, so blocks are found independently of the
+/// surrounding headers. This includes freed blocks, which keep their tag until the memory is
+/// reused. The protected bit of the tag is ignored. Pages that can not be read are skipped.
+pub fn scan_pool_tag(
+    mem: &mut impl MemoryView,
+    layout: PoolLayout,
+    start: Address,
+    len: umem,
+    tag: PoolTag,
+) -> Result<Vec<PoolAllocation>> {
+    let page_size = size::kb(4);
+    let tag = tag.unprotected();
+
+    let mut ret = vec![];
+    let mut buf = vec![0u8; page_size];
+
+    let mut page = start.as_page_aligned(page_size);
+    while page < start + len {
+        if mem.read_raw_into(page, &mut buf).is_err() {
+            page += page_size;
+            continue;
+        }
+
+        for offset in (0..page_size).step_by(layout.header_size) {
+            let header = &buf[offset..offset + layout.header_size];
+            let block_tag = PoolTag::from_u32(u32::from_le_bytes(header[4..8].try_into().unwrap()));
+            if block_tag.unprotected() != tag {
+                continue;
+            }
+
+            // small blocks never cross page boundaries
+            let (_, blocks, pool_type) = decode_header(layout, header);
+            let size = blocks * layout.header_size;
+            let address = page + offset;
+            if blocks == 0 || offset + size > page_size || address < start {
+                continue;
+            }
+
+            ret.push(PoolAllocation {
+                address,
+                data: address + layout.header_size,
+                size: size as umem,
+                tag: block_tag,
+                paged: pool_type != 0 && (pool_type - 1) & 1 != 0,
+                big: false,
+                free: pool_type == 0,
+            });
+        }
+
+        page += page_size;
+    }
+
+    Ok(ret)
+}
+
+/// Decodes the previous size, block size and pool type of a `POOL_HEADER`.
+fn decode_header(layout: PoolLayout, header: &[u8]) -> (usize, usize, u8) {
+    if layout.pointer_size == 8 {
+        (header[0] as usize, header[2] as usize, header[3])
+    } else {
+        let lo = u16::from_le_bytes([header[0], header[1]]);
+        let hi = u16::from_le_bytes([header[2], header[3]]);
+        (
+            (lo & 0x1ff) as usize,
+            (hi & 0x1ff) as usize,
+            (hi >> 9) as u8,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    fn big_entry(va: u64, tag: &[u8; 4], pool_type: u32, size: u64) -> Vec<u8> {
+        let mut entry = va.to_le_bytes().to_vec();
+        entry.extend_from_slice(tag);
+        entry.extend_from_slice(&(pool_type << 8).to_le_bytes());
+        entry.extend_from_slice(&size.to_le_bytes());
+        entry
+    }
+
+    fn pool_header(prev: u8, blocks: u8, pool_type: u8, tag: &[u8; 4]) -> Vec<u8> {
+        let mut header = vec![prev, 0, blocks, pool_type];
+        header.extend_from_slice(tag);
+        header.extend_from_slice(&[0; 8]);
+        header
+    }
+
+    #[test]
+    fn allocations() {
+        let mut buf = vec![0u8; 0x3000];
+
+        // small allocations in the second page: 0x20 bytes "Proc", 0x40 bytes free, 0x30 "File"
+        let page = 0x1000;
+        buf[page..page + 0x10].copy_from_slice(&pool_header(0, 2, 1, b"Proc"));
+        buf[page + 0x20..page + 0x30].copy_from_slice(&pool_header(2, 4, 0, b"Free"));
+        buf[page + 0x60..page + 0x70].copy_from_slice(&pool_header(4, 3, 2, b"File"));
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // the big pool table is placed at the start and contains one free and one used entry
+        let mut table = big_entry(0xfffff000_00000001, b"Free", 0, 0x2000);
+        table.extend(big_entry(
+            (base + 0x2000usize).to_umem() as u64,
+            b"Big ",
+            1,
+            0x1000,
+        ));
+        buf[..table.len()].copy_from_slice(&table);
+
+        proc.write_raw(base, &buf).unwrap();
+
+        let layout = PoolLayout::x64();
+        let big_pool = BigPoolTable::read(&mut proc, layout, base, 2).unwrap();
+        assert_eq!(big_pool.entries().len(), 1);
+
+        let alloc =
+            find_pool_allocation(&mut proc, layout, Some(&big_pool), base + 0x2800usize).unwrap();
+        assert!(alloc.big && alloc.paged);
+        assert_eq!(alloc.tag, PoolTag::new(b"Big "));
+        assert_eq!(alloc.size, 0x1000);
+
+        let alloc = find_pool_allocation(&mut proc, layout, None, base + 0x1018usize).unwrap();
+        assert_eq!(alloc.tag, PoolTag::new(b"Proc"));
+        assert_eq!(alloc.address, base + 0x1000usize);
+        assert_eq!(alloc.size, 0x20);
+        assert!(!alloc.paged);
+
+        let alloc = find_pool_allocation(&mut proc, layout, None, base + 0x1070usize).unwrap();
+        assert_eq!(alloc.tag.to_string(), "File");
+        assert!(alloc.paged);
+
+        assert!(find_pool_allocation(&mut proc, layout, None, base + 0x1040usize).is_err());
+
+        let found = scan_pool_tag(
+            &mut proc,
+            layout,
+            base,
+            0x3000,
+            PoolTag::new(&[b'F', b'r', b'e', b'e' | 0x80]),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].address, base + 0x1020usize);
+        assert!(found[0].free);
+        assert_eq!(
+            big_pool
+                .entries_in(base + 0x1000usize, base + 0x2001usize)
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Regular expression scanning over memory.
+//!
+//! A [`RegexScanner`] streams through a memory range and matches a byte oriented regular
+//! expression against it. Memory is read in chunks, while a sliding window of the last
+//! [`max_match_len`](RegexScanner::max_match_len) bytes is kept across chunk boundaries:
+//!
+//! - Matches that start close to the end of the window are deferred until the next chunk has been
+//!   read, so matches spanning chunk boundaries are found in full.
+//! - Matches are never reported twice, since scanning resumes behind the end of the last reported
+//!   match.
+//!
+//! Matches are thus identical to matching the whole range at once, as long as no match is longer
+//! than `max_match_len` bytes. Longer matches are truncated.
+//!
+//! Unreadable memory is treated as zero bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::regex_scan::RegexScanner;
+//!
+//! fn find_keys(proc: &mut (impl Process + MemoryView)) -> Result<()> {
+//!     let scanner = RegexScanner::new(r"AKIA[0-9A-Z]{16}")?;
+//!
+//!     for m in scanner.scan_process(proc)? {
+//!         println!("{}: {}", m.address, String::from_utf8_lossy(&m.data));
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), b"\0AKIAABCDEFGHIJKLMNOP\0");
+//! # find_keys(&mut proc).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use regex::bytes::Regex;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::os::Process;
+use crate::types::{umem, Address};
+
+/// Default chunk size used when streaming through memory.
+pub const DEFAULT_CHUNK_SIZE: usize = 0x100000;
+
+/// Default upper bound for the length of a match.
+pub const DEFAULT_MAX_MATCH_LEN: usize = 0x1000;
+
+/// Match found by a [`RegexScanner`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RegexMatch {
+    /// Address of the first byte of the match
+    pub address: Address,
+    /// Contents of the match
+    pub data: Vec<u8>,
+}
+
+/// Streaming regular expression scanner.
+#[derive(Clone, Debug)]
+pub struct RegexScanner {
+    regex: Regex,
+    max_match_len: usize,
+    chunk_size: usize,
+}
+
+impl RegexScanner {
+    /// Compiles `pattern` into a new scanner.
+    ///
+    /// The pattern is matched against raw bytes, Unicode support can be disabled with `(?-u)` to
+    /// match arbitrary bytes through escapes like `\xff`.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| Error(ErrorOrigin::Other, ErrorKind::InvalidArgument).log_error(err))?;
+        Ok(Self::from_regex(regex))
+    }
+
+    /// Creates a new scanner from an already compiled regex.
+    pub fn from_regex(regex: Regex) -> Self {
+        Self {
+            regex,
+            max_match_len: DEFAULT_MAX_MATCH_LEN,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the maximum length of a match, which is the amount of bytes kept between chunks.
+    pub fn max_match_len(mut self, max_match_len: usize) -> Self {
+        self.max_match_len = std::cmp::max(max_match_len, 1);
+        self
+    }
+
+    /// Sets the number of bytes read at once.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns the underlying regex.
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// Returns all matches in the given range.
+    pub fn scan(
+        &self,
+        mem: &mut impl MemoryView,
+        addr: Address,
+        len: umem,
+    ) -> Result<Vec<RegexMatch>> {
+        let mut ret = vec![];
+        self.scan_with(mem, addr, len, |m| {
+            ret.push(m);
+            true
+        })?;
+        Ok(ret)
+    }
+
+    /// Returns all matches in the mapped memory of `proc`.
+    pub fn scan_process(&self, proc: &mut (impl Process + MemoryView)) -> Result<Vec<RegexMatch>> {
+        let mut ret = vec![];
+        for range in proc.mapped_mem_vec(0) {
+            ret.extend(self.scan(proc, range.0, range.1)?);
+        }
+        Ok(ret)
+    }
+
+    /// Returns all matches in the entire physical memory of `mem`.
+    pub fn scan_physical(&self, mem: &mut impl PhysicalMemory) -> Result<Vec<RegexMatch>> {
+        let len = mem.metadata().max_address.to_umem().saturating_add(1);
+        self.scan(&mut mem.phys_view(), Address::null(), len)
+    }
+
+    /// Calls `out` for every match in the given range, in ascending order.
+    ///
+    /// The scan stops once `out` returns false.
+    pub fn scan_with(
+        &self,
+        mem: &mut impl MemoryView,
+        addr: Address,
+        len: umem,
+        mut out: impl FnMut(RegexMatch) -> bool,
+    ) -> Result<()> {
+        // the window always starts at `window_addr` and ends at `chunk_addr`
+        let mut window: Vec<u8> = vec![];
+        let mut window_addr = addr;
+        // offset into the window at which matching resumes
+        let mut pos = 0;
+
+        let end = addr + len;
+        let mut chunk_addr = addr;
+
+        while chunk_addr < end {
+            let chunk_len = std::cmp::min(self.chunk_size as umem, (end - chunk_addr) as umem);
+            let old_len = window.len();
+            window.resize(old_len + chunk_len as usize, 0);
+            mem.read_raw_into(chunk_addr, &mut window[old_len..])
+                .data_part()?;
+            chunk_addr += chunk_len;
+
+            let last = chunk_addr >= end;
+
+            while pos <= window.len() {
+                let m = match self.regex.find_at(&window, pos) {
+                    Some(m) => m,
+                    None => {
+                        // a match might still start in the tail once more data is available
+                        if !last {
+                            pos =
+                                std::cmp::max(pos, window.len().saturating_sub(self.max_match_len));
+                        }
+                        break;
+                    }
+                };
+
+                // the match might continue in the next chunk
+                if !last && m.start() + self.max_match_len > window.len() {
+                    pos = m.start();
+                    break;
+                }
+
+                let found = RegexMatch {
+                    address: window_addr + m.start(),
+                    data: window[m.start()..m.end()].to_vec(),
+                };
+                if !out(found) {
+                    return Ok(());
+                }
+
+                // empty matches would otherwise be found over and over again
+                pos = if m.end() > m.start() {
+                    m.end()
+                } else {
+                    m.end() + 1
+                };
+            }
+
+            // keep enough bytes for deferred matches and look-behind assertions like `\b`
+            let keep_from = std::cmp::min(pos, window.len().saturating_sub(self.max_match_len));
+            window.drain(..keep_from);
+            window_addr += keep_from;
+            pos -= keep_from;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn chunk_boundaries() {
+        let mut buf = vec![0u8; 5];
+        buf.extend_from_slice(b"key=0123456789abcdef;");
+        buf.extend_from_slice(&[0; 3]);
+        buf.extend_from_slice(b"key=fedcba;key=42;");
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let addr = proc.info().address;
+
+        let expected = vec![
+            RegexMatch {
+                address: addr + 5,
+                data: b"key=0123456789abcdef;".to_vec(),
+            },
+            RegexMatch {
+                address: addr + 29,
+                data: b"key=fedcba;".to_vec(),
+            },
+            RegexMatch {
+                address: addr + 40,
+                data: b"key=42;".to_vec(),
+            },
+        ];
+
+        for chunk_size in [1, 3, 7, 64] {
+            let matches = RegexScanner::new("key=[0-9a-f]+;")
+                .unwrap()
+                .max_match_len(32)
+                .chunk_size(chunk_size)
+                .scan(&mut proc, addr, buf.len() as umem)
+                .unwrap();
+            assert_eq!(matches, expected);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Remains of unloaded drivers and exited processes.
+//!
+//! Drivers and processes disappear from the kernel lists as soon as they are unloaded or exit,
+//! but traces of them stay around for a while:
+//!
+//! * The kernel records the name, address range and unload time of the last 50 unloaded drivers
+//!   in the `MmUnloadedDrivers` ring buffer. [`read_unloaded_drivers`] reads it.
+//! * The `EPROCESS` allocation of an exited process is freed once the last reference is gone,
+//!   but its contents remain intact until the memory is reused. [`find_exited_processes`] scans
+//!   for `Proc` pool blocks and reports the processes that are no longer active.
+//!
+//! Both are a common forensic signal of short lived tools and drivers that were loaded just
+//! long enough to do their job.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::residue::unloaded_drivers_from_symbols;
+//!
+//! fn print_unloaded(
+//!     kernel: &mut impl MemoryView,
+//!     arch: ArchitectureObj,
+//!     symbols: impl FnMut(&str) -> Option<Address>,
+//! ) -> Result<()> {
+//!     for driver in unloaded_drivers_from_symbols(kernel, arch, symbols)? {
+//!         println!("{} unloaded at {:?}", driver.name, driver.unix_time());
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # assert!(print_unloaded(&mut proc, x64::ARCH, |_| None).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use super::pool::{scan_pool_tag, PoolLayout, PoolTag};
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Number of entries of the `MmUnloadedDrivers` ring buffer.
+pub const MAX_UNLOADED_DRIVERS: usize = 50;
+
+/// Pool tag of process objects.
+pub const PROCESS_POOL_TAG: PoolTag = PoolTag::new(b"Proc");
+
+/// Earliest plausible process creation time (2000-01-01) as a Windows `FILETIME`.
+const MIN_FILETIME: u64 = 125_911_584_000_000_000;
+
+/// Latest plausible process creation time (2100-01-01) as a Windows `FILETIME`.
+const MAX_FILETIME: u64 = 157_469_184_000_000_000;
+
+/// Converts a Windows `FILETIME` to seconds since the unix epoch.
+fn filetime_to_unix(time: u64) -> Option<u64> {
+    (time / 10_000_000).checked_sub(11_644_473_600)
+}
+
+/// A driver recorded in `MmUnloadedDrivers`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct UnloadedDriver {
+    /// Name of the driver image
+    pub name: String,
+    /// Start of the address range the driver was loaded at
+    pub start: Address,
+    /// End of the address range the driver was loaded at
+    pub end: Address,
+    /// Time the driver was unloaded, as a Windows `FILETIME`
+    pub unload_time: u64,
+}
+
+impl UnloadedDriver {
+    /// Returns the time the driver was unloaded in seconds since the unix epoch.
+    pub fn unix_time(&self) -> Option<u64> {
+        filetime_to_unix(self.unload_time)
+    }
+
+    /// Returns true if `address` was part of the driver image.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.start && address < self.end
+    }
+}
+
+/// Reads the unloaded driver ring buffer at `array`.
+///
+/// `count` is the value of `MmLastUnloadedDriver`, the number of valid entries until the buffer
+/// wrapped around. The drivers are returned with the most recently unloaded one first.
+pub fn read_unloaded_drivers(
+    mem: &mut impl MemoryView,
+    arch: ArchitectureObj,
+    array: Address,
+    count: usize,
+) -> Result<Vec<UnloadedDriver>> {
+    // UNICODE_STRING Name, PVOID StartAddress, PVOID EndAddress, LARGE_INTEGER CurrentTime
+    let ptr = arch.size_addr();
+    let entry_size = ptr * 4 + 8;
+
+    let count = std::cmp::min(count, MAX_UNLOADED_DRIVERS);
+    let mut buf = vec![0u8; entry_size * count];
+    mem.read_raw_into(array, &mut buf).data_part()?;
+
+    let read_ptr = |data: &[u8]| -> umem {
+        if ptr == 8 {
+            u64::from_le_bytes(data[..8].try_into().unwrap()) as umem
+        } else {
+            u32::from_le_bytes(data[..4].try_into().unwrap()) as umem
+        }
+    };
+
+    let mut ret = vec![];
+    for entry in buf.chunks_exact(entry_size) {
+        let len = u16::from_le_bytes([entry[0], entry[1]]) as usize;
+        let buffer = Address::from(read_ptr(&entry[ptr..]));
+        if len == 0 || buffer.is_null() {
+            continue;
+        }
+
+        let name = match mem.read_raw(buffer, len & !1).data() {
+            Ok(bytes) => {
+                let chars = bytes
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&chars)
+            }
+            // the name buffer was freed already, the remaining fields are still useful
+            Err(_) => String::new(),
+        };
+
+        ret.push(UnloadedDriver {
+            name,
+            start: Address::from(read_ptr(&entry[ptr * 2..])),
+            end: Address::from(read_ptr(&entry[ptr * 3..])),
+            unload_time: u64::from_le_bytes(entry[ptr * 4..ptr * 4 + 8].try_into().unwrap()),
+        });
+    }
+
+    ret.sort_by(|a, b| b.unload_time.cmp(&a.unload_time));
+    Ok(ret)
+}
+
+/// Reads the unloaded drivers located through the `MmUnloadedDrivers` and
+/// `MmLastUnloadedDriver` kernel variables, resolved through `resolve`.
+pub fn unloaded_drivers_from_symbols(
+    mem: &mut impl MemoryView,
+    arch: ArchitectureObj,
+    mut resolve: impl FnMut(&str) -> Option<Address>,
+) -> Result<Vec<UnloadedDriver>> {
+    let (array_ptr, last_ptr) = resolve("MmUnloadedDrivers")
+        .zip(resolve("MmLastUnloadedDriver"))
+        .ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_info("unable to resolve the unloaded driver list")
+        })?;
+
+    let array = mem.read_addr_arch(arch, array_ptr).data_part()?;
+    if array.is_null() {
+        // the list is only allocated once the first driver was unloaded
+        return Ok(vec![]);
+    }
+
+    let count = mem.read::<u32>(last_ptr).data_part()? as usize;
+    read_unloaded_drivers(mem, arch, array, count)
+}
+
+/// Offsets of the `EPROCESS` fields used to identify exited processes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProcessResidueLayout {
+    /// Offset of `CreateTime`
+    pub create_time: usize,
+    /// Offset of `ExitTime`
+    pub exit_time: usize,
+    /// Offset of `UniqueProcessId`
+    pub unique_process_id: usize,
+    /// Offset of `ImageFileName`
+    pub image_file_name: usize,
+    /// Size of a pointer
+    pub pointer_size: usize,
+    /// Maximum offset of the `EPROCESS` within its pool block
+    ///
+    /// The object is preceded by the `OBJECT_HEADER` and a varying set of optional headers.
+    pub max_body_offset: usize,
+}
+
+impl ProcessResidueLayout {
+    /// Layout of 64-bit Windows 7 SP1 kernels.
+    ///
+    /// The offsets of other builds can be obtained from their symbols.
+    pub const fn x64_win7() -> Self {
+        Self {
+            create_time: 0x168,
+            exit_time: 0x170,
+            unique_process_id: 0x180,
+            image_file_name: 0x2e0,
+            pointer_size: 8,
+            max_body_offset: 0x100,
+        }
+    }
+
+    /// Layout of 32-bit Windows 7 SP1 kernels.
+    pub const fn x86_win7() -> Self {
+        Self {
+            create_time: 0xa0,
+            exit_time: 0xa8,
+            unique_process_id: 0xb4,
+            image_file_name: 0x16c,
+            pointer_size: 4,
+            max_body_offset: 0x80,
+        }
+    }
+
+    /// Number of bytes that have to be read to decode all fields.
+    fn size(&self) -> usize {
+        *[
+            self.create_time + 8,
+            self.exit_time + 8,
+            self.unique_process_id + self.pointer_size,
+            self.image_file_name + 15,
+        ]
+        .iter()
+        .max()
+        .unwrap()
+    }
+}
+
+/// Process that is no longer active but whose `EPROCESS` is still resident.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExitedProcess {
+    /// Address of the `EPROCESS`
+    pub address: Address,
+    /// Id of the process
+    pub pid: u64,
+    /// Truncated image name of the process
+    pub name: String,
+    /// Time the process was created, as a Windows `FILETIME`
+    pub create_time: u64,
+    /// Time the process exited, as a Windows `FILETIME`
+    pub exit_time: u64,
+    /// True if the pool block of the process was already freed
+    pub freed: bool,
+}
+
+impl ExitedProcess {
+    /// Returns the time the process was created in seconds since the unix epoch.
+    pub fn create_unix_time(&self) -> Option<u64> {
+        filetime_to_unix(self.create_time)
+    }
+
+    /// Returns the time the process exited in seconds since the unix epoch.
+    pub fn exit_unix_time(&self) -> Option<u64> {
+        filetime_to_unix(self.exit_time)
+    }
+
+    /// Decodes the `EPROCESS` at `address` from `data`, returning `None` if the fields do not
+    /// describe a plausible process.
+    pub fn parse(layout: ProcessResidueLayout, address: Address, data: &[u8]) -> Option<Self> {
+        let data = data.get(..layout.size())?;
+        let u64_at = |off: usize| u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
+
+        let create_time = u64_at(layout.create_time);
+        let exit_time = u64_at(layout.exit_time);
+        if !(MIN_FILETIME..MAX_FILETIME).contains(&create_time) {
+            return None;
+        }
+        // the exit time is zero while the process is still running
+        if exit_time != 0 && !(create_time..MAX_FILETIME).contains(&exit_time) {
+            return None;
+        }
+
+        let pid = if layout.pointer_size == 8 {
+            u64_at(layout.unique_process_id)
+        } else {
+            u32::from_le_bytes(
+                data[layout.unique_process_id..layout.unique_process_id + 4]
+                    .try_into()
+                    .unwrap(),
+            ) as u64
+        };
+        // process ids are handle values and thus multiples of four
+        if pid == 0 || pid % 4 != 0 || pid > 0xffff_ffff {
+            return None;
+        }
+
+        let name = &data[layout.image_file_name..layout.image_file_name + 15];
+        let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+        if name.is_empty() || !name.iter().all(|c| c.is_ascii_graphic() || *c == b' ') {
+            return None;
+        }
+
+        Some(Self {
+            address,
+            pid,
+            name: String::from_utf8_lossy(name).to_string(),
+            create_time,
+            exit_time,
+            freed: false,
+        })
+    }
+}
+
+/// Scans `len` bytes of kernel memory starting at `start` for processes that have exited.
+///
+/// Every `Proc` pool block in the range is searched for a plausible `EPROCESS` with an exit
+/// time set. Processes located at one of the `active` addresses are skipped, this usually is
+/// the address of every process in the process list. The processes are returned with the most
+/// recently exited one first.
+pub fn find_exited_processes(
+    mem: &mut impl MemoryView,
+    pool_layout: PoolLayout,
+    layout: ProcessResidueLayout,
+    start: Address,
+    len: umem,
+    active: &[Address],
+) -> Result<Vec<ExitedProcess>> {
+    let mut ret: Vec<ExitedProcess> = vec![];
+
+    for block in scan_pool_tag(mem, pool_layout, start, len, PROCESS_POOL_TAG)? {
+        let data = mem.read_raw(block.data, block.size as usize).data_part()?;
+
+        let found = (0..=layout.max_body_offset)
+            .step_by(pool_layout.header_size)
+            .find_map(|off| {
+                ExitedProcess::parse(layout, block.data + off, data.get(off..)?)
+                    .filter(|p| p.exit_time != 0)
+            });
+
+        if let Some(mut process) = found {
+            if active.contains(&process.address) || ret.iter().any(|p| p.address == process.address)
+            {
+                continue;
+            }
+            process.freed = block.free;
+            ret.push(process);
+        }
+    }
+
+    ret.sort_by(|a, b| b.exit_time.cmp(&a.exit_time));
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    const TIME: u64 = 132_539_328_000_000_000;
+
+    #[test]
+    fn unloaded_drivers() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut buf = vec![0u8; 0x1000];
+        let names = [(0x800, "evil.sys"), (0x900, "beep.sys")];
+        for (i, (name_off, name)) in names.iter().enumerate() {
+            let name = name
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>();
+            buf[*name_off..name_off + name.len()].copy_from_slice(&name);
+
+            let entry = i * 0x28;
+            buf[entry..entry + 2].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            let ptr = (base + *name_off).to_umem() as u64;
+            buf[entry + 8..entry + 16].copy_from_slice(&ptr.to_le_bytes());
+            let start = 0xfffff880_01000000u64 + i as u64 * 0x10000;
+            buf[entry + 16..entry + 24].copy_from_slice(&start.to_le_bytes());
+            buf[entry + 24..entry + 32].copy_from_slice(&(start + 0x8000).to_le_bytes());
+            buf[entry + 32..entry + 40].copy_from_slice(&(TIME + i as u64).to_le_bytes());
+        }
+        proc.write_raw(base, &buf).unwrap();
+
+        let drivers = read_unloaded_drivers(&mut proc, x64::ARCH, base, 3).unwrap();
+        assert_eq!(drivers.len(), 2);
+        assert_eq!(drivers[0].name, "beep.sys");
+        assert_eq!(drivers[1].name, "evil.sys");
+        assert_eq!(drivers[1].unix_time(), Some(1_609_459_200));
+        assert!(drivers[1].contains(0xfffff880_01004000u64.into()));
+    }
+
+    #[test]
+    fn exited_processes() {
+        let layout = ProcessResidueLayout::x64_win7();
+        let pool_layout = PoolLayout::x64();
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // two blocks of 0x400 bytes with the EPROCESS 0x50 bytes after the pool header
+        let mut buf = vec![0u8; 0x2000];
+        for (i, (name, exit, pool_type)) in [
+            (&b"mimikatz.exe"[..], TIME + 100, 0u8),
+            (&b"explorer.exe"[..], 0, 1),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let block = i * 0x1000;
+            buf[block..block + 8]
+                .copy_from_slice(&[0, 0, 0x40, *pool_type, b'P', b'r', b'o', 0xe3]);
+
+            let eprocess = block + 0x10 + 0x50;
+            let mut put = |off: usize, data: &[u8]| {
+                buf[eprocess + off..eprocess + off + data.len()].copy_from_slice(data)
+            };
+            put(layout.create_time, &TIME.to_le_bytes());
+            put(layout.exit_time, &exit.to_le_bytes());
+            put(
+                layout.unique_process_id,
+                &(0x1234u64 + i as u64 * 4).to_le_bytes(),
+            );
+            put(layout.image_file_name, *name);
+        }
+        proc.write_raw(base, &buf).unwrap();
+
+        let found =
+            find_exited_processes(&mut proc, pool_layout, layout, base, 0x2000, &[]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "mimikatz.exe");
+        assert_eq!(found[0].address, base + 0x60usize);
+        assert_eq!(found[0].pid, 0x1234);
+        assert!(found[0].freed);
+        assert_eq!(found[0].exit_unix_time(), Some(1_609_459_200));
+
+        let found = find_exited_processes(
+            &mut proc,
+            pool_layout,
+            layout,
+            base,
+            0x2000,
+            &[base + 0x60usize],
+        )
+        .unwrap();
+        assert!(found.is_empty());
+    }
+}
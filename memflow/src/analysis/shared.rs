@@ -0,0 +1,221 @@
+//! Detection of physical memory shared between address spaces.
+//!
+//! Shared sections, mapped files and copy-on-write pages all end up as the same physical pages
+//! being mapped into multiple address spaces. By comparing the virtual translation maps of a set
+//! of address spaces, [`find_shared_mappings`] reports every physical range that is reachable
+//! from more than one of them, along with the virtual address it is mapped at in each.
+//!
+//! This is useful for spotting code injected through shared sections, as well as for
+//! deduplicating scans across processes, since shared ranges only have to be scanned once.
+//!
+//! The detection only relies on the page tables, so it works for any OS. Pages that are not
+//! currently resident (e.g. paged out, or prototype PTEs on Windows that were never touched by a
+//! process) are not visible this way. Enumerating the section objects themselves requires
+//! parsing OS specific structures and is left to the OS plugins.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::shared::find_shared_mappings;
+//! # use memflow::architecture::x86::x64;
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//!
+//! fn print_shared<T: VirtualTranslate>(spaces: &mut [T]) {
+//!     for shared in find_shared_mappings(spaces) {
+//!         print!("{} ({:x} bytes):", shared.physical, shared.size);
+//!         for (space, addr) in shared.mappings.iter() {
+//!             print!(" {}@{}", space, addr);
+//!         }
+//!         println!();
+//!     }
+//! }
+//! # let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+//! # let pid = os.alloc_process(size::kb(16), &[]);
+//! # let dtb = os.process_by_pid(pid).unwrap().proc.dtb;
+//! # let mem = os.into_inner();
+//! # let view = || VirtualDma::new(mem.clone(), x64::ARCH, x64::new_translator(dtb));
+//! # print_shared(&mut [view(), view()]);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::mem::virt_translate::{VirtualTranslate, VirtualTranslation};
+use crate::types::{umem, Address};
+
+/// Physical range mapped into more than one address space.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SharedMapping {
+    /// Start of the physical range
+    pub physical: Address,
+    /// Size of the range
+    pub size: umem,
+    /// Index of the address space and virtual address of every mapping of the range
+    pub mappings: Vec<(usize, Address)>,
+}
+
+impl SharedMapping {
+    /// Returns the indices of all address spaces mapping the range, without duplicates.
+    pub fn spaces(&self) -> Vec<usize> {
+        let mut ret = self
+            .mappings
+            .iter()
+            .map(|&(space, _)| space)
+            .collect::<Vec<_>>();
+        ret.dedup();
+        ret
+    }
+
+    /// Returns true if the range is mapped more than once into the same address space.
+    pub fn is_aliased(&self) -> bool {
+        self.spaces().len() != self.mappings.len()
+    }
+}
+
+/// Finds all physical ranges that are mapped into at least two of the given address spaces.
+///
+/// Address spaces are identified by their index in `spaces`. The returned ranges are sorted by
+/// their physical address, mappings of each range are sorted by the address space index.
+/// Adjacent ranges that are mapped contiguously by the same address spaces are merged.
+pub fn find_shared_mappings<T: VirtualTranslate>(spaces: &mut [T]) -> Vec<SharedMapping> {
+    let mut entries = vec![];
+    for (space, mem) in spaces.iter_mut().enumerate() {
+        entries.extend(
+            mem.virt_translation_map_vec()
+                .into_iter()
+                .map(|t| (space, t)),
+        );
+    }
+
+    shared_ranges(entries)
+}
+
+fn shared_ranges(mut entries: Vec<(usize, VirtualTranslation)>) -> Vec<SharedMapping> {
+    let start = |t: &VirtualTranslation| t.out_physical.address();
+    let end = |t: &VirtualTranslation| t.out_physical.address() + t.size;
+
+    entries.retain(|(_, t)| t.size > 0);
+    entries.sort_by_key(|(space, t)| (start(t), *space));
+
+    let mut bounds = entries
+        .iter()
+        .flat_map(|(_, t)| std::iter::once(start(t)).chain(std::iter::once(end(t))))
+        .collect::<Vec<_>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut ret: Vec<SharedMapping> = vec![];
+    let mut active: Vec<&(usize, VirtualTranslation)> = vec![];
+    let mut next = 0;
+
+    for w in bounds.windows(2) {
+        let (lo, hi) = (w[0], w[1]);
+
+        active.retain(|(_, t)| end(t) > lo);
+        while next < entries.len() && start(&entries[next].1) <= lo {
+            active.push(&entries[next]);
+            next += 1;
+        }
+
+        let mut mappings = active
+            .iter()
+            .map(|(space, t)| (*space, t.in_virtual + (lo - start(t)) as umem))
+            .collect::<Vec<_>>();
+        mappings.sort_unstable();
+
+        if mappings.len() < 2 || mappings.iter().all(|&(space, _)| space == mappings[0].0) {
+            continue;
+        }
+
+        let size = (hi - lo) as umem;
+
+        // extend the previous range if every mapping continues it seamlessly
+        if let Some(prev) = ret.last_mut() {
+            let contiguous = prev.physical + prev.size == lo
+                && prev.mappings.len() == mappings.len()
+                && prev
+                    .mappings
+                    .iter()
+                    .zip(mappings.iter())
+                    .all(|(&(ps, pa), &(s, a))| ps == s && pa + prev.size == a);
+
+            if contiguous {
+                prev.size += size;
+                continue;
+            }
+        }
+
+        ret.push(SharedMapping {
+            physical: lo,
+            size,
+            mappings,
+        });
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::prelude::v1::*;
+
+    fn translation(virt: u64, size: umem, phys: u64) -> VirtualTranslation {
+        VirtualTranslation {
+            in_virtual: virt.into(),
+            size,
+            out_physical: PhysicalAddress::from(phys),
+        }
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let shared = shared_ranges(vec![
+            (0, translation(0x10000, 0x3000, 0x1000)),
+            (1, translation(0x20000, 0x1000, 0x2000)),
+            (1, translation(0x21000, 0x1000, 0x3000)),
+            (2, translation(0x30000, 0x1000, 0x8000)),
+        ]);
+
+        assert_eq!(
+            shared,
+            vec![SharedMapping {
+                physical: 0x2000u64.into(),
+                size: 0x2000,
+                mappings: vec![(0, 0x11000u64.into()), (1, 0x20000u64.into())],
+            }]
+        );
+    }
+
+    #[test]
+    fn shared_address_space() {
+        let mut os = DummyOs::with_seed(DummyMemory::new(size::mb(4)), 1);
+        let pid = os.alloc_process(size::kb(64), &[]);
+        let other = os.alloc_process(size::kb(64), &[]);
+
+        let dtb = os.process_by_pid(pid).unwrap().proc.dtb;
+        let other_dtb = os.process_by_pid(other).unwrap().proc.dtb;
+
+        // clones of the dummy memory share the same underlying buffer
+        let mem = os.into_inner();
+        let view = |dtb| VirtualDma::new(mem.clone(), x64::ARCH, x64::new_translator(dtb));
+
+        // two views over the same page tables share all of their memory
+        let mut spaces = [view(dtb), view(other_dtb), view(dtb)];
+        let mapped = spaces[0]
+            .virt_translation_map_vec()
+            .iter()
+            .map(|t| t.size)
+            .sum::<umem>();
+
+        let shared = find_shared_mappings(&mut spaces);
+
+        assert!(!shared.is_empty());
+        assert!(shared.iter().all(|s| s.spaces() == [0, 2]));
+        assert!(shared.iter().all(|s| !s.is_aliased()));
+        assert_eq!(shared.iter().map(|s| s.size).sum::<umem>(), mapped);
+    }
+}
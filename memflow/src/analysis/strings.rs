@@ -0,0 +1,388 @@
+//! Search for ASCII and UTF-16 strings in memory.
+//!
+//! This is the equivalent of the `strings` utility for live memory. A [`StringSearch`] streams
+//! through a memory range chunk by chunk and extracts all runs of printable characters, in both
+//! single byte (ASCII) and little endian UTF-16 encoding. UTF-16 strings are detected at even and
+//! odd byte offsets alike, so strings inside packed structures are not missed.
+//!
+//! Strings spanning chunk boundaries are reassembled, so the chunk size only affects the amount of
+//! memory held at any time and never the results. Each found string can optionally carry a number
+//! of bytes surrounding it, which helps to judge the context a string was found in.
+//!
+//! The search works on any [`MemoryView`], use [`phys_view`](crate::mem::PhysicalMemory::phys_view)
+//! to search physical memory.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::strings::StringSearch;
+//!
+//! fn find_passwords(proc: &mut (impl Process + MemoryView)) -> Result<()> {
+//!     let search = StringSearch::new().min_len(6).contains("password").context(16);
+//!
+//!     for range in proc.mapped_mem_vec(0) {
+//!         for s in search.search(proc, range.0, range.1)? {
+//!             println!("{} {:?}: {}", s.address, s.encoding, s.text);
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), b"\0my password\0");
+//! # find_passwords(&mut proc).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Default chunk size used when streaming through memory.
+pub const DEFAULT_CHUNK_SIZE: usize = 0x100000;
+
+/// Encoding of a found string.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum StringEncoding {
+    /// One byte per character
+    Ascii,
+    /// Two bytes per character, little endian
+    Utf16,
+}
+
+impl StringEncoding {
+    /// Returns the number of bytes per character.
+    pub fn char_size(self) -> usize {
+        match self {
+            StringEncoding::Ascii => 1,
+            StringEncoding::Utf16 => 2,
+        }
+    }
+}
+
+/// String found by a [`StringSearch`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FoundString {
+    /// Address of the first character
+    pub address: Address,
+    /// Encoding the string was found in
+    pub encoding: StringEncoding,
+    /// Contents of the string
+    pub text: String,
+    /// Address of the first byte of `context`
+    pub context_address: Address,
+    /// Bytes surrounding the string, including the string itself. Unreadable bytes are zeroed.
+    pub context: Vec<u8>,
+}
+
+impl FoundString {
+    /// Returns the size of the string in bytes.
+    pub fn byte_len(&self) -> usize {
+        self.text.len() * self.encoding.char_size()
+    }
+}
+
+/// Configurable search for strings in memory.
+#[derive(Clone, Debug)]
+pub struct StringSearch {
+    min_len: usize,
+    max_len: usize,
+    ascii: bool,
+    utf16: bool,
+    contains: Option<String>,
+    ignore_case: bool,
+    context: usize,
+    chunk_size: usize,
+}
+
+impl Default for StringSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StringSearch {
+    /// Creates a search for all strings of at least 4 characters in both encodings.
+    pub fn new() -> Self {
+        Self {
+            min_len: 4,
+            max_len: 4096,
+            ascii: true,
+            utf16: true,
+            contains: None,
+            ignore_case: true,
+            context: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the minimum number of characters of a string.
+    pub fn min_len(mut self, min_len: usize) -> Self {
+        self.min_len = std::cmp::max(min_len, 1);
+        self
+    }
+
+    /// Sets the maximum number of characters of a string. Longer runs are split.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = std::cmp::max(max_len, 1);
+        self
+    }
+
+    /// Enables or disables the search for ASCII strings.
+    pub fn ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    /// Enables or disables the search for UTF-16 strings.
+    pub fn utf16(mut self, utf16: bool) -> Self {
+        self.utf16 = utf16;
+        self
+    }
+
+    /// Only returns strings containing `pattern`.
+    pub fn contains(mut self, pattern: &str) -> Self {
+        self.contains = Some(pattern.to_string());
+        self
+    }
+
+    /// Sets whether [`contains`](Self::contains) ignores ASCII case, which is the default.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Sets the number of bytes before and after each string to return as context.
+    pub fn context(mut self, context: usize) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Sets the number of bytes read at once.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns all matching strings in the given range.
+    pub fn search(
+        &self,
+        mem: &mut impl MemoryView,
+        addr: Address,
+        len: umem,
+    ) -> Result<Vec<FoundString>> {
+        let mut ret = vec![];
+        self.search_with(mem, addr, len, |s| {
+            ret.push(s);
+            true
+        })?;
+        Ok(ret)
+    }
+
+    /// Calls `out` for every matching string in the given range, in order of their end address.
+    ///
+    /// The search stops once `out` returns false.
+    pub fn search_with(
+        &self,
+        mem: &mut impl MemoryView,
+        addr: Address,
+        len: umem,
+        mut out: impl FnMut(FoundString) -> bool,
+    ) -> Result<()> {
+        let mut ascii = Run::default();
+        // UTF-16 runs starting at even and odd addresses
+        let mut utf16 = [Run::default(), Run::default()];
+        let mut prev_byte = None;
+
+        let mut buf = vec![0u8; self.chunk_size];
+        let mut found = vec![];
+        let end = addr + len;
+        let mut chunk_addr = addr;
+
+        while chunk_addr < end {
+            let chunk_len = std::cmp::min(self.chunk_size as umem, (end - chunk_addr) as umem);
+            let chunk = &mut buf[..chunk_len as usize];
+            chunk.iter_mut().for_each(|b| *b = 0);
+            mem.read_raw_into(chunk_addr, chunk).data_part()?;
+
+            for (i, &b) in chunk.iter().enumerate() {
+                let cur = chunk_addr + i;
+
+                if self.ascii {
+                    self.push_char(&mut ascii, cur, b as u16, StringEncoding::Ascii, &mut found);
+                }
+
+                if self.utf16 {
+                    if let Some(lo) = prev_byte {
+                        let start = cur - 1usize;
+                        let run = &mut utf16[(start.to_umem() & 1) as usize];
+                        let c = u16::from_le_bytes([lo, b]);
+                        self.push_char(run, start, c, StringEncoding::Utf16, &mut found);
+                    }
+                    prev_byte = Some(b);
+                }
+            }
+
+            chunk_addr += chunk_len;
+
+            if !self.emit(mem, &mut found, &mut out)? {
+                return Ok(());
+            }
+        }
+
+        self.finish(&mut ascii, StringEncoding::Ascii, &mut found);
+        for run in utf16.iter_mut() {
+            self.finish(run, StringEncoding::Utf16, &mut found);
+        }
+        self.emit(mem, &mut found, &mut out)?;
+
+        Ok(())
+    }
+
+    /// Adds the character `c` at `addr` to `run`, completing the run if it is not printable.
+    fn push_char(
+        &self,
+        run: &mut Run,
+        addr: Address,
+        c: u16,
+        encoding: StringEncoding,
+        found: &mut Vec<(Address, StringEncoding, String)>,
+    ) {
+        if c == b'\t' as u16 || (0x20..0x7f).contains(&c) {
+            if run.chars.is_empty() {
+                run.address = addr;
+            }
+            run.chars.push(c as u8);
+
+            if run.chars.len() >= self.max_len {
+                self.finish(run, encoding, found);
+            }
+        } else {
+            self.finish(run, encoding, found);
+        }
+    }
+
+    /// Completes `run` and records it if it matches the search.
+    fn finish(
+        &self,
+        run: &mut Run,
+        encoding: StringEncoding,
+        found: &mut Vec<(Address, StringEncoding, String)>,
+    ) {
+        if run.chars.len() >= self.min_len {
+            // the run only consists of printable ascii characters
+            let text = String::from_utf8_lossy(&run.chars).into_owned();
+            if self.matches(&text) {
+                found.push((run.address, encoding, text));
+            }
+        }
+        run.chars.clear();
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match &self.contains {
+            None => true,
+            Some(pattern) if self.ignore_case => text
+                .to_ascii_lowercase()
+                .contains(&pattern.to_ascii_lowercase()),
+            Some(pattern) => text.contains(pattern.as_str()),
+        }
+    }
+
+    /// Reads the context of all `found` strings and passes them to `out`.
+    ///
+    /// Returns false if `out` requested to stop.
+    fn emit(
+        &self,
+        mem: &mut impl MemoryView,
+        found: &mut Vec<(Address, StringEncoding, String)>,
+        out: &mut impl FnMut(FoundString) -> bool,
+    ) -> Result<bool> {
+        for (address, encoding, text) in found.drain(..) {
+            let byte_len = text.len() * encoding.char_size();
+            let context_address =
+                Address::from(address.to_umem().saturating_sub(self.context as umem));
+            let mut context =
+                vec![0u8; (address - context_address) as usize + byte_len + self.context];
+
+            if self.context > 0 {
+                mem.read_raw_into(context_address, &mut context)
+                    .data_part()?;
+            } else {
+                context.clear();
+            }
+
+            let s = FoundString {
+                address,
+                encoding,
+                text,
+                context_address,
+                context,
+            };
+
+            if !out(s) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Run of printable characters being assembled.
+#[derive(Default)]
+struct Run {
+    address: Address,
+    chars: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn both_encodings() {
+        let mut buf = b"\0\0hello world\0\0\0\0".to_vec();
+        // odd aligned utf-16 string
+        buf.extend("secret".encode_utf16().flat_map(|c| c.to_le_bytes()));
+        buf.extend_from_slice(&[0, 0]);
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let addr = proc.info().address;
+
+        // small chunks, so both strings span chunk boundaries
+        let found = StringSearch::new()
+            .chunk_size(3)
+            .context(2)
+            .search(&mut proc, addr, buf.len() as umem)
+            .unwrap();
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].address, addr + 2);
+        assert_eq!(found[0].encoding, StringEncoding::Ascii);
+        assert_eq!(found[0].text, "hello world");
+        assert_eq!(&found[0].context[..3], b"\0\0h");
+        assert_eq!(found[0].context.len(), 15);
+
+        assert_eq!(found[1].address, addr + 17);
+        assert_eq!(found[1].encoding, StringEncoding::Utf16);
+        assert_eq!(found[1].text, "secret");
+
+        let found = StringSearch::new()
+            .contains("SECRET")
+            .search(&mut proc, addr, buf.len() as umem)
+            .unwrap();
+        assert_eq!(found.len(), 1);
+    }
+}
@@ -0,0 +1,402 @@
+//! Timeline of events gathered from multiple sources.
+//!
+//! Many analysis passes produce timestamped results: process creation and exit times, unload
+//! times of drivers, event log records. Putting them into a single timeline is usually the first
+//! step of an investigation. A [`Timeline`] collects [`TimelineEvent`]s from any
+//! [`TimelineSource`] and keeps them sorted by time.
+//!
+//! All timestamps are stored as nanoseconds since the unix epoch in UTC, like
+//! [`OsTime`](crate::os::OsTime). Windows `FILETIME` values can be converted with
+//! [`TimelineEvent::from_filetime`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::residue::unloaded_drivers_from_symbols;
+//! use memflow::analysis::timeline::{Timeline, TimelineEvent, TimelineEventKind};
+//!
+//! fn print_timeline(
+//!     kernel: &mut impl MemoryView,
+//!     arch: ArchitectureObj,
+//!     symbols: impl FnMut(&str) -> Option<Address>,
+//! ) -> Result<()> {
+//!     let mut timeline = Timeline::new();
+//!
+//!     timeline.add_all(&unloaded_drivers_from_symbols(kernel, arch, symbols)?);
+//!     timeline.push(TimelineEvent::new(
+//!         1_609_459_200_000_000_000,
+//!         TimelineEventKind::Other,
+//!         "acquisition started",
+//!     ));
+//!
+//!     for event in timeline.events() {
+//!         println!("{}", event);
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # assert!(print_timeline(&mut proc, x64::ARCH, |_| None).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::fmt;
+
+use super::evtx::EvtxRecord;
+use super::residue::{ExitedProcess, UnloadedDriver};
+use crate::os::Pid;
+use crate::types::Address;
+
+/// Difference between the Windows (1601-01-01) and unix (1970-01-01) epoch in 100ns units.
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+/// Kind of a timeline event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum TimelineEventKind {
+    ProcessCreated,
+    ProcessExited,
+    ThreadCreated,
+    ThreadExited,
+    DriverLoaded,
+    DriverUnloaded,
+    /// A network endpoint was created or bound
+    NetworkEndpoint,
+    /// An event log record was written
+    EventLogRecord,
+    /// Event without a more specific kind
+    Other,
+}
+
+impl fmt::Display for TimelineEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TimelineEventKind::ProcessCreated => "ProcessCreated",
+            TimelineEventKind::ProcessExited => "ProcessExited",
+            TimelineEventKind::ThreadCreated => "ThreadCreated",
+            TimelineEventKind::ThreadExited => "ThreadExited",
+            TimelineEventKind::DriverLoaded => "DriverLoaded",
+            TimelineEventKind::DriverUnloaded => "DriverUnloaded",
+            TimelineEventKind::NetworkEndpoint => "NetworkEndpoint",
+            TimelineEventKind::EventLogRecord => "EventLogRecord",
+            TimelineEventKind::Other => "Other",
+        };
+        f.pad(name)
+    }
+}
+
+/// A single timestamped event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TimelineEvent {
+    /// Time of the event in nanoseconds since the unix epoch (UTC)
+    pub time_ns: u64,
+    /// Kind of the event
+    pub kind: TimelineEventKind,
+    /// Address of the structure the event was derived from, if any
+    pub address: Option<Address>,
+    /// Id of the process the event belongs to, if any
+    pub pid: Option<Pid>,
+    /// Human readable description, e.g. the name of the process or driver
+    pub description: String,
+}
+
+impl TimelineEvent {
+    /// Creates an event at `time_ns` nanoseconds since the unix epoch.
+    pub fn new(time_ns: u64, kind: TimelineEventKind, description: &str) -> Self {
+        Self {
+            time_ns,
+            kind,
+            address: None,
+            pid: None,
+            description: description.to_string(),
+        }
+    }
+
+    /// Creates an event from a Windows `FILETIME`.
+    ///
+    /// Returns `None` if the time lies before the unix epoch, which includes unset (zero)
+    /// timestamps.
+    pub fn from_filetime(
+        filetime: u64,
+        kind: TimelineEventKind,
+        description: &str,
+    ) -> Option<Self> {
+        let time_ns = filetime
+            .checked_sub(FILETIME_UNIX_EPOCH)?
+            .checked_mul(100)?;
+        Some(Self::new(time_ns, kind, description))
+    }
+
+    /// Sets the address of the structure the event was derived from.
+    pub fn address(mut self, address: Address) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sets the id of the process the event belongs to.
+    pub fn pid(mut self, pid: Pid) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Returns the time of the event in seconds since the unix epoch.
+    pub fn unix_time(&self) -> u64 {
+        self.time_ns / 1_000_000_000
+    }
+}
+
+/// Formats the event as `2021-01-01 00:00:00.000 ProcessCreated  [pid 1234] description`.
+impl fmt::Display for TimelineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:<16}", format_time(self.time_ns), self.kind)?;
+        if let Some(pid) = self.pid {
+            write!(f, " [pid {}]", pid)?;
+        }
+        write!(f, " {}", self.description)?;
+        if let Some(address) = self.address {
+            write!(f, " @ {:x}", address)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats nanoseconds since the unix epoch as `YYYY-MM-DD hh:mm:ss.mmm` in UTC.
+pub fn format_time(time_ns: u64) -> String {
+    let secs = time_ns / 1_000_000_000;
+    let millis = (time_ns / 1_000_000) % 1000;
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // conversion of days since the epoch to a civil date
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem / 60) % 60,
+        rem % 60,
+        millis
+    )
+}
+
+/// A result that contributes events to a timeline.
+pub trait TimelineSource {
+    /// Returns the events described by this result.
+    fn timeline_events(&self) -> Vec<TimelineEvent>;
+}
+
+impl TimelineSource for UnloadedDriver {
+    fn timeline_events(&self) -> Vec<TimelineEvent> {
+        TimelineEvent::from_filetime(
+            self.unload_time,
+            TimelineEventKind::DriverUnloaded,
+            &self.name,
+        )
+        .map(|e| e.address(self.start))
+        .into_iter()
+        .collect()
+    }
+}
+
+impl TimelineSource for ExitedProcess {
+    fn timeline_events(&self) -> Vec<TimelineEvent> {
+        let pid = self.pid as Pid;
+        [
+            (self.create_time, TimelineEventKind::ProcessCreated),
+            (self.exit_time, TimelineEventKind::ProcessExited),
+        ]
+        .iter()
+        .filter_map(|&(time, kind)| TimelineEvent::from_filetime(time, kind, &self.name))
+        .map(|e| e.address(self.address).pid(pid))
+        .collect()
+    }
+}
+
+impl TimelineSource for EvtxRecord {
+    fn timeline_events(&self) -> Vec<TimelineEvent> {
+        TimelineEvent::from_filetime(
+            self.timestamp,
+            TimelineEventKind::EventLogRecord,
+            &format!("record {}", self.id),
+        )
+        .map(|e| e.address(self.address))
+        .into_iter()
+        .collect()
+    }
+}
+
+impl TimelineSource for TimelineEvent {
+    fn timeline_events(&self) -> Vec<TimelineEvent> {
+        vec![self.clone()]
+    }
+}
+
+/// Events sorted by time.
+///
+/// Events with the same timestamp keep the order they were added in.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Timeline {
+    events: Vec<TimelineEvent>,
+}
+
+impl Timeline {
+    /// Creates an empty timeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a single event.
+    pub fn push(&mut self, event: TimelineEvent) {
+        let idx = self.events.partition_point(|e| e.time_ns <= event.time_ns);
+        self.events.insert(idx, event);
+    }
+
+    /// Adds the events of `source`.
+    pub fn add(&mut self, source: &impl TimelineSource) {
+        self.extend(source.timeline_events());
+    }
+
+    /// Adds the events of all `sources`.
+    pub fn add_all<'a, T: TimelineSource + 'a>(
+        &mut self,
+        sources: impl IntoIterator<Item = &'a T>,
+    ) {
+        self.extend(sources.into_iter().flat_map(|s| s.timeline_events()));
+    }
+
+    /// Adds all `events`.
+    pub fn extend(&mut self, events: impl IntoIterator<Item = TimelineEvent>) {
+        self.events.extend(events);
+        // stable, so events with equal timestamps keep their order
+        self.events.sort_by_key(|e| e.time_ns);
+    }
+
+    /// Returns all events sorted by time.
+    pub fn events(&self) -> &[TimelineEvent] {
+        &self.events
+    }
+
+    /// Returns all events from `start_ns` (inclusive) to `end_ns` (exclusive).
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> &[TimelineEvent] {
+        let start = self.events.partition_point(|e| e.time_ns < start_ns);
+        let end = self.events.partition_point(|e| e.time_ns < end_ns);
+        &self.events[start..end.max(start)]
+    }
+
+    /// Returns all events of the given kind.
+    pub fn events_of_kind(
+        &self,
+        kind: TimelineEventKind,
+    ) -> impl Iterator<Item = &TimelineEvent> + '_ {
+        self.events.iter().filter(move |e| e.kind == kind)
+    }
+
+    /// Returns the number of events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if the timeline contains no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Consumes the timeline and returns its events.
+    pub fn into_events(self) -> Vec<TimelineEvent> {
+        self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2021-01-01 00:00:00 UTC
+    const FILETIME: u64 = 132_539_328_000_000_000;
+    const UNIX_NS: u64 = 1_609_459_200_000_000_000;
+
+    #[test]
+    fn sorted_sources() {
+        let mut timeline = Timeline::new();
+
+        timeline.add(&ExitedProcess {
+            address: Address::from(0xffff_8000_1234_0000u64),
+            pid: 1234,
+            name: "cmd.exe".to_string(),
+            create_time: FILETIME,
+            exit_time: FILETIME + 600_000_000,
+            freed: true,
+        });
+        timeline.add_all(&[UnloadedDriver {
+            name: "evil.sys".to_string(),
+            start: Address::from(0xfffff880_01000000u64),
+            end: Address::from(0xfffff880_01008000u64),
+            unload_time: FILETIME + 300_000_000,
+        }]);
+        timeline.push(TimelineEvent::new(
+            UNIX_NS,
+            TimelineEventKind::Other,
+            "boot",
+        ));
+        // unset timestamps are skipped
+        timeline.add(&UnloadedDriver {
+            name: "none.sys".to_string(),
+            start: Address::null(),
+            end: Address::null(),
+            unload_time: 0,
+        });
+
+        let kinds = timeline.events().iter().map(|e| e.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TimelineEventKind::ProcessCreated,
+                TimelineEventKind::Other,
+                TimelineEventKind::DriverUnloaded,
+                TimelineEventKind::ProcessExited,
+            ]
+        );
+        assert_eq!(timeline.events()[0].time_ns, UNIX_NS);
+        assert_eq!(timeline.events()[0].pid, Some(1234));
+
+        let range = timeline.range(UNIX_NS + 1, UNIX_NS + 60_000_000_000);
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].description, "evil.sys");
+        assert_eq!(
+            range[0].to_string(),
+            "2021-01-01 00:00:30.000 DriverUnloaded   evil.sys @ fffff88001000000"
+        );
+        assert_eq!(
+            timeline
+                .events_of_kind(TimelineEventKind::ProcessExited)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn time_format() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00.000");
+        assert_eq!(
+            format_time(951_782_400_123_000_000),
+            "2000-02-29 00:00:00.123"
+        );
+        assert_eq!(format_time(UNIX_NS - 1_000_000), "2020-12-31 23:59:59.999");
+    }
+}
@@ -0,0 +1,684 @@
+//! Parsing of x64 exception and unwind information of loaded PE images.
+//!
+//! Every non-leaf function of an x64 PE image is described by a `RUNTIME_FUNCTION` entry in the
+//! exception directory (`.pdata`). The entry references an `UNWIND_INFO` structure (`.xdata`),
+//! which describes how the prolog of the function modified the stack and optionally references
+//! the language specific exception handler of the function.
+//!
+//! [`ExceptionDirectory`] reads this information from the loaded image of a module. It is used
+//! for two purposes:
+//!
+//! * [`ExceptionDirectory::handlers`] enumerates all registered exception handlers. Handlers
+//!   that point outside of the image they are registered in are a strong indicator of tampering
+//!   and are reported by [`ExceptionHandler::outside_image`].
+//! * [`ExceptionDirectory::unwind`] performs a virtual unwind of a single frame, which is the
+//!   building block of a stack walker.
+//!
+//! # Remarks
+//!
+//! Only the prolog of a function is taken into account while unwinding. Frames that are
+//! interrupted inside of an epilog are therefore not unwound correctly, which only affects the
+//! innermost frame of a thread.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::unwind::ExceptionDirectory;
+//!
+//! fn check_handlers(proc: &mut (impl Process + MemoryView)) -> Result<()> {
+//!     for module in proc.module_list()? {
+//!         let directory = match ExceptionDirectory::read(proc, module.base) {
+//!             Ok(directory) => directory,
+//!             Err(_) => continue,
+//!         };
+//!         for handler in directory.handlers(proc)? {
+//!             if handler.outside_image {
+//!                 println!("{}: foreign handler at {:x}", module.name, handler.handler);
+//!             }
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # check_handlers(&mut proc).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Maximum number of runtime functions read, protecting against corrupted directory sizes.
+pub const MAX_RUNTIME_FUNCTIONS: usize = 0x100000;
+
+/// Maximum number of chained unwind infos followed for a single function.
+const MAX_CHAIN_DEPTH: usize = 32;
+
+/// The function has an exception handler.
+pub const UNW_FLAG_EHANDLER: u8 = 0x1;
+/// The function has a termination handler.
+pub const UNW_FLAG_UHANDLER: u8 = 0x2;
+/// The unwind info is chained to the unwind info of another runtime function.
+pub const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+
+/// A `RUNTIME_FUNCTION` entry of the exception directory.
+///
+/// All members are relative to the image base.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RuntimeFunction {
+    /// RVA of the first instruction of the function
+    pub begin: u32,
+    /// RVA right after the last instruction of the function
+    pub end: u32,
+    /// RVA of the unwind info of the function
+    pub unwind_info: u32,
+}
+
+impl RuntimeFunction {
+    fn parse(buf: &[u8]) -> Self {
+        Self {
+            begin: read_u32(buf, 0),
+            end: read_u32(buf, 4),
+            unwind_info: read_u32(buf, 8),
+        }
+    }
+
+    /// Returns true if `rva` is part of the function.
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.begin && rva < self.end
+    }
+}
+
+/// A single operation of the prolog of a function.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum UnwindOp {
+    /// A nonvolatile integer register has been pushed
+    PushNonvol { reg: u8 },
+    /// Stack space has been allocated
+    Alloc { size: u32 },
+    /// The frame pointer register has been established
+    SetFpReg,
+    /// A nonvolatile integer register has been saved at `offset` from the stack pointer
+    SaveNonvol { reg: u8, offset: u32 },
+    /// A nonvolatile xmm register has been saved at `offset` from the stack pointer
+    SaveXmm128 { reg: u8, offset: u32 },
+    /// A machine frame has been pushed by the processor (interrupts and exceptions)
+    PushMachFrame { error_code: bool },
+    /// Epilog description or unknown operation which does not affect the prolog
+    Other { op: u8 },
+}
+
+/// An `UNWIND_CODE` describing an operation of the prolog.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct UnwindCode {
+    /// Offset of the end of the instruction performing the operation from the function start
+    pub prolog_offset: u8,
+    /// The operation
+    pub op: UnwindOp,
+}
+
+/// The parsed `UNWIND_INFO` of a function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct UnwindInfo {
+    /// Version of the unwind info, 1 or 2
+    pub version: u8,
+    /// Combination of the `UNW_FLAG_*` flags
+    pub flags: u8,
+    /// Size of the prolog in bytes
+    pub prolog_size: u8,
+    /// Register used as frame pointer, 0 if the function does not use a frame pointer
+    pub frame_register: u8,
+    /// Offset of the frame pointer from the stack pointer, in multiples of 16 bytes
+    pub frame_offset: u8,
+    /// Operations of the prolog, in reverse order of execution
+    pub codes: Vec<UnwindCode>,
+    /// RVA of the language specific handler, if the function has an exception or termination
+    /// handler
+    pub handler: Option<u32>,
+    /// RVA of the language specific handler data, following the handler RVA
+    pub handler_data: Option<u32>,
+    /// The runtime function this unwind info is chained to
+    pub chained: Option<RuntimeFunction>,
+}
+
+impl UnwindInfo {
+    /// Parses the unwind info located at `rva` from `buf`.
+    ///
+    /// `buf` has to contain the unwind info including the trailing handler or chain entry.
+    pub fn parse(buf: &[u8], rva: u32) -> Result<Self> {
+        if buf.len() < 4 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("unwind info is truncated"));
+        }
+
+        let version = buf[0] & 0x7;
+        let flags = buf[0] >> 3;
+        if version != 1 && version != 2 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("unsupported unwind info version"));
+        }
+
+        let count = buf[2] as usize;
+        let slots_end = 4 + count * 2;
+        if buf.len() < slots_end {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("unwind codes are truncated"));
+        }
+        let slot = |idx: usize| u16::from_le_bytes([buf[4 + idx * 2], buf[5 + idx * 2]]);
+
+        let mut codes = vec![];
+        let mut idx = 0;
+        while idx < count {
+            let code = slot(idx);
+            let prolog_offset = (code & 0xff) as u8;
+            let op = ((code >> 8) & 0xf) as u8;
+            let info = (code >> 12) as u8;
+
+            // number of additional slots used by the operation
+            let (op, extra) = match op {
+                0 => (UnwindOp::PushNonvol { reg: info }, 0),
+                1 if info == 0 => (UnwindOp::Alloc { size: 0 }, 1),
+                1 => (UnwindOp::Alloc { size: 0 }, 2),
+                2 => (
+                    UnwindOp::Alloc {
+                        size: info as u32 * 8 + 8,
+                    },
+                    0,
+                ),
+                3 => (UnwindOp::SetFpReg, 0),
+                4 => (
+                    UnwindOp::SaveNonvol {
+                        reg: info,
+                        offset: 0,
+                    },
+                    1,
+                ),
+                5 => (
+                    UnwindOp::SaveNonvol {
+                        reg: info,
+                        offset: 0,
+                    },
+                    2,
+                ),
+                6 if version == 1 => (
+                    UnwindOp::SaveXmm128 {
+                        reg: info,
+                        offset: 0,
+                    },
+                    1,
+                ),
+                7 if version == 1 => (
+                    UnwindOp::SaveXmm128 {
+                        reg: info,
+                        offset: 0,
+                    },
+                    2,
+                ),
+                6 => (UnwindOp::Other { op }, 0),
+                7 => (UnwindOp::Other { op }, 1),
+                8 => (
+                    UnwindOp::SaveXmm128 {
+                        reg: info,
+                        offset: 0,
+                    },
+                    1,
+                ),
+                9 => (
+                    UnwindOp::SaveXmm128 {
+                        reg: info,
+                        offset: 0,
+                    },
+                    2,
+                ),
+                10 => (
+                    UnwindOp::PushMachFrame {
+                        error_code: info != 0,
+                    },
+                    0,
+                ),
+                _ => {
+                    return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                        .log_debug("unknown unwind operation"))
+                }
+            };
+
+            if idx + extra >= count {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                    .log_debug("unwind operation exceeds the unwind codes"));
+            }
+
+            // decode the operand stored in the additional slots
+            let operand = match extra {
+                1 => slot(idx + 1) as u32,
+                2 => slot(idx + 1) as u32 | (slot(idx + 2) as u32) << 16,
+                _ => 0,
+            };
+            let op = match (op, extra) {
+                (UnwindOp::Alloc { .. }, 1) => UnwindOp::Alloc { size: operand * 8 },
+                (UnwindOp::Alloc { .. }, 2) => UnwindOp::Alloc { size: operand },
+                (UnwindOp::SaveNonvol { reg, .. }, 1) => UnwindOp::SaveNonvol {
+                    reg,
+                    offset: operand * 8,
+                },
+                (UnwindOp::SaveNonvol { reg, .. }, 2) => UnwindOp::SaveNonvol {
+                    reg,
+                    offset: operand,
+                },
+                (UnwindOp::SaveXmm128 { reg, .. }, 1) => UnwindOp::SaveXmm128 {
+                    reg,
+                    offset: operand * 16,
+                },
+                (UnwindOp::SaveXmm128 { reg, .. }, 2) => UnwindOp::SaveXmm128 {
+                    reg,
+                    offset: operand,
+                },
+                (op, _) => op,
+            };
+
+            codes.push(UnwindCode { prolog_offset, op });
+            idx += 1 + extra;
+        }
+
+        // the unwind code array is padded to an even number of slots
+        let trailer = 4 + ((count + 1) & !1) * 2;
+        let (mut handler, mut handler_data, mut chained) = (None, None, None);
+        if flags & UNW_FLAG_CHAININFO != 0 {
+            if buf.len() >= trailer + 12 {
+                chained = Some(RuntimeFunction::parse(&buf[trailer..]));
+            }
+        } else if flags & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0 && buf.len() >= trailer + 4 {
+            handler = Some(read_u32(buf, trailer));
+            handler_data = Some(rva + trailer as u32 + 4);
+        }
+
+        Ok(Self {
+            version,
+            flags,
+            prolog_size: buf[1],
+            frame_register: buf[3] & 0xf,
+            frame_offset: buf[3] >> 4,
+            codes,
+            handler,
+            handler_data,
+            chained,
+        })
+    }
+
+    /// Returns the maximum size of an unwind info with `count` unwind codes.
+    fn max_size(count: u8) -> usize {
+        4 + ((count as usize + 1) & !1) * 2 + 12
+    }
+}
+
+/// An exception or termination handler registered for a function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExceptionHandler {
+    /// The function the handler is registered for
+    pub function: RuntimeFunction,
+    /// Virtual address of the language specific handler
+    pub handler: Address,
+    /// The `UNW_FLAG_*` flags of the unwind info
+    pub flags: u8,
+    /// True if the handler does not point into the image it is registered in
+    pub outside_image: bool,
+}
+
+/// Register state of a frame during a virtual unwind.
+///
+/// General purpose registers are indexed in the order used by the unwind codes: `rax`, `rcx`,
+/// `rdx`, `rbx`, `rsp`, `rbp`, `rsi`, `rdi`, `r8` - `r15`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct UnwindContext {
+    pub rip: u64,
+    pub rsp: u64,
+    pub gprs: [u64; 16],
+}
+
+impl UnwindContext {
+    /// Constructs a context from the instruction and stack pointer of a frame.
+    ///
+    /// Registers restored by the unwind of a frame that uses a frame pointer have to be set
+    /// through [`gprs`](Self::gprs) before unwinding it.
+    pub fn new(rip: u64, rsp: u64) -> Self {
+        let mut gprs = [0; 16];
+        gprs[4] = rsp;
+        Self { rip, rsp, gprs }
+    }
+}
+
+/// The exception directory of a loaded x64 PE image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExceptionDirectory {
+    /// Base address of the image
+    pub base: Address,
+    /// Size of the image in memory
+    pub image_size: u32,
+    /// The runtime functions sorted by their start address
+    pub functions: Vec<RuntimeFunction>,
+}
+
+impl ExceptionDirectory {
+    /// Reads the exception directory of the image loaded at `base`.
+    ///
+    /// Fails with `ErrorKind::NotSupported` if the image is not a PE32+ image and with
+    /// `ErrorKind::NotFound` if the image does not have an exception directory.
+    pub fn read(mem: &mut impl MemoryView, base: Address) -> Result<Self> {
+        let dos = mem.read_raw(base, 0x40).data_part()?;
+        if &dos[..2] != b"MZ" {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("image does not start with a dos header"));
+        }
+        let nt = base + read_u32(&dos, 0x3c) as umem;
+
+        let headers = mem.read_raw(nt, 0x18 + 0x70 + 16 * 8).data_part()?;
+        if &headers[..4] != b"PE\0\0" {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("invalid nt header signature"));
+        }
+
+        let optional = &headers[0x18..];
+        if u16::from_le_bytes([optional[0], optional[1]]) != IMAGE_NT_OPTIONAL_HDR64_MAGIC {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+                .log_debug("exception directories are only supported for PE32+ images"));
+        }
+        let image_size = read_u32(optional, 0x38);
+
+        let directory = 0x70 + IMAGE_DIRECTORY_ENTRY_EXCEPTION * 8;
+        let (rva, size) = (
+            read_u32(optional, directory),
+            read_u32(optional, directory + 4) as usize,
+        );
+        if rva == 0 || size == 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("image does not have an exception directory"));
+        }
+
+        let count = std::cmp::min(size / 12, MAX_RUNTIME_FUNCTIONS);
+        let buf = mem.read_raw(base + rva as umem, count * 12).data_part()?;
+
+        let mut functions = buf
+            .chunks_exact(12)
+            .map(RuntimeFunction::parse)
+            .filter(|f| f.begin < f.end)
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|f| f.begin);
+
+        Ok(Self {
+            base,
+            image_size,
+            functions,
+        })
+    }
+
+    /// Returns the runtime function containing `address`.
+    pub fn lookup(&self, address: Address) -> Option<&RuntimeFunction> {
+        if address < self.base {
+            return None;
+        }
+        let rva: u32 = ((address - self.base) as u64).try_into().ok()?;
+        let idx = self
+            .functions
+            .partition_point(|f| f.begin <= rva)
+            .checked_sub(1)?;
+        Some(&self.functions[idx]).filter(|f| f.contains(rva))
+    }
+
+    /// Reads the unwind info of `function`.
+    pub fn unwind_info(
+        &self,
+        mem: &mut impl MemoryView,
+        function: &RuntimeFunction,
+    ) -> Result<UnwindInfo> {
+        // unwind infos are referenced through an rva with the lowest bit set in some images
+        let rva = function.unwind_info & !1;
+        let addr = self.base + rva as umem;
+
+        let header = mem.read_raw(addr, 4).data_part()?;
+        let buf = mem
+            .read_raw(addr, UnwindInfo::max_size(header[2]))
+            .data_part()?;
+        UnwindInfo::parse(&buf, rva)
+    }
+
+    /// Enumerates the exception and termination handlers of all functions.
+    ///
+    /// Functions whose unwind info can not be read are skipped.
+    pub fn handlers(&self, mem: &mut impl MemoryView) -> Result<Vec<ExceptionHandler>> {
+        let mut handlers = vec![];
+        for function in self.functions.iter() {
+            let info = match self.unwind_info(mem, function) {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+
+            if let Some(handler) = info.handler {
+                handlers.push(ExceptionHandler {
+                    function: *function,
+                    handler: self.base + handler as umem,
+                    flags: info.flags,
+                    outside_image: handler >= self.image_size,
+                });
+            }
+        }
+        Ok(handlers)
+    }
+
+    /// Unwinds a single frame and returns the context of the caller.
+    ///
+    /// If `context.rip` is not covered by a runtime function the frame is treated as a leaf
+    /// function, whose return address is located directly at the stack pointer.
+    pub fn unwind(
+        &self,
+        mem: &mut impl MemoryView,
+        context: &UnwindContext,
+    ) -> Result<UnwindContext> {
+        let mut ctx = *context;
+
+        let function = match self.lookup(Address::from(ctx.rip)) {
+            Some(function) => *function,
+            None => return pop_return_address(mem, ctx),
+        };
+
+        let prolog_offset = ctx.rip - (self.base.to_umem() as u64 + function.begin as u64);
+        let mut info = self.unwind_info(mem, &function)?;
+
+        for depth in 0..MAX_CHAIN_DEPTH {
+            // chained infos describe parts of the prolog which have been fully executed
+            let primary = depth == 0;
+
+            for code in info.codes.iter() {
+                if primary && code.prolog_offset as u64 > prolog_offset {
+                    continue;
+                }
+
+                match code.op {
+                    UnwindOp::PushNonvol { reg } => {
+                        ctx.gprs[reg as usize] = read_u64(mem, ctx.rsp)?;
+                        ctx.rsp += 8;
+                    }
+                    UnwindOp::Alloc { size } => ctx.rsp += size as u64,
+                    UnwindOp::SetFpReg => {
+                        ctx.rsp = ctx.gprs[info.frame_register as usize]
+                            .wrapping_sub(info.frame_offset as u64 * 16);
+                    }
+                    UnwindOp::SaveNonvol { reg, offset } => {
+                        ctx.gprs[reg as usize] = read_u64(mem, ctx.rsp + offset as u64)?;
+                    }
+                    UnwindOp::PushMachFrame { error_code } => {
+                        let frame = ctx.rsp + if error_code { 8 } else { 0 };
+                        ctx.rip = read_u64(mem, frame)?;
+                        ctx.rsp = read_u64(mem, frame + 24)?;
+                        ctx.gprs[4] = ctx.rsp;
+                        return Ok(ctx);
+                    }
+                    UnwindOp::SaveXmm128 { .. } | UnwindOp::Other { .. } => {}
+                }
+            }
+
+            match info.chained {
+                Some(chained) => info = self.unwind_info(mem, &chained)?,
+                None => return pop_return_address(mem, ctx),
+            }
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+            .log_debug("unwind info chain is too long"))
+    }
+}
+
+fn pop_return_address(mem: &mut impl MemoryView, mut ctx: UnwindContext) -> Result<UnwindContext> {
+    ctx.rip = read_u64(mem, ctx.rsp)?;
+    ctx.rsp += 8;
+    ctx.gprs[4] = ctx.rsp;
+    Ok(ctx)
+}
+
+fn read_u64(mem: &mut impl MemoryView, addr: u64) -> Result<u64> {
+    mem.read::<u64>(Address::from(addr)).data_part()
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const BASE: u64 = 0x10000;
+
+    /// Builds a minimal PE32+ image with two functions:
+    ///
+    /// * `0x1000 - 0x1100`: `push rbx; sub rsp, 0x20` with an exception handler at `0x1200`
+    /// * `0x1100 - 0x1200`: `push rbp; mov rbp, rsp` with a handler outside of the image
+    fn build_image(mem: &mut impl MemoryView) {
+        let base = Address::from(BASE);
+        mem.write_raw(base, b"MZ").unwrap();
+        mem.write(base + 0x3cusize, &0x80u32).unwrap();
+
+        let nt = base + 0x80usize;
+        mem.write_raw(nt, b"PE\0\0").unwrap();
+        let optional = nt + 0x18usize;
+        mem.write(optional, &IMAGE_NT_OPTIONAL_HDR64_MAGIC).unwrap();
+        mem.write(optional + 0x38usize, &0x3000u32).unwrap();
+        mem.write(optional + 0x70usize + 3 * 8usize, &0x2000u32)
+            .unwrap();
+        mem.write(optional + 0x74usize + 3 * 8usize, &24u32)
+            .unwrap();
+
+        // .pdata
+        let pdata: [u32; 6] = [0x1000, 0x1100, 0x2100, 0x1100, 0x1200, 0x2200];
+        for (i, v) in pdata.iter().enumerate() {
+            mem.write(base + 0x2000usize + i * 4, v).unwrap();
+        }
+
+        // push rbx (offset 1); sub rsp, 0x20 (offset 5), with an exception handler
+        mem.write_raw(
+            base + 0x2100usize,
+            &[1 | (UNW_FLAG_EHANDLER << 3), 5, 2, 0, 5, 0x32, 1, 0x30],
+        )
+        .unwrap();
+        mem.write(base + 0x2108usize, &0x1200u32).unwrap();
+
+        // push rbp (offset 1); mov rbp, rsp (offset 4), with a handler outside of the image
+        mem.write_raw(
+            base + 0x2200usize,
+            &[1 | (UNW_FLAG_UHANDLER << 3), 4, 2, 5, 4, 0x03, 1, 0x50],
+        )
+        .unwrap();
+        mem.write(base + 0x2208usize, &0x9000u32).unwrap();
+    }
+
+    #[test]
+    fn enumerate_handlers() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        build_image(&mut view);
+
+        let directory = ExceptionDirectory::read(&mut view, Address::from(BASE)).unwrap();
+        assert_eq!(directory.functions.len(), 2);
+        assert_eq!(
+            directory
+                .lookup(Address::from(BASE + 0x1150))
+                .unwrap()
+                .begin,
+            0x1100
+        );
+        assert!(directory.lookup(Address::from(BASE + 0x1300)).is_none());
+
+        let info = directory
+            .unwind_info(&mut view, &directory.functions[0])
+            .unwrap();
+        assert_eq!(
+            info.codes,
+            vec![
+                UnwindCode {
+                    prolog_offset: 5,
+                    op: UnwindOp::Alloc { size: 0x20 }
+                },
+                UnwindCode {
+                    prolog_offset: 1,
+                    op: UnwindOp::PushNonvol { reg: 3 }
+                },
+            ]
+        );
+
+        let handlers = directory.handlers(&mut view).unwrap();
+        assert_eq!(handlers.len(), 2);
+        assert_eq!(handlers[0].handler, Address::from(BASE + 0x1200));
+        assert!(!handlers[0].outside_image);
+        assert!(handlers[1].outside_image);
+    }
+
+    #[test]
+    fn unwind_frames() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        build_image(&mut view);
+        let directory = ExceptionDirectory::read(&mut view, Address::from(BASE)).unwrap();
+
+        // stack after the prolog of the first function: 0x20 bytes, saved rbx, return address
+        let rsp = 0x80000u64;
+        view.write(Address::from(rsp + 0x20), &0x1234u64).unwrap();
+        view.write(Address::from(rsp + 0x28), &0x4242u64).unwrap();
+
+        let caller = directory
+            .unwind(&mut view, &UnwindContext::new(BASE + 0x1050, rsp))
+            .unwrap();
+        assert_eq!(caller.rip, 0x4242);
+        assert_eq!(caller.rsp, rsp + 0x30);
+        assert_eq!(caller.gprs[3], 0x1234);
+
+        // interrupted after the push only, the allocation has not been performed yet
+        let caller = directory
+            .unwind(&mut view, &UnwindContext::new(BASE + 0x1002, rsp + 0x20))
+            .unwrap();
+        assert_eq!(caller.rip, 0x4242);
+        assert_eq!(caller.rsp, rsp + 0x30);
+
+        // frame pointer based unwind of the second function
+        let mut ctx = UnwindContext::new(BASE + 0x1150, rsp);
+        ctx.gprs[5] = rsp + 0x20;
+        let caller = directory.unwind(&mut view, &ctx).unwrap();
+        assert_eq!(caller.gprs[5], 0x1234);
+        assert_eq!(caller.rip, 0x4242);
+    }
+}
@@ -0,0 +1,327 @@
+//! Enumeration of terminal sessions, window stations and desktops.
+//!
+//! On multi-session systems (terminal servers, RDP hosts, fast user switching) every logged on
+//! user gets a session with its own window stations and desktops. Window, clipboard and keyboard
+//! state is tracked per session, so tools accessing them have to know which session a process
+//! belongs to instead of assuming the console session.
+//!
+//! [`SessionEnumerator::sessions`] groups the processes of a system by their session id, which is
+//! read from the `MM_SESSION_SPACE` referenced by every `EPROCESS`. The window stations of a
+//! session are the `WindowStation` objects in `\Sessions\<id>\Windows\WindowStations` (or
+//! `\Windows\WindowStations` for session 0), their desktops are linked through
+//! `tagWINDOWSTATION::rpdeskList` and `tagDESKTOP::rpdeskNext`.
+//!
+//! # Remarks
+//!
+//! Window stations and desktops are allocated from session space, which is only mapped in the
+//! address space of processes of the same session. They are therefore read through a process of
+//! the respective session. The offsets of the win32k structures differ between builds and have
+//! to be supplied through a [`WinstaLayout`], e.g. from the win32k debug symbols.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::analysis::objdir::ObjectNamespace;
+//! use memflow::analysis::winsta::{SessionEnumerator, WinstaLayout};
+//!
+//! fn print_sessions(
+//!     os: &mut impl Os,
+//!     namespace: &mut ObjectNamespace,
+//!     layout: WinstaLayout,
+//! ) -> Result<()> {
+//!     let enumerator = SessionEnumerator::new(x86::x64::ARCH, layout);
+//!     for session in enumerator.sessions(os, namespace)? {
+//!         println!("session {}: {} processes", session.id, session.processes.len());
+//!         for winsta in session.window_stations.iter() {
+//!             for desktop in winsta.desktops.iter() {
+//!                 println!("  {}\\{}", winsta.name, desktop.name);
+//!             }
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::analysis::objdir::ObjectLayout;
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! # let mut namespace =
+//! #     ObjectNamespace::new(x86::x64::ARCH, ObjectLayout::x64(), Address::null(), Address::null());
+//! # print_sessions(&mut os, &mut namespace, WinstaLayout::default()).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use super::objdir::ObjectNamespace;
+use crate::architecture::ArchitectureObj;
+use crate::error::{ErrorKind, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{Os, Pid};
+use crate::types::Address;
+
+/// Maximum number of desktops walked per window station.
+pub const MAX_DESKTOPS: usize = 0x100;
+
+/// Offsets of the structures describing sessions, window stations and desktops.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WinstaLayout {
+    /// Offset of `EPROCESS::Session`
+    pub process_session: usize,
+    /// Offset of `MM_SESSION_SPACE::SessionId`
+    pub session_id: usize,
+    /// Offset of `tagWINDOWSTATION::rpdeskList`
+    pub winsta_desktop_list: usize,
+    /// Offset of `tagDESKTOP::rpdeskNext`
+    pub desktop_next: usize,
+}
+
+/// A process running in a session.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SessionProcess {
+    /// ID of the process
+    pub pid: Pid,
+    /// Name of the process
+    pub name: String,
+    /// Address of the `EPROCESS`
+    pub address: Address,
+}
+
+/// A desktop of a window station.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Desktop {
+    /// Address of the `tagDESKTOP`
+    pub address: Address,
+    /// Name of the desktop, e.g. `Default` or `Winlogon`
+    pub name: String,
+}
+
+/// A window station of a session.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WindowStation {
+    /// Address of the `tagWINDOWSTATION`
+    pub address: Address,
+    /// Name of the window station, e.g. `WinSta0`
+    pub name: String,
+    /// The desktops of the window station
+    pub desktops: Vec<Desktop>,
+}
+
+impl WindowStation {
+    /// Returns true if this is the interactive window station.
+    pub fn is_interactive(&self) -> bool {
+        self.name.eq_ignore_ascii_case("WinSta0")
+    }
+}
+
+/// A terminal session with its processes and window stations.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Session {
+    /// ID of the session
+    pub id: u32,
+    /// Processes running in the session
+    pub processes: Vec<SessionProcess>,
+    /// Window stations of the session
+    pub window_stations: Vec<WindowStation>,
+}
+
+/// Enumerates sessions, window stations and desktops.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionEnumerator {
+    arch: ArchitectureObj,
+    layout: WinstaLayout,
+}
+
+impl SessionEnumerator {
+    /// Constructs a new enumerator for the given architecture and structure layout.
+    pub fn new(arch: ArchitectureObj, layout: WinstaLayout) -> Self {
+        Self { arch, layout }
+    }
+
+    /// Enumerates all sessions of `os` with their processes, window stations and desktops.
+    ///
+    /// Processes that are not part of any session (`System`, `Registry`, ...) are skipped.
+    /// Sessions whose window stations can not be found are reported without them.
+    pub fn sessions(
+        &self,
+        os: &mut impl Os,
+        namespace: &mut ObjectNamespace,
+    ) -> Result<Vec<Session>> {
+        let mut sessions = BTreeMap::<u32, Session>::new();
+
+        for info in os.process_info_list()? {
+            let mut proc = match os.process_by_info(info.clone()) {
+                Ok(proc) => proc,
+                Err(_) => continue,
+            };
+
+            let id = match self.process_session(&mut proc, info.address) {
+                Ok(Some(id)) => id,
+                _ => continue,
+            };
+
+            let session = sessions.entry(id).or_insert_with(|| Session {
+                id,
+                ..Default::default()
+            });
+            let first = session.processes.is_empty();
+            session.processes.push(SessionProcess {
+                pid: info.pid,
+                name: info.name.to_string(),
+                address: info.address,
+            });
+
+            // session space is only accessible through a process of the session
+            if first {
+                session.window_stations = match self.window_stations(&mut proc, namespace, id) {
+                    Ok(window_stations) => window_stations,
+                    Err(err) if err.1 == ErrorKind::NotFound => vec![],
+                    Err(err) => return Err(err),
+                };
+            }
+        }
+
+        Ok(sessions.into_values().collect())
+    }
+
+    /// Returns the id of the session of the process with the `EPROCESS` at `process`.
+    ///
+    /// Returns `None` for processes that are not part of any session.
+    pub fn process_session(
+        &self,
+        mem: &mut impl MemoryView,
+        process: Address,
+    ) -> Result<Option<u32>> {
+        let session = mem
+            .read_addr_arch(self.arch, process + self.layout.process_session)
+            .data_part()?;
+        if session.is_null() {
+            return Ok(None);
+        }
+
+        let id = mem
+            .read::<u32>(session + self.layout.session_id)
+            .data_part()?;
+        Ok(Some(id))
+    }
+
+    /// Returns the window stations of session `id`.
+    ///
+    /// `mem` has to be the address space of a process running in the session.
+    pub fn window_stations(
+        &self,
+        mem: &mut impl MemoryView,
+        namespace: &mut ObjectNamespace,
+        id: u32,
+    ) -> Result<Vec<WindowStation>> {
+        let path = if id == 0 {
+            "\\Windows\\WindowStations".to_string()
+        } else {
+            format!("\\Sessions\\{}\\Windows\\WindowStations", id)
+        };
+
+        let mut ret = vec![];
+        for object in namespace.list(mem, &path)? {
+            if object.type_name != "WindowStation" {
+                continue;
+            }
+            ret.push(WindowStation {
+                desktops: self.desktops(mem, namespace, object.address)?,
+                address: object.address,
+                name: object.name,
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Returns the desktops of the window station at `winsta`.
+    pub fn desktops(
+        &self,
+        mem: &mut impl MemoryView,
+        namespace: &mut ObjectNamespace,
+        winsta: Address,
+    ) -> Result<Vec<Desktop>> {
+        let mut ret = vec![];
+        let mut desktop = mem
+            .read_addr_arch(self.arch, winsta + self.layout.winsta_desktop_list)
+            .data_part()?;
+
+        while !desktop.is_null() && ret.len() < MAX_DESKTOPS {
+            ret.push(Desktop {
+                address: desktop,
+                name: namespace.object_name(mem, desktop).unwrap_or_default(),
+            });
+            desktop = mem
+                .read_addr_arch(self.arch, desktop + self.layout.desktop_next)
+                .data_part()?;
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::objdir::ObjectLayout;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const LAYOUT: WinstaLayout = WinstaLayout {
+        process_session: 0x400,
+        session_id: 0x8,
+        winsta_desktop_list: 0x10,
+        desktop_next: 0x18,
+    };
+
+    #[test]
+    fn process_sessions() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        let enumerator = SessionEnumerator::new(x64::ARCH, LAYOUT);
+
+        // System process without a session
+        let system = Address::from(0x1000u64);
+        assert_eq!(enumerator.process_session(&mut view, system).unwrap(), None);
+
+        let process = Address::from(0x2000u64);
+        view.write(process + 0x400usize, &0x8000u64).unwrap();
+        view.write(Address::from(0x8008u64), &2u32).unwrap();
+        assert_eq!(
+            enumerator.process_session(&mut view, process).unwrap(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn desktop_list() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        let enumerator = SessionEnumerator::new(x64::ARCH, LAYOUT);
+        let mut namespace = ObjectNamespace::new(
+            x64::ARCH,
+            ObjectLayout::x64(),
+            Address::null(),
+            Address::null(),
+        );
+
+        let winsta = Address::from(0x10000u64);
+        view.write(winsta + 0x10usize, &0x11000u64).unwrap();
+        view.write(Address::from(0x11018u64), &0x12000u64).unwrap();
+
+        let desktops = enumerator
+            .desktops(&mut view, &mut namespace, winsta)
+            .unwrap();
+        assert_eq!(
+            desktops.iter().map(|d| d.address).collect::<Vec<_>>(),
+            vec![Address::from(0x11000u64), Address::from(0x12000u64)]
+        );
+    }
+}
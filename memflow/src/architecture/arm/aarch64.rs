@@ -19,6 +19,15 @@ pub(super) static ARCH_SPEC: ArmArchitecture = ArmArchitecture {
         present_bit: |a| a.bit_at(0),
         writeable_bit: |a, _| a.bit_at(10),
         nx_bit: |a, _| a.bit_at(54),
+        // table descriptors deny EL0 accesses to the following levels through APTable[0],
+        // block and page descriptors grant them through AP[1]
+        supervisor_bit: Some(|a, pb, step| {
+            pb || if step < 3 && a.bit_at(1) {
+                a.bit_at(61)
+            } else {
+                !a.bit_at(6)
+            }
+        }),
         large_page_bit: |a| !a.bit_at(1),
     }
     .into_spec(),
@@ -29,3 +38,41 @@ pub static ARCH: ArchitectureObj = &ARCH_SPEC;
 pub fn new_translator(dtb1: Address, dtb2: Address) -> ArmVirtualTranslate {
     ArmVirtualTranslate::new(&ARCH_SPEC, dtb1, dtb2)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mem::virt_translate::mmu::FlagsType;
+    use crate::types::{Address, PageType};
+
+    #[test]
+    fn aarch64_page_access() {
+        let mmu = &super::ARCH_SPEC.mmu;
+
+        // table descriptors, the second one denies EL0 accesses through APTable[0]
+        let table = Address::from(0x2003u64);
+        let flags = FlagsType::NONE.inherit(&mmu.def, table, 0);
+        assert!(!flags.contains(FlagsType::SUPERVISOR));
+        let flags = FlagsType::NONE.inherit(&mmu.def, Address::from(0x2003u64 | 1 << 61), 0);
+        assert!(flags.contains(FlagsType::SUPERVISOR));
+
+        // page descriptor accessible from EL0 through AP[1]
+        let page = Address::from(0x5003u64 | 1 << 6);
+        assert_eq!(
+            mmu.get_phys_page(page, Address::NULL, 4, FlagsType::NONE)
+                .page_type(),
+            PageType::READ_ONLY | PageType::USER | PageType::EXECUTABLE
+        );
+        assert_eq!(
+            mmu.get_phys_page(page, Address::NULL, 4, flags).page_type(),
+            PageType::READ_ONLY | PageType::SUPERVISOR | PageType::EXECUTABLE
+        );
+
+        // block descriptor without AP[1]
+        let block = Address::from(0x20_0001u64);
+        assert_eq!(
+            mmu.get_phys_page(block, Address::NULL, 3, FlagsType::NONE)
+                .page_type(),
+            PageType::READ_ONLY | PageType::SUPERVISOR | PageType::EXECUTABLE
+        );
+    }
+}
@@ -7,7 +7,7 @@ use crate::mem::virt_translate::{
         translate_data::{TranslateDataVec, TranslationChunk},
         ArchMmuSpec, MmuTranslationBase,
     },
-    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    PageWalk, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
 };
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -132,6 +132,14 @@ impl VirtualTranslate3 for ArmVirtualTranslate {
     fn arch(&self) -> ArchitectureObj {
         self.arch
     }
+
+    fn page_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<PageWalk> {
+        Ok(self.arch.mmu.page_walk(mem, self.dtb, addr))
+    }
 }
 
 // This lint doesn't make any sense in our usecase, since we nevel leak ARCH_SPECs, and ARCH is
@@ -26,7 +26,7 @@ use crate::types::size;
 /// See the [wikipedia article](https://en.wikipedia.org/wiki/Endianness) for more information on the subject.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
 pub enum Endianess {
     /// Little Endianess
@@ -178,6 +178,45 @@ impl ArchitectureIdent {
     pub fn into_obj(self) -> ArchitectureObj {
         self.into()
     }
+
+    /// Converts the `Machine` field of a PE/COFF file header into an architecture.
+    ///
+    /// ARM64EC binaries contain ARM64 code and are reported as AArch64. Since the header does
+    /// not tell whether PAE is used, 32-bit x86 binaries are always reported without it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::architecture::ArchitectureIdent;
+    /// use memflow::types::size;
+    ///
+    /// let arm64 = ArchitectureIdent::from_pe_machine(0xaa64);
+    /// assert_eq!(arm64, Some(ArchitectureIdent::AArch64(size::kb(4))));
+    ///
+    /// let amd64 = ArchitectureIdent::from_pe_machine(0x8664);
+    /// assert_eq!(amd64, Some(ArchitectureIdent::X86(64, false)));
+    ///
+    /// // 32-bit ARM is not supported
+    /// assert_eq!(ArchitectureIdent::from_pe_machine(0x1c0), None);
+    /// ```
+    pub fn from_pe_machine(machine: u16) -> Option<Self> {
+        match machine {
+            0x014c => Some(ArchitectureIdent::X86(32, false)),
+            0x8664 => Some(ArchitectureIdent::X86(64, false)),
+            0xaa64 | 0xa641 => Some(ArchitectureIdent::AArch64(size::kb(4))),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Machine` value of PE/COFF images of this architecture.
+    pub fn pe_machine(&self) -> Option<u16> {
+        match self {
+            ArchitectureIdent::X86(32, _) => Some(0x014c),
+            ArchitectureIdent::X86(64, _) => Some(0x8664),
+            ArchitectureIdent::AArch64(_) => Some(0xaa64),
+            _ => None,
+        }
+    }
 }
 
 impl From<ArchitectureIdent> for ArchitectureObj {
@@ -210,3 +249,57 @@ impl serde::Serialize for ArchitectureObj {
         state.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_pe_machine() {
+        assert_eq!(
+            ArchitectureIdent::from_pe_machine(0x014c),
+            Some(ArchitectureIdent::X86(32, false))
+        );
+        assert_eq!(
+            ArchitectureIdent::from_pe_machine(0x8664),
+            Some(ArchitectureIdent::X86(64, false))
+        );
+        assert_eq!(
+            ArchitectureIdent::from_pe_machine(0xaa64),
+            Some(ArchitectureIdent::AArch64(size::kb(4)))
+        );
+        // ARM64EC
+        assert_eq!(
+            ArchitectureIdent::from_pe_machine(0xa641),
+            Some(ArchitectureIdent::AArch64(size::kb(4)))
+        );
+        // ARMNT, IA64 and unknown machines
+        assert_eq!(ArchitectureIdent::from_pe_machine(0x01c4), None);
+        assert_eq!(ArchitectureIdent::from_pe_machine(0x0200), None);
+        assert_eq!(ArchitectureIdent::from_pe_machine(0), None);
+    }
+
+    #[test]
+    fn pe_machine() {
+        assert_eq!(ArchitectureIdent::X86(32, false).pe_machine(), Some(0x014c));
+        assert_eq!(ArchitectureIdent::X86(32, true).pe_machine(), Some(0x014c));
+        assert_eq!(ArchitectureIdent::X86(64, false).pe_machine(), Some(0x8664));
+        assert_eq!(
+            ArchitectureIdent::AArch64(size::kb(4)).pe_machine(),
+            Some(0xaa64)
+        );
+        assert_eq!(ArchitectureIdent::Unknown(0).pe_machine(), None);
+    }
+
+    #[test]
+    fn pe_machine_round_trip() {
+        for arch in [
+            ArchitectureIdent::X86(32, false),
+            ArchitectureIdent::X86(64, false),
+            ArchitectureIdent::AArch64(size::kb(4)),
+        ] {
+            let machine = arch.pe_machine().unwrap();
+            assert_eq!(ArchitectureIdent::from_pe_machine(machine), Some(arch));
+        }
+    }
+}
@@ -0,0 +1,247 @@
+/*!
+Validation of x64 directory table bases.
+
+A directory table base (DTB) found by scanning physical memory, or read from a structure of the
+target, is only trustworthy if the page it points to actually looks like a top level page table.
+[`dtb_validate`] scores a candidate PML4 by the following properties:
+
+* A self-referencing entry, which Windows uses to map the page tables into the kernel address
+  space. Since Windows 10 its index is randomized, but it is always located in the kernel half.
+* Present entries in the kernel half of the address space, which every process maps.
+* A sane user/kernel split, i.e. no user accessible entries in the kernel half.
+* All present entries referencing memory inside of the physical address space.
+
+[`dtb_scan`] applies the same scoring to every page of a physical memory range and returns the
+plausible candidates, best first.
+
+# Examples
+
+```
+use memflow::architecture::x86::dtb::dtb_validate;
+use memflow::mem::PhysicalMemory;
+use memflow::types::Address;
+
+fn trust_dtb(mem: &mut impl PhysicalMemory, dtb: Address) -> bool {
+    match dtb_validate(mem, dtb) {
+        Ok(score) => score.is_valid(),
+        Err(_) => false,
+    }
+}
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+# let mut mem = DummyMemory::new(size::mb(1));
+# assert!(!trust_dtb(&mut mem, Address::from(0x1000u64)));
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use crate::error::Result;
+use crate::mem::PhysicalMemory;
+use crate::types::{bitfield::X86Pte, umem, Address};
+
+/// Number of entries of a page table.
+const PT_ENTRIES: usize = 512;
+
+/// Size of a page table.
+const PT_SIZE: usize = PT_ENTRIES * 8;
+
+/// Number of page tables read at once by [`dtb_scan`].
+const SCAN_CHUNK_PAGES: usize = 256;
+
+/// Minimum score of a plausible directory table base.
+pub const DTB_VALID_SCORE: u32 = 50;
+
+/// Score of a candidate directory table base.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DtbScore {
+    /// The candidate directory table base
+    pub dtb: Address,
+    /// Index of the entry referencing the table itself
+    pub self_ref: Option<usize>,
+    /// Number of present entries in the user half of the address space
+    pub user_entries: usize,
+    /// Number of present entries in the kernel half, excluding the self-reference
+    pub kernel_entries: usize,
+    /// Number of user accessible entries in the kernel half
+    pub split_violations: usize,
+    /// Number of present entries referencing memory outside of the physical address space
+    pub invalid_entries: usize,
+    /// Total score of the candidate between 0 and 100
+    pub score: u32,
+}
+
+impl DtbScore {
+    /// Scores the top level page table `table` located at `dtb`.
+    ///
+    /// `max_address` is the highest valid physical address of the target.
+    pub fn from_table(dtb: Address, table: &[u8], max_address: Address) -> Self {
+        let mut ret = Self {
+            dtb,
+            ..Default::default()
+        };
+
+        for (idx, entry) in table.chunks_exact(8).take(PT_ENTRIES).enumerate() {
+            let pte = X86Pte(u64::from_le_bytes(entry.try_into().unwrap()));
+            if !pte.present() {
+                continue;
+            }
+
+            if pte.page_frame() as umem > max_address.to_umem() {
+                ret.invalid_entries += 1;
+            } else if idx < PT_ENTRIES / 2 {
+                ret.user_entries += 1;
+            } else if pte.page_frame() as umem == dtb.to_umem() && !pte.user() {
+                ret.self_ref.get_or_insert(idx);
+            } else {
+                ret.kernel_entries += 1;
+                if pte.user() {
+                    ret.split_violations += 1;
+                }
+            }
+        }
+
+        let present = ret.user_entries
+            + ret.kernel_entries
+            + ret.invalid_entries
+            + ret.self_ref.map_or(0, |_| 1);
+        if present > 0 {
+            if ret.self_ref.is_some() {
+                ret.score += 40;
+            }
+            if ret.kernel_entries > 0 {
+                ret.score += 30;
+            }
+            if ret.split_violations == 0 {
+                ret.score += 20;
+            }
+            if ret.invalid_entries == 0 {
+                ret.score += 10;
+            }
+        }
+
+        ret
+    }
+
+    /// Returns true if the candidate is plausible enough to be used for translations.
+    pub fn is_valid(&self) -> bool {
+        self.score >= DTB_VALID_SCORE && self.invalid_entries == 0
+    }
+}
+
+/// Scores the candidate directory table base `dtb`.
+///
+/// The lower 12 bits of `dtb` (e.g. the PCID of a `CR3` value) are ignored.
+pub fn dtb_validate(mem: &mut impl PhysicalMemory, dtb: Address) -> Result<DtbScore> {
+    let dtb = Address::from(dtb.to_umem() & X86Pte::ADDRESS_MASK as umem);
+    let max_address = mem.metadata().max_address;
+
+    let mut table = vec![0u8; PT_SIZE];
+    mem.phys_read_into(dtb.into(), &mut table[..])?;
+
+    Ok(DtbScore::from_table(dtb, &table, max_address))
+}
+
+/// Scans the physical memory between `start` and `end` for directory table bases.
+///
+/// Returns all plausible candidates, sorted by their score in descending order.
+pub fn dtb_scan(
+    mem: &mut impl PhysicalMemory,
+    start: Address,
+    end: Address,
+) -> Result<Vec<DtbScore>> {
+    let max_address = mem.metadata().max_address;
+    let end = std::cmp::min(end.to_umem(), max_address.to_umem() + 1);
+
+    let mut ret = vec![];
+    let mut buf = vec![0u8; PT_SIZE * SCAN_CHUNK_PAGES];
+    let mut addr = start.to_umem() & X86Pte::ADDRESS_MASK as umem;
+    while addr < end {
+        let len = std::cmp::min(buf.len() as umem, end - addr) as usize;
+        let chunk = &mut buf[..len];
+        mem.phys_read_into(Address::from(addr).into(), chunk)?;
+
+        for (i, table) in chunk.chunks_exact(PT_SIZE).enumerate() {
+            let score = DtbScore::from_table(
+                Address::from(addr + (i * PT_SIZE) as umem),
+                table,
+                max_address,
+            );
+            if score.is_valid() {
+                ret.push(score);
+            }
+        }
+
+        addr += len as umem;
+    }
+
+    ret.sort_by(|a, b| b.score.cmp(&a.score).then(a.dtb.cmp(&b.dtb)));
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    fn write_pml4(mem: &mut DummyMemory, dtb: u64) {
+        let entries: [(usize, u64); 3] = [
+            // user mode pdpt
+            (0, 0x10000 | 0x7),
+            // self-reference
+            (0x1ed, dtb | 0x3),
+            // kernel pdpt
+            (0x1f0, 0x11000 | 0x3),
+        ];
+        for (idx, entry) in entries.iter() {
+            mem.phys_write(Address::from(dtb + *idx as u64 * 8).into(), entry)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_pml4(&mut mem, 0x5000);
+
+        let score = dtb_validate(&mut mem, Address::from(0x5001u64)).unwrap();
+        assert_eq!(score.dtb, Address::from(0x5000u64));
+        assert_eq!(score.self_ref, Some(0x1ed));
+        assert_eq!(score.user_entries, 1);
+        assert_eq!(score.kernel_entries, 1);
+        assert_eq!(score.score, 100);
+        assert!(score.is_valid());
+
+        // empty page
+        let score = dtb_validate(&mut mem, Address::from(0x6000u64)).unwrap();
+        assert_eq!(score.score, 0);
+        assert!(!score.is_valid());
+
+        // entries outside of physical memory and user accessible kernel entries
+        mem.phys_write(Address::from(0x7000u64).into(), &0x1_0000_0007u64)
+            .unwrap();
+        mem.phys_write(Address::from(0x7800u64).into(), &0x12007u64)
+            .unwrap();
+        let score = dtb_validate(&mut mem, Address::from(0x7000u64)).unwrap();
+        assert_eq!(score.invalid_entries, 1);
+        assert_eq!(score.split_violations, 1);
+        assert!(!score.is_valid());
+    }
+
+    #[test]
+    fn scan() {
+        let mut mem = DummyMemory::new(size::mb(2));
+        write_pml4(&mut mem, 0x5000);
+        write_pml4(&mut mem, 0x1f_f000);
+
+        let found = dtb_scan(&mut mem, Address::null(), Address::invalid()).unwrap();
+        assert_eq!(
+            found.iter().map(|s| s.dtb).collect::<Vec<_>>(),
+            vec![Address::from(0x5000u64), Address::from(0x1f_f000u64)]
+        );
+    }
+}
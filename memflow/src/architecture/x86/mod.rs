@@ -1,3 +1,4 @@
+pub mod dtb;
 pub mod x32;
 pub mod x32_pae;
 pub mod x64;
@@ -5,7 +6,7 @@ pub mod x64;
 use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
 
 use crate::mem::virt_translate::{
-    mmu::ArchMmuSpec, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    mmu::ArchMmuSpec, PageWalk, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
 };
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -89,6 +90,14 @@ impl VirtualTranslate3 for X86VirtualTranslate {
     fn arch(&self) -> ArchitectureObj {
         self.arch
     }
+
+    fn page_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<PageWalk> {
+        Ok(self.arch.mmu.page_walk(mem, self.dtb, addr))
+    }
 }
 
 // This lint doesn't make any sense in our usecase, since we nevel leak ARCH_SPECs, and ARCH is
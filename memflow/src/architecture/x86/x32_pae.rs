@@ -5,7 +5,7 @@ use super::{
 
 use crate::mem::virt_translate::mmu::ArchMmuDef;
 
-use crate::types::Address;
+use crate::types::{bitfield::X86Pte, Address};
 
 pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
     bits: 32,
@@ -16,10 +16,12 @@ pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
         endianess: Endianess::LittleEndian,
         addr_size: 4,
         pte_size: 8,
-        present_bit: |a| a.bit_at(0),
-        writeable_bit: |a, pb| pb || a.bit_at(1),
-        nx_bit: |a, pb| pb || a.bit_at(63),
-        large_page_bit: |a| a.bit_at(7),
+        present_bit: |a| X86Pte::from(a).present(),
+        writeable_bit: |a, pb| pb || X86Pte::from(a).writeable(),
+        nx_bit: |a, pb| pb || X86Pte::from(a).nx(),
+        // PDPTEs do not contain a user bit
+        supervisor_bit: Some(|a, pb, step| pb || (step != 0 && !X86Pte::from(a).user())),
+        large_page_bit: |a| X86Pte::from(a).large_page(),
     }
     .into_spec(),
 };
@@ -5,7 +5,7 @@ use super::{
 
 use crate::mem::virt_translate::mmu::ArchMmuDef;
 
-use crate::types::Address;
+use crate::types::{bitfield::X86Pte, Address};
 
 pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
     bits: 64,
@@ -16,10 +16,11 @@ pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
         endianess: Endianess::LittleEndian,
         addr_size: 8,
         pte_size: 8,
-        present_bit: |a| a.bit_at(0),
-        writeable_bit: |a, pb| pb || a.bit_at(1),
-        nx_bit: |a, pb| pb || a.bit_at(63),
-        large_page_bit: |a| a.bit_at(7),
+        present_bit: |a| X86Pte::from(a).present(),
+        writeable_bit: |a, pb| pb || X86Pte::from(a).writeable(),
+        nx_bit: |a, pb| pb || X86Pte::from(a).nx(),
+        supervisor_bit: Some(|a, pb, _| pb || !X86Pte::from(a).user()),
+        large_page_bit: |a| X86Pte::from(a).large_page(),
     }
     .into_spec(),
 };
@@ -154,7 +155,22 @@ mod tests {
         assert_eq!(
             mmu.get_phys_page(pte_address, virt_address, 4, prev_flags)
                 .page_type(),
-            PageType::READ_ONLY
+            PageType::READ_ONLY | PageType::SUPERVISOR | PageType::EXECUTABLE
+        );
+        assert_eq!(
+            mmu.get_phys_page(pte_address + 0b110u64, virt_address, 4, prev_flags)
+                .page_type(),
+            PageType::WRITEABLE | PageType::USER | PageType::EXECUTABLE
+        );
+        assert_eq!(
+            mmu.get_phys_page(
+                pte_address + 0b110u64,
+                virt_address,
+                4,
+                FlagsType::SUPERVISOR
+            )
+            .page_type(),
+            PageType::WRITEABLE | PageType::SUPERVISOR | PageType::EXECUTABLE
         );
         assert_eq!(
             mmu.get_phys_page(pte_address, virt_address, 4, prev_flags)
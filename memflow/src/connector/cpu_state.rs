@@ -1,7 +1,17 @@
 //! Describes optional cpu state for a connector
+//!
+//! Connectors with access to the virtual cpus of the target (e.g. hypervisor or vm snapshot
+//! connectors) can expose their registers through [`CpuState`]. OS layers can use the page table
+//! base in `CR3` to validate a directory table base found by scanning, see [`is_dtb_active`].
+//! Without cpu state, candidates can still be scored by their contents, see
+//! [`dtb_validate`](crate::architecture::x86::dtb::dtb_validate).
+
+use std::prelude::v1::*;
 
 use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin};
 use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
 
 #[cfg_attr(feature = "plugins", cglue_trait)]
 #[int_result]
@@ -25,14 +35,248 @@ cglue_trait_group!(IntoCpuState, { CpuState, Clone }, {});
 #[cglue_forward]
 pub trait CpuState {
     // TODO:
-    // max cpu index
-    // read_register(s)
-    // write_register(s)
-    // pause
-    // resume
     // single-step
     // breakpoints
 
     fn pause(&mut self);
     fn resume(&mut self);
+
+    /// Returns the number of virtual cpus of the target.
+    ///
+    /// By default no cpus are exposed.
+    #[inline]
+    fn cpu_count(&mut self) -> usize {
+        0
+    }
+
+    /// Reads the register `reg` of the cpu with the index `cpu`.
+    ///
+    /// Connectors able to access the registers of the target should override this function.
+    /// By default an error of kind [`ErrorKind::NotSupported`] is returned.
+    fn read_register(&mut self, cpu: usize, reg: CpuRegister) -> Result<u64> {
+        let _ = (cpu, reg);
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_debug("connector does not support reading registers"))
+    }
+
+    /// Writes `value` to the register `reg` of the cpu with the index `cpu`.
+    ///
+    /// Connectors able to access the registers of the target should override this function.
+    /// By default an error of kind [`ErrorKind::NotSupported`] is returned.
+    fn write_register(&mut self, cpu: usize, reg: CpuRegister, value: u64) -> Result<()> {
+        let _ = (cpu, reg, value);
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_debug("connector does not support writing registers"))
+    }
+}
+
+/// Keeps the target paused while it is alive.
+///
+/// OS layers can wrap operations that have to read a lot of interdependent memory (e.g. walking
+/// handle tables or taking a consistent dump) into a guard. The target is paused when the guard
+/// is created and resumed when it is dropped, even if the operation returns early.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+/// use memflow::connector::cpu_state::consistency_guard;
+///
+/// fn consistent_read(conn: &mut impl ConnectorCpuState, mem: &mut impl MemoryView) -> Result<u64> {
+///     // connectors without cpu state can not be paused, the read is then performed anyways
+///     let _guard = consistency_guard(conn);
+///     mem.read(0x1000.into()).data_part()
+/// }
+/// ```
+pub struct ConsistencyGuard<T: CpuState> {
+    state: Option<T>,
+}
+
+impl<T: CpuState> ConsistencyGuard<T> {
+    /// Pauses the target until the guard is dropped.
+    pub fn new(mut state: T) -> Self {
+        state.pause();
+        Self { state: Some(state) }
+    }
+
+    /// Resumes the target and returns the cpu state.
+    pub fn into_inner(mut self) -> T {
+        let mut state = self.state.take().unwrap();
+        state.resume();
+        state
+    }
+}
+
+/// Pauses the target of the connector until the returned guard is dropped.
+///
+/// Returns `None` if the connector does not expose its cpu state.
+pub fn consistency_guard<C: ConnectorCpuState>(
+    conn: &mut C,
+) -> Option<ConsistencyGuard<C::CpuStateType<'_>>> {
+    conn.cpu_state().ok().map(ConsistencyGuard::new)
+}
+
+impl<T: CpuState> core::ops::Deref for ConsistencyGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.state.as_ref().unwrap()
+    }
+}
+
+impl<T: CpuState> core::ops::DerefMut for ConsistencyGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.state.as_mut().unwrap()
+    }
+}
+
+impl<T: CpuState> Drop for ConsistencyGuard<T> {
+    fn drop(&mut self) {
+        if let Some(state) = &mut self.state {
+            state.resume();
+        }
+    }
+}
+
+/// Registers of a virtual cpu.
+///
+/// Registers that do not exist on the architecture of the target are rejected by the connector.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum CpuRegister {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    Rbp,
+    Rsp,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rip,
+    Rflags,
+
+    Cr0,
+    Cr2,
+    Cr3,
+    Cr4,
+    Cr8,
+    Efer,
+    FsBase,
+    GsBase,
+    KernelGsBase,
+
+    /// `IA32_RTIT_CTL`, the control register of Intel Processor Trace
+    RtitCtl,
+    /// `IA32_RTIT_STATUS`
+    RtitStatus,
+    /// `IA32_RTIT_OUTPUT_BASE`, the physical base of the trace output
+    RtitOutputBase,
+    /// `IA32_RTIT_OUTPUT_MASK_PTRS`, the current position in the trace output
+    RtitOutputMaskPtrs,
+}
+
+/// Mask of the page table base in `CR3`, stripping the PCID and the no-flush bit.
+const CR3_BASE_MASK: umem = 0x000f_ffff_ffff_f000;
+
+/// Returns the page table base loaded in `CR3` of every cpu.
+///
+/// The PCID and flag bits are stripped. Cpus whose registers can not be read are skipped, an error
+/// is only returned if the registers of no cpu could be read.
+pub fn page_table_bases(state: &mut impl CpuState) -> Result<Vec<Address>> {
+    let mut bases = vec![];
+    let mut error = Error(ErrorOrigin::Connector, ErrorKind::NotSupported);
+
+    for cpu in 0..state.cpu_count() {
+        match state.read_register(cpu, CpuRegister::Cr3) {
+            Ok(cr3) => bases.push(Address::from(cr3 as umem & CR3_BASE_MASK)),
+            Err(err) => error = err,
+        }
+    }
+
+    if bases.is_empty() {
+        Err(error)
+    } else {
+        Ok(bases)
+    }
+}
+
+/// Checks if the directory table base `dtb` is currently loaded on any cpu of the target.
+///
+/// With kernel page table isolation the user mode page tables of a process are placed directly
+/// after its kernel page tables, so a `CR3` one page above `dtb` is accepted as well.
+pub fn is_dtb_active(state: &mut impl CpuState, dtb: Address) -> Result<bool> {
+    let dtb = Address::from(dtb.to_umem() & CR3_BASE_MASK);
+    Ok(page_table_bases(state)?
+        .into_iter()
+        .any(|base| base == dtb || base == dtb + 0x1000usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestCpus(Vec<u64>, bool);
+
+    impl CpuState for TestCpus {
+        fn pause(&mut self) {
+            self.1 = true;
+        }
+
+        fn resume(&mut self) {
+            self.1 = false;
+        }
+
+        fn cpu_count(&mut self) -> usize {
+            self.0.len()
+        }
+
+        fn read_register(&mut self, cpu: usize, reg: CpuRegister) -> Result<u64> {
+            match (self.0.get(cpu), reg) {
+                (Some(&cr3), CpuRegister::Cr3) => Ok(cr3),
+                _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)),
+            }
+        }
+    }
+
+    #[test]
+    fn dtb_validation() {
+        // cpu 0 runs a process with pcid 5, cpu 1 the user mode page tables of another process
+        let mut cpus = TestCpus(vec![0x1ab005, 0x8000_0000_0023_f000], false);
+
+        assert_eq!(
+            page_table_bases(&mut cpus).unwrap(),
+            vec![Address::from(0x1ab000u64), Address::from(0x23f000u64)]
+        );
+        assert!(is_dtb_active(&mut cpus, 0x1ab000.into()).unwrap());
+        assert!(is_dtb_active(&mut cpus, 0x23e000.into()).unwrap());
+        assert!(is_dtb_active(&mut cpus, 0x23f000.into()).unwrap());
+        assert!(!is_dtb_active(&mut cpus, 0x5000.into()).unwrap());
+    }
+
+    #[test]
+    fn unsupported_registers() {
+        let mut cpus = TestCpus::default();
+        assert!(page_table_bases(&mut cpus).is_err());
+        assert!(cpus.write_register(0, CpuRegister::Cr3, 0).is_err());
+    }
+
+    #[test]
+    fn consistency_guard() {
+        let guard = ConsistencyGuard::new(TestCpus::default());
+        assert!(guard.1);
+
+        let cpus = guard.into_inner();
+        assert!(!cpus.1);
+    }
 }
@@ -0,0 +1,214 @@
+//! Common interface of hardware DMA devices.
+//!
+//! Connectors for PCIe DMA hardware (FPGA boards like the screamer or LeetDMA family, or
+//! devices using an integrated GPU as DMA engine) share the same limitations: memory is
+//! transferred in TLPs of a limited size which have to be DWORD aligned, the device might only
+//! be able to address part of the physical address space, and TLPs can get lost and have to be
+//! retransmitted. [`DmaDevice`] allows such connectors to expose these properties in a uniform
+//! way, so they can be taken into account without knowing about the concrete device.
+//!
+//! The read limits are also reflected in the [`PhysicalMemoryMetadata`] of a device (see
+//! [`DmaLimits::constrain`]), which makes the
+//! [`ConstrainedPhysicalMemory`](crate::mem::ConstrainedPhysicalMemory) middleware split and
+//! align reads accordingly.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::connector::dma::DmaDevice;
+//! use memflow::error::Result;
+//! use memflow::mem::ConstrainedPhysicalMemory;
+//!
+//! fn open_constrained<T: DmaDevice>(device: T) -> Result<ConstrainedPhysicalMemory<T>> {
+//!     let info = device.info();
+//!     let limits = device.limits();
+//!     println!(
+//!         "{} ({:04x}:{:04x}), reads of up to {} bytes",
+//!         info.name, info.vendor_id, info.device_id, limits.max_read_request
+//!     );
+//!
+//!     Ok(ConstrainedPhysicalMemory::with_dma_limits(device, &limits))
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use core::time::Duration;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{PhysicalMemory, PhysicalMemoryMetadata};
+use crate::types::Address;
+
+/// Size of a PCIe DWORD, the granularity of TLP payloads.
+pub const PCIE_DWORD: u32 = 4;
+
+/// Transfer limits of a DMA device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DmaLimits {
+    /// Maximum number of bytes requested by a single read TLP (max read request size)
+    pub max_read_request: u32,
+    /// Maximum number of bytes transferred by a single write TLP (max payload size)
+    pub max_payload: u32,
+    /// Alignment of the address and size of TLPs
+    pub alignment: u32,
+    /// Highest physical address the device is able to access
+    pub max_address: Address,
+    /// Number of read requests the device keeps in flight at once
+    pub max_outstanding: u32,
+    /// Number of times a failed TLP is retransmitted before the access is reported as failed
+    pub retries: u32,
+    /// Time to wait before retransmitting a failed TLP
+    pub retry_delay: Duration,
+}
+
+impl Default for DmaLimits {
+    /// Returns the limits every PCIe device is able to handle.
+    fn default() -> Self {
+        Self {
+            max_read_request: 128,
+            max_payload: 128,
+            alignment: PCIE_DWORD,
+            max_address: Address::invalid(),
+            max_outstanding: 1,
+            retries: 0,
+            retry_delay: Duration::from_millis(0),
+        }
+    }
+}
+
+impl DmaLimits {
+    /// Restricts `metadata` to the limits of the device.
+    ///
+    /// Connectors should use this to compute the metadata they report, so the read limits are
+    /// enforced by [`ConstrainedPhysicalMemory`](crate::mem::ConstrainedPhysicalMemory).
+    pub fn constrain(&self, metadata: PhysicalMemoryMetadata) -> PhysicalMemoryMetadata {
+        let max_address = std::cmp::min(metadata.max_address, self.max_address);
+        PhysicalMemoryMetadata {
+            max_address,
+            real_size: std::cmp::min(metadata.real_size, max_address.to_umem().saturating_add(1)),
+            max_read_size: std::cmp::min(metadata.max_read_size, self.max_read_request),
+            read_alignment: std::cmp::max(metadata.read_alignment, self.alignment),
+            ..metadata
+        }
+    }
+}
+
+/// Information about a DMA device.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DmaDeviceInfo {
+    /// Name of the device, e.g. the board or firmware name
+    pub name: String,
+    /// PCI vendor id the device presents to the target
+    pub vendor_id: u16,
+    /// PCI device id the device presents to the target
+    pub device_id: u16,
+    /// Negotiated PCIe link width, `0` if unknown
+    pub link_width: u8,
+    /// Negotiated PCIe generation, `0` if unknown
+    pub link_generation: u8,
+}
+
+/// Options for opening a DMA device.
+///
+/// Options that are not set are left at the defaults of the device.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DmaConfig {
+    /// Device to open, e.g. a serial number or index, the first device if unset
+    pub device: Option<String>,
+    /// Maximum read request size to use, limited by the capabilities of the device
+    pub max_read_request: Option<u32>,
+    /// Number of retransmissions of failed TLPs
+    pub retries: Option<u32>,
+    /// Time to wait before retransmitting a failed TLP
+    pub retry_delay: Option<Duration>,
+}
+
+impl DmaConfig {
+    /// Parses the configuration from connector arguments.
+    ///
+    /// The arguments `device`, `max_read_request`, `retries` and `retry_delay` (in
+    /// milliseconds) are recognized, the default argument is used as `device`.
+    #[cfg(feature = "plugins")]
+    pub fn from_args(args: &crate::plugins::Args) -> Result<Self> {
+        fn parse<T: core::str::FromStr>(
+            args: &crate::plugins::Args,
+            key: &str,
+        ) -> Result<Option<T>> {
+            args.get(key)
+                .map(|value| {
+                    value.parse::<T>().map_err(|_| {
+                        Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                            .log_error(format!("invalid value for `{}`: {}", key, value))
+                    })
+                })
+                .transpose()
+        }
+
+        Ok(Self {
+            device: args
+                .get("device")
+                .or_else(|| args.get_default())
+                .map(str::to_string),
+            max_read_request: parse(args, "max_read_request")?,
+            retries: parse(args, "retries")?,
+            retry_delay: parse::<u64>(args, "retry_delay")?.map(Duration::from_millis),
+        })
+    }
+}
+
+/// A hardware DMA device exposing the physical memory of the target.
+pub trait DmaDevice: PhysicalMemory {
+    /// Opens the device described by `config`.
+    fn open(config: &DmaConfig) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Returns information about the device.
+    fn info(&self) -> DmaDeviceInfo;
+
+    /// Returns the current transfer limits of the device.
+    fn limits(&self) -> DmaLimits;
+
+    /// Configures the retransmission of failed TLPs.
+    ///
+    /// Devices that retransmit in firmware should override this function. By default an error
+    /// of kind [`ErrorKind::NotSupported`] is returned.
+    fn set_retransmission(&mut self, retries: u32, delay: Duration) -> Result<()> {
+        let _ = (retries, delay);
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_debug("device does not support configuring retransmissions"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    #[test]
+    fn constrain_metadata() {
+        let limits = DmaLimits {
+            max_read_request: 0x200,
+            max_address: Address::from(0xffff_ffffu64),
+            ..Default::default()
+        };
+
+        let mem = DummyMemory::new(size::mb(1));
+        let metadata = limits.constrain(mem.metadata());
+        assert_eq!(metadata.max_address, mem.metadata().max_address);
+        assert_eq!(metadata.max_read_size, 0x200);
+        assert_eq!(metadata.read_alignment, PCIE_DWORD);
+
+        let metadata = limits.constrain(PhysicalMemoryMetadata {
+            max_address: Address::from(0x1_ffff_ffffu64),
+            real_size: 0x2_0000_0000,
+            ..mem.metadata()
+        });
+        assert_eq!(metadata.max_address, Address::from(0xffff_ffffu64));
+        assert_eq!(metadata.real_size, 0x1_0000_0000);
+    }
+}
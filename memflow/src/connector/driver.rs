@@ -0,0 +1,785 @@
+//! Connector for physical memory served by a companion kernel driver.
+//!
+//! Reading physical memory of the local machine requires kernel privileges. This module defines
+//! an IOCTL based protocol a kernel driver can implement to serve physical memory to user mode,
+//! and the connector speaking it: [`DriverMemory`] issues the requests through a
+//! [`DriverChannel`], on Windows [`DeviceHandle`] sends them to the device object of the
+//! driver with `DeviceIoControl`. Reads and writes are batched, a single IOCTL transfers up to
+//! the maximum transfer size reported by the driver. The driver itself is not part of memflow,
+//! [`serve_ioctl`] is a reference implementation of the driver side of the protocol.
+//!
+//! # Protocol
+//!
+//! The driver exposes a device (by default [`DEFAULT_DEVICE`]) accepting the IOCTLs
+//! [`IOCTL_MF_QUERY_INFO`], [`IOCTL_MF_MEMORY_MAP`], [`IOCTL_MF_READ`] and [`IOCTL_MF_WRITE`].
+//! All values are stored in little endian.
+//!
+//! [`IOCTL_MF_QUERY_INFO`] takes no input and returns [`DRV_INFO_SIZE`] bytes:
+//!
+//! | Offset | Size | Description                                                  |
+//! |--------|------|--------------------------------------------------------------|
+//! | `0x00` | 4    | magic, [`DRV_MAGIC`]                                         |
+//! | `0x04` | 4    | version of the protocol, [`DRV_VERSION`]                     |
+//! | `0x08` | 4    | flags, [`DRV_FLAG_WRITE`] if writes are supported            |
+//! | `0x0c` | 4    | maximum number of bytes transferred by a single request      |
+//! | `0x10` | 8    | highest physical address                                     |
+//!
+//! [`IOCTL_MF_MEMORY_MAP`] takes no input and returns the number of physical memory ranges as
+//! `u32` (followed by 4 reserved bytes), and as many ranges as fit into the output buffer, each
+//! consisting of the base address and the size as `u64`. The count always reflects all ranges,
+//! so the request can be repeated with a larger buffer.
+//!
+//! [`IOCTL_MF_READ`] and [`IOCTL_MF_WRITE`] take a header consisting of the number of entries
+//! as `u32` (followed by 4 reserved bytes), followed by the entries:
+//!
+//! | Offset | Size | Description                          |
+//! |--------|------|--------------------------------------|
+//! | `0x00` | 8    | physical address                     |
+//! | `0x08` | 4    | length in bytes                      |
+//! | `0x0c` | 4    | reserved                             |
+//!
+//! The input of [`IOCTL_MF_WRITE`] is followed by the data of all entries. Both requests
+//! output a `u32` status per entry, [`DRV_STATUS_OK`] on success, padded to a multiple of 8
+//! bytes. The output of [`IOCTL_MF_READ`] is followed by the data of all entries. The sum of
+//! all lengths of a request never exceeds the maximum transfer size.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[cfg(windows)]
+//! # fn main() {
+//! use memflow::connector::driver::DriverMemory;
+//! use memflow::mem::{MemoryView, PhysicalMemory};
+//!
+//! let mut mem = DriverMemory::open().unwrap().readonly();
+//! println!("driver version {}", mem.driver_info().version);
+//! let value = mem.phys_view().read::<u64>(0x1000.into()).unwrap();
+//! # }
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemOps, MemoryMap, PhysicalMemory, PhysicalMemoryMapping,
+    PhysicalMemoryMappingCallback, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+use crate::cglue::*;
+
+/// Default path of the device object of the driver.
+pub const DEFAULT_DEVICE: &str = r"\\.\MemflowPhys";
+
+/// Magic number returned by [`IOCTL_MF_QUERY_INFO`] (`MFDR`).
+pub const DRV_MAGIC: u32 = 0x5244_464d;
+/// Version of the protocol implemented by this module.
+pub const DRV_VERSION: u32 = 1;
+/// The driver accepts [`IOCTL_MF_WRITE`] requests.
+pub const DRV_FLAG_WRITE: u32 = 1;
+/// Status of a successful entry.
+pub const DRV_STATUS_OK: u32 = 0;
+/// Status of a failed entry, drivers may return any other non zero status.
+pub const DRV_STATUS_FAILED: u32 = 1;
+
+/// Size of the output of [`IOCTL_MF_QUERY_INFO`].
+pub const DRV_INFO_SIZE: usize = 0x18;
+/// Size of the header of the variable length requests and outputs.
+pub const DRV_HEADER_SIZE: usize = 8;
+/// Size of an entry of [`IOCTL_MF_READ`] and [`IOCTL_MF_WRITE`].
+pub const DRV_ENTRY_SIZE: usize = 0x10;
+/// Size of a range returned by [`IOCTL_MF_MEMORY_MAP`].
+pub const DRV_RANGE_SIZE: usize = 0x10;
+
+/// Maximum number of entries the connector puts into a single request.
+pub const MAX_BATCH_ENTRIES: usize = 256;
+
+/// Maximum transfer size used by [`serve_ioctl`].
+pub const SERVE_MAX_TRANSFER: u32 = 0x10_0000;
+
+const FILE_DEVICE_UNKNOWN: u32 = 0x22;
+const METHOD_BUFFERED: u32 = 0;
+const METHOD_OUT_DIRECT: u32 = 2;
+const FILE_ANY_ACCESS: u32 = 0;
+
+/// Builds an IOCTL code like the `CTL_CODE` macro of the Windows DDK.
+const fn ctl_code(function: u32, method: u32) -> u32 {
+    (FILE_DEVICE_UNKNOWN << 16) | (FILE_ANY_ACCESS << 14) | (function << 2) | method
+}
+
+/// Queries the driver information.
+pub const IOCTL_MF_QUERY_INFO: u32 = ctl_code(0x800, METHOD_BUFFERED);
+/// Enumerates the physical memory ranges.
+pub const IOCTL_MF_MEMORY_MAP: u32 = ctl_code(0x801, METHOD_BUFFERED);
+/// Reads physical memory.
+pub const IOCTL_MF_READ: u32 = ctl_code(0x802, METHOD_OUT_DIRECT);
+/// Writes physical memory.
+pub const IOCTL_MF_WRITE: u32 = ctl_code(0x803, METHOD_BUFFERED);
+
+/// Channel delivering IOCTLs to the driver.
+pub trait DriverChannel: Send {
+    /// Sends the request `code` with `input` to the driver.
+    ///
+    /// Returns the number of bytes the driver stored in `output`.
+    fn ioctl(&mut self, code: u32, input: &[u8], output: &mut [u8]) -> Result<usize>;
+}
+
+/// Information reported by the driver.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DriverInfo {
+    /// Version of the protocol
+    pub version: u32,
+    /// Feature flags, see [`DRV_FLAG_WRITE`]
+    pub flags: u32,
+    /// Maximum number of bytes transferred by a single request
+    pub max_transfer: u32,
+    /// Highest physical address
+    pub max_address: Address,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// Size of the status array of a request with `count` entries.
+fn status_size(count: usize) -> usize {
+    (count * 4 + 7) & !7
+}
+
+fn invalid_response(msg: &str) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg)
+}
+
+/// Part of an access transferred by a single entry.
+struct Piece {
+    /// Index of the access
+    op: usize,
+    addr: u64,
+    /// Offset of the piece in the buffer of the access
+    offset: usize,
+    len: usize,
+}
+
+/// Splits accesses into requests respecting the maximum transfer size.
+fn batches(ops: impl Iterator<Item = (Address, usize)>, max_transfer: usize) -> Vec<Vec<Piece>> {
+    let mut ret = vec![];
+    let mut batch = vec![];
+    let mut batch_len = 0;
+
+    for (op, (addr, len)) in ops.enumerate() {
+        let mut offset = 0;
+        while offset < len {
+            let piece_len = std::cmp::min(len - offset, max_transfer);
+            if batch_len + piece_len > max_transfer || batch.len() == MAX_BATCH_ENTRIES {
+                ret.push(std::mem::take(&mut batch));
+                batch_len = 0;
+            }
+            batch.push(Piece {
+                op,
+                addr: addr.to_umem() as u64 + offset as u64,
+                offset,
+                len: piece_len,
+            });
+            batch_len += piece_len;
+            offset += piece_len;
+        }
+    }
+
+    if !batch.is_empty() {
+        ret.push(batch);
+    }
+    ret
+}
+
+/// Encodes the header and entries of a read or write request.
+fn encode_entries(batch: &[Piece]) -> Vec<u8> {
+    let mut input = Vec::with_capacity(DRV_HEADER_SIZE + batch.len() * DRV_ENTRY_SIZE);
+    input.extend_from_slice(&(batch.len() as u32).to_le_bytes());
+    input.extend_from_slice(&[0; 4]);
+    for piece in batch {
+        input.extend_from_slice(&piece.addr.to_le_bytes());
+        input.extend_from_slice(&(piece.len as u32).to_le_bytes());
+        input.extend_from_slice(&[0; 4]);
+    }
+    input
+}
+
+/// Physical memory served by a kernel driver.
+#[derive(Clone)]
+pub struct DriverMemory<C> {
+    channel: C,
+    info: DriverInfo,
+    mem_map: MemoryMap<(Address, umem)>,
+    readonly: bool,
+}
+
+#[cfg(windows)]
+impl DriverMemory<DeviceHandle> {
+    /// Opens the driver at [`DEFAULT_DEVICE`].
+    pub fn open() -> Result<Self> {
+        Self::new(DeviceHandle::open(DEFAULT_DEVICE)?)
+    }
+}
+
+impl<C: DriverChannel> DriverMemory<C> {
+    /// Creates a connector talking to the driver through `channel`.
+    ///
+    /// Queries the driver information and the physical memory map, fails if the driver does
+    /// not implement a compatible protocol version.
+    pub fn new(mut channel: C) -> Result<Self> {
+        let mut out = [0u8; DRV_INFO_SIZE];
+        let len = channel.ioctl(IOCTL_MF_QUERY_INFO, &[], &mut out)?;
+        if len < DRV_INFO_SIZE || read_u32(&out, 0) != DRV_MAGIC {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                .log_error("device is not a memflow driver"));
+        }
+
+        let info = DriverInfo {
+            version: read_u32(&out, 4),
+            flags: read_u32(&out, 8),
+            max_transfer: read_u32(&out, 12),
+            max_address: Address::from(read_u64(&out, 16)),
+        };
+        if info.version != DRV_VERSION {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::VersionMismatch).log_error(format!(
+                    "driver implements protocol version {}, expected {}",
+                    info.version, DRV_VERSION
+                )),
+            );
+        }
+        if info.max_transfer == 0 {
+            return Err(invalid_response(
+                "driver reported a maximum transfer size of 0",
+            ));
+        }
+
+        let mut mem_map = MemoryMap::new();
+        for (base, size) in Self::query_memory_map(&mut channel)? {
+            mem_map.push_remap(base.into(), size as umem, base.into());
+        }
+        if mem_map.is_empty() {
+            let size = info.max_address.to_umem().saturating_add(1);
+            mem_map.push_remap(Address::null(), size, Address::null());
+        }
+
+        Ok(Self {
+            channel,
+            readonly: info.flags & DRV_FLAG_WRITE == 0,
+            info,
+            mem_map,
+        })
+    }
+
+    fn query_memory_map(channel: &mut C) -> Result<Vec<(u64, u64)>> {
+        let mut out = vec![0u8; DRV_HEADER_SIZE + 16 * DRV_RANGE_SIZE];
+        loop {
+            let len = channel.ioctl(IOCTL_MF_MEMORY_MAP, &[], &mut out)?;
+            if len < DRV_HEADER_SIZE {
+                return Err(invalid_response("invalid memory map returned by driver"));
+            }
+
+            let count = read_u32(&out, 0) as usize;
+            let required = DRV_HEADER_SIZE + count * DRV_RANGE_SIZE;
+            if required > out.len() {
+                out.resize(required, 0);
+                continue;
+            }
+            if len < required {
+                return Err(invalid_response(
+                    "memory map returned by driver is truncated",
+                ));
+            }
+
+            return Ok((0..count)
+                .map(|i| DRV_HEADER_SIZE + i * DRV_RANGE_SIZE)
+                .map(|off| (read_u64(&out, off), read_u64(&out, off + 8)))
+                .filter(|&(_, size)| size > 0)
+                .collect());
+        }
+    }
+
+    /// Rejects all writes to physical memory.
+    pub fn readonly(mut self) -> Self {
+        self.readonly = true;
+        self
+    }
+
+    /// Returns the information reported by the driver.
+    pub fn driver_info(&self) -> DriverInfo {
+        self.info
+    }
+
+    /// Consumes self and returns the channel.
+    pub fn into_inner(self) -> C {
+        self.channel
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<C: DriverChannel> PhysicalMemory for DriverMemory<C> {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let channel = &mut self.channel;
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+        let mut ops = (&mut iter).collect::<Vec<_>>();
+        let mut failed = vec![false; ops.len()];
+
+        let pieces = ops
+            .iter()
+            .map(|CTup3((addr, _), _, buf)| (*addr, buf.len()));
+        for batch in batches(pieces, self.info.max_transfer as usize) {
+            let statuses = status_size(batch.len());
+            let total = batch.iter().map(|p| p.len).sum::<usize>();
+            let mut out = vec![0u8; statuses + total];
+
+            let len = channel.ioctl(IOCTL_MF_READ, &encode_entries(&batch), &mut out)?;
+            if len < statuses {
+                return Err(invalid_response(
+                    "read response returned by driver is truncated",
+                ));
+            }
+
+            let mut pos = statuses;
+            for (i, piece) in batch.iter().enumerate() {
+                if read_u32(&out, i * 4) == DRV_STATUS_OK && pos + piece.len <= len {
+                    let CTup3(_, _, buf) = &mut ops[piece.op];
+                    buf[piece.offset..piece.offset + piece.len]
+                        .copy_from_slice(&out[pos..pos + piece.len]);
+                } else {
+                    failed[piece.op] = true;
+                }
+                pos += piece.len;
+            }
+        }
+
+        for (CTup3(_, meta_addr, buf), failed) in ops.into_iter().zip(failed) {
+            if failed {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        if self.readonly {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("physical memory is opened read only"));
+        }
+
+        let channel = &mut self.channel;
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+        let ops = (&mut iter).collect::<Vec<_>>();
+        let mut failed = vec![false; ops.len()];
+
+        let pieces = ops
+            .iter()
+            .map(|CTup3((addr, _), _, buf)| (*addr, buf.len()));
+        for batch in batches(pieces, self.info.max_transfer as usize) {
+            let mut input = encode_entries(&batch);
+            for piece in batch.iter() {
+                let CTup3(_, _, buf) = &ops[piece.op];
+                input.extend_from_slice(&buf[piece.offset..piece.offset + piece.len]);
+            }
+
+            let mut out = vec![0u8; status_size(batch.len())];
+            let len = channel.ioctl(IOCTL_MF_WRITE, &input, &mut out)?;
+            if len < batch.len() * 4 {
+                return Err(invalid_response(
+                    "write response returned by driver is truncated",
+                ));
+            }
+
+            for (i, piece) in batch.iter().enumerate() {
+                if read_u32(&out, i * 4) != DRV_STATUS_OK {
+                    failed[piece.op] = true;
+                }
+            }
+        }
+
+        for (CTup3(_, meta_addr, buf), failed) in ops.into_iter().zip(failed) {
+            if failed {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: self.readonly,
+            ideal_batch_size: MAX_BATCH_ENTRIES as u32,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        for map in self.mem_map.iter() {
+            let (real_base, size) = *map.output();
+            let mapping = PhysicalMemoryMapping {
+                base: map.base(),
+                size,
+                real_base,
+            };
+            if !out.call(mapping) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(DriverMemory<C>, crate::plugins::ConnectorInstance, {});
+
+/// Handles a request of the protocol by accessing `mem`.
+///
+/// This is a reference implementation of the driver side, it answers the request `code` with
+/// `input` and returns the number of bytes stored in `output`.
+pub fn serve_ioctl(
+    mem: &mut impl PhysicalMemory,
+    code: u32,
+    input: &[u8],
+    output: &mut [u8],
+) -> Result<usize> {
+    let metadata = mem.metadata();
+    let too_small =
+        || Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument).log_error("buffer too small");
+
+    match code {
+        IOCTL_MF_QUERY_INFO => {
+            let out = output.get_mut(..DRV_INFO_SIZE).ok_or_else(too_small)?;
+            let flags = if metadata.readonly { 0 } else { DRV_FLAG_WRITE };
+            out[0..4].copy_from_slice(&DRV_MAGIC.to_le_bytes());
+            out[4..8].copy_from_slice(&DRV_VERSION.to_le_bytes());
+            out[8..12].copy_from_slice(&flags.to_le_bytes());
+            out[12..16].copy_from_slice(&SERVE_MAX_TRANSFER.to_le_bytes());
+            out[16..24].copy_from_slice(&(metadata.max_address.to_umem() as u64).to_le_bytes());
+            Ok(DRV_INFO_SIZE)
+        }
+        IOCTL_MF_MEMORY_MAP => {
+            let mut ranges: Vec<PhysicalMemoryMapping> = vec![];
+            mem.phys_mem_map((&mut ranges).into());
+
+            let out = output.get_mut(..DRV_HEADER_SIZE).ok_or_else(too_small)?;
+            out[0..4].copy_from_slice(&(ranges.len() as u32).to_le_bytes());
+            out[4..8].copy_from_slice(&[0; 4]);
+
+            let fitting = std::cmp::min(
+                ranges.len(),
+                (output.len() - DRV_HEADER_SIZE) / DRV_RANGE_SIZE,
+            );
+            for (i, range) in ranges.iter().take(fitting).enumerate() {
+                let off = DRV_HEADER_SIZE + i * DRV_RANGE_SIZE;
+                output[off..off + 8].copy_from_slice(&(range.base.to_umem() as u64).to_le_bytes());
+                output[off + 8..off + 16].copy_from_slice(&(range.size as u64).to_le_bytes());
+            }
+            Ok(DRV_HEADER_SIZE + fitting * DRV_RANGE_SIZE)
+        }
+        IOCTL_MF_READ | IOCTL_MF_WRITE => {
+            if input.len() < DRV_HEADER_SIZE {
+                return Err(too_small());
+            }
+            let count = read_u32(input, 0) as usize;
+            let entries_end = DRV_HEADER_SIZE + count * DRV_ENTRY_SIZE;
+            let entries = input
+                .get(DRV_HEADER_SIZE..entries_end)
+                .ok_or_else(too_small)?;
+            let entries = entries
+                .chunks_exact(DRV_ENTRY_SIZE)
+                .map(|e| (read_u64(e, 0), read_u32(e, 8) as usize))
+                .collect::<Vec<_>>();
+
+            let total = entries.iter().map(|&(_, len)| len).sum::<usize>();
+            if total > SERVE_MAX_TRANSFER as usize {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                    .log_error("request exceeds the maximum transfer size"));
+            }
+
+            let statuses = status_size(count);
+            let mut pos = 0;
+
+            if code == IOCTL_MF_READ {
+                if output.len() < statuses + total {
+                    return Err(too_small());
+                }
+                for (i, &(addr, len)) in entries.iter().enumerate() {
+                    let off = statuses + pos;
+                    let ok = serve_read(mem, addr, &mut output[off..off + len])?;
+                    set_status(output, i, ok);
+                    pos += len;
+                }
+                Ok(statuses + total)
+            } else {
+                let data = input
+                    .get(entries_end..entries_end + total)
+                    .ok_or_else(too_small)?;
+                if output.len() < statuses {
+                    return Err(too_small());
+                }
+                for (i, &(addr, len)) in entries.iter().enumerate() {
+                    let ok = !metadata.readonly && serve_write(mem, addr, &data[pos..pos + len])?;
+                    set_status(output, i, ok);
+                    pos += len;
+                }
+                Ok(statuses)
+            }
+        }
+        _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_error(format!("unknown ioctl {:#x}", code))),
+    }
+}
+
+fn set_status(output: &mut [u8], entry: usize, ok: bool) {
+    let value = if ok { DRV_STATUS_OK } else { DRV_STATUS_FAILED };
+    output[entry * 4..entry * 4 + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn serve_read(mem: &mut impl PhysicalMemory, addr: u64, buf: &mut [u8]) -> Result<bool> {
+    let mut ok = true;
+    MemOps::with(
+        std::iter::once((PhysicalAddress::from(addr), CSliceMut::from(buf))),
+        None,
+        Some(
+            &mut (&mut |_| {
+                ok = false;
+                true
+            })
+                .into(),
+        ),
+        |data| mem.phys_read_raw_iter(data),
+    )?;
+    Ok(ok)
+}
+
+fn serve_write(mem: &mut impl PhysicalMemory, addr: u64, buf: &[u8]) -> Result<bool> {
+    let mut ok = true;
+    MemOps::with(
+        std::iter::once((PhysicalAddress::from(addr), CSliceRef::from(buf))),
+        None,
+        Some(
+            &mut (&mut |_| {
+                ok = false;
+                true
+            })
+                .into(),
+        ),
+        |data| mem.phys_write_raw_iter(data),
+    )?;
+    Ok(ok)
+}
+
+#[cfg(windows)]
+pub use self::windows::DeviceHandle;
+
+#[cfg(windows)]
+mod windows {
+    use std::prelude::v1::*;
+
+    use std::ffi::c_void;
+    use std::sync::Arc;
+
+    use super::DriverChannel;
+    use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+    const GENERIC_READ: u32 = 0x8000_0000;
+    const GENERIC_WRITE: u32 = 0x4000_0000;
+    const FILE_SHARE_READ: u32 = 1;
+    const FILE_SHARE_WRITE: u32 = 2;
+    const OPEN_EXISTING: u32 = 3;
+    const INVALID_HANDLE_VALUE: *mut c_void = !0 as *mut c_void;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileW(
+            name: *const u16,
+            access: u32,
+            share_mode: u32,
+            security_attributes: *mut c_void,
+            creation_disposition: u32,
+            flags: u32,
+            template: *mut c_void,
+        ) -> *mut c_void;
+        fn DeviceIoControl(
+            device: *mut c_void,
+            code: u32,
+            input: *const c_void,
+            input_size: u32,
+            output: *mut c_void,
+            output_size: u32,
+            returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+        fn GetLastError() -> u32;
+    }
+
+    struct RawHandle(*mut c_void);
+
+    impl Drop for RawHandle {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Handle to the device object of the driver, shared between clones.
+    #[derive(Clone)]
+    pub struct DeviceHandle(Arc<RawHandle>);
+
+    // device handles can be used from any thread
+    unsafe impl Send for DeviceHandle {}
+    unsafe impl Sync for DeviceHandle {}
+
+    impl DeviceHandle {
+        /// Opens the device at `path`, e.g. `\\.\MemflowPhys`.
+        pub fn open(path: &str) -> Result<Self> {
+            let name = path.encode_utf16().chain(Some(0)).collect::<Vec<_>>();
+            let handle = unsafe {
+                CreateFileW(
+                    name.as_ptr(),
+                    GENERIC_READ | GENERIC_WRITE,
+                    FILE_SHARE_READ | FILE_SHARE_WRITE,
+                    std::ptr::null_mut(),
+                    OPEN_EXISTING,
+                    0,
+                    std::ptr::null_mut(),
+                )
+            };
+            if handle == INVALID_HANDLE_VALUE {
+                let err = unsafe { GetLastError() };
+                return Err(
+                    Error(ErrorOrigin::Connector, ErrorKind::TargetNotFound).log_error(format!(
+                        "unable to open {} (error {}), is the driver loaded?",
+                        path, err
+                    )),
+                );
+            }
+            Ok(Self(Arc::new(RawHandle(handle))))
+        }
+    }
+
+    impl DriverChannel for DeviceHandle {
+        fn ioctl(&mut self, code: u32, input: &[u8], output: &mut [u8]) -> Result<usize> {
+            let mut returned = 0;
+            let ok = unsafe {
+                DeviceIoControl(
+                    (self.0).0,
+                    code,
+                    input.as_ptr() as *const c_void,
+                    input.len() as u32,
+                    output.as_mut_ptr() as *mut c_void,
+                    output.len() as u32,
+                    &mut returned,
+                    std::ptr::null_mut(),
+                )
+            };
+            if ok == 0 {
+                let err = unsafe { GetLastError() };
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)
+                    .log_error(format!("ioctl {:#x} failed with error {}", code, err)));
+            }
+            Ok(returned as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    /// Serves requests from memory with two ranges and a hole in between.
+    #[derive(Clone)]
+    struct Loopback {
+        mem: DummyMemory,
+        ioctls: usize,
+    }
+
+    impl DriverChannel for Loopback {
+        fn ioctl(&mut self, code: u32, input: &[u8], output: &mut [u8]) -> Result<usize> {
+            self.ioctls += 1;
+            serve_ioctl(&mut self.mem, code, input, output)
+        }
+    }
+
+    fn connect() -> DriverMemory<Loopback> {
+        let mut mem = DummyMemory::new(size::mb(4));
+        mem.set_mem_map(&[
+            PhysicalMemoryMapping {
+                base: Address::null(),
+                size: size::mb(1) as umem,
+                real_base: Address::null(),
+            },
+            PhysicalMemoryMapping {
+                base: Address::from(size::mb(2) as u64),
+                size: size::mb(2) as umem,
+                real_base: Address::from(size::mb(2) as u64),
+            },
+        ]);
+        DriverMemory::new(Loopback { mem, ioctls: 0 }).unwrap()
+    }
+
+    #[test]
+    fn memory_map() {
+        let mem = connect();
+        assert_eq!(mem.driver_info().version, DRV_VERSION);
+        assert!(!mem.metadata().readonly);
+
+        let map = mem.phys_mem_map_vec();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[1].base, Address::from(size::mb(2) as u64));
+        assert_eq!(mem.metadata().real_size, size::mb(3) as umem);
+    }
+
+    #[test]
+    fn read_write() {
+        let mut mem = connect();
+
+        // larger than the maximum transfer size
+        let data = (0..SERVE_MAX_TRANSFER as usize + 0x123)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let addr = Address::from(size::mb(2) as u64 + 0x10);
+        mem.phys_write(addr, data.as_slice()).unwrap();
+
+        let ioctls = mem.channel.ioctls;
+        assert_eq!(mem.phys_view().read_raw(addr, data.len()).unwrap(), data);
+        assert_eq!(mem.channel.ioctls, ioctls + 2);
+
+        // the hole between the ranges
+        assert!(mem
+            .phys_view()
+            .read::<u64>(Address::from(size::mb(1) as u64 + 8))
+            .is_err());
+
+        let mut mem = mem.readonly();
+        assert!(mem.phys_write(addr, &0u64).is_err());
+    }
+
+    #[test]
+    fn not_a_driver() {
+        struct Empty;
+        impl DriverChannel for Empty {
+            fn ioctl(&mut self, _code: u32, _input: &[u8], _output: &mut [u8]) -> Result<usize> {
+                Ok(0)
+            }
+        }
+        assert!(DriverMemory::new(Empty).is_err());
+    }
+}
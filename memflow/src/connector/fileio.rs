@@ -2,8 +2,8 @@
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::mem::{
-    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
 };
 use crate::types::{umem, Address};
 
@@ -210,6 +210,22 @@ impl<T: Seek + Read + Write + Send> PhysicalMemory for FileIoMemory<T> {
             real_size: self.mem_map.real_size(),
             readonly: false,
             ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        for map in self.mem_map.iter() {
+            let (real_base, size) = *map.output();
+            let mapping = PhysicalMemoryMapping {
+                base: map.base(),
+                size,
+                real_base,
+            };
+            if !out.call(mapping) {
+                break;
+            }
         }
     }
 }
@@ -219,3 +235,37 @@ cglue_impl_group!(
     crate::plugins::ConnectorInstance,
     {}
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn mem_map_holes() {
+        let mut mem_map = MemoryMap::new();
+        mem_map.push_remap(0x0.into(), 0x1000, 0x0.into());
+        mem_map.push_remap(0x3000.into(), 0x2000, 0x1000.into());
+        let mem = FileIoMemory::with_mem_map(Cursor::new(vec![0u8; 0x3000]), mem_map).unwrap();
+
+        let ranges = mem
+            .phys_mem_map_vec()
+            .iter()
+            .map(|m| (m.base, m.size, m.real_base))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![
+                (Address::from(0x0u64), 0x1000, Address::from(0x0u64)),
+                (Address::from(0x3000u64), 0x2000, Address::from(0x1000u64)),
+            ]
+        );
+
+        assert_eq!(mem.metadata().max_address, Address::from(0x4fffu64));
+        assert_eq!(mem.phys_backed_size(Address::null(), 0x5000), 0x3000);
+        assert_eq!(
+            mem.phys_backed_size(Address::from(0x800u64), 0x3000),
+            0x1000
+        );
+    }
+}
@@ -0,0 +1,884 @@
+//! Connector for FPGA and USB3380 based PCIe DMA boards.
+//!
+//! DMA boards sit on the PCIe bus of the target and read its memory by issuing memory read
+//! requests (`MRd` TLPs), which the root complex answers with completions (`CplD` TLPs). The
+//! host talks to the board over USB, the framing of TLPs on the USB link differs between board
+//! families and is implemented by a [`FpgaTransport`]. [`FpgaMemory`] implements everything
+//! above it: splitting accesses into TLPs according to the limits of the device, matching
+//! completions to requests, retransmitting lost requests and reporting the device through the
+//! [`DmaDevice`] interface.
+//!
+//! No transport is shipped with memflow, the USB protocols of FT601 and USB3380 boards have to
+//! be implemented on top of the respective vendor libraries by the user of this module.
+//!
+//! Supported boards are detected by their USB ids, see [`KNOWN_BOARDS`] and
+//! [`detect_devices`]. Detection is only implemented on Linux.
+//!
+//! # TLP representation
+//!
+//! TLPs are passed to and from the transport as DWORDs. Header DWORDs hold the header fields as
+//! laid out in the PCIe specification (bit 31 being the most significant bit of the first byte
+//! on the wire), payload DWORDs hold four bytes of memory in little endian order.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::connector::fpga::{FpgaMemory, FpgaTransport};
+//! use memflow::error::Result;
+//! use memflow::mem::{MemoryView, PhysicalMemory, ThrottledMemory};
+//!
+//! fn open<T: FpgaTransport>(transport: T) -> Result<()> {
+//!     let mem = FpgaMemory::new(transport)?;
+//!     let (major, minor) = mem.firmware_version();
+//!     println!("firmware v{}.{}", major, minor);
+//!
+//!     // reads are split into TLPs by the connector, throttling protects the target
+//!     let mut mem = ThrottledMemory::builder(mem)
+//!         .bytes_per_sec(64 * 1024 * 1024)
+//!         .build()?;
+//!     let _ = mem.phys_view().read::<u64>(0x1000.into());
+//!     Ok(())
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::dma::{DmaConfig, DmaDevice, DmaDeviceInfo, DmaLimits, PCIE_DWORD};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use crate::cglue::*;
+
+/// Default highest physical address accessed, 1 TiB.
+pub const DEFAULT_MAX_ADDRESS: umem = 0xff_ffff_ffff;
+
+/// Default time to wait for the completions of a batch of read requests.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Requests must not cross a 4 KiB boundary.
+const TLP_BOUNDARY: umem = 0x1000;
+
+/// Largest payload a TLP can carry.
+const MAX_TLP_PAYLOAD: u32 = 0x1000;
+
+/// Family of a DMA board, which defines the USB protocol used to talk to it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum FpgaBoardKind {
+    /// FPGA boards connected through a FTDI FT601 USB3 bridge
+    Ft601,
+    /// Boards based on the PLX/Broadcom USB3380 bridge
+    Usb3380,
+}
+
+/// USB ids of a supported board.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KnownBoard {
+    /// USB vendor id
+    pub vendor_id: u16,
+    /// USB product id
+    pub product_id: u16,
+    /// Family of the board
+    pub kind: FpgaBoardKind,
+    /// Human readable name
+    pub name: &'static str,
+}
+
+/// All boards recognized by [`detect_devices`].
+pub const KNOWN_BOARDS: &[KnownBoard] = &[
+    KnownBoard {
+        vendor_id: 0x0403,
+        product_id: 0x601f,
+        kind: FpgaBoardKind::Ft601,
+        name: "FT601 FPGA board",
+    },
+    KnownBoard {
+        vendor_id: 0x18d1,
+        product_id: 0x9001,
+        kind: FpgaBoardKind::Usb3380,
+        name: "USB3380",
+    },
+];
+
+/// A board found on the USB bus.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DetectedDevice {
+    /// Family of the board
+    pub kind: FpgaBoardKind,
+    /// Human readable name
+    pub name: String,
+    /// USB serial number, if reported by the device
+    pub serial: Option<String>,
+    /// Location of the device on the USB bus, e.g. `2-1.4`
+    pub location: String,
+}
+
+/// Lists all supported boards connected to the system.
+///
+/// Devices are detected through sysfs, on other platforms an error of kind
+/// [`ErrorKind::NotSupported`] is returned.
+pub fn detect_devices() -> Result<Vec<DetectedDevice>> {
+    if cfg!(target_os = "linux") {
+        detect_devices_in(Path::new("/sys/bus/usb/devices"))
+    } else {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_error("device detection is only supported on linux"))
+    }
+}
+
+/// Lists all supported boards in a sysfs style usb device directory.
+fn detect_devices_in(root: &Path) -> Result<Vec<DetectedDevice>> {
+    let entries = std::fs::read_dir(root)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadDir).log_error(err))?;
+
+    let read_attr = |dir: &Path, name: &str| {
+        std::fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+    let read_id = |dir: &Path, name: &str| {
+        read_attr(dir, name).and_then(|s| u16::from_str_radix(&s, 16).ok())
+    };
+
+    let mut ret = vec![];
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let (vendor_id, product_id) = match (read_id(&dir, "idVendor"), read_id(&dir, "idProduct"))
+        {
+            (Some(vendor_id), Some(product_id)) => (vendor_id, product_id),
+            _ => continue,
+        };
+
+        if let Some(board) = KNOWN_BOARDS
+            .iter()
+            .find(|b| b.vendor_id == vendor_id && b.product_id == product_id)
+        {
+            ret.push(DetectedDevice {
+                kind: board.kind,
+                name: board.name.to_string(),
+                serial: read_attr(&dir, "serial"),
+                location: entry.file_name().to_string_lossy().into_owned(),
+            });
+        }
+    }
+
+    ret.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(ret)
+}
+
+/// Identification of a board as reported by its firmware.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FpgaDeviceInfo {
+    /// Major version of the firmware
+    pub firmware_major: u8,
+    /// Minor version of the firmware
+    pub firmware_minor: u8,
+    /// PCIe requester id (bus, device and function) assigned to the board
+    pub requester_id: u16,
+    /// PCI vendor id the board presents to the target
+    pub vendor_id: u16,
+    /// PCI device id the board presents to the target
+    pub device_id: u16,
+}
+
+/// USB link to a DMA board, transferring TLPs.
+pub trait FpgaTransport: Send {
+    /// Returns true if the transport is able to talk to boards of the family `kind`.
+    fn supports(kind: FpgaBoardKind) -> bool
+    where
+        Self: Sized;
+
+    /// Opens the detected board `device`.
+    fn open(device: &DetectedDevice) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Returns the family of the board.
+    fn kind(&self) -> FpgaBoardKind;
+
+    /// Queries the firmware of the board.
+    fn device_info(&mut self) -> Result<FpgaDeviceInfo>;
+
+    /// Sends `tlps` to the target.
+    fn send_tlps(&mut self, tlps: &[Vec<u32>]) -> Result<()>;
+
+    /// Receives TLPs from the target, waiting at most `timeout` for the first one.
+    ///
+    /// Returns an empty list if no TLP has been received in time.
+    fn recv_tlps(&mut self, timeout: Duration) -> Result<Vec<Vec<u32>>>;
+}
+
+/// Builds a memory read request for `len` bytes at the DWORD aligned address `addr`.
+///
+/// `len` has to be a non zero multiple of 4, not larger than 4096 bytes.
+pub fn mrd_tlp(requester_id: u16, tag: u8, addr: Address, len: usize) -> Vec<u32> {
+    let dwords = (len / 4) as u32;
+    let last_be = if dwords > 1 { 0xf } else { 0 };
+    request_tlp(
+        0x00,
+        dwords,
+        requester_id,
+        tag,
+        0xf,
+        last_be,
+        addr.to_umem() as u64,
+    )
+}
+
+/// Builds a memory write request writing `data` to `addr`.
+///
+/// The write may be unaligned, `data` must not cross a 4 KiB boundary and must not be larger
+/// than 4096 bytes.
+pub fn mwr_tlp(requester_id: u16, tag: u8, addr: Address, data: &[u8]) -> Vec<u32> {
+    let addr = addr.to_umem() as u64;
+    let start = (addr & 3) as usize;
+    let dwords = ((start + data.len() + 3) / 4) as u32;
+    let end_pad = dwords as usize * 4 - start - data.len();
+
+    let mut first_be = (0xf << start) & 0xf;
+    let mut last_be = 0xf >> end_pad;
+    if dwords == 1 {
+        first_be &= last_be;
+        last_be = 0;
+    }
+
+    let mut payload = vec![0u8; dwords as usize * 4];
+    payload[start..start + data.len()].copy_from_slice(data);
+
+    let mut tlp = request_tlp(0x40, dwords, requester_id, tag, first_be, last_be, addr);
+    tlp.extend(
+        payload
+            .chunks_exact(4)
+            .map(|dw| u32::from_le_bytes([dw[0], dw[1], dw[2], dw[3]])),
+    );
+    tlp
+}
+
+/// Builds the header of a memory request, `fmt_type` being the 3 DWORD variant.
+fn request_tlp(
+    fmt_type: u32,
+    dwords: u32,
+    requester_id: u16,
+    tag: u8,
+    first_be: u32,
+    last_be: u32,
+    addr: u64,
+) -> Vec<u32> {
+    let dw1 = (requester_id as u32) << 16 | (tag as u32) << 8 | last_be << 4 | first_be;
+    // a length of 1024 DWORDs is encoded as 0
+    let length = dwords & 0x3ff;
+
+    // addresses below 4 GiB have to use the 3 DWORD header
+    if addr >> 32 == 0 {
+        vec![fmt_type << 24 | length, dw1, addr as u32 & !3]
+    } else {
+        vec![
+            (fmt_type | 0x20) << 24 | length,
+            dw1,
+            (addr >> 32) as u32,
+            addr as u32 & !3,
+        ]
+    }
+}
+
+/// A completion received from the target.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Completion {
+    /// Tag of the request
+    pub tag: u8,
+    /// Completion status, `0` for a successful completion
+    pub status: u8,
+    /// Number of bytes remaining for the request, including this completion
+    pub byte_count: usize,
+    /// Lower 7 bits of the address of the first byte of this completion
+    pub lower_address: u8,
+    /// Payload of the completion
+    pub data: Vec<u8>,
+}
+
+impl Completion {
+    /// Parses a `Cpl` or `CplD` TLP, returns `None` for all other TLPs.
+    pub fn parse(tlp: &[u32]) -> Option<Self> {
+        if tlp.len() < 3 {
+            return None;
+        }
+
+        let fmt_type = tlp[0] >> 24;
+        let has_data = match fmt_type {
+            0x0a => false,
+            0x4a => true,
+            _ => return None,
+        };
+
+        let byte_count = match tlp[1] & 0xfff {
+            0 => 0x1000,
+            n => n as usize,
+        };
+
+        let data = if has_data {
+            let length = match tlp[0] & 0x3ff {
+                0 => 0x400,
+                n => n as usize,
+            };
+            tlp[3..]
+                .iter()
+                .take(length)
+                .flat_map(|dw| dw.to_le_bytes())
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Some(Self {
+            tag: (tlp[2] >> 8) as u8,
+            status: ((tlp[1] >> 13) & 0x7) as u8,
+            byte_count,
+            lower_address: (tlp[2] & 0x7f) as u8,
+            data,
+        })
+    }
+}
+
+/// A read request in flight.
+struct PendingRead {
+    /// Index of the destination buffer
+    buf: usize,
+    /// Offset of the request in the destination buffer
+    offset: usize,
+    addr: Address,
+    len: usize,
+    received: usize,
+    failed: bool,
+}
+
+/// Physical memory of the target accessed through a PCIe DMA board.
+pub struct FpgaMemory<T> {
+    transport: T,
+    info: FpgaDeviceInfo,
+    limits: DmaLimits,
+    timeout: Duration,
+}
+
+impl<T: FpgaTransport> FpgaMemory<T> {
+    /// Creates a connector talking to a board through `transport`.
+    pub fn new(mut transport: T) -> Result<Self> {
+        let info = transport.device_info()?;
+        let limits = DmaLimits {
+            max_read_request: MAX_TLP_PAYLOAD,
+            max_payload: 128,
+            alignment: PCIE_DWORD,
+            max_address: Address::from(DEFAULT_MAX_ADDRESS),
+            max_outstanding: 32,
+            retries: 1,
+            retry_delay: Duration::from_millis(0),
+        };
+
+        Ok(Self {
+            transport,
+            info,
+            limits,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets the maximum read request size, limited to 4096 bytes.
+    pub fn max_read_request(mut self, max_read_request: u32) -> Self {
+        self.limits.max_read_request = max_read_request.clamp(PCIE_DWORD, MAX_TLP_PAYLOAD) & !3;
+        self
+    }
+
+    /// Sets the maximum number of read requests in flight, limited to 256.
+    pub fn max_outstanding(mut self, max_outstanding: u32) -> Self {
+        self.limits.max_outstanding = max_outstanding.clamp(1, 256);
+        self
+    }
+
+    /// Sets the highest physical address accessed.
+    pub fn max_address(mut self, max_address: Address) -> Self {
+        self.limits.max_address = max_address;
+        self
+    }
+
+    /// Sets the time to wait for the completions of a batch of read requests.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the firmware version of the board as `(major, minor)`.
+    pub fn firmware_version(&self) -> (u8, u8) {
+        (self.info.firmware_major, self.info.firmware_minor)
+    }
+
+    /// Returns the identification reported by the firmware of the board.
+    pub fn device_info(&self) -> FpgaDeviceInfo {
+        self.info
+    }
+
+    /// Consumes self and returns the transport.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Sends the read requests in `pending` and collects their completions into `bufs`.
+    ///
+    /// Requests that are not completed in time are retransmitted up to `retries` times.
+    fn read_batch(&mut self, pending: &mut [PendingRead], bufs: &mut [Vec<u8>]) -> Result<()> {
+        let requester_id = self.info.requester_id;
+
+        for attempt in 0..=self.limits.retries {
+            let missing = pending
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| !r.failed && r.received < r.len)
+                .map(|(tag, r)| mrd_tlp(requester_id, tag as u8, r.addr, r.len))
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                return Ok(());
+            }
+
+            if attempt > 0 && !self.limits.retry_delay.is_zero() {
+                std::thread::sleep(self.limits.retry_delay);
+            }
+
+            // restart the retransmitted requests from scratch
+            for r in pending.iter_mut().filter(|r| !r.failed) {
+                if r.received < r.len {
+                    r.received = 0;
+                }
+            }
+            self.transport.send_tlps(&missing)?;
+
+            let deadline = Instant::now() + self.timeout;
+            while pending.iter().any(|r| !r.failed && r.received < r.len) {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+
+                for tlp in self.transport.recv_tlps(deadline - now)? {
+                    let cpl = match Completion::parse(&tlp) {
+                        Some(cpl) => cpl,
+                        None => continue,
+                    };
+                    let req = match pending.get_mut(cpl.tag as usize) {
+                        Some(req) if !req.failed && req.received < req.len => req,
+                        _ => continue,
+                    };
+
+                    if cpl.status != 0 || cpl.byte_count > req.len {
+                        req.failed = true;
+                        continue;
+                    }
+
+                    let offset = req.len - cpl.byte_count;
+                    let len = std::cmp::min(cpl.data.len(), cpl.byte_count);
+                    let dst = req.offset + offset;
+                    bufs[req.buf][dst..dst + len].copy_from_slice(&cpl.data[..len]);
+                    req.received = offset + len;
+                }
+            }
+        }
+
+        for r in pending.iter_mut() {
+            if r.received < r.len {
+                r.failed = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits the range of `len` bytes at `addr` into chunks of at most `max_len` bytes which do
+/// not cross a 4 KiB boundary.
+fn split_tlp_ranges(addr: umem, len: usize, max_len: usize) -> impl Iterator<Item = (umem, usize)> {
+    let end = addr + len as umem;
+    let mut cur = addr;
+    std::iter::from_fn(move || {
+        if cur >= end {
+            return None;
+        }
+        let boundary = (cur / TLP_BOUNDARY + 1) * TLP_BOUNDARY;
+        let next = std::cmp::min(std::cmp::min(end, boundary), cur + max_len as umem);
+        let ret = (cur, (next - cur) as usize);
+        cur = next;
+        Some(ret)
+    })
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: FpgaTransport> PhysicalMemory for FpgaMemory<T> {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let max_address = self.limits.max_address.to_umem();
+        let max_outstanding = self.limits.max_outstanding as usize;
+        let max_read_request = self.limits.max_read_request as usize;
+
+        let ops = data.inp.collect::<Vec<_>>();
+
+        // reads are widened to DWORD alignment into temporary buffers
+        let mut bufs = Vec::with_capacity(ops.len());
+        let mut failed = vec![false; ops.len()];
+        let mut pending = vec![];
+
+        for (i, CTup3(addr, _, buf)) in ops.iter().enumerate() {
+            let addr = addr.to_umem();
+            let start = addr & !3;
+            let end = (addr + buf.len() as umem + 3) & !3;
+            bufs.push(vec![0u8; (end - start) as usize]);
+
+            if end.saturating_sub(1) > max_address {
+                failed[i] = true;
+                continue;
+            }
+
+            for (req_addr, len) in split_tlp_ranges(start, (end - start) as usize, max_read_request)
+            {
+                pending.push(PendingRead {
+                    buf: i,
+                    offset: (req_addr - start) as usize,
+                    addr: req_addr.into(),
+                    len,
+                    received: 0,
+                    failed: false,
+                });
+            }
+        }
+
+        for batch in pending.chunks_mut(max_outstanding) {
+            self.read_batch(batch, &mut bufs)?;
+        }
+        for r in pending.iter().filter(|r| r.failed) {
+            failed[r.buf] = true;
+        }
+
+        for ((CTup3(addr, meta_addr, mut buf), tmp), failed) in
+            ops.into_iter().zip(bufs).zip(failed)
+        {
+            if failed {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                let start = (addr.to_umem() & 3) as usize;
+                let len = buf.len();
+                buf.copy_from_slice(&tmp[start..start + len]);
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        let requester_id = self.info.requester_id;
+        let max_address = self.limits.max_address.to_umem();
+        let max_payload = self.limits.max_payload as usize;
+
+        for CTup3(addr, meta_addr, buf) in data.inp {
+            let addr = addr.to_umem();
+            if (addr + buf.len() as umem).saturating_sub(1) > max_address {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+                continue;
+            }
+
+            // writes are posted, there is no completion telling whether they succeeded
+            let tlps = split_tlp_ranges(addr, buf.len(), max_payload)
+                .map(|(chunk_addr, len)| {
+                    let off = (chunk_addr - addr) as usize;
+                    mwr_tlp(requester_id, 0, chunk_addr.into(), &buf[off..off + len])
+                })
+                .collect::<Vec<_>>();
+
+            if self.transport.send_tlps(&tlps).is_ok() {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        let max_address = self.limits.max_address;
+        PhysicalMemoryMetadata {
+            max_address,
+            real_size: max_address.to_umem().saturating_add(1),
+            readonly: false,
+            ideal_batch_size: self.limits.max_outstanding,
+            // reads are split and aligned by the connector itself
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+}
+
+impl<T: FpgaTransport> DmaDevice for FpgaMemory<T> {
+    /// Opens the first detected board supported by the transport `T`.
+    ///
+    /// If `config.device` is set, the board with a matching serial number or bus location is
+    /// opened instead.
+    fn open(config: &DmaConfig) -> Result<Self> {
+        let device = detect_devices()?
+            .into_iter()
+            .filter(|d| T::supports(d.kind))
+            .find(|d| match &config.device {
+                Some(device) => d.serial.as_ref() == Some(device) || &d.location == device,
+                None => true,
+            })
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                    .log_error("no supported dma board found")
+            })?;
+
+        let mut ret = Self::new(T::open(&device)?)?;
+        if let Some(max_read_request) = config.max_read_request {
+            ret = ret.max_read_request(max_read_request);
+        }
+        if config.retries.is_some() || config.retry_delay.is_some() {
+            ret.set_retransmission(
+                config.retries.unwrap_or(ret.limits.retries),
+                config.retry_delay.unwrap_or(ret.limits.retry_delay),
+            )?;
+        }
+        Ok(ret)
+    }
+
+    fn info(&self) -> DmaDeviceInfo {
+        let name = match self.transport.kind() {
+            FpgaBoardKind::Ft601 => "FT601 FPGA board",
+            FpgaBoardKind::Usb3380 => "USB3380",
+        };
+        DmaDeviceInfo {
+            name: format!(
+                "{} (firmware v{}.{})",
+                name, self.info.firmware_major, self.info.firmware_minor
+            ),
+            vendor_id: self.info.vendor_id,
+            device_id: self.info.device_id,
+            link_width: 0,
+            link_generation: 0,
+        }
+    }
+
+    fn limits(&self) -> DmaLimits {
+        self.limits
+    }
+
+    /// Retransmissions are performed by the connector.
+    fn set_retransmission(&mut self, retries: u32, delay: Duration) -> Result<()> {
+        self.limits.retries = retries;
+        self.limits.retry_delay = delay;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    /// Answers requests from memory, splitting completions at 64 byte boundaries.
+    struct Loopback {
+        mem: DummyMemory,
+        completions: Vec<Vec<u32>>,
+        /// Number of read requests to drop
+        drop_requests: usize,
+    }
+
+    impl FpgaTransport for Loopback {
+        fn supports(_kind: FpgaBoardKind) -> bool {
+            true
+        }
+
+        fn open(_device: &DetectedDevice) -> Result<Self> {
+            unreachable!()
+        }
+
+        fn kind(&self) -> FpgaBoardKind {
+            FpgaBoardKind::Ft601
+        }
+
+        fn device_info(&mut self) -> Result<FpgaDeviceInfo> {
+            Ok(FpgaDeviceInfo {
+                firmware_major: 4,
+                firmware_minor: 14,
+                requester_id: 0x0100,
+                vendor_id: 0x10ee,
+                device_id: 0x0666,
+            })
+        }
+
+        fn send_tlps(&mut self, tlps: &[Vec<u32>]) -> Result<()> {
+            for tlp in tlps {
+                let fmt_type = tlp[0] >> 24;
+                let wide = fmt_type & 0x20 != 0;
+                let addr = if wide {
+                    (tlp[2] as u64) << 32 | tlp[3] as u64
+                } else {
+                    tlp[2] as u64
+                };
+                let dwords = match tlp[0] & 0x3ff {
+                    0 => 0x400,
+                    n => n as usize,
+                };
+                let tag = (tlp[1] >> 8) & 0xff;
+
+                match fmt_type & !0x20 {
+                    0x00 => {
+                        if self.drop_requests > 0 {
+                            self.drop_requests -= 1;
+                            continue;
+                        }
+                        let mut data = vec![0u8; dwords * 4];
+                        if self.mem.phys_read_into(addr.into(), &mut data[..]).is_err()
+                            || addr + data.len() as u64 > size::mb(1) as u64
+                        {
+                            // unsupported request
+                            self.completions.push(vec![
+                                0x0a << 24,
+                                1 << 13 | data.len() as u32,
+                                tag << 8,
+                            ]);
+                            continue;
+                        }
+                        for (i, chunk) in data.chunks(64).enumerate() {
+                            let mut cpl = vec![
+                                0x4a << 24 | (chunk.len() / 4) as u32,
+                                (data.len() - i * 64) as u32 & 0xfff,
+                                tag << 8 | (addr as u32 + i as u32 * 64) & 0x7f,
+                            ];
+                            cpl.extend(
+                                chunk
+                                    .chunks(4)
+                                    .map(|dw| u32::from_le_bytes([dw[0], dw[1], dw[2], dw[3]])),
+                            );
+                            self.completions.push(cpl);
+                        }
+                    }
+                    0x40 => {
+                        let first_be = tlp[1] & 0xf;
+                        let last_be = (tlp[1] >> 4) & 0xf;
+                        let payload = &tlp[if wide { 4 } else { 3 }..];
+                        for (i, dw) in payload.iter().enumerate() {
+                            let be = match i {
+                                0 => first_be,
+                                i if i == payload.len() - 1 => last_be,
+                                _ => 0xf,
+                            };
+                            for (b, byte) in dw.to_le_bytes().iter().enumerate() {
+                                if be & (1 << b) != 0 {
+                                    let a = addr + (i * 4 + b) as u64;
+                                    self.mem.phys_write(a.into(), byte).unwrap();
+                                }
+                            }
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Ok(())
+        }
+
+        fn recv_tlps(&mut self, _timeout: Duration) -> Result<Vec<Vec<u32>>> {
+            Ok(std::mem::take(&mut self.completions))
+        }
+    }
+
+    fn connect(drop_requests: usize) -> FpgaMemory<Loopback> {
+        FpgaMemory::new(Loopback {
+            mem: DummyMemory::new(size::mb(1)),
+            completions: vec![],
+            drop_requests,
+        })
+        .unwrap()
+        .max_read_request(0x200)
+        .timeout(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn read_write() {
+        let mut mem = connect(0);
+        assert_eq!(mem.firmware_version(), (4, 14));
+        assert!(mem.info().name.contains("v4.14"));
+
+        let data = (0..0x1801u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        mem.phys_write(0x1ffd.into(), data.as_slice()).unwrap();
+        assert_eq!(
+            mem.phys_view().read_raw(0x1ffd.into(), data.len()).unwrap(),
+            data
+        );
+
+        // bytes around the write are untouched
+        assert_eq!(mem.phys_view().read::<u8>(0x1ffc.into()).unwrap(), 0);
+        assert_eq!(mem.phys_view().read::<u8>(0x37fe.into()).unwrap(), 0);
+
+        // unsupported request
+        assert!(mem
+            .phys_view()
+            .read::<u32>(Address::from(size::mb(2) as u64))
+            .is_err());
+    }
+
+    #[test]
+    fn retransmission() {
+        let mut mem = connect(1);
+        assert!(mem.phys_view().read::<u64>(0x1000.into()).is_ok());
+
+        let mut mem = connect(2);
+        assert!(mem.phys_view().read::<u64>(0x1000.into()).is_err());
+    }
+
+    #[test]
+    fn tlp_encoding() {
+        assert_eq!(
+            mrd_tlp(0x0100, 3, Address::from(0x1000u64), 8),
+            vec![0x0000_0002, 0x0100_03ff, 0x1000]
+        );
+        assert_eq!(
+            mrd_tlp(0x0100, 3, Address::from(0x1_0000_1000u64), 4),
+            vec![0x2000_0001, 0x0100_030f, 0x1, 0x1000]
+        );
+        assert_eq!(
+            mwr_tlp(0x0100, 0, Address::from(0x1001u64), &[1, 2]),
+            vec![0x4000_0001, 0x0100_0006, 0x1000, 0x0002_0100]
+        );
+        assert_eq!(
+            split_tlp_ranges(0xf80, 0x200, 0x100).collect::<Vec<_>>(),
+            vec![(0xf80, 0x80), (0x1000, 0x100), (0x1100, 0x80)]
+        );
+    }
+
+    #[test]
+    fn detect() {
+        let root = std::env::temp_dir().join(format!("memflow-fpga-{}", std::process::id()));
+        let board = root.join("2-1.4");
+        let other = root.join("1-1");
+        std::fs::create_dir_all(&board).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(board.join("idVendor"), "0403\n").unwrap();
+        std::fs::write(board.join("idProduct"), "601f\n").unwrap();
+        std::fs::write(board.join("serial"), "FT4R2Z1\n").unwrap();
+        std::fs::write(other.join("idVendor"), "1d6b\n").unwrap();
+        std::fs::write(other.join("idProduct"), "0002\n").unwrap();
+
+        let devices = detect_devices_in(&root).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            devices,
+            vec![DetectedDevice {
+                kind: FpgaBoardKind::Ft601,
+                name: "FT601 FPGA board".to_string(),
+                serial: Some("FT4R2Z1".to_string()),
+                location: "2-1.4".to_string(),
+            }]
+        );
+    }
+}
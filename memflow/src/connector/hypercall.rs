@@ -0,0 +1,746 @@
+//! Connector for custom hypervisors communicating through a shared request ring.
+//!
+//! Hypervisors written for a specific purpose rarely provide a standard introspection interface.
+//! This module defines a simple ABI through which such a hypervisor can serve physical memory
+//! to memflow, as well as a reference implementation of both sides of it:
+//! [`HypercallMemory`] is the connector issuing requests, [`init_shared_page`] and
+//! [`serve_requests`] implement the hypervisor side.
+//!
+//! # ABI
+//!
+//! Both sides share a memory region (usually a single page) which the hypervisor initializes
+//! and then polls for new requests. All values are stored in little endian. The region starts
+//! with a header:
+//!
+//! | Offset | Size | Written by | Description                                           |
+//! |--------|------|------------|-------------------------------------------------------|
+//! | `0x00` | 4    | hypervisor | magic, [`HC_MAGIC`]                                   |
+//! | `0x04` | 2    | hypervisor | version of the ABI, [`HC_VERSION`]                    |
+//! | `0x06` | 2    | hypervisor | number of request slots                               |
+//! | `0x08` | 4    | connector  | head, total number of submitted requests              |
+//! | `0x0c` | 4    | hypervisor | tail, total number of completed requests              |
+//! | `0x10` | 8    | hypervisor | highest valid physical address                        |
+//! | `0x18` | 4    | hypervisor | offset of the data area from the start of the region  |
+//! | `0x1c` | 4    | hypervisor | size of the data area                                 |
+//!
+//! The request slots follow the header at offset [`HC_HEADER_SIZE`], every slot is
+//! [`HC_SLOT_SIZE`] bytes large:
+//!
+//! | Offset | Size | Description                                                   |
+//! |--------|------|---------------------------------------------------------------|
+//! | `0x00` | 4    | operation, [`HC_OP_READ`] or [`HC_OP_WRITE`]                  |
+//! | `0x04` | 4    | status, [`HC_STATUS_PENDING`], [`HC_STATUS_OK`] or [`HC_STATUS_FAILED`] |
+//! | `0x08` | 8    | physical address                                              |
+//! | `0x10` | 4    | length in bytes                                               |
+//! | `0x14` | 4    | offset of the request data inside of the data area            |
+//!
+//! Head and tail are free running counters, request `n` is stored in slot `n % slots`.
+//! The connector fills the slots and the data of write requests, and then publishes them by
+//! advancing the head. The hypervisor processes all slots between tail and head, stores the
+//! status and the data of read requests, and then advances the tail. Counters are accessed
+//! atomically, all other stores have to be visible before the counter update publishing them.
+//!
+//! The connector only submits a new batch after the previous one has been completed, so the
+//! data area is never shared between batches. Hypervisors that do not want to poll can be
+//! notified through [`HypercallChannel::notify`], e.g. with a `vmcall`.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::connector::hypercall::{HypercallMemory, SharedPageChannel};
+//! use memflow::error::Result;
+//!
+//! /// Connects to a hypervisor sharing the page mapped at `page`.
+//! ///
+//! /// # Safety
+//! ///
+//! /// `page` has to point to the page shared with the hypervisor.
+//! unsafe fn connect(page: *mut u8) -> Result<HypercallMemory<SharedPageChannel>> {
+//!     HypercallMemory::new(SharedPageChannel::new(page, 0x1000))
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+use crate::cglue::*;
+
+/// Magic number identifying an initialized shared region (`MFHC`).
+pub const HC_MAGIC: u32 = 0x4348_464d;
+/// Version of the ABI implemented by this module.
+pub const HC_VERSION: u16 = 1;
+
+/// Size of the header of the shared region.
+pub const HC_HEADER_SIZE: usize = 0x40;
+/// Size of a single request slot.
+pub const HC_SLOT_SIZE: usize = 0x20;
+
+/// Reads physical memory into the data area.
+pub const HC_OP_READ: u32 = 1;
+/// Writes the data area to physical memory.
+pub const HC_OP_WRITE: u32 = 2;
+
+/// The request has not been processed yet.
+pub const HC_STATUS_PENDING: u32 = 0;
+/// The request has been processed successfully.
+pub const HC_STATUS_OK: u32 = 1;
+/// The request has failed.
+pub const HC_STATUS_FAILED: u32 = 2;
+
+/// Default number of request slots set up by [`init_shared_page`].
+pub const DEFAULT_SLOTS: u16 = 32;
+
+/// Default time the connector waits for the hypervisor to complete a batch.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+const MAGIC: usize = 0x00;
+const VERSION: usize = 0x04;
+const SLOT_COUNT: usize = 0x06;
+const HEAD: usize = 0x08;
+const TAIL: usize = 0x0c;
+const MAX_ADDRESS: usize = 0x10;
+const DATA_OFFSET: usize = 0x18;
+const DATA_SIZE: usize = 0x1c;
+
+/// Access to the memory region shared between connector and hypervisor.
+pub trait HypercallChannel: Send {
+    /// Returns the size of the shared region.
+    fn size(&self) -> usize;
+
+    /// Reads `buf.len()` bytes at `offset` of the shared region.
+    fn read_shared(&mut self, offset: usize, buf: &mut [u8]) -> Result<()>;
+
+    /// Writes `data` at `offset` of the shared region.
+    fn write_shared(&mut self, offset: usize, data: &[u8]) -> Result<()>;
+
+    /// Loads the counter at `offset`.
+    ///
+    /// Regions shared with another CPU have to load the counter atomically, with acquire
+    /// semantics.
+    fn load_counter(&mut self, offset: usize) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        self.read_shared(offset, &mut buf)?;
+        fence(Ordering::Acquire);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Stores the counter at `offset`.
+    ///
+    /// Regions shared with another CPU have to store the counter atomically, with release
+    /// semantics.
+    fn store_counter(&mut self, offset: usize, value: u32) -> Result<()> {
+        fence(Ordering::Release);
+        self.write_shared(offset, &value.to_le_bytes())
+    }
+
+    /// Signals the other side that a counter has been updated.
+    ///
+    /// Hypervisors polling the shared region do not need to be notified, so the default
+    /// implementation does nothing.
+    fn notify(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A shared region held in local memory, e.g. for hypervisors running in the same process.
+impl HypercallChannel for Vec<u8> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn read_shared(&mut self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let src = self
+            .get(offset..offset + buf.len())
+            .ok_or_else(|| shared_out_of_bounds(offset))?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_shared(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        self.get_mut(offset..offset + data.len())
+            .ok_or_else(|| shared_out_of_bounds(offset))?
+            .copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// A shared region mapped into the address space of the current process.
+pub struct SharedPageChannel {
+    ptr: *mut u8,
+    size: usize,
+}
+
+unsafe impl Send for SharedPageChannel {}
+
+impl SharedPageChannel {
+    /// Creates a channel for the region of `size` bytes at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` has to be valid for reads and writes of `size` bytes for the lifetime of the channel
+    /// and aligned to at least 4 bytes. The region must only be accessed by the hypervisor
+    /// according to the ABI.
+    pub unsafe fn new(ptr: *mut u8, size: usize) -> Self {
+        Self { ptr, size }
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => Err(shared_out_of_bounds(offset)),
+        }
+    }
+
+    fn counter(&self, offset: usize) -> Result<&AtomicU32> {
+        self.check(offset, 4)?;
+        // Safety: the region is valid and aligned, counters are 4 byte aligned
+        Ok(unsafe { &*(self.ptr.add(offset) as *const AtomicU32) })
+    }
+}
+
+impl HypercallChannel for SharedPageChannel {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read_shared(&mut self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        self.check(offset, buf.len())?;
+        for (i, b) in buf.iter_mut().enumerate() {
+            // Safety: bounds are checked above
+            *b = unsafe { self.ptr.add(offset + i).read_volatile() };
+        }
+        Ok(())
+    }
+
+    fn write_shared(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        self.check(offset, data.len())?;
+        for (i, b) in data.iter().enumerate() {
+            // Safety: bounds are checked above
+            unsafe { self.ptr.add(offset + i).write_volatile(*b) };
+        }
+        Ok(())
+    }
+
+    fn load_counter(&mut self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le(self.counter(offset)?.load(Ordering::Acquire)))
+    }
+
+    fn store_counter(&mut self, offset: usize, value: u32) -> Result<()> {
+        self.counter(offset)?
+            .store(value.to_le(), Ordering::Release);
+        Ok(())
+    }
+}
+
+fn shared_out_of_bounds(offset: usize) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds)
+        .log_error(format!("access at {:#x} exceeds the shared region", offset))
+}
+
+/// Layout of the shared region as announced in its header.
+#[derive(Clone, Copy, Debug)]
+struct Header {
+    slot_count: u32,
+    max_address: Address,
+    data_offset: usize,
+    data_size: usize,
+}
+
+impl Header {
+    fn read(channel: &mut impl HypercallChannel) -> Result<Self> {
+        let mut buf = [0u8; HC_HEADER_SIZE];
+        channel.read_shared(0, &mut buf)?;
+
+        if u32::from_le_bytes(buf[MAGIC..MAGIC + 4].try_into().unwrap()) != HC_MAGIC {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                .log_error("shared region has not been initialized by the hypervisor"));
+        }
+
+        let version = u16::from_le_bytes(buf[VERSION..VERSION + 2].try_into().unwrap());
+        if version != HC_VERSION {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::VersionMismatch)
+                .log_error(format!("unsupported hypercall abi version {}", version)));
+        }
+
+        let ret = Self {
+            slot_count: u16::from_le_bytes(buf[SLOT_COUNT..SLOT_COUNT + 2].try_into().unwrap())
+                as u32,
+            max_address: u64::from_le_bytes(buf[MAX_ADDRESS..MAX_ADDRESS + 8].try_into().unwrap())
+                .into(),
+            data_offset: u32::from_le_bytes(buf[DATA_OFFSET..DATA_OFFSET + 4].try_into().unwrap())
+                as usize,
+            data_size: u32::from_le_bytes(buf[DATA_SIZE..DATA_SIZE + 4].try_into().unwrap())
+                as usize,
+        };
+
+        let slots_end = HC_HEADER_SIZE + ret.slot_count as usize * HC_SLOT_SIZE;
+        if ret.slot_count == 0
+            || ret.data_size == 0
+            || ret.data_offset < slots_end
+            || ret.data_offset + ret.data_size > channel.size()
+        {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("invalid layout of the shared region"));
+        }
+
+        Ok(ret)
+    }
+
+    fn slot_offset(&self, request: u32) -> usize {
+        HC_HEADER_SIZE + (request % self.slot_count) as usize * HC_SLOT_SIZE
+    }
+}
+
+/// A single request slot.
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    op: u32,
+    status: u32,
+    address: Address,
+    len: u32,
+    data: u32,
+}
+
+impl Slot {
+    fn from_bytes(buf: &[u8; HC_SLOT_SIZE]) -> Self {
+        Self {
+            op: u32::from_le_bytes(buf[0x0..0x4].try_into().unwrap()),
+            status: u32::from_le_bytes(buf[0x4..0x8].try_into().unwrap()),
+            address: u64::from_le_bytes(buf[0x8..0x10].try_into().unwrap()).into(),
+            len: u32::from_le_bytes(buf[0x10..0x14].try_into().unwrap()),
+            data: u32::from_le_bytes(buf[0x14..0x18].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; HC_SLOT_SIZE] {
+        let mut buf = [0u8; HC_SLOT_SIZE];
+        buf[0x0..0x4].copy_from_slice(&self.op.to_le_bytes());
+        buf[0x4..0x8].copy_from_slice(&self.status.to_le_bytes());
+        buf[0x8..0x10].copy_from_slice(&(self.address.to_umem() as u64).to_le_bytes());
+        buf[0x10..0x14].copy_from_slice(&self.len.to_le_bytes());
+        buf[0x14..0x18].copy_from_slice(&self.data.to_le_bytes());
+        buf
+    }
+}
+
+/// Physical memory served by a hypervisor through the hypercall ABI.
+pub struct HypercallMemory<C> {
+    channel: C,
+    header: Header,
+    head: u32,
+    timeout: Duration,
+}
+
+impl<C: HypercallChannel> HypercallMemory<C> {
+    /// Connects to the hypervisor through `channel`.
+    ///
+    /// The shared region has to be initialized by the hypervisor already.
+    pub fn new(mut channel: C) -> Result<Self> {
+        let header = Header::read(&mut channel)?;
+        let head = channel.load_counter(HEAD)?;
+        if channel.load_counter(TAIL)? != head {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                .log_error("the shared region still contains pending requests"));
+        }
+
+        Ok(Self {
+            channel,
+            header,
+            head,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets the time to wait for the hypervisor to complete a batch of requests.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Consumes self and returns the underlying channel.
+    pub fn into_inner(self) -> C {
+        self.channel
+    }
+
+    /// Submits a batch of requests of the operation `op` and waits for its completion.
+    ///
+    /// The data of the requests is stored consecutively in `data`. Returns whether each request
+    /// succeeded.
+    fn transact(
+        &mut self,
+        op: u32,
+        requests: &[(Address, usize)],
+        data: &mut [u8],
+    ) -> Result<Vec<bool>> {
+        let data_offset = self.header.data_offset;
+        if op == HC_OP_WRITE {
+            self.channel.write_shared(data_offset, data)?;
+        }
+
+        let mut offset = 0;
+        for (i, &(address, len)) in requests.iter().enumerate() {
+            let slot = Slot {
+                op,
+                status: HC_STATUS_PENDING,
+                address,
+                len: len as u32,
+                data: offset as u32,
+            };
+            let slot_offset = self.header.slot_offset(self.head.wrapping_add(i as u32));
+            self.channel.write_shared(slot_offset, &slot.to_bytes())?;
+            offset += len;
+        }
+
+        let head = self.head.wrapping_add(requests.len() as u32);
+        self.channel.store_counter(HEAD, head)?;
+        self.channel.notify()?;
+
+        let start = Instant::now();
+        while self.channel.load_counter(TAIL)? != head {
+            if start.elapsed() > self.timeout {
+                // the hypervisor may still complete the batch, which is awaited by the next one
+                self.head = head;
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Unknown)
+                    .log_error("timed out waiting for the hypervisor to complete requests"));
+            }
+            std::hint::spin_loop();
+        }
+
+        let mut ret = Vec::with_capacity(requests.len());
+        for i in 0..requests.len() {
+            let slot_offset = self.header.slot_offset(self.head.wrapping_add(i as u32));
+            let mut status = [0u8; 4];
+            self.channel.read_shared(slot_offset + 4, &mut status)?;
+            ret.push(u32::from_le_bytes(status) == HC_STATUS_OK);
+        }
+        self.head = head;
+
+        if op == HC_OP_READ {
+            self.channel.read_shared(data_offset, data)?;
+        }
+
+        Ok(ret)
+    }
+
+    /// Splits the buffers into requests fitting the data area and submits them in batches.
+    ///
+    /// Returns whether each buffer has been transferred completely.
+    fn transfer(&mut self, op: u32, bufs: &mut [(Address, &mut [u8])]) -> Result<Vec<bool>> {
+        let mut ok = vec![true; bufs.len()];
+        let mut staging = vec![0u8; self.header.data_size];
+
+        // (buffer index, offset in buffer, length)
+        let mut batch: Vec<(usize, usize, usize)> = vec![];
+        let mut used = 0;
+
+        let chunks = bufs.iter().enumerate().flat_map(|(i, (_, buf))| {
+            let data_size = self.header.data_size;
+            (0..buf.len())
+                .step_by(data_size)
+                .map(move |off| (i, off, std::cmp::min(data_size, buf.len() - off)))
+        });
+        let chunks = chunks.collect::<Vec<_>>();
+
+        for (idx, chunk) in chunks.iter().enumerate() {
+            batch.push(*chunk);
+            used += chunk.2;
+
+            let next_fits = chunks
+                .get(idx + 1)
+                .map(|next| used + next.2 <= staging.len())
+                .unwrap_or(false);
+            if next_fits && batch.len() < self.header.slot_count as usize {
+                continue;
+            }
+
+            let mut requests = Vec::with_capacity(batch.len());
+            let mut offset = 0;
+            for &(i, off, len) in batch.iter() {
+                requests.push((bufs[i].0 + off, len));
+                if op == HC_OP_WRITE {
+                    staging[offset..offset + len].copy_from_slice(&bufs[i].1[off..off + len]);
+                }
+                offset += len;
+            }
+
+            let status = self.transact(op, &requests, &mut staging[..used])?;
+
+            let mut offset = 0;
+            for (&(i, off, len), success) in batch.iter().zip(status) {
+                if op == HC_OP_READ {
+                    bufs[i].1[off..off + len].copy_from_slice(&staging[offset..offset + len]);
+                }
+                ok[i] &= success;
+                offset += len;
+            }
+
+            batch.clear();
+            used = 0;
+        }
+
+        Ok(ok)
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<C: HypercallChannel> PhysicalMemory for HypercallMemory<C> {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let mut ops = data.inp.collect::<Vec<_>>();
+        let mut bufs = ops
+            .iter_mut()
+            .map(|CTup3(addr, _, buf)| (addr.address(), &mut **buf))
+            .collect::<Vec<_>>();
+        let status = self.transfer(HC_OP_READ, &mut bufs)?;
+
+        for (CTup3(_, meta_addr, buf), ok) in ops.into_iter().zip(status) {
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        let ops = data.inp.collect::<Vec<_>>();
+        let mut copies = ops
+            .iter()
+            .map(|CTup3(addr, _, buf)| (addr.address(), buf.to_vec()))
+            .collect::<Vec<_>>();
+        let mut bufs = copies
+            .iter_mut()
+            .map(|(addr, buf)| (*addr, buf.as_mut_slice()))
+            .collect::<Vec<_>>();
+        let status = self.transfer(HC_OP_WRITE, &mut bufs)?;
+
+        for (CTup3(_, meta_addr, buf), ok) in ops.into_iter().zip(status) {
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.header.max_address,
+            real_size: self.header.max_address.to_umem() + 1,
+            readonly: false,
+            ideal_batch_size: self.header.slot_count,
+            max_read_size: self.header.data_size as u32,
+            read_alignment: 1,
+        }
+    }
+}
+
+/// Initializes the shared region of `channel` on the hypervisor side.
+///
+/// Sets up [`DEFAULT_SLOTS`] request slots (less for small regions) and uses the rest of the
+/// region as the data area. `max_address` is the highest physical address served.
+pub fn init_shared_page(channel: &mut impl HypercallChannel, max_address: Address) -> Result<()> {
+    let size = channel.size();
+    let slot_count = std::cmp::min(
+        DEFAULT_SLOTS as usize,
+        size.saturating_sub(HC_HEADER_SIZE) / 2 / HC_SLOT_SIZE,
+    );
+    if slot_count == 0 || size > u32::MAX as usize {
+        return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+            .log_error("shared region is too small or too large"));
+    }
+
+    let data_offset = HC_HEADER_SIZE + slot_count * HC_SLOT_SIZE;
+
+    let mut header = [0u8; HC_HEADER_SIZE];
+    header[VERSION..VERSION + 2].copy_from_slice(&HC_VERSION.to_le_bytes());
+    header[SLOT_COUNT..SLOT_COUNT + 2].copy_from_slice(&(slot_count as u16).to_le_bytes());
+    header[MAX_ADDRESS..MAX_ADDRESS + 8]
+        .copy_from_slice(&(max_address.to_umem() as u64).to_le_bytes());
+    header[DATA_OFFSET..DATA_OFFSET + 4].copy_from_slice(&(data_offset as u32).to_le_bytes());
+    header[DATA_SIZE..DATA_SIZE + 4].copy_from_slice(&((size - data_offset) as u32).to_le_bytes());
+    channel.write_shared(0, &header)?;
+
+    // the magic is written last, marking the region as ready
+    fence(Ordering::Release);
+    channel.write_shared(MAGIC, &HC_MAGIC.to_le_bytes())
+}
+
+/// Processes all pending requests of the shared region of `channel` on the hypervisor side.
+///
+/// Requests are served from `mem`. Returns the number of processed requests.
+pub fn serve_requests(
+    channel: &mut impl HypercallChannel,
+    mem: &mut impl PhysicalMemory,
+) -> Result<usize> {
+    let header = Header::read(channel)?;
+    let head = channel.load_counter(HEAD)?;
+    let mut tail = channel.load_counter(TAIL)?;
+
+    let mut count = 0;
+    while tail != head {
+        let slot_offset = header.slot_offset(tail);
+        let mut buf = [0u8; HC_SLOT_SIZE];
+        channel.read_shared(slot_offset, &mut buf)?;
+        let slot = Slot::from_bytes(&buf);
+
+        let range = slot.data as usize..slot.data as usize + slot.len as usize;
+        let ok = range.end <= header.data_size
+            && serve_request(channel, mem, &slot, header.data_offset + range.start)?;
+
+        let status = if ok { HC_STATUS_OK } else { HC_STATUS_FAILED };
+        channel.write_shared(slot_offset + 4, &status.to_le_bytes())?;
+
+        tail = tail.wrapping_add(1);
+        count += 1;
+    }
+
+    channel.store_counter(TAIL, tail)?;
+    Ok(count)
+}
+
+fn serve_request(
+    channel: &mut impl HypercallChannel,
+    mem: &mut impl PhysicalMemory,
+    slot: &Slot,
+    data_offset: usize,
+) -> Result<bool> {
+    let addr = PhysicalAddress::from(slot.address);
+    let mut buf = vec![0u8; slot.len as usize];
+    let mut ok = true;
+
+    match slot.op {
+        HC_OP_READ => {
+            MemOps::with(
+                std::iter::once((addr, CSliceMut::from(&mut buf[..]))),
+                None,
+                Some(
+                    &mut (&mut |_| {
+                        ok = false;
+                        true
+                    })
+                        .into(),
+                ),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+            if ok {
+                channel.write_shared(data_offset, &buf)?;
+            }
+        }
+        HC_OP_WRITE => {
+            channel.read_shared(data_offset, &mut buf)?;
+            MemOps::with(
+                std::iter::once((addr, CSliceRef::from(&buf[..]))),
+                None,
+                Some(
+                    &mut (&mut |_| {
+                        ok = false;
+                        true
+                    })
+                        .into(),
+                ),
+                |data| mem.phys_write_raw_iter(data),
+            )?;
+        }
+        _ => ok = false,
+    }
+
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    /// Serves the requests as soon as they are submitted.
+    struct Loopback {
+        page: Vec<u8>,
+        mem: DummyMemory,
+    }
+
+    impl HypercallChannel for Loopback {
+        fn size(&self) -> usize {
+            self.page.size()
+        }
+
+        fn read_shared(&mut self, offset: usize, buf: &mut [u8]) -> Result<()> {
+            self.page.read_shared(offset, buf)
+        }
+
+        fn write_shared(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+            self.page.write_shared(offset, data)
+        }
+
+        fn notify(&mut self) -> Result<()> {
+            serve_requests(&mut self.page, &mut self.mem).map(|_| ())
+        }
+    }
+
+    fn connect() -> HypercallMemory<Loopback> {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut page = vec![0u8; 0x1000];
+        init_shared_page(&mut page, mem.metadata().max_address).unwrap();
+        HypercallMemory::new(Loopback { page, mem }).unwrap()
+    }
+
+    #[test]
+    fn read_write() {
+        let mut conn = connect();
+        assert_eq!(conn.metadata().real_size, size::mb(1) as umem);
+
+        // larger than the data area
+        let data = (0..0x2800u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        conn.phys_write(0x1800.into(), data.as_slice()).unwrap();
+        assert_eq!(
+            conn.phys_view()
+                .read_raw(0x1800.into(), data.len())
+                .unwrap(),
+            data
+        );
+
+        // more requests than slots
+        let mut view = conn.phys_view();
+        for i in 0..100u64 {
+            view.write(Address::from(0x10000 + i * 8), &i).unwrap();
+        }
+        for i in 0..100u64 {
+            assert_eq!(view.read::<u64>(Address::from(0x10000 + i * 8)).unwrap(), i);
+        }
+
+        let inner = conn.into_inner();
+        let mut mem = inner.mem;
+        assert_eq!(
+            mem.phys_view().read_raw(0x1800.into(), data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn failed_request() {
+        let mut conn = connect();
+        assert!(conn
+            .phys_view()
+            .read::<u64>(Address::from(size::mb(2) as u64))
+            .is_err());
+        assert!(conn.phys_view().read::<u64>(Address::null()).is_ok());
+    }
+
+    #[test]
+    fn uninitialized() {
+        assert!(HypercallMemory::new(vec![0u8; 0x1000]).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! Connector for virtual machines run by Hypervisor.framework based VMMs on macOS.
+//!
+//! Hypervisor.framework maps guest memory from the address space of the VMM process
+//! (`hv_vm_map`), so the memory of a guest can be accessed by reading the memory of its VMM
+//! through the mach VM APIs. [`MachVmMemory`] translates guest physical addresses to addresses
+//! of the VMM process through a [`MemoryMap`] and accesses them with `mach_vm_read_overwrite`
+//! and `mach_vm_write`.
+//!
+//! The location of guest memory inside of the VMM can not be queried from outside of the
+//! process. If it is not known, [`MachVmMemory::with_guest_ram`] locates it by searching the
+//! address space of the VMM for a writeable region matching the memory size of the guest.
+//!
+//! The connector is only available with the `mach` feature.
+//!
+//! # Remarks
+//!
+//! Accessing another process requires a task port obtained through `task_for_pid`, which
+//! requires running as root and the target process not being protected by the hardened
+//! runtime (or the caller having the `com.apple.security.cs.debugger` entitlement).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::connector::mach::MachVmMemory;
+//! use memflow::types::{size, umem, Address};
+//!
+//! // a QEMU guest on Apple silicon with 4 GiB of memory starting at 1 GiB
+//! let gpa_base = Address::from(0x4000_0000u64);
+//! let mem = MachVmMemory::with_guest_ram(1234, gpa_base, size::gb(4) as umem)
+//!     .unwrap()
+//!     .readonly();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::os::raw::c_int;
+use std::sync::Arc;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use crate::cglue::*;
+
+#[allow(non_camel_case_types)]
+type kern_return_t = c_int;
+#[allow(non_camel_case_types)]
+type mach_port_t = u32;
+
+const KERN_SUCCESS: kern_return_t = 0;
+const VM_REGION_BASIC_INFO_64: c_int = 9;
+const VM_PROT_READ: c_int = 1;
+const VM_PROT_WRITE: c_int = 2;
+
+#[repr(C, packed(4))]
+#[derive(Default)]
+struct VmRegionBasicInfo64 {
+    protection: c_int,
+    max_protection: c_int,
+    inheritance: u32,
+    shared: u32,
+    reserved: u32,
+    offset: u64,
+    behavior: c_int,
+    user_wired_count: u16,
+}
+
+const VM_REGION_BASIC_INFO_COUNT_64: u32 =
+    (std::mem::size_of::<VmRegionBasicInfo64>() / std::mem::size_of::<c_int>()) as u32;
+
+extern "C" {
+    static mach_task_self_: mach_port_t;
+
+    fn task_for_pid(target_tport: mach_port_t, pid: c_int, task: *mut mach_port_t)
+        -> kern_return_t;
+    fn mach_port_deallocate(task: mach_port_t, name: mach_port_t) -> kern_return_t;
+    fn mach_vm_read_overwrite(
+        target_task: mach_port_t,
+        address: u64,
+        size: u64,
+        data: u64,
+        outsize: *mut u64,
+    ) -> kern_return_t;
+    fn mach_vm_write(
+        target_task: mach_port_t,
+        address: u64,
+        data: usize,
+        data_count: u32,
+    ) -> kern_return_t;
+    fn mach_vm_region(
+        target_task: mach_port_t,
+        address: *mut u64,
+        size: *mut u64,
+        flavor: c_int,
+        info: *mut VmRegionBasicInfo64,
+        info_count: *mut u32,
+        object_name: *mut mach_port_t,
+    ) -> kern_return_t;
+}
+
+/// Send right to the task port of the VMM, shared between all clones of a connector.
+struct TaskPort(mach_port_t);
+
+impl Drop for TaskPort {
+    fn drop(&mut self) {
+        unsafe { mach_port_deallocate(mach_task_self_, self.0) };
+    }
+}
+
+/// A region of the address space of the VMM process.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TaskRegion {
+    /// Start address of the region
+    pub address: Address,
+    /// Size of the region
+    pub size: umem,
+    /// Whether the region is currently readable
+    pub readable: bool,
+    /// Whether the region is currently writeable
+    pub writeable: bool,
+    /// Whether the region is shared with other processes
+    pub shared: bool,
+}
+
+/// Physical memory of a virtual machine, read from the address space of its VMM.
+#[derive(Clone)]
+pub struct MachVmMemory {
+    task: Arc<TaskPort>,
+    mem_map: MemoryMap<(Address, umem)>,
+    readonly: bool,
+}
+
+impl MachVmMemory {
+    /// Opens the VMM process `pid`, mapping guest physical memory to VMM addresses via `mem_map`.
+    pub fn new(pid: i32, mem_map: MemoryMap<(Address, umem)>) -> Result<Self> {
+        let mut task: mach_port_t = 0;
+        let ret = unsafe { task_for_pid(mach_task_self_, pid, &mut task) };
+        if ret != KERN_SUCCESS {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::TargetNotFound).log_error(format!(
+                    "task_for_pid({}) failed with {}, root privileges are required",
+                    pid, ret
+                )),
+            );
+        }
+
+        Ok(Self {
+            task: Arc::new(TaskPort(task)),
+            mem_map,
+            readonly: false,
+        })
+    }
+
+    /// Opens the VMM process `pid` and locates the guest memory of `ram_size` bytes mapped at
+    /// `gpa_base`.
+    ///
+    /// Fails if there is not exactly one readable and writeable region of this size.
+    pub fn with_guest_ram(pid: i32, gpa_base: Address, ram_size: umem) -> Result<Self> {
+        let mut ret = Self::new(pid, MemoryMap::new())?;
+
+        let candidates = ret
+            .regions()?
+            .into_iter()
+            .filter(|r| r.size == ram_size && r.readable && r.writeable)
+            .collect::<Vec<_>>();
+
+        match candidates.as_slice() {
+            [region] => {
+                ret.mem_map.push_remap(gpa_base, ram_size, region.address);
+                Ok(ret)
+            }
+            [] => Err(Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                .log_error("no region matching the guest memory size found in the vmm")),
+            _ => Err(
+                Error(ErrorOrigin::Connector, ErrorKind::NotFound).log_error(format!(
+                    "{} regions match the guest memory size, a memory map has to be supplied",
+                    candidates.len()
+                )),
+            ),
+        }
+    }
+
+    /// Rejects all writes to guest memory.
+    pub fn readonly(mut self) -> Self {
+        self.readonly = true;
+        self
+    }
+
+    /// Returns all regions of the address space of the VMM process.
+    pub fn regions(&self) -> Result<Vec<TaskRegion>> {
+        let mut ret = vec![];
+        let mut address = 0u64;
+
+        loop {
+            let mut size = 0u64;
+            let mut info = VmRegionBasicInfo64::default();
+            let mut info_count = VM_REGION_BASIC_INFO_COUNT_64;
+            let mut object_name: mach_port_t = 0;
+
+            let kr = unsafe {
+                mach_vm_region(
+                    self.task.0,
+                    &mut address,
+                    &mut size,
+                    VM_REGION_BASIC_INFO_64,
+                    &mut info,
+                    &mut info_count,
+                    &mut object_name,
+                )
+            };
+            if kr != KERN_SUCCESS {
+                // KERN_INVALID_ADDRESS marks the end of the address space
+                break;
+            }
+
+            let protection = info.protection;
+            ret.push(TaskRegion {
+                address: address.into(),
+                size: size as umem,
+                readable: protection & VM_PROT_READ != 0,
+                writeable: protection & VM_PROT_WRITE != 0,
+                shared: info.shared != 0,
+            });
+
+            address = match address.checked_add(size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(ret)
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl PhysicalMemory for MachVmMemory {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let task = self.task.0;
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+        while let Some(CTup3((vmm_addr, _), meta_addr, mut buf)) = iter.next() {
+            let mut outsize = 0u64;
+            let kr = unsafe {
+                mach_vm_read_overwrite(
+                    task,
+                    vmm_addr.to_umem() as u64,
+                    buf.len() as u64,
+                    buf.as_mut_ptr() as u64,
+                    &mut outsize,
+                )
+            };
+            if kr == KERN_SUCCESS && outsize == buf.len() as u64 {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        if self.readonly {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("guest memory is opened read only"));
+        }
+
+        let task = self.task.0;
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+        while let Some(CTup3((vmm_addr, _), meta_addr, buf)) = iter.next() {
+            let kr = unsafe {
+                mach_vm_write(
+                    task,
+                    vmm_addr.to_umem() as u64,
+                    buf.as_ptr() as usize,
+                    buf.len() as u32,
+                )
+            };
+            if kr == KERN_SUCCESS {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: self.readonly,
+            ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        for map in self.mem_map.iter() {
+            let (real_base, size) = *map.output();
+            let mapping = PhysicalMemoryMapping {
+                base: map.base(),
+                size,
+                real_base,
+            };
+            if !out.call(mapping) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(MachVmMemory, crate::plugins::ConnectorInstance, {});
@@ -1,14 +1,16 @@
 ///! Basic connector which works on mapped memory.
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::phys_mem::phys_cas_emulated;
 use crate::mem::{
-    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
 };
-use crate::types::{umem, Address};
+use crate::types::{umem, Address, PhysicalAddress};
 
 use crate::cglue::*;
 
 use std::convert::TryInto;
+use std::sync::atomic::Ordering;
 
 pub struct MappedPhysicalMemory<T, F> {
     info: F,
@@ -112,6 +114,29 @@ impl<'a, F: AsRef<MemoryMap<&'a mut [u8]>> + Send> PhysicalMemory
         Ok(())
     }
 
+    /// Exchanges naturally aligned 1, 2, 4 and 8 byte values atomically.
+    ///
+    /// All other exchanges, as well as exchanges spanning multiple mappings, are emulated.
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        if expected.len() == new.len() {
+            let mut chunks = self.info.as_ref().map(
+                addr.address(),
+                expected,
+                None::<&mut OpaqueCallback<CTup2<Address, &[u8]>>>,
+            );
+
+            if let (Some(CTup3(mapped_buf, _, buf)), None) = (chunks.next(), chunks.next()) {
+                if buf.len() == expected.len() {
+                    if let Some(ret) = atomic_cas(mapped_buf, expected, new) {
+                        return Ok(ret);
+                    }
+                }
+            }
+        }
+
+        phys_cas_emulated(self, addr, expected, new)
+    }
+
     fn metadata(&self) -> PhysicalMemoryMetadata {
         let max_address = self
             .info
@@ -131,10 +156,61 @@ impl<'a, F: AsRef<MemoryMap<&'a mut [u8]>> + Send> PhysicalMemory
             real_size,
             readonly: false,
             ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        for map in self.info.as_ref().iter() {
+            let mapping = PhysicalMemoryMapping {
+                base: map.base(),
+                size: map.output().len() as umem,
+                real_base: map.base(),
+            };
+            if !out.call(mapping) {
+                break;
+            }
         }
     }
 }
 
+/// Performs a compare-and-swap of `buf` using native atomics.
+///
+/// Returns `None` if the size or alignment of `buf` is not supported by the atomics of the host.
+fn atomic_cas(buf: &mut [u8], expected: &[u8], new: &[u8]) -> Option<bool> {
+    macro_rules! cas {
+        ($atomic:ident, $int:ty) => {{
+            // the buffer is naturally aligned and exclusively borrowed
+            let atomic = unsafe { &*(buf.as_mut_ptr() as *const std::sync::atomic::$atomic) };
+            atomic
+                .compare_exchange(
+                    <$int>::from_ne_bytes(expected.try_into().ok()?),
+                    <$int>::from_ne_bytes(new.try_into().ok()?),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+        }};
+    }
+
+    if buf.as_ptr() as usize % buf.len().max(1) != 0 {
+        return None;
+    }
+
+    Some(match buf.len() {
+        #[cfg(target_has_atomic = "8")]
+        1 => cas!(AtomicU8, u8),
+        #[cfg(target_has_atomic = "16")]
+        2 => cas!(AtomicU16, u16),
+        #[cfg(target_has_atomic = "32")]
+        4 => cas!(AtomicU32, u32),
+        #[cfg(target_has_atomic = "64")]
+        8 => cas!(AtomicU64, u64),
+        _ => return None,
+    })
+}
+
 #[allow(clippy::needless_option_as_deref)]
 impl<'a, F: AsRef<MemoryMap<&'a [u8]>> + Send> PhysicalMemory
     for MappedPhysicalMemory<&'a [u8], F>
@@ -173,6 +249,21 @@ impl<'a, F: AsRef<MemoryMap<&'a [u8]>> + Send> PhysicalMemory
             real_size,
             readonly: true,
             ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        for map in self.info.as_ref().iter() {
+            let mapping = PhysicalMemoryMapping {
+                base: map.base(),
+                size: map.output().len() as umem,
+                real_base: map.base(),
+            };
+            if !out.call(mapping) {
+                break;
+            }
         }
     }
 }
@@ -23,13 +23,62 @@ pub use filemap::{
     MmapInfo, MmapInfoMut, ReadMappedFilePhysicalMemory, WriteMappedFilePhysicalMemory,
 };
 
+#[cfg(feature = "hypercall")]
+pub mod hypercall;
+#[doc(hidden)]
+#[cfg(feature = "hypercall")]
+pub use hypercall::{HypercallChannel, HypercallMemory};
+
+#[cfg(all(feature = "mach", target_os = "macos"))]
+pub mod mach;
+#[doc(hidden)]
+#[cfg(all(feature = "mach", target_os = "macos"))]
+pub use mach::MachVmMemory;
+
 pub mod mmap;
 #[doc(hidden)]
 pub use mmap::MappedPhysicalMemory;
 
+#[cfg(feature = "zstd_snapshot")]
+pub mod zstd_snapshot;
+#[doc(hidden)]
+#[cfg(feature = "zstd_snapshot")]
+pub use zstd_snapshot::ZstdSnapshotMemory;
+
+#[cfg(feature = "vm_snapshot")]
+pub mod vm_snapshot;
+
+#[cfg(all(feature = "whvp", windows))]
+pub mod whvp;
+#[doc(hidden)]
+#[cfg(all(feature = "whvp", windows))]
+pub use whvp::WhvpMemory;
+
+#[cfg(all(feature = "xen", target_os = "linux"))]
+pub mod xen;
+#[doc(hidden)]
+#[cfg(all(feature = "xen", target_os = "linux"))]
+pub use xen::XenMemory;
+
+pub mod dma;
+#[doc(hidden)]
+pub use dma::{DmaConfig, DmaDevice, DmaDeviceInfo, DmaLimits};
+
+#[cfg(feature = "driver")]
+pub mod driver;
+#[doc(hidden)]
+#[cfg(feature = "driver")]
+pub use driver::{DriverChannel, DriverMemory};
+
+#[cfg(feature = "fpga")]
+pub mod fpga;
+#[doc(hidden)]
+#[cfg(feature = "fpga")]
+pub use fpga::{FpgaMemory, FpgaTransport};
+
 pub mod cpu_state;
 #[doc(hidden)]
-pub use cpu_state::{ConnectorCpuState, CpuState};
+pub use cpu_state::{ConnectorCpuState, ConsistencyGuard, CpuRegister, CpuState};
 #[doc(hidden)]
 #[cfg(feature = "plugins")]
 pub use cpu_state::{CpuStateArcBox, IntoCpuStateArcBox};
@@ -0,0 +1,367 @@
+//! Connectors for Firecracker and cloud-hypervisor snapshots.
+//!
+//! Both VMMs store the guest memory of a snapshot as a raw file, next to a description of the
+//! virtual machine state. The guest physical address space is not contiguous, so the layout of
+//! the memory file has to be known to access it:
+//!
+//! * Firecracker stores all guest memory regions back to back. The regions are fixed by the
+//!   architecture and the memory size of the microVM: on x86_64 the memory is split around the
+//!   32 bit MMIO gap, on aarch64 it starts at 2 GiB. See [`firecracker_memory_map`].
+//! * cloud-hypervisor stores the ranges listed in the memory manager state of `state.json` back
+//!   to back in the `memory-ranges` file. See [`cloud_hypervisor_memory_map`].
+//!
+//! Neither VMM provides access to the memory of a running virtual machine through its API.
+//! On unix systems, [`VmmApi`] can instead be used to pause a running virtual machine, take a
+//! snapshot and resume it again.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::architecture::ArchitectureIdent;
+//! use memflow::connector::vm_snapshot::{open_cloud_hypervisor, open_firecracker};
+//!
+//! let firecracker =
+//!     open_firecracker("/tmp/snapshot/memory", ArchitectureIdent::X86(64, false)).unwrap();
+//! let cloud_hypervisor = open_cloud_hypervisor("/tmp/ch-snapshot").unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use crate::architecture::ArchitectureIdent;
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryMap;
+use crate::types::{umem, Address};
+
+use serde_json::Value;
+
+/// Start of the 32 bit MMIO gap of Firecracker x86_64 microVMs.
+pub const FIRECRACKER_X86_MMIO_START: umem = 0xd000_0000;
+/// Start of guest memory of Firecracker aarch64 microVMs.
+pub const FIRECRACKER_ARM_DRAM_START: umem = 0x8000_0000;
+
+/// Name of the memory file in a cloud-hypervisor snapshot directory.
+pub const CLOUD_HYPERVISOR_MEMORY_FILE: &str = "memory-ranges";
+/// Name of the state file in a cloud-hypervisor snapshot directory.
+pub const CLOUD_HYPERVISOR_STATE_FILE: &str = "state.json";
+
+/// The virtual machine monitor that created a snapshot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Vmm {
+    /// Firecracker
+    Firecracker,
+    /// cloud-hypervisor
+    CloudHypervisor,
+}
+
+/// Returns the memory map of the memory file of a Firecracker microVM with `mem_size` bytes
+/// of memory.
+pub fn firecracker_memory_map(
+    mem_size: umem,
+    arch: ArchitectureIdent,
+) -> Result<MemoryMap<(Address, umem)>> {
+    let mut mem_map = MemoryMap::new();
+    match arch {
+        ArchitectureIdent::X86(64, _) => {
+            if mem_size <= FIRECRACKER_X86_MMIO_START {
+                mem_map.push_remap(Address::null(), mem_size, Address::null());
+            } else {
+                mem_map.push_remap(Address::null(), FIRECRACKER_X86_MMIO_START, Address::null());
+                mem_map.push_remap(
+                    Address::from(0x1_0000_0000u64),
+                    mem_size - FIRECRACKER_X86_MMIO_START,
+                    Address::from(FIRECRACKER_X86_MMIO_START),
+                );
+            }
+        }
+        ArchitectureIdent::AArch64(_) => {
+            mem_map.push_remap(
+                Address::from(FIRECRACKER_ARM_DRAM_START),
+                mem_size,
+                Address::null(),
+            );
+        }
+        _ => {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::InvalidArchitecture)
+                    .log_error("firecracker only supports x86_64 and aarch64 guests"),
+            )
+        }
+    }
+    Ok(mem_map)
+}
+
+/// Parses the memory map of a cloud-hypervisor snapshot from the contents of its `state.json`.
+///
+/// The memory ranges are located by searching the state for the `memory_ranges` table of the
+/// memory manager, which allows parsing the state files of different cloud-hypervisor versions.
+/// Component states that are stored as serialized JSON strings are searched as well.
+pub fn cloud_hypervisor_memory_map(state: &str) -> Result<MemoryMap<(Address, umem)>> {
+    let state: Value = serde_json::from_str(state)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(err))?;
+
+    let ranges = find_memory_ranges(&state).ok_or_else(|| {
+        Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+            .log_error("memory ranges not found in the snapshot state")
+    })?;
+
+    let mut mem_map = MemoryMap::new();
+    let mut file_offset: umem = 0;
+    for range in ranges {
+        let (gpa, length) = match (
+            range.get("gpa").and_then(Value::as_u64),
+            range.get("length").and_then(Value::as_u64),
+        ) {
+            (Some(gpa), Some(length)) => (gpa as umem, length as umem),
+            _ => {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                    .log_error("invalid memory range in the snapshot state"))
+            }
+        };
+        mem_map.push_remap(gpa.into(), length, file_offset.into());
+        file_offset += length;
+    }
+
+    Ok(mem_map)
+}
+
+/// Searches `value` for the `memory_ranges` table and returns its entries.
+fn find_memory_ranges(value: &Value) -> Option<Vec<Value>> {
+    match value {
+        Value::Object(map) => map
+            .get("memory_ranges")
+            .and_then(|ranges| ranges.get("data"))
+            .and_then(Value::as_array)
+            .cloned()
+            .or_else(|| map.values().find_map(find_memory_ranges)),
+        Value::Array(values) => values.iter().find_map(find_memory_ranges),
+        // component states serialized as strings
+        Value::String(s) if s.trim_start().starts_with('{') => serde_json::from_str::<Value>(s)
+            .ok()
+            .and_then(|value| find_memory_ranges(&value)),
+        _ => None,
+    }
+}
+
+fn open_memory_file(path: &Path) -> Result<CloneFile> {
+    OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map(CloneFile::from)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))
+}
+
+/// Opens the memory file of a Firecracker snapshot.
+///
+/// The memory size of the microVM is taken from the size of the memory file, which equals the
+/// configured memory size for both full and diff snapshots.
+pub fn open_firecracker<P: AsRef<Path>>(
+    mem_file: P,
+    arch: ArchitectureIdent,
+) -> Result<FileIoMemory<CloneFile>> {
+    let file = open_memory_file(mem_file.as_ref())?;
+    let mem_size = file
+        .metadata()
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))?
+        .len() as umem;
+
+    FileIoMemory::with_mem_map(file, firecracker_memory_map(mem_size, arch)?)
+}
+
+/// Opens the cloud-hypervisor snapshot in the directory `dir`.
+pub fn open_cloud_hypervisor<P: AsRef<Path>>(dir: P) -> Result<FileIoMemory<CloneFile>> {
+    let dir = dir.as_ref();
+    let state = std::fs::read_to_string(dir.join(CLOUD_HYPERVISOR_STATE_FILE))
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))?;
+    let mem_map = cloud_hypervisor_memory_map(&state)?;
+
+    let file = open_memory_file(&dir.join(CLOUD_HYPERVISOR_MEMORY_FILE))?;
+    FileIoMemory::with_mem_map(file, mem_map)
+}
+
+/// Client for the API socket of a running Firecracker or cloud-hypervisor instance.
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct VmmApi {
+    socket: std::path::PathBuf,
+    vmm: Vmm,
+}
+
+#[cfg(unix)]
+impl VmmApi {
+    /// Connects to the API socket at `socket` of the given VMM.
+    pub fn new<P: AsRef<Path>>(socket: P, vmm: Vmm) -> Self {
+        Self {
+            socket: socket.as_ref().to_path_buf(),
+            vmm,
+        }
+    }
+
+    /// Pauses the virtual machine.
+    pub fn pause(&self) -> Result<()> {
+        match self.vmm {
+            Vmm::Firecracker => self.request("PATCH", "/vm", r#"{"state":"Paused"}"#),
+            Vmm::CloudHypervisor => self.request("PUT", "/api/v1/vm.pause", ""),
+        }
+    }
+
+    /// Resumes the virtual machine.
+    pub fn resume(&self) -> Result<()> {
+        match self.vmm {
+            Vmm::Firecracker => self.request("PATCH", "/vm", r#"{"state":"Resumed"}"#),
+            Vmm::CloudHypervisor => self.request("PUT", "/api/v1/vm.resume", ""),
+        }
+    }
+
+    /// Writes a full snapshot of the paused virtual machine to the directory `dir`.
+    ///
+    /// Firecracker snapshots are stored as `vmstate` and `memory` in the directory.
+    pub fn snapshot<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        let body = match self.vmm {
+            Vmm::Firecracker => serde_json::json!({
+                "snapshot_type": "Full",
+                "snapshot_path": dir.join("vmstate").display().to_string(),
+                "mem_file_path": dir.join("memory").display().to_string(),
+            }),
+            Vmm::CloudHypervisor => serde_json::json!({
+                "destination_url": format!("file://{}", dir.display()),
+            }),
+        };
+
+        let path = match self.vmm {
+            Vmm::Firecracker => "/snapshot/create",
+            Vmm::CloudHypervisor => "/api/v1/vm.snapshot",
+        };
+        self.request("PUT", path, &body.to_string())
+    }
+
+    /// Pauses the virtual machine, writes a snapshot to `dir` and resumes it again.
+    ///
+    /// The virtual machine is resumed even if taking the snapshot failed. `arch` is only used
+    /// for Firecracker snapshots.
+    pub fn capture<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        arch: ArchitectureIdent,
+    ) -> Result<FileIoMemory<CloneFile>> {
+        let dir = dir.as_ref();
+        self.pause()?;
+        let snapshot = self.snapshot(dir);
+        self.resume()?;
+        snapshot?;
+
+        match self.vmm {
+            Vmm::Firecracker => open_firecracker(dir.join("memory"), arch),
+            Vmm::CloudHypervisor => open_cloud_hypervisor(dir),
+        }
+    }
+
+    /// Sends a single HTTP request and checks the response status.
+    fn request(&self, method: &str, path: &str, body: &str) -> Result<()> {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let io_err = |err| Error(ErrorOrigin::Connector, ErrorKind::Unknown).log_error(err);
+
+        let mut stream = UnixStream::connect(&self.socket).map_err(io_err)?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .map_err(io_err)?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).map_err(io_err)?;
+
+        let status = response
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .unwrap_or(0);
+        if (200..300).contains(&status) {
+            Ok(())
+        } else {
+            Err(
+                Error(ErrorOrigin::Connector, ErrorKind::Unknown).log_error(format!(
+                    "{} {} failed: {}",
+                    method,
+                    path,
+                    response.lines().last().unwrap_or_default()
+                )),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::size;
+
+    fn mappings(mem_map: MemoryMap<(Address, umem)>) -> Vec<(umem, umem, umem)> {
+        mem_map
+            .into_vec()
+            .into_iter()
+            .map(|m| (m.base.to_umem(), m.size, m.real_base.to_umem()))
+            .collect()
+    }
+
+    #[test]
+    fn firecracker_layout() {
+        let x64 = ArchitectureIdent::X86(64, false);
+        assert_eq!(
+            mappings(firecracker_memory_map(size::mb(128) as umem, x64).unwrap()),
+            vec![(0, size::mb(128) as umem, 0)]
+        );
+        assert_eq!(
+            mappings(firecracker_memory_map(size::gb(4) as umem, x64).unwrap()),
+            vec![
+                (0, 0xd000_0000, 0),
+                (0x1_0000_0000, 0x3000_0000, 0xd000_0000)
+            ]
+        );
+        assert_eq!(
+            mappings(
+                firecracker_memory_map(
+                    size::mb(256) as umem,
+                    ArchitectureIdent::AArch64(size::kb(4))
+                )
+                .unwrap()
+            ),
+            vec![(0x8000_0000, size::mb(256) as umem, 0)]
+        );
+        assert!(firecracker_memory_map(0x1000, ArchitectureIdent::X86(32, false)).is_err());
+    }
+
+    #[test]
+    fn cloud_hypervisor_state() {
+        let manager = r#"{"memory_ranges":{"data":[{"gpa":0,"length":3221225472},{"gpa":4294967296,"length":1073741824}]}}"#;
+        let state = serde_json::json!({
+            "id": "vmm",
+            "snapshots": {
+                "memory-manager": {
+                    "snapshot_data": { "state": manager }
+                }
+            }
+        });
+
+        assert_eq!(
+            mappings(cloud_hypervisor_memory_map(&state.to_string()).unwrap()),
+            vec![
+                (0, 0xc000_0000, 0),
+                (0x1_0000_0000, 0x4000_0000, 0xc000_0000)
+            ]
+        );
+        assert!(cloud_hypervisor_memory_map(r#"{"snapshots":{}}"#).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Connector for Windows Hypervisor Platform partitions.
+//!
+//! [`WhvpMemory`] accesses the guest physical memory of a partition through
+//! `WHvReadGpaRange` and `WHvWriteGpaRange` of `WinHvPlatform.dll`. The accesses are performed
+//! by the hypervisor on behalf of the given virtual processor, so they work on live partitions
+//! without pausing them and also reach memory that is not mapped into the VMM process.
+//!
+//! The connector is only available with the `whvp` feature, since linking against
+//! `WinHvPlatform` requires the Windows Hypervisor Platform to be installed.
+//!
+//! # Remarks
+//!
+//! Partition handles are only valid in the process that created the partition with
+//! `WHvCreatePartition`. The connector is therefore meant to be used inside of VMMs built on
+//! the Windows Hypervisor Platform (or plugins loaded into them). Virtual machines managed by
+//! Hyper-V Manager are owned by their worker process and can not be attached to through this
+//! API.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::connector::whvp::WhvpMemory;
+//! use memflow::mem::{MemoryView, PhysicalMemory};
+//! use memflow::types::{size, umem};
+//!
+//! fn inspect(partition: *mut std::ffi::c_void) {
+//!     // Safety: the partition has been created by this process and outlives the connector
+//!     let mut mem = unsafe { WhvpMemory::from_handle(partition, 0, size::gb(2) as umem) };
+//!     let value = mem.phys_view().read::<u64>(0x1000.into()).unwrap();
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use std::ffi::c_void;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::umem;
+
+use crate::cglue::*;
+
+/// `WHvCacheTypeWriteBack`, the cache type of regular guest memory.
+const WHV_CACHE_TYPE_WRITE_BACK: u64 = 6;
+
+#[link(name = "WinHvPlatform")]
+extern "system" {
+    fn WHvReadGpaRange(
+        partition: *mut c_void,
+        vp_index: u32,
+        guest_address: u64,
+        controls: u64,
+        data: *mut c_void,
+        data_size: u32,
+    ) -> i32;
+    fn WHvWriteGpaRange(
+        partition: *mut c_void,
+        vp_index: u32,
+        guest_address: u64,
+        controls: u64,
+        data: *const c_void,
+        data_size: u32,
+    ) -> i32;
+}
+
+/// Guest physical memory of a Windows Hypervisor Platform partition.
+#[derive(Clone)]
+pub struct WhvpMemory {
+    partition: *mut c_void,
+    vp_index: u32,
+    size: umem,
+    readonly: bool,
+}
+
+// partition handles can be used from any thread
+unsafe impl Send for WhvpMemory {}
+
+impl WhvpMemory {
+    /// Creates a connector for the partition `partition` with `size` bytes of guest physical
+    /// address space, performing accesses on behalf of the virtual processor `vp_index`.
+    ///
+    /// # Safety
+    ///
+    /// `partition` has to be a valid partition handle created by this process. The partition
+    /// must not be deleted while the connector or any of its clones are in use.
+    pub unsafe fn from_handle(partition: *mut c_void, vp_index: u32, size: umem) -> Self {
+        Self {
+            partition,
+            vp_index,
+            size,
+            readonly: false,
+        }
+    }
+
+    /// Rejects all writes to guest memory.
+    pub fn readonly(mut self) -> Self {
+        self.readonly = true;
+        self
+    }
+
+    /// Returns true if the range of `len` bytes at `addr` is part of the address space.
+    fn in_range(&self, addr: umem, len: usize) -> bool {
+        len <= u32::MAX as usize
+            && addr
+                .checked_add(len as umem)
+                .map_or(false, |end| end <= self.size)
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl PhysicalMemory for WhvpMemory {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in data.inp {
+            let addr = addr.to_umem();
+            let ok = self.in_range(addr, buf.len())
+                && unsafe {
+                    WHvReadGpaRange(
+                        self.partition,
+                        self.vp_index,
+                        addr as u64,
+                        WHV_CACHE_TYPE_WRITE_BACK,
+                        buf.as_mut_ptr() as *mut c_void,
+                        buf.len() as u32,
+                    )
+                } >= 0;
+
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        if self.readonly {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("guest memory is opened read only"));
+        }
+
+        for CTup3(addr, meta_addr, buf) in data.inp {
+            let addr = addr.to_umem();
+            let ok = self.in_range(addr, buf.len())
+                && unsafe {
+                    WHvWriteGpaRange(
+                        self.partition,
+                        self.vp_index,
+                        addr as u64,
+                        WHV_CACHE_TYPE_WRITE_BACK,
+                        buf.as_ptr() as *const c_void,
+                        buf.len() as u32,
+                    )
+                } >= 0;
+
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.size.saturating_sub(1).into(),
+            real_size: self.size,
+            readonly: self.readonly,
+            ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(WhvpMemory, crate::plugins::ConnectorInstance, {});
@@ -0,0 +1,461 @@
+//! Connector for Xen guests based on foreign memory mappings.
+//!
+//! [`XenMemory`] maps the pages of a domain through `libxenforeignmemory`, which requires running
+//! in a privileged domain (usually dom0) with access to the Xen privcmd driver. The size of the
+//! guest physical address space is queried through `libxenctrl`. Both libraries are loaded at
+//! runtime when a connector is created.
+//!
+//! Guest memory is mapped in chunks of [`CHUNK_PAGES`] pages, the most recently used chunks are
+//! kept mapped. Pages of a chunk that can not be mapped (e.g. ballooned out or MMIO ranges) are
+//! tracked individually, accesses to them fail without affecting the rest of the chunk.
+//! All mappings are released when the connector is dropped, the Xen interfaces are closed once
+//! the connector and all of its clones are dropped.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::connector::xen::XenMemory;
+//! use memflow::mem::{MemoryView, PhysicalMemory};
+//!
+//! let mut mem = XenMemory::new(1).unwrap().cached_chunks(64);
+//! let mut header = [0u8; 2];
+//! mem.phys_view().read_into(0x1000.into(), &mut header).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::VecDeque;
+use std::os::raw::{c_int, c_uint, c_ulong, c_void};
+use std::ptr::{null_mut, NonNull};
+use std::sync::Arc;
+
+use libloading::Library;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use crate::cglue::*;
+
+/// Number of pages mapped at once.
+pub const CHUNK_PAGES: usize = 512;
+
+/// Default number of chunks kept mapped.
+pub const DEFAULT_CACHED_CHUNKS: usize = 16;
+
+const PAGE_SIZE: usize = 0x1000;
+const CHUNK_SIZE: usize = CHUNK_PAGES * PAGE_SIZE;
+
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+
+#[allow(non_camel_case_types)]
+type xen_pfn_t = c_ulong;
+
+#[repr(C)]
+struct XcInterface {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct XenForeignMemoryHandle {
+    _private: [u8; 0],
+}
+
+type XcInterfaceOpen = unsafe extern "C" fn(*mut c_void, *mut c_void, c_uint) -> *mut XcInterface;
+type XcInterfaceClose = unsafe extern "C" fn(*mut XcInterface) -> c_int;
+type XcDomainMaximumGpfn = unsafe extern "C" fn(*mut XcInterface, u32, *mut xen_pfn_t) -> c_int;
+type XenForeignMemoryOpen =
+    unsafe extern "C" fn(*mut c_void, c_uint) -> *mut XenForeignMemoryHandle;
+type XenForeignMemoryClose = unsafe extern "C" fn(*mut XenForeignMemoryHandle) -> c_int;
+type XenForeignMemoryMap = unsafe extern "C" fn(
+    *mut XenForeignMemoryHandle,
+    u32,
+    c_int,
+    usize,
+    *const xen_pfn_t,
+    *mut c_int,
+) -> *mut c_void;
+type XenForeignMemoryUnmap =
+    unsafe extern "C" fn(*mut XenForeignMemoryHandle, *mut c_void, usize) -> c_int;
+
+/// Names under which `libxenctrl` is looked up, its soname changes with every Xen release.
+const XENCTRL_NAMES: &[&str] = &[
+    "libxenctrl.so",
+    "libxenctrl.so.4.19",
+    "libxenctrl.so.4.18",
+    "libxenctrl.so.4.17",
+    "libxenctrl.so.4.16",
+    "libxenctrl.so.4.15",
+    "libxenctrl.so.4.14",
+    "libxenctrl.so.4.13",
+    "libxenctrl.so.4.12",
+    "libxenctrl.so.4.11",
+];
+
+/// Names under which `libxenforeignmemory` is looked up.
+const XENFOREIGNMEMORY_NAMES: &[&str] = &["libxenforeignmemory.so", "libxenforeignmemory.so.1"];
+
+/// Functions of `libxenctrl` and `libxenforeignmemory`.
+///
+/// The libraries are loaded when the connector is created, so memflow can be built and linked on
+/// hosts without the Xen libraries installed.
+struct XenLibs {
+    xc_interface_open: XcInterfaceOpen,
+    xc_interface_close: XcInterfaceClose,
+    xc_domain_maximum_gpfn: XcDomainMaximumGpfn,
+    xenforeignmemory_open: XenForeignMemoryOpen,
+    xenforeignmemory_close: XenForeignMemoryClose,
+    xenforeignmemory_map: XenForeignMemoryMap,
+    xenforeignmemory_unmap: XenForeignMemoryUnmap,
+    // keeps the resolved functions above valid
+    _xenctrl: Library,
+    _xenforeignmemory: Library,
+}
+
+impl XenLibs {
+    fn load() -> Result<Self> {
+        let xenctrl = load_library(XENCTRL_NAMES)?;
+        let xenforeignmemory = load_library(XENFOREIGNMEMORY_NAMES)?;
+
+        unsafe {
+            Ok(Self {
+                xc_interface_open: symbol(&xenctrl, b"xc_interface_open\0")?,
+                xc_interface_close: symbol(&xenctrl, b"xc_interface_close\0")?,
+                xc_domain_maximum_gpfn: symbol(&xenctrl, b"xc_domain_maximum_gpfn\0")?,
+                xenforeignmemory_open: symbol(&xenforeignmemory, b"xenforeignmemory_open\0")?,
+                xenforeignmemory_close: symbol(&xenforeignmemory, b"xenforeignmemory_close\0")?,
+                xenforeignmemory_map: symbol(&xenforeignmemory, b"xenforeignmemory_map\0")?,
+                xenforeignmemory_unmap: symbol(&xenforeignmemory, b"xenforeignmemory_unmap\0")?,
+                _xenctrl: xenctrl,
+                _xenforeignmemory: xenforeignmemory,
+            })
+        }
+    }
+}
+
+/// Loads the first library of `names` that can be found.
+fn load_library(names: &[&str]) -> Result<Library> {
+    names
+        .iter()
+        .find_map(|name| unsafe { Library::new(name) }.ok())
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToLoadLibrary)
+                .log_error(format!("unable to load {}", names[0]))
+        })
+}
+
+/// Resolves the function `name` of `lib`.
+///
+/// # Safety
+///
+/// `T` has to match the signature of the function.
+unsafe fn symbol<T: Copy>(lib: &Library, name: &[u8]) -> Result<T> {
+    lib.get::<T>(name).map(|sym| *sym).map_err(|err| {
+        Error(ErrorOrigin::Connector, ErrorKind::UnableToLoadLibrary).log_error(format!(
+            "unable to resolve {}: {}",
+            String::from_utf8_lossy(&name[..name.len() - 1]),
+            err
+        ))
+    })
+}
+
+/// The Xen interfaces, shared between all clones of a connector.
+struct Handles {
+    libs: XenLibs,
+    xc: NonNull<XcInterface>,
+    fmem: NonNull<XenForeignMemoryHandle>,
+}
+
+// the handles are thread safe according to the libxenctrl and libxenforeignmemory documentation
+unsafe impl Send for Handles {}
+unsafe impl Sync for Handles {}
+
+impl Handles {
+    fn open() -> Result<Self> {
+        let libs = XenLibs::load()?;
+
+        let xc = NonNull::new(unsafe { (libs.xc_interface_open)(null_mut(), null_mut(), 0) })
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                    .log_error("unable to open the xenctrl interface")
+            })?;
+
+        match NonNull::new(unsafe { (libs.xenforeignmemory_open)(null_mut(), 0) }) {
+            Some(fmem) => Ok(Self { libs, xc, fmem }),
+            None => {
+                unsafe { (libs.xc_interface_close)(xc.as_ptr()) };
+                Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                    .log_error("unable to open the xenforeignmemory interface"))
+            }
+        }
+    }
+}
+
+impl Drop for Handles {
+    fn drop(&mut self) {
+        unsafe {
+            (self.libs.xenforeignmemory_close)(self.fmem.as_ptr());
+            (self.libs.xc_interface_close)(self.xc.as_ptr());
+        }
+    }
+}
+
+/// A mapped chunk of guest memory.
+struct Mapping {
+    handles: Arc<Handles>,
+    /// Index of the chunk in the guest physical address space
+    chunk: umem,
+    ptr: NonNull<u8>,
+    pages: usize,
+    /// Whether each page of the chunk has been mapped successfully
+    mapped: Vec<bool>,
+}
+
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    /// Returns the mapped bytes of the chunk between `start` and `end`, if all pages are mapped.
+    fn get(&mut self, start: usize, end: usize) -> Option<&mut [u8]> {
+        if end > self.pages * PAGE_SIZE
+            || !self.mapped[start / PAGE_SIZE..(end + PAGE_SIZE - 1) / PAGE_SIZE]
+                .iter()
+                .all(|&m| m)
+        {
+            return None;
+        }
+
+        // Safety: the range is part of the mapping, which lives as long as self
+        Some(unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr().add(start), end - start) })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            (self.handles.libs.xenforeignmemory_unmap)(
+                self.handles.fmem.as_ptr(),
+                self.ptr.as_ptr() as *mut c_void,
+                self.pages,
+            );
+        }
+    }
+}
+
+/// Physical memory of a Xen domain.
+pub struct XenMemory {
+    handles: Arc<Handles>,
+    domid: u32,
+    size: umem,
+    prot: c_int,
+    mappings: VecDeque<Mapping>,
+    cached_chunks: usize,
+}
+
+impl Clone for XenMemory {
+    /// Shares the Xen interfaces, mappings are not shared between clones.
+    fn clone(&self) -> Self {
+        Self {
+            handles: self.handles.clone(),
+            domid: self.domid,
+            size: self.size,
+            prot: self.prot,
+            mappings: VecDeque::new(),
+            cached_chunks: self.cached_chunks,
+        }
+    }
+}
+
+impl XenMemory {
+    /// Connects to the domain with the id `domid`.
+    ///
+    /// Guest memory is mapped writeable, use [`readonly`](Self::readonly) to map it read only.
+    pub fn new(domid: u32) -> Result<Self> {
+        let handles = Arc::new(Handles::open()?);
+
+        let mut max_gpfn: xen_pfn_t = 0;
+        let ret = unsafe {
+            (handles.libs.xc_domain_maximum_gpfn)(handles.xc.as_ptr(), domid, &mut max_gpfn)
+        };
+        if ret < 0 {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::TargetNotFound).log_error(format!(
+                    "unable to query the memory size of domain {}",
+                    domid
+                )),
+            );
+        }
+
+        Ok(Self {
+            handles,
+            domid,
+            size: (max_gpfn as umem + 1) * PAGE_SIZE as umem,
+            prot: PROT_READ | PROT_WRITE,
+            mappings: VecDeque::new(),
+            cached_chunks: DEFAULT_CACHED_CHUNKS,
+        })
+    }
+
+    /// Maps guest memory read only, rejecting all writes.
+    pub fn readonly(mut self) -> Self {
+        self.prot = PROT_READ;
+        self.mappings.clear();
+        self
+    }
+
+    /// Sets the number of chunks kept mapped.
+    pub fn cached_chunks(mut self, cached_chunks: usize) -> Self {
+        self.cached_chunks = cached_chunks.max(1);
+        self.mappings.truncate(self.cached_chunks);
+        self
+    }
+
+    /// Returns the id of the domain.
+    pub fn domid(&self) -> u32 {
+        self.domid
+    }
+
+    /// Releases all mappings of guest memory.
+    ///
+    /// Mappings are not updated when the guest memory layout changes, e.g. after ballooning.
+    pub fn unmap_all(&mut self) {
+        self.mappings.clear();
+    }
+
+    /// Returns the mapping of the chunk `chunk`, mapping it if necessary.
+    fn mapping(&mut self, chunk: umem) -> Result<&mut Mapping> {
+        if let Some(pos) = self.mappings.iter().position(|m| m.chunk == chunk) {
+            let mapping = self.mappings.remove(pos).unwrap();
+            self.mappings.push_front(mapping);
+            return Ok(&mut self.mappings[0]);
+        }
+
+        let first = chunk * CHUNK_PAGES as umem;
+        let pages =
+            std::cmp::min(CHUNK_PAGES as umem, self.size / PAGE_SIZE as umem - first) as usize;
+        let pfns = (0..pages)
+            .map(|i| (first + i as umem) as xen_pfn_t)
+            .collect::<Vec<_>>();
+        let mut err = vec![0 as c_int; pages];
+
+        let ptr = unsafe {
+            (self.handles.libs.xenforeignmemory_map)(
+                self.handles.fmem.as_ptr(),
+                self.domid,
+                self.prot,
+                pages,
+                pfns.as_ptr(),
+                err.as_mut_ptr(),
+            )
+        };
+        let ptr = NonNull::new(ptr as *mut u8).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToMapFile).log_trace(format!(
+                "unable to map guest frames {:#x}..{:#x}",
+                first,
+                first + pages as umem
+            ))
+        })?;
+
+        self.mappings.truncate(self.cached_chunks - 1);
+        self.mappings.push_front(Mapping {
+            handles: self.handles.clone(),
+            chunk,
+            ptr,
+            pages,
+            mapped: err.iter().map(|&e| e == 0).collect(),
+        });
+        Ok(&mut self.mappings[0])
+    }
+
+    /// Calls `func` with the mapped guest memory of every chunk covered by `addr` and `len`.
+    ///
+    /// Returns false if any of the pages is not mapped.
+    fn access(
+        &mut self,
+        addr: Address,
+        len: usize,
+        mut func: impl FnMut(usize, &mut [u8]),
+    ) -> bool {
+        let addr = addr.to_umem();
+        if addr
+            .checked_add(len as umem)
+            .map_or(true, |end| end > self.size)
+        {
+            return false;
+        }
+
+        let mut done = 0;
+        while done < len {
+            let cur = addr + done as umem;
+            let chunk = cur / CHUNK_SIZE as umem;
+            let start = (cur % CHUNK_SIZE as umem) as usize;
+            let end = std::cmp::min(CHUNK_SIZE, start + len - done);
+
+            let mem = match self.mapping(chunk) {
+                Ok(mapping) => mapping.get(start, end),
+                Err(_) => None,
+            };
+            match mem {
+                Some(mem) => func(done, mem),
+                None => return false,
+            }
+
+            done += end - start;
+        }
+
+        true
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl PhysicalMemory for XenMemory {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in data.inp {
+            let len = buf.len();
+            let ok = self.access(addr.address(), len, |off, mem| {
+                buf[off..off + mem.len()].copy_from_slice(mem)
+            });
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        if self.prot & PROT_WRITE == 0 {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("guest memory is mapped read only"));
+        }
+
+        for CTup3(addr, meta_addr, buf) in data.inp {
+            let ok = self.access(addr.address(), buf.len(), |off, mem| {
+                mem.copy_from_slice(&buf[off..off + mem.len()])
+            });
+            if ok {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: (self.size - 1).into(),
+            real_size: self.size,
+            readonly: self.prot & PROT_WRITE == 0,
+            ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(XenMemory, crate::plugins::ConnectorInstance, {});
@@ -0,0 +1,368 @@
+//! Connector serving physical memory from zstd compressed snapshots.
+//!
+//! Snapshots are stored in the [zstd seekable format](https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md):
+//! the raw image is split into independently compressed frames, followed by a seek table
+//! mapping every frame to its position in the raw image. This allows serving reads by only
+//! decompressing the frames that are actually needed. Recently used frames are kept in a small
+//! LRU cache, so sequential or localized reads only decompress every frame once.
+//!
+//! Seekable archives can be produced with [`compress_snapshot`] or with the `zstd` seekable
+//! format tooling. Frame checksums are not verified.
+//!
+//! # Examples
+//! ```
+//! use memflow::connector::zstd_snapshot::{compress_snapshot, ZstdSnapshotMemory};
+//! use memflow::mem::{MemoryView, PhysicalMemory};
+//! use std::io::Cursor;
+//!
+//! fn snapshot(mem: &mut impl PhysicalMemory) -> ZstdSnapshotMemory<Cursor<Vec<u8>>> {
+//!     let mut archive = Cursor::new(vec![]);
+//!     compress_snapshot(mem, &mut archive, 0x10000, 3).unwrap();
+//!
+//!     ZstdSnapshotMemory::new(archive).unwrap()
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # let mut mem = DummyMemory::new(size::mb(1));
+//! # mem.phys_write(0x1000.into(), &0xdeadbeefu32).unwrap();
+//! # let mut snapshot = snapshot(&mut mem);
+//! # assert_eq!(snapshot.phys_view().read::<u32>(0x1000.into()).unwrap(), 0xdeadbeef);
+//! ```
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::umem;
+
+use crate::cglue::*;
+
+/// Magic number of the skippable frame containing the seek table.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a5e;
+/// Magic number at the very end of a seekable archive.
+const SEEKABLE_MAGIC: u32 = 0x8f92_eab1;
+/// Size of the seek table footer.
+const FOOTER_SIZE: u64 = 9;
+
+/// Default number of decompressed frames kept in memory.
+const DEFAULT_CACHE_FRAMES: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+struct SeekEntry {
+    /// Offset of the compressed frame in the archive
+    compressed_offset: u64,
+    compressed_size: u32,
+    /// Offset of the decompressed frame in the raw image
+    offset: umem,
+    size: u32,
+}
+
+/// Read-only physical memory backed by a zstd seekable archive.
+#[derive(Clone)]
+pub struct ZstdSnapshotMemory<T> {
+    reader: T,
+    frames: Vec<SeekEntry>,
+    cache: VecDeque<(usize, Vec<u8>)>,
+    cache_frames: usize,
+    size: umem,
+}
+
+impl<T: Read + Seek + Send> ZstdSnapshotMemory<T> {
+    /// Opens the seekable archive in `reader`.
+    pub fn new(reader: T) -> Result<Self> {
+        Self::with_cache_frames(reader, DEFAULT_CACHE_FRAMES)
+    }
+
+    /// Opens the seekable archive in `reader`, keeping up to `cache_frames` decompressed frames
+    /// in memory.
+    pub fn with_cache_frames(mut reader: T, cache_frames: usize) -> Result<Self> {
+        let frames = read_seek_table(&mut reader)?;
+        let size = frames
+            .last()
+            .map(|f| f.offset + f.size as umem)
+            .unwrap_or(0);
+
+        Ok(Self {
+            reader,
+            frames,
+            cache: VecDeque::new(),
+            cache_frames: cache_frames.max(1),
+            size,
+        })
+    }
+
+    /// Returns the number of compressed frames in the archive.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Consumes self and returns the underlying reader.
+    pub fn into_inner(self) -> T {
+        self.reader
+    }
+
+    /// Returns the index of the frame containing `offset` of the raw image.
+    fn frame_index(&self, offset: umem) -> Option<usize> {
+        let idx = self
+            .frames
+            .partition_point(|f| f.offset + f.size as umem <= offset);
+        Some(idx).filter(|&idx| idx < self.frames.len())
+    }
+
+    /// Returns the decompressed contents of the frame at `idx`, going through the cache.
+    fn frame(&mut self, idx: usize) -> Result<&[u8]> {
+        if let Some(pos) = self.cache.iter().position(|(i, _)| *i == idx) {
+            let entry = self.cache.remove(pos).unwrap();
+            self.cache.push_front(entry);
+        } else {
+            let entry = self.frames[idx];
+
+            let mut compressed = vec![0u8; entry.compressed_size as usize];
+            self.reader
+                .seek(SeekFrom::Start(entry.compressed_offset))
+                .map_err(|err| {
+                    Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err)
+                })?;
+            self.reader.read_exact(&mut compressed).map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+            })?;
+
+            let data = zstd::bulk::decompress(&compressed, entry.size as usize)
+                .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(err))?;
+
+            if data.len() != entry.size as usize {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                    .log_error("decompressed frame size does not match the seek table"));
+            }
+
+            self.cache.truncate(self.cache_frames - 1);
+            self.cache.push_front((idx, data));
+        }
+
+        Ok(&self.cache[0].1)
+    }
+
+    /// Fills `buf` with the contents of the raw image starting at `offset`.
+    fn read_image(&mut self, mut offset: umem, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let idx = self.frame_index(offset).ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds)
+                    .log_trace("read beyond the end of the snapshot")
+            })?;
+
+            let start = (offset - self.frames[idx].offset) as usize;
+            let data = &self.frame(idx)?[start..];
+
+            let len = std::cmp::min(buf.len(), data.len());
+            let (head, tail) = std::mem::take(&mut buf).split_at_mut(len);
+            head.copy_from_slice(&data[..len]);
+
+            buf = tail;
+            offset += len as umem;
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: Read + Seek + Send> PhysicalMemory for ZstdSnapshotMemory<T> {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in data.inp {
+            if self.read_image(addr.to_umem(), &mut buf).is_ok() {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(data.out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_error("compressed snapshots are not writeable"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.size.saturating_sub(1).into(),
+            real_size: self.size,
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(
+    ZstdSnapshotMemory<T: Read + Seek + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+/// Parses the seek table at the end of the archive.
+fn read_seek_table(reader: &mut (impl Read + Seek)) -> Result<Vec<SeekEntry>> {
+    let invalid = |msg| Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg);
+    let seek_err = |err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err);
+    let read_err = |err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err);
+
+    let end = reader.seek(SeekFrom::End(0)).map_err(seek_err)?;
+    if end < FOOTER_SIZE + 8 {
+        return Err(invalid("file is too small to be a seekable archive"));
+    }
+
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    reader
+        .seek(SeekFrom::Start(end - FOOTER_SIZE))
+        .map_err(seek_err)?;
+    reader.read_exact(&mut footer).map_err(read_err)?;
+
+    if u32::from_le_bytes(footer[5..9].try_into().unwrap()) != SEEKABLE_MAGIC {
+        return Err(invalid("seekable archive magic not found"));
+    }
+
+    let frame_count = u32::from_le_bytes(footer[0..4].try_into().unwrap()) as u64;
+    let entry_size = if footer[4] & 0x80 != 0 { 12 } else { 8 };
+    let table_size = frame_count * entry_size;
+
+    let table_start = (end - FOOTER_SIZE)
+        .checked_sub(table_size + 8)
+        .ok_or_else(|| invalid("seek table exceeds the file size"))?;
+
+    let mut table = vec![0u8; (table_size + 8) as usize];
+    reader
+        .seek(SeekFrom::Start(table_start))
+        .map_err(seek_err)?;
+    reader.read_exact(&mut table).map_err(read_err)?;
+
+    if u32::from_le_bytes(table[0..4].try_into().unwrap()) != SKIPPABLE_MAGIC
+        || u32::from_le_bytes(table[4..8].try_into().unwrap()) as u64 != table_size + FOOTER_SIZE
+    {
+        return Err(invalid("invalid seek table header"));
+    }
+
+    let mut frames = Vec::with_capacity(frame_count as usize);
+    let (mut compressed_offset, mut offset) = (0u64, 0 as umem);
+
+    for entry in table[8..].chunks_exact(entry_size as usize) {
+        let compressed_size = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+        let size = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+
+        frames.push(SeekEntry {
+            compressed_offset,
+            compressed_size,
+            offset,
+            size,
+        });
+
+        compressed_offset += compressed_size as u64;
+        offset += size as umem;
+    }
+
+    if compressed_offset > table_start {
+        return Err(invalid("compressed frames exceed the file size"));
+    }
+
+    Ok(frames)
+}
+
+/// Writes the entire physical memory of `mem` as a zstd seekable archive to `out`.
+///
+/// The image is split into frames of `frame_size` bytes which are compressed with the given
+/// zstd compression `level`. Smaller frames speed up random access at the cost of a worse
+/// compression ratio. Memory that can not be read is stored as zeroes.
+pub fn compress_snapshot(
+    mem: &mut impl PhysicalMemory,
+    out: &mut impl Write,
+    frame_size: usize,
+    level: i32,
+) -> Result<()> {
+    if frame_size == 0 || frame_size > u32::MAX as usize {
+        return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+            .log_error("frame size has to be between 1 byte and 4 GiB"));
+    }
+
+    let write_err =
+        |err| Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile).log_error(err);
+
+    let size = mem.metadata().max_address.to_umem() + 1;
+    let mut buf = vec![0u8; frame_size];
+    let mut table = vec![];
+    let mut offset = 0 as umem;
+
+    while offset < size {
+        let len = std::cmp::min(frame_size as umem, size - offset) as usize;
+        let buf = &mut buf[..len];
+
+        mem.phys_read_into(offset.into(), buf)?;
+
+        let compressed = zstd::bulk::compress(buf, level)
+            .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(err))?;
+        out.write_all(&compressed).map_err(write_err)?;
+
+        table.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        table.extend_from_slice(&(len as u32).to_le_bytes());
+
+        offset += len as umem;
+    }
+
+    let frame_count = (table.len() / 8) as u32;
+
+    let mut seek_table = vec![];
+    seek_table.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+    seek_table.extend_from_slice(&(table.len() as u32 + FOOTER_SIZE as u32).to_le_bytes());
+    seek_table.extend_from_slice(&table);
+    seek_table.extend_from_slice(&frame_count.to_le_bytes());
+    seek_table.push(0);
+    seek_table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+
+    out.write_all(&seek_table).map_err(write_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn roundtrip() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let data = (0..0x3000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        mem.phys_write(0x7800.into(), data.as_slice()).unwrap();
+
+        let mut archive = Cursor::new(vec![]);
+        compress_snapshot(&mut mem, &mut archive, 0x1000, 1).unwrap();
+
+        let mut snapshot = ZstdSnapshotMemory::with_cache_frames(archive, 2).unwrap();
+        assert_eq!(snapshot.frame_count(), 256);
+        assert_eq!(snapshot.metadata().real_size, size::mb(1) as umem);
+
+        // spans 4 frames, more than the cache holds
+        let read = snapshot
+            .phys_view()
+            .read_raw(0x7800.into(), 0x3000)
+            .unwrap();
+        assert_eq!(read, data);
+
+        assert!(snapshot
+            .phys_view()
+            .read_raw((size::mb(1) - 4).into(), 8)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_archive() {
+        assert!(ZstdSnapshotMemory::new(Cursor::new(vec![0u8; 64])).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! Deterministic synthetic memory images for tests.
+//!
+//! The [`TestImageBuilder`] generates small memory images that look like a running Windows or
+//! Linux system to code parsing OS structures: the kernel is mapped through valid x64 page
+//! tables at a randomized base in the upper half of the address space, contains a version
+//! marker of the requested version, and a circular doubly linked list of fake process
+//! structures, each with its own page tables.
+//!
+//! The same seed always produces the exact same image, so regression tests for OS-layer parsing
+//! can run in CI without shipping real memory dumps. The structure layouts are described by
+//! [`ProcessListLayout`] and do not match any real kernel build, tests should read offsets from
+//! the layout instead of hardcoding them.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::corpus::TestImageBuilder;
+//! use memflow::mem::MemoryView;
+//! use memflow::types::Address;
+//!
+//! let image = TestImageBuilder::windows()
+//!     .seed(7)
+//!     .version(10, 0, 19041)
+//!     .process(4, "System")
+//!     .process(1234, "notepad.exe")
+//!     .build();
+//!
+//! let layout = image.layout;
+//! let mut kernel = image.kernel_view();
+//!
+//! // walk the process list like an OS plugin would
+//! let mut entry: Address = kernel.read_addr64(image.process_list_head).unwrap();
+//! let mut pids = vec![];
+//! while entry != image.process_list_head {
+//!     let process = entry - layout.links;
+//!     pids.push(kernel.read::<u32>(process + layout.pid).unwrap());
+//!     entry = kernel.read_addr64(entry).unwrap();
+//! }
+//! assert_eq!(pids, vec![4, 1234]);
+//! ```
+
+use std::prelude::v1::*;
+
+use super::{DummyMemory, DummyOs};
+use crate::architecture::x86::{x64, X86VirtualTranslate};
+use crate::mem::{DirectTranslate, MemoryView, VirtualDma};
+use crate::os::Pid;
+use crate::types::{size, Address};
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+/// Size of the mapped kernel region, containing the image and all process structures.
+const KERNEL_SIZE: usize = size::kb(256);
+
+/// Offset of the version marker inside of the kernel region.
+const VERSION_OFFSET: usize = 0x1000;
+
+/// Offset of the process list head inside of the kernel region.
+const LIST_HEAD_OFFSET: usize = 0x2000;
+
+/// Offset of the first process structure inside of the kernel region.
+const PROCESSES_OFFSET: usize = 0x4000;
+
+/// Size of the user space mapping of every process.
+const PROCESS_SIZE: usize = size::kb(64);
+
+/// Operating system family imitated by a test image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ImageFamily {
+    /// NT kernel with a PE image and a `NtBuildNumber` style version block
+    Windows,
+    /// Linux kernel with a `linux_banner` style version string
+    Linux,
+}
+
+impl ImageFamily {
+    /// Returns the address the kernel is linked at, before the randomized slide is applied.
+    pub const fn kernel_link_base(self) -> u64 {
+        match self {
+            ImageFamily::Windows => 0xfffff800_00000000,
+            ImageFamily::Linux => 0xffffffff_80000000,
+        }
+    }
+}
+
+/// Offsets of the fake process structure of a test image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProcessListLayout {
+    /// Offset of the `LIST_ENTRY` / `list_head` linking all processes
+    pub links: usize,
+    /// Offset of the 32 bit process id
+    pub pid: usize,
+    /// Offset of the nul terminated process name
+    pub name: usize,
+    /// Maximum length of the process name, including the nul terminator
+    pub name_len: usize,
+    /// Offset of the physical address of the page table root of the process
+    pub dtb: usize,
+    /// Offset of the virtual base address of the user space mapping of the process
+    pub base: usize,
+    /// Size of the whole structure
+    pub size: usize,
+}
+
+impl ProcessListLayout {
+    /// Layout of the fake `EPROCESS` structures of Windows images.
+    pub const fn windows() -> Self {
+        Self {
+            links: 0x448,
+            pid: 0x440,
+            name: 0x5a8,
+            name_len: 15,
+            dtb: 0x28,
+            base: 0x520,
+            size: 0x850,
+        }
+    }
+
+    /// Layout of the fake `task_struct` structures of Linux images.
+    pub const fn linux() -> Self {
+        Self {
+            links: 0x7e0,
+            pid: 0x8d8,
+            name: 0xa90,
+            name_len: 16,
+            dtb: 0x50,
+            base: 0x58,
+            size: 0xc00,
+        }
+    }
+
+    /// Returns the layout used for images of the given family.
+    pub const fn for_family(family: ImageFamily) -> Self {
+        match family {
+            ImageFamily::Windows => Self::windows(),
+            ImageFamily::Linux => Self::linux(),
+        }
+    }
+}
+
+/// A process contained in a test image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestProcess {
+    /// Process id
+    pub pid: Pid,
+    /// Process name, truncated to the name length of the layout
+    pub name: String,
+    /// Kernel address of the process structure
+    pub address: Address,
+    /// Physical address of the page table root of the process
+    pub dtb: Address,
+    /// Base of the user space mapping of the process
+    pub base: Address,
+    /// Size of the user space mapping of the process
+    pub size: usize,
+}
+
+impl TestProcess {
+    /// Returns the translator for the address space of the process.
+    pub fn translator(&self) -> X86VirtualTranslate {
+        x64::new_translator(self.dtb)
+    }
+}
+
+/// A generated memory image along with everything needed to verify parsers against it.
+#[derive(Clone)]
+pub struct TestImage {
+    /// Physical memory of the image
+    pub mem: DummyMemory,
+    /// Family of the image
+    pub family: ImageFamily,
+    /// Major, minor and build number of the imitated kernel
+    pub version: (u32, u32, u32),
+    /// Physical address of the page table root of the kernel
+    pub kernel_dtb: Address,
+    /// Base address of the kernel image
+    pub kernel_base: Address,
+    /// Size of the mapped kernel region
+    pub kernel_size: usize,
+    /// Address of the version marker
+    ///
+    /// On Windows images this holds the major and minor version, followed by the build number in
+    /// the `NtBuildNumber` encoding. On Linux images this holds the `linux_banner` string.
+    pub version_address: Address,
+    /// Address of the list head linking all process structures
+    pub process_list_head: Address,
+    /// Layout of the process structures
+    pub layout: ProcessListLayout,
+    /// All processes in list order
+    pub processes: Vec<TestProcess>,
+}
+
+impl TestImage {
+    /// Returns the translator for the kernel address space.
+    pub fn kernel_translator(&self) -> X86VirtualTranslate {
+        x64::new_translator(self.kernel_dtb)
+    }
+
+    /// Returns a view of the kernel address space.
+    pub fn kernel_view(&self) -> VirtualDma<DummyMemory, DirectTranslate, X86VirtualTranslate> {
+        VirtualDma::new(self.mem.clone(), x64::ARCH, self.kernel_translator())
+    }
+
+    /// Returns a view of the address space of `process`.
+    pub fn process_view(
+        &self,
+        process: &TestProcess,
+    ) -> VirtualDma<DummyMemory, DirectTranslate, X86VirtualTranslate> {
+        VirtualDma::new(self.mem.clone(), x64::ARCH, process.translator())
+    }
+
+    /// Returns the version of the image as `major.minor.build` on Windows, or the contents of
+    /// the `linux_banner` on Linux.
+    pub fn version_string(&self) -> String {
+        version_string(self.family, self.version)
+    }
+}
+
+fn version_string(family: ImageFamily, (major, minor, build): (u32, u32, u32)) -> String {
+    match family {
+        ImageFamily::Windows => format!("{}.{}.{}", major, minor, build),
+        ImageFamily::Linux => format!(
+            "Linux version {}.{}.{}-memflow (memflow@corpus) (gcc version 12.2.0) #1 SMP\n",
+            major, minor, build
+        ),
+    }
+}
+
+/// Builder for deterministic [`TestImage`]s.
+#[derive(Clone, Debug)]
+pub struct TestImageBuilder {
+    family: ImageFamily,
+    seed: u64,
+    mem_size: usize,
+    version: (u32, u32, u32),
+    processes: Vec<(Pid, String)>,
+}
+
+impl TestImageBuilder {
+    /// Creates a builder for images of the given family with default settings.
+    ///
+    /// By default the image has 16 mb of memory, seed 0, no processes and the version of
+    /// Windows 10 19045 or Linux 6.1.0 respectively.
+    pub fn new(family: ImageFamily) -> Self {
+        Self {
+            family,
+            seed: 0,
+            mem_size: size::mb(16),
+            version: match family {
+                ImageFamily::Windows => (10, 0, 19045),
+                ImageFamily::Linux => (6, 1, 0),
+            },
+            processes: vec![],
+        }
+    }
+
+    /// Creates a builder for Windows images.
+    pub fn windows() -> Self {
+        Self::new(ImageFamily::Windows)
+    }
+
+    /// Creates a builder for Linux images.
+    pub fn linux() -> Self {
+        Self::new(ImageFamily::Linux)
+    }
+
+    /// Sets the seed all randomized parts of the image are derived from.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the size of the physical memory of the image.
+    pub fn memory_size(mut self, mem_size: usize) -> Self {
+        self.mem_size = mem_size;
+        self
+    }
+
+    /// Sets the version of the imitated kernel.
+    ///
+    /// On Linux the build number is used as the patch level.
+    pub fn version(mut self, major: u32, minor: u32, build: u32) -> Self {
+        self.version = (major, minor, build);
+        self
+    }
+
+    /// Adds a process to the end of the process list.
+    pub fn process(mut self, pid: Pid, name: &str) -> Self {
+        self.processes.push((pid, name.to_string()));
+        self
+    }
+
+    /// Generates the image.
+    ///
+    /// # Panics
+    ///
+    /// If the memory of the image is too small, or the process structures do not fit into the
+    /// kernel region.
+    pub fn build(self) -> TestImage {
+        let layout = ProcessListLayout::for_family(self.family);
+        assert!(
+            PROCESSES_OFFSET + self.processes.len() * layout.size <= KERNEL_SIZE,
+            "too many processes for a test image"
+        );
+
+        let mut rng = XorShiftRng::seed_from_u64(self.seed ^ 0x6d65_6d66_6c6f_7721);
+        let kernel_base = Address::from(self.family.kernel_link_base())
+            + rng.gen_range(0..0x100usize) * size::mb(2);
+
+        let mut os = DummyOs::with_seed(DummyMemory::new(self.mem_size), self.seed);
+
+        let list_head = kernel_base + LIST_HEAD_OFFSET;
+        let mut kernel = vec![0u8; KERNEL_SIZE];
+
+        let processes = self
+            .processes
+            .iter()
+            .enumerate()
+            .map(|(i, (pid, name))| {
+                let (dtb, base) = os.alloc_dtb(PROCESS_SIZE, &[]);
+                let mut name = name.clone();
+                name.truncate(layout.name_len - 1);
+                TestProcess {
+                    pid: *pid,
+                    name,
+                    address: kernel_base + PROCESSES_OFFSET + i * layout.size,
+                    dtb,
+                    base,
+                    size: PROCESS_SIZE,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // circular doubly linked list through all processes and the list head
+        let links = std::iter::once(list_head)
+            .chain(processes.iter().map(|p| p.address + layout.links))
+            .collect::<Vec<_>>();
+        for (i, &entry) in links.iter().enumerate() {
+            let next = links[(i + 1) % links.len()];
+            let prev = links[(i + links.len() - 1) % links.len()];
+            let offset = (entry - kernel_base) as usize;
+            put_u64(&mut kernel, offset, next.to_umem() as u64);
+            put_u64(&mut kernel, offset + 8, prev.to_umem() as u64);
+        }
+
+        for p in processes.iter() {
+            let offset = (p.address - kernel_base) as usize;
+            put_u32(&mut kernel, offset + layout.pid, p.pid);
+            put_u64(&mut kernel, offset + layout.dtb, p.dtb.to_umem() as u64);
+            put_u64(&mut kernel, offset + layout.base, p.base.to_umem() as u64);
+            let name = offset + layout.name;
+            kernel[name..(name + p.name.len())].copy_from_slice(p.name.as_bytes());
+        }
+
+        let (major, minor, build) = self.version;
+        match self.family {
+            ImageFamily::Windows => {
+                write_pe_header(&mut kernel, kernel_base);
+                put_u32(&mut kernel, VERSION_OFFSET, major);
+                put_u32(&mut kernel, VERSION_OFFSET + 4, minor);
+                put_u32(&mut kernel, VERSION_OFFSET + 8, build | 0xf000_0000);
+            }
+            ImageFamily::Linux => {
+                let banner = version_string(self.family, self.version);
+                kernel[VERSION_OFFSET..(VERSION_OFFSET + banner.len())]
+                    .copy_from_slice(banner.as_bytes());
+            }
+        }
+
+        let kernel_dtb = os.alloc_dtb_const_base(kernel_base, KERNEL_SIZE, &kernel);
+        let mem = os.into_inner();
+
+        // fill the user space of every process with a pattern identifying it
+        for p in processes.iter() {
+            let pattern = (0..PROCESS_SIZE)
+                .map(|i| (i as u32 ^ p.pid) as u8)
+                .collect::<Vec<_>>();
+            VirtualDma::new(mem.clone(), x64::ARCH, p.translator())
+                .write_raw(p.base, &pattern)
+                .unwrap();
+        }
+
+        TestImage {
+            mem,
+            family: self.family,
+            version: self.version,
+            kernel_dtb,
+            kernel_base,
+            kernel_size: KERNEL_SIZE,
+            version_address: kernel_base + VERSION_OFFSET,
+            process_list_head: list_head,
+            layout,
+            processes,
+        }
+    }
+}
+
+/// Writes a minimal 64 bit PE header describing the kernel region.
+fn write_pe_header(kernel: &mut [u8], image_base: Address) {
+    const PE_OFFSET: usize = 0x80;
+
+    kernel[0..2].copy_from_slice(b"MZ");
+    put_u32(kernel, 0x3c, PE_OFFSET as u32);
+    kernel[PE_OFFSET..(PE_OFFSET + 4)].copy_from_slice(b"PE\0\0");
+
+    // IMAGE_FILE_HEADER: AMD64, no sections, size of the optional header
+    put_u16(kernel, PE_OFFSET + 4, 0x8664);
+    put_u16(kernel, PE_OFFSET + 20, 0xf0);
+    put_u16(kernel, PE_OFFSET + 22, 0x22);
+
+    // IMAGE_OPTIONAL_HEADER64: magic, image base, alignments and sizes
+    let opt = PE_OFFSET + 24;
+    put_u16(kernel, opt, 0x20b);
+    put_u64(kernel, opt + 24, image_base.to_umem() as u64);
+    put_u32(kernel, opt + 32, size::kb(4) as u32);
+    put_u32(kernel, opt + 36, 0x200);
+    put_u32(kernel, opt + 56, KERNEL_SIZE as u32);
+    put_u32(kernel, opt + 60, size::kb(4) as u32);
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..(offset + 2)].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..(offset + 4)].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..(offset + 8)].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::PhysicalMemory;
+
+    #[test]
+    fn deterministic() {
+        let build = |seed| {
+            TestImageBuilder::linux()
+                .seed(seed)
+                .memory_size(size::mb(4))
+                .process(1, "systemd")
+                .build()
+        };
+
+        let mut a = build(3);
+        let mut b = build(3);
+        assert_eq!(a.kernel_base, b.kernel_base);
+        assert_eq!(a.kernel_dtb, b.kernel_dtb);
+        assert_eq!(a.processes, b.processes);
+
+        let mut buf_a = vec![0u8; size::mb(4)];
+        let mut buf_b = vec![0u8; size::mb(4)];
+        a.mem.phys_read_into(0.into(), &mut buf_a[..]).unwrap();
+        b.mem.phys_read_into(0.into(), &mut buf_b[..]).unwrap();
+        assert!(buf_a == buf_b);
+
+        let banner = a.version_string();
+        let mut read = vec![0u8; banner.len()];
+        a.kernel_view()
+            .read_raw_into(a.version_address, &mut read)
+            .unwrap();
+        assert_eq!(read, banner.as_bytes());
+        assert!(banner.starts_with("Linux version 6.1.0-"));
+
+        let process = a.processes[0].clone();
+        let name: [u8; 8] = a
+            .kernel_view()
+            .read(process.address + a.layout.name)
+            .unwrap();
+        assert_eq!(&name, b"systemd\0");
+        let value: u8 = a
+            .process_view(&process)
+            .read(process.base + 7usize)
+            .unwrap();
+        assert_eq!(value, 7 ^ 1);
+    }
+
+    #[test]
+    fn windows_version() {
+        let image = TestImageBuilder::windows().version(6, 1, 7601).build();
+        let mut kernel = image.kernel_view();
+
+        assert_eq!(kernel.read::<[u8; 2]>(image.kernel_base).unwrap(), *b"MZ");
+        let version: [u32; 3] = kernel.read(image.version_address).unwrap();
+        assert_eq!(version, [6, 1, 7601 | 0xf000_0000]);
+
+        // an empty process list points back to its head
+        let head: Address = kernel.read_addr64(image.process_list_head).unwrap();
+        assert_eq!(head, image.process_list_head);
+    }
+}
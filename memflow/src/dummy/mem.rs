@@ -3,9 +3,12 @@ use crate::connector::MappedPhysicalMemory;
 use crate::derive::connector;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::mem::mem_data::*;
-use crate::mem::{MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata,
+};
 use crate::plugins::*;
-use crate::types::{size, umem, Address};
+use crate::types::{size, umem, Address, PhysicalAddress};
 
 cglue_impl_group!(DummyMemory, ConnectorInstance, {});
 
@@ -69,6 +72,21 @@ impl PhysicalMemory for DummyMemory {
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
 }
 
 pub fn parse_size(args: &Args) -> Result<usize> {
@@ -1,3 +1,4 @@
+pub mod corpus;
 pub mod mem;
 pub mod os;
 pub mod process;
@@ -15,7 +15,8 @@ use rand_xorshift::XorShiftRng;
 use std::collections::VecDeque;
 use std::convert::TryInto;
 
-use crate::architecture::x86::{x64, X86VirtualTranslate};
+use crate::architecture::x86::x64;
+use crate::mem::virt_translate::{translator_for, ArchTranslate};
 
 use x86_64::{
     structures::paging,
@@ -231,6 +232,7 @@ impl DummyOs {
                 base: Address::INVALID,
                 size: 0,
                 arch: ArchitectureIdent::X86(64, false),
+                version: OsVersion::default(),
             },
         }
     }
@@ -445,7 +447,7 @@ impl DummyOs {
     }
 }
 
-pub type DummyVirtMem<T> = VirtualDma<T, DirectTranslate, X86VirtualTranslate>;
+pub type DummyVirtMem<T> = VirtualDma<T, DirectTranslate, ArchTranslate>;
 
 impl Os for DummyOs {
     type ProcessType<'a> = DummyProcess<DummyVirtMem<Fwd<&'a mut DummyMemory>>>;
@@ -482,12 +484,10 @@ impl Os for DummyOs {
             .find(|p| p.info.address == info.address)
             .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo))?
             .clone();
+        // a registered translation scheme may target a different architecture
+        let translator = translator_for(proc.info.proc_arch, proc.dtb)?;
         Ok(DummyProcess {
-            mem: VirtualDma::new(
-                self.mem.forward_mut(),
-                x64::ARCH,
-                x64::new_translator(proc.dtb),
-            ),
+            mem: VirtualDma::new(self.mem.forward_mut(), translator.arch(), translator),
             proc,
         })
     }
@@ -506,8 +506,10 @@ impl Os for DummyOs {
             .find(|p| p.info.address == info.address)
             .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo))?
             .clone();
+        // a registered translation scheme may target a different architecture
+        let translator = translator_for(proc.info.proc_arch, proc.dtb)?;
         Ok(DummyProcess {
-            mem: VirtualDma::new(self.mem, x64::ARCH, x64::new_translator(proc.dtb)),
+            mem: VirtualDma::new(self.mem, translator.arch(), translator),
             proc,
         })
     }
@@ -589,6 +591,11 @@ impl PhysicalMemory for DummyOs {
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
 }
 
 #[doc(hidden)]
@@ -631,3 +638,78 @@ pub fn create_dummy(args: &OsArgs, lib: LibArc) -> Result<OsInstanceArcBox<'stat
     let obj = group_obj!((os, lib) as OsInstance);
     Ok(obj)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::arm::aarch64;
+    use crate::architecture::ArchitectureObj;
+    use crate::mem::virt_translate::{
+        register_translation_scheme, unregister_translation_scheme, TranslationScheme,
+    };
+    use crate::types::PhysicalAddress;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Translates through the page tables of the dummy os and counts the translations.
+    struct CountingScheme(AtomicUsize);
+
+    impl TranslationScheme for CountingScheme {
+        fn translate(
+            &self,
+            mem: &mut dyn PhysicalMemory,
+            base: Address,
+            addr: Address,
+        ) -> Result<PhysicalAddress> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            x64::new_translator(base).virt_to_phys(&mut DynForward(mem), addr)
+        }
+
+        fn arch(&self) -> ArchitectureObj {
+            aarch64::ARCH
+        }
+    }
+
+    struct DynForward<'a>(&'a mut dyn PhysicalMemory);
+
+    impl PhysicalMemory for DynForward<'_> {
+        fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+            self.0.phys_read_raw_iter(data)
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.0.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.0.metadata()
+        }
+    }
+
+    static SCHEME: CountingScheme = CountingScheme(AtomicUsize::new(0));
+
+    #[test]
+    fn registered_translation_scheme() {
+        // the registry is global, use an architecture no other test registers a scheme for
+        let arch = ArchitectureIdent::AArch64(size::kb(64));
+
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+        let pid = os.alloc_process(size::mb(1), &[0xaa; 0x10]);
+        let proc = os.processes.iter_mut().find(|p| p.info.pid == pid).unwrap();
+        proc.info.proc_arch = arch;
+        let info = proc.info.clone();
+
+        // there is no built-in translator for 64 KiB pages
+        assert!(os.process_by_info(info.clone()).is_err());
+
+        register_translation_scheme(arch, &SCHEME);
+        {
+            let mut proc = os.process_by_info(info.clone()).unwrap();
+            assert_eq!(proc.mem.proc_arch().ident(), aarch64::ARCH.ident());
+            assert_eq!(proc.read::<[u8; 0x10]>(info.address).unwrap(), [0xaa; 0x10]);
+        }
+        assert!(SCHEME.0.load(Ordering::SeqCst) > 0);
+
+        assert!(unregister_translation_scheme(arch));
+        assert!(os.process_by_info(info).is_err());
+    }
+}
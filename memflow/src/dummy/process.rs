@@ -173,6 +173,10 @@ impl<T: MemoryView> MemoryView for DummyProcess<T> {
     fn metadata(&self) -> MemoryViewMetadata {
         self.mem.metadata()
     }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        self.mem.prefetch(addr, len)
+    }
 }
 
 #[cfg(test)]
@@ -9,10 +9,13 @@ use std::{fmt, result, str};
 use log::{debug, error, info, trace, warn};
 
 use crate::cglue::IntError;
+use crate::types::{umem, Address};
 
 #[cfg(feature = "std")]
 use std::error;
 
+pub mod stats;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Error(pub ErrorOrigin, pub ErrorKind);
 
@@ -28,29 +31,64 @@ impl Error {
     }
 
     pub fn log_error(self, err: impl std::fmt::Display) -> Self {
-        error!("{}: {} ({})", self.0.to_str(), self.1.to_str(), err);
+        error!(
+            target: self.0.log_target(),
+            "{}: {} ({})",
+            self.0.to_str(),
+            self.1.to_str(),
+            err
+        );
         self
     }
 
     pub fn log_warn(self, err: impl std::fmt::Display) -> Self {
-        warn!("{}: {} ({})", self.0.to_str(), self.1.to_str(), err);
+        warn!(
+            target: self.0.log_target(),
+            "{}: {} ({})",
+            self.0.to_str(),
+            self.1.to_str(),
+            err
+        );
         self
     }
 
     pub fn log_info(self, err: impl std::fmt::Display) -> Self {
-        info!("{}: {} ({})", self.0.to_str(), self.1.to_str(), err);
+        info!(
+            target: self.0.log_target(),
+            "{}: {} ({})",
+            self.0.to_str(),
+            self.1.to_str(),
+            err
+        );
         self
     }
 
     pub fn log_debug(self, err: impl std::fmt::Display) -> Self {
-        debug!("{}: {} ({})", self.0.to_str(), self.1.to_str(), err);
+        debug!(
+            target: self.0.log_target(),
+            "{}: {} ({})",
+            self.0.to_str(),
+            self.1.to_str(),
+            err
+        );
         self
     }
 
     pub fn log_trace(self, err: impl std::fmt::Display) -> Self {
-        trace!("{}: {} ({})", self.0.to_str(), self.1.to_str(), err);
+        trace!(
+            target: self.0.log_target(),
+            "{}: {} ({})",
+            self.0.to_str(),
+            self.1.to_str(),
+            err
+        );
         self
     }
+
+    /// Attaches the location of the failed memory access to the error.
+    pub fn at(self, address: Address, len: umem) -> AccessError {
+        AccessError::new(self, address, len)
+    }
 }
 
 impl IntError for Error {
@@ -103,6 +141,69 @@ impl<T> From<PartialError<T>> for Error {
     }
 }
 
+/// A failed memory access together with its location.
+///
+/// The origin of the contained [`Error`] identifies the layer the access failed in. For example
+/// an unmapped page is reported with [`ErrorOrigin::VirtualTranslate`], while an I/O failure of
+/// the connector is reported with [`ErrorOrigin::Connector`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AccessError {
+    /// The error that caused the access to fail
+    pub error: Error,
+    /// The first address that could not be accessed
+    pub address: Address,
+    /// The number of bytes starting at `address` that could not be accessed
+    pub len: umem,
+}
+
+impl AccessError {
+    pub fn new(error: Error, address: Address, len: umem) -> Self {
+        Self {
+            error,
+            address,
+            len,
+        }
+    }
+
+    /// Returns the layer the access failed in.
+    pub fn origin(&self) -> ErrorOrigin {
+        self.error.0
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.error.1
+    }
+
+    /// Returns true if the access failed because the address is not mapped.
+    pub fn is_unmapped(&self) -> bool {
+        self.error.0 == ErrorOrigin::VirtualTranslate
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:x} ({:x} bytes)",
+            self.error, self.address, self.len
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for AccessError {
+    fn description(&self) -> &str {
+        self.error.as_str()
+    }
+}
+
+impl From<AccessError> for Error {
+    fn from(err: AccessError) -> Self {
+        err.error
+    }
+}
+
 impl From<ErrorOrigin> for Error {
     fn from(origin: ErrorOrigin) -> Self {
         Error(origin, ErrorKind::Unknown)
@@ -171,6 +272,57 @@ impl ErrorOrigin {
             ErrorOrigin::Other => "other",
         }
     }
+
+    /// Returns the log target messages originating from this layer are logged to.
+    ///
+    /// The targets allow filtering log output per layer, e.g. `RUST_LOG=memflow::cache=trace`.
+    pub fn log_target(self) -> &'static str {
+        match self {
+            ErrorOrigin::Memory
+            | ErrorOrigin::MemoryMap
+            | ErrorOrigin::PhysicalMemory
+            | ErrorOrigin::Connector => log_target::CONNECTOR,
+
+            ErrorOrigin::Cache | ErrorOrigin::TlbCache | ErrorOrigin::PageCache => {
+                log_target::CACHE
+            }
+
+            ErrorOrigin::Pointer
+            | ErrorOrigin::Mmu
+            | ErrorOrigin::VirtualTranslate
+            | ErrorOrigin::VirtualMemory => log_target::TRANSLATE,
+
+            ErrorOrigin::OsLayer => log_target::OS,
+
+            ErrorOrigin::Args
+            | ErrorOrigin::ArgsValidator
+            | ErrorOrigin::Inventory
+            | ErrorOrigin::Ffi => log_target::PLUGINS,
+
+            ErrorOrigin::Other => log_target::OTHER,
+        }
+    }
+}
+
+/// Log targets of the individual memflow layers.
+///
+/// All errors logged via [`Error::log_error`] and friends are logged to the target of their
+/// [`ErrorOrigin`]. This allows enabling verbose output for a single layer only.
+pub mod log_target {
+    /// Physical memory, memory maps and connectors.
+    pub const CONNECTOR: &str = "memflow::connector";
+    /// Page caches and translation caches.
+    pub const CACHE: &str = "memflow::cache";
+    /// Virtual address translation and virtual memory.
+    pub const TRANSLATE: &str = "memflow::translate";
+    /// Os layers.
+    pub const OS: &str = "memflow::os";
+    /// Plugin inventory, arguments and the ffi boundary.
+    pub const PLUGINS: &str = "memflow::plugins";
+    /// Everything that does not belong to a specific layer.
+    pub const OTHER: &str = "memflow";
+    /// Slow memory accesses reported by the `TracedPhysicalMemory` middleware.
+    pub const READ_TRACE: &str = "memflow::trace";
 }
 
 #[repr(u16)]
@@ -230,6 +382,15 @@ pub enum ErrorKind {
     ExportNotFound,
     ImportNotFound,
     SectionNotFound,
+    TargetChanged,
+
+    Cancelled,
+
+    // kinds cross the plugin boundary as their discriminant, so new kinds
+    // have to be appended right before `Unknown` to keep existing codes stable
+    BlockedMemoryRange,
+    ProcessGone,
+    UnableToWriteMemory,
 
     Unknown,
 }
@@ -291,6 +452,13 @@ impl ErrorKind {
             ErrorKind::ExportNotFound => "export not found",
             ErrorKind::ImportNotFound => "import not found",
             ErrorKind::SectionNotFound => "section not found",
+            ErrorKind::TargetChanged => "the target has changed",
+
+            ErrorKind::Cancelled => "the operation was cancelled",
+
+            ErrorKind::BlockedMemoryRange => "access to a blocked memory range",
+            ErrorKind::ProcessGone => "the process has exited",
+            ErrorKind::UnableToWriteMemory => "unable to write memory",
 
             ErrorKind::Unknown => "unknown error",
         }
@@ -495,6 +663,80 @@ mod tests {
         assert_eq!(err.1, ErrorKind::InvalidExeFile);
     }
 
+    #[test]
+    pub fn error_kind_to_from_i32() {
+        // all kinds in declaration order, the discriminants are part of the plugin abi
+        let kinds = [
+            ErrorKind::Uninitialized,
+            ErrorKind::NotSupported,
+            ErrorKind::NotImplemented,
+            ErrorKind::Configuration,
+            ErrorKind::Offset,
+            ErrorKind::Http,
+            ErrorKind::ArgNotExists,
+            ErrorKind::ArgValidation,
+            ErrorKind::RequiredArgNotFound,
+            ErrorKind::InvalidArgument,
+            ErrorKind::PartialData,
+            ErrorKind::NotFound,
+            ErrorKind::OutOfBounds,
+            ErrorKind::OutOfMemoryRange,
+            ErrorKind::Encoding,
+            ErrorKind::InvalidPath,
+            ErrorKind::ReadOnly,
+            ErrorKind::UnableToReadDir,
+            ErrorKind::UnableToReadDirEntry,
+            ErrorKind::UnableToReadFile,
+            ErrorKind::UnableToCreateDirectory,
+            ErrorKind::UnableToWriteFile,
+            ErrorKind::UnableToSeekFile,
+            ErrorKind::UnableToMapFile,
+            ErrorKind::MemoryMapOutOfRange,
+            ErrorKind::UnableToReadMemory,
+            ErrorKind::InvalidArchitecture,
+            ErrorKind::InvalidMemorySize,
+            ErrorKind::InvalidMemorySizeUnit,
+            ErrorKind::UnableToLoadLibrary,
+            ErrorKind::InvalidExeFile,
+            ErrorKind::MemflowExportsNotFound,
+            ErrorKind::VersionMismatch,
+            ErrorKind::AlreadyExists,
+            ErrorKind::PluginNotFound,
+            ErrorKind::TargetNotFound,
+            ErrorKind::InvalidAbi,
+            ErrorKind::UnsupportedOptionalFeature,
+            ErrorKind::ProcessNotFound,
+            ErrorKind::InvalidProcessInfo,
+            ErrorKind::ModuleNotFound,
+            ErrorKind::ExportNotFound,
+            ErrorKind::ImportNotFound,
+            ErrorKind::SectionNotFound,
+            ErrorKind::TargetChanged,
+            ErrorKind::Cancelled,
+            ErrorKind::BlockedMemoryRange,
+            ErrorKind::ProcessGone,
+            ErrorKind::UnableToWriteMemory,
+            ErrorKind::Unknown,
+        ];
+
+        // kinds that existed before have to keep their codes
+        assert_eq!(ErrorKind::SectionNotFound as u16, 43);
+
+        for (i, &kind) in kinds.iter().enumerate() {
+            assert_eq!(kind as usize, i);
+
+            let err = Error::from_int_err(Error(ErrorOrigin::Memory, kind).into_int_err());
+            assert_eq!(err.0, ErrorOrigin::Memory);
+            assert_eq!(err.1, kind);
+        }
+
+        // codes past the end of the enum decode as unknown
+        let past_end = Error::from_int_err(
+            NonZeroI32::new(-(1 + (1 << 4) + ((kinds.len() as i32 + 1) << 16))).unwrap(),
+        );
+        assert_eq!(past_end.1, ErrorKind::Unknown);
+    }
+
     #[test]
     pub fn result_ok_void_ffi() {
         let r: Result<()> = Ok(());
@@ -566,4 +808,16 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.err().unwrap(), PartialError::PartialVirtualWrite(()));
     }
+
+    #[test]
+    pub fn access_error_location() {
+        let err = Error(ErrorOrigin::VirtualTranslate, ErrorKind::OutOfBounds).at(0x1000.into(), 8);
+        assert!(err.is_unmapped());
+        assert_eq!(err.kind(), ErrorKind::OutOfBounds);
+        assert_eq!(
+            err.to_string(),
+            "virtual translate: out of bounds at 1000 (8 bytes)"
+        );
+        assert_eq!(Error::from(err).0, ErrorOrigin::VirtualTranslate);
+    }
 }
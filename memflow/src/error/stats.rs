@@ -0,0 +1,251 @@
+/*!
+Optional collection of failure statistics of the individual memflow layers.
+
+The collector is disabled by default. Once enabled via [`enable`] the memory views count how
+many accesses failed in which layer. A [`snapshot`] of the counters can be queried at
+any time, e.g. to tell apart wrong offsets (many translation failures) from an unreliable
+connector (many connector failures).
+
+Hits and misses of the caches are collected separately by
+[`types::cache::stats`](crate::types::cache::stats).
+
+The counters are process wide and shared by all connectors and os layers. While the collector
+is disabled recording a statistic is a single relaxed atomic load.
+
+# Examples
+```
+use memflow::error::stats;
+use memflow::mem::MemoryView;
+
+# use memflow::dummy::DummyOs;
+# use memflow::types::size;
+# let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+stats::enable();
+
+// reading unmapped memory fails in the translation layer
+let mut buf = [0u8; 8];
+let _ = proc.read_raw_into(0.into(), &mut buf);
+
+let summary = stats::snapshot();
+assert!(summary.translation_failures > 0);
+
+stats::disable();
+```
+*/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// targets without 64 bit atomics fall back to pointer sized counters
+#[cfg(target_has_atomic = "64")]
+type Counter = std::sync::atomic::AtomicU64;
+#[cfg(not(target_has_atomic = "64"))]
+type Counter = std::sync::atomic::AtomicUsize;
+
+use crate::error::Result;
+use crate::mem::mem_data::{opt_call, MemOps};
+use cglue::callback::OpaqueCallback;
+
+/// The individual statistics recorded by the collector.
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ErrorStat {
+    /// A virtual address could not be translated to a physical address.
+    TranslationFailure,
+    /// The connector failed to read an element of a batch.
+    ConnectorReadFailure,
+    /// The connector failed to write an element of a batch.
+    ConnectorWriteFailure,
+    /// A whole read or write batch of the connector returned an error.
+    ConnectorError,
+}
+
+const STAT_COUNT: usize = ErrorStat::ConnectorError as usize + 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static COUNTERS: [Counter; STAT_COUNT] = [
+    Counter::new(0),
+    Counter::new(0),
+    Counter::new(0),
+    Counter::new(0),
+];
+
+/// Snapshot of all statistics recorded since the collector was last reset.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ErrorStats {
+    pub translation_failures: u64,
+    pub connector_read_failures: u64,
+    pub connector_write_failures: u64,
+    pub connector_errors: u64,
+}
+
+impl ErrorStats {
+    /// Returns the total number of failures of the connector.
+    pub fn connector_failures(&self) -> u64 {
+        self.connector_read_failures + self.connector_write_failures + self.connector_errors
+    }
+}
+
+/// Enables the collector.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Disables the collector, already recorded statistics are kept.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns true if the collector is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Resets all statistics to zero.
+pub fn reset() {
+    COUNTERS
+        .iter()
+        .for_each(|counter| counter.store(0, Ordering::Relaxed));
+}
+
+/// Returns the current value of a single statistic.
+#[allow(clippy::unnecessary_cast)]
+pub fn get(stat: ErrorStat) -> u64 {
+    COUNTERS[stat as usize].load(Ordering::Relaxed) as u64
+}
+
+/// Returns a snapshot of all statistics.
+pub fn snapshot() -> ErrorStats {
+    ErrorStats {
+        translation_failures: get(ErrorStat::TranslationFailure),
+        connector_read_failures: get(ErrorStat::ConnectorReadFailure),
+        connector_write_failures: get(ErrorStat::ConnectorWriteFailure),
+        connector_errors: get(ErrorStat::ConnectorError),
+    }
+}
+
+/// Adds `count` to a statistic if the collector is enabled.
+#[inline]
+#[allow(clippy::unnecessary_cast)]
+pub fn record(stat: ErrorStat, count: u64) {
+    if count > 0 && is_enabled() {
+        COUNTERS[stat as usize].fetch_add(count as _, Ordering::Relaxed);
+    }
+}
+
+/// Records a [`ErrorStat::ConnectorError`] if the result of a connector operation is an error.
+#[inline]
+pub(crate) fn record_result<T>(result: &Result<T>) {
+    if result.is_err() {
+        record(ErrorStat::ConnectorError, 1);
+    }
+}
+
+/// Runs a connector operation and records all of its failed elements as `stat`.
+///
+/// The callbacks are only wrapped while the collector is enabled, otherwise this is equivalent
+/// to [`MemOps::with_raw`].
+#[inline]
+pub(crate) fn with_counted_failures<'a, T, P: 'a, O, F: FnOnce(MemOps<T, P>) -> Result<O>>(
+    stat: ErrorStat,
+    iter: impl Iterator<Item = T>,
+    mut out: Option<&mut OpaqueCallback<'a, P>>,
+    mut out_fail: Option<&mut OpaqueCallback<'a, P>>,
+    func: F,
+) -> Result<O> {
+    if !is_enabled() {
+        return MemOps::with_raw(iter, out, out_fail, func);
+    }
+
+    // the callbacks have to be rewrapped together as both of them share the same lifetime
+    let out = &mut |data: P| opt_call(out.as_deref_mut(), data);
+    let out_fail = &mut |data: P| {
+        record(stat, 1);
+        opt_call(out_fail.as_deref_mut(), data)
+    };
+
+    let result = MemOps::with_raw(
+        iter,
+        Some(&mut out.into()),
+        Some(&mut out_fail.into()),
+        func,
+    );
+    record_result(&result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, ErrorKind, ErrorOrigin};
+    use std::sync::Mutex;
+
+    /// Fails every odd element and the whole batch if any element failed.
+    fn run(iter: impl Iterator<Item = u32>, fails: &mut u32) -> Result<()> {
+        let out_fail = &mut |_: u32| {
+            *fails += 1;
+            true
+        };
+        with_counted_failures(
+            ErrorStat::ConnectorWriteFailure,
+            iter,
+            None,
+            Some(&mut out_fail.into()),
+            |MemOps {
+                 inp,
+                 mut out,
+                 mut out_fail,
+             }| {
+                let mut ok = true;
+                for i in inp {
+                    if i % 2 == 0 {
+                        opt_call(out.as_deref_mut(), i);
+                    } else {
+                        ok = false;
+                        opt_call(out_fail.as_deref_mut(), i);
+                    }
+                }
+                if ok {
+                    Ok(())
+                } else {
+                    Err(Error(
+                        ErrorOrigin::Connector,
+                        ErrorKind::UnableToWriteMemory,
+                    ))
+                }
+            },
+        )
+    }
+
+    /// Has to be held by tests enabling the collector, its state is shared by all tests.
+    static COLLECTOR_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn counted_failures() {
+        let _lock = COLLECTOR_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let mut fails = 0;
+
+        // while disabled nothing is recorded, but the callbacks are still invoked
+        disable();
+        let before = snapshot();
+        assert!(run(0..4, &mut fails).is_err());
+        assert_eq!(fails, 2);
+        assert_eq!(snapshot(), before);
+
+        // while enabled every failed element and the failed batch are recorded
+        enable();
+        let before = snapshot();
+        let results = (run(0..4, &mut fails), run(0..1, &mut fails));
+        let after = snapshot();
+        disable();
+
+        assert!(results.0.is_err());
+        assert!(results.1.is_ok());
+        assert_eq!(fails, 4);
+        // failures of tests running in parallel are recorded as well while the collector is
+        // enabled, so only a lower bound of the deltas can be checked
+        assert!(after.connector_write_failures - before.connector_write_failures >= 2);
+        assert!(after.connector_errors - before.connector_errors >= 1);
+    }
+}
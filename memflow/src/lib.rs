@@ -245,6 +245,8 @@ pub mod os;
 
 pub mod iter;
 
+pub mod analysis;
+
 // forward declare
 #[doc(hidden)]
 pub mod derive {
@@ -275,6 +277,7 @@ pub mod dummy;
 #[doc(hidden)]
 pub mod prelude {
     pub mod v1 {
+        pub use crate::analysis::*;
         pub use crate::architecture::*;
         pub use crate::cglue::*;
         pub use crate::connector::*;
@@ -1,5 +1,8 @@
 //! Generic address and buffer association structure.
 
+use std::prelude::v1::*;
+
+use crate::error::{AccessError, Error, ErrorKind, ErrorOrigin};
 use crate::types::{umem, Address, PageType, PhysicalAddress};
 use cglue::callback::{Callbackable, OpaqueCallback};
 use cglue::iter::CIterator;
@@ -129,3 +132,80 @@ pub type PhysicalReadMemOps<'buf, 'a, 'b, 'c> =
     MemOps<'a, 'b, 'c, PhysicalReadData<'buf>, ReadData<'buf>>;
 pub type PhysicalWriteMemOps<'buf, 'a, 'b, 'c> =
     MemOps<'a, 'b, 'c, PhysicalWriteData<'buf>, WriteData<'buf>>;
+
+/// Per element results of a batched memory operation.
+///
+/// Every failed element is described by an [`AccessError`] pointing at its first inaccessible
+/// address, with the length being the number of bytes of the element that failed.
+pub type ChunkResults = Vec<core::result::Result<(), AccessError>>;
+
+/// Maps failed parts of a batched memory operation back to the elements they belong to.
+///
+/// Parts are identified by their buffers, which always point into the buffer of their element,
+/// no matter how often the element was split along the way.
+pub(crate) struct ChunkFailures {
+    /// Buffer start, buffer length and index of every element, sorted by buffer start
+    chunks: Vec<(usize, usize, usize)>,
+    /// Lowest failed address and number of failed bytes of every element
+    failed: Vec<Option<(Address, umem)>>,
+    lens: Vec<usize>,
+}
+
+impl ChunkFailures {
+    pub(crate) fn new(bufs: impl Iterator<Item = (*const u8, usize)>) -> Self {
+        let mut chunks = vec![];
+        let mut lens = vec![];
+        for (idx, (ptr, len)) in bufs.enumerate() {
+            if len > 0 {
+                chunks.push((ptr as usize, len, idx));
+            }
+            lens.push(len);
+        }
+        chunks.sort_unstable_by_key(|&(ptr, _, _)| ptr);
+
+        Self {
+            chunks,
+            failed: vec![None; lens.len()],
+            lens,
+        }
+    }
+
+    /// Records that `len` bytes at `ptr`, read from or written to `addr`, failed.
+    pub(crate) fn fail(&mut self, addr: Address, ptr: *const u8, len: usize) {
+        let ptr = ptr as usize;
+        let pos = self.chunks.partition_point(|&(start, _, _)| start <= ptr);
+        if let Some(&(start, clen, idx)) = pos.checked_sub(1).and_then(|p| self.chunks.get(p)) {
+            if ptr < start + clen {
+                let failed = self.failed[idx].get_or_insert((addr, 0));
+                failed.0 = failed.0.min(addr);
+                failed.1 += len as umem;
+            }
+        }
+    }
+
+    /// Returns the results of all elements and whether any of them failed.
+    ///
+    /// Elements that failed entirely are reported with `kind`, partially failed ones with
+    /// [`ErrorKind::PartialData`].
+    pub(crate) fn into_results(self, kind: ErrorKind) -> (ChunkResults, bool) {
+        let mut any_failed = false;
+        let results = self
+            .failed
+            .into_iter()
+            .zip(self.lens)
+            .map(|(failed, len)| match failed {
+                Some((addr, failed_len)) => {
+                    any_failed = true;
+                    let kind = if failed_len >= len as umem {
+                        kind
+                    } else {
+                        ErrorKind::PartialData
+                    };
+                    Err(Error(ErrorOrigin::Memory, kind).at(addr, failed_len))
+                }
+                None => Ok(()),
+            })
+            .collect();
+        (results, any_failed)
+    }
+}
@@ -210,9 +210,11 @@ impl<M: SplitAtIndex> MemoryMap<M> {
 #[cfg(feature = "serde")]
 #[allow(unused)]
 #[derive(::serde::Deserialize)]
-struct MemoryMapFile {
+pub(crate) struct MemoryMapFile {
     #[serde(rename = "range")]
     ranges: Vec<MemoryMapFileRange>,
+    #[serde(default)]
+    pub(crate) blocked: Vec<MemoryMapFileBlocked>,
 }
 
 #[cfg(feature = "serde")]
@@ -224,6 +226,14 @@ struct MemoryMapFileRange {
     real_base: Option<u64>,
 }
 
+#[cfg(feature = "serde")]
+#[allow(unused)]
+#[derive(::serde::Deserialize)]
+pub(crate) struct MemoryMapFileBlocked {
+    pub(crate) base: u64,
+    pub(crate) length: u64,
+}
+
 // FFI Safe MemoryMapping type for `MemoryMap<(Address, umem)>`.
 // TODO: this could be removed if the RefCell requirement above would be removed.
 #[repr(C)]
@@ -236,6 +246,8 @@ pub struct PhysicalMemoryMapping {
     pub real_base: Address,
 }
 
+pub type PhysicalMemoryMappingCallback<'a> = OpaqueCallback<'a, PhysicalMemoryMapping>;
+
 impl MemoryMap<(Address, umem)> {
     /// Constructs a new memory map by parsing the mapping table from a [TOML](https://toml.io/) file.
     ///
@@ -818,11 +830,17 @@ length=0x1000
 [[range]]
 base=0x2000
 length=0x1000
-real_base=0x3000",
+real_base=0x3000
+
+[[blocked]]
+base=0xfed00000
+length=0x1000",
         )
         .unwrap();
 
         assert_eq!(mappings.ranges.len(), 2);
+        assert_eq!(mappings.blocked.len(), 1);
+        assert_eq!(mappings.blocked[0].base, 0xfed00000);
         assert_eq!(mappings.ranges[0].real_base, None);
         assert_eq!(mappings.ranges[1].real_base, Some(0x3000));
     }
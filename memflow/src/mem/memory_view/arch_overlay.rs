@@ -50,4 +50,8 @@ impl<T: MemoryView> MemoryView for ArchOverlayView<T> {
             ..self.mem.metadata()
         }
     }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        self.mem.prefetch(addr, len)
+    }
 }
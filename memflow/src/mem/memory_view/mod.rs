@@ -8,6 +8,12 @@ use std::prelude::v1::*;
 pub mod arch_overlay;
 pub mod batcher;
 pub mod remap_view;
+#[cfg(feature = "std")]
+pub mod scheduler;
+pub mod stream;
+pub mod transaction;
+pub mod virtual_overlay;
+pub mod watch;
 
 #[cfg(feature = "std")]
 pub mod cursor;
@@ -15,9 +21,15 @@ pub mod cursor;
 pub use arch_overlay::ArchOverlayView;
 pub use batcher::MemoryViewBatcher;
 pub use remap_view::RemapView;
+pub use stream::{MemoryStream, StreamChunk};
+pub use transaction::{MemoryTransaction, MemoryTransactionGuard};
+pub use virtual_overlay::{OverlayRoute, VirtualMemoryOverlay};
+pub use watch::{WatchExpr, WatchId, WatchList, WatchType, WatchValue};
 
 #[cfg(feature = "std")]
 pub use cursor::MemoryCursor;
+#[cfg(feature = "std")]
+pub use scheduler::{CompletedRead, FrameResult, FrameScheduler, ReadRequestId};
 
 /// The `MemoryView` trait implements generic access to memory, no matter if it is a process
 /// virtual memory, or machine's physical memory.
@@ -61,6 +73,35 @@ pub trait MemoryView: Send {
 
     fn metadata(&self) -> MemoryViewMetadata;
 
+    /// Hints that `len` bytes starting at `addr` are about to be read.
+    ///
+    /// Sequential scanners can call this before processing a region, so that caching layers
+    /// below the view fetch the pages in one batch instead of one roundtrip per small read.
+    /// The hint may be ignored, it never fails and never alters memory contents.
+    ///
+    /// By default this is a no-op.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::DummyOs;
+    ///
+    /// fn scan(mem: &mut impl MemoryView, addr: Address) -> usize {
+    ///     mem.prefetch(addr, size::kb(4) as umem);
+    ///
+    ///     (0..size::kb(4))
+    ///         .step_by(8)
+    ///         .filter(|&off| mem.read::<u64>(addr + off).ok() == Some(!0))
+    ///         .count()
+    /// }
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[0xff; 0x1000]);
+    /// # let addr = proc.info().address;
+    /// # assert_eq!(scan(&mut proc, addr), 512);
+    /// ```
+    #[inline]
+    fn prefetch(&mut self, _addr: Address, _len: umem) {}
+
     // Read helpers
 
     /// Read arbitrary amount of data.
@@ -171,6 +212,62 @@ pub trait MemoryView: Send {
         out
     }
 
+    /// Reads a list of chunks and reports the outcome of every chunk individually.
+    ///
+    /// Unlike [`read_raw_list`](Self::read_raw_list), which only reports whether the list was
+    /// read completely, this returns one result per element of `data`, in the same order. Failed
+    /// parts of the chunks are zeroed out, just like with `read_raw_list`.
+    ///
+    /// If any chunk failed, all results are returned as a [`PartialError::PartialVirtualRead`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::DummyOs;
+    /// # let mut proc = DummyOs::quick_process(size::kb(8), &[1, 2, 3, 4]);
+    /// # let base = proc.info().address;
+    ///
+    /// let mut first = [0u8; 4];
+    /// let mut second = [0u8; 4];
+    /// let res = proc.read_raw_list_chunks(&mut [
+    ///     CTup2(base, first.as_mut().into()),
+    ///     CTup2(base + 0x100000usize, second.as_mut().into()),
+    /// ]);
+    ///
+    /// let results = res.data_part().unwrap();
+    /// assert!(results[0].is_ok());
+    /// assert_eq!(results[1].unwrap_err().address, base + 0x100000usize);
+    /// assert_eq!(first, [1, 2, 3, 4]);
+    /// ```
+    #[skip_func]
+    fn read_raw_list_chunks(&mut self, data: &mut [ReadData]) -> PartialResult<ChunkResults> {
+        let mut chunks = ChunkFailures::new(data.iter().map(|CTup2(_, d)| (d.as_ptr(), d.len())));
+
+        let callback = &mut |CTup2(addr, mut d): ReadData| {
+            chunks.fail(addr, d.as_ptr(), d.len());
+
+            for v in d.iter_mut() {
+                *v = 0;
+            }
+
+            true
+        };
+
+        let iter = data
+            .iter_mut()
+            .map(|CTup2(d1, d2)| CTup3(*d1, *d1, d2.into()));
+
+        MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+            self.read_raw_iter(data)
+        })?;
+
+        match chunks.into_results(ErrorKind::UnableToReadMemory) {
+            (results, true) => Err(PartialError::PartialVirtualRead(results)),
+            (results, false) => Ok(results),
+        }
+    }
+
     fn read_raw_into(&mut self, addr: Address, out: &mut [u8]) -> PartialResult<()> {
         self.read_raw_list(&mut [CTup2(addr, out.into())])
     }
@@ -354,6 +451,31 @@ pub trait MemoryView: Send {
         out
     }
 
+    /// Writes a list of chunks and reports the outcome of every chunk individually.
+    ///
+    /// See [`read_raw_list_chunks`](Self::read_raw_list_chunks) for details. If any chunk
+    /// failed, all results are returned as a [`PartialError::PartialVirtualWrite`].
+    #[skip_func]
+    fn write_raw_list_chunks(&mut self, data: &[WriteData]) -> PartialResult<ChunkResults> {
+        let mut chunks = ChunkFailures::new(data.iter().map(|CTup2(_, d)| (d.as_ptr(), d.len())));
+
+        let callback = &mut |CTup2(addr, d): WriteData| {
+            chunks.fail(addr, d.as_ptr(), d.len());
+            true
+        };
+
+        let iter = data.iter().copied();
+
+        MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+            self.write_iter(data.inp, data.out, data.out_fail)
+        })?;
+
+        match chunks.into_results(ErrorKind::UnableToWriteMemory) {
+            (results, true) => Err(PartialError::PartialVirtualWrite(results)),
+            (results, false) => Ok(results),
+        }
+    }
+
     fn write_raw(&mut self, addr: Address, data: &[u8]) -> PartialResult<()> {
         self.write_raw_list(&[CTup2(addr, data.into())])
     }
@@ -484,6 +606,52 @@ pub trait MemoryView: Send {
         MemoryCursor::at(self, address)
     }
 
+    /// Reads `len` bytes starting at `addr` lazily, in chunks of `chunk_size` bytes.
+    ///
+    /// Only a single chunk is kept in memory at any time. See the [stream](stream) module for
+    /// details on how unreadable memory is handled.
+    #[skip_func]
+    fn read_stream(
+        &mut self,
+        addr: Address,
+        len: umem,
+        chunk_size: usize,
+    ) -> MemoryStream<Fwd<&mut Self>>
+    where
+        Self: Sized,
+    {
+        MemoryStream::new(self.forward_mut(), addr, len, chunk_size)
+    }
+
+    /// Consumes the view and reads `len` bytes starting at `addr` lazily, in chunks of
+    /// `chunk_size` bytes.
+    #[skip_func]
+    fn into_read_stream(self, addr: Address, len: umem, chunk_size: usize) -> MemoryStream<Self>
+    where
+        Self: Sized,
+    {
+        MemoryStream::new(self, addr, len, chunk_size)
+    }
+
+    /// Stages a set of writes and applies them as a single transaction.
+    ///
+    /// The original contents of all written ranges are captured before the writes are applied.
+    /// The returned guard restores them once it is dropped, unless
+    /// [`commit`](MemoryTransactionGuard::commit) is called. If the closure returns an error
+    /// nothing is written.
+    ///
+    /// See the [transaction](transaction) module for an example.
+    #[skip_func]
+    fn transaction<F>(&mut self, func: F) -> Result<MemoryTransactionGuard<Fwd<&mut Self>>>
+    where
+        Self: Sized,
+        F: FnOnce(&mut MemoryTransaction) -> Result<()>,
+    {
+        let mut tx = MemoryTransaction::new();
+        func(&mut tx)?;
+        tx.apply(self.forward_mut())
+    }
+
     #[skip_func]
     fn batcher(&mut self) -> MemoryViewBatcher<Self>
     where
@@ -0,0 +1,318 @@
+//! Frame budgeted scheduling of reads.
+//!
+//! Real-time consumers like overlays render at 60 to 144 frames per second and only have a few
+//! milliseconds per frame for reading memory. When the connector is slow or the number of values
+//! grows, reading everything every frame is not possible anymore, and the frame rate drops.
+//!
+//! A [`FrameScheduler`] accepts read requests with a priority and executes as many of them as
+//! fit into a time budget per frame. The remaining requests are deferred to the next frame.
+//! Requests are executed in batches, the batch size is derived from the measured latency of
+//! previous reads, so the budget is met without measuring every single read.
+//!
+//! Deferred requests age: their priority increases by the aging factor for every frame they
+//! were deferred, so low priority requests are not starved by a steady stream of high priority
+//! ones. At least one request is executed every frame, even if the budget is already exceeded.
+//!
+//! # Examples:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use memflow::prelude::v1::*;
+//! use memflow::mem::memory_view::scheduler::FrameScheduler;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn frame(
+//!     mem: &mut impl MemoryView,
+//!     scheduler: &mut FrameScheduler,
+//!     player: Address,
+//! ) -> Result<()> {
+//!     // positions are needed every frame, the name only once in a while
+//!     scheduler.submit(player + 0x10usize, 12, 10);
+//!     scheduler.submit(player + 0x100usize, 32, 1);
+//!
+//!     let result = scheduler.run_frame(mem)?;
+//!     for read in result.completed.iter().filter(|r| r.is_ok()) {
+//!         println!("{:x}: {:?}", read.address, read.data);
+//!     }
+//!     println!("{} reads deferred", result.deferred);
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # let mut scheduler = FrameScheduler::new(Duration::from_millis(4));
+//! # frame(&mut proc, &mut scheduler, addr).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::time::{Duration, Instant};
+
+use super::*;
+use crate::error::{Error, PartialResultExt, Result};
+
+/// Weight of the latest measurement in the moving average of the request latency.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Latency assumed for a single request before anything has been measured.
+const INITIAL_REQUEST_LATENCY: Duration = Duration::from_micros(50);
+
+/// Identifier of a request submitted to a [`FrameScheduler`].
+pub type ReadRequestId = u64;
+
+/// A read that was executed by the scheduler.
+#[derive(Clone, Debug)]
+pub struct CompletedRead {
+    /// Id returned by [`FrameScheduler::submit`]
+    pub id: ReadRequestId,
+    /// Address of the read
+    pub address: Address,
+    /// Data that was read, failed parts are zeroed
+    pub data: Vec<u8>,
+    /// Number of frames the request was deferred before it was executed
+    pub frames_deferred: u32,
+    /// Error of the read, if any part of it failed
+    pub error: Option<Error>,
+}
+
+impl CompletedRead {
+    /// Returns true if the whole range could be read.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Outcome of a single frame.
+#[derive(Clone, Debug, Default)]
+pub struct FrameResult {
+    /// Reads executed during the frame, in the order they were executed
+    pub completed: Vec<CompletedRead>,
+    /// Number of requests deferred to the next frame
+    pub deferred: usize,
+    /// Time spent executing reads
+    pub elapsed: Duration,
+}
+
+struct PendingRead {
+    id: ReadRequestId,
+    address: Address,
+    len: usize,
+    priority: u32,
+    frames_deferred: u32,
+}
+
+/// Executes prioritized reads within a per-frame time budget.
+pub struct FrameScheduler {
+    budget: Duration,
+    aging: u32,
+    max_batch_size: usize,
+    pending: Vec<PendingRead>,
+    next_id: ReadRequestId,
+    request_latency: Duration,
+}
+
+impl FrameScheduler {
+    /// Creates a scheduler with the given time budget per frame.
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            aging: 1,
+            max_batch_size: 256,
+            pending: vec![],
+            next_id: 0,
+            request_latency: INITIAL_REQUEST_LATENCY,
+        }
+    }
+
+    /// Sets the priority increase of a request for every frame it was deferred.
+    ///
+    /// An aging factor of 0 disables aging, low priority requests may then be starved.
+    pub fn aging(mut self, aging: u32) -> Self {
+        self.aging = aging;
+        self
+    }
+
+    /// Sets the maximum number of requests executed in a single batch.
+    ///
+    /// Smaller batches allow the scheduler to react to latency changes within a frame, larger
+    /// batches have less overhead.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = std::cmp::max(max_batch_size, 1);
+        self
+    }
+
+    /// Changes the time budget per frame.
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+
+    /// Returns the time budget per frame.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Returns the moving average of the latency of a single request.
+    pub fn request_latency(&self) -> Duration {
+        self.request_latency
+    }
+
+    /// Queues a read of `len` bytes at `address`.
+    ///
+    /// Requests with a higher priority are executed first, requests of the same priority in the
+    /// order they were submitted.
+    pub fn submit(&mut self, address: Address, len: usize, priority: u32) -> ReadRequestId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push(PendingRead {
+            id,
+            address,
+            len,
+            priority,
+            frames_deferred: 0,
+        });
+        id
+    }
+
+    /// Removes a queued request. Returns false if the request was already executed.
+    pub fn cancel(&mut self, id: ReadRequestId) -> bool {
+        let len = self.pending.len();
+        self.pending.retain(|r| r.id != id);
+        self.pending.len() != len
+    }
+
+    /// Returns the number of queued requests.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Executes queued requests until the budget of the frame is used up.
+    pub fn run_frame(&mut self, mem: &mut impl MemoryView) -> Result<FrameResult> {
+        let start = Instant::now();
+
+        // highest effective priority first, the sort is stable so ties keep their order
+        let aging = self.aging;
+        self.pending.sort_by_key(|r| {
+            std::cmp::Reverse(
+                r.priority
+                    .saturating_add(r.frames_deferred.saturating_mul(aging)),
+            )
+        });
+
+        let mut completed = vec![];
+        let mut queue = std::mem::take(&mut self.pending).into_iter().peekable();
+
+        while queue.peek().is_some() {
+            let remaining = self.budget.saturating_sub(start.elapsed());
+            let latency = std::cmp::max(self.request_latency.as_nanos(), 1);
+            let fit = (remaining.as_nanos() / latency) as usize;
+            let batch_size = fit.clamp(1, self.max_batch_size);
+            if fit == 0 && !completed.is_empty() {
+                break;
+            }
+
+            let batch = queue.by_ref().take(batch_size).collect::<Vec<_>>();
+            let batch_start = Instant::now();
+            self.execute(mem, batch, &mut completed)?;
+            self.record_latency(batch_start.elapsed(), batch_size);
+        }
+
+        self.pending = queue
+            .map(|mut r| {
+                r.frames_deferred = r.frames_deferred.saturating_add(1);
+                r
+            })
+            .collect();
+
+        Ok(FrameResult {
+            completed,
+            deferred: self.pending.len(),
+            elapsed: start.elapsed(),
+        })
+    }
+
+    fn execute(
+        &mut self,
+        mem: &mut impl MemoryView,
+        batch: Vec<PendingRead>,
+        completed: &mut Vec<CompletedRead>,
+    ) -> Result<()> {
+        let mut buffers = batch.iter().map(|r| vec![0u8; r.len]).collect::<Vec<_>>();
+
+        let results = {
+            let mut reads = batch
+                .iter()
+                .zip(buffers.iter_mut())
+                .map(|(r, buf)| CTup2(r.address, buf.as_mut_slice().into()))
+                .collect::<Vec<_>>();
+            mem.read_raw_list_chunks(&mut reads).data_part()?
+        };
+
+        for ((request, data), result) in batch.into_iter().zip(buffers).zip(results) {
+            completed.push(CompletedRead {
+                id: request.id,
+                address: request.address,
+                data,
+                frames_deferred: request.frames_deferred,
+                error: result.err().map(|err| err.error),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn record_latency(&mut self, elapsed: Duration, requests: usize) {
+        let measured = elapsed.as_secs_f64() / requests as f64;
+        let average = self.request_latency.as_secs_f64();
+        self.request_latency =
+            Duration::from_secs_f64(average + (measured - average) * LATENCY_SMOOTHING);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    #[test]
+    fn budget_and_priorities() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let base = proc.info().address;
+
+        // without any budget exactly one request is executed per frame
+        let mut scheduler = FrameScheduler::new(Duration::from_secs(0));
+        let low = scheduler.submit(base, 4, 1);
+        let high = scheduler.submit(base + 4usize, 4, 2);
+        let unreadable = scheduler.submit(Address::null(), 4, 2);
+
+        let frame = scheduler.run_frame(&mut proc).unwrap();
+        assert_eq!(frame.completed.len(), 1);
+        assert_eq!(frame.completed[0].id, high);
+        assert_eq!(frame.completed[0].data, vec![5, 6, 7, 8]);
+        assert_eq!(frame.deferred, 2);
+
+        let frame = scheduler.run_frame(&mut proc).unwrap();
+        assert_eq!(frame.completed[0].id, unreadable);
+        assert!(!frame.completed[0].is_ok());
+
+        // the low priority request is executed once it aged enough
+        let frame = scheduler.run_frame(&mut proc).unwrap();
+        assert_eq!(frame.completed[0].id, low);
+        assert_eq!(frame.completed[0].frames_deferred, 2);
+        assert_eq!(scheduler.pending(), 0);
+
+        // a generous budget executes everything at once
+        scheduler.set_budget(Duration::from_secs(10));
+        for i in 0..8 {
+            scheduler.submit(base + i as usize, 1, 0);
+        }
+        let cancelled = scheduler.submit(base, 1, 0);
+        assert!(scheduler.cancel(cancelled));
+
+        let frame = scheduler.run_frame(&mut proc).unwrap();
+        assert_eq!(frame.completed.len(), 8);
+        assert_eq!(frame.deferred, 0);
+        assert!(frame.completed.iter().all(|r| r.is_ok()));
+    }
+}
@@ -0,0 +1,195 @@
+//! Lazy chunked reads over large memory ranges.
+//!
+//! A [`MemoryStream`] reads a range of memory in fixed-size chunks as it is iterated. Only a
+//! single chunk is held in memory at any time, which allows processing multi-gigabyte regions
+//! (e.g. hashing a whole address space) without allocating a buffer for the entire range.
+//!
+//! By default a chunk that can only be read partially is reported as an error. With
+//! [`zero_fill_gaps`](MemoryStream::zero_fill_gaps) enabled the unreadable parts are zeroed
+//! instead and recorded in [`StreamChunk::gaps`].
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn sum(mem: &mut impl MemoryView, addr: Address, len: umem) -> u64 {
+//!     mem.read_stream(addr, len, size::kb(4))
+//!         .zero_fill_gaps()
+//!         .filter_map(|chunk| chunk.ok())
+//!         .map(|chunk| chunk.data.iter().map(|&b| b as u64).sum::<u64>())
+//!         .sum()
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[1; 16]);
+//! # let addr = proc.info().address;
+//! # assert_eq!(sum(&mut proc, addr, 16), 16);
+//! ```
+
+use super::*;
+
+/// Single chunk yielded by a [`MemoryStream`].
+#[derive(Clone, Debug)]
+pub struct StreamChunk {
+    /// Address of the first byte of the chunk
+    pub address: Address,
+    /// Contents of the chunk
+    pub data: Vec<u8>,
+    /// Ranges of the chunk that could not be read and have been zero filled
+    pub gaps: Vec<CTup2<Address, umem>>,
+}
+
+impl StreamChunk {
+    /// Returns true if the entire chunk could be read.
+    pub fn is_complete(&self) -> bool {
+        self.gaps.is_empty()
+    }
+}
+
+/// Iterator reading a memory range chunk by chunk.
+pub struct MemoryStream<T> {
+    mem: T,
+    start: Address,
+    address: Address,
+    end: Address,
+    chunk_size: usize,
+    zero_fill_gaps: bool,
+}
+
+impl<T: MemoryView> MemoryStream<T> {
+    /// Creates a new stream reading `len` bytes starting at `address`, in chunks of `chunk_size`.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    pub fn new(mem: T, address: Address, len: umem, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+
+        Self {
+            mem,
+            start: address,
+            address,
+            end: address + len,
+            chunk_size,
+            zero_fill_gaps: false,
+        }
+    }
+
+    /// Yields partially readable chunks with their unreadable ranges zeroed, instead of failing.
+    pub fn zero_fill_gaps(mut self) -> Self {
+        self.zero_fill_gaps = true;
+        self
+    }
+
+    /// Returns the address of the next chunk.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Returns the number of bytes streamed so far out of the total length of the stream.
+    pub fn progress(&self) -> Progress {
+        Progress::new(
+            (self.address - self.start) as umem,
+            (self.end - self.start) as umem,
+        )
+    }
+
+    /// Consumes the stream and returns the underlying memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+impl<T: MemoryView> Iterator for MemoryStream<T> {
+    type Item = Result<StreamChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.address >= self.end {
+            return None;
+        }
+
+        let address = self.address;
+        let len = std::cmp::min(self.chunk_size as umem, (self.end - address) as umem) as usize;
+        self.address += len;
+
+        let mut data = vec![0u8; len];
+        let mut gaps = vec![];
+
+        let out_fail = &mut |CTup2(addr, mut buf): ReadData| {
+            buf.iter_mut().for_each(|b| *b = 0);
+            gaps.push(CTup2(addr, buf.len() as umem));
+            true
+        };
+
+        if let Err(err) = self.mem.read_iter(
+            std::iter::once(CTup2(address, data.as_mut_slice().into())),
+            None,
+            Some(&mut out_fail.into()),
+        ) {
+            return Some(Err(err));
+        }
+
+        if !gaps.is_empty() && !self.zero_fill_gaps {
+            return Some(Err(Error(ErrorOrigin::Memory, ErrorKind::PartialData)));
+        }
+
+        gaps.sort_unstable_by_key(|CTup2(addr, _)| *addr);
+
+        Some(Ok(StreamChunk {
+            address,
+            data,
+            gaps,
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.end - self.address).max(0) as umem;
+        let chunks = ((remaining + self.chunk_size as umem - 1) / self.chunk_size as umem) as usize;
+        (chunks, Some(chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dummy::DummyOs;
+    use crate::prelude::v1::*;
+
+    #[test]
+    fn chunked() {
+        let bytes = (0..100u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &bytes);
+        let addr = proc.info().address;
+
+        let chunks = proc
+            .read_stream(addr, 100, 32)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[3].address, addr + 96usize);
+        assert_eq!(chunks[3].data.len(), 4);
+        assert_eq!(
+            chunks.into_iter().flat_map(|c| c.data).collect::<Vec<_>>(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn gaps() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+
+        let mut strict = proc.read_stream(Address::null(), 0x2000, 0x1000);
+        assert!(strict.next().unwrap().is_err());
+
+        let chunk = proc
+            .read_stream(Address::null(), 0x1000, 0x1000)
+            .zero_fill_gaps()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert!(!chunk.is_complete());
+        assert_eq!(chunk.data, vec![0; 0x1000]);
+        assert_eq!(chunk.gaps, vec![CTup2(Address::null(), 0x1000)]);
+    }
+}
@@ -0,0 +1,298 @@
+//! Transactional writes with automatic rollback.
+//!
+//! A transaction stages a set of writes, captures the original bytes of every written range,
+//! applies all writes in a single batch and restores the original bytes once the returned guard
+//! goes out of scope (unless the transaction has been explicitly committed).
+//!
+//! This is useful for temporary patches and hooks that must not persist in the target when the
+//! tool exits or crashes while unwinding.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn patch(mem: &mut impl MemoryView, addr: Address) -> Result<()> {
+//!     {
+//!         let mut guard = mem.transaction(|tx| {
+//!             tx.write(addr, &0xdeadbeefu32);
+//!             Ok(())
+//!         })?;
+//!
+//!         // the patch is live while the guard is alive
+//!         assert_eq!(guard.read::<u32>(addr).unwrap(), 0xdeadbeef);
+//!     }
+//!
+//!     // the original bytes are restored after the guard has been dropped
+//!     assert_ne!(mem.read::<u32>(addr).unwrap(), 0xdeadbeef);
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # patch(&mut proc, addr).unwrap();
+//! ```
+
+use super::*;
+use crate::dataview::PodMethods;
+
+/// Staged list of writes that are part of a transaction.
+///
+/// The writes are not applied to memory until the transaction closure returns successfully.
+#[derive(Default, Clone, Debug)]
+pub struct MemoryTransaction {
+    writes: Vec<(Address, Vec<u8>)>,
+}
+
+impl MemoryTransaction {
+    /// Creates a new empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages a raw write of `data` at `addr`.
+    pub fn write_raw(&mut self, addr: Address, data: &[u8]) -> &mut Self {
+        if !data.is_empty() {
+            self.writes.push((addr, data.to_vec()));
+        }
+        self
+    }
+
+    /// Stages a write of the pod object `data` at `addr`.
+    pub fn write<T: Pod + ?Sized>(&mut self, addr: Address, data: &T) -> &mut Self {
+        self.write_raw(addr, data.as_bytes())
+    }
+
+    /// Returns the number of staged writes.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Returns true if no writes have been staged.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Applies all staged writes to `mem` and returns a guard that restores the original memory.
+    ///
+    /// The original bytes of all written ranges are read first. If any of them can not be read,
+    /// nothing is written and an error is returned. If any of the writes fails, all ranges that
+    /// have been touched are restored before the error is returned.
+    pub fn apply<T: MemoryView>(self, mut mem: T) -> Result<MemoryTransactionGuard<T>> {
+        let mut originals = self
+            .writes
+            .iter()
+            .map(|(addr, data)| (*addr, vec![0u8; data.len()]))
+            .collect::<Vec<_>>();
+
+        {
+            let mut reads = originals
+                .iter_mut()
+                .map(|(addr, buf)| CTup2(*addr, buf.as_mut_slice().into()))
+                .collect::<Vec<_>>();
+
+            mem.read_raw_list(&mut reads).map_err(|err| {
+                Error::from(err).log_warn("unable to capture original memory for transaction")
+            })?;
+        }
+
+        let writes = self
+            .writes
+            .iter()
+            .map(|(addr, data)| CTup2(*addr, data.as_slice().into()))
+            .collect::<Vec<_>>();
+
+        let mut guard = MemoryTransactionGuard {
+            mem,
+            originals,
+            active: true,
+        };
+
+        if let Err(err) = guard.mem.write_raw_list(&writes) {
+            // attempt to undo any writes that did make it through
+            guard.active = false;
+            let _ = guard.restore();
+            return Err(Error::from(err).log_warn("unable to apply transaction"));
+        }
+
+        Ok(guard)
+    }
+}
+
+/// Guard holding the original memory contents of an applied transaction.
+///
+/// Dropping the guard restores the original contents of all ranges written by the transaction.
+/// Call [`commit`](Self::commit) to keep the written data in place instead.
+///
+/// The guard implements [`MemoryView`] itself, so the patched memory can still be accessed
+/// while the transaction is active.
+pub struct MemoryTransactionGuard<T: MemoryView> {
+    mem: T,
+    originals: Vec<(Address, Vec<u8>)>,
+    active: bool,
+}
+
+impl<T: MemoryView> MemoryTransactionGuard<T> {
+    /// Keeps all written data in place.
+    pub fn commit(mut self) {
+        self.active = false;
+    }
+
+    /// Restores the original memory contents.
+    ///
+    /// In contrast to dropping the guard this function will report any failures while restoring.
+    pub fn rollback(mut self) -> PartialResult<()> {
+        self.active = false;
+        self.restore()
+    }
+
+    /// Returns the ranges (and their original contents) that are restored on rollback.
+    pub fn originals(&self) -> &[(Address, Vec<u8>)] {
+        &self.originals
+    }
+
+    fn restore(&mut self) -> PartialResult<()> {
+        let Self { mem, originals, .. } = self;
+
+        // restore in reverse order, so overlapping writes end up with the initial data
+        originals
+            .iter()
+            .rev()
+            .map(|(addr, data)| mem.write_raw(*addr, data))
+            .fold(Ok(()), |acc, r| acc.and(r))
+    }
+}
+
+impl<T: MemoryView> Drop for MemoryTransactionGuard<T> {
+    fn drop(&mut self) {
+        if self.active {
+            if let Err(err) = self.restore() {
+                log::warn!("unable to roll back memory transaction: {:?}", err);
+            }
+        }
+    }
+}
+
+impl<T: MemoryView> MemoryView for MemoryTransactionGuard<T> {
+    fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+        self.mem.read_raw_iter(data)
+    }
+
+    fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+        self.mem.write_raw_iter(data)
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        self.mem.metadata()
+    }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        self.mem.prefetch(addr, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dummy::DummyOs;
+    use crate::mem::{MemoryViewMetadata, ReadRawMemOps, WriteRawMemOps};
+    use crate::prelude::v1::*;
+    use cglue::tuple::CTup3;
+
+    #[test]
+    fn rollback_on_drop() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xaa; 16]);
+        let addr = proc.info().address;
+
+        {
+            let mut guard = proc
+                .transaction(|tx| {
+                    tx.write(addr, &0u32).write(addr + 4usize, &0x11u32);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(guard.read::<u64>(addr).unwrap(), 0x11_0000_0000);
+        }
+
+        assert_eq!(proc.read::<[u8; 16]>(addr).unwrap(), [0xaa; 16]);
+    }
+
+    #[test]
+    fn commit_keeps_writes() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xaa; 16]);
+        let addr = proc.info().address;
+
+        proc.transaction(|tx| {
+            tx.write(addr, &0u32);
+            Ok(())
+        })
+        .unwrap()
+        .commit();
+
+        assert_eq!(proc.read::<u32>(addr).unwrap(), 0);
+    }
+
+    #[test]
+    fn closure_error_writes_nothing() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xaa; 16]);
+        let addr = proc.info().address;
+
+        let ret = proc.transaction(|tx| {
+            tx.write(addr, &0u32);
+            Err(Error(ErrorOrigin::Memory, ErrorKind::Unknown))
+        });
+
+        assert!(ret.is_err());
+        assert_eq!(proc.read::<u32>(addr).unwrap(), 0xaaaa_aaaa);
+    }
+
+    /// Memory view that fails the next write batch after applying only its first write.
+    struct TornWrites<T> {
+        mem: T,
+        fail_writes: usize,
+    }
+
+    impl<T: MemoryView> MemoryView for TornWrites<T> {
+        fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+            self.mem.read_raw_iter(data)
+        }
+
+        fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+            if self.fail_writes == 0 {
+                return self.mem.write_raw_iter(data);
+            }
+
+            self.fail_writes -= 1;
+            let mut inp = data.inp;
+            if let Some(CTup3(addr, _, buf)) = inp.next() {
+                self.mem.write_raw(addr, &buf).data()?;
+            }
+            Err(Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteMemory))
+        }
+
+        fn metadata(&self) -> MemoryViewMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    #[test]
+    fn failed_write_rolls_back() {
+        let proc = DummyOs::quick_process(size::mb(2), &[0xaa; 16]);
+        let addr = proc.info().address;
+        let mut mem = TornWrites {
+            mem: proc,
+            fail_writes: 1,
+        };
+
+        let ret = mem.transaction(|tx| {
+            tx.write(addr, &0u32)
+                .write(addr + 4usize, &0x11u32)
+                .write(addr + 8usize, &0x22u32);
+            Ok(())
+        });
+
+        // the first write made it through before the batch failed and has been restored
+        assert_eq!(ret.err().unwrap().1, ErrorKind::UnableToWriteMemory);
+        assert_eq!(mem.fail_writes, 0);
+        assert_eq!(mem.read::<[u8; 16]>(addr).unwrap(), [0xaa; 16]);
+    }
+}
@@ -0,0 +1,344 @@
+//! Synthetic address space stitched together from multiple memory views.
+//!
+//! A [`VirtualMemoryOverlay`] owns a list of source memory views (e.g. several processes, or
+//! physical memory views) and a routing table mapping ranges of its own address space onto ranges
+//! of the sources. Reads and writes are split along the routes and dispatched to the sources in
+//! one batch per source. Accesses to unrouted ranges fail.
+//!
+//! All sources have to be of the same type. Sources of different types can be combined by
+//! wrapping them in an enum implementing [`MemoryView`], or by using the connector and process
+//! trait objects of the plugin system.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::mem::memory_view::VirtualMemoryOverlay;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn stitch<T: Process + MemoryView>(a: T, b: T) -> Result<VirtualMemoryOverlay<T>> {
+//!     let (base_a, base_b) = (a.info().address, b.info().address);
+//!
+//!     let mut overlay = VirtualMemoryOverlay::new();
+//!     let a = overlay.add_source(a);
+//!     let b = overlay.add_source(b);
+//!
+//!     // map one page of each process next to each other
+//!     overlay
+//!         .map(0x1000.into(), 0x1000, a, base_a)?
+//!         .map(0x2000.into(), 0x1000, b, base_b)?;
+//!
+//!     Ok(overlay)
+//! }
+//! # let a = DummyOs::quick_process(size::mb(2), &[1; 8]);
+//! # let b = DummyOs::quick_process(size::mb(2), &[2; 8]);
+//! # let mut overlay = stitch(a, b).unwrap();
+//! # assert_eq!(overlay.read::<[u8; 2]>(0x1000.into()).unwrap(), [1, 1]);
+//! # assert_eq!(overlay.read::<[u8; 2]>(0x2000.into()).unwrap(), [2, 2]);
+//! ```
+
+use super::*;
+use crate::iter::SplitAtIndex;
+
+/// Single entry of the routing table of a [`VirtualMemoryOverlay`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OverlayRoute {
+    /// Start of the range in the overlay address space
+    pub base: Address,
+    /// Size of the range
+    pub size: umem,
+    /// Index of the source the range is mapped to
+    pub source: usize,
+    /// Start of the range in the address space of the source
+    pub source_base: Address,
+}
+
+impl OverlayRoute {
+    fn end(&self) -> Address {
+        self.base + self.size
+    }
+}
+
+/// Memory view combining ranges of several source views into one address space.
+#[derive(Clone)]
+pub struct VirtualMemoryOverlay<T> {
+    sources: Vec<T>,
+    routes: Vec<OverlayRoute>,
+}
+
+impl<T: MemoryView> Default for VirtualMemoryOverlay<T> {
+    fn default() -> Self {
+        Self {
+            sources: vec![],
+            routes: vec![],
+        }
+    }
+}
+
+impl<T: MemoryView> VirtualMemoryOverlay<T> {
+    /// Creates a new overlay without any sources or routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a source view and returns its index, to be used in [`map`](Self::map).
+    pub fn add_source(&mut self, mem: T) -> usize {
+        self.sources.push(mem);
+        self.sources.len() - 1
+    }
+
+    /// Maps `size` bytes at `base` of the overlay to `source_base` of the source at `source`.
+    ///
+    /// Fails if the source does not exist or if the range overlaps an existing route.
+    pub fn map(
+        &mut self,
+        base: Address,
+        size: umem,
+        source: usize,
+        source_base: Address,
+    ) -> Result<&mut Self> {
+        if source >= self.sources.len() {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                .log_error("overlay source does not exist"));
+        }
+
+        if size == 0 {
+            return Ok(self);
+        }
+
+        let route = OverlayRoute {
+            base,
+            size,
+            source,
+            source_base,
+        };
+
+        let idx = self.routes.partition_point(|r| r.base < base);
+
+        let overlaps_prev = idx > 0 && self.routes[idx - 1].end() > base;
+        let overlaps_next = self.routes.get(idx).map_or(false, |r| r.base < route.end());
+        if overlaps_prev || overlaps_next {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::AlreadyExists)
+                .log_error("overlay route overlaps an existing route"));
+        }
+
+        self.routes.insert(idx, route);
+
+        Ok(self)
+    }
+
+    /// Removes all routes overlapping the given range.
+    pub fn unmap(&mut self, base: Address, size: umem) {
+        let end = base + size;
+        self.routes.retain(|r| r.end() <= base || r.base >= end);
+    }
+
+    /// Returns the routing table in ascending order.
+    pub fn routes(&self) -> &[OverlayRoute] {
+        &self.routes
+    }
+
+    /// Returns all source views.
+    pub fn sources(&self) -> &[T] {
+        &self.sources
+    }
+
+    /// Returns the source view at `idx`.
+    pub fn source_mut(&mut self, idx: usize) -> Option<&mut T> {
+        self.sources.get_mut(idx)
+    }
+
+    /// Consumes self and returns all source views.
+    pub fn into_sources(self) -> Vec<T> {
+        self.sources
+    }
+
+    /// Translates an address of the overlay into a source index and an address of that source.
+    pub fn translate(&self, addr: Address) -> Option<(usize, Address)> {
+        self.routes
+            .get(self.routes.partition_point(|r| r.end() <= addr))
+            .filter(|r| r.base <= addr)
+            .map(|r| (r.source, r.source_base + (addr - r.base) as umem))
+    }
+
+    /// Splits the access at `addr` along the routes.
+    ///
+    /// Routed parts are pushed into the batch of their source, unrouted parts into `failed`.
+    fn route<B: SplitAtIndex>(
+        &self,
+        CTup3(addr, meta_addr, buf): CTup3<Address, Address, B>,
+        batches: &mut [Vec<CTup3<Address, Address, B>>],
+        failed: &mut Vec<CTup2<Address, B>>,
+    ) {
+        let mut next = Some((addr, (meta_addr, buf)));
+
+        while let Some((addr, data)) = next.take() {
+            match self
+                .routes
+                .get(self.routes.partition_point(|r| r.end() <= addr))
+            {
+                Some(r) if r.base <= addr => {
+                    let offset = (addr - r.base) as umem;
+                    let (head, tail) = data.split_at(r.size - offset);
+                    if let Some((meta_addr, buf)) = head {
+                        batches[r.source].push(CTup3(r.source_base + offset, meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (r.end(), tail));
+                }
+                Some(r) => {
+                    let (head, tail) = data.split_at((r.base - addr) as umem);
+                    if let Some((meta_addr, buf)) = head {
+                        failed.push(CTup2(meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (r.base, tail));
+                }
+                None => failed.push(CTup2(data.0, data.1)),
+            }
+        }
+    }
+}
+
+impl<T: MemoryView> MemoryView for VirtualMemoryOverlay<T> {
+    fn read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        let mut batches = self.sources.iter().map(|_| vec![]).collect::<Vec<_>>();
+        let mut failed = vec![];
+
+        for data in inp {
+            self.route(data, &mut batches, &mut failed);
+        }
+
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        for (source, batch) in self.sources.iter_mut().zip(batches) {
+            if !batch.is_empty() {
+                MemOps::with_raw(
+                    batch.into_iter(),
+                    out.as_deref_mut(),
+                    out_fail.as_deref_mut(),
+                    |data| source.read_raw_iter(data),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+    ) -> Result<()> {
+        let mut batches = self.sources.iter().map(|_| vec![]).collect::<Vec<_>>();
+        let mut failed = vec![];
+
+        for data in inp {
+            self.route(data, &mut batches, &mut failed);
+        }
+
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        for (source, batch) in self.sources.iter_mut().zip(batches) {
+            if !batch.is_empty() {
+                MemOps::with_raw(
+                    batch.into_iter(),
+                    out.as_deref_mut(),
+                    out_fail.as_deref_mut(),
+                    |data| source.write_raw_iter(data),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let max_address = self
+            .routes
+            .last()
+            .map(|r| r.end() - 1_usize)
+            .unwrap_or_else(Address::null);
+        let real_size = self.routes.iter().map(|r| r.size).sum();
+        let readonly = self.sources.iter().all(|s| s.metadata().readonly);
+
+        match self.sources.first() {
+            Some(source) => MemoryViewMetadata {
+                max_address,
+                real_size,
+                readonly,
+                ..source.metadata()
+            },
+            None => MemoryViewMetadata {
+                max_address,
+                real_size,
+                readonly,
+                little_endian: cfg!(target_endian = "little"),
+                arch_bits: 64,
+            },
+        }
+    }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        let mut batches = self.sources.iter().map(|_| vec![]).collect::<Vec<_>>();
+        self.route(CTup3(addr, addr, len), &mut batches, &mut vec![]);
+
+        for (source, batch) in self.sources.iter_mut().zip(batches) {
+            for CTup3(addr, _, len) in batch {
+                source.prefetch(addr, len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VirtualMemoryOverlay;
+    use crate::dummy::DummyOs;
+    use crate::prelude::v1::*;
+
+    #[test]
+    fn stitched_reads() {
+        let a = DummyOs::quick_process(size::mb(2), &[1; 0x1000]);
+        let b = DummyOs::quick_process(size::mb(2), &[2; 0x1000]);
+        let (base_a, base_b) = (a.info().address, b.info().address);
+
+        let mut overlay = VirtualMemoryOverlay::new();
+        let a = overlay.add_source(a);
+        let b = overlay.add_source(b);
+        overlay
+            .map(0x1000.into(), 0x1000, a, base_a)
+            .unwrap()
+            .map(0x3000.into(), 0x1000, b, base_b)
+            .unwrap();
+
+        assert!(overlay.map(0x1800.into(), 0x1000, b, base_b).is_err());
+        assert_eq!(
+            overlay.translate(0x3010.into()),
+            Some((b, base_b + 0x10usize))
+        );
+        assert_eq!(overlay.translate(0x2000.into()), None);
+
+        // reads crossing the gap between both routes fail
+        assert!(overlay.read::<[u8; 2]>(0x1fff.into()).is_err());
+
+        overlay.write(0x3004.into(), &[9u8; 2]).unwrap();
+        assert_eq!(
+            overlay.read::<[u8; 4]>(0x3002.into()).unwrap(),
+            [2, 2, 9, 9]
+        );
+        assert_eq!(overlay.read::<[u8; 2]>(0x1ffe.into()).unwrap(), [1, 1]);
+    }
+}
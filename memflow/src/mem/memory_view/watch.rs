@@ -0,0 +1,460 @@
+//! Batched re-evaluation of typed watch expressions.
+//!
+//! Overlays and other real-time tools read the same set of values over and over again, often
+//! hundreds of them per frame and many of them behind pointer chains. Reading them one by one
+//! costs a round trip per pointer.
+//!
+//! A [`WatchList`] holds a set of [`WatchExpr`]s, each consisting of a base address, a chain of
+//! dereferences and the type of the final value. [`WatchList::evaluate`] resolves all of them
+//! level by level: every level of pointers is read in a single batched read, followed by one
+//! batched read of all values. The number of reads per evaluation is thus bounded by the
+//! longest pointer chain plus one, independent of the number of expressions.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::mem::memory_view::watch::{WatchExpr, WatchList, WatchType, WatchValue};
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//!
+//! fn hud(mem: &mut impl MemoryView, arch: ArchitectureObj, game: Address) -> Result<()> {
+//!     let mut watches = WatchList::new(arch);
+//!
+//!     // [[game + 0x10] + 0x28] + 0x4 as f32
+//!     let health = watches.add(
+//!         WatchExpr::new(game, WatchType::F32)
+//!             .deref(0x10)
+//!             .deref(0x28)
+//!             .field(0x4),
+//!     );
+//!     let score = watches.add(WatchExpr::new(game + 0x100usize, WatchType::U32));
+//!
+//!     // once per frame
+//!     let values = watches.evaluate(mem)?;
+//!     if let Ok(WatchValue::U32(score)) = &values[score] {
+//!         println!("score: {}", score);
+//!     }
+//!     if let Ok(health) = &values[health] {
+//!         println!("health: {}", health);
+//!     }
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # hud(&mut proc, x64::ARCH, addr).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fmt;
+
+use super::*;
+use crate::architecture::{ArchitectureObj, Endianess};
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+
+/// Type of the value a [`WatchExpr`] evaluates to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum WatchType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Pointer of the architecture of the watch list
+    Pointer,
+    /// Raw bytes of the given length
+    Bytes(usize),
+    /// UTF-8 string of at most the given length, terminated by the first null byte
+    Utf8(usize),
+}
+
+impl WatchType {
+    /// Returns the number of bytes read for a value of this type.
+    pub fn size(&self, arch: ArchitectureObj) -> usize {
+        match self {
+            WatchType::U8 | WatchType::I8 => 1,
+            WatchType::U16 | WatchType::I16 => 2,
+            WatchType::U32 | WatchType::I32 | WatchType::F32 => 4,
+            WatchType::U64 | WatchType::I64 | WatchType::F64 => 8,
+            WatchType::Pointer => arch.size_addr(),
+            WatchType::Bytes(len) | WatchType::Utf8(len) => *len,
+        }
+    }
+}
+
+/// Current value of a [`WatchExpr`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum WatchValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Pointer(Address),
+    Bytes(Vec<u8>),
+    Utf8(String),
+}
+
+impl WatchValue {
+    /// Decodes a value of type `ty` from `data`, which has to hold exactly the size of the type.
+    pub fn decode(ty: WatchType, data: &[u8], arch: ArchitectureObj) -> Self {
+        macro_rules! num {
+            ($t:ty) => {{
+                let bytes = data.try_into().unwrap();
+                match arch.endianess() {
+                    Endianess::LittleEndian => <$t>::from_le_bytes(bytes),
+                    Endianess::BigEndian => <$t>::from_be_bytes(bytes),
+                }
+            }};
+        }
+
+        match ty {
+            WatchType::U8 => WatchValue::U8(data[0]),
+            WatchType::U16 => WatchValue::U16(num!(u16)),
+            WatchType::U32 => WatchValue::U32(num!(u32)),
+            WatchType::U64 => WatchValue::U64(num!(u64)),
+            WatchType::I8 => WatchValue::I8(data[0] as i8),
+            WatchType::I16 => WatchValue::I16(num!(i16)),
+            WatchType::I32 => WatchValue::I32(num!(i32)),
+            WatchType::I64 => WatchValue::I64(num!(i64)),
+            WatchType::F32 => WatchValue::F32(num!(f32)),
+            WatchType::F64 => WatchValue::F64(num!(f64)),
+            WatchType::Pointer => WatchValue::Pointer(decode_ptr(data, arch)),
+            WatchType::Bytes(_) => WatchValue::Bytes(data.to_vec()),
+            WatchType::Utf8(_) => {
+                let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                WatchValue::Utf8(String::from_utf8_lossy(&data[..len]).to_string())
+            }
+        }
+    }
+
+    /// Returns the value as an integer, if it is one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            WatchValue::U8(v) => Some(v as u64),
+            WatchValue::U16(v) => Some(v as u64),
+            WatchValue::U32(v) => Some(v as u64),
+            WatchValue::U64(v) => Some(v),
+            WatchValue::I8(v) => Some(v as u64),
+            WatchValue::I16(v) => Some(v as u64),
+            WatchValue::I32(v) => Some(v as u64),
+            WatchValue::I64(v) => Some(v as u64),
+            WatchValue::Pointer(v) => Some(v.to_umem() as u64),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a float, converting integers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            WatchValue::F32(v) => Some(v as f64),
+            WatchValue::F64(v) => Some(v),
+            WatchValue::I8(v) => Some(v as f64),
+            WatchValue::I16(v) => Some(v as f64),
+            WatchValue::I32(v) => Some(v as f64),
+            WatchValue::I64(v) => Some(v as f64),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+}
+
+impl fmt::Display for WatchValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchValue::U8(v) => write!(f, "{}", v),
+            WatchValue::U16(v) => write!(f, "{}", v),
+            WatchValue::U32(v) => write!(f, "{}", v),
+            WatchValue::U64(v) => write!(f, "{}", v),
+            WatchValue::I8(v) => write!(f, "{}", v),
+            WatchValue::I16(v) => write!(f, "{}", v),
+            WatchValue::I32(v) => write!(f, "{}", v),
+            WatchValue::I64(v) => write!(f, "{}", v),
+            WatchValue::F32(v) => write!(f, "{}", v),
+            WatchValue::F64(v) => write!(f, "{}", v),
+            WatchValue::Pointer(v) => write!(f, "{:#x}", v.to_umem()),
+            WatchValue::Bytes(v) => v.iter().try_for_each(|b| write!(f, "{:02x}", b)),
+            WatchValue::Utf8(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+fn decode_ptr(data: &[u8], arch: ArchitectureObj) -> Address {
+    let le = arch.endianess() == Endianess::LittleEndian;
+    match data.len() {
+        8 if le => Address::from(u64::from_le_bytes(data.try_into().unwrap())),
+        8 => Address::from(u64::from_be_bytes(data.try_into().unwrap())),
+        _ if le => Address::from(u32::from_le_bytes(data[..4].try_into().unwrap())),
+        _ => Address::from(u32::from_be_bytes(data[..4].try_into().unwrap())),
+    }
+}
+
+/// Typed value behind a chain of pointers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WatchExpr {
+    /// Address the chain starts at
+    pub base: Address,
+    /// Offsets at which a pointer is read, one per level of the chain
+    pub derefs: Vec<umem>,
+    /// Offset of the value from the last dereferenced pointer
+    pub offset: umem,
+    /// Type of the value
+    pub ty: WatchType,
+}
+
+impl WatchExpr {
+    /// Creates an expression of the value of type `ty` at `base`.
+    pub fn new(base: Address, ty: WatchType) -> Self {
+        Self {
+            base,
+            derefs: vec![],
+            offset: 0,
+            ty,
+        }
+    }
+
+    /// Follows the pointer located `offset` bytes after the current address.
+    pub fn deref(mut self, offset: umem) -> Self {
+        self.derefs.push(self.offset.wrapping_add(offset));
+        self.offset = 0;
+        self
+    }
+
+    /// Moves the current address by `offset` bytes, e.g. to a field of a struct.
+    pub fn field(mut self, offset: umem) -> Self {
+        self.offset = self.offset.wrapping_add(offset);
+        self
+    }
+}
+
+/// Identifier of an expression in a [`WatchList`].
+///
+/// It is the index of the value of the expression in the result of [`WatchList::evaluate`].
+pub type WatchId = usize;
+
+/// Set of expressions that are evaluated together.
+#[derive(Clone, Debug)]
+pub struct WatchList {
+    arch: ArchitectureObj,
+    exprs: Vec<WatchExpr>,
+}
+
+impl WatchList {
+    /// Creates an empty list, pointers are read with the pointer size and endianess of `arch`.
+    pub fn new(arch: ArchitectureObj) -> Self {
+        Self {
+            arch,
+            exprs: vec![],
+        }
+    }
+
+    /// Adds an expression and returns its id.
+    pub fn add(&mut self, expr: WatchExpr) -> WatchId {
+        self.exprs.push(expr);
+        self.exprs.len() - 1
+    }
+
+    /// Replaces the expression with the given id.
+    pub fn set(&mut self, id: WatchId, expr: WatchExpr) {
+        self.exprs[id] = expr;
+    }
+
+    /// Returns all expressions in the order of their ids.
+    pub fn exprs(&self) -> &[WatchExpr] {
+        &self.exprs
+    }
+
+    /// Returns the number of expressions.
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    /// Returns true if the list contains no expressions.
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    /// Removes all expressions.
+    pub fn clear(&mut self) {
+        self.exprs.clear();
+    }
+
+    /// Resolves the address of the value of every expression.
+    ///
+    /// Expressions whose pointer chain could not be read are reported as errors.
+    pub fn resolve(&self, mem: &mut impl MemoryView) -> Result<Vec<Result<Address>>> {
+        let mut addrs = self
+            .exprs
+            .iter()
+            .map(|e| Ok(e.base))
+            .collect::<Vec<Result<Address>>>();
+
+        let ptr_size = self.arch.size_addr();
+        let depth = self.exprs.iter().map(|e| e.derefs.len()).max().unwrap_or(0);
+
+        for level in 0..depth {
+            let pending = self
+                .exprs
+                .iter()
+                .zip(addrs.iter())
+                .enumerate()
+                .filter(|(_, (e, a))| e.derefs.len() > level && a.is_ok())
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            let mut buf = vec![0u8; pending.len() * ptr_size];
+            let results = {
+                let mut reads = pending
+                    .iter()
+                    .zip(buf.chunks_exact_mut(ptr_size))
+                    .map(|(&i, chunk)| {
+                        let addr = *addrs[i].as_ref().unwrap() + self.exprs[i].derefs[level];
+                        CTup2(addr, chunk.into())
+                    })
+                    .collect::<Vec<_>>();
+                mem.read_raw_list_chunks(&mut reads).data_part()?
+            };
+
+            for ((&i, chunk), res) in pending
+                .iter()
+                .zip(buf.chunks_exact(ptr_size))
+                .zip(results.into_iter())
+            {
+                addrs[i] = match res {
+                    Ok(()) => Ok(decode_ptr(chunk, self.arch)),
+                    Err(err) => Err(err.error),
+                };
+            }
+        }
+
+        Ok(addrs
+            .into_iter()
+            .zip(self.exprs.iter())
+            .map(|(a, e)| a.map(|a| a + e.offset))
+            .collect())
+    }
+
+    /// Evaluates all expressions and returns their values, indexed by their [`WatchId`].
+    ///
+    /// Every level of pointers and the final values are read in a single batched read each.
+    /// Expressions that could not be read are reported as errors, a hard error of the memory
+    /// backend aborts the evaluation.
+    pub fn evaluate(&self, mem: &mut impl MemoryView) -> Result<Vec<Result<WatchValue>>> {
+        let addrs = self.resolve(mem)?;
+
+        let sizes = self
+            .exprs
+            .iter()
+            .map(|e| e.ty.size(self.arch))
+            .collect::<Vec<_>>();
+        let mut buf = vec![0u8; sizes.iter().sum()];
+
+        let results = {
+            let mut rest = buf.as_mut_slice();
+            let mut reads = vec![];
+            for (addr, size) in addrs.iter().zip(sizes.iter()) {
+                let (chunk, tail) = rest.split_at_mut(*size);
+                rest = tail;
+                if let Ok(addr) = addr {
+                    reads.push(CTup2(*addr, chunk.into()));
+                }
+            }
+            mem.read_raw_list_chunks(&mut reads).data_part()?
+        };
+
+        let mut results = results.into_iter();
+        let mut offset = 0;
+        let mut ret = Vec::with_capacity(self.exprs.len());
+        for ((addr, size), expr) in addrs.into_iter().zip(sizes).zip(self.exprs.iter()) {
+            let data = &buf[offset..offset + size];
+            offset += size;
+
+            ret.push(addr.and_then(|_| {
+                match results
+                    .next()
+                    .ok_or(Error(ErrorOrigin::Memory, ErrorKind::UnableToReadMemory))?
+                {
+                    Ok(()) => Ok(WatchValue::decode(expr.ty, data, self.arch)),
+                    Err(err) => Err(err.error),
+                }
+            }));
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    #[test]
+    fn pointer_chains() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // base + 0x10 -> base + 0x100, base + 0x128 -> base + 0x200
+        proc.write(base + 0x10usize, &(base + 0x100usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x128usize, &(base + 0x200usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x204usize, &75.5f32).unwrap();
+        proc.write(base + 0x300usize, &-3i16).unwrap();
+        proc.write_raw(base + 0x400usize, b"player\0garbage")
+            .unwrap();
+
+        let mut watches = WatchList::new(x64::ARCH);
+        let health = watches.add(
+            WatchExpr::new(base, WatchType::F32)
+                .deref(0x10)
+                .deref(0x28)
+                .field(0x4),
+        );
+        let ammo = watches.add(WatchExpr::new(base + 0x300usize, WatchType::I16));
+        let name = watches.add(WatchExpr::new(base + 0x400usize, WatchType::Utf8(16)));
+        // the pointer at base + 0x20 is null
+        let broken = watches.add(
+            WatchExpr::new(base, WatchType::U32)
+                .field(0x20)
+                .deref(0)
+                .deref(0),
+        );
+        let ptr = watches.add(
+            WatchExpr::new(base, WatchType::Pointer)
+                .deref(0x10)
+                .field(0x28),
+        );
+
+        let values = watches.evaluate(&mut proc).unwrap();
+        assert_eq!(values[health].as_ref().unwrap(), &WatchValue::F32(75.5));
+        assert_eq!(values[ammo].as_ref().unwrap().as_f64(), Some(-3.0));
+        assert_eq!(values[name].as_ref().unwrap().to_string(), "player");
+        assert!(values[broken].is_err());
+        assert_eq!(
+            values[ptr].as_ref().unwrap(),
+            &WatchValue::Pointer(base + 0x200usize)
+        );
+
+        proc.write(base + 0x204usize, &50f32).unwrap();
+        let values = watches.evaluate(&mut proc).unwrap();
+        assert_eq!(values[health].as_ref().unwrap().as_f64(), Some(50.0));
+    }
+}
@@ -10,21 +10,34 @@
 pub mod mem_data;
 pub mod mem_map;
 pub mod memory_view;
+pub mod patcher;
 pub mod phys_mem;
+pub mod progress;
 pub mod virt_mem;
 pub mod virt_translate;
 
-pub use mem_map::{MemoryMap, PhysicalMemoryMapping};
-pub use phys_mem::{CachedPhysicalMemory, PhysicalMemory, PhysicalMemoryMetadata};
+pub use mem_map::{MemoryMap, PhysicalMemoryMapping, PhysicalMemoryMappingCallback};
+#[cfg(feature = "access_jitter")]
+pub use phys_mem::JitteredPhysicalMemory;
 #[cfg(feature = "std")]
-pub use phys_mem::{DelayedPhysicalMemory, PhysicalMemoryMetrics};
-pub use virt_mem::VirtualDma;
+pub use phys_mem::{
+    AdaptiveBatchPhysicalMemory, DelayedPhysicalMemory, PhysicalMemoryMetrics,
+    SharedPhysicalMemory, ThrottledMemory, TracedPhysicalMemory,
+};
+pub use phys_mem::{
+    BlockedMemoryFilter, CachedPhysicalMemory, ConstrainedPhysicalMemory, OverlayMemory,
+    PhysicalMemory, PhysicalMemoryMetadata, ReconnectingPhysicalMemory, SecureMemoryFilter,
+};
+pub use progress::{Progress, ProgressCallback};
+pub use virt_mem::{MappedVirtualMemory, VirtualDma, VirtualMapping};
 //#[doc(hidden)]
 //pub use virt_mem_batcher::VirtualMemoryBatcher;
 pub use virt_translate::{
-    CachedVirtualTranslate, DirectTranslate, VirtualTranslate, VirtualTranslate2,
-    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    CachedVirtualTranslate, DirectTranslate, PageWalk, PageWalkEntry, VirtualTranslate,
+    VirtualTranslate2, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
 };
+#[cfg(feature = "std")]
+pub use virt_translate::{SharedVirtualTranslate, TranslationDiagnostics};
 
 pub use memory_view::{MemoryView, MemoryViewMetadata};
 
@@ -0,0 +1,371 @@
+//! Code patching helpers.
+//!
+//! The [`Patcher`] writes byte patches into a [`MemoryView`] and keeps track of the original
+//! bytes of every patched range, so the patches can be reverted individually or all at once.
+//!
+//! With the `assembler` feature enabled small x86 code stubs can be assembled directly at their
+//! target address via [`Patcher::assemble`].
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::mem::patcher::Patcher;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn nop_out(mem: impl MemoryView, addr: Address) -> Result<()> {
+//!     let mut patcher = Patcher::new(mem, ArchitectureIdent::X86(64, false));
+//!
+//!     patcher.nop(addr, 5)?;
+//!     assert_eq!(patcher.mem().read::<[u8; 5]>(addr).unwrap(), [0x90; 5]);
+//!
+//!     patcher.restore_all()?;
+//!     assert_eq!(patcher.mem().read::<[u8; 5]>(addr).unwrap(), [0xcc; 5]);
+//!
+//!     Ok(())
+//! }
+//! # let proc = DummyOs::quick_process(size::mb(2), &[0xcc; 16]);
+//! # let addr = proc.info().address;
+//! # nop_out(proc, addr).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Single patch applied by the [`Patcher`].
+#[derive(Clone, Debug)]
+pub struct Patch {
+    /// Address the patch was written to.
+    pub address: Address,
+    /// Bytes that were present before the patch was applied.
+    pub original: Vec<u8>,
+    /// Bytes that have been written.
+    pub patched: Vec<u8>,
+}
+
+impl Patch {
+    /// Returns the address right after the end of the patch.
+    pub fn end(&self) -> Address {
+        self.address + self.patched.len()
+    }
+
+    fn overlaps(&self, address: Address, len: usize) -> bool {
+        address < self.end() && self.address < address + len
+    }
+}
+
+/// Writes patches into memory and keeps track of the original bytes.
+///
+/// Patches are not reverted automatically. Use [`restore`](Self::restore) or
+/// [`restore_all`](Self::restore_all) to revert them, or build on top of a
+/// [transaction](crate::mem::memory_view::transaction) if they must not outlive the tool.
+pub struct Patcher<T> {
+    mem: T,
+    arch: ArchitectureIdent,
+    patches: Vec<Patch>,
+}
+
+impl<T: MemoryView> Patcher<T> {
+    /// Creates a new patcher for code of the given architecture.
+    pub fn new(mem: T, arch: ArchitectureIdent) -> Self {
+        Self {
+            mem,
+            arch,
+            patches: vec![],
+        }
+    }
+
+    /// Returns the underlying memory object.
+    pub fn mem(&mut self) -> &mut T {
+        &mut self.mem
+    }
+
+    /// Consumes the patcher and returns the underlying memory object.
+    ///
+    /// Patches that have not been restored stay in place.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Returns all currently applied patches.
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// Writes `data` at `address` and remembers the original bytes.
+    ///
+    /// Patching a range that overlaps an existing patch is rejected, because the original bytes
+    /// could not be restored reliably otherwise.
+    ///
+    /// If the write fails, parts of it may already have landed. The original bytes are written
+    /// back before the error is returned, and no patch is recorded.
+    pub fn patch(&mut self, address: Address, data: &[u8]) -> Result<&Patch> {
+        if self.patches.iter().any(|p| p.overlaps(address, data.len())) {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::AlreadyExists)
+                .log_warn("patch overlaps an existing patch"));
+        }
+
+        let original = self.mem.read_raw(address, data.len()).data()?;
+        if let Err(err) = self.mem.write_raw(address, data).data() {
+            if self.mem.write_raw(address, &original).data().is_err() {
+                log::warn!(
+                    "unable to restore original bytes at {} after a failed patch",
+                    address
+                );
+            }
+            return Err(err);
+        }
+
+        self.patches.push(Patch {
+            address,
+            original,
+            patched: data.to_vec(),
+        });
+
+        Ok(self.patches.last().unwrap())
+    }
+
+    /// Overwrites `len` bytes at `address` with no-op instructions of the patcher architecture.
+    pub fn nop(&mut self, address: Address, len: usize) -> Result<&Patch> {
+        let data = nop_bytes(self.arch, len)?;
+        self.patch(address, &data)
+    }
+
+    /// Assembles a code stub for `address` and writes it.
+    ///
+    /// The closure receives a [`CodeAssembler`](iced_x86::code_asm::CodeAssembler) with the
+    /// bitness of the patcher architecture. Relative branches are resolved against `address`.
+    #[cfg(feature = "assembler")]
+    pub fn assemble(
+        &mut self,
+        address: Address,
+        func: impl FnOnce(&mut iced_x86::code_asm::CodeAssembler) -> Result<()>,
+    ) -> Result<&Patch> {
+        let bitness = match self.arch {
+            ArchitectureIdent::X86(bits, _) => bits as u32,
+            _ => return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArchitecture)),
+        };
+
+        let mut asm = iced_x86::code_asm::CodeAssembler::new(bitness)
+            .map_err(|e| Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument).log_error(e))?;
+        func(&mut asm)?;
+        let data = asm
+            .assemble(address.to_umem() as u64)
+            .map_err(|e| Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument).log_error(e))?;
+
+        self.patch(address, &data)
+    }
+
+    /// Restores the patch that starts at `address`.
+    pub fn restore(&mut self, address: Address) -> Result<()> {
+        let idx = self
+            .patches
+            .iter()
+            .position(|p| p.address == address)
+            .ok_or(Error(ErrorOrigin::Memory, ErrorKind::NotFound))?;
+
+        self.mem
+            .write_raw(address, &self.patches[idx].original)
+            .data()?;
+        self.patches.remove(idx);
+
+        Ok(())
+    }
+
+    /// Restores all patches in reverse order of application.
+    ///
+    /// Patches that could not be restored are kept and the first error is returned.
+    pub fn restore_all(&mut self) -> Result<()> {
+        let mut ret = Ok(());
+        let mut failed = vec![];
+
+        while let Some(patch) = self.patches.pop() {
+            if let Err(err) = self.mem.write_raw(patch.address, &patch.original).data() {
+                failed.push(patch);
+                if ret.is_ok() {
+                    ret = Err(err);
+                }
+            }
+        }
+
+        failed.reverse();
+        self.patches = failed;
+
+        ret
+    }
+}
+
+/// Returns `len` bytes of no-op instructions for the given architecture.
+///
+/// On x86 single byte `nop` instructions are used. On AArch64 `len` has to be a multiple of the
+/// 4 byte instruction size.
+pub fn nop_bytes(arch: ArchitectureIdent, len: usize) -> Result<Vec<u8>> {
+    match arch {
+        ArchitectureIdent::X86(_, _) => Ok(vec![0x90; len]),
+        ArchitectureIdent::AArch64(_) if len % 4 == 0 => Ok([0x1f, 0x20, 0x03, 0xd5]
+            .iter()
+            .copied()
+            .cycle()
+            .take(len)
+            .collect()),
+        ArchitectureIdent::AArch64(_) => {
+            Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                .log_warn("nop range must be a multiple of the instruction size"))
+        }
+        _ => Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArchitecture)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{MemoryViewMetadata, PhysicalMemory, ReadRawMemOps, WriteRawMemOps};
+    use crate::types::size;
+    use cglue::tuple::CTup3;
+
+    fn patcher(mem: &mut DummyMemory) -> Patcher<impl MemoryView + '_> {
+        let mut view = mem.phys_view();
+        view.write_raw(Address::from(0x1000u64), &[0xcc; 16])
+            .unwrap();
+        Patcher::new(view, ArchitectureIdent::X86(64, false))
+    }
+
+    #[test]
+    fn overlapping_patches() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut patcher = patcher(&mut mem);
+        let addr = Address::from(0x1000u64);
+
+        patcher.nop(addr + 4usize, 4).unwrap();
+
+        // overlapping the start, the end or the whole patch is rejected
+        for (offset, len) in [(2usize, 4), (6, 4), (0, 16), (5, 1)] {
+            assert_eq!(
+                patcher.patch(addr + offset, &vec![0u8; len]).unwrap_err().1,
+                ErrorKind::AlreadyExists
+            );
+        }
+        assert_eq!(patcher.patches().len(), 1);
+
+        // rejected patches do not touch memory
+        let mut buf = [0u8; 16];
+        patcher.mem().read_raw_into(addr, &mut buf).unwrap();
+        assert_eq!(
+            buf,
+            [
+                0xcc, 0xcc, 0xcc, 0xcc, 0x90, 0x90, 0x90, 0x90, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+                0xcc, 0xcc
+            ]
+        );
+
+        // adjacent patches are fine
+        patcher.patch(addr, &[1, 2, 3, 4]).unwrap();
+        patcher.patch(addr + 8usize, &[5, 6]).unwrap();
+        assert_eq!(patcher.patches().len(), 3);
+    }
+
+    #[test]
+    fn restore_order() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut patcher = patcher(&mut mem);
+        let addr = Address::from(0x1000u64);
+
+        patcher.patch(addr, &[1, 1]).unwrap();
+        patcher.patch(addr + 2usize, &[2, 2]).unwrap();
+        patcher.patch(addr + 4usize, &[3, 3]).unwrap();
+
+        // patches can be restored individually, in any order
+        patcher.restore(addr + 2usize).unwrap();
+        assert_eq!(
+            patcher.mem().read::<[u8; 6]>(addr).unwrap(),
+            [1, 1, 0xcc, 0xcc, 3, 3]
+        );
+        assert_eq!(
+            patcher.restore(addr + 2usize).unwrap_err().1,
+            ErrorKind::NotFound
+        );
+
+        // the original bytes are taken when patching, so a repatched range is restored properly
+        patcher.patch(addr + 2usize, &[4, 4]).unwrap();
+        assert_eq!(
+            patcher
+                .patches()
+                .iter()
+                .map(|p| p.address)
+                .collect::<Vec<_>>(),
+            vec![addr, addr + 4usize, addr + 2usize]
+        );
+
+        patcher.restore_all().unwrap();
+        assert!(patcher.patches().is_empty());
+        assert_eq!(patcher.mem().read::<[u8; 6]>(addr).unwrap(), [0xcc; 6]);
+    }
+
+    /// Memory view that fails the next `fail_writes` writes after writing their first byte.
+    struct TornWrites<T> {
+        mem: T,
+        fail_writes: usize,
+    }
+
+    impl<T: MemoryView> MemoryView for TornWrites<T> {
+        fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+            self.mem.read_raw_iter(data)
+        }
+
+        fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+            if self.fail_writes == 0 {
+                return self.mem.write_raw_iter(data);
+            }
+
+            self.fail_writes -= 1;
+            for CTup3(addr, _, buf) in data.inp {
+                self.mem.write_raw(addr, &buf[..1]).data()?;
+            }
+            Err(Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteMemory))
+        }
+
+        fn metadata(&self) -> MemoryViewMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    #[test]
+    fn failed_patch_restores_original() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let addr = Address::from(0x1000u64);
+        let mut view = mem.phys_view();
+        view.write_raw(addr, &[0xcc; 16]).unwrap();
+        let mut patcher = Patcher::new(
+            TornWrites {
+                mem: view,
+                fail_writes: 1,
+            },
+            ArchitectureIdent::X86(64, false),
+        );
+
+        // the torn write is undone and nothing is recorded
+        assert_eq!(
+            patcher.patch(addr, &[1, 2, 3, 4]).unwrap_err().1,
+            ErrorKind::UnableToWriteMemory
+        );
+        assert!(patcher.patches().is_empty());
+        assert_eq!(patcher.mem().read::<[u8; 4]>(addr).unwrap(), [0xcc; 4]);
+
+        // a failing restore does not record the patch either
+        patcher.mem().fail_writes = 2;
+        assert_eq!(
+            patcher.nop(addr, 4).unwrap_err().1,
+            ErrorKind::UnableToWriteMemory
+        );
+        assert!(patcher.patches().is_empty());
+
+        // the range can be patched once writes succeed again
+        patcher.patch(addr, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(patcher.mem().read::<[u8; 4]>(addr).unwrap(), [1, 2, 3, 4]);
+    }
+}
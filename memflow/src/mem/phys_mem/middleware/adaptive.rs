@@ -0,0 +1,362 @@
+use ::std::time::{Duration, Instant};
+
+use std::prelude::v1::*;
+
+use crate::cglue::CTup2;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, PhysicalAddress};
+
+/// Number of full batches that are measured before the batch size is adjusted.
+const SAMPLE_WINDOW: usize = 8;
+
+/// Tunes a batch size towards the highest observed throughput.
+///
+/// The tuner performs a simple hill climb: after every 8 measured batches the
+/// batch size is doubled or halved, depending on whether the previous step improved the
+/// throughput or not. The batch size always stays within the configured bounds.
+#[derive(Clone, Debug)]
+pub struct BatchSizeTuner {
+    min: usize,
+    max: usize,
+    current: usize,
+    growing: bool,
+    last_throughput: f64,
+    samples: usize,
+    bytes: usize,
+    elapsed: Duration,
+}
+
+impl BatchSizeTuner {
+    /// Creates a new tuner starting at `initial`, bounded by `min` and `max`.
+    pub fn new(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+
+        Self {
+            min,
+            max,
+            current: initial.max(min).min(max),
+            growing: true,
+            last_throughput: 0.0,
+            samples: 0,
+            bytes: 0,
+            elapsed: Duration::default(),
+        }
+    }
+
+    /// Returns the current batch size.
+    pub fn batch_size(&self) -> usize {
+        self.current
+    }
+
+    /// Records a batch of `bytes` bytes that took `elapsed` to complete.
+    ///
+    /// Only full batches should be recorded, since smaller batches say nothing about the
+    /// performance of the current batch size.
+    pub fn record(&mut self, bytes: usize, elapsed: Duration) {
+        self.samples += 1;
+        self.bytes += bytes;
+        self.elapsed += elapsed;
+
+        if self.samples < SAMPLE_WINDOW {
+            return;
+        }
+
+        let throughput = self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON);
+
+        if throughput < self.last_throughput {
+            self.growing = !self.growing;
+        }
+
+        self.last_throughput = throughput;
+        self.samples = 0;
+        self.bytes = 0;
+        self.elapsed = Duration::default();
+
+        self.current = if self.growing {
+            self.current.saturating_mul(2).min(self.max)
+        } else {
+            (self.current / 2).max(self.min)
+        };
+    }
+}
+
+/// The adaptive batching middleware splits physical reads into batches and tunes the batch size
+/// based on the measured throughput of the underlying connector.
+///
+/// Different connectors favor vastly different batch sizes. Memory mapped connectors do not care,
+/// while connectors talking to external hardware (e.g. over USB) suffer from both too small
+/// batches (roundtrip latency dominates) and too large batches (transfer buffers overflow).
+/// This middleware finds a good batch size at runtime, so it does not have to be chosen per setup.
+///
+/// The batch size is bounded by the `ideal_batch_size` reported in the
+/// [`PhysicalMemoryMetadata`] of the underlying connector.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct AdaptiveBatchPhysicalMemory<T> {
+    mem: T,
+    tuner: BatchSizeTuner,
+}
+
+impl<T> Clone for AdaptiveBatchPhysicalMemory<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            tuner: self.tuner.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> AdaptiveBatchPhysicalMemory<T> {
+    /// Constructs a new middleware with the given tuner.
+    ///
+    /// This function is used when manually constructing a middleware inside of the memflow crate itself.
+    ///
+    /// For general usage it is advised to just use the [builder](struct.AdaptiveBatchPhysicalMemoryBuilder.html)
+    /// to construct the middleware.
+    pub fn new(mem: T, tuner: BatchSizeTuner) -> Self {
+        Self { mem, tuner }
+    }
+
+    /// Returns a new builder for the adaptive batching middleware with default settings.
+    pub fn builder(mem: T) -> AdaptiveBatchPhysicalMemoryBuilder<T> {
+        AdaptiveBatchPhysicalMemoryBuilder::new(mem)
+    }
+
+    /// Returns the batch size currently used for reads.
+    pub fn batch_size(&self) -> usize {
+        self.tuner.batch_size()
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for AdaptiveBatchPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            mut inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let mut batch = vec![];
+
+        loop {
+            let batch_size = self.tuner.batch_size();
+            batch.extend((&mut inp).take(batch_size));
+
+            if batch.is_empty() {
+                return Ok(());
+            }
+
+            let full = batch.len() == batch_size;
+            let bytes = batch.iter().map(|data| data.2.len()).sum::<usize>();
+
+            let start_time = Instant::now();
+            {
+                let mut drain = batch.drain(..);
+                self.mem.phys_read_raw_iter(MemOps {
+                    inp: (&mut drain).into(),
+                    out: out.as_deref_mut(),
+                    out_fail: out_fail.as_deref_mut(),
+                })?;
+            }
+
+            if full {
+                self.tuner.record(bytes, start_time.elapsed());
+            }
+        }
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+/// The builder interface for constructing a `AdaptiveBatchPhysicalMemory` object.
+pub struct AdaptiveBatchPhysicalMemoryBuilder<T> {
+    mem: T,
+    initial_batch_size: usize,
+    min_batch_size: usize,
+    max_batch_size: usize,
+}
+
+impl<T: PhysicalMemory> AdaptiveBatchPhysicalMemoryBuilder<T> {
+    /// Creates a new `AdaptiveBatchPhysicalMemory` builder.
+    /// The memory object is mandatory as the AdaptiveBatchPhysicalMemory struct wraps around it.
+    ///
+    /// Without further adjustments this function creates a middleware starting with batches of
+    /// 64 reads, which are tuned between 1 and 4096 reads per batch.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::mem::{PhysicalMemory, AdaptiveBatchPhysicalMemory, MemoryView};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let mut middleware = AdaptiveBatchPhysicalMemory::builder(mem)
+    ///         .initial_batch_size(16)
+    ///         .max_batch_size(256)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     let value: u64 = middleware.phys_view().read(0.into()).unwrap();
+    ///     # assert_eq!(value, 0x23bd_318f_f3a3_5821);
+    ///     assert_eq!(middleware.batch_size(), 16);
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # mem.phys_write(0.into(), &0x23bd_318f_f3a3_5821u64).unwrap();
+    /// # build(mem);
+    /// ```
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            initial_batch_size: 64,
+            min_batch_size: 1,
+            max_batch_size: 4096,
+        }
+    }
+
+    /// Changes the batch size the middleware starts with.
+    pub fn initial_batch_size(mut self, size: usize) -> Self {
+        self.initial_batch_size = size;
+        self
+    }
+
+    /// Changes the smallest batch size the middleware will use.
+    pub fn min_batch_size(mut self, size: usize) -> Self {
+        self.min_batch_size = size;
+        self
+    }
+
+    /// Changes the largest batch size the middleware will use.
+    ///
+    /// The `ideal_batch_size` reported by the underlying connector is used instead if it is smaller.
+    pub fn max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = size;
+        self
+    }
+
+    /// Builds the `AdaptiveBatchPhysicalMemory` object or returns an error.
+    pub fn build(self) -> Result<AdaptiveBatchPhysicalMemory<T>> {
+        if self.min_batch_size > self.max_batch_size {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                .log_error("minimum batch size is larger than the maximum batch size"));
+        }
+
+        let ideal = self.mem.metadata().ideal_batch_size as usize;
+        let max = self.max_batch_size.min(ideal.max(self.min_batch_size));
+
+        Ok(AdaptiveBatchPhysicalMemory::new(
+            self.mem,
+            BatchSizeTuner::new(self.initial_batch_size, self.min_batch_size, max),
+        ))
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    AdaptiveBatchPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuner_bounds() {
+        let mut tuner = BatchSizeTuner::new(64, 16, 128);
+
+        // steadily improving throughput keeps growing the batch size up to the bound
+        for i in 1..=(SAMPLE_WINDOW * 4) {
+            tuner.record(i * 0x1000, Duration::from_millis(1));
+        }
+        assert_eq!(tuner.batch_size(), 128);
+
+        // degrading throughput reverses the direction
+        for _ in 0..SAMPLE_WINDOW {
+            tuner.record(1, Duration::from_millis(1));
+        }
+        assert_eq!(tuner.batch_size(), 64);
+    }
+
+    #[test]
+    fn read_batches() {
+        use crate::cglue::CSliceMut;
+        use crate::dataview::PodMethods;
+        use crate::dummy::DummyMemory;
+        use crate::types::size;
+
+        let mut mem = AdaptiveBatchPhysicalMemory::builder(DummyMemory::new(size::mb(2)))
+            .initial_batch_size(4)
+            .build()
+            .unwrap();
+
+        let addrs = (0..100u64).map(|i| i * 8).collect::<Vec<_>>();
+        for &addr in addrs.iter() {
+            mem.phys_write(addr.into(), &addr).unwrap();
+        }
+
+        let mut bufs = vec![0u64; addrs.len()];
+        MemOps::with(
+            addrs.iter().zip(bufs.iter_mut()).map(|(&addr, buf)| {
+                (
+                    PhysicalAddress::from(addr),
+                    CSliceMut::from(buf.as_bytes_mut()),
+                )
+            }),
+            None,
+            None,
+            |data| mem.phys_read_raw_iter(data),
+        )
+        .unwrap();
+
+        assert_eq!(bufs, addrs);
+    }
+}
@@ -0,0 +1,375 @@
+//! Blocklist of physical memory ranges that must never be accessed.
+//!
+//! Physical address spaces contain more than just RAM. Memory mapped device registers (MMIO)
+//! are interleaved with it and reading or writing them can have side effects on the device,
+//! which in the worst case hangs or bricks the target. Scanning the physical address space
+//! naively will eventually run into such ranges.
+//!
+//! The [`BlockedMemoryFilter`] middleware checks all accesses against a list of blocked ranges.
+//! Blocked parts of an access are never forwarded to the connector. They are reported as failed
+//! and the access returns an error of kind [`ErrorKind::BlockedMemoryRange`], after all other
+//! parts of it have been processed.
+//!
+//! Blocked ranges can be added manually, derived from the gaps of a memory map, or loaded from
+//! the `[[blocked]]` entries of a memory map file.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{BlockedMemoryFilter, MemoryView, PhysicalMemory};
+//! use memflow::error::ErrorKind;
+//!
+//! fn filter<T: PhysicalMemory>(mem: T) {
+//!     let mut mem = BlockedMemoryFilter::new(mem);
+//!     mem.add_range(0xf0000.into(), 0x10000);
+//!
+//!     assert!(mem.phys_view().read::<u64>(0x1000.into()).is_ok());
+//!
+//!     let err = mem.phys_write(0xf0000.into(), &0u64).unwrap_err();
+//!     assert_eq!(err.1, ErrorKind::BlockedMemoryRange);
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # filter(DummyMemory::new(size::mb(1)));
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::{
+    opt_call, MemOps, MemoryMap, PhysicalMemory, PhysicalMemoryMapping,
+    PhysicalMemoryMappingCallback, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// Physical memory middleware refusing all accesses to blocked ranges.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+#[derive(Clone)]
+pub struct BlockedMemoryFilter<T> {
+    mem: T,
+    /// Sorted, non-overlapping list of blocked ranges as `(start, end)`
+    ranges: Vec<(Address, Address)>,
+}
+
+impl<T: PhysicalMemory> BlockedMemoryFilter<T> {
+    /// Constructs a new filter without any blocked ranges.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            ranges: vec![],
+        }
+    }
+
+    /// Blocks `size` bytes starting at `base`.
+    ///
+    /// Overlapping and adjacent ranges are merged.
+    pub fn add_range(&mut self, base: Address, size: umem) {
+        if size == 0 {
+            return;
+        }
+
+        let mut start = base;
+        let mut end = base + size;
+
+        // merge all ranges overlapping or touching the new one
+        self.ranges.retain(|&(s, e)| {
+            if s <= end && e >= start {
+                start = start.min(s);
+                end = end.max(e);
+                false
+            } else {
+                true
+            }
+        });
+
+        let idx = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(idx, (start, end));
+    }
+
+    /// Blocks all addresses that are not backed by the given memory map.
+    ///
+    /// Memory maps usually only describe RAM, the gaps between the mappings are where device
+    /// memory resides. Everything above the last mapping is blocked as well.
+    pub fn block_unmapped(&mut self, map: &MemoryMap<(Address, umem)>) {
+        let mut prev = Address::null();
+        for mapping in map.iter() {
+            let base = mapping.base();
+            if base > prev {
+                self.add_range(prev, (base - prev) as umem);
+            }
+            prev = prev.max(base + mapping.output().1);
+        }
+
+        if prev < Address::invalid() {
+            self.add_range(prev, Address::invalid().to_umem() - prev.to_umem());
+        }
+    }
+
+    /// Adds all `[[blocked]]` ranges of a memory map file.
+    ///
+    /// The file uses the same format as [`MemoryMap::open`], blocked ranges are described by
+    /// their base and length:
+    ///
+    /// ```toml
+    /// [[range]]
+    /// base=0x1000
+    /// length=0x9e000
+    ///
+    /// [[blocked]]
+    /// base=0xfed00000
+    /// length=0x1000
+    /// ```
+    #[cfg(feature = "memmapfiles")]
+    pub fn add_ranges_from_file<P: AsRef<::std::path::Path>>(&mut self, path: P) -> Result<()> {
+        let contents = ::std::fs::read_to_string(path).map_err(|err| {
+            Error(ErrorOrigin::MemoryMap, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the memory mapping file: {}", err))
+        })?;
+        let mappings: crate::mem::mem_map::MemoryMapFile =
+            ::toml::from_str(&contents).map_err(|err| {
+                Error(ErrorOrigin::MemoryMap, ErrorKind::UnableToReadFile).log_error(format!(
+                    "unable to parse the memory mapping toml file: {}",
+                    err
+                ))
+            })?;
+
+        for range in mappings.blocked.iter() {
+            self.add_range(range.base.into(), range.length as umem);
+        }
+
+        Ok(())
+    }
+
+    /// Removes all blocked ranges.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Returns all blocked ranges as `(start, end)` pairs in ascending order.
+    pub fn ranges(&self) -> &[(Address, Address)] {
+        &self.ranges
+    }
+
+    /// Returns true if `addr` is blocked.
+    pub fn is_blocked(&self, addr: Address) -> bool {
+        self.ranges
+            .get(self.ranges.partition_point(|&(_, e)| e <= addr))
+            .map_or(false, |&(s, _)| s <= addr)
+    }
+
+    /// Returns true if any byte of the `size` bytes starting at `addr` is blocked.
+    pub fn overlaps(&self, addr: Address, size: umem) -> bool {
+        self.ranges
+            .get(self.ranges.partition_point(|&(_, e)| e <= addr))
+            .map_or(false, |&(s, _)| s < addr + size)
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Splits the access at `addr` along the blocked ranges.
+    ///
+    /// Accessible parts are pushed into `pass`, blocked parts into `failed`.
+    fn split<B: SplitAtIndex>(
+        &self,
+        CTup3(addr, meta_addr, buf): CTup3<PhysicalAddress, Address, B>,
+        pass: &mut Vec<CTup3<PhysicalAddress, Address, B>>,
+        failed: &mut Vec<CTup2<Address, B>>,
+    ) {
+        let mut next = Some((addr.address(), (meta_addr, buf)));
+
+        while let Some((cur, data)) = next.take() {
+            let wrap = |a: Address| {
+                PhysicalAddress::with_page(a, addr.page_type(), addr.page_size() as umem)
+            };
+
+            match self
+                .ranges
+                .get(self.ranges.partition_point(|&(_, e)| e <= cur))
+            {
+                Some(&(s, e)) if s <= cur => {
+                    let (head, tail) = data.split_at((e - cur) as umem);
+                    if let Some((meta_addr, buf)) = head {
+                        failed.push(CTup2(meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (e, tail));
+                }
+                Some(&(s, _)) => {
+                    let (head, tail) = data.split_at((s - cur) as umem);
+                    if let Some((meta_addr, buf)) = head {
+                        pass.push(CTup3(wrap(cur), meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (s, tail));
+                }
+                None => pass.push(CTup3(wrap(cur), data.0, data.1)),
+            }
+        }
+    }
+}
+
+fn blocked_error(addr: Address) -> Error {
+    Error(ErrorOrigin::PhysicalMemory, ErrorKind::BlockedMemoryRange)
+        .log_debug(format!("access to blocked address {:x}", addr))
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for BlockedMemoryFilter<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        if self.ranges.is_empty() {
+            return self.mem.phys_read_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let mut pass = vec![];
+        let mut failed = vec![];
+        for data in inp {
+            self.split(data, &mut pass, &mut failed);
+        }
+
+        let blocked = failed.first().map(|CTup2(addr, _)| *addr);
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(pass.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })?;
+
+        blocked.map_or(Ok(()), |addr| Err(blocked_error(addr)))
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        if self.ranges.is_empty() {
+            return self.mem.phys_write_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let mut pass = vec![];
+        let mut failed = vec![];
+        for data in inp {
+            self.split(data, &mut pass, &mut failed);
+        }
+
+        let blocked = failed.first().map(|CTup2(addr, _)| *addr);
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(pass.into_iter(), out, out_fail, |data| {
+            mem.phys_write_raw_iter(data)
+        })?;
+
+        blocked.map_or(Ok(()), |addr| Err(blocked_error(addr)))
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        let ranges = ranges
+            .iter()
+            .filter(|CTup2(addr, size)| !self.overlaps(addr.address(), *size))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.mem.phys_prefetch(&ranges)
+    }
+
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        if self.overlaps(addr.address(), new.len() as umem) {
+            return Err(blocked_error(addr.address()));
+        }
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    BlockedMemoryFilter<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn blocked_access() {
+        let mut mem = BlockedMemoryFilter::new(DummyMemory::new(size::mb(1)));
+        mem.add_range(0x2000.into(), 0x1000);
+
+        mem.phys_write(0x1ff0.into(), &[1u8; 0x10]).unwrap();
+        assert_eq!(
+            mem.phys_write(0x1ff0.into(), &[2u8; 0x20]).unwrap_err().1,
+            ErrorKind::BlockedMemoryRange
+        );
+
+        // the accessible part of a blocked write has been written
+        let mut view = mem.phys_view();
+        assert_eq!(view.read::<[u8; 0x10]>(0x1ff0.into()).unwrap(), [2u8; 0x10]);
+        assert!(view.read::<u64>(0x2ffc.into()).is_err());
+        assert!(view.read::<u64>(0x3000.into()).is_ok());
+
+        assert!(mem.overlaps(0x1ff0.into(), 0x20));
+        assert!(!mem.overlaps(0x1ff0.into(), 0x10));
+    }
+
+    #[test]
+    fn unmapped_ranges() {
+        let mut map = MemoryMap::new();
+        map.push_remap(0x1000.into(), 0x1000, 0.into());
+        map.push_remap(0x4000.into(), 0x2000, 0x1000.into());
+
+        let mut mem = BlockedMemoryFilter::new(DummyMemory::new(size::mb(1)));
+        mem.block_unmapped(&map);
+
+        assert_eq!(
+            mem.ranges(),
+            &[
+                (Address::null(), Address::from(0x1000u64)),
+                (Address::from(0x2000u64), Address::from(0x4000u64)),
+                (Address::from(0x6000u64), Address::invalid()),
+            ]
+        );
+        assert!(!mem.is_blocked(0x5fff.into()));
+    }
+}
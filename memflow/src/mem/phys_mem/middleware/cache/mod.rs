@@ -33,15 +33,18 @@ use crate::architecture::ArchitectureObj;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::iter::PageChunks;
 use crate::mem::{
-    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
 };
+use cglue::slice::CSliceMut;
 use cglue::tuple::*;
 use page_cache::{PageCache, PageValidity};
 
 use crate::types::cache::{CacheValidator, DefaultCacheValidator};
 
-use crate::types::{size, PageType};
+use crate::types::{size, umem, PageType, PhysicalAddress};
+
+use std::prelude::v1::*;
 
 use bumpalo::Bump;
 
@@ -120,6 +123,108 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemory<'a, T, Q> {
     }
 }
 
+#[cfg(feature = "std")]
+impl<'a, T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemory<'a, T, Q> {
+    /// Saves all valid pages of the cache to the file at `path`.
+    ///
+    /// The `identity` has to uniquely identify the state of the target,
+    /// see the [`persist`](crate::types::cache::persist) module for details.
+    ///
+    /// Returns the number of pages written.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory, MemoryView};
+    /// use std::time::Duration;
+    ///
+    /// fn persist<T: PhysicalMemory + Clone>(mem: T, path: &std::path::Path) {
+    ///     let mut cache = CachedPhysicalMemory::builder(mem.clone())
+    ///         .arch(x64::ARCH)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     // reads warm up the cache, saving it preserves it for the next session
+    ///     cache.phys_view().read::<u64>(0x1000.into()).unwrap();
+    ///     cache.save(path, "snapshot").unwrap();
+    ///
+    ///     let mut cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .build()
+    ///         .unwrap();
+    ///     cache.load(path, "snapshot", Duration::from_secs(3600)).unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let path = std::env::temp_dir().join("memflow_page_cache_doctest");
+    /// # persist(DummyMemory::new(size::mb(4)), &path);
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn save(&mut self, path: impl AsRef<std::path::Path>, identity: &str) -> Result<usize> {
+        use crate::types::cache::persist::{create_cache_file, write_error, CacheFileHeader};
+        use std::io::Write;
+
+        self.cache.validator.update_validity();
+
+        let page_size = self.cache.page_size();
+        let count = self.cache.valid_pages().count();
+
+        let mut file = create_cache_file(path)?;
+        CacheFileHeader::new(*b"PAGE", identity, (8 + page_size) as u64, count as u64)
+            .write(&mut file)?;
+
+        for (addr, page) in self.cache.valid_pages() {
+            file.write_all(&(addr.to_umem() as u64).to_le_bytes())
+                .and_then(|_| file.write_all(page))
+                .map_err(write_error)?;
+        }
+        file.flush().map_err(write_error)?;
+
+        Ok(count)
+    }
+
+    /// Loads the pages stored in the file at `path` into the cache.
+    ///
+    /// The file is rejected if it was saved from a target with a different `identity`,
+    /// is older than `max_age` or was saved from a cache with a different page size.
+    ///
+    /// Returns the number of pages loaded.
+    pub fn load(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        identity: &str,
+        max_age: std::time::Duration,
+    ) -> Result<usize> {
+        use crate::types::cache::persist::{
+            open_cache_file, read_error, read_u64, CacheFileHeader,
+        };
+        use std::io::Read;
+
+        let mut file = open_cache_file(path)?;
+        let header = CacheFileHeader::read(&mut file, *b"PAGE", identity, max_age)?;
+
+        let page_size = self.cache.page_size();
+        if header.entry_size != (8 + page_size) as u64 {
+            return Err(Error(ErrorOrigin::PageCache, ErrorKind::Configuration)
+                .log_error("cache file was saved with a different page size"));
+        }
+
+        self.cache.validator.update_validity();
+
+        let mut page = vec![0u8; page_size];
+        let mut loaded = 0;
+        for _ in 0..header.entry_count {
+            let addr = read_u64(&mut file)?;
+            file.read_exact(&mut page).map_err(read_error)?;
+            if self.cache.insert_page((addr as umem).into(), &page) {
+                loaded += 1;
+            }
+        }
+
+        Ok(loaded)
+    }
+}
+
 impl<'a, T: PhysicalMemory> CachedPhysicalMemory<'a, T, DefaultCacheValidator> {
     /// Returns a new builder for this cache with default settings.
     pub fn builder(mem: T) -> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
@@ -175,6 +280,79 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysical
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    /// Reads all cacheable pages of the given ranges into the cache in a single batch.
+    ///
+    /// Ranges exceeding the size of the cache are truncated, since prefetching more would only
+    /// evict pages fetched by the same call.
+    ///
+    /// Ranges of unknown page type (e.g. prefetched through a
+    /// [`PhysicalMemoryView`](crate::mem::PhysicalMemoryView)) are fetched as read-only pages.
+    /// Subsequent reads of a cacheable page type are served from the cache.
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        let mut remaining = self.cache.cache_size() as umem;
+
+        let ranges = ranges
+            .iter()
+            .map(|&CTup2(addr, len)| {
+                if addr.page_type() == PageType::UNKNOWN {
+                    let page_size = addr.page_size() as umem;
+                    let addr =
+                        PhysicalAddress::with_page(addr.address(), PageType::READ_ONLY, page_size);
+                    (addr, len)
+                } else {
+                    (addr, len)
+                }
+            })
+            .filter(|(addr, _)| self.cache.is_cached_page_type(addr.page_type()))
+            .map(|(addr, len)| {
+                let len = std::cmp::min(len, remaining);
+                remaining -= len;
+                (addr, len as usize)
+            })
+            .take_while(|(_, len)| *len > 0)
+            .collect::<Vec<_>>();
+
+        let mut buf = vec![0u8; ranges.iter().map(|(_, len)| len).sum()];
+        let mut rest = buf.as_mut_slice();
+
+        let reads = ranges.into_iter().map(|(addr, len)| {
+            let (chunk, tail) = std::mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+            (addr, CSliceMut::from(chunk))
+        });
+
+        // failed reads are simply not cached
+        let _ = MemOps::with(reads, None, None, |data| self.phys_read_raw_iter(data));
+    }
+
+    /// Forwards the exchange to the underlying memory.
+    ///
+    /// The comparison has to observe the current state of the target, so all cached pages
+    /// touched by the exchange are invalidated beforehand.
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.cache.validator.update_validity();
+
+        let page_size = self.cache.page_size();
+        let end = addr.address() + expected.len();
+        let mut page = addr.address().as_page_aligned(page_size);
+        while page < end {
+            self.cache.invalidate_page(page, addr.page_type());
+            page += page_size;
+        }
+
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
 }
 
 /// The builder interface for constructing a `CachedPhysicalMemory` object.
@@ -424,6 +602,10 @@ impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
     /// All page types matching this bitmask will be kept in the cache.
     /// All pages that are not matching the bitmask will be re-read/re-written on every request.
     ///
+    /// The privilege level and executable flags (see [`PageType::ACCESS`]) can be used to further
+    /// restrict the cached pages, e.g. to only cache user mode pages. They are ignored in case the
+    /// mask does not contain any of them. See [`PageType::matches_mask`] for details.
+    ///
     /// The default setting is `PageType::PAGE_TABLE | PageType::READ_ONLY`.
     ///
     /// This setting can drastically impact the performance of the cache.
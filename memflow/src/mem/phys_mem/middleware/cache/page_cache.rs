@@ -3,6 +3,7 @@ use crate::error::Result;
 use crate::iter::PageChunks;
 use crate::mem::mem_data::*;
 use crate::mem::phys_mem::*;
+use crate::types::cache::stats::{self, CacheStat};
 use crate::types::{cache::CacheValidator, umem, Address, PageType, PhysicalAddress};
 
 use std::alloc::{alloc, alloc_zeroed, dealloc, Layout};
@@ -122,8 +123,12 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
         self.page_size
     }
 
+    pub fn cache_size(&self) -> usize {
+        self.address.len() * self.page_size
+    }
+
     pub fn is_cached_page_type(&self, page_type: PageType) -> bool {
-        self.page_type_mask.contains(page_type)
+        page_type.matches_mask(self.page_type_mask)
     }
 
     pub fn cached_page_mut(&mut self, addr: Address, skip_validator: bool) -> CacheEntry<'a> {
@@ -176,11 +181,42 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
     }
 
     pub fn invalidate_page(&mut self, addr: Address, page_type: PageType) {
-        if self.page_type_mask.contains(page_type) {
+        if page_type.matches_mask(self.page_type_mask) {
             self.invalidate_page_raw(addr)
         }
     }
 
+    /// Returns all pages currently held in the cache that are still valid.
+    pub fn valid_pages(&self) -> impl Iterator<Item = (Address, &[u8])> + '_ {
+        (0..self.address.len())
+            .filter(move |&idx| {
+                self.address[idx] != Address::INVALID && self.validator.is_slot_valid(idx)
+            })
+            .filter_map(move |idx| {
+                self.page_refs[idx]
+                    .as_deref()
+                    .map(|page| (self.address[idx], page))
+            })
+    }
+
+    /// Inserts the contents of a page into the cache and validates it.
+    ///
+    /// Returns false if the slot of the page is currently in use by a read.
+    pub fn insert_page(&mut self, addr: Address, data: &[u8]) -> bool {
+        let aligned_addr = addr.as_page_aligned(self.page_size);
+        let idx = self.page_index(aligned_addr);
+
+        match self.page_refs[idx].take() {
+            Some(buf) => {
+                let len = std::cmp::min(buf.len(), data.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                self.validate_page(aligned_addr, buf);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn split_to_chunks(
         CTup3(addr, meta_addr, out): PhysicalReadData<'_>,
         page_size: usize,
@@ -209,6 +245,9 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
     ) -> Result<()> {
         let page_size = self.page_size;
 
+        // flush the queued reads once they reach the batch size preferred by the connector
+        let batch_size = std::cmp::min(64, mem.metadata().ideal_batch_size.max(1) as usize);
+
         {
             let mut next = iter.next();
             let mut clist = BumpVec::new_in(arena);
@@ -234,6 +273,7 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
 
                             match cached_page.validity {
                                 PageValidity::Valid(buf) => {
+                                    stats::record(CacheStat::PageCacheHit, 1);
                                     let aligned_addr = paddr.as_page_aligned(self.page_size);
                                     let start = paddr - aligned_addr;
                                     let cached_buf = buf
@@ -246,6 +286,7 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                                     self.put_page(cached_page.address, buf);
                                 }
                                 PageValidity::Validatable(buf) => {
+                                    stats::record(CacheStat::PageCacheMiss, 1);
                                     clist.push(prd);
                                     wlistcache.push(CTup3(
                                         PhysicalAddress::from(cached_page.address),
@@ -255,9 +296,11 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                                     self.mark_page_for_validation(cached_page.address);
                                 }
                                 PageValidity::ToBeValidated => {
+                                    stats::record(CacheStat::PageCacheHit, 1);
                                     clist.push(prd);
                                 }
                                 PageValidity::Invalid => {
+                                    stats::record(CacheStat::PageCacheMiss, 1);
                                     wlist.push(prd);
                                 }
                             }
@@ -269,9 +312,9 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                 next = iter.next();
 
                 if next.is_none()
-                    || wlist.len() >= 64
-                    || wlistcache.len() >= 64
-                    || clist.len() >= 64
+                    || wlist.len() >= batch_size
+                    || wlistcache.len() >= batch_size
+                    || clist.len() >= batch_size
                 {
                     if !wlist.is_empty() {
                         {
@@ -746,6 +789,106 @@ mod tests {
 
         assert_eq!(buf_start, buf_1);
     }
+    #[test]
+    fn prefetch() {
+        let mut dummy_mem = DummyMemory::new(size::mb(16));
+
+        let addr = PhysicalAddress::with_page(
+            Address::from(0x5000),
+            PageType::default().write(false),
+            0x1000,
+        );
+
+        dummy_mem.phys_write(addr, &[1u8; 16]).unwrap();
+
+        let cache = PageCache::new(
+            x86::x64::ARCH,
+            size::mb(2),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+
+        // clones of the dummy memory share the same underlying buffer
+        let mut mem_cache = CachedPhysicalMemory::new(dummy_mem.clone(), cache);
+        mem_cache.phys_prefetch(&[CTup2(addr, 0x1000)]);
+
+        dummy_mem.phys_write(addr, &[2u8; 16]).unwrap();
+
+        let mut buf = [0u8; 16];
+        mem_cache.phys_read_into(addr, &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 16]);
+    }
+
+    #[test]
+    fn prefetch_view() {
+        let mut dummy_mem = DummyMemory::new(size::mb(16));
+
+        let addr = PhysicalAddress::with_page(
+            Address::from(0x5000),
+            PageType::default().write(false),
+            0x1000,
+        );
+
+        dummy_mem.phys_write(addr, &[1u8; 16]).unwrap();
+
+        let cache = PageCache::new(
+            x86::x64::ARCH,
+            size::mb(2),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+
+        let mut mem_cache = CachedPhysicalMemory::new(dummy_mem.clone(), cache);
+
+        // the view does not know the page type of the prefetched range
+        mem_cache.phys_view().prefetch(addr.address(), 0x1000);
+
+        dummy_mem.phys_write(addr, &[2u8; 16]).unwrap();
+
+        let mut buf = [0u8; 16];
+        mem_cache.phys_read_into(addr, &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 16]);
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let mut dummy_mem = DummyMemory::new(size::mb(16));
+
+        let addr = PhysicalAddress::with_page(
+            Address::from(0x5000),
+            PageType::default().write(false),
+            0x1000,
+        );
+
+        let cache = PageCache::new(
+            x86::x64::ARCH,
+            size::mb(2),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+
+        let mut mem_cache = CachedPhysicalMemory::new(dummy_mem.clone(), cache);
+        let mut value = 0u64;
+        mem_cache.phys_write(addr, &1u64).unwrap();
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 1);
+
+        // the exchange has to compare against the memory, not the stale cache
+        dummy_mem.phys_write(addr, &2u64).unwrap();
+        assert!(!mem_cache.phys_cas(addr, &1u64, &3u64).unwrap());
+        assert!(mem_cache.phys_cas(addr, &2u64, &3u64).unwrap());
+
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 3);
+
+        // unaligned exchanges are emulated
+        assert!(mem_cache
+            .phys_cas(addr, &[3u8, 0, 0], &[4u8, 5, 6])
+            .unwrap());
+        dummy_mem.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 0x060504);
+    }
+
     #[test]
     fn cache_phys_mem_diffpages() {
         let dummy_mem = DummyMemory::new(size::mb(16));
@@ -0,0 +1,309 @@
+//! Enforcement of the read constraints of a connector.
+//!
+//! Some connectors can not serve arbitrary reads. DMA devices for example transfer memory in
+//! PCIe TLPs, which are limited in size and often have to be DWORD aligned. Connectors declare
+//! such limitations through the `max_read_size` and `read_alignment` fields of their
+//! [`PhysicalMemoryMetadata`].
+//!
+//! The [`ConstrainedPhysicalMemory`] middleware rewrites all reads to satisfy these constraints:
+//!
+//! - Reads larger than `max_read_size` are split into multiple reads.
+//! - Unaligned reads are widened to the surrounding aligned range, read into a temporary buffer
+//!   and copied back. If any part of the widened range fails, the entire original read is
+//!   reported as failed.
+//!
+//! Writes are forwarded unmodified.
+//!
+//! Connectors created through the plugin system are wrapped automatically if they declare any
+//! constraints, so neither the connector nor its users have to handle them.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{ConstrainedPhysicalMemory, MemoryView, PhysicalMemory};
+//!
+//! fn constrained<T: PhysicalMemory>(mem: T) {
+//!     // reads are split into aligned 128 byte reads
+//!     let mut mem = ConstrainedPhysicalMemory::with_constraints(mem, 128, 4);
+//!
+//!     mem.phys_write(0x1001.into(), &[1u8; 0x200]).unwrap();
+//!     assert_eq!(mem.phys_view().read::<[u8; 0x200]>(0x1001.into()).unwrap(), [1u8; 0x200]);
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # constrained(DummyMemory::new(size::mb(1)));
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::connector::dma::DmaLimits;
+use crate::error::Result;
+use crate::iter::SplitAtIndex;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps, ReadData,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// Physical memory middleware splitting and aligning reads to the constraints of a connector.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+#[derive(Clone)]
+pub struct ConstrainedPhysicalMemory<T> {
+    mem: T,
+    max_read_size: usize,
+    alignment: usize,
+}
+
+impl<T: PhysicalMemory> ConstrainedPhysicalMemory<T> {
+    /// Constructs a new middleware enforcing the constraints declared in the metadata of `mem`.
+    pub fn new(mem: T) -> Self {
+        let metadata = mem.metadata();
+        Self::with_constraints(mem, metadata.max_read_size, metadata.read_alignment)
+    }
+
+    /// Constructs a new middleware enforcing the given constraints.
+    ///
+    /// The alignment is rounded up to the next power of two and the maximum read size is rounded
+    /// down to a multiple of the alignment.
+    pub fn with_constraints(mem: T, max_read_size: u32, read_alignment: u32) -> Self {
+        let alignment = (read_alignment.max(1) as usize).next_power_of_two();
+        let max_read_size = std::cmp::max(max_read_size as usize & !(alignment - 1), alignment);
+
+        Self {
+            mem,
+            max_read_size,
+            alignment,
+        }
+    }
+
+    /// Constructs a new middleware enforcing the read limits of a DMA device.
+    pub fn with_dma_limits(mem: T, limits: &DmaLimits) -> Self {
+        Self::with_constraints(mem, limits.max_read_request, limits.alignment)
+    }
+
+    /// Returns true if reads have to be rewritten at all.
+    pub fn is_constrained(&self) -> bool {
+        self.alignment > 1 || self.max_read_size < u32::MAX as usize
+    }
+
+    /// Returns the maximum size of a single read.
+    pub fn max_read_size(&self) -> usize {
+        self.max_read_size
+    }
+
+    /// Returns the alignment of reads.
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for ConstrainedPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        if !self.is_constrained() {
+            return self.mem.phys_read_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let (max_read_size, alignment) = (self.max_read_size as umem, self.alignment as umem);
+
+        let mut pass = vec![];
+        let mut unaligned = vec![];
+        for CTup3(addr, meta_addr, buf) in inp {
+            if addr.address().to_umem() % alignment != 0 || buf.len() as umem % alignment != 0 {
+                unaligned.push(CTup3(addr, meta_addr, buf));
+                continue;
+            }
+
+            let mut next = Some((addr.address(), (meta_addr, buf)));
+            while let Some((cur, data)) = next.take() {
+                let (head, tail) = data.split_at(max_read_size);
+                if let Some((meta_addr, buf)) = head {
+                    let cur_addr =
+                        PhysicalAddress::with_page(cur, addr.page_type(), addr.page_size() as umem);
+                    pass.push(CTup3(cur_addr, meta_addr, buf));
+                }
+                next = tail.map(|tail| (cur + max_read_size, tail));
+            }
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(
+            pass.into_iter(),
+            out.as_deref_mut(),
+            out_fail.as_deref_mut(),
+            |data| mem.phys_read_raw_iter(data),
+        )?;
+
+        if unaligned.is_empty() {
+            return Ok(());
+        }
+
+        // widen all unaligned reads and issue them as a single batch
+        let mut widened = unaligned
+            .iter()
+            .map(|CTup3(addr, _, buf)| {
+                let start = addr.address().to_umem() & !(alignment - 1);
+                let end = (addr.address().to_umem() + buf.len() as umem + alignment - 1)
+                    & !(alignment - 1);
+                (start, vec![0u8; (end - start) as usize])
+            })
+            .collect::<Vec<_>>();
+
+        let mut failed = vec![];
+        {
+            let pieces = widened.iter_mut().flat_map(|(start, tmp)| {
+                let start = *start;
+                tmp.chunks_mut(max_read_size as usize)
+                    .enumerate()
+                    .map(move |(i, chunk)| {
+                        let addr = Address::from(start + i as umem * max_read_size);
+                        (PhysicalAddress::from(addr), CSliceMut::from(chunk))
+                    })
+            });
+
+            let fail = &mut |CTup2(addr, buf): ReadData| {
+                failed.push((addr, buf.len() as umem));
+                true
+            };
+
+            MemOps::with(pieces, None, Some(&mut fail.into()), |data| {
+                mem.phys_read_raw_iter(data)
+            })?;
+        }
+
+        for (CTup3(addr, meta_addr, mut buf), (start, tmp)) in unaligned.into_iter().zip(widened) {
+            let offset = (addr.address().to_umem() - start) as usize;
+            let len = buf.len();
+            buf.copy_from_slice(&tmp[offset..offset + len]);
+
+            let (addr, end) = (addr.address(), addr.address() + len);
+            if failed.iter().any(|&(f, size)| f < end && f + size > addr) {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_read_size: u32::MAX,
+            read_alignment: 1,
+            ..self.mem.metadata()
+        }
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    ConstrainedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    /// Memory asserting that all reads are aligned and small enough.
+    struct Checked(DummyMemory);
+
+    impl PhysicalMemory for Checked {
+        fn phys_read_raw_iter(
+            &mut self,
+            MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+        ) -> Result<()> {
+            let inp = inp.inspect(|CTup3(addr, _, buf)| {
+                assert_eq!(addr.address().to_umem() % 8, 0);
+                assert_eq!(buf.len() % 8, 0);
+                assert!(buf.len() <= 64);
+            });
+            let mem = &mut self.0;
+            MemOps::with_raw(inp, out, out_fail, |data| mem.phys_read_raw_iter(data))
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.0.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            PhysicalMemoryMetadata {
+                max_read_size: 64,
+                read_alignment: 8,
+                ..self.0.metadata()
+            }
+        }
+    }
+
+    #[test]
+    fn split_and_align() {
+        let data = (0..0x100).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut mem = ConstrainedPhysicalMemory::new(Checked(DummyMemory::new(size::mb(1))));
+        assert_eq!(mem.max_read_size(), 64);
+        assert_eq!(mem.alignment(), 8);
+
+        mem.phys_write(0x1000.into(), data.as_slice()).unwrap();
+
+        let mut view = mem.phys_view();
+        assert_eq!(
+            view.read::<[u8; 0x100]>(0x1000.into()).unwrap()[..],
+            data[..]
+        );
+        assert_eq!(
+            view.read::<[u8; 0x80]>(0x1003.into()).unwrap()[..],
+            data[3..0x83]
+        );
+        assert_eq!(view.read::<u16>(0x1007.into()).unwrap(), 0x0807);
+    }
+}
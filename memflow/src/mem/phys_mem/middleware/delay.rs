@@ -1,10 +1,12 @@
 use ::std::{thread, time::Duration};
 
+use crate::cglue::CTup2;
 use crate::error::Result;
 use crate::mem::{
-    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
 };
+use crate::types::{umem, PhysicalAddress};
 
 /// The delay middleware introduces delay and jitter into physical reads which allows
 /// users to simulate different connectors and setups.
@@ -104,6 +106,27 @@ impl<T: PhysicalMemory> PhysicalMemory for DelayedPhysicalMemory<T> {
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        thread::sleep(self.delay);
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
 }
 
 /// The builder interface for constructing a `DelayedPhysicalMemory` object.
@@ -0,0 +1,333 @@
+//! Randomization of physical memory access patterns.
+//!
+//! Memory introspection tools access memory in very regular patterns: page tables are walked in
+//! the same order every time, batches are sorted by address and issued back to back. Detection
+//! heuristics for DMA based introspection rely on such patterns. The [`JitteredPhysicalMemory`]
+//! middleware makes the pattern irregular, so the robustness of these heuristics can be
+//! evaluated:
+//!
+//! - The elements of a read batch are issued in random order.
+//! - A random delay is inserted in front of every batch.
+//! - Additional decoy reads of random pages are mixed into read batches. Their contents are
+//!   discarded.
+//!
+//! Decoy reads are only issued to explicitly configured ranges or to the memory map set through
+//! [`set_mem_map`](PhysicalMemory::set_mem_map), never to arbitrary addresses, since reading
+//! device memory can have side effects. Writes are never reordered, only delayed.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{JitteredPhysicalMemory, MemoryView, PhysicalMemory};
+//! use std::time::Duration;
+//!
+//! fn jittered<T: PhysicalMemory>(mem: T) {
+//!     let mut mem = JitteredPhysicalMemory::builder(mem)
+//!         .shuffle(true)
+//!         .jitter(Duration::from_micros(10), Duration::from_micros(100))
+//!         .decoys(0.5, 0x1000)
+//!         .decoy_range(0x10000.into(), 0x10000)
+//!         .build()
+//!         .unwrap();
+//!
+//!     assert!(mem.phys_view().read::<u64>(0x1000.into()).is_ok());
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # jittered(DummyMemory::new(size::mb(1)));
+//! ```
+
+use ::std::{thread, time::Duration};
+
+use std::prelude::v1::*;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps, ReadData,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// The jitter middleware randomizes the order, timing and footprint of physical reads.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct JitteredPhysicalMemory<T> {
+    mem: T,
+    rng: XorShiftRng,
+    shuffle: bool,
+    jitter: (Duration, Duration),
+    decoy_ratio: f32,
+    decoy_size: usize,
+    decoy_ranges: Vec<(Address, umem)>,
+    /// True if the decoy ranges have been configured explicitly and must not be replaced by the
+    /// memory map
+    explicit_ranges: bool,
+}
+
+impl<T: Clone> Clone for JitteredPhysicalMemory<T> {
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            // clones must not produce the same pattern
+            rng: XorShiftRng::from_rng(rand::thread_rng()).unwrap(),
+            shuffle: self.shuffle,
+            jitter: self.jitter,
+            decoy_ratio: self.decoy_ratio,
+            decoy_size: self.decoy_size,
+            decoy_ranges: self.decoy_ranges.clone(),
+            explicit_ranges: self.explicit_ranges,
+        }
+    }
+}
+
+impl<T: PhysicalMemory> JitteredPhysicalMemory<T> {
+    /// Returns a new builder for the jitter middleware with default settings.
+    pub fn builder(mem: T) -> JitteredPhysicalMemoryBuilder<T> {
+        JitteredPhysicalMemoryBuilder::new(mem)
+    }
+
+    /// Returns the ranges decoy reads are issued to.
+    pub fn decoy_ranges(&self) -> &[(Address, umem)] {
+        &self.decoy_ranges
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn sleep(&mut self) {
+        let (min, max) = self.jitter;
+        if max > Duration::default() {
+            thread::sleep(self.rng.gen_range(min..=max));
+        }
+    }
+
+    /// Returns the address of a random decoy read, aligned to the decoy size.
+    fn decoy_address(&mut self) -> Option<Address> {
+        let size = self.decoy_size as umem;
+        let ranges = self
+            .decoy_ranges
+            .iter()
+            .filter(|(_, len)| *len >= size)
+            .collect::<Vec<_>>();
+
+        let (base, len) = **ranges.choose(&mut self.rng)?;
+        let slot = self.rng.gen_range(0..len / size);
+        Some(base + slot * size)
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for JitteredPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        self.sleep();
+
+        let mut inp = inp.collect::<Vec<_>>();
+
+        let mut decoys = vec![];
+        for _ in 0..inp.len() {
+            if self.rng.gen::<f32>() < self.decoy_ratio {
+                decoys.extend(self.decoy_address());
+            }
+        }
+
+        let mut decoy_buf = vec![0u8; decoys.len() * self.decoy_size];
+        for (addr, buf) in decoys
+            .into_iter()
+            .zip(decoy_buf.chunks_mut(self.decoy_size))
+        {
+            // decoys are tagged with the invalid address, so their results can be dropped
+            inp.push(CTup3(addr.into(), Address::invalid(), buf.into()));
+        }
+
+        if self.shuffle {
+            inp.shuffle(&mut self.rng);
+        }
+
+        let pass = &mut |data: ReadData| {
+            data.0 == Address::invalid() || opt_call(out.as_deref_mut(), data)
+        };
+        let pass_fail = &mut |data: ReadData| {
+            data.0 == Address::invalid() || opt_call(out_fail.as_deref_mut(), data)
+        };
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(
+            inp.into_iter(),
+            Some(&mut pass.into()),
+            Some(&mut pass_fail.into()),
+            |data| mem.phys_read_raw_iter(data),
+        )
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.sleep();
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        if !self.explicit_ranges {
+            self.decoy_ranges = mem_map.iter().map(|m| (m.base, m.size)).collect();
+        }
+        self.mem.set_mem_map(mem_map)
+    }
+
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.sleep();
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+/// The builder interface for constructing a `JitteredPhysicalMemory` object.
+pub struct JitteredPhysicalMemoryBuilder<T> {
+    mem: T,
+    seed: Option<u64>,
+    shuffle: bool,
+    jitter: (Duration, Duration),
+    decoy_ratio: f32,
+    decoy_size: usize,
+    decoy_ranges: Vec<(Address, umem)>,
+}
+
+impl<T: PhysicalMemory> JitteredPhysicalMemoryBuilder<T> {
+    /// Creates a new `JitteredPhysicalMemory` builder.
+    /// The memory object is mandatory as the JitteredPhysicalMemory struct wraps around it.
+    ///
+    /// Without further adjustments this function creates a middleware which shuffles read
+    /// batches, but neither delays them nor issues decoy reads.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            seed: None,
+            shuffle: true,
+            jitter: (Duration::default(), Duration::default()),
+            decoy_ratio: 0.0,
+            decoy_size: 0x1000,
+            decoy_ranges: vec![],
+        }
+    }
+
+    /// Seeds the random number generator, which makes the access pattern reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Enables or disables the shuffling of read batches.
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Delays every access by a random duration between `min` and `max`.
+    pub fn jitter(mut self, min: Duration, max: Duration) -> Self {
+        self.jitter = (min.min(max), max.max(min));
+        self
+    }
+
+    /// Adds a decoy read of `size` bytes with probability `ratio` for every element of a read
+    /// batch.
+    pub fn decoys(mut self, ratio: f32, size: usize) -> Self {
+        self.decoy_ratio = ratio.clamp(0.0, 1.0);
+        self.decoy_size = size.max(1);
+        self
+    }
+
+    /// Adds a range decoy reads may be issued to.
+    ///
+    /// Once a range has been added, the memory map is no longer used to pick decoy addresses.
+    pub fn decoy_range(mut self, base: Address, len: umem) -> Self {
+        self.decoy_ranges.push((base, len));
+        self
+    }
+
+    /// Builds the `JitteredPhysicalMemory` object or returns an error.
+    pub fn build(self) -> Result<JitteredPhysicalMemory<T>> {
+        let rng = match self.seed {
+            Some(seed) => XorShiftRng::seed_from_u64(seed),
+            None => XorShiftRng::from_rng(rand::thread_rng()).unwrap(),
+        };
+
+        Ok(JitteredPhysicalMemory {
+            mem: self.mem,
+            rng,
+            shuffle: self.shuffle,
+            jitter: self.jitter,
+            decoy_ratio: self.decoy_ratio,
+            decoy_size: self.decoy_size,
+            explicit_ranges: !self.decoy_ranges.is_empty(),
+            decoy_ranges: self.decoy_ranges,
+        })
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    JitteredPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn shuffled_reads() {
+        let data = (0..0x100u32).collect::<Vec<_>>();
+
+        let mut mem = JitteredPhysicalMemory::builder(DummyMemory::new(size::mb(1)))
+            .seed(1)
+            .decoys(1.0, 0x100)
+            .decoy_range(0x80000.into(), 0x1000)
+            .build()
+            .unwrap();
+        mem.phys_write(0x1000.into(), data.as_slice()).unwrap();
+
+        let mut out = vec![0u32; data.len()];
+        let mut view = mem.phys_view();
+        let mut batcher = view.batcher();
+        for (i, v) in out.iter_mut().enumerate() {
+            batcher.read_into(Address::from(0x1000 + i as umem * 4), v);
+        }
+        batcher.commit_rw().unwrap();
+        std::mem::drop(batcher);
+
+        assert_eq!(out, data);
+    }
+}
@@ -1,10 +1,12 @@
 use ::log::info;
 use ::std::{collections::VecDeque, time::Instant};
 
+use crate::cglue::CTup2;
 use crate::mem::{
-    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
 };
+use crate::types::{umem, PhysicalAddress};
 use crate::{error::Result, mem::MemOps};
 
 /// The metrics middleware collects metrics data (latency and number of bytes) for all read and write operations.
@@ -154,6 +156,26 @@ impl<T: PhysicalMemory> PhysicalMemory for PhysicalMemoryMetrics<T> {
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
 }
 
 #[cfg(feature = "plugins")]
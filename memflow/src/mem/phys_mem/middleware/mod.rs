@@ -1,17 +1,68 @@
+pub mod blocked;
 pub mod cache;
+pub mod constrained;
+pub mod overlay;
+pub mod reconnect;
+pub mod secure;
+
+#[cfg(feature = "std")]
+pub mod adaptive;
 
 #[cfg(feature = "std")]
 pub mod delay;
+#[cfg(feature = "access_jitter")]
+pub mod jitter;
 #[cfg(feature = "std")]
 pub mod metrics;
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod throttle;
+#[cfg(feature = "std")]
+pub mod trace;
+
+#[doc(hidden)]
+pub use blocked::*;
 
 #[doc(hidden)]
 pub use cache::*;
 
+#[doc(hidden)]
+pub use constrained::*;
+
+#[doc(hidden)]
+pub use overlay::*;
+
+#[doc(hidden)]
+pub use reconnect::*;
+
+#[doc(hidden)]
+pub use secure::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use adaptive::*;
+
 #[cfg(feature = "std")]
 #[doc(hidden)]
 pub use delay::*;
 
+#[cfg(feature = "access_jitter")]
+#[doc(hidden)]
+pub use jitter::*;
+
 #[cfg(feature = "std")]
 #[doc(hidden)]
 pub use metrics::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use shared::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use throttle::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use trace::*;
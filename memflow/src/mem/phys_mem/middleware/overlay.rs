@@ -0,0 +1,302 @@
+//! Copy-on-write overlay over physical memory.
+//!
+//! The [`OverlayMemory`] middleware redirects all writes into an in-memory overlay, while the
+//! underlying memory object stays untouched. Reads are served from the overlay for all pages that
+//! have been written to, and from the underlying memory otherwise.
+//!
+//! This allows patching read-only sources like memory snapshots, or running "what-if" experiments
+//! against a live target without ever modifying it. The overlay can be inspected, discarded, or
+//! flushed into the underlying memory at any point.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{MemoryView, OverlayMemory, PhysicalMemory};
+//!
+//! fn experiment<T: PhysicalMemory>(mem: T) {
+//!     let mut overlay = OverlayMemory::new(mem);
+//!
+//!     overlay.phys_write(0x1000.into(), &0xdeadbeefu32).unwrap();
+//!     assert_eq!(overlay.phys_view().read::<u32>(0x1000.into()).unwrap(), 0xdeadbeef);
+//!     assert_eq!(overlay.pages().count(), 1);
+//!
+//!     // throw away all modifications
+//!     overlay.discard();
+//!     assert_ne!(overlay.phys_view().read::<u32>(0x1000.into()).unwrap(), 0xdeadbeef);
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # experiment(DummyMemory::new(size::mb(4)));
+//! ```
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::iter::PageChunks;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{size, umem, Address, PhysicalAddress};
+
+/// Physical memory middleware keeping all writes in a copy-on-write overlay.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct OverlayMemory<T> {
+    mem: T,
+    pages: BTreeMap<Address, Box<[u8]>>,
+    page_size: usize,
+}
+
+impl<T> Clone for OverlayMemory<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            pages: self.pages.clone(),
+            page_size: self.page_size,
+        }
+    }
+}
+
+impl<T: PhysicalMemory> OverlayMemory<T> {
+    /// Constructs a new overlay with a granularity of 4 kb pages.
+    pub fn new(mem: T) -> Self {
+        Self::with_page_size(mem, size::kb(4))
+    }
+
+    /// Constructs a new overlay with the given page granularity.
+    ///
+    /// # Panics
+    ///
+    /// If `page_size` is not a power of two.
+    pub fn with_page_size(mem: T, page_size: usize) -> Self {
+        assert!(
+            page_size.is_power_of_two(),
+            "page size must be a power of two"
+        );
+
+        Self {
+            mem,
+            pages: BTreeMap::new(),
+            page_size,
+        }
+    }
+
+    /// Returns the page granularity of the overlay.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns true if no writes have been made to the overlay.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Returns all modified pages in ascending order, along with their current contents.
+    pub fn pages(&self) -> impl Iterator<Item = (Address, &[u8])> + '_ {
+        self.pages.iter().map(|(&addr, page)| (addr, &page[..]))
+    }
+
+    /// Discards all modifications.
+    pub fn discard(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Discards the modifications of all pages overlapping the given range.
+    pub fn discard_range(&mut self, addr: Address, len: umem) {
+        if len == 0 {
+            return;
+        }
+
+        let start = addr.as_page_aligned(self.page_size);
+        let end = addr + (len - 1);
+
+        let discarded = self
+            .pages
+            .range(start..=end)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+
+        for addr in discarded {
+            self.pages.remove(&addr);
+        }
+    }
+
+    /// Writes all modified pages into the underlying memory and clears the overlay.
+    ///
+    /// If writing fails the overlay is left intact.
+    pub fn flush(&mut self) -> Result<()> {
+        for (&addr, page) in self.pages.iter() {
+            self.mem.phys_write(addr.into(), &page[..])?;
+        }
+
+        self.pages.clear();
+
+        Ok(())
+    }
+
+    /// Consumes self and returns the containing memory object, dropping all modifications.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Returns the overlay page at `page_addr`, copying it from the underlying memory if needed.
+    fn page_mut(&mut self, page_addr: Address) -> &mut [u8] {
+        let mem = &mut self.mem;
+        let page_size = self.page_size;
+
+        self.pages.entry(page_addr).or_insert_with(|| {
+            // unreadable parts of the page are zero filled by `phys_read_into`
+            let mut page = vec![0u8; page_size].into_boxed_slice();
+            let _ = mem.phys_read_into(page_addr.into(), &mut page[..]);
+            page
+        })
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for OverlayMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        if self.pages.is_empty() {
+            return self.mem.phys_read_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let page_size = self.page_size;
+        let mut base = vec![];
+
+        for CTup3(addr, meta_addr, buf) in inp {
+            for (paddr, (meta_addr, mut chunk)) in
+                (meta_addr, buf).page_chunks(addr.address(), page_size)
+            {
+                let page_addr = paddr.as_page_aligned(page_size);
+
+                match self.pages.get(&page_addr) {
+                    Some(page) => {
+                        let start = (paddr - page_addr) as usize;
+                        chunk.copy_from_slice(&page[start..(start + chunk.len())]);
+                        opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+                    }
+                    None => base.push(CTup3(
+                        PhysicalAddress::with_page(
+                            paddr,
+                            addr.page_type(),
+                            addr.page_size() as umem,
+                        ),
+                        meta_addr,
+                        chunk,
+                    )),
+                }
+            }
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(base.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, mut out, .. }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let page_size = self.page_size;
+
+        for CTup3(addr, meta_addr, data) in inp {
+            for (paddr, (meta_addr, chunk)) in
+                (meta_addr, data).page_chunks(addr.address(), page_size)
+            {
+                let page_addr = paddr.as_page_aligned(page_size);
+                let start = (paddr - page_addr) as usize;
+
+                self.page_mut(page_addr)[start..(start + chunk.len())].copy_from_slice(&chunk);
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: false,
+            ..self.mem.metadata()
+        }
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    OverlayMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+
+    #[test]
+    fn copy_on_write() {
+        // clones of the dummy memory share the same underlying buffer
+        let mut base = DummyMemory::new(size::mb(1));
+        base.phys_write(0x1ffc.into(), &[1u8; 8]).unwrap();
+
+        let mut overlay = OverlayMemory::new(base.clone());
+
+        // the write crosses a page boundary
+        overlay.phys_write(0x1ffe.into(), &[2u8; 4]).unwrap();
+
+        let mut buf = [0u8; 8];
+        overlay.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+        assert_eq!(buf, [1, 1, 2, 2, 2, 2, 1, 1]);
+        assert_eq!(overlay.pages().count(), 2);
+
+        base.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 8]);
+
+        overlay.discard_range(0x2000.into(), 1);
+        let value: [u8; 8] = overlay.phys_view().read(0x1ffc.into()).unwrap();
+        assert_eq!(value, [1, 1, 2, 2, 1, 1, 1, 1]);
+
+        overlay.flush().unwrap();
+        assert!(overlay.is_empty());
+
+        base.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+        assert_eq!(buf, [1, 1, 2, 2, 1, 1, 1, 1]);
+    }
+}
@@ -0,0 +1,280 @@
+//! Transparent reconnection of connectors that can drop.
+//!
+//! Connectors talking to the target over a network or USB can lose their connection, for
+//! example when the device is reset. Without this middleware all accesses keep failing from then
+//! on and the whole stack, including the OS layer, has to be rebuilt.
+//!
+//! The [`ReconnectingPhysicalMemory`] middleware wraps such a connector together with a function
+//! creating a new connection. Whenever an access fails it runs the
+//! [`health_check`](PhysicalMemory::health_check) of the connector and replaces the connector
+//! by a new one if the check fails. The memory map that was previously set through
+//! [`set_mem_map`](PhysicalMemory::set_mem_map) is restored on the new connector.
+//!
+//! The access that detected the dropped connection still returns its error, all following
+//! accesses go to the new connection. In case reconnecting fails it is retried on every access
+//! until it succeeds.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{PhysicalMemory, ReconnectingPhysicalMemory};
+//! use memflow::error::Result;
+//!
+//! fn reconnecting<T: PhysicalMemory>(connect: impl FnMut() -> Result<T> + Send) -> Result<()> {
+//!     let mut mem = ReconnectingPhysicalMemory::connect(connect)?;
+//!
+//!     mem.health_check()?;
+//!     assert_eq!(mem.reconnects(), 0);
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # reconnecting(|| Ok(DummyMemory::new(size::mb(1)))).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, Result};
+use crate::mem::{
+    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, PhysicalAddress};
+
+use log::warn;
+
+/// Physical memory middleware replacing the connector once its connection dropped.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+#[derive(Clone)]
+pub struct ReconnectingPhysicalMemory<T, F> {
+    mem: T,
+    connect: F,
+    mem_map: Option<Vec<PhysicalMemoryMapping>>,
+    dropped: bool,
+    reconnects: usize,
+}
+
+impl<T: PhysicalMemory, F: FnMut() -> Result<T> + Send> ReconnectingPhysicalMemory<T, F> {
+    /// Constructs a new middleware from an existing connection.
+    ///
+    /// `connect` is invoked every time a new connection is required.
+    pub fn new(mem: T, connect: F) -> Self {
+        Self {
+            mem,
+            connect,
+            mem_map: None,
+            dropped: false,
+            reconnects: 0,
+        }
+    }
+
+    /// Constructs a new middleware and establishes the initial connection through `connect`.
+    pub fn connect(mut connect: F) -> Result<Self> {
+        let mem = connect()?;
+        Ok(Self::new(mem, connect))
+    }
+
+    /// Replaces the current connection by a new one.
+    ///
+    /// The memory map set on the previous connection is restored on the new one.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let mut mem = (self.connect)().map_err(|err| {
+            self.dropped = true;
+            err
+        })?;
+
+        if let Some(mem_map) = &self.mem_map {
+            mem.set_mem_map(mem_map);
+        }
+
+        self.mem = mem;
+        self.dropped = false;
+        self.reconnects += 1;
+        Ok(())
+    }
+
+    /// Returns the number of times the connection was replaced.
+    pub fn reconnects(&self) -> usize {
+        self.reconnects
+    }
+
+    /// Returns true if the connection dropped and could not be established again yet.
+    pub fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Reconnects if a previous attempt failed.
+    fn ensure_connected(&mut self) -> Result<()> {
+        if self.dropped {
+            self.reconnect()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the connection after `err` occurred and reconnects if it dropped.
+    ///
+    /// Always returns `err`.
+    fn handle_error(&mut self, err: Error) -> Error {
+        if let Err(health) = self.mem.health_check() {
+            warn!("connection dropped ({}), reconnecting", health);
+            self.reconnect().ok();
+        }
+        err
+    }
+}
+
+impl<T: PhysicalMemory, F: FnMut() -> Result<T> + Send> PhysicalMemory
+    for ReconnectingPhysicalMemory<T, F>
+{
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.ensure_connected()?;
+        self.mem
+            .phys_read_raw_iter(data)
+            .map_err(|err| self.handle_error(err))
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.ensure_connected()?;
+        self.mem
+            .phys_write_raw_iter(data)
+            .map_err(|err| self.handle_error(err))
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem_map = Some(mem_map.to_vec());
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        if !self.dropped {
+            self.mem.phys_prefetch(ranges)
+        }
+    }
+
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.ensure_connected()?;
+        self.mem
+            .phys_cas_raw(addr, expected, new)
+            .map_err(|err| self.handle_error(err))
+    }
+
+    fn health_check(&mut self) -> Result<()> {
+        self.ensure_connected()?;
+        match self.mem.health_check() {
+            Ok(()) => Ok(()),
+            Err(_) => self.reconnect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::error::{ErrorKind, ErrorOrigin};
+    use crate::types::{size, Address};
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Connector failing all accesses once the shared flag is set.
+    struct Flaky {
+        mem: DummyMemory,
+        dropped: Arc<AtomicBool>,
+        mem_map: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn check(&self) -> Result<()> {
+            if self.dropped.load(Ordering::SeqCst) {
+                Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl PhysicalMemory for Flaky {
+        fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+            self.check()?;
+            self.mem.phys_read_raw_iter(data)
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.check()?;
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.mem.metadata()
+        }
+
+        fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+            self.mem_map.store(mem_map.len(), Ordering::SeqCst);
+        }
+
+        fn health_check(&mut self) -> Result<()> {
+            self.check()
+        }
+    }
+
+    #[test]
+    fn reconnect_after_drop() {
+        let mut dummy = DummyMemory::new(size::mb(1));
+        dummy.phys_write(0x1000.into(), &0x1234u32).unwrap();
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let mem_map = Arc::new(AtomicUsize::new(0));
+
+        let connect = {
+            let dropped = dropped.clone();
+            let mem_map = mem_map.clone();
+            move || {
+                dropped.store(false, Ordering::SeqCst);
+                Ok(Flaky {
+                    mem: dummy.clone(),
+                    dropped: dropped.clone(),
+                    mem_map: mem_map.clone(),
+                })
+            }
+        };
+
+        let mut mem = ReconnectingPhysicalMemory::connect(connect).unwrap();
+        mem.set_mem_map(&[PhysicalMemoryMapping {
+            base: Address::null(),
+            size: size::mb(1) as umem,
+            real_base: Address::null(),
+        }]);
+
+        dropped.store(true, Ordering::SeqCst);
+        mem_map.store(0, Ordering::SeqCst);
+
+        // the access detecting the drop fails, the next one uses the new connection
+        let mut value = 0u32;
+        assert!(mem.phys_read_into(0x1000.into(), &mut value).is_err());
+        assert_eq!(mem.reconnects(), 1);
+        mem.phys_read_into(0x1000.into(), &mut value).unwrap();
+        assert_eq!(value, 0x1234);
+        assert_eq!(mem_map.load(Ordering::SeqCst), 1);
+    }
+}
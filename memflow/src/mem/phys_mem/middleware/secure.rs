@@ -0,0 +1,284 @@
+//! Filter for physical memory owned by a secure kernel.
+//!
+//! With virtualization based security (VBS) enabled, Windows runs a secure kernel next to the
+//! normal kernel. The memory of the secure kernel (VTL1) is protected by the hypervisor, so reading
+//! it through a connector yields zeroes or garbage instead of failing. Translations that walk
+//! through such memory produce plausible looking, but wrong results.
+//!
+//! memflow does not detect VBS or the ranges owned by the secure kernel. Once they have been
+//! determined, e.g. by an OS plugin or from user configuration, they are passed to the
+//! [`SecureMemoryFilter`] middleware, which makes all accesses to them fail explicitly, so users
+//! get a clear failure instead of bogus data. OS layers can use [`SecureMemoryFilter::page_type`] to
+//! mark such pages with [`PageType::SECURE`] in their page maps.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{MemoryView, PhysicalMemory, SecureMemoryFilter};
+//!
+//! fn filter<T: PhysicalMemory>(mem: T) {
+//!     let mut mem = SecureMemoryFilter::new(mem);
+//!     mem.add_range(0x10000.into(), 0x4000);
+//!
+//!     assert!(mem.phys_view().read::<u64>(0x1000.into()).is_ok());
+//!     assert!(mem.phys_view().read::<u64>(0x10ffc.into()).is_err());
+//!     assert!(mem.is_secure(0x13fff.into()));
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # filter(DummyMemory::new(size::mb(1)));
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::iter::SplitAtIndex;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address, PageType, PhysicalAddress};
+
+/// Physical memory middleware failing all accesses to memory owned by a secure kernel.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+#[derive(Clone)]
+pub struct SecureMemoryFilter<T> {
+    mem: T,
+    /// Sorted, non-overlapping list of secure ranges as `(start, end)`
+    ranges: Vec<(Address, Address)>,
+}
+
+impl<T: PhysicalMemory> SecureMemoryFilter<T> {
+    /// Constructs a new filter without any secure ranges.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            ranges: vec![],
+        }
+    }
+
+    /// Marks `size` bytes starting at `base` as owned by the secure kernel.
+    ///
+    /// Overlapping and adjacent ranges are merged.
+    pub fn add_range(&mut self, base: Address, size: umem) {
+        if size == 0 {
+            return;
+        }
+
+        let mut start = base;
+        let mut end = base + size;
+
+        // merge all ranges overlapping or touching the new one
+        self.ranges.retain(|&(s, e)| {
+            if s <= end && e >= start {
+                start = start.min(s);
+                end = end.max(e);
+                false
+            } else {
+                true
+            }
+        });
+
+        let idx = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(idx, (start, end));
+    }
+
+    /// Removes all secure ranges.
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Returns all secure ranges as `(start, end)` pairs in ascending order.
+    pub fn ranges(&self) -> &[(Address, Address)] {
+        &self.ranges
+    }
+
+    /// Returns true if `addr` is owned by the secure kernel.
+    pub fn is_secure(&self, addr: Address) -> bool {
+        self.ranges
+            .get(self.ranges.partition_point(|&(_, e)| e <= addr))
+            .map_or(false, |&(s, _)| s <= addr)
+    }
+
+    /// Returns the page type of `addr` with [`PageType::SECURE`] set accordingly.
+    pub fn page_type(&self, addr: PhysicalAddress) -> PageType {
+        addr.page_type().secure(self.is_secure(addr.address()))
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Splits the access at `addr` along the secure ranges.
+    ///
+    /// Accessible parts are pushed into `pass`, secure parts into `failed`.
+    fn split<B: SplitAtIndex>(
+        &self,
+        CTup3(addr, meta_addr, buf): CTup3<PhysicalAddress, Address, B>,
+        pass: &mut Vec<CTup3<PhysicalAddress, Address, B>>,
+        failed: &mut Vec<CTup2<Address, B>>,
+    ) {
+        let mut next = Some((addr.address(), (meta_addr, buf)));
+
+        while let Some((cur, data)) = next.take() {
+            let wrap = |a: Address| {
+                PhysicalAddress::with_page(a, addr.page_type(), addr.page_size() as umem)
+            };
+
+            match self
+                .ranges
+                .get(self.ranges.partition_point(|&(_, e)| e <= cur))
+            {
+                Some(&(s, e)) if s <= cur => {
+                    let (head, tail) = data.split_at((e - cur) as umem);
+                    if let Some((meta_addr, buf)) = head {
+                        failed.push(CTup2(meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (e, tail));
+                }
+                Some(&(s, _)) => {
+                    let (head, tail) = data.split_at((s - cur) as umem);
+                    if let Some((meta_addr, buf)) = head {
+                        pass.push(CTup3(wrap(cur), meta_addr, buf));
+                    }
+                    next = tail.map(|tail| (s, tail));
+                }
+                None => pass.push(CTup3(wrap(cur), data.0, data.1)),
+            }
+        }
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for SecureMemoryFilter<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        if self.ranges.is_empty() {
+            return self.mem.phys_read_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let mut pass = vec![];
+        let mut failed = vec![];
+        for data in inp {
+            self.split(data, &mut pass, &mut failed);
+        }
+
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(pass.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        if self.ranges.is_empty() {
+            return self.mem.phys_write_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        let mut pass = vec![];
+        let mut failed = vec![];
+        for data in inp {
+            self.split(data, &mut pass, &mut failed);
+        }
+
+        for data in failed {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(pass.into_iter(), out, out_fail, |data| {
+            mem.phys_write_raw_iter(data)
+        })
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        let ranges = ranges
+            .iter()
+            .filter(|CTup2(addr, _)| !self.is_secure(addr.address()))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.mem.phys_prefetch(&ranges)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    SecureMemoryFilter<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn merged_ranges() {
+        let mut mem = SecureMemoryFilter::new(DummyMemory::new(size::mb(1)));
+        mem.add_range(0x3000.into(), 0x1000);
+        mem.add_range(0x1000.into(), 0x1000);
+        mem.add_range(0x2000.into(), 0x800);
+
+        assert_eq!(
+            mem.ranges(),
+            &[
+                (Address::from(0x1000u64), Address::from(0x2800u64)),
+                (Address::from(0x3000u64), Address::from(0x4000u64)),
+            ]
+        );
+        assert!(!mem.is_secure(0x2800.into()));
+        assert!(mem
+            .page_type(Address::from(0x3000u64).into())
+            .contains(PageType::SECURE));
+
+        mem.phys_write(0x2ffc.into(), &[1u8; 4]).unwrap();
+
+        // only the accessible part of a read spanning multiple ranges succeeds
+        let mut buf = [0u8; 0x20];
+        let mut view = mem.phys_view();
+        assert!(view.read_raw_into(0x27f0.into(), &mut buf).is_err());
+        assert_eq!(view.read::<[u8; 4]>(0x2ffc.into()).unwrap(), [1u8; 4]);
+    }
+}
@@ -0,0 +1,527 @@
+//! Physical memory shared between threads.
+//!
+//! The [`SharedPhysicalMemory`] middleware allows multiple threads to work on the same connector
+//! without wrapping it in a single global lock. Every clone of the middleware is a handle to the
+//! same underlying memory object and the same page cache, so each thread can own its own handle
+//! and e.g. build its own [`VirtualDma`](crate::mem::VirtualDma) or process on top of it.
+//!
+//! The page cache is split into shards by page address. Each shard is guarded by its own
+//! read-write lock, so threads reading cached pages never block each other and only contend
+//! when they insert pages into the same shard. Cache misses, writes and all other operations
+//! are forwarded to the underlying memory object, which is guarded by a mutex.
+//!
+//! # Consistency
+//!
+//! - Every page served from the cache is a snapshot of the whole page at the time it was read
+//!   from the underlying memory. Reads spanning multiple pages may combine snapshots taken at
+//!   different points in time.
+//! - Writes are written through to the underlying memory. All cached pages touched by a write are
+//!   invalidated before the write returns, so reads issued through any handle after the write
+//!   returned observe the written data.
+//! - Changes of the target that are not made through one of the handles become visible after at
+//!   most the configured validity of a cached page.
+//! - Only pages matching the page type mask are cached, all other reads always go to the
+//!   underlying memory.
+//!
+//! # Examples
+//! ```
+//! use std::thread;
+//!
+//! use memflow::mem::{MemoryView, PhysicalMemory, SharedPhysicalMemory};
+//! use memflow::types::PageType;
+//!
+//! fn scan<T: PhysicalMemory + Send + 'static>(mem: T) {
+//!     let shared = SharedPhysicalMemory::builder(mem)
+//!         .page_type_mask(PageType::all())
+//!         .build();
+//!
+//!     let threads = (0..4u64)
+//!         .map(|i| {
+//!             let mut mem = shared.clone();
+//!             thread::spawn(move || {
+//!                 let mut buf = vec![0u8; 0x10000];
+//!                 mem.phys_read_into((i * 0x10000).into(), &mut buf[..]).unwrap();
+//!             })
+//!         })
+//!         .collect::<Vec<_>>();
+//!
+//!     for t in threads {
+//!         t.join().unwrap();
+//!     }
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # scan(DummyMemory::new(size::mb(1)));
+//! ```
+
+use std::collections::HashMap;
+use std::prelude::v1::*;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::iter::PageChunks;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps, ReadData,
+};
+use crate::types::cache::stats::{self, CacheStat};
+use crate::types::{size, umem, Address, PageType, PhysicalAddress};
+
+struct CachedPage {
+    data: Box<[u8]>,
+    fetched: Instant,
+}
+
+struct SharedState<T> {
+    mem: Mutex<T>,
+    shards: Box<[RwLock<HashMap<Address, CachedPage>>]>,
+    shard_capacity: usize,
+    page_size: usize,
+    page_type_mask: PageType,
+    validity: Duration,
+}
+
+impl<T> SharedState<T> {
+    fn mem(&self) -> MutexGuard<T> {
+        // a panicking thread can not leave the memory object in an invalid state
+        self.mem.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn shard(&self, page_addr: Address) -> &RwLock<HashMap<Address, CachedPage>> {
+        let page = page_addr.to_umem() / self.page_size as umem;
+        &self.shards[(page % self.shards.len() as umem) as usize]
+    }
+
+    fn read_shard(&self, page_addr: Address) -> RwLockReadGuard<HashMap<Address, CachedPage>> {
+        self.shard(page_addr)
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write_shard(&self, page_addr: Address) -> RwLockWriteGuard<HashMap<Address, CachedPage>> {
+        self.shard(page_addr)
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Copies the cached contents of the page into `chunk`, returns false on a cache miss.
+    fn copy_cached(&self, page_addr: Address, offset: usize, chunk: &mut [u8]) -> bool {
+        match self.read_shard(page_addr).get(&page_addr) {
+            Some(page) if page.fetched.elapsed() < self.validity => {
+                chunk.copy_from_slice(&page.data[offset..(offset + chunk.len())]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn insert(&self, page_addr: Address, data: Box<[u8]>) {
+        let mut shard = self.write_shard(page_addr);
+
+        if shard.len() >= self.shard_capacity && !shard.contains_key(&page_addr) {
+            let validity = self.validity;
+            shard.retain(|_, page| page.fetched.elapsed() < validity);
+
+            if shard.len() >= self.shard_capacity {
+                let oldest = shard
+                    .iter()
+                    .min_by_key(|(_, page)| page.fetched)
+                    .map(|(&addr, _)| addr);
+                if let Some(oldest) = oldest {
+                    shard.remove(&oldest);
+                }
+            }
+        }
+
+        shard.insert(
+            page_addr,
+            CachedPage {
+                data,
+                fetched: Instant::now(),
+            },
+        );
+    }
+
+    fn invalidate_range(&self, addr: Address, len: umem) {
+        if len == 0 {
+            return;
+        }
+
+        let mut page_addr = addr.as_page_aligned(self.page_size);
+        let end = addr + (len - 1);
+        while page_addr <= end {
+            self.write_shard(page_addr).remove(&page_addr);
+            page_addr += self.page_size;
+        }
+    }
+
+    fn invalidate_all(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap_or_else(|err| err.into_inner()).clear();
+        }
+    }
+}
+
+/// Physical memory middleware that can be shared between threads.
+///
+/// Cloning the middleware creates a new handle to the same memory object and page cache.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct SharedPhysicalMemory<T> {
+    inner: Arc<SharedState<T>>,
+}
+
+impl<T> Clone for SharedPhysicalMemory<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> SharedPhysicalMemory<T> {
+    /// Constructs a new middleware with default settings.
+    pub fn new(mem: T) -> Self {
+        Self::builder(mem).build()
+    }
+
+    /// Returns a new builder for this middleware with default settings.
+    pub fn builder(mem: T) -> SharedPhysicalMemoryBuilder<T> {
+        SharedPhysicalMemoryBuilder::new(mem)
+    }
+
+    /// Returns the number of handles sharing the memory object.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// Returns the number of pages currently held in the cache.
+    pub fn cached_pages(&self) -> usize {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(|err| err.into_inner()).len())
+            .sum()
+    }
+
+    /// Drops all cached pages for all handles.
+    pub fn invalidate(&self) {
+        self.inner.invalidate_all()
+    }
+
+    /// Runs `func` with exclusive access to the underlying memory object.
+    ///
+    /// All other handles block on cache misses and writes while `func` runs. The cache is not
+    /// invalidated, use [`invalidate`](Self::invalidate) if `func` modifies memory.
+    pub fn with_inner<O>(&self, func: impl FnOnce(&mut T) -> O) -> O {
+        func(&mut self.inner.mem())
+    }
+
+    /// Consumes the handle and returns the containing memory object.
+    ///
+    /// Fails and returns the handle back if other handles to the memory object still exist.
+    pub fn try_into_inner(self) -> std::result::Result<T, Self> {
+        Arc::try_unwrap(self.inner)
+            .map(|state| {
+                state
+                    .mem
+                    .into_inner()
+                    .unwrap_or_else(|err| err.into_inner())
+            })
+            .map_err(|inner| Self { inner })
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for SharedPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let state = &*self.inner;
+        let page_size = state.page_size;
+
+        let mut missed = vec![];
+        let mut uncached = vec![];
+
+        for CTup3(addr, meta_addr, buf) in inp {
+            if !addr.page_type().matches_mask(state.page_type_mask) {
+                uncached.push(CTup3(addr, meta_addr, buf));
+                continue;
+            }
+
+            for (paddr, (meta_addr, mut chunk)) in
+                (meta_addr, buf).page_chunks(addr.address(), page_size)
+            {
+                let page_addr = paddr.as_page_aligned(page_size);
+                if state.copy_cached(page_addr, (paddr - page_addr) as usize, &mut chunk) {
+                    stats::record(CacheStat::PageCacheHit, 1);
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+                } else {
+                    stats::record(CacheStat::PageCacheMiss, 1);
+                    missed.push((
+                        PhysicalAddress::with_page(
+                            paddr,
+                            addr.page_type(),
+                            addr.page_size() as umem,
+                        ),
+                        meta_addr,
+                        chunk,
+                    ));
+                }
+            }
+        }
+
+        if missed.is_empty() && uncached.is_empty() {
+            return Ok(());
+        }
+
+        let mut mem = state.mem();
+
+        if !missed.is_empty() {
+            let mut pages = missed
+                .iter()
+                .map(|(paddr, _, _)| paddr.address().as_page_aligned(page_size))
+                .collect::<Vec<_>>();
+            pages.sort_unstable();
+            pages.dedup();
+
+            let mut bufs = vec![0u8; pages.len() * page_size];
+            let mut failed = vec![];
+            {
+                let mut fail = |CTup2(addr, _): ReadData| {
+                    failed.push(addr.as_page_aligned(page_size));
+                    true
+                };
+                let iter = pages
+                    .iter()
+                    .zip(bufs.chunks_mut(page_size))
+                    .map(|(&addr, buf)| CTup3(PhysicalAddress::from(addr), addr, buf.into()));
+                MemOps::with_raw(iter, None, Some(&mut (&mut fail).into()), |data| {
+                    mem.phys_read_raw_iter(data)
+                })?;
+            }
+
+            // pages are inserted while the memory object is still locked, so a concurrent write
+            // can not be overtaken by an older copy of the page
+            for (&page_addr, data) in pages.iter().zip(bufs.chunks(page_size)) {
+                if !failed.contains(&page_addr) {
+                    state.insert(page_addr, data.into());
+                }
+            }
+
+            for (paddr, meta_addr, mut chunk) in missed {
+                let page_addr = paddr.address().as_page_aligned(page_size);
+                if failed.contains(&page_addr) {
+                    // read the chunk again to report the exact failure
+                    uncached.push(CTup3(paddr, meta_addr, chunk));
+                } else {
+                    let idx = pages.binary_search(&page_addr).unwrap();
+                    let start = idx * page_size + (paddr.address() - page_addr) as usize;
+                    chunk.copy_from_slice(&bufs[start..(start + chunk.len())]);
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+                }
+            }
+        }
+
+        MemOps::with_raw(uncached.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let state = &*self.inner;
+        let mut mem = state.mem();
+
+        let iter = inp.inspect(|CTup3(addr, _, data)| {
+            state.invalidate_range(addr.address(), data.len() as umem)
+        });
+        MemOps::with_raw(iter, out, out_fail, |data| mem.phys_write_raw_iter(data))
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.inner.mem().metadata()
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        let mut mem = self.inner.mem();
+        mem.set_mem_map(mem_map);
+        self.inner.invalidate_all();
+    }
+
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.inner.mem().phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.inner.mem().phys_prefetch(ranges)
+    }
+
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        let mut mem = self.inner.mem();
+        self.inner
+            .invalidate_range(addr.address(), new.len() as umem);
+        mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.inner.mem().health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    SharedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+/// The builder interface for constructing a [`SharedPhysicalMemory`] object.
+pub struct SharedPhysicalMemoryBuilder<T> {
+    mem: T,
+    page_size: usize,
+    cache_size: usize,
+    shards: usize,
+    page_type_mask: PageType,
+    validity: Duration,
+}
+
+impl<T: PhysicalMemory> SharedPhysicalMemoryBuilder<T> {
+    /// Creates a new builder with default settings.
+    ///
+    /// By default 2 mb of 4 kb page table and read-only pages are cached in 16 shards for
+    /// one second.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            page_size: size::kb(4),
+            cache_size: size::mb(2),
+            shards: 16,
+            page_type_mask: PageType::PAGE_TABLE | PageType::READ_ONLY,
+            validity: Duration::from_secs(1),
+        }
+    }
+
+    /// Builds the [`SharedPhysicalMemory`] object.
+    pub fn build(self) -> SharedPhysicalMemory<T> {
+        let shard_capacity = std::cmp::max(self.cache_size / self.page_size / self.shards, 1);
+
+        SharedPhysicalMemory {
+            inner: Arc::new(SharedState {
+                mem: Mutex::new(self.mem),
+                shards: (0..self.shards)
+                    .map(|_| RwLock::new(HashMap::new()))
+                    .collect(),
+                shard_capacity,
+                page_size: self.page_size,
+                page_type_mask: self.page_type_mask,
+                validity: self.validity,
+            }),
+        }
+    }
+
+    /// Changes the page granularity of the cache.
+    ///
+    /// # Panics
+    ///
+    /// If `page_size` is not a power of two.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        assert!(
+            page_size.is_power_of_two(),
+            "page size must be a power of two"
+        );
+        self.page_size = page_size;
+        self
+    }
+
+    /// Changes the total size of the cache in bytes.
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Changes the number of independently locked shards of the cache.
+    ///
+    /// More shards reduce the contention between threads inserting pages at the same time.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = std::cmp::max(shards, 1);
+        self
+    }
+
+    /// Changes the page types that are cached.
+    pub fn page_type_mask(mut self, page_type_mask: PageType) -> Self {
+        self.page_type_mask = page_type_mask;
+        self
+    }
+
+    /// Changes how long a cached page stays valid.
+    pub fn validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+
+    #[test]
+    fn shared_between_threads() {
+        // clones of the dummy memory share the same underlying buffer
+        let mut base = DummyMemory::new(size::mb(1));
+        base.phys_write(0x1ffc.into(), &[1u8; 8]).unwrap();
+
+        let shared = SharedPhysicalMemory::builder(base.clone())
+            .page_type_mask(PageType::all())
+            .validity(Duration::from_secs(60))
+            .build();
+
+        let threads = (0..4)
+            .map(|_| {
+                let mut mem = shared.clone();
+                std::thread::spawn(move || {
+                    let mut buf = [0u8; 8];
+                    mem.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+                    buf
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            assert_eq!(t.join().unwrap(), [1u8; 8]);
+        }
+        assert_eq!(shared.cached_pages(), 2);
+
+        // changes made behind the back of the cache are not observed
+        base.phys_write(0x1ffc.into(), &[2u8; 8]).unwrap();
+        let mut mem = shared.clone();
+        let mut buf = [0u8; 8];
+        mem.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 8]);
+
+        // writes through any handle are observed by all handles
+        shared.clone().phys_write(0x1ffe.into(), &[3u8; 4]).unwrap();
+        mem.phys_read_into(0x1ffc.into(), &mut buf).unwrap();
+        assert_eq!(buf, [2, 2, 3, 3, 3, 3, 2, 2]);
+
+        shared.invalidate();
+        assert_eq!(shared.cached_pages(), 0);
+
+        assert_eq!(shared.handle_count(), 2);
+        drop(mem);
+        assert!(shared.try_into_inner().is_ok());
+    }
+}
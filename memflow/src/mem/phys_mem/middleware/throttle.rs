@@ -0,0 +1,302 @@
+//! Bandwidth throttling of physical memory accesses.
+//!
+//! Reading memory as fast as possible is not always desirable. Fragile DMA bridges can lock up
+//! under sustained load and production hypervisors should not be slowed down noticeably by an
+//! introspection tool. The [`ThrottledMemory`] middleware bounds the load put on the target by
+//! capping the number of bytes and the number of requests per second.
+//!
+//! Both caps are enforced with token buckets holding at most one second worth of tokens, so
+//! short bursts up to the configured rates pass without delay while the long term average never
+//! exceeds them. Every element of a batch counts as a single request. Batches exceeding the
+//! remaining budget are still forwarded as a whole, the middleware then sleeps before returning
+//! until the budget has recovered.
+//!
+//! # Examples
+//! ```
+//! use memflow::mem::{MemoryView, PhysicalMemory, ThrottledMemory};
+//!
+//! fn throttled<T: PhysicalMemory>(mem: T) {
+//!     let mut mem = ThrottledMemory::builder(mem)
+//!         .bytes_per_sec(16 * 1024 * 1024)
+//!         .requests_per_sec(10_000)
+//!         .build()
+//!         .unwrap();
+//!
+//!     assert!(mem.phys_view().read::<u64>(0x1000.into()).is_ok());
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # throttled(DummyMemory::new(size::mb(1)));
+//! ```
+
+use ::std::time::{Duration, Instant};
+use ::std::{cmp, thread};
+
+use std::prelude::v1::*;
+
+use crate::cglue::CTup2;
+use crate::error::Result;
+use crate::mem::{
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, PhysicalAddress};
+
+/// Token bucket refilling at a constant rate, holding at most one second worth of tokens.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket refilling `rate` tokens per second.
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Returns the number of tokens refilled per second.
+    pub fn rate(&self) -> u64 {
+        self.rate as u64
+    }
+
+    /// Takes `amount` tokens at time `now` and returns how long to wait until the bucket is no
+    /// longer in debt.
+    pub fn take(&mut self, amount: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = cmp::max(self.last, now);
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate) - amount as f64;
+
+        if self.tokens >= 0.0 {
+            Duration::default()
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// The throttling middleware caps the bytes and requests per second forwarded to the underlying
+/// memory.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct ThrottledMemory<T> {
+    mem: T,
+    bytes: Option<TokenBucket>,
+    requests: Option<TokenBucket>,
+}
+
+impl<T> Clone for ThrottledMemory<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            bytes: self.bytes.clone(),
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> ThrottledMemory<T> {
+    /// Constructs a new middleware with the given caps, `0` disables a cap.
+    ///
+    /// This function is used when manually constructing a middleware inside of the memflow crate itself.
+    ///
+    /// For general usage it is advised to just use the [builder](struct.ThrottledMemoryBuilder.html)
+    /// to construct the middleware.
+    pub fn new(mem: T, bytes_per_sec: u64, requests_per_sec: u64) -> Self {
+        let bucket = |rate| {
+            if rate > 0 {
+                Some(TokenBucket::new(rate))
+            } else {
+                None
+            }
+        };
+
+        Self {
+            mem,
+            bytes: bucket(bytes_per_sec),
+            requests: bucket(requests_per_sec),
+        }
+    }
+
+    /// Returns a new builder for the throttling middleware with default settings.
+    pub fn builder(mem: T) -> ThrottledMemoryBuilder<T> {
+        ThrottledMemoryBuilder::new(mem)
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Accounts for an access and sleeps until both budgets have recovered.
+    fn throttle(&mut self, requests: usize, bytes: umem) {
+        let now = Instant::now();
+
+        let wait = cmp::max(
+            self.bytes
+                .as_mut()
+                .map(|b| b.take(bytes as u64, now))
+                .unwrap_or_default(),
+            self.requests
+                .as_mut()
+                .map(|b| b.take(requests as u64, now))
+                .unwrap_or_default(),
+        );
+
+        if wait > Duration::default() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for ThrottledMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let inp = inp.collect::<Vec<_>>();
+        let bytes = inp.iter().map(|d| d.2.len() as umem).sum();
+        let requests = inp.len();
+
+        let mem = &mut self.mem;
+        let ret = MemOps::with_raw(inp.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        });
+
+        self.throttle(requests, bytes);
+        ret
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let inp = inp.collect::<Vec<_>>();
+        let bytes = inp.iter().map(|d| d.2.len() as umem).sum();
+        let requests = inp.len();
+
+        let mem = &mut self.mem;
+        let ret = MemOps::with_raw(inp.into_iter(), out, out_fail, |data| {
+            mem.phys_write_raw_iter(data)
+        });
+
+        self.throttle(requests, bytes);
+        ret
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        let ret = self.mem.phys_cas_raw(addr, expected, new);
+        self.throttle(2, (expected.len() + new.len()) as umem);
+        ret
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+/// The builder interface for constructing a `ThrottledMemory` object.
+pub struct ThrottledMemoryBuilder<T> {
+    mem: T,
+    bytes_per_sec: u64,
+    requests_per_sec: u64,
+}
+
+impl<T: PhysicalMemory> ThrottledMemoryBuilder<T> {
+    /// Creates a new `ThrottledMemory` builder.
+    /// The memory object is mandatory as the ThrottledMemory struct wraps around it.
+    ///
+    /// Without further adjustments this function creates a middleware without any caps.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            bytes_per_sec: 0,
+            requests_per_sec: 0,
+        }
+    }
+
+    /// Caps the number of bytes read and written per second, `0` disables the cap.
+    pub fn bytes_per_sec(mut self, bytes_per_sec: u64) -> Self {
+        self.bytes_per_sec = bytes_per_sec;
+        self
+    }
+
+    /// Caps the number of requests per second, `0` disables the cap.
+    pub fn requests_per_sec(mut self, requests_per_sec: u64) -> Self {
+        self.requests_per_sec = requests_per_sec;
+        self
+    }
+
+    /// Builds the `ThrottledMemory` object or returns an error.
+    pub fn build(self) -> Result<ThrottledMemory<T>> {
+        Ok(ThrottledMemory::new(
+            self.mem,
+            self.bytes_per_sec,
+            self.requests_per_sec,
+        ))
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    ThrottledMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000);
+
+        // the initial burst is free
+        assert_eq!(bucket.take(1000, start), Duration::default());
+
+        // the debt has to be waited off
+        assert_eq!(bucket.take(500, start), Duration::from_millis(500));
+
+        // after the debt has been paid off the bucket refills
+        let later = start + Duration::from_millis(1000);
+        assert_eq!(bucket.take(500, later), Duration::default());
+
+        // the bucket never holds more than one second worth of tokens
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(bucket.take(1500, much_later), Duration::from_millis(500));
+    }
+}
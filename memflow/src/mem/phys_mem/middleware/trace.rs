@@ -0,0 +1,245 @@
+/*!
+Middleware which traces slow physical memory accesses.
+
+Every read and write batch is timed. Batches which take longer than the configured threshold
+are logged to the [`READ_TRACE`](crate::error::log_target::READ_TRACE) log target together
+with the number of elements, the total length and the first accessed address.
+
+This makes it possible to find slow accesses in a running application without enabling
+verbose logging of all layers, e.g. via `RUST_LOG=memflow::trace=info`.
+*/
+
+use ::log::{info, trace};
+use ::std::time::{Duration, Instant};
+
+use crate::cglue::CTup2;
+use crate::error::{log_target::READ_TRACE, Result};
+use crate::mem::{
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMappingCallback,
+    PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, PhysicalAddress};
+
+/// The trace middleware logs all read and write batches which take longer than a threshold.
+///
+/// With a threshold of zero every single batch is logged via `::log::trace`, otherwise slow
+/// batches are logged via `::log::info`.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+#[derive(Clone)]
+pub struct TracedPhysicalMemory<T> {
+    mem: T,
+    threshold: Duration,
+    traced: u64,
+}
+
+impl<T: PhysicalMemory> TracedPhysicalMemory<T> {
+    /// Constructs a new middleware which traces all accesses slower than `threshold`.
+    pub fn new(mem: T, threshold: Duration) -> Self {
+        Self {
+            mem,
+            threshold,
+            traced: 0,
+        }
+    }
+
+    /// Returns the threshold above which accesses are traced.
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Returns the number of batches that have been traced so far.
+    pub fn traced(&self) -> u64 {
+        self.traced
+    }
+
+    /// Consumes self and returns the containing memory object.
+    ///
+    /// This function can be useful in case the ownership over the memory object has been given to the middleware
+    /// when it was being constructed.
+    /// It will destroy the `self` and return back the ownership of the underlying memory object.
+    ///
+    /// # Examples
+    /// ```
+    /// # const MAGIC_VALUE: u64 = 0x23bd_318f_f3a3_5821;
+    /// use memflow::mem::{PhysicalMemory, TracedPhysicalMemory, MemoryView};
+    /// use std::time::Duration;
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) -> T {
+    ///     let mut middleware = TracedPhysicalMemory::new(mem, Duration::from_millis(10));
+    ///
+    ///     // use the middleware...
+    ///     let value: u64 = middleware.phys_view().read(0.into()).unwrap();
+    ///     assert_eq!(value, MAGIC_VALUE);
+    ///
+    ///     // retrieve ownership of mem and return it back
+    ///     middleware.into_inner()
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # mem.phys_write(0.into(), &MAGIC_VALUE).unwrap();
+    /// # build(mem);
+    /// ```
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn trace(&mut self, op: &str, elapsed: Duration, stats: &AccessStats) {
+        if self.threshold == Duration::ZERO {
+            self.traced += 1;
+            trace!(
+                target: READ_TRACE,
+                "{} of {} elements ({} bytes) starting at {} took {:.4}ms",
+                op,
+                stats.count,
+                stats.bytes,
+                stats.first.unwrap_or_default(),
+                elapsed.as_secs_f64() * 1000f64,
+            );
+        } else if elapsed >= self.threshold {
+            self.traced += 1;
+            info!(
+                target: READ_TRACE,
+                "slow {} of {} elements ({} bytes) starting at {} took {:.4}ms (threshold {}ms)",
+                op,
+                stats.count,
+                stats.bytes,
+                stats.first.unwrap_or_default(),
+                elapsed.as_secs_f64() * 1000f64,
+                self.threshold.as_millis(),
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct AccessStats {
+    count: usize,
+    bytes: usize,
+    first: Option<PhysicalAddress>,
+}
+
+impl AccessStats {
+    fn add(&mut self, addr: PhysicalAddress, len: usize) {
+        if self.first.is_none() {
+            self.first = Some(addr);
+        }
+        self.count += 1;
+        self.bytes += len;
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for TracedPhysicalMemory<T> {
+    #[inline]
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps { inp, out_fail, out }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let mut stats = AccessStats::default();
+        let iter = inp.inspect(|e| stats.add(e.0, e.2.len()));
+
+        let start_time = Instant::now();
+
+        let mem = &mut self.mem;
+        let result = MemOps::with_raw(iter, out, out_fail, |data| mem.phys_read_raw_iter(data));
+
+        self.trace("read", start_time.elapsed(), &stats);
+
+        result
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, out_fail, out }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let mut stats = AccessStats::default();
+        let iter = inp.inspect(|e| stats.add(e.0, e.2.len()));
+
+        let start_time = Instant::now();
+
+        let mem = &mut self.mem;
+        let result = MemOps::with_raw(iter, out, out_fail, |data| mem.phys_write_raw_iter(data));
+
+        self.trace("write", start_time.elapsed(), &stats);
+
+        result
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn phys_mem_map(&self, out: PhysicalMemoryMappingCallback) {
+        self.mem.phys_mem_map(out)
+    }
+
+    #[inline]
+    fn phys_prefetch(&mut self, ranges: &[CTup2<PhysicalAddress, umem>]) {
+        self.mem.phys_prefetch(ranges)
+    }
+
+    #[inline]
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        self.mem.phys_cas_raw(addr, expected, new)
+    }
+
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        self.mem.health_check()
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    TracedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn trace_all_accesses() {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut traced = TracedPhysicalMemory::new(mem, Duration::ZERO);
+
+        let mut buf = [0u8; 16];
+        traced
+            .phys_view()
+            .read_raw_into(0x100.into(), &mut buf)
+            .unwrap();
+        traced.phys_view().write_raw(0x200.into(), &buf).unwrap();
+
+        assert_eq!(traced.traced(), 2);
+    }
+
+    #[test]
+    fn skip_fast_accesses() {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut traced = TracedPhysicalMemory::new(mem, Duration::from_secs(60));
+
+        let mut buf = [0u8; 16];
+        traced
+            .phys_view()
+            .read_raw_into(0x100.into(), &mut buf)
+            .unwrap();
+
+        assert_eq!(traced.traced(), 0);
+    }
+}
@@ -1,10 +1,11 @@
 use crate::cglue::*;
 use crate::dataview::{Pod, PodMethods};
-use crate::error::Result;
+use crate::error::stats::{self, ErrorStat};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::types::{umem, Address, PhysicalAddress};
 
 use super::mem_data::*;
-use super::PhysicalMemoryMapping;
+use super::{PhysicalMemoryMapping, PhysicalMemoryMappingCallback};
 
 use std::prelude::v1::*;
 
@@ -100,7 +101,9 @@ pub use middleware::*;
 ///             max_address: (self.mem.len() - 1).into(),
 ///             real_size: self.mem.len() as umem,
 ///             readonly: false,
-///             ideal_batch_size: u32::MAX
+///             ideal_batch_size: u32::MAX,
+///             max_read_size: u32::MAX,
+///             read_alignment: 1,
 ///         }
 ///     }
 /// }
@@ -159,6 +162,56 @@ pub trait PhysicalMemory: Send {
     #[inline]
     fn set_mem_map(&mut self, _mem_map: &[PhysicalMemoryMapping]) {}
 
+    /// Retrieves the ranges of the physical address space which are backed by memory
+    ///
+    /// Physical address spaces usually contain holes (e.g. for MMIO or reserved regions) which
+    /// can not be read. Layers above can use the ranges to avoid issuing reads into holes, or
+    /// to report the coverage of a dump relative to the memory actually present.
+    ///
+    /// The ranges are reported in ascending order. By default the whole address space up to
+    /// `max_address` of [`metadata`](Self::metadata) is reported as a single range.
+    fn phys_mem_map(&self, mut out: PhysicalMemoryMappingCallback) {
+        let _ = out.call(PhysicalMemoryMapping {
+            base: Address::null(),
+            size: self.metadata().max_address.to_umem().saturating_add(1),
+            real_base: Address::null(),
+        });
+    }
+
+    /// Hints that the given physical ranges are about to be read
+    ///
+    /// Middleware like the page cache can use this hint to fetch the pages in a single batch
+    /// ahead of time, instead of issuing many small reads later on. Implementations are free to
+    /// ignore the hint, it never fails and never alters memory contents.
+    ///
+    /// By default this is a no-op.
+    #[inline]
+    fn phys_prefetch(&mut self, _ranges: &[CTup2<PhysicalAddress, umem>]) {}
+
+    /// Writes `new` to `addr` if the memory at `addr` currently equals `expected`
+    ///
+    /// Returns `true` if the memory matched and `new` was written, `false` if the memory did not
+    /// match and was left untouched. `expected` and `new` are required to have the same length.
+    ///
+    /// Connectors able to perform the exchange atomically on the target should override this
+    /// function. By default it is emulated through [`phys_cas_emulated`].
+    fn phys_cas_raw(&mut self, addr: PhysicalAddress, expected: &[u8], new: &[u8]) -> Result<bool> {
+        phys_cas_emulated(self, addr, expected, new)
+    }
+
+    /// Checks if the connection to the target is still alive
+    ///
+    /// Connectors which can lose the connection to the target (e.g. network or USB devices)
+    /// should override this function and return an error once the target stopped responding.
+    /// Middleware like [`ReconnectingPhysicalMemory`](crate::mem::ReconnectingPhysicalMemory) use it to decide if a failed access was
+    /// caused by a dropped connection.
+    ///
+    /// By default the connection is assumed to always be alive.
+    #[inline]
+    fn health_check(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     #[skip_func]
     fn phys_read_into<T: Pod + ?Sized>(&mut self, addr: PhysicalAddress, out: &mut T) -> Result<()>
     where
@@ -191,6 +244,81 @@ pub trait PhysicalMemory: Send {
         )
     }
 
+    /// Writes `new` to `addr` if the memory at `addr` currently equals `expected`
+    ///
+    /// See [`phys_cas_raw`](Self::phys_cas_raw) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::mem::PhysicalMemory;
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = DummyMemory::new(size::mb(1));
+    ///
+    /// assert!(mem.phys_cas(0x1000.into(), &0u32, &1u32).unwrap());
+    /// assert!(!mem.phys_cas(0x1000.into(), &0u32, &2u32).unwrap());
+    /// ```
+    #[skip_func]
+    fn phys_cas<T: Pod + ?Sized>(
+        &mut self,
+        addr: PhysicalAddress,
+        expected: &T,
+        new: &T,
+    ) -> Result<bool>
+    where
+        Self: Sized,
+    {
+        self.phys_cas_raw(addr, expected.as_bytes(), new.as_bytes())
+    }
+
+    /// Returns the ranges of the physical address space which are backed by memory
+    ///
+    /// See [`phys_mem_map`](Self::phys_mem_map) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::mem::PhysicalMemory;
+    /// use memflow::types::mem;
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mem = DummyMemory::new(size::mb(1));
+    ///
+    /// let mem_map = mem.phys_mem_map_vec();
+    /// assert_eq!(mem_map.len(), 1);
+    /// assert_eq!(mem_map[0].size, mem::mb(1));
+    /// ```
+    #[skip_func]
+    fn phys_mem_map_vec(&self) -> Vec<PhysicalMemoryMapping>
+    where
+        Self: Sized,
+    {
+        let mut out = vec![];
+        self.phys_mem_map((&mut out).into());
+        out
+    }
+
+    /// Returns the number of bytes between `addr` and `addr + size` which are backed by memory
+    ///
+    /// Dividing the number of bytes read into a dump by this value yields its actual coverage,
+    /// without counting holes of the physical address space as missing.
+    #[skip_func]
+    fn phys_backed_size(&self, addr: Address, size: umem) -> umem
+    where
+        Self: Sized,
+    {
+        let end = addr.to_umem().saturating_add(size);
+        self.phys_mem_map_vec()
+            .iter()
+            .map(|m| {
+                let start = std::cmp::max(m.base.to_umem(), addr.to_umem());
+                let stop = std::cmp::min(m.base.to_umem().saturating_add(m.size), end);
+                stop.saturating_sub(start)
+            })
+            .sum()
+    }
+
     #[vtbl_only('static, wrap_with_obj(MemoryView))]
     fn into_phys_view(self) -> PhysicalMemoryView<Self>
     where
@@ -211,6 +339,57 @@ pub trait PhysicalMemory: Send {
     }
 }
 
+/// Emulates a compare-and-swap on memory that does not support it natively.
+///
+/// The memory at `addr` is read and compared against `expected`. If it matches, `new` is written.
+/// This narrows the window in which the target can modify the memory in between, but does not
+/// close it. Connectors only able to exchange some sizes or alignments atomically can use this
+/// as a fallback for the remaining ones.
+pub fn phys_cas_emulated<T: PhysicalMemory + ?Sized>(
+    mem: &mut T,
+    addr: PhysicalAddress,
+    expected: &[u8],
+    new: &[u8],
+) -> Result<bool> {
+    if expected.len() != new.len() {
+        return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+            .log_error("expected and new values differ in length"));
+    }
+
+    let mut current = vec![0u8; expected.len()];
+    let mut failed = false;
+    MemOps::with(
+        std::iter::once((addr, CSliceMut::from(&mut current[..]))),
+        None,
+        Some(
+            &mut (&mut |_: ReadData| {
+                failed = true;
+                true
+            })
+                .into(),
+        ),
+        |data| mem.phys_read_raw_iter(data),
+    )?;
+
+    if failed {
+        return Err(Error(ErrorOrigin::Memory, ErrorKind::PartialData)
+            .log_error("unable to read the current value"));
+    }
+
+    if current != expected {
+        return Ok(false);
+    }
+
+    MemOps::with(
+        std::iter::once((addr, CSliceRef::from(new))),
+        None,
+        None,
+        |data| mem.phys_write_raw_iter(data),
+    )?;
+
+    Ok(true)
+}
+
 #[repr(C)]
 #[derive(Clone)]
 #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
@@ -248,6 +427,7 @@ impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
             let out_fail = out_fail.unwrap();
 
             let out_fail = &mut |mut data: ReadData<'a>| {
+                stats::record(ErrorStat::ConnectorReadFailure, 1);
                 if data.0 < ma {
                     data.1.iter_mut().for_each(|b| *b = 0);
                     out1(data)
@@ -260,20 +440,32 @@ impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
             let out_fail = Some(out_fail);
 
             let data = MemOps { inp, out, out_fail };
-            self.mem.phys_read_raw_iter(data)
+            let result = self.mem.phys_read_raw_iter(data);
+            stats::record_result(&result);
+            result
         } else {
-            let data = MemOps { inp, out, out_fail };
-            self.mem.phys_read_raw_iter(data)
+            let mem = &mut self.mem;
+            stats::with_counted_failures(
+                ErrorStat::ConnectorReadFailure,
+                inp,
+                out,
+                out_fail,
+                |data| mem.phys_read_raw_iter(data),
+            )
         }
     }
 
     fn write_raw_iter(&mut self, MemOps { inp, out, out_fail }: WriteRawMemOps) -> Result<()> {
-        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
-        let inp = inp.into();
-
-        let data = MemOps { inp, out, out_fail };
+        let inp = inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
 
-        self.mem.phys_write_raw_iter(data)
+        let mem = &mut self.mem;
+        stats::with_counted_failures(
+            ErrorStat::ConnectorWriteFailure,
+            inp,
+            out,
+            out_fail,
+            |data| mem.phys_write_raw_iter(data),
+        )
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
@@ -298,6 +490,10 @@ impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
             little_endian: false,
         }
     }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        self.mem.phys_prefetch(&[CTup2(addr.into(), len)])
+    }
 }
 
 #[repr(C)]
@@ -309,4 +505,11 @@ pub struct PhysicalMemoryMetadata {
     pub real_size: umem,
     pub readonly: bool,
     pub ideal_batch_size: u32,
+    /// Maximum number of bytes a single read may request, `u32::MAX` if reads are unlimited
+    pub max_read_size: u32,
+    /// Alignment required for the address and size of reads, `1` if reads may be unaligned
+    ///
+    /// See [`ConstrainedPhysicalMemory`](crate::mem::ConstrainedPhysicalMemory) for how these
+    /// constraints are enforced.
+    pub read_alignment: u32,
 }
@@ -0,0 +1,95 @@
+//! Progress reporting for long running operations.
+//!
+//! Operations that walk large ranges of memory, like hashing or diffing whole address spaces,
+//! can take minutes on slow connectors. Their `*_with_progress` variants accept a
+//! [`ProgressCallback`] that is invoked after every processed chunk with the current
+//! [`Progress`]. Returning `false` from the callback cancels the operation, which then fails with
+//! [`ErrorKind::Cancelled`].
+//!
+//! The `done` and `total` counters map directly onto progress bar libraries like `indicatif`.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::mem::Progress;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn read_all(mem: &mut impl MemoryView, addr: Address, len: umem) -> Result<()> {
+//!     let mut report = |p: Progress| {
+//!         // e.g. `bar.set_length(p.total); bar.set_position(p.done);`
+//!         println!("{:.1}%", p.fraction() * 100.0);
+//!         true
+//!     };
+//!     let mut progress = Some((&mut report).into());
+//!
+//!     let mut stream = mem.read_stream(addr, len, size::kb(4)).zero_fill_gaps();
+//!     while let Some(chunk) = stream.next() {
+//!         chunk?;
+//!         memflow::mem::progress::report(&mut progress, stream.progress())?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # read_all(&mut proc, addr, size::kb(16) as umem).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::umem;
+
+/// Progress of a long running operation.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct Progress {
+    /// Number of units (usually bytes) that have been processed
+    pub done: umem,
+    /// Total number of units the operation will process
+    pub total: umem,
+}
+
+impl Progress {
+    /// Creates a new progress report.
+    pub fn new(done: umem, total: umem) -> Self {
+        Self { done, total }
+    }
+
+    /// Returns the processed fraction in the range `0.0..=1.0`.
+    ///
+    /// An operation without any work is reported as finished.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+
+    /// Returns true if all units have been processed.
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+/// Callback receiving progress reports, returning `false` cancels the operation.
+pub type ProgressCallback<'a> = OpaqueCallback<'a, Progress>;
+
+/// Invokes the optional `callback` with `progress`.
+///
+/// Returns an error of kind [`ErrorKind::Cancelled`] if the callback requested cancellation.
+pub fn report(callback: &mut Option<ProgressCallback>, progress: Progress) -> Result<()> {
+    match callback {
+        Some(cb) if !cb.call(progress) => Err(Error(ErrorOrigin::Other, ErrorKind::Cancelled)
+            .log_debug(format!(
+                "cancelled after {:x} of {:x}",
+                progress.done, progress.total
+            ))),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,398 @@
+//! Virtual memory described by an explicit mapping table.
+use std::prelude::v1::*;
+
+use crate::architecture::{ArchitectureObj, Endianess};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::memory_view::*;
+use crate::mem::{
+    mem_data::*,
+    virt_translate::{
+        VirtualTranslate, VirtualTranslation, VirtualTranslationCallback, VirtualTranslationFail,
+        VirtualTranslationFailCallback,
+    },
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata,
+};
+use crate::types::{umem, Address, PageType, PhysicalAddress};
+use cglue::tuple::*;
+
+/// A single entry of the mapping table of a [`MappedVirtualMemory`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct VirtualMapping {
+    /// Start of the mapping in the virtual address space
+    pub virt: Address,
+    /// Start of the mapping in physical memory
+    pub phys: Address,
+    /// Size of the mapping in bytes
+    pub size: umem,
+    /// Access flags of the mapping, only `PageType::WRITEABLE` mappings accept writes
+    pub page_type: PageType,
+}
+
+impl VirtualMapping {
+    pub fn new(virt: Address, phys: Address, size: umem, page_type: PageType) -> Self {
+        Self {
+            virt,
+            phys,
+            size,
+            page_type,
+        }
+    }
+
+    fn contains(&self, addr: Address) -> bool {
+        self.virt <= addr && addr.to_umem() - self.virt.to_umem() < self.size
+    }
+
+    fn overlaps(&self, other: &VirtualMapping) -> bool {
+        self.virt.to_umem() < other.virt.to_umem() + other.size
+            && other.virt.to_umem() < self.virt.to_umem() + self.size
+    }
+}
+
+/// Virtual memory built from a user supplied mapping table.
+///
+/// Unlike [`VirtualDma`](crate::mem::VirtualDma) this does not walk any page tables. Every
+/// virtual address is translated through the list of [`VirtualMapping`]s, which makes it useful
+/// for custom operating systems, unikernels, or early boot stages where the memory layout is
+/// known upfront but no (usable) page tables exist yet.
+///
+/// Reads from unmapped memory and writes to mappings without `PageType::WRITEABLE` are reported
+/// as failures.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::architecture::x86::x64;
+/// use memflow::dummy::DummyMemory;
+/// use memflow::mem::{MappedVirtualMemory, MemoryView, PhysicalMemory, VirtualMapping};
+/// use memflow::types::{size, Address, PageType};
+///
+/// let mut mem = DummyMemory::new(size::mb(1));
+/// mem.phys_write(Address::from(0x2000).into(), &0x1234u32).unwrap();
+///
+/// let mut virt_mem = MappedVirtualMemory::from_mappings(
+///     mem,
+///     x64::ARCH,
+///     vec![VirtualMapping::new(
+///         Address::from(0xffff_8000_0000_0000u64),
+///         Address::from(0x2000),
+///         size::kb(4) as _,
+///         PageType::WRITEABLE,
+///     )],
+/// )
+/// .unwrap();
+///
+/// let value: u32 = virt_mem.read(Address::from(0xffff_8000_0000_0000u64)).unwrap();
+/// assert_eq!(value, 0x1234);
+/// ```
+#[derive(Clone)]
+pub struct MappedVirtualMemory<T> {
+    phys_mem: T,
+    arch: ArchitectureObj,
+    mappings: Vec<VirtualMapping>,
+    read_map: MemoryMap<(Address, umem)>,
+    write_map: MemoryMap<(Address, umem)>,
+}
+
+impl<T: PhysicalMemory> MappedVirtualMemory<T> {
+    /// Constructs a `MappedVirtualMemory` without any mappings.
+    ///
+    /// The architecture is used to determine pointer sizes and the page size reported in
+    /// translations.
+    pub fn new(phys_mem: T, arch: impl Into<ArchitectureObj>) -> Self {
+        Self {
+            phys_mem,
+            arch: arch.into(),
+            mappings: vec![],
+            read_map: MemoryMap::new(),
+            write_map: MemoryMap::new(),
+        }
+    }
+
+    /// Constructs a `MappedVirtualMemory` from a list of mappings.
+    ///
+    /// This fails if any of the mappings overlap, or are empty.
+    pub fn from_mappings(
+        phys_mem: T,
+        arch: impl Into<ArchitectureObj>,
+        mappings: impl IntoIterator<Item = VirtualMapping>,
+    ) -> Result<Self> {
+        let mut ret = Self::new(phys_mem, arch);
+        for mapping in mappings {
+            ret.push_mapping(mapping)?;
+        }
+        Ok(ret)
+    }
+
+    /// Adds a new mapping to the mapping table.
+    ///
+    /// Mappings may not overlap with existing ones in the virtual address space. Multiple
+    /// mappings pointing to the same physical memory are allowed.
+    pub fn push_mapping(&mut self, mapping: VirtualMapping) -> Result<()> {
+        if mapping.size == 0 {
+            return Err(
+                Error(ErrorOrigin::VirtualMemory, ErrorKind::InvalidArgument)
+                    .log_error("mapping size must not be zero"),
+            );
+        }
+
+        if self.mappings.iter().any(|m| m.overlaps(&mapping)) {
+            return Err(Error(ErrorOrigin::VirtualMemory, ErrorKind::AlreadyExists)
+                .log_error("mapping overlaps with an existing mapping"));
+        }
+
+        self.read_map
+            .push_remap(mapping.virt, mapping.size, mapping.phys);
+        if mapping.page_type.contains(PageType::WRITEABLE) {
+            self.write_map
+                .push_remap(mapping.virt, mapping.size, mapping.phys);
+        }
+
+        let idx = self
+            .mappings
+            .binary_search_by(|m| m.virt.cmp(&mapping.virt))
+            .unwrap_or_else(|idx| idx);
+        self.mappings.insert(idx, mapping);
+
+        Ok(())
+    }
+
+    /// Returns all mappings, sorted by their virtual address.
+    pub fn mappings(&self) -> &[VirtualMapping] {
+        &self.mappings
+    }
+
+    /// Returns the mapping containing the given virtual address.
+    pub fn mapping_at(&self, addr: Address) -> Option<&VirtualMapping> {
+        let idx = match self.mappings.binary_search_by(|m| m.virt.cmp(&addr)) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        self.mappings.get(idx).filter(|m| m.contains(addr))
+    }
+
+    /// Returns a reference to the underlying physical memory.
+    pub fn phys_mem(&mut self) -> &mut T {
+        &mut self.phys_mem
+    }
+
+    /// Consumes this object, returning the underlying physical memory.
+    pub fn into_inner(self) -> T {
+        self.phys_mem
+    }
+}
+
+impl<T: PhysicalMemory> MemoryView for MappedVirtualMemory<T> {
+    fn read_raw_iter(&mut self, MemOps { inp, out_fail, out }: ReadRawMemOps) -> Result<()> {
+        let out_fail = out_fail.map(std::cell::RefCell::new);
+
+        let mut out_fail1 = out_fail
+            .as_ref()
+            .map(|of| move |data| of.borrow_mut().call(data));
+        let mut out_fail2 = out_fail
+            .as_ref()
+            .map(|of| move |data| of.borrow_mut().call(data));
+        let mut out_fail2 = out_fail2.as_mut().map(<_>::into);
+        let out_fail2 = out_fail2.as_mut();
+
+        let mut out = out.map(|o| move |data| o.call(data));
+        let mut out = out.as_mut().map(<_>::into);
+        let out = out.as_mut();
+
+        let read_map = &self.read_map;
+        let phys_mem = &mut self.phys_mem;
+
+        let iter = read_map
+            .map_base_iter(inp, out_fail1.as_mut())
+            .map(|CTup3((a, _), m, b)| CTup3(PhysicalAddress::from(a), m, b));
+
+        MemOps::with_raw(iter, out, out_fail2, |data| {
+            phys_mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn write_raw_iter(&mut self, MemOps { inp, out_fail, out }: WriteRawMemOps) -> Result<()> {
+        let out_fail = out_fail.map(std::cell::RefCell::new);
+
+        let mut out_fail1 = out_fail
+            .as_ref()
+            .map(|of| move |data| of.borrow_mut().call(data));
+        let mut out_fail2 = out_fail
+            .as_ref()
+            .map(|of| move |data| of.borrow_mut().call(data));
+        let mut out_fail2 = out_fail2.as_mut().map(<_>::into);
+        let out_fail2 = out_fail2.as_mut();
+
+        let mut out = out.map(|o| move |data| o.call(data));
+        let mut out = out.as_mut().map(<_>::into);
+        let out = out.as_mut();
+
+        let write_map = &self.write_map;
+        let phys_mem = &mut self.phys_mem;
+
+        let iter = write_map
+            .map_base_iter(inp, out_fail1.as_mut())
+            .map(|CTup3((a, _), m, b)| CTup3(PhysicalAddress::from(a), m, b));
+
+        MemOps::with_raw(iter, out, out_fail2, |data| {
+            phys_mem.phys_write_raw_iter(data)
+        })
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let PhysicalMemoryMetadata { readonly, .. } = self.phys_mem.metadata();
+
+        MemoryViewMetadata {
+            max_address: self.read_map.max_address(),
+            real_size: self.read_map.real_size(),
+            readonly: readonly || self.write_map.is_empty(),
+            little_endian: self.arch.endianess() == Endianess::LittleEndian,
+            arch_bits: self.arch.bits(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> VirtualTranslate for MappedVirtualMemory<T> {
+    fn virt_to_phys_list(
+        &mut self,
+        addrs: &[VtopRange],
+        mut out: VirtualTranslationCallback,
+        mut out_fail: VirtualTranslationFailCallback,
+    ) {
+        let page_size = self.arch.page_size() as umem;
+
+        let fail = &mut |CTup2(from, size): CTup2<Address, umem>| {
+            out_fail.call(VirtualTranslationFail { from, size })
+        };
+
+        let iter = self.read_map.map_base_iter(
+            addrs
+                .iter()
+                .map(|&CTup2(address, size)| CTup3(address, address, size)),
+            Some(fail),
+        );
+
+        for CTup3((phys, _), in_virtual, size) in iter {
+            let page_type = self
+                .mapping_at(in_virtual)
+                .map(|m| m.page_type)
+                .unwrap_or(PageType::UNKNOWN);
+
+            if !out.call(VirtualTranslation {
+                in_virtual,
+                size,
+                out_physical: PhysicalAddress::with_page(phys, page_type, page_size),
+            }) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    const CODE_BASE: umem = 0x4000_0000;
+    const DATA_BASE: umem = 0x4000_3000;
+
+    fn build_mem() -> MappedVirtualMemory<DummyMemory> {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let buf = (0..size::kb(16)).map(|i| i as u8).collect::<Vec<_>>();
+        mem.phys_write(Address::from(0x1_0000).into(), buf.as_slice())
+            .unwrap();
+
+        MappedVirtualMemory::from_mappings(
+            mem,
+            x64::ARCH,
+            vec![
+                // deliberately placed out of order
+                VirtualMapping::new(
+                    DATA_BASE.into(),
+                    Address::from(0x1_3000),
+                    size::kb(4) as umem,
+                    PageType::WRITEABLE | PageType::NOEXEC,
+                ),
+                VirtualMapping::new(
+                    CODE_BASE.into(),
+                    Address::from(0x1_0000),
+                    size::kb(12) as umem,
+                    PageType::READ_ONLY,
+                ),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn read_across_mappings() {
+        let mut mem = build_mem();
+
+        let mut out = vec![0u8; 0x20];
+        mem.read_raw_into(Address::from(DATA_BASE - 0x10), &mut out)
+            .unwrap();
+        let expected = (0x2ff0..0x3010).map(|i| i as u8).collect::<Vec<_>>();
+        assert_eq!(out, expected);
+
+        assert!(mem
+            .read_raw_into(Address::from(DATA_BASE + 0xff0), &mut out)
+            .is_err());
+        assert!(mem.read::<u32>(Address::null()).is_err());
+    }
+
+    #[test]
+    fn write_respects_flags() {
+        let mut mem = build_mem();
+
+        mem.write(Address::from(DATA_BASE + 8), &0xdead_beefu32)
+            .unwrap();
+        assert_eq!(
+            mem.read::<u32>(Address::from(DATA_BASE + 8)).unwrap(),
+            0xdead_beef
+        );
+
+        assert!(mem.write(Address::from(CODE_BASE), &0u32).is_err());
+        assert_eq!(mem.read::<u8>(Address::from(CODE_BASE + 1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn overlapping_mappings() {
+        let mut mem = build_mem();
+
+        let overlapping = VirtualMapping::new(
+            Address::from(CODE_BASE + 0x1000),
+            Address::null(),
+            0x1000,
+            PageType::WRITEABLE,
+        );
+        assert_eq!(
+            mem.push_mapping(overlapping).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert_eq!(mem.mappings().len(), 2);
+        assert_eq!(mem.mappings()[0].virt, Address::from(CODE_BASE));
+    }
+
+    #[test]
+    fn translation_map() {
+        let mut mem = build_mem();
+
+        let mut out = vec![];
+        mem.virt_translation_map_range(Address::null(), Address::invalid(), (&mut out).into());
+
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].in_virtual, Address::from(CODE_BASE));
+        assert_eq!(out[0].size, size::kb(12) as umem);
+        assert_eq!(out[0].out_physical.address(), Address::from(0x1_0000));
+        assert_eq!(
+            out[1].out_physical.page_type(),
+            PageType::WRITEABLE | PageType::NOEXEC
+        );
+    }
+}
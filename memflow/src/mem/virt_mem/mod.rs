@@ -1,4 +1,7 @@
+pub mod mapped;
 pub mod virtual_dma;
 
+#[doc(hidden)]
+pub use mapped::{MappedVirtualMemory, VirtualMapping};
 #[doc(hidden)]
 pub use virtual_dma::VirtualDma;
@@ -1,6 +1,7 @@
 use std::prelude::v1::*;
 
 use crate::architecture::{ArchitectureObj, Endianess};
+use crate::error::stats::{self, ErrorStat};
 use crate::error::{Error, Result, *};
 use crate::mem::memory_view::*;
 use crate::mem::{
@@ -13,6 +14,7 @@ use crate::mem::{
     MemoryView, PhysicalMemory, PhysicalMemoryMetadata,
 };
 use crate::types::{umem, Address, PhysicalAddress};
+use cglue::slice::{CSliceMut, CSliceRef};
 use cglue::tuple::*;
 
 use bumpalo::{collections::Vec as BumpVec, Bump};
@@ -158,6 +160,226 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
         }
     }
 
+    /// Reads `out.len()` bytes at the virtual address `addr` and returns how each chunk of the
+    /// read was translated.
+    ///
+    /// Every returned [`VirtualTranslation`] describes a chunk of the read together with the
+    /// physical address and page information it was read from, sorted by virtual address.
+    /// Tools correlating the virtual and physical view of memory can use this to avoid
+    /// translating the range a second time.
+    ///
+    /// Chunks that could not be translated are missing from the output. If any part of the read
+    /// failed, the translations are returned as a [`PartialError::PartialVirtualRead`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::{DummyMemory, DummyOs};
+    /// # use memflow::architecture::x86::x64;
+    /// # let mem = DummyMemory::new(size::mb(4));
+    /// # let (mut os, dtb, virt_base) = DummyOs::new_and_dtb(mem, size::mb(2), &[1, 2, 3, 4]);
+    /// # let mut virt_mem = VirtualDma::new(os.forward_mut(), x64::ARCH, x64::new_translator(dtb));
+    ///
+    /// let mut buf = [0u8; 4];
+    /// let info = virt_mem.virt_read_raw_info_into(virt_base, &mut buf).unwrap();
+    ///
+    /// assert_eq!(buf, [1, 2, 3, 4]);
+    /// assert_eq!(info[0].in_virtual, virt_base);
+    /// assert!(info[0].out_physical.page_type().contains(PageType::WRITEABLE));
+    /// ```
+    pub fn virt_read_raw_info_into(
+        &mut self,
+        addr: Address,
+        out: &mut [u8],
+    ) -> PartialResult<Vec<VirtualTranslation>> {
+        let phys_mem = &mut self.phys_mem;
+
+        let mut info = vec![];
+        let mut translation = vec![];
+        let mut failed = false;
+
+        self.vat.virt_to_phys_iter(
+            phys_mem,
+            &self.translator,
+            std::iter::once(CTup3(addr, addr, CSliceMut::from(out))),
+            &mut (&mut |CTup3(phys, virt, buf): CTup3<PhysicalAddress, Address, CSliceMut<u8>>| {
+                info.push(VirtualTranslation {
+                    in_virtual: virt,
+                    size: buf.len() as umem,
+                    out_physical: phys,
+                });
+                translation.push(CTup3(phys, virt, buf));
+                true
+            })
+                .into(),
+            &mut (&mut |_: (Error, CTup3<Address, Address, CSliceMut<u8>>)| {
+                stats::record(ErrorStat::TranslationFailure, 1);
+                failed = true;
+                true
+            })
+                .into(),
+        );
+
+        MemOps::with_raw(
+            translation.into_iter(),
+            None,
+            Some(
+                &mut (&mut |_: ReadData| {
+                    stats::record(ErrorStat::ConnectorReadFailure, 1);
+                    failed = true;
+                    true
+                })
+                    .into(),
+            ),
+            |data| phys_mem.phys_read_raw_iter(data),
+        )?;
+
+        info.sort_unstable_by_key(|t| t.in_virtual);
+
+        if failed {
+            Err(PartialError::PartialVirtualRead(info))
+        } else {
+            Ok(info)
+        }
+    }
+
+    /// Reads `len` bytes at the virtual address `addr` and returns them along with the
+    /// translation of each chunk of the read.
+    ///
+    /// See [`virt_read_raw_info_into`](Self::virt_read_raw_info_into) for details.
+    pub fn virt_read_raw_info(
+        &mut self,
+        addr: Address,
+        len: usize,
+    ) -> PartialResult<(Vec<u8>, Vec<VirtualTranslation>)> {
+        let mut buf = vec![0u8; len];
+        self.virt_read_raw_info_into(addr, &mut buf)
+            .map_data(|info| (buf, info))
+    }
+
+    /// Reads `out.len()` bytes at the virtual address `addr` and reports where the read failed.
+    ///
+    /// All accessible parts of the range are read. If any part failed, the chunk with the lowest
+    /// address is returned as an [`AccessError`]. Its origin tells apart pages that are not
+    /// mapped ([`ErrorOrigin::VirtualTranslate`]) from I/O failures of the underlying connector
+    /// ([`ErrorOrigin::Connector`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::{DummyMemory, DummyOs};
+    /// # use memflow::architecture::x86::x64;
+    /// # let mem = DummyMemory::new(size::mb(4));
+    /// # let (mut os, dtb, virt_base) = DummyOs::new_and_dtb(mem, size::mb(2), &[1, 2, 3, 4]);
+    /// # let mut virt_mem = VirtualDma::new(os.forward_mut(), x64::ARCH, x64::new_translator(dtb));
+    ///
+    /// let mut buf = [0u8; 0x10];
+    /// let end = virt_base + size::mb(2);
+    /// let err = virt_mem
+    ///     .virt_read_raw_checked_into(end - 8usize, &mut buf)
+    ///     .unwrap_err();
+    ///
+    /// assert!(err.is_unmapped());
+    /// assert_eq!(err.address, end);
+    /// assert_eq!(err.len, 8);
+    /// ```
+    pub fn virt_read_raw_checked_into(
+        &mut self,
+        addr: Address,
+        out: &mut [u8],
+    ) -> std::result::Result<(), AccessError> {
+        let phys_mem = &mut self.phys_mem;
+        let len = out.len() as umem;
+
+        let mut translation = vec![];
+        let mut failed = None;
+
+        self.vat.virt_to_phys_iter(
+            phys_mem,
+            &self.translator,
+            std::iter::once(CTup3(addr, addr, CSliceMut::from(out))),
+            &mut translation.from_extend(),
+            &mut (&mut |(err, CTup3(virt, _, buf)): (
+                Error,
+                CTup3<Address, Address, CSliceMut<u8>>,
+            )| {
+                stats::record(ErrorStat::TranslationFailure, 1);
+                lowest_failure(&mut failed, err.at(virt, buf.len() as umem));
+                true
+            })
+                .into(),
+        );
+
+        MemOps::with_raw(
+            translation.into_iter(),
+            None,
+            Some(
+                &mut (&mut |CTup2(virt, buf): ReadData| {
+                    stats::record(ErrorStat::ConnectorReadFailure, 1);
+                    let err = Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory);
+                    lowest_failure(&mut failed, err.at(virt, buf.len() as umem));
+                    true
+                })
+                    .into(),
+            ),
+            |data| phys_mem.phys_read_raw_iter(data),
+        )
+        .map_err(|err| err.at(addr, len))?;
+
+        failed.map_or(Ok(()), Err)
+    }
+
+    /// Writes `data` to the virtual address `addr` and reports where the write failed.
+    ///
+    /// See [`virt_read_raw_checked_into`](Self::virt_read_raw_checked_into) for details.
+    pub fn virt_write_raw_checked(
+        &mut self,
+        addr: Address,
+        data: &[u8],
+    ) -> std::result::Result<(), AccessError> {
+        let phys_mem = &mut self.phys_mem;
+        let len = data.len() as umem;
+
+        let mut translation = vec![];
+        let mut failed = None;
+
+        self.vat.virt_to_phys_iter(
+            phys_mem,
+            &self.translator,
+            std::iter::once(CTup3(addr, addr, CSliceRef::from(data))),
+            &mut translation.from_extend(),
+            &mut (&mut |(err, CTup3(virt, _, buf)): (
+                Error,
+                CTup3<Address, Address, CSliceRef<u8>>,
+            )| {
+                stats::record(ErrorStat::TranslationFailure, 1);
+                lowest_failure(&mut failed, err.at(virt, buf.len() as umem));
+                true
+            })
+                .into(),
+        );
+
+        MemOps::with_raw(
+            translation.into_iter(),
+            None,
+            Some(
+                &mut (&mut |CTup2(virt, buf): WriteData| {
+                    stats::record(ErrorStat::ConnectorWriteFailure, 1);
+                    let err = Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteMemory);
+                    lowest_failure(&mut failed, err.at(virt, buf.len() as umem));
+                    true
+                })
+                    .into(),
+            ),
+            |data| phys_mem.phys_write_raw_iter(data),
+        )
+        .map_err(|err| err.at(addr, len))?;
+
+        failed.map_or(Ok(()), Err)
+    }
+
     /// Consumes this VirtualDma object, returning the underlying memory and vat objects
     pub fn into_inner(self) -> (T, V) {
         (self.phys_mem, self.vat)
@@ -180,6 +402,13 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
     }
 }
 
+/// Keeps the failure with the lowest address in `failed`.
+fn lowest_failure(failed: &mut Option<AccessError>, err: AccessError) {
+    if failed.map_or(true, |f| err.address < f.address) {
+        *failed = Some(err);
+    }
+}
+
 impl<T, V, D> Clone for VirtualDma<T, V, D>
 where
     T: Clone,
@@ -211,23 +440,88 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
     ) -> Result<()> {
         self.arena.reset();
 
-        let mut translation = BumpVec::with_capacity_in(inp.size_hint().0, &self.arena);
+        let page_size = self.proc_arch.page_size();
         let phys_mem = &mut self.phys_mem;
 
+        // Reads that fit into a single page are grouped by their page, so that every page only
+        // has to be translated once. Reads crossing a page boundary are translated individually.
+        let mut single = BumpVec::with_capacity_in(inp.size_hint().0, &self.arena);
+        let mut spanning = BumpVec::new_in(&self.arena);
+
+        for data in inp {
+            let CTup3(addr, _, buf) = &data;
+            if (addr.to_umem() % page_size as umem) + buf.len() as umem <= page_size as umem {
+                single.push(data);
+            } else {
+                spanning.push(data);
+            }
+        }
+
+        single.sort_unstable_by_key(|CTup3(addr, _, _)| *addr);
+
+        let mut pages = BumpVec::with_capacity_in(single.len(), &self.arena);
+        for CTup3(addr, _, _) in single.iter() {
+            let page = addr.as_page_aligned(page_size);
+            if pages.last() != Some(&page) {
+                pages.push(page);
+            }
+        }
+
+        let mut page_translation = BumpVec::with_capacity_in(pages.len(), &self.arena);
+
         self.vat.virt_to_phys_iter(
             phys_mem,
             &self.translator,
-            inp,
+            pages.iter().map(|&page| CTup3(page, page, 1 as umem)),
+            &mut page_translation.from_extend(),
+            &mut (&mut |_: (Error, CTup3<Address, Address, umem>)| true).into(),
+        );
+
+        page_translation.sort_unstable_by_key(|CTup3(_, page, _)| *page);
+
+        let mut translation = BumpVec::with_capacity_in(single.len() + spanning.len(), &self.arena);
+
+        for CTup3(addr, meta, buf) in single {
+            let page = addr.as_page_aligned(page_size);
+            match page_translation.binary_search_by_key(&page, |CTup3(_, page, _)| *page) {
+                Ok(idx) => {
+                    let phys = page_translation[idx].0;
+                    translation.push(CTup3(
+                        PhysicalAddress::with_page(
+                            phys.address() + (addr - page),
+                            phys.page_type(),
+                            phys.page_size(),
+                        ),
+                        meta,
+                        buf,
+                    ));
+                }
+                Err(_) => {
+                    stats::record(ErrorStat::TranslationFailure, 1);
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta, buf));
+                }
+            }
+        }
+
+        self.vat.virt_to_phys_iter(
+            phys_mem,
+            &self.translator,
+            spanning.into_iter(),
             &mut translation.from_extend(),
             &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                stats::record(ErrorStat::TranslationFailure, 1);
                 opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
             })
                 .into(),
         );
 
-        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
-            phys_mem.phys_read_raw_iter(data)
-        })
+        stats::with_counted_failures(
+            ErrorStat::ConnectorReadFailure,
+            translation.into_iter(),
+            out,
+            out_fail,
+            |data| phys_mem.phys_read_raw_iter(data),
+        )
     }
 
     fn write_raw_iter(
@@ -249,14 +543,19 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
             inp,
             &mut translation.from_extend(),
             &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                stats::record(ErrorStat::TranslationFailure, 1);
                 opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
             })
                 .into(),
         );
 
-        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
-            phys_mem.phys_write_raw_iter(data)
-        })
+        stats::with_counted_failures(
+            ErrorStat::ConnectorWriteFailure,
+            translation.into_iter(),
+            out,
+            out_fail,
+            |data| phys_mem.phys_write_raw_iter(data),
+        )
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
@@ -275,6 +574,29 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
             arch_bits: self.proc_arch.bits(),
         }
     }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        self.arena.reset();
+
+        let mut ranges = BumpVec::new_in(&self.arena);
+
+        // unmapped parts of the range are simply not prefetched
+        self.vat.virt_to_phys_iter(
+            &mut self.phys_mem,
+            &self.translator,
+            std::iter::once(CTup3(addr, addr, len)),
+            &mut (&mut |CTup3(phys, _, len): CTup3<PhysicalAddress, Address, umem>| {
+                ranges.push(CTup2(phys, len));
+                true
+            })
+                .into(),
+            &mut (&mut |_: (Error, CTup3<Address, Address, umem>)| true).into(),
+        );
+
+        if !ranges.is_empty() {
+            self.phys_mem.phys_prefetch(&ranges);
+        }
+    }
 }
 
 impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualTranslate
@@ -1,7 +1,13 @@
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::cache::stats::{self, CacheStat};
 
 mod tlb_cache;
 
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub use shared::{SharedVirtualTranslate, SharedVirtualTranslateBuilder};
+
 use crate::architecture::ArchitectureObj;
 use crate::iter::{PageChunks, SplitAtIndex};
 use crate::mem::virt_translate::VirtualTranslate2;
@@ -119,6 +125,100 @@ impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslate<V, Q> {
     }
 }
 
+#[cfg(feature = "std")]
+impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslate<V, Q> {
+    /// Saves all valid translations of the cache to the file at `path`.
+    ///
+    /// The `identity` has to uniquely identify the state of the target,
+    /// see the [`persist`](crate::types::cache::persist) module for details.
+    ///
+    /// Returns the number of translations written.
+    pub fn save(&mut self, path: impl AsRef<std::path::Path>, identity: &str) -> Result<usize> {
+        use crate::types::cache::persist::{create_cache_file, write_error, CacheFileHeader};
+        use std::io::Write;
+
+        self.tlb.validator.update_validity();
+
+        let count = self.tlb.valid_entries().count();
+
+        let mut file = create_cache_file(path)?;
+        CacheFileHeader::new(*b"TLB\0", identity, TLB_FILE_ENTRY_SIZE, count as u64)
+            .write(&mut file)?;
+
+        for entry in self.tlb.valid_entries() {
+            file.write_all(&(entry.pt_index as u64).to_le_bytes())
+                .and_then(|_| file.write_all(&(entry.virt_addr.to_umem() as u64).to_le_bytes()))
+                .and_then(|_| {
+                    file.write_all(&(entry.phys_addr.address().to_umem() as u64).to_le_bytes())
+                })
+                .and_then(|_| file.write_all(&entry.phys_addr.page_type().bits().to_le_bytes()))
+                .and_then(|_| file.write_all(&(entry.phys_addr.page_size() as u64).to_le_bytes()))
+                .map_err(write_error)?;
+        }
+        file.flush().map_err(write_error)?;
+
+        Ok(count)
+    }
+
+    /// Loads the translations stored in the file at `path` into the cache.
+    ///
+    /// The file is rejected if it was saved from a target with a different `identity`
+    /// or is older than `max_age`.
+    ///
+    /// Returns the number of translations loaded.
+    pub fn load(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        identity: &str,
+        max_age: std::time::Duration,
+    ) -> Result<usize> {
+        use crate::types::cache::persist::{
+            open_cache_file, read_error, read_u64, CacheFileHeader,
+        };
+        use crate::types::{PageType, PhysicalAddress};
+        use std::io::Read;
+        use tlb_cache::TlbEntry;
+
+        let mut file = open_cache_file(path)?;
+        let header = CacheFileHeader::read(&mut file, *b"TLB\0", identity, max_age)?;
+        if header.entry_size != TLB_FILE_ENTRY_SIZE {
+            return Err(Error(ErrorOrigin::TlbCache, ErrorKind::VersionMismatch)
+                .log_error("unexpected tlb cache file entry size"));
+        }
+
+        self.tlb.validator.update_validity();
+
+        let page_size = self.arch.page_size();
+        for _ in 0..header.entry_count {
+            let pt_index = read_u64(&mut file)?;
+            let virt_addr = read_u64(&mut file)?;
+            let phys_addr = read_u64(&mut file)?;
+            let mut page_type = [0u8; 2];
+            file.read_exact(&mut page_type).map_err(read_error)?;
+            let phys_page_size = read_u64(&mut file)?;
+
+            self.tlb.insert_entry(
+                TlbEntry {
+                    pt_index: pt_index as umem,
+                    virt_addr: Address::from(virt_addr as umem),
+                    phys_addr: PhysicalAddress::with_page(
+                        Address::from(phys_addr as umem),
+                        PageType::from_bits_truncate(u16::from_le_bytes(page_type)),
+                        phys_page_size as umem,
+                    ),
+                },
+                page_size,
+            );
+        }
+
+        Ok(header.entry_count as usize)
+    }
+}
+
+/// Size of a single translation in a tlb cache file.
+#[cfg(feature = "std")]
+const TLB_FILE_ENTRY_SIZE: u64 = 8 + 8 + 8 + 2 + 8;
+
 impl<V: VirtualTranslate2> CachedVirtualTranslate<V, DefaultCacheValidator> {
     pub fn builder(vat: V) -> CachedVirtualTranslateBuilder<V, DefaultCacheValidator> {
         CachedVirtualTranslateBuilder::new(vat)
@@ -234,6 +334,9 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
 
         self.hitc += hitc;
         self.misc += misc;
+
+        stats::record(CacheStat::TlbHit, hitc as u64);
+        stats::record(CacheStat::TlbMiss, misc as u64);
     }
 }
 
@@ -381,4 +484,53 @@ mod tests {
             .unwrap();
         assert!(read_into == buffer);
     }
+
+    #[test]
+    fn persisted_translations() {
+        let buffer = standard_buffer(size::kb(64));
+        let mem = DummyMemory::new(buffer.len() + size::mb(2));
+        let (os, dtb, virt_base) = DummyOs::new_and_dtb(mem, buffer.len(), &buffer);
+        let translator = x86::x64::new_translator(dtb);
+        let mut mem = os.into_inner();
+
+        let build = || {
+            CachedVirtualTranslate::builder(DirectTranslate::new())
+                .arch(x86::x64::ARCH)
+                .validator(TimedCacheValidator::new(Duration::from_secs(100)))
+                .build()
+                .unwrap()
+        };
+
+        let mut vat = build();
+        let phys = vat.virt_to_phys(&mut mem, &translator, virt_base).unwrap();
+
+        let path = std::env::temp_dir().join("memflow_tlb_cache_test");
+        assert!(vat.save(&path, "dummy").unwrap() > 0);
+
+        let mut loaded = build();
+        assert_eq!(
+            loaded
+                .load(&path, "other", std::time::Duration::from_secs(60))
+                .unwrap_err()
+                .1,
+            ErrorKind::TargetChanged
+        );
+        assert!(
+            loaded
+                .load(&path, "dummy", std::time::Duration::from_secs(60))
+                .unwrap()
+                > 0
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        // translations are served from the loaded cache
+        mem.phys_write(dtb.into(), vec![0u8; size::kb(4)].as_slice())
+            .unwrap();
+        assert_eq!(
+            loaded
+                .virt_to_phys(&mut mem, &translator, virt_base)
+                .unwrap(),
+            phys
+        );
+    }
 }
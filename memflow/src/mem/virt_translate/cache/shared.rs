@@ -0,0 +1,427 @@
+//! Translation cache shared between threads.
+//!
+//! Cloning a [`CachedVirtualTranslate`](super::CachedVirtualTranslate) copies its cache, so every
+//! worker thread has to translate all addresses of the process again. A
+//! [`SharedVirtualTranslate`] keeps its translations behind an `Arc` instead: every clone is an
+//! independent handle with its own batching state, but all handles serve and fill the same cache.
+//!
+//! Together with [`SharedPhysicalMemory`](crate::mem::SharedPhysicalMemory) this makes a
+//! [`VirtualDma`](crate::mem::VirtualDma) cheaply clonable, so each worker thread can own a
+//! handle to the same process without initializing it again.
+//!
+//! Translations are stored in shards guarded by read-write locks. Threads looking up cached
+//! translations do not block each other. Only successful translations are cached, a cached
+//! translation stays valid for the configured validity.
+//!
+//! # Examples
+//! ```
+//! use std::thread;
+//!
+//! use memflow::architecture::x86::x64;
+//! use memflow::mem::{
+//!     DirectTranslate, MemoryView, SharedPhysicalMemory, SharedVirtualTranslate, VirtualDma,
+//! };
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # use memflow::types::size;
+//! # let mem = DummyMemory::new(size::mb(4));
+//! # let (os, dtb, virt_base) = DummyOs::new_and_dtb(mem, size::mb(1), &[]);
+//! # let mem = os.into_inner();
+//!
+//! let vat = SharedVirtualTranslate::builder(DirectTranslate::new())
+//!     .arch(x64::ARCH)
+//!     .build()
+//!     .unwrap();
+//! let process = VirtualDma::with_vat(
+//!     SharedPhysicalMemory::new(mem),
+//!     x64::ARCH,
+//!     x64::new_translator(dtb),
+//!     vat,
+//! );
+//!
+//! let threads = (0..4usize)
+//!     .map(|i| {
+//!         let mut process = process.clone();
+//!         thread::spawn(move || process.read::<u64>(virt_base + i * 0x1000).unwrap())
+//!     })
+//!     .collect::<Vec<_>>();
+//!
+//! for t in threads {
+//!     t.join().unwrap();
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::prelude::v1::*;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::{PageChunks, SplitAtIndex};
+use crate::mem::virt_translate::VirtualTranslate2;
+use crate::mem::PhysicalMemory;
+use crate::types::cache::stats::{self, CacheStat};
+use crate::types::{umem, Address, PhysicalAddress};
+use cglue::tuple::*;
+
+use super::{VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+
+use cglue::callback::FromExtend;
+
+use bumpalo::{collections::Vec as BumpVec, Bump};
+
+/// Translation table id and virtual page of a cached translation.
+type TlbKey = (umem, Address);
+
+struct SharedEntry {
+    phys_page: PhysicalAddress,
+    cached: Instant,
+}
+
+struct SharedTlb {
+    shards: Box<[RwLock<HashMap<TlbKey, SharedEntry>>]>,
+    shard_capacity: usize,
+    validity: Duration,
+}
+
+impl SharedTlb {
+    fn shard(&self, virt_page: Address, page_size: usize) -> &RwLock<HashMap<TlbKey, SharedEntry>> {
+        let page = virt_page.to_umem() / page_size as umem;
+        &self.shards[(page % self.shards.len() as umem) as usize]
+    }
+
+    fn get(&self, pt_index: umem, virt_page: Address, page_size: usize) -> Option<PhysicalAddress> {
+        let shard = self
+            .shard(virt_page, page_size)
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        shard
+            .get(&(pt_index, virt_page))
+            .filter(|entry| entry.cached.elapsed() < self.validity)
+            .map(|entry| entry.phys_page)
+    }
+
+    fn insert(
+        &self,
+        pt_index: umem,
+        virt_page: Address,
+        phys_page: PhysicalAddress,
+        page_size: usize,
+    ) {
+        let mut shard = self
+            .shard(virt_page, page_size)
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        let key = (pt_index, virt_page);
+
+        if shard.len() >= self.shard_capacity && !shard.contains_key(&key) {
+            let validity = self.validity;
+            shard.retain(|_, entry| entry.cached.elapsed() < validity);
+
+            if shard.len() >= self.shard_capacity {
+                let oldest = shard
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.cached)
+                    .map(|(&key, _)| key);
+                if let Some(oldest) = oldest {
+                    shard.remove(&oldest);
+                }
+            }
+        }
+
+        shard.insert(
+            key,
+            SharedEntry {
+                phys_page,
+                cached: Instant::now(),
+            },
+        );
+    }
+
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(|err| err.into_inner()).len())
+            .sum()
+    }
+
+    fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap_or_else(|err| err.into_inner()).clear();
+        }
+    }
+}
+
+/// Caches virtual address translations in a cache shared by all clones.
+///
+/// Using the `builder` function is the recommended way to create such a cache.
+pub struct SharedVirtualTranslate<V> {
+    vat: V,
+    tlb: Arc<SharedTlb>,
+    arch: ArchitectureObj,
+    arena: Bump,
+    pub hitc: umem,
+    pub misc: umem,
+}
+
+impl<V: VirtualTranslate2> SharedVirtualTranslate<V> {
+    pub fn builder(vat: V) -> SharedVirtualTranslateBuilder<V> {
+        SharedVirtualTranslateBuilder::new(vat)
+    }
+
+    /// Returns the number of translations currently held in the shared cache.
+    pub fn cached_translations(&self) -> usize {
+        self.tlb.len()
+    }
+
+    /// Drops all cached translations for all handles.
+    pub fn invalidate(&self) {
+        self.tlb.clear()
+    }
+
+    /// Returns true if `other` shares its translations with this handle.
+    pub fn shares_cache_with(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.tlb, &other.tlb)
+    }
+}
+
+impl<V: VirtualTranslate2 + Clone> Clone for SharedVirtualTranslate<V> {
+    /// Creates a new handle sharing the cache of this one.
+    ///
+    /// Hit and miss counters of the new handle start at zero.
+    fn clone(&self) -> Self {
+        Self {
+            vat: self.vat.clone(),
+            tlb: self.tlb.clone(),
+            arch: self.arch,
+            arena: Bump::new(),
+            hitc: 0,
+            misc: 0,
+        }
+    }
+}
+
+impl<V: VirtualTranslate2> VirtualTranslate2 for SharedVirtualTranslate<V> {
+    fn virt_to_phys_iter<T, B, D, VI>(
+        &mut self,
+        phys_mem: &mut T,
+        translator: &D,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+    ) where
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        D: VirtualTranslate3,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    {
+        self.arena.reset();
+
+        let tlb = &*self.tlb;
+        let vat = &mut self.vat;
+        let mut uncached_out = BumpVec::new_in(&self.arena);
+        let mut uncached_out_fail = BumpVec::new_in(&self.arena);
+
+        let mut hitc = 0;
+        let mut misc = 0;
+
+        let page_size = self.arch.page_size();
+        let mut addrs = addrs
+            .flat_map(|CTup3(addr, meta_addr, buf)| (meta_addr, buf).page_chunks(addr, page_size))
+            .filter_map(|(addr, (meta_addr, buf))| {
+                let virt_page = addr.as_page_aligned(page_size);
+                let pt_index = translator.translation_table_id(addr);
+                match tlb.get(pt_index, virt_page, page_size) {
+                    Some(phys_page) => {
+                        hitc += 1;
+                        let phys_addr = PhysicalAddress::with_page(
+                            phys_page.address() + (addr - virt_page),
+                            phys_page.page_type(),
+                            page_size as umem,
+                        );
+                        out.call(CTup3(phys_addr, meta_addr, buf));
+                        None
+                    }
+                    None => {
+                        misc += 1;
+                        Some(CTup3(addr, meta_addr, (addr, buf)))
+                    }
+                }
+            })
+            .peekable();
+
+        if addrs.peek().is_some() {
+            vat.virt_to_phys_iter(
+                phys_mem,
+                translator,
+                addrs,
+                &mut uncached_out.from_extend(),
+                &mut uncached_out_fail.from_extend(),
+            );
+        }
+
+        out.extend(
+            uncached_out
+                .into_iter()
+                .map(|CTup3(paddr, meta_addr, (addr, buf))| {
+                    let virt_page = addr.as_page_aligned(page_size);
+                    let phys_page = PhysicalAddress::with_page(
+                        paddr.address() - (addr - virt_page),
+                        paddr.page_type(),
+                        page_size as umem,
+                    );
+                    tlb.insert(
+                        translator.translation_table_id(addr),
+                        virt_page,
+                        phys_page,
+                        page_size,
+                    );
+                    CTup3(paddr, meta_addr, buf)
+                }),
+        );
+
+        out_fail.extend(
+            uncached_out_fail
+                .into_iter()
+                .map(|(err, CTup3(vaddr, meta_addr, (_, buf)))| {
+                    (err, CTup3(vaddr, meta_addr, buf))
+                }),
+        );
+
+        self.hitc += hitc;
+        self.misc += misc;
+
+        stats::record(CacheStat::TlbHit, hitc as u64);
+        stats::record(CacheStat::TlbMiss, misc as u64);
+    }
+}
+
+pub struct SharedVirtualTranslateBuilder<V> {
+    vat: V,
+    entries: usize,
+    shards: usize,
+    validity: Duration,
+    arch: Option<ArchitectureObj>,
+}
+
+impl<V: VirtualTranslate2> SharedVirtualTranslateBuilder<V> {
+    fn new(vat: V) -> Self {
+        Self {
+            vat,
+            entries: 2048,
+            shards: 16,
+            validity: Duration::from_secs(1),
+            arch: None,
+        }
+    }
+
+    pub fn build(self) -> Result<SharedVirtualTranslate<V>> {
+        let arch = self.arch.ok_or_else(|| {
+            Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
+                .log_error("arch must be initialized")
+        })?;
+
+        Ok(SharedVirtualTranslate {
+            vat: self.vat,
+            tlb: Arc::new(SharedTlb {
+                shards: (0..self.shards)
+                    .map(|_| RwLock::new(HashMap::new()))
+                    .collect(),
+                shard_capacity: std::cmp::max(self.entries / self.shards, 1),
+                validity: self.validity,
+            }),
+            arch,
+            arena: Bump::new(),
+            hitc: 0,
+            misc: 0,
+        })
+    }
+
+    /// Sets the total number of translations held in the cache.
+    pub fn entries(mut self, entries: usize) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Sets the number of independently locked shards of the cache.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = std::cmp::max(shards, 1);
+        self
+    }
+
+    /// Sets how long a cached translation stays valid.
+    pub fn validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    pub fn arch(mut self, arch: impl Into<ArchitectureObj>) -> Self {
+        self.arch = Some(arch.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::error::PartialResultExt;
+    use crate::mem::{DirectTranslate, MemoryView, VirtualDma};
+    use crate::types::size;
+
+    #[test]
+    fn shared_between_clones() {
+        let buffer = (0..size::kb(64)).map(|i| i as u8).collect::<Vec<_>>();
+        let mem = DummyMemory::new(buffer.len() + size::mb(2));
+        let (os, dtb, virt_base) = DummyOs::new_and_dtb(mem, buffer.len(), &buffer);
+        let mut mem = os.into_inner();
+
+        let vat = SharedVirtualTranslate::builder(DirectTranslate::new())
+            .arch(x86::x64::ARCH)
+            .validity(Duration::from_secs(100))
+            .build()
+            .unwrap();
+        let mut vmem = VirtualDma::with_vat(
+            mem.clone(),
+            x86::x64::ARCH,
+            x86::x64::new_translator(dtb),
+            vat,
+        );
+
+        let mut read_into = vec![0u8; buffer.len()];
+        vmem.read_raw_into(virt_base, &mut read_into)
+            .data()
+            .unwrap();
+        assert_eq!(read_into, buffer);
+        assert_eq!(vmem.vat().cached_translations(), 16);
+
+        // destroy the page tables, clones are served from the shared cache
+        mem.phys_write(dtb.into(), vec![0u8; size::kb(4)].as_slice())
+            .unwrap();
+
+        let threads = (0..4)
+            .map(|_| {
+                let mut vmem = vmem.clone();
+                let len = buffer.len();
+                std::thread::spawn(move || {
+                    let mut read_into = vec![0u8; len];
+                    vmem.read_raw_into(virt_base, &mut read_into)
+                        .data()
+                        .unwrap();
+                    assert_eq!(vmem.vat().misc, 0);
+                    read_into
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            assert_eq!(t.join().unwrap(), buffer);
+        }
+
+        vmem.vat().invalidate();
+        assert!(vmem
+            .clone()
+            .read_raw_into(virt_base, &mut read_into)
+            .is_err());
+    }
+}
@@ -58,6 +58,38 @@ impl<T: CacheValidator> TlbCache<T> {
         ((page_addr.to_umem() / page_size as umem) % (self.entries.len() as umem)) as usize
     }
 
+    /// Returns all valid translations currently held in the cache.
+    ///
+    /// Cached failed translations are omitted.
+    pub fn valid_entries(&self) -> impl Iterator<Item = TlbEntry> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(idx, entry)| {
+                entry.pt_index != !0
+                    && entry.phys_page.is_valid()
+                    && entry.phys_page.has_page()
+                    && self.validator.is_slot_valid(*idx)
+            })
+            .map(|(_, entry)| TlbEntry {
+                pt_index: entry.pt_index,
+                virt_addr: entry.virt_page,
+                phys_addr: entry.phys_page,
+            })
+    }
+
+    /// Inserts a translation into the cache and validates it.
+    pub fn insert_entry(&mut self, entry: TlbEntry, page_size: usize) {
+        let virt_page = entry.virt_addr.as_page_aligned(page_size);
+        let idx = self.get_cache_index(virt_page, page_size);
+        self.entries[idx] = CachedEntry {
+            pt_index: entry.pt_index,
+            virt_page,
+            phys_page: entry.phys_addr,
+        };
+        self.validator.validate_slot(idx);
+    }
+
     #[inline]
     pub fn is_read_too_long(&self, arch: ArchitectureObj, size: umem) -> bool {
         size / arch.page_size() as umem > self.entries.len() as umem
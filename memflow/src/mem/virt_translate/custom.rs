@@ -0,0 +1,450 @@
+/*!
+Extension point for custom address translation schemes.
+
+By default every architecture comes with a single hard-wired translator (for example
+[`X86VirtualTranslate`] for all x86 variants). Some targets require a different approach though,
+for example when translation is mediated by a hypervisor, or when the target uses a software
+managed TLB instead of hardware page tables.
+
+A [`TranslationScheme`] describes such a mechanism. It is wrapped into a [`CustomTranslate`]
+which implements [`VirtualTranslate3`] and can therefore be used anywhere a regular translator
+can, including [`VirtualDma`](crate::mem::VirtualDma) on top of a
+[`CachedVirtualTranslate`](crate::mem::CachedVirtualTranslate).
+
+With the `std` feature enabled schemes can also be registered globally for a given
+[`ArchitectureIdent`]. OS layers pick their translator through [`translator_for`], which prefers
+a registered scheme over the architecture default.
+
+# Global registry
+
+The registry is shared by the whole process. A registered scheme applies to every OS instance
+that creates translators for the architecture afterwards, regardless of the connector or plugin
+it was created from. Translators that have already been created keep their scheme, registering or
+unregistering a scheme only affects translators created later on.
+
+Since tests run in parallel within one process, a test registering a scheme affects all other
+tests creating translators for the same architecture while it is registered. Tests should
+therefore register schemes for architectures no other test relies on.
+
+# Examples
+
+```
+use memflow::architecture::{x86::x64, ArchitectureObj};
+use memflow::dummy::DummyMemory;
+use memflow::error::Result;
+use memflow::mem::virt_translate::custom::{CustomTranslate, TranslationScheme};
+use memflow::mem::{MemoryView, PhysicalMemory, VirtualDma};
+use memflow::types::{size, Address, PageType, PhysicalAddress};
+
+/// Maps every virtual address 1:1 on top of the translation base.
+struct LinearScheme;
+
+impl TranslationScheme for LinearScheme {
+    fn translate(
+        &self,
+        _mem: &mut dyn PhysicalMemory,
+        base: Address,
+        addr: Address,
+    ) -> Result<PhysicalAddress> {
+        Ok(PhysicalAddress::with_page(
+            base + addr.to_umem(),
+            PageType::READ_ONLY,
+            size::kb(4) as _,
+        ))
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        x64::ARCH
+    }
+}
+
+static SCHEME: LinearScheme = LinearScheme;
+
+let mut mem = DummyMemory::new(size::mb(2));
+mem.phys_write(Address::from(0x10_0000).into(), &0xdeadu32).unwrap();
+
+let translator = CustomTranslate::new(&SCHEME, Address::from(0x10_0000));
+let mut virt_mem = VirtualDma::new(mem, x64::ARCH, translator);
+
+assert_eq!(virt_mem.read::<u32>(Address::null()).unwrap(), 0xdead);
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{PageWalk, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+
+use crate::architecture::arm::{aarch64, ArmVirtualTranslate};
+use crate::architecture::x86::{self, X86VirtualTranslate};
+use crate::architecture::{ArchitectureIdent, ArchitectureObj};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::{PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps};
+use crate::types::{size, umem, Address, PhysicalAddress};
+
+use cglue::tuple::*;
+
+/// A user provided virtual address translation mechanism.
+///
+/// The scheme translates one address at a time. The returned [`PhysicalAddress`] should carry
+/// page information (see [`PhysicalAddress::with_page`]), it is used to determine how many bytes
+/// following `addr` are mapped contiguously. Without it the architecture page size is assumed.
+pub trait TranslationScheme: Send + Sync {
+    /// Translates `addr` in the address space described by `base`.
+    fn translate(
+        &self,
+        mem: &mut dyn PhysicalMemory,
+        base: Address,
+        addr: Address,
+    ) -> Result<PhysicalAddress>;
+
+    /// Returns the architecture this scheme translates for.
+    fn arch(&self) -> ArchitectureObj;
+
+    /// Returns an identifier of the translation table used for `addr`.
+    ///
+    /// This is used by the caching layers to tell address spaces apart.
+    fn translation_table_id(&self, base: Address, _addr: Address) -> umem {
+        base.to_umem()
+    }
+}
+
+/// Translator backed by a [`TranslationScheme`].
+#[derive(Clone, Copy)]
+pub struct CustomTranslate {
+    scheme: &'static dyn TranslationScheme,
+    base: Address,
+}
+
+impl CustomTranslate {
+    /// Creates a new translator for the address space at `base`.
+    ///
+    /// The meaning of `base` is up to the scheme, usually it is the process' dtb.
+    pub fn new(scheme: &'static dyn TranslationScheme, base: Address) -> Self {
+        Self { scheme, base }
+    }
+
+    /// Returns the translation base of this translator.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+}
+
+impl VirtualTranslate3 for CustomTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        _tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        let mut mem = PhysForward(mem);
+        let default_page_size = self.scheme.arch().page_size() as umem;
+
+        for CTup3(mut addr, mut meta_addr, buf) in addrs {
+            let mut rest = Some(buf);
+
+            while let Some(buf) = rest.take() {
+                match self.scheme.translate(&mut mem, self.base, addr) {
+                    Ok(paddr) => {
+                        let page_size = if paddr.has_page() {
+                            paddr.page_size()
+                        } else {
+                            default_page_size
+                        };
+                        let len = page_size - (addr.to_umem() % page_size);
+                        let (head, tail) = buf.split_at(len);
+
+                        if let Some(head) = head {
+                            if !out.call(CTup3(paddr, meta_addr, head)) {
+                                return;
+                            }
+                        }
+
+                        addr += len;
+                        meta_addr += len;
+                        rest = tail;
+                    }
+                    Err(err) => {
+                        if !out_fail.call((err, CTup3(addr, meta_addr, buf))) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn translation_table_id(&self, address: Address) -> umem {
+        self.scheme.translation_table_id(self.base, address)
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.scheme.arch()
+    }
+}
+
+/// Translator selected for an architecture by [`translator_for`].
+#[derive(Clone, Copy)]
+pub enum ArchTranslate {
+    X86(X86VirtualTranslate),
+    Arm(ArmVirtualTranslate),
+    Custom(CustomTranslate),
+}
+
+impl VirtualTranslate3 for ArchTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        match self {
+            ArchTranslate::X86(t) => t.virt_to_phys_iter(mem, addrs, out, out_fail, tmp_buf),
+            ArchTranslate::Arm(t) => t.virt_to_phys_iter(mem, addrs, out, out_fail, tmp_buf),
+            ArchTranslate::Custom(t) => t.virt_to_phys_iter(mem, addrs, out, out_fail, tmp_buf),
+        }
+    }
+
+    fn translation_table_id(&self, address: Address) -> umem {
+        match self {
+            ArchTranslate::X86(t) => t.translation_table_id(address),
+            ArchTranslate::Arm(t) => t.translation_table_id(address),
+            ArchTranslate::Custom(t) => t.translation_table_id(address),
+        }
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        match self {
+            ArchTranslate::X86(t) => t.arch(),
+            ArchTranslate::Arm(t) => t.arch(),
+            ArchTranslate::Custom(t) => t.arch(),
+        }
+    }
+
+    fn page_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<PageWalk> {
+        match self {
+            ArchTranslate::X86(t) => t.page_walk(mem, addr),
+            ArchTranslate::Arm(t) => t.page_walk(mem, addr),
+            ArchTranslate::Custom(t) => t.page_walk(mem, addr),
+        }
+    }
+}
+
+impl From<X86VirtualTranslate> for ArchTranslate {
+    fn from(t: X86VirtualTranslate) -> Self {
+        ArchTranslate::X86(t)
+    }
+}
+
+impl From<ArmVirtualTranslate> for ArchTranslate {
+    fn from(t: ArmVirtualTranslate) -> Self {
+        ArchTranslate::Arm(t)
+    }
+}
+
+impl From<CustomTranslate> for ArchTranslate {
+    fn from(t: CustomTranslate) -> Self {
+        ArchTranslate::Custom(t)
+    }
+}
+
+#[cfg(feature = "std")]
+static SCHEMES: std::sync::RwLock<Vec<(ArchitectureIdent, &'static dyn TranslationScheme)>> =
+    std::sync::RwLock::new(Vec::new());
+
+/// Registers a scheme that will be used instead of the default translator of `arch`.
+///
+/// A previously registered scheme for the same architecture is replaced. The registration is
+/// process-global, see the [module documentation](self#global-registry).
+#[cfg(feature = "std")]
+pub fn register_translation_scheme(
+    arch: ArchitectureIdent,
+    scheme: &'static dyn TranslationScheme,
+) {
+    let mut schemes = SCHEMES.write().unwrap();
+    schemes.retain(|(a, _)| *a != arch);
+    schemes.push((arch, scheme));
+}
+
+/// Removes the scheme registered for `arch`, restoring the default translator.
+///
+/// Returns `true` if a scheme was registered.
+#[cfg(feature = "std")]
+pub fn unregister_translation_scheme(arch: ArchitectureIdent) -> bool {
+    let mut schemes = SCHEMES.write().unwrap();
+    let len = schemes.len();
+    schemes.retain(|(a, _)| *a != arch);
+    schemes.len() != len
+}
+
+#[cfg(feature = "std")]
+fn registered_scheme(arch: ArchitectureIdent) -> Option<&'static dyn TranslationScheme> {
+    SCHEMES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(a, _)| *a == arch)
+        .map(|(_, s)| *s)
+}
+
+#[cfg(not(feature = "std"))]
+fn registered_scheme(_arch: ArchitectureIdent) -> Option<&'static dyn TranslationScheme> {
+    None
+}
+
+/// Creates the translator for the address space at `dtb` of the given architecture.
+///
+/// A scheme registered through [`register_translation_scheme`] takes precedence over the built-in
+/// translator. Architectures without a built-in translator return
+/// `ErrorKind::InvalidArchitecture` unless a scheme is registered for them.
+pub fn translator_for(arch: ArchitectureIdent, dtb: Address) -> Result<ArchTranslate> {
+    if let Some(scheme) = registered_scheme(arch) {
+        return Ok(CustomTranslate::new(scheme, dtb).into());
+    }
+
+    match arch {
+        ArchitectureIdent::X86(32, _) | ArchitectureIdent::X86(64, false) => {
+            x86::new_translator(dtb, arch.into()).map(ArchTranslate::from)
+        }
+        ArchitectureIdent::AArch64(page_size) if page_size == size::kb(4) => {
+            Ok(aarch64::new_translator(dtb, dtb + size::kb(2)).into())
+        }
+        _ => Err(Error(ErrorOrigin::Mmu, ErrorKind::InvalidArchitecture)),
+    }
+}
+
+/// Forwards a possibly unsized physical memory object so it can be passed on as a trait object.
+struct PhysForward<'a, T: ?Sized>(&'a mut T);
+
+impl<'a, T: PhysicalMemory + ?Sized> PhysicalMemory for PhysForward<'a, T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.0.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.0.phys_write_raw_iter(data)
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.0.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{CachedVirtualTranslate, DirectTranslate, MemoryView, VirtualDma};
+    use crate::types::PageType;
+
+    const MAPPED_SIZE: umem = size::kb(64) as umem;
+
+    struct OffsetScheme;
+
+    impl TranslationScheme for OffsetScheme {
+        fn translate(
+            &self,
+            _mem: &mut dyn PhysicalMemory,
+            base: Address,
+            addr: Address,
+        ) -> Result<PhysicalAddress> {
+            if addr.to_umem() >= MAPPED_SIZE {
+                return Err(Error(ErrorOrigin::VirtualTranslate, ErrorKind::OutOfBounds));
+            }
+            Ok(PhysicalAddress::with_page(
+                base + addr.to_umem(),
+                PageType::WRITEABLE,
+                size::kb(4) as umem,
+            ))
+        }
+
+        fn arch(&self) -> ArchitectureObj {
+            x64::ARCH
+        }
+    }
+
+    static SCHEME: OffsetScheme = OffsetScheme;
+
+    fn setup_mem(base: Address) -> (DummyMemory, Vec<u8>) {
+        let mut mem = DummyMemory::new(size::mb(2));
+        let buf = (0..MAPPED_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+        mem.phys_write(base.into(), buf.as_slice()).unwrap();
+        (mem, buf)
+    }
+
+    #[test]
+    fn custom_read_across_pages() {
+        let base = Address::from(0x4_0000);
+        let (mem, buf) = setup_mem(base);
+        let mut virt_mem = VirtualDma::new(mem, x64::ARCH, CustomTranslate::new(&SCHEME, base));
+
+        let mut out = vec![0u8; 0x3000];
+        virt_mem
+            .read_raw_into(Address::from(0xff0), &mut out)
+            .unwrap();
+        assert_eq!(out, buf[0xff0..0x3ff0]);
+
+        let mut out = [0u8; 0x20];
+        assert!(virt_mem
+            .read_raw_into(Address::from(MAPPED_SIZE - 0x10), &mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn custom_with_cache() {
+        let base = Address::from(0x8_0000);
+        let (mem, buf) = setup_mem(base);
+        let vat = CachedVirtualTranslate::builder(DirectTranslate::new())
+            .arch(x64::ARCH)
+            .build()
+            .unwrap();
+        let mut virt_mem =
+            VirtualDma::with_vat(mem, x64::ARCH, CustomTranslate::new(&SCHEME, base), vat);
+
+        for _ in 0..2 {
+            let mut out = vec![0u8; 0x1800];
+            virt_mem
+                .read_raw_into(Address::from(0x2400), &mut out)
+                .unwrap();
+            assert_eq!(out, buf[0x2400..0x3c00]);
+        }
+    }
+
+    #[test]
+    fn registered_scheme_is_preferred() {
+        // the registry is global, use an architecture no other test registers a scheme for
+        let arch = ArchitectureIdent::Unknown(0x1238);
+        assert!(translator_for(arch, Address::null()).is_err());
+
+        register_translation_scheme(arch, &SCHEME);
+        let translator = translator_for(arch, Address::from(0x1000)).unwrap();
+        assert!(
+            matches!(translator, ArchTranslate::Custom(t) if t.base() == Address::from(0x1000))
+        );
+
+        assert!(unregister_translation_scheme(arch));
+        assert!(translator_for(arch, Address::null()).is_err());
+
+        assert!(matches!(
+            translator_for(ArchitectureIdent::X86(64, false), Address::null()),
+            Ok(ArchTranslate::X86(_))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+/*!
+Diagnostics for virtual address translation.
+
+Page table walks that fail or take unexpectedly long are hard to debug on unusual targets,
+since the batched translation does not report how far it got. A [`PageWalk`] records every page
+table entry read while translating a single address, its [`Display`](core::fmt::Display)
+implementation prints the full chain:
+
+```text
+page walk of 0x7ff6a1230000:
+  level 4: pte @ 0x1aa000 = 0x8000000012345867
+  level 3: pte @ 0x12345ff8 = 0x0
+  -> failed: mmu: out of memory range
+```
+
+[`TranslationDiagnostics`] wraps a [`VirtualTranslate2`] and logs the page walks of all
+translations that fail or exceed a time threshold. It can be inserted in place of any other
+`VirtualTranslate2`, for instance between a cache and the `DirectTranslate`, so that only the
+uncached translations are measured.
+*/
+
+use std::prelude::v1::*;
+
+use super::{VirtualTranslate2, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+use crate::error::{Error, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address, PhysicalAddress};
+use cglue::tuple::*;
+
+use core::fmt;
+
+/// A single page table entry read during a page walk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageWalkEntry {
+    /// The page mapping level of the entry, with 1 being the level of the smallest pages
+    pub level: usize,
+    /// Physical address of the entry
+    pub pte_address: Address,
+    /// Raw value of the entry
+    pub entry: Address,
+}
+
+/// The page table entries read while translating a single virtual address.
+#[derive(Clone, Debug)]
+pub struct PageWalk {
+    /// The virtual address that was translated
+    pub address: Address,
+    /// All entries read, starting at the top level page table
+    pub entries: Vec<PageWalkEntry>,
+    /// The result of the translation
+    pub result: Result<PhysicalAddress>,
+}
+
+impl fmt::Display for PageWalk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "page walk of {:#x}:", self.address.to_umem())?;
+        for entry in self.entries.iter() {
+            writeln!(
+                f,
+                "  level {}: pte @ {:#x} = {:#x}",
+                entry.level,
+                entry.pte_address.to_umem(),
+                entry.entry.to_umem()
+            )?;
+        }
+        match &self.result {
+            Ok(phys) => write!(
+                f,
+                "  -> {:#x} ({:#x} byte page)",
+                phys.address().to_umem(),
+                phys.page_size()
+            ),
+            Err(err) => write!(f, "  -> failed: {}", err),
+        }
+    }
+}
+
+/// Default number of page walks logged per translation batch.
+pub const DEFAULT_MAX_DUMPS: usize = 8;
+
+/// Logs the page walks of failed and slow translations.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::mem::{DirectTranslate, TranslationDiagnostics, VirtualTranslate2};
+/// # use memflow::architecture::x86::x64;
+/// # use memflow::dummy::{DummyMemory, DummyOs};
+/// # use memflow::types::size;
+/// # let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+/// # let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+/// # let mut mem = os.into_inner();
+/// # let translator = x64::new_translator(dtb);
+///
+/// let mut vat = TranslationDiagnostics::new(DirectTranslate::new())
+///     .threshold(std::time::Duration::from_millis(10))
+///     .level(log::Level::Warn);
+///
+/// // the page walk of this translation is logged
+/// assert!(vat.virt_to_phys(&mut mem, &translator, virt_base - 1).is_err());
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct TranslationDiagnostics<V> {
+    vat: V,
+    threshold: Option<std::time::Duration>,
+    log_failures: bool,
+    level: log::Level,
+    max_dumps: usize,
+}
+
+#[cfg(feature = "std")]
+impl<V: VirtualTranslate2> TranslationDiagnostics<V> {
+    /// Wraps `vat`, logging the page walks of all failed translations.
+    pub fn new(vat: V) -> Self {
+        Self {
+            vat,
+            threshold: None,
+            log_failures: true,
+            level: log::Level::Info,
+            max_dumps: DEFAULT_MAX_DUMPS,
+        }
+    }
+
+    /// Also logs the page walk of translation batches which take longer than `threshold`.
+    ///
+    /// Since translations are performed in batches, the walk of the first address of the batch
+    /// is logged.
+    pub fn threshold(mut self, threshold: std::time::Duration) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Sets whether failed translations are logged.
+    pub fn log_failures(mut self, log_failures: bool) -> Self {
+        self.log_failures = log_failures;
+        self
+    }
+
+    /// Sets the log level of the page walk dumps.
+    pub fn level(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets the maximum number of page walks logged per translation batch.
+    pub fn max_dumps(mut self, max_dumps: usize) -> Self {
+        self.max_dumps = max_dumps;
+        self
+    }
+
+    /// Returns the wrapped translator.
+    pub fn into_inner(self) -> V {
+        self.vat
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: VirtualTranslate2> VirtualTranslate2 for TranslationDiagnostics<V> {
+    fn virt_to_phys_iter<T, B, D, VI>(
+        &mut self,
+        phys_mem: &mut T,
+        translator: &D,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+    ) where
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        D: VirtualTranslate3,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    {
+        if !log::log_enabled!(self.level) {
+            self.vat
+                .virt_to_phys_iter(phys_mem, translator, addrs, out, out_fail);
+            return;
+        }
+
+        let mut first = None;
+        let mut count: umem = 0;
+        let addrs = addrs.inspect(|CTup3(addr, _, _)| {
+            first.get_or_insert(*addr);
+            count += 1;
+        });
+
+        let max_dumps = if self.log_failures { self.max_dumps } else { 0 };
+        let mut failed = Vec::new();
+        let fail = &mut |(err, data): (Error, CTup3<Address, Address, B>)| {
+            if failed.len() < max_dumps {
+                failed.push(data.0);
+            }
+            out_fail.call((err, data))
+        };
+
+        let start = std::time::Instant::now();
+        self.vat
+            .virt_to_phys_iter(phys_mem, translator, addrs, out, &mut fail.into());
+        let elapsed = start.elapsed();
+
+        for addr in failed {
+            log_walk(self.level, translator.page_walk(phys_mem, addr), "failed");
+        }
+
+        if let (Some(threshold), Some(addr)) = (self.threshold, first) {
+            if elapsed > threshold {
+                log::log!(
+                    self.level,
+                    "translation of {} ranges took {:?} (threshold {:?})",
+                    count,
+                    elapsed,
+                    threshold
+                );
+                log_walk(self.level, translator.page_walk(phys_mem, addr), "slow");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn log_walk(level: log::Level, walk: Result<PageWalk>, reason: &str) {
+    match walk {
+        Ok(walk) => log::log!(level, "{} translation, {}", reason, walk),
+        Err(err) => log::log!(
+            level,
+            "{} translation, unable to walk page tables: {}",
+            reason,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn walk_mapped() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let mut mem = os.into_inner();
+        let translator = x64::new_translator(dtb);
+
+        let walk = translator
+            .page_walk(&mut mem, virt_base + 0x10usize)
+            .unwrap();
+        let phys = translator
+            .virt_to_phys(&mut mem, virt_base + 0x10usize)
+            .unwrap();
+        assert_eq!(walk.result.unwrap().address(), phys.address());
+        assert_eq!(walk.entries.first().unwrap().level, 4);
+        assert!(walk.entries.len() >= 2);
+    }
+
+    #[test]
+    fn walk_unmapped() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let mut mem = os.into_inner();
+        let translator = x64::new_translator(dtb);
+
+        let walk = translator.page_walk(&mut mem, virt_base - 1).unwrap();
+        assert!(walk.result.is_err());
+        assert!(!walk.entries.is_empty());
+        assert!(walk.to_string().contains("failed"));
+    }
+}
@@ -41,6 +41,13 @@ pub struct ArchMmuDef {
     pub writeable_bit: fn(Address, bool) -> bool,
     /// index of a bit in PTE defining if the page is non-executable.
     pub nx_bit: fn(Address, bool) -> bool,
+    /// index of a bit in PTE defining if the page is only accessible from supervisor mode.
+    ///
+    /// Besides the PTE and the flag of the previous levels, the function receives the step of the
+    /// page walk the PTE was read at, since not every level of the page tables contains the bit.
+    ///
+    /// `None` if the privilege level of pages is not tracked for the architecture.
+    pub supervisor_bit: Option<fn(Address, bool, usize) -> bool>,
     /// function for checking a bit in PTE to see if the PTE points to a large page.
     pub large_page_bit: fn(Address) -> bool,
 }
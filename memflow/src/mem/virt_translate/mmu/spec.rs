@@ -6,7 +6,7 @@ use crate::mem::{PhysicalMemory, PhysicalReadData};
 use crate::types::{umem, Address, PageType, PhysicalAddress, UMEM_BITS};
 use cglue::tuple::*;
 
-use super::super::{VtopFailureCallback, VtopOutputCallback};
+use super::super::{PageWalk, PageWalkEntry, VtopFailureCallback, VtopOutputCallback};
 use super::translate_data::{
     FlagsType, TranslateData, TranslateDataVec, TranslateVec, TranslationChunk,
 };
@@ -16,6 +16,7 @@ use super::MmuTranslationBase;
 pub(crate) use fixed_slice_vec::FixedSliceVec as MVec;
 
 use std::convert::TryInto;
+use std::prelude::v1::*;
 
 #[cfg(feature = "trace_mmu")]
 macro_rules! vtop_trace {
@@ -283,19 +284,26 @@ impl ArchMmuSpec {
             self.pte_addr_mask(pte_addr, step) | self.virt_addr_to_page_offset(virt_addr, step),
         );
 
-        PhysicalAddress::with_page(
-            phys_addr,
-            PageType::default()
-                .write((self.def.writeable_bit)(
-                    pte_addr,
-                    prev_flags.contains(FlagsType::WRITEABLE),
-                ))
-                .noexec((self.def.nx_bit)(
-                    pte_addr,
-                    prev_flags.contains(FlagsType::NX),
-                )),
-            self.page_size_step(step),
-        )
+        let mut page_type = PageType::default()
+            .write((self.def.writeable_bit)(
+                pte_addr,
+                prev_flags.contains(FlagsType::WRITEABLE),
+            ))
+            .noexec((self.def.nx_bit)(
+                pte_addr,
+                prev_flags.contains(FlagsType::NX),
+            ));
+
+        if let Some(supervisor_bit) = self.def.supervisor_bit {
+            // the entry mapping a page of `step` is read one step earlier
+            page_type = page_type.user(!supervisor_bit(
+                pte_addr,
+                prev_flags.contains(FlagsType::SUPERVISOR),
+                step - 1,
+            ));
+        }
+
+        PhysicalAddress::with_page(phys_addr, page_type, self.page_size_step(step))
     }
 
     /// Check if the current page table entry is valid
@@ -324,6 +332,85 @@ impl ArchMmuSpec {
             || ((self.def.large_page_bit)(pte_addr) && self.valid_final_page_steps[step])
     }
 
+    /// Walks the page tables for a single virtual address, recording every entry on the way.
+    ///
+    /// This is a slow, scalar version of the translation in `virt_to_phys_iter` which is meant
+    /// for diagnostics. It does not apply the heuristic against page tables pointing to
+    /// themselves, thus it may succeed for addresses the batched translation rejects.
+    pub(crate) fn page_walk<T, D>(&self, mem: &mut T, dtb: D, virt_addr: Address) -> PageWalk
+    where
+        T: PhysicalMemory + ?Sized,
+        D: MmuTranslationBase,
+    {
+        let mut walk = PageWalk {
+            address: virt_addr,
+            entries: Vec::new(),
+            result: Err(Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange)),
+        };
+
+        let splits = self.def.virtual_address_splits.len();
+        let mut pt_addr = dtb.get_pt_by_virt_addr(virt_addr);
+        let mut flags = FlagsType::NONE;
+
+        for step in 0..splits - 1 {
+            let pte_address = self.vtop_step(pt_addr, virt_addr, step);
+
+            let mut buf = [0u8; 8];
+            let buf = &mut buf[..self.def.pte_size];
+            let read = {
+                let mut read = Some(CTup3(
+                    PhysicalAddress::with_page(
+                        pte_address,
+                        PageType::PAGE_TABLE,
+                        self.pt_leaf_size(step) as umem,
+                    ),
+                    Address::NULL,
+                    (&mut *buf).into(),
+                ))
+                .into_iter();
+                mem.phys_read_raw_iter((&mut read).into())
+            };
+            if let Err(err) = read {
+                walk.result = Err(err);
+                return walk;
+            }
+
+            let entry = match (self.def.endianess, self.def.pte_size) {
+                (Endianess::LittleEndian, 8) => {
+                    Address::from(u64::from_le_bytes((&*buf).try_into().unwrap()))
+                }
+                (Endianess::LittleEndian, _) => {
+                    Address::from(u32::from_le_bytes((&*buf).try_into().unwrap()))
+                }
+                (Endianess::BigEndian, 8) => {
+                    Address::from(u64::from_be_bytes((&*buf).try_into().unwrap()))
+                }
+                (Endianess::BigEndian, _) => {
+                    Address::from(u32::from_be_bytes((&*buf).try_into().unwrap()))
+                }
+            };
+
+            walk.entries.push(PageWalkEntry {
+                level: splits - 1 - step,
+                pte_address,
+                entry,
+            });
+
+            flags = flags.inherit(&self.def, entry, step);
+
+            if !self.check_entry(entry, step + 2) {
+                return walk;
+            } else if self.is_final_mapping(entry, step + 1) {
+                walk.result = Ok(self.get_phys_page(entry, virt_addr, step + 1, flags));
+                return walk;
+            }
+
+            pt_addr = entry;
+        }
+
+        walk
+    }
+
     /// This function will do a virtual to physical memory translation for the `ArchMmuSpec` in
     /// `MmuTranslationBase` scope, over multiple elements.
     pub(crate) fn virt_to_phys_iter<T, B, D, VI>(
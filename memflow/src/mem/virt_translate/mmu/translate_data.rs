@@ -120,11 +120,13 @@ bitflags! {
     #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
     #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
     pub struct FlagsType: u8 {
-        const NONE = 0b00;
+        const NONE = 0b000;
         // Maps MMUDef's writeable_bit
-        const WRITEABLE = 0b01;
+        const WRITEABLE = 0b001;
         // Maps MMUDef's nx_bit
-        const NX = 0b10;
+        const NX = 0b010;
+        // Maps MMUDef's supervisor_bit
+        const SUPERVISOR = 0b100;
     }
 }
 
@@ -157,19 +159,34 @@ impl FlagsType {
             self
         }
     }
+
+    pub fn supervisor(mut self, flag: bool) -> Self {
+        self &= !(FlagsType::SUPERVISOR);
+        if flag {
+            self | FlagsType::SUPERVISOR
+        } else {
+            self
+        }
+    }
+
+    /// Returns the flags after reading the page table entry `pte` at the page walk step `step`,
+    /// taking inheritance of the previous levels into account.
+    pub fn inherit(self, mmu_def: &ArchMmuDef, pte: Address, step: usize) -> Self {
+        FlagsType::NONE
+            .writeable((mmu_def.writeable_bit)(
+                pte,
+                self.contains(FlagsType::WRITEABLE),
+            ))
+            .nx((mmu_def.nx_bit)(pte, self.contains(FlagsType::NX)))
+            .supervisor(mmu_def.supervisor_bit.map_or(false, |supervisor_bit| {
+                supervisor_bit(pte, self.contains(FlagsType::SUPERVISOR), step)
+            }))
+    }
 }
 
 impl TranslationChunk<Address> {
     pub fn update_flags(&mut self, mmu_def: &ArchMmuDef) {
-        self.prev_flags = FlagsType::NONE
-            .writeable((mmu_def.writeable_bit)(
-                self.pt_addr,
-                self.prev_flags.contains(FlagsType::WRITEABLE),
-            ))
-            .nx((mmu_def.nx_bit)(
-                self.pt_addr,
-                self.prev_flags.contains(FlagsType::NX),
-            ));
+        self.prev_flags = self.prev_flags.inherit(mmu_def, self.pt_addr, self.step);
     }
 }
 
@@ -83,13 +83,23 @@ pub mod cache;
 
 pub use cache::*;
 
+pub mod custom;
+#[cfg(feature = "std")]
+pub use custom::{register_translation_scheme, unregister_translation_scheme};
+pub use custom::{translator_for, ArchTranslate, CustomTranslate, TranslationScheme};
+
+pub mod diagnostics;
+#[cfg(feature = "std")]
+pub use diagnostics::TranslationDiagnostics;
+pub use diagnostics::{PageWalk, PageWalkEntry};
+
 #[cfg(test)]
 mod tests;
 
 use crate::error::{Result, *};
 
 use crate::mem::PhysicalMemory;
-use crate::types::{imem, umem, Address, Page, PhysicalAddress};
+use crate::types::{imem, umem, Address, Page, PageType, PhysicalAddress};
 
 /// Translates virtual addresses into physical ones.
 ///
@@ -362,7 +372,10 @@ pub trait VirtualTranslate: Send {
     /// fn vtop(mem: &mut impl VirtualTranslate, addr: Address) {
     ///     let page = mem.virt_page_info(addr).unwrap();
     ///     assert_eq!(page.page_size, mem::kb(4));
-    ///     assert_eq!(page.page_type, PageType::WRITEABLE);
+    ///     assert_eq!(
+    ///         page.page_type,
+    ///         PageType::WRITEABLE | PageType::SUPERVISOR | PageType::EXECUTABLE
+    ///     );
     /// }
     /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
     /// # let addr = proc.info().address;
@@ -413,6 +426,86 @@ pub trait VirtualTranslate: Send {
         out
     }
 
+    /// Retrieve a vector of physical pages within given range, only including pages whose type
+    /// matches `mask`.
+    ///
+    /// Pages are filtered using [`PageType::matches_mask`] before gaps are removed, so that
+    /// scanners can easily restrict themselves to a subset of the address space, e.g. executable
+    /// user mode pages.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::{DummyMemory, DummyOs};
+    /// # use memflow::architecture::x86::x64;
+    /// # let dummy_mem = DummyMemory::new(size::mb(16));
+    /// # let mut dummy_os = DummyOs::new(dummy_mem);
+    /// # let (dtb, virt_base) = dummy_os.alloc_dtb(size::mb(2), &[]);
+    /// # let translator = x64::new_translator(dtb);
+    /// # let arch = x64::ARCH;
+    /// # let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), arch, translator);
+    /// let user_code = PageType::READ_ONLY
+    ///     | PageType::WRITEABLE
+    ///     | PageType::USER
+    ///     | PageType::EXECUTABLE;
+    ///
+    /// let out = virt_mem.virt_page_map_range_mask_vec(
+    ///     0,
+    ///     Address::null(),
+    ///     Address::invalid(),
+    ///     user_code,
+    /// );
+    ///
+    /// // the dummy os only maps supervisor pages
+    /// assert!(out.is_empty());
+    ///
+    /// let kernel_code = PageType::READ_ONLY
+    ///     | PageType::WRITEABLE
+    ///     | PageType::SUPERVISOR
+    ///     | PageType::EXECUTABLE;
+    ///
+    /// let out = virt_mem.virt_page_map_range_mask_vec(
+    ///     0,
+    ///     Address::null(),
+    ///     Address::invalid(),
+    ///     kernel_code,
+    /// );
+    /// assert!(!out.is_empty());
+    /// ```
+    #[skip_func]
+    fn virt_page_map_range_mask_vec(
+        &mut self,
+        gap_size: imem,
+        start: Address,
+        end: Address,
+        mask: PageType,
+    ) -> Vec<MemoryRange> {
+        let mut out = vec![];
+
+        {
+            let mut gap_remover = GapRemover::new((&mut out).into(), gap_size, start, end);
+
+            self.virt_to_phys_range(
+                start,
+                end,
+                (&mut |VirtualTranslation {
+                           in_virtual,
+                           size,
+                           out_physical,
+                       }| {
+                    if out_physical.page_type.matches_mask(mask) {
+                        gap_remover.push_range(CTup3(in_virtual, size, out_physical.page_type));
+                    }
+                    true
+                })
+                    .into(),
+            );
+        }
+
+        out
+    }
+
     // page map helpers
 
     /// Get virtual translation map over entire address space.
@@ -945,6 +1038,20 @@ pub trait VirtualTranslate3: Clone + Copy + Send {
     fn translation_table_id(&self, address: Address) -> umem;
 
     fn arch(&self) -> ArchitectureObj;
+
+    /// Walks the page tables for a single virtual address, recording every entry read.
+    ///
+    /// This is meant for diagnosing failed translations, see the
+    /// [`diagnostics`](crate::mem::virt_translate::diagnostics) module for details.
+    /// Translators that do not use page tables return `ErrorKind::NotSupported`.
+    fn page_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<PageWalk> {
+        let _ = (mem, addr);
+        Err(Error(ErrorOrigin::Mmu, ErrorKind::NotSupported))
+    }
 }
 
 pub type VtopOutputCallback<'a, B> = OpaqueCallback<'a, CTup3<PhysicalAddress, Address, B>>;
@@ -1,11 +1,12 @@
-use crate::architecture::x86::x64;
+use crate::architecture::x86::{x32, x32_pae, x64};
 use crate::cglue::ForwardMut;
+use crate::dataview::Pod;
 use crate::dummy::{DummyMemory, DummyOs};
 use crate::mem::{
     DirectTranslate, MemoryView, PhysicalMemory, VirtualDma, VirtualTranslate, VirtualTranslate2,
     VirtualTranslate3,
 };
-use crate::types::{mem, size, PageType};
+use crate::types::{mem, size, umem, Address, PageType};
 use cglue::tuple::*;
 
 #[test]
@@ -78,6 +79,119 @@ fn test_x86_flag_inheritance() {
     assert!(paddr.page_type.contains(PageType::NOEXEC));
 }
 
+fn write_entries<T: Pod>(mem: &mut DummyMemory, entries: &[(u64, T)]) {
+    let mut phys_view = mem.phys_view();
+    for (addr, entry) in entries {
+        phys_view.write(Address::from(*addr), entry).unwrap();
+    }
+}
+
+#[test]
+fn test_x64_page_access() {
+    let mut mem = DummyMemory::new(size::mb(1));
+    write_entries(
+        &mut mem,
+        &[
+            (0x1000, 0x2007u64),
+            (0x2000, 0x3007),
+            (0x3000, 0x4007),
+            (0x4000, 0x5007),
+            // supervisor only and not executable
+            (0x4008, 0x6003 | 1 << 63),
+        ],
+    );
+    let translator = x64::new_translator(Address::from(0x1000u64));
+
+    let paddr = translator.virt_to_phys(&mut mem, Address::NULL).unwrap();
+    assert_eq!(paddr.address(), Address::from(0x5000u64));
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::USER | PageType::EXECUTABLE
+    );
+
+    let paddr = translator
+        .virt_to_phys(&mut mem, Address::from(0x1000u64))
+        .unwrap();
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::SUPERVISOR | PageType::NOEXEC
+    );
+}
+
+#[test]
+fn test_x32_page_access() {
+    let mut mem = DummyMemory::new(size::mb(1));
+    write_entries(
+        &mut mem,
+        &[
+            (0x1000, 0x2007u32),
+            (0x2000, 0x5007),
+            // supervisor only
+            (0x2004, 0x6003),
+        ],
+    );
+    let translator = x32::new_translator(Address::from(0x1000u64));
+
+    let paddr = translator.virt_to_phys(&mut mem, Address::NULL).unwrap();
+    assert_eq!(paddr.address(), Address::from(0x5000u64));
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::USER | PageType::EXECUTABLE
+    );
+
+    let paddr = translator
+        .virt_to_phys(&mut mem, Address::from(0x1000u64))
+        .unwrap();
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::SUPERVISOR | PageType::EXECUTABLE
+    );
+}
+
+#[test]
+fn test_x32_pae_page_access() {
+    let mut mem = DummyMemory::new(size::mb(1));
+    write_entries(
+        &mut mem,
+        &[
+            // PDPTEs do not have a user bit
+            (0x1000, 0x2001u64),
+            (0x2000, 0x3007),
+            // the second 2 MiB are supervisor only
+            (0x2008, 0x4003),
+            (0x3000, 0x5007),
+            // supervisor only and not executable
+            (0x3008, 0x6003 | 1 << 63),
+            (0x4000, 0x7007),
+        ],
+    );
+    let translator = x32_pae::new_translator(Address::from(0x1000u64));
+
+    let paddr = translator.virt_to_phys(&mut mem, Address::NULL).unwrap();
+    assert_eq!(paddr.address(), Address::from(0x5000u64));
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::USER | PageType::EXECUTABLE
+    );
+
+    let paddr = translator
+        .virt_to_phys(&mut mem, Address::from(0x1000u64))
+        .unwrap();
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::SUPERVISOR | PageType::NOEXEC
+    );
+
+    let paddr = translator
+        .virt_to_phys(&mut mem, Address::from(0x20_0000u64))
+        .unwrap();
+    assert_eq!(paddr.address(), Address::from(0x7000u64));
+    assert_eq!(
+        paddr.page_type,
+        PageType::WRITEABLE | PageType::SUPERVISOR | PageType::EXECUTABLE
+    );
+}
+
 #[test]
 fn test_virt_page_map() {
     let dummy_mem = DummyMemory::new(size::mb(16));
@@ -331,3 +445,108 @@ fn test_virt_write_big_shifted() {
     assert_eq!(buf.to_vec().len(), input.len());
     assert_eq!(buf.to_vec(), input);
 }
+
+#[test]
+fn test_virt_read_batched_same_page() {
+    let dummy_mem = DummyMemory::new(size::mb(2));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let mut buf = vec![0u8; 0x1000 * 4];
+    for (i, item) in buf.iter_mut().enumerate() {
+        *item = (i / 8) as u8;
+    }
+    let (dtb, virt_base) = dummy_os.alloc_dtb(buf.len(), &buf);
+    let translator = x64::new_translator(dtb);
+    let arch = x64::ARCH;
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), arch, translator);
+
+    // many small reads in few pages, plus reads crossing page boundaries, in random order
+    let offsets = [0x2008, 0x10, 0xff8, 0x2ffc, 0x18, 0x1ffe, 0x2000, 0x0];
+    let mut out = vec![[0u8; 8]; offsets.len()];
+
+    {
+        let mut batcher = virt_mem.batcher();
+        for (&off, out) in offsets.iter().zip(out.iter_mut()) {
+            batcher.read_into(virt_base + off as usize, out);
+        }
+    }
+
+    for (&off, out) in offsets.iter().zip(out.iter()) {
+        assert_eq!(&out[..], &buf[off..off + 8]);
+    }
+}
+
+#[test]
+fn test_virt_read_raw_info() {
+    let dummy_mem = DummyMemory::new(size::mb(2));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let mut buf = vec![0u8; 0x1000 * 4];
+    for (i, item) in buf.iter_mut().enumerate() {
+        *item = i as u8;
+    }
+    let (dtb, virt_base) = dummy_os.alloc_dtb(buf.len(), &buf);
+    let translator = x64::new_translator(dtb);
+    let arch = x64::ARCH;
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), arch, translator);
+
+    let (out, info) = virt_mem
+        .virt_read_raw_info(virt_base + 0x800, 0x2000)
+        .unwrap();
+    assert_eq!(out, buf[0x800..0x2800].to_vec());
+
+    // every chunk has to match a separate translation
+    assert_eq!(info.iter().map(|t| t.size).sum::<umem>(), 0x2000);
+    assert_eq!(info[0].in_virtual, virt_base + 0x800);
+    for t in info.iter() {
+        let phys = virt_mem.virt_to_phys(t.in_virtual).unwrap();
+        assert_eq!(phys.address(), t.out_physical.address());
+        assert_eq!(phys.page_type(), t.out_physical.page_type());
+    }
+
+    // reads past the allocated region only partially succeed
+    let info = virt_mem.virt_read_raw_info(virt_base + 0x3800, 0x1000);
+    assert!(matches!(
+        info,
+        Err(crate::error::PartialError::PartialVirtualRead((_, ref info))) if info.len() == 1
+    ));
+}
+
+#[test]
+fn test_read_raw_list_chunks() {
+    let dummy_mem = DummyMemory::new(size::mb(2));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let buf = vec![0xffu8; 0x1000 * 4];
+    let (dtb, virt_base) = dummy_os.alloc_dtb(buf.len(), &buf);
+    let translator = x64::new_translator(dtb);
+    let arch = x64::ARCH;
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), arch, translator);
+
+    let mut full = [0u8; 0x10];
+    let mut partial = [0u8; 0x10];
+    let mut failed = [0u8; 0x10];
+
+    let results = virt_mem
+        .read_raw_list_chunks(&mut [
+            CTup2(virt_base, full.as_mut().into()),
+            CTup2(virt_base + 0x3ff8, partial.as_mut().into()),
+            CTup2(virt_base + 0x5000, failed.as_mut().into()),
+        ])
+        .unwrap_err();
+
+    let results = match results {
+        crate::error::PartialError::PartialVirtualRead(results) => results,
+        _ => panic!("expected a partial read"),
+    };
+
+    assert_eq!(results[0], Ok(()));
+    assert_eq!(full, [0xff; 0x10]);
+
+    let err = results[1].unwrap_err();
+    assert_eq!(err.kind(), crate::error::ErrorKind::PartialData);
+    assert_eq!(err.address, virt_base + 0x4000);
+    assert_eq!(err.len, 8);
+    assert_eq!(partial[..8], [0xff; 8]);
+
+    let err = results[2].unwrap_err();
+    assert_eq!(err.kind(), crate::error::ErrorKind::UnableToReadMemory);
+    assert_eq!(err.len, 0x10);
+}
@@ -0,0 +1,387 @@
+//! User supplied labels for addresses and address ranges.
+//!
+//! While exploring a target, users give names to the things they find: `player_base`, `config
+//! struct`, `suspicious shellcode`. An [`AnnotationStore`] keeps these labels, answers which
+//! label applies to an address and can be persisted to a sidecar file next to a dump or a
+//! project, so every tool working on the same target shares the same names.
+//!
+//! Formatters consult the store through [`AnnotationStore::format_address`], which prefers a
+//! user label over the module and export based name of [`format_address`].
+//!
+//! The sidecar file is a plain text file with one annotation per line, so it can be edited by
+//! hand and kept in version control:
+//!
+//! ```text
+//! # memflow annotations v1
+//! 0x7ff6a0001000	0x40	player_base	updated every frame
+//! 0x1c0000	0x1000	suspicious shellcode
+//! ```
+//!
+//! Address, size, label and an optional comment are separated by tabs. Tabs, newlines and
+//! backslashes in labels and comments are escaped with a backslash.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::os::annotations::AnnotationStore;
+//! use memflow::types::Address;
+//!
+//! let mut store = AnnotationStore::new();
+//! store.label_range(Address::from(0x1000), 0x40, "player_base");
+//! store.label(Address::from(0x1010), "health");
+//!
+//! assert_eq!(store.describe(Address::from(0x1010)), "health");
+//! assert_eq!(store.describe(Address::from(0x1020)), "player_base+0x20");
+//!
+//! let restored = AnnotationStore::from_text(&store.to_text()).unwrap();
+//! assert_eq!(restored.annotations(), store.annotations());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::fmt::Write;
+
+use super::module::{format_address, ExportInfo, ModuleInfo};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{umem, Address};
+
+/// First line of a serialized annotation store.
+const ANNOTATIONS_HEADER: &str = "# memflow annotations v1";
+
+/// Label attached to an address range.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Annotation {
+    /// Start of the annotated range
+    pub address: Address,
+    /// Size of the annotated range in bytes, 1 for single addresses
+    pub size: umem,
+    /// Short name of the range
+    pub label: String,
+    /// Free form comment, may be empty
+    pub comment: String,
+}
+
+impl Annotation {
+    /// Creates an annotation of the range without a comment.
+    pub fn new(address: Address, size: umem, label: &str) -> Self {
+        Self {
+            address,
+            size: std::cmp::max(size, 1),
+            label: label.to_string(),
+            comment: String::new(),
+        }
+    }
+
+    /// Sets the comment of the annotation.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = comment.to_string();
+        self
+    }
+
+    /// Returns true if `address` is part of the annotated range.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.address && address.to_umem() - self.address.to_umem() < self.size
+    }
+}
+
+/// Collection of annotations sorted by address.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AnnotationStore {
+    annotations: Vec<Annotation>,
+}
+
+impl AnnotationStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an annotation.
+    ///
+    /// An existing annotation of the same range is replaced.
+    pub fn insert(&mut self, annotation: Annotation) {
+        match self
+            .annotations
+            .iter_mut()
+            .find(|a| a.address == annotation.address && a.size == annotation.size)
+        {
+            Some(existing) => *existing = annotation,
+            None => {
+                let idx = self
+                    .annotations
+                    .partition_point(|a| a.address <= annotation.address);
+                self.annotations.insert(idx, annotation);
+            }
+        }
+    }
+
+    /// Labels a single address.
+    pub fn label(&mut self, address: Address, label: &str) {
+        self.insert(Annotation::new(address, 1, label));
+    }
+
+    /// Labels the range of `size` bytes starting at `address`.
+    pub fn label_range(&mut self, address: Address, size: umem, label: &str) {
+        self.insert(Annotation::new(address, size, label));
+    }
+
+    /// Removes all annotations starting at `address` and returns them.
+    pub fn remove(&mut self, address: Address) -> Vec<Annotation> {
+        let (removed, kept) = std::mem::take(&mut self.annotations)
+            .into_iter()
+            .partition(|a| a.address == address);
+        self.annotations = kept;
+        removed
+    }
+
+    /// Removes all annotations.
+    pub fn clear(&mut self) {
+        self.annotations.clear();
+    }
+
+    /// Returns all annotations sorted by address.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Returns all annotations containing `address`.
+    pub fn annotations_at(&self, address: Address) -> impl Iterator<Item = &Annotation> + '_ {
+        self.annotations
+            .iter()
+            .take_while(move |a| a.address <= address)
+            .filter(move |a| a.contains(address))
+    }
+
+    /// Returns all annotations overlapping the range from `start` to `end`.
+    pub fn annotations_in(
+        &self,
+        start: Address,
+        end: Address,
+    ) -> impl Iterator<Item = &Annotation> + '_ {
+        self.annotations
+            .iter()
+            .take_while(move |a| a.address < end)
+            .filter(move |a| a.address.to_umem() + a.size > start.to_umem())
+    }
+
+    /// Returns the most specific annotation containing `address`.
+    ///
+    /// If annotations are nested, e.g. a field inside of a labelled struct, the smallest one is
+    /// returned.
+    pub fn lookup(&self, address: Address) -> Option<&Annotation> {
+        self.annotations_at(address).min_by_key(|a| a.size)
+    }
+
+    /// Formats `address` as `label+0x10`, or as a plain address if it is not annotated.
+    pub fn describe(&self, address: Address) -> String {
+        match self.lookup(address) {
+            Some(a) if a.address == address => a.label.clone(),
+            Some(a) => format!("{}+{:#x}", a.label, address - a.address),
+            None => format!("{:#x}", address.to_umem()),
+        }
+    }
+
+    /// Formats `address` like [`format_address`], but prefers user labels.
+    pub fn format_address(
+        &self,
+        address: Address,
+        module: Option<&ModuleInfo>,
+        exports: &[ExportInfo],
+    ) -> String {
+        if self.lookup(address).is_some() {
+            self.describe(address)
+        } else {
+            format_address(address, module, exports)
+        }
+    }
+
+    /// Returns the number of annotations.
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    /// Returns true if the store contains no annotations.
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    /// Serializes the store into the sidecar file format.
+    pub fn to_text(&self) -> String {
+        let mut ret = String::new();
+        writeln!(ret, "{}", ANNOTATIONS_HEADER).ok();
+        for a in self.annotations.iter() {
+            write!(
+                ret,
+                "{:#x}\t{:#x}\t{}",
+                a.address.to_umem(),
+                a.size,
+                escape(&a.label)
+            )
+            .ok();
+            if !a.comment.is_empty() {
+                write!(ret, "\t{}", escape(&a.comment)).ok();
+            }
+            ret.push('\n');
+        }
+        ret
+    }
+
+    /// Parses a store from the sidecar file format.
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut ret = Self::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                Error(ErrorOrigin::Other, ErrorKind::Encoding)
+                    .log_warn(format_args!("invalid annotation in line {}", idx + 1))
+            };
+
+            let mut fields = line.split('\t');
+            let address = fields.next().and_then(parse_hex).ok_or_else(invalid)?;
+            let size = fields.next().and_then(parse_hex).ok_or_else(invalid)?;
+            let label = fields.next().map(unescape).ok_or_else(invalid)?;
+            let comment = fields.next().map(unescape).unwrap_or_default();
+
+            ret.insert(
+                Annotation::new(Address::from(address), size as umem, &label).comment(&comment),
+            );
+        }
+
+        Ok(ret)
+    }
+
+    /// Writes the store to the sidecar file at `path`.
+    #[cfg(feature = "std")]
+    pub fn save(&self, path: impl AsRef<::std::path::Path>) -> Result<()> {
+        ::std::fs::write(path, self.to_text())
+            .map_err(|err| Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile).log_error(err))
+    }
+
+    /// Reads the store from the sidecar file at `path`.
+    #[cfg(feature = "std")]
+    pub fn load(path: impl AsRef<::std::path::Path>) -> Result<Self> {
+        let text = ::std::fs::read_to_string(path)
+            .map_err(|err| Error(ErrorOrigin::Other, ErrorKind::UnableToReadFile).log_error(err))?;
+        Self::from_text(&text)
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(s, 16).ok()
+}
+
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => ret.push_str("\\\\"),
+            '\t' => ret.push_str("\\t"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+fn unescape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => ret.push('\t'),
+            Some('n') => ret.push('\n'),
+            Some('r') => ret.push('\r'),
+            Some(c) => ret.push(c),
+            None => ret.push('\\'),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::ArchitectureIdent;
+
+    #[test]
+    fn nested_labels() {
+        let mut store = AnnotationStore::new();
+        store.label_range(Address::from(0x1000), 0x100, "config");
+        store.label_range(Address::from(0x1040), 0x10, "key");
+        store.label_range(Address::from(0x1000), 0x100, "config struct");
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.describe(Address::from(0x1044)), "key+0x4");
+        assert_eq!(store.describe(Address::from(0x1080)), "config struct+0x80");
+        assert_eq!(store.describe(Address::from(0x2000)), "0x2000");
+        assert_eq!(
+            store
+                .annotations_in(Address::from(0x1050), Address::from(0x1060))
+                .count(),
+            1
+        );
+
+        let module = ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: Address::from(0x1000),
+            size: 0x1000,
+            name: "game.exe".into(),
+            path: "".into(),
+            arch: ArchitectureIdent::X86(64, false),
+        };
+        assert_eq!(
+            store.format_address(Address::from(0x1040), Some(&module), &[]),
+            "key"
+        );
+        assert_eq!(
+            store.format_address(Address::from(0x1800), Some(&module), &[]),
+            "game.exe+0x800"
+        );
+
+        assert_eq!(store.remove(Address::from(0x1040)).len(), 1);
+        assert_eq!(store.describe(Address::from(0x1044)), "config struct+0x44");
+    }
+
+    #[test]
+    fn sidecar_format() {
+        let mut store = AnnotationStore::new();
+        store.insert(
+            Annotation::new(Address::from(0x7ff6a0001000u64), 0x40, "player\tbase")
+                .comment("updated\nevery frame \\o/"),
+        );
+        store.label(Address::from(0x1c0000), "shellcode");
+
+        let text = store.to_text();
+        assert_eq!(
+            text,
+            "# memflow annotations v1\n\
+             0x1c0000\t0x1\tshellcode\n\
+             0x7ff6a0001000\t0x40\tplayer\\tbase\tupdated\\nevery frame \\\\o/\n"
+        );
+
+        let restored = AnnotationStore::from_text(&text).unwrap();
+        assert_eq!(restored.annotations(), store.annotations());
+
+        assert!(AnnotationStore::from_text("0x1000\tlabel").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Consistent reads of structures that are modified concurrently.
+//!
+//! Enumerating processes or modules of a live system reads a list and then a number of
+//! structures it references. The kernel keeps modifying those structures in the meantime, so
+//! the result may mix the state from before and after a modification, for example by reporting
+//! a process that has already been unlinked or by missing a module that was loaded during the
+//! enumeration.
+//!
+//! Enumerations can guard against this by reading validation fields (list sequence counters,
+//! object headers, or the list of entries itself) before and after the bulk reads. If the fields
+//! changed, the enumeration overlapped with a modification and is repeated.
+//! [`with_consistency`] implements this loop for arbitrary validation state and
+//! [`read_consistent`] for validation fields located in memory.
+//!
+//! The enumeration functions of [`Os`](super::Os) and [`Process`](super::Process) accept the
+//! desired behaviour through [`EnumerationOptions`], e.g.
+//! [`process_info_list_with`](super::Os::process_info_list_with). OS layers providing additional
+//! enumerations (handle tables, threads, ...) can build on the same helpers.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::consistency::{Consistency, EnumerationOptions};
+//!
+//! fn processes(os: &mut impl Os) -> Result<Vec<ProcessInfo>> {
+//!     os.process_info_list_with(EnumerationOptions {
+//!         consistency: Consistency::Retry(3),
+//!     })
+//! }
+//! # use memflow::dummy::{DummyMemory, DummyOs};
+//! # let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+//! # os.alloc_process(size::kb(8), &[]);
+//! # assert_eq!(processes(&mut os).unwrap().len(), 1);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Describes how enumerations deal with concurrent modifications.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Consistency {
+    /// Read everything once without validation.
+    BestEffort,
+    /// Validate the result and repeat the enumeration up to the given number of times if it
+    /// overlapped with a modification.
+    ///
+    /// If no attempt is consistent, the result of the last attempt is returned.
+    Retry(usize),
+}
+
+impl Default for Consistency {
+    fn default() -> Self {
+        Consistency::BestEffort
+    }
+}
+
+/// Options for enumerating processes, modules and other OS structures.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct EnumerationOptions {
+    /// How concurrent modifications are dealt with
+    pub consistency: Consistency,
+}
+
+impl EnumerationOptions {
+    /// Options that validate the result and retry up to `retries` times.
+    pub const fn retry(retries: usize) -> Self {
+        Self {
+            consistency: Consistency::Retry(retries),
+        }
+    }
+
+    /// Options that read everything once without validation.
+    pub const fn best_effort() -> Self {
+        Self {
+            consistency: Consistency::BestEffort,
+        }
+    }
+}
+
+/// The result of a validated read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ConsistentRead<T> {
+    /// The value of the last attempt
+    pub value: T,
+    /// True if the validation state did not change while the value was read
+    ///
+    /// Always false for [`Consistency::BestEffort`], as the state is never validated.
+    pub consistent: bool,
+    /// Number of times the value was read
+    pub attempts: usize,
+}
+
+/// Reads a value and validates it against the state returned by `snapshot`.
+///
+/// `snapshot` is taken before and after every call to `read`. The state taken before the read
+/// is passed to `read`, which allows reusing it (e.g. the list of entries to read). If the
+/// state changed the read is repeated according to `consistency`, reusing the latest state as
+/// the state before the next attempt. Errors of `snapshot` and `read` are returned immediately.
+pub fn with_consistency<C: ?Sized, S: PartialEq, T>(
+    ctx: &mut C,
+    consistency: Consistency,
+    mut snapshot: impl FnMut(&mut C) -> Result<S>,
+    mut read: impl FnMut(&mut C, &S) -> Result<T>,
+) -> Result<ConsistentRead<T>> {
+    let mut before = snapshot(ctx)?;
+
+    let retries = match consistency {
+        Consistency::BestEffort => {
+            return Ok(ConsistentRead {
+                value: read(ctx, &before)?,
+                consistent: false,
+                attempts: 1,
+            })
+        }
+        Consistency::Retry(retries) => retries,
+    };
+
+    let mut attempt = 1;
+    loop {
+        let value = read(ctx, &before)?;
+        let after = snapshot(ctx)?;
+
+        if after == before || attempt > retries {
+            if after != before {
+                log::debug!("state changed during all {} read attempts", attempt);
+            }
+
+            return Ok(ConsistentRead {
+                value,
+                consistent: after == before,
+                attempts: attempt,
+            });
+        }
+
+        before = after;
+        attempt += 1;
+    }
+}
+
+/// Reads a value and validates it against the validation fields at `fields`.
+///
+/// Every field is given as its address and length in bytes. The fields are read before and after
+/// `read` and compared bytewise, see [`with_consistency`] for details. The validation fields
+/// have to be readable.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+/// use memflow::os::consistency::{read_consistent, Consistency};
+///
+/// // reads an array guarded by a sequence counter that is incremented on every modification
+/// fn read_table(mem: &mut impl MemoryView, seq: Address, table: Address) -> Result<[u64; 4]> {
+///     let read = read_consistent(mem, Consistency::Retry(5), &[(seq, 4)], |mem| {
+///         Ok(mem.read::<[u64; 4]>(table).data_part()?)
+///     })?;
+///     Ok(read.value)
+/// }
+/// # use memflow::dummy::DummyOs;
+/// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+/// # let addr = proc.info().address;
+/// # proc.write(addr + 8usize, &[1u64, 2, 3, 4]).unwrap();
+/// # assert_eq!(read_table(&mut proc, addr, addr + 8usize).unwrap(), [1, 2, 3, 4]);
+/// ```
+pub fn read_consistent<M: MemoryView, T>(
+    mem: &mut M,
+    consistency: Consistency,
+    fields: &[(Address, usize)],
+    mut read: impl FnMut(&mut M) -> Result<T>,
+) -> Result<ConsistentRead<T>> {
+    with_consistency(
+        mem,
+        consistency,
+        |mem| {
+            let mut buf = vec![0u8; fields.iter().map(|(_, len)| len).sum()];
+            let mut offset = 0;
+            for &(addr, len) in fields {
+                mem.read_raw_into(addr, &mut buf[offset..offset + len])
+                    .data_part()?;
+                offset += len;
+            }
+            Ok(buf)
+        },
+        |mem, _| read(mem),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::PhysicalMemory;
+    use crate::os::{Os, Process};
+    use crate::types::size;
+
+    #[test]
+    fn retry_on_change() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        view.write(Address::from(0x1000u64), &1u32).unwrap();
+
+        // a concurrent writer increments the sequence counter during the first two reads
+        let mut writes = 2;
+        let read = read_consistent(
+            &mut view,
+            Consistency::Retry(3),
+            &[(Address::from(0x1000u64), 4)],
+            |mem| {
+                let seq = mem.read::<u32>(Address::from(0x1000u64)).data_part()?;
+                if writes > 0 {
+                    writes -= 1;
+                    mem.write(Address::from(0x1000u64), &(seq + 1))
+                        .data_part()?;
+                }
+                Ok(seq)
+            },
+        )
+        .unwrap();
+
+        assert!(read.consistent);
+        assert_eq!(read.attempts, 3);
+        assert_eq!(read.value, 3);
+    }
+
+    #[test]
+    fn retries_exhausted() {
+        let mut counter = 0;
+        let read = with_consistency(
+            &mut counter,
+            Consistency::Retry(2),
+            |counter| {
+                *counter += 1;
+                Ok(*counter)
+            },
+            |_, &before| Ok(before),
+        )
+        .unwrap();
+
+        assert!(!read.consistent);
+        assert_eq!(read.attempts, 3);
+        assert_eq!(read.value, 3);
+
+        let read = with_consistency(
+            &mut counter,
+            Consistency::BestEffort,
+            |counter| Ok(*counter),
+            |_, &before| Ok(before),
+        )
+        .unwrap();
+        assert_eq!(read.attempts, 1);
+    }
+
+    #[test]
+    fn enumerations() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(8)));
+        let pid = os.alloc_process_with_module(size::mb(1), &[]);
+        os.alloc_process(size::kb(8), &[]);
+
+        let options = EnumerationOptions::retry(2);
+        let processes = os.process_info_list_with(options).unwrap();
+        assert_eq!(processes.len(), 2);
+        assert_eq!(
+            os.module_list_with(options).unwrap().len(),
+            os.module_list().unwrap().len()
+        );
+
+        let mut proc = os.process_by_pid(pid).unwrap();
+        assert_eq!(
+            proc.module_list_with(None, options).unwrap().len(),
+            proc.module_list().unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Describes optional control over the execution state of processes
+//!
+//! OS layers implement these primitives by modifying kernel structures of the target from the
+//! outside (e.g. the suspend count and thread state), without any cooperation of the target. This
+//! is inherently racy and can destabilize or crash the target, which is why this module is only
+//! available with the `unsafe_writes` feature and requires a writeable connector.
+//!
+//! The main use case is freezing a process before dumping it, so its memory does not change
+//! while it is being read.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::control::{suspended, ProcessControl};
+//!
+//! fn dump(proc: &mut (impl Process + MemoryView + ProcessControl)) -> Result<Vec<u8>> {
+//!     let base = proc.info().address;
+//!
+//!     // the process is resumed again even if reading fails
+//!     suspended(proc, |proc| proc.read_raw(base, 0x1000).data_part())?
+//! }
+//! ```
+
+use crate::cglue::*;
+use crate::error::Error;
+use crate::prelude::v1::Result;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+#[cglue_forward]
+pub trait ProcessControl: Send {
+    /// Suspends all threads of the process
+    ///
+    /// Suspensions are counted, every call has to be matched by a call to [`resume`](Self::resume).
+    fn suspend(&mut self) -> Result<()>;
+
+    /// Resumes all threads of the process
+    fn resume(&mut self) -> Result<()>;
+
+    /// Suspends a single thread of the process
+    fn suspend_thread(&mut self, tid: u32) -> Result<()>;
+
+    /// Resumes a single thread of the process
+    fn resume_thread(&mut self, tid: u32) -> Result<()>;
+}
+
+/// Error returned by [`suspended`].
+#[derive(Debug)]
+pub enum SuspendedError<R> {
+    /// The process could not be suspended, the function was not called
+    Suspend(Error),
+    /// The function was called, but the process could not be resumed afterwards
+    ///
+    /// Contains the return value of the function.
+    Resume(R, Error),
+}
+
+impl<R> From<SuspendedError<R>> for Error {
+    /// Converts the error, discarding the return value of the function.
+    fn from(err: SuspendedError<R>) -> Self {
+        match err {
+            SuspendedError::Suspend(err) | SuspendedError::Resume(_, err) => err,
+        }
+    }
+}
+
+/// Suspends the process for the duration of `func`.
+///
+/// The process is resumed after `func` returns. If resuming the process fails, the return value
+/// of `func` is handed back together with the error, so the caller can decide whether to use it.
+pub fn suspended<P: ProcessControl, R>(
+    proc: &mut P,
+    func: impl FnOnce(&mut P) -> R,
+) -> core::result::Result<R, SuspendedError<R>> {
+    proc.suspend().map_err(SuspendedError::Suspend)?;
+    let ret = func(proc);
+    match proc.resume() {
+        Ok(()) => Ok(ret),
+        Err(err) => Err(SuspendedError::Resume(ret, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ErrorKind, ErrorOrigin};
+
+    /// Process that counts its suspensions.
+    #[derive(Default)]
+    struct DummyControl {
+        suspend_count: u32,
+        fail_suspend: bool,
+        fail_resume: bool,
+    }
+
+    impl ProcessControl for DummyControl {
+        fn suspend(&mut self) -> Result<()> {
+            if self.fail_suspend {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToWriteMemory));
+            }
+            self.suspend_count += 1;
+            Ok(())
+        }
+
+        fn resume(&mut self) -> Result<()> {
+            if self.fail_resume {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToWriteMemory));
+            }
+            self.suspend_count -= 1;
+            Ok(())
+        }
+
+        fn suspend_thread(&mut self, _tid: u32) -> Result<()> {
+            self.suspend()
+        }
+
+        fn resume_thread(&mut self, _tid: u32) -> Result<()> {
+            self.resume()
+        }
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        let mut proc = DummyControl::default();
+
+        let ret = suspended(&mut proc, |proc| proc.suspend_count).unwrap();
+        assert_eq!(ret, 1);
+        assert_eq!(proc.suspend_count, 0);
+    }
+
+    #[test]
+    fn suspend_failure() {
+        let mut proc = DummyControl {
+            fail_suspend: true,
+            ..Default::default()
+        };
+
+        let mut called = false;
+        let err = suspended(&mut proc, |_| called = true).unwrap_err();
+        assert!(matches!(err, SuspendedError::Suspend(_)));
+        assert!(!called);
+    }
+
+    #[test]
+    fn resume_failure() {
+        let mut proc = DummyControl {
+            fail_resume: true,
+            ..Default::default()
+        };
+
+        // the result of the function is returned together with the error
+        match suspended(&mut proc, |proc| proc.suspend_count) {
+            Err(SuspendedError::Resume(ret, err)) => {
+                assert_eq!(ret, 1);
+                assert_eq!(err.1, ErrorKind::UnableToWriteMemory);
+            }
+            _ => panic!("resume failure was not reported"),
+        }
+        assert_eq!(proc.suspend_count, 1);
+    }
+}
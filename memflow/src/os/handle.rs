@@ -0,0 +1,208 @@
+//! Process handles that detect exited processes.
+//!
+//! A process object keeps working after the process it refers to has exited. Its page tables
+//! are freed and eventually reused by a different process, so reads through a stale process
+//! object silently return memory of another process.
+//!
+//! [`ProcessHandle`] wraps a process and validates that it is still the same, running process
+//! before forwarding memory accesses. A process is considered gone once its
+//! [`state`](Process::state) is [`ProcessState::Dead`], or once an identity value captured on
+//! creation changed. A good identity is a value unique to the process instance, for example the
+//! creation time stored in the kernel structure of the process. Validation is not free, so it is
+//! only performed every few batches of operations and whenever an access fails.
+//!
+//! All accesses to a process that is gone fail with [`ErrorKind::ProcessGone`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::handle::ProcessHandle;
+//!
+//! fn read_header(proc: impl Process + MemoryView) -> Result<[u8; 2]> {
+//!     let base = proc.info().address;
+//!     let mut handle = ProcessHandle::new(proc).identity(base, 8)?;
+//!
+//!     match handle.read(base) {
+//!         Err(PartialError::Error(Error(_, ErrorKind::ProcessGone))) => {
+//!             println!("process exited");
+//!             Err(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessGone))
+//!         }
+//!         res => res.data(),
+//!     }
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let proc = DummyOs::quick_process(size::mb(2), &[0x4d, 0x5a]);
+//! # assert_eq!(read_header(proc).unwrap(), [0x4d, 0x5a]);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::mem_data::{ReadRawMemOps, WriteRawMemOps};
+use crate::mem::{MemoryView, MemoryViewMetadata};
+use crate::os::{Process, ProcessState};
+use crate::types::{umem, Address};
+
+/// Number of batches after which a process is validated by default.
+pub const DEFAULT_CHECK_INTERVAL: usize = 64;
+
+/// Process wrapper failing all accesses once the process exited.
+#[derive(Clone, Debug)]
+pub struct ProcessHandle<P> {
+    proc: P,
+    identity: Option<(Address, Vec<u8>)>,
+    check_interval: usize,
+    batches: usize,
+    gone: bool,
+}
+
+impl<P: Process + MemoryView> ProcessHandle<P> {
+    /// Creates a new handle only validating the state of the process.
+    pub fn new(proc: P) -> Self {
+        Self {
+            proc,
+            identity: None,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+            batches: 0,
+            gone: false,
+        }
+    }
+
+    /// Captures `len` bytes at `addr` as the identity of the process.
+    ///
+    /// The bytes are read through the process right away. The process is considered gone once
+    /// they change or cannot be read anymore.
+    pub fn identity(mut self, addr: Address, len: usize) -> Result<Self> {
+        let bytes = self.proc.read_raw(addr, len).data()?;
+        self.identity = Some((addr, bytes));
+        Ok(self)
+    }
+
+    /// Sets the number of batches of operations after which the process is validated again.
+    ///
+    /// An interval of `0` or `1` validates the process before every batch.
+    pub fn check_interval(mut self, batches: usize) -> Self {
+        self.check_interval = batches;
+        self
+    }
+
+    /// Returns true if the process was found to be gone.
+    pub fn is_gone(&self) -> bool {
+        self.gone
+    }
+
+    /// Validates the process right away.
+    pub fn validate(&mut self) -> Result<()> {
+        if !self.gone {
+            self.batches = 0;
+            self.gone = self.proc.state().is_dead() || !self.identity_matches();
+        }
+
+        if self.gone {
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessGone)
+                .log_debug(format!("process {} is gone", self.proc.info().pid)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the wrapped process after validating it.
+    pub fn get(&mut self) -> Result<&mut P> {
+        self.validate()?;
+        Ok(&mut self.proc)
+    }
+
+    /// Consumes self and returns the wrapped process.
+    pub fn into_inner(self) -> P {
+        self.proc
+    }
+
+    fn identity_matches(&mut self) -> bool {
+        match &self.identity {
+            Some((addr, bytes)) => match self.proc.read_raw(*addr, bytes.len()).data() {
+                Ok(cur) => &cur == bytes,
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Validates the process if the check interval elapsed.
+    fn validate_cached(&mut self) -> Result<()> {
+        self.batches += 1;
+        if self.gone || self.batches >= self.check_interval {
+            self.validate()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces `err` if it was caused by the process being gone.
+    fn map_error(&mut self, err: Error) -> Error {
+        self.validate().err().unwrap_or(err)
+    }
+}
+
+impl<P: Process + MemoryView> MemoryView for ProcessHandle<P> {
+    fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+        self.validate_cached()?;
+        self.proc
+            .read_raw_iter(data)
+            .map_err(|err| self.map_error(err))
+    }
+
+    fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+        self.validate_cached()?;
+        self.proc
+            .write_raw_iter(data)
+            .map_err(|err| self.map_error(err))
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        self.proc.metadata()
+    }
+
+    fn prefetch(&mut self, addr: Address, len: umem) {
+        if !self.gone {
+            self.proc.prefetch(addr, len)
+        }
+    }
+}
+
+impl<P: Process> ProcessHandle<P> {
+    /// Returns the state of the wrapped process without validating it.
+    pub fn state(&mut self) -> ProcessState {
+        self.proc.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::error::PartialError;
+    use crate::types::size;
+
+    #[test]
+    fn identity_change() {
+        let proc = DummyOs::quick_process(size::mb(2), &[0x4d, 0x5a, 0x90, 0x00]);
+        let base = proc.info().address;
+
+        let mut handle = ProcessHandle::new(proc)
+            .identity(base, 4)
+            .unwrap()
+            .check_interval(2);
+        assert_eq!(handle.read::<u16>(base).unwrap(), 0x5a4d);
+
+        // the process is only validated on every second batch
+        handle.get().unwrap().write(base, &0u32).unwrap();
+        assert_eq!(handle.read::<u16>(base).unwrap(), 0);
+        assert!(matches!(
+            handle.read::<u16>(base),
+            Err(PartialError::Error(Error(_, ErrorKind::ProcessGone)))
+        ));
+        assert!(handle.is_gone());
+        assert!(handle.get().is_err());
+    }
+}
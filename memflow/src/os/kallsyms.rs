@@ -0,0 +1,377 @@
+//! Parsers for the in-memory symbol tables of Linux kernels.
+//!
+//! Linux kernels carry two symbol tables in their image, which allow resolving kernel symbols
+//! without access to the `vmlinux` file or a `System.map` matching the target:
+//!
+//! * `kallsyms` - a compressed table of (nearly) all symbols, decoded by [`read_kallsyms`]
+//! * `__ksymtab` - the table of symbols exported to modules, decoded by [`read_ksymtab`]
+//!
+//! Both parsers only decode the tables, they do not locate them. The OS plugin is expected to
+//! find the table addresses first, e.g. through signatures and a [`KaslrSolver`](super::KaslrSolver).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::kallsyms::{read_ksymtab, KsymtabLayout};
+//!
+//! fn print_exports(mem: &mut impl MemoryView, start: Address, end: Address) -> Result<()> {
+//!     for sym in read_ksymtab(mem, start, end, KsymtabLayout::Prel32Namespaced)? {
+//!         println!("{} {}", sym.address, sym.name);
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # let mut mem = DummyMemory::new(size::mb(1));
+//! # print_exports(&mut mem.phys_view(), 0x1000.into(), 0x1000.into()).unwrap();
+//! ```
+
+use std::convert::TryInto;
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{size, umem, Address};
+
+/// Maximum length of symbol names read from `__ksymtab` string tables.
+const MAX_NAME_LEN: usize = 512;
+
+/// Kernel symbol decoded from one of the kernel symbol tables.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KernelSymbol {
+    /// Name of the symbol
+    pub name: String,
+    /// Absolute address of the symbol
+    pub address: Address,
+    /// Symbol type as reported in `/proc/kallsyms` (e.g. `T` for global text symbols)
+    ///
+    /// Symbols read from `__ksymtab` do not carry type information and are reported as `?`.
+    pub kind: char,
+}
+
+/// Location of the address table of `kallsyms`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum KallsymsAddresses {
+    /// `kallsyms_addresses`, an array of 64-bit absolute addresses
+    Absolute(Address),
+    /// `kallsyms_offsets`, an array of 32-bit offsets relative to `kallsyms_relative_base`
+    ///
+    /// With `absolute_percpu` (`CONFIG_KALLSYMS_ABSOLUTE_PERCPU`) positive offsets are absolute
+    /// values and negative offsets are relative to the base.
+    Relative {
+        offsets: Address,
+        relative_base: Address,
+        absolute_percpu: bool,
+    },
+}
+
+/// Addresses of the `kallsyms` tables of a kernel image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KallsymsTables {
+    /// Number of symbols, the value of `kallsyms_num_syms`
+    pub num_syms: usize,
+    /// Address of `kallsyms_names`
+    pub names: Address,
+    /// Address of `kallsyms_token_table`
+    pub token_table: Address,
+    /// Address of `kallsyms_token_index`
+    pub token_index: Address,
+    /// Address table of the symbols
+    pub addresses: KallsymsAddresses,
+}
+
+/// Layout of the entries of `__ksymtab`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum KsymtabLayout {
+    /// 64-bit value and name pointers (kernels without `CONFIG_HAVE_ARCH_PREL32_RELOCATIONS`)
+    Absolute64,
+    /// 64-bit value, name and namespace pointers (5.4+)
+    Absolute64Namespaced,
+    /// 32-bit value and name offsets relative to the entry fields (4.19+)
+    Prel32,
+    /// 32-bit value, name and namespace offsets relative to the entry fields (5.4+)
+    Prel32Namespaced,
+}
+
+impl KsymtabLayout {
+    /// Returns the size of a single `struct kernel_symbol`.
+    pub fn entry_size(self) -> usize {
+        match self {
+            KsymtabLayout::Absolute64 => 16,
+            KsymtabLayout::Absolute64Namespaced => 24,
+            KsymtabLayout::Prel32 => 8,
+            KsymtabLayout::Prel32Namespaced => 12,
+        }
+    }
+}
+
+/// Decodes all entries of a `__ksymtab` section spanning `start..end`.
+pub fn read_ksymtab(
+    mem: &mut impl MemoryView,
+    start: Address,
+    end: Address,
+    layout: KsymtabLayout,
+) -> Result<Vec<KernelSymbol>> {
+    if end < start {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+            .log_error("ksymtab end is located before its start"));
+    }
+
+    let entry_size = layout.entry_size();
+    let buf = mem.read_raw(start, (end - start) as usize).data()?;
+
+    let mut ret = vec![];
+
+    for (i, entry) in buf.chunks_exact(entry_size).enumerate() {
+        let entry_addr = start + i * entry_size;
+
+        let (address, name_addr) = match layout {
+            KsymtabLayout::Absolute64 | KsymtabLayout::Absolute64Namespaced => (
+                Address::from(read_u64(entry, 0)),
+                Address::from(read_u64(entry, 8)),
+            ),
+            KsymtabLayout::Prel32 | KsymtabLayout::Prel32Namespaced => (
+                entry_addr + read_u32(entry, 0) as i32,
+                entry_addr + 4usize + read_u32(entry, 4) as i32,
+            ),
+        };
+
+        let name = mem
+            .read_char_string_n(name_addr, MAX_NAME_LEN)
+            .data_part()?;
+
+        ret.push(KernelSymbol {
+            name,
+            address,
+            kind: '?',
+        });
+    }
+
+    Ok(ret)
+}
+
+/// Decodes all symbols of the `kallsyms` tables.
+///
+/// Both the single byte and the two byte name length encoding (6.1+) are supported.
+pub fn read_kallsyms(
+    mem: &mut impl MemoryView,
+    tables: &KallsymsTables,
+) -> Result<Vec<KernelSymbol>> {
+    let tokens = read_tokens(mem, tables)?;
+    let addresses = read_addresses(mem, tables)?;
+
+    let mut names = ChunkReader::new(tables.names);
+    let mut ret = Vec::with_capacity(tables.num_syms);
+
+    for address in addresses {
+        let mut len = names.next(mem)? as usize;
+        if len & 0x80 != 0 {
+            len = (len & 0x7f) | ((names.next(mem)? as usize) << 7);
+        }
+
+        let mut symbol = String::new();
+        for _ in 0..len {
+            symbol.push_str(&tokens[names.next(mem)? as usize]);
+        }
+
+        let mut chars = symbol.chars();
+        let kind = chars.next().ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_error("empty kallsyms entry")
+        })?;
+
+        ret.push(KernelSymbol {
+            name: chars.as_str().to_string(),
+            address,
+            kind,
+        });
+    }
+
+    Ok(ret)
+}
+
+/// Reads the 256 expansion tokens of the name compression.
+fn read_tokens(mem: &mut impl MemoryView, tables: &KallsymsTables) -> Result<Vec<String>> {
+    let index = mem.read_raw(tables.token_index, 256 * 2).data()?;
+    let index = index
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]) as usize)
+        .collect::<Vec<_>>();
+
+    // tokens are short, the last one ends well within this bound
+    let len = index.iter().max().copied().unwrap_or_default() + 256;
+    let table = mem.read_raw(tables.token_table, len).data_part()?;
+
+    Ok(index
+        .into_iter()
+        .map(|start| {
+            let token = &table[start..];
+            let end = token.iter().position(|&c| c == 0).unwrap_or(token.len());
+            String::from_utf8_lossy(&token[..end]).into_owned()
+        })
+        .collect())
+}
+
+fn read_addresses(mem: &mut impl MemoryView, tables: &KallsymsTables) -> Result<Vec<Address>> {
+    let num = tables.num_syms;
+
+    match tables.addresses {
+        KallsymsAddresses::Absolute(addr) => {
+            let buf = mem.read_raw(addr, num * 8).data()?;
+            Ok(buf
+                .chunks_exact(8)
+                .map(|c| Address::from(read_u64(c, 0)))
+                .collect())
+        }
+        KallsymsAddresses::Relative {
+            offsets,
+            relative_base,
+            absolute_percpu,
+        } => {
+            let buf = mem.read_raw(offsets, num * 4).data()?;
+            Ok(buf
+                .chunks_exact(4)
+                .map(|c| {
+                    let offset = read_u32(c, 0);
+                    match absolute_percpu {
+                        true if (offset as i32) >= 0 => Address::from(offset),
+                        true => relative_base - 1usize - (offset as i32),
+                        false => relative_base + offset,
+                    }
+                })
+                .collect())
+        }
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// Sequential byte reader fetching memory in larger chunks.
+struct ChunkReader {
+    addr: Address,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    fn new(addr: Address) -> Self {
+        Self {
+            addr,
+            buf: vec![],
+            pos: 0,
+        }
+    }
+
+    fn next(&mut self, mem: &mut impl MemoryView) -> Result<u8> {
+        if self.pos == self.buf.len() {
+            // the end of the names table may be followed by unreadable memory
+            self.buf = mem.read_raw(self.addr, size::kb(64)).data_part()?;
+            self.addr += self.buf.len() as umem;
+            self.pos = 0;
+        }
+
+        self.pos += 1;
+        Ok(self.buf[self.pos - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+
+    #[test]
+    fn kallsyms() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // tokens: 0 = "T", 1 = "t", 2 = "start_", 3 = "kernel", 4 = "do_"
+        let tokens: &[&[u8]] = &[b"T\0", b"t\0", b"start_\0", b"kernel\0", b"do_\0"];
+        let mut token_table = vec![];
+        let mut token_index = vec![0u8; 512];
+        for (i, token) in tokens.iter().enumerate() {
+            token_index[i * 2..i * 2 + 2]
+                .copy_from_slice(&(token_table.len() as u16).to_le_bytes());
+            token_table.extend_from_slice(token);
+        }
+
+        // "Tstart_kernel", "tdo_start_" and a name using the two byte length encoding
+        let mut names = vec![3, 0, 2, 3, 3, 1, 4, 2];
+        names.extend_from_slice(&[0x82, 0x01]);
+        names.push(1);
+        names.extend(std::iter::repeat(3).take(129));
+
+        let offsets = [0x100i32, 0x200, -0x11]
+            .iter()
+            .flat_map(|o| o.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        mem.phys_write(0x1000.into(), &token_table[..]).unwrap();
+        mem.phys_write(0x2000.into(), &token_index[..]).unwrap();
+        mem.phys_write(0x3000.into(), &names[..]).unwrap();
+        mem.phys_write(0x4000.into(), &offsets[..]).unwrap();
+
+        let tables = KallsymsTables {
+            num_syms: 3,
+            names: 0x3000.into(),
+            token_table: 0x1000.into(),
+            token_index: 0x2000.into(),
+            addresses: KallsymsAddresses::Relative {
+                offsets: 0x4000.into(),
+                relative_base: 0xffff_ffff_8100_0000u64.into(),
+                absolute_percpu: true,
+            },
+        };
+
+        let syms = read_kallsyms(&mut mem.phys_view(), &tables).unwrap();
+
+        assert_eq!(syms.len(), 3);
+        assert_eq!(syms[0].name, "start_kernel");
+        assert_eq!(syms[0].kind, 'T');
+        assert_eq!(syms[0].address, Address::from(0x100u64));
+        assert_eq!(syms[1].name, "do_start_");
+        assert_eq!(syms[1].kind, 't');
+        assert_eq!(syms[2].name, "kernel".repeat(129));
+        assert_eq!(syms[2].address, Address::from(0xffff_ffff_8100_0010u64));
+    }
+
+    #[test]
+    fn ksymtab_prel32() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // single entry at 0x1000: value at 0x800, name at 0x2004, namespace unused
+        let mut entry = vec![];
+        entry.extend_from_slice(&(-0x800i32).to_le_bytes());
+        entry.extend_from_slice(&0x1000i32.to_le_bytes());
+        entry.extend_from_slice(&0i32.to_le_bytes());
+
+        mem.phys_write(0x1000.into(), &entry[..]).unwrap();
+        mem.phys_write(0x2004.into(), b"printk\0").unwrap();
+
+        let syms = read_ksymtab(
+            &mut mem.phys_view(),
+            0x1000.into(),
+            0x100c.into(),
+            KsymtabLayout::Prel32Namespaced,
+        )
+        .unwrap();
+
+        assert_eq!(
+            syms,
+            vec![KernelSymbol {
+                name: "printk".into(),
+                address: 0x800u64.into(),
+                kind: '?',
+            }]
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Helpers for resolving randomized kernel and module base addresses.
+//!
+//! Kernels with address space layout randomization (KASLR) are loaded at a random offset, the
+//! slide, relative to their link-time base. OS plugins usually know a few facts about the image
+//! that allow recovering the slide:
+//!
+//! * runtime addresses of symbols with known link-time addresses, e.g. found through an exported
+//!   pointer, a register value, or a symbol table exposed by the kernel itself
+//! * byte signatures found at known link-time addresses of the image
+//!
+//! The [`KaslrSolver`] combines any number of such anchors into a single base address. Symbol
+//! anchors propose slides directly, signatures are used to verify them. If no symbol anchors are
+//! available, every aligned base inside of the search range is tried instead.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::kaslr::{KaslrAnchor, KaslrSolver};
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn find_base(mem: &mut impl MemoryView, lo: Address, hi: Address) -> Result<Address> {
+//!     // the image is linked at 0xffffffff81000000 and contains a known prologue at +0x200
+//!     let solution = KaslrSolver::new(0xffffffff81000000u64.into())
+//!         .alignment(size::kb(4) as umem)
+//!         .search_range(lo, hi)
+//!         .anchor(KaslrAnchor::signature(
+//!             0xffffffff81000200u64.into(),
+//!             &[0x55, 0x48, 0x89, 0xe5],
+//!         ))
+//!         .solve(mem)?;
+//!
+//!     Ok(solution.base)
+//! }
+//! # let mut buf = vec![0u8; 0x4000];
+//! # buf[0x3200..0x3204].copy_from_slice(&[0x55, 0x48, 0x89, 0xe5]);
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+//! # let base = proc.info().address;
+//! # let found = find_base(&mut proc, base, base + 0x8000usize).unwrap();
+//! # assert_eq!(found, base + 0x3000usize);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::types::{imem, size, umem, Address};
+
+/// Fact about an image used to determine its randomized base.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum KaslrAnchor {
+    /// A symbol with a known link-time address was observed at a runtime address
+    Symbol {
+        link_address: Address,
+        address: Address,
+    },
+    /// The given bytes are found at a link-time address, `None` bytes match any value
+    Signature {
+        link_address: Address,
+        pattern: Vec<Option<u8>>,
+    },
+}
+
+impl KaslrAnchor {
+    /// Creates an anchor from the link-time and runtime address of a symbol.
+    pub fn symbol(link_address: Address, address: Address) -> Self {
+        Self::Symbol {
+            link_address,
+            address,
+        }
+    }
+
+    /// Creates an anchor from bytes found at a link-time address.
+    pub fn signature(link_address: Address, bytes: &[u8]) -> Self {
+        Self::Signature {
+            link_address,
+            pattern: bytes.iter().copied().map(Some).collect(),
+        }
+    }
+
+    /// Creates an anchor from a pattern found at a link-time address, `None` bytes match any value.
+    pub fn masked_signature(link_address: Address, pattern: &[Option<u8>]) -> Self {
+        Self::Signature {
+            link_address,
+            pattern: pattern.to_vec(),
+        }
+    }
+}
+
+/// Base address determined by a [`KaslrSolver`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KaslrSolution {
+    /// Runtime base address of the image
+    pub base: Address,
+    /// Offset between the runtime and the link-time base address
+    pub slide: imem,
+    /// Number of anchors agreeing with the solution
+    pub matched: usize,
+}
+
+/// Solver determining the randomized base of an image from a set of anchors.
+#[derive(Clone, Debug)]
+pub struct KaslrSolver {
+    link_base: Address,
+    alignment: umem,
+    range: Option<(Address, Address)>,
+    anchors: Vec<KaslrAnchor>,
+}
+
+impl KaslrSolver {
+    /// Creates a new solver for an image linked at `link_base`.
+    ///
+    /// By default slides are expected to be page aligned and no search range is set.
+    pub fn new(link_base: Address) -> Self {
+        Self {
+            link_base,
+            alignment: size::kb(4) as umem,
+            range: None,
+            anchors: vec![],
+        }
+    }
+
+    /// Changes the alignment of the slide, e.g. 2 mb for x86_64 Linux kernels.
+    pub fn alignment(mut self, alignment: umem) -> Self {
+        self.alignment = alignment.max(1);
+        self
+    }
+
+    /// Sets the range of runtime base addresses that is searched if no symbol anchors are given.
+    ///
+    /// Bases proposed by symbol anchors outside of this range are rejected.
+    pub fn search_range(mut self, start: Address, end: Address) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    /// Adds an anchor to the solver.
+    pub fn anchor(mut self, anchor: KaslrAnchor) -> Self {
+        self.anchors.push(anchor);
+        self
+    }
+
+    /// Adds multiple anchors to the solver.
+    pub fn anchors(mut self, anchors: impl IntoIterator<Item = KaslrAnchor>) -> Self {
+        self.anchors.extend(anchors);
+        self
+    }
+
+    /// Determines the runtime base of the image.
+    ///
+    /// Slides proposed by symbol anchors are tried in the order of the number of anchors
+    /// agreeing with them. The first slide satisfying all signatures is returned. Without symbol
+    /// anchors the search range is scanned in ascending order instead.
+    pub fn solve(&self, mem: &mut impl MemoryView) -> Result<KaslrSolution> {
+        let signatures = self
+            .anchors
+            .iter()
+            .filter(|a| matches!(a, KaslrAnchor::Signature { .. }))
+            .count();
+
+        // proposed runtime bases along with the number of symbols agreeing with them
+        let mut candidates: Vec<(Address, usize)> = vec![];
+        for anchor in self.anchors.iter() {
+            if let KaslrAnchor::Symbol {
+                link_address,
+                address,
+            } = anchor
+            {
+                let base = self
+                    .link_base
+                    .wrapping_add(address.wrapping_sub(*link_address));
+                match candidates.iter_mut().find(|(b, _)| *b == base) {
+                    Some((_, votes)) => *votes += 1,
+                    None => candidates.push((base, 1)),
+                }
+            }
+        }
+
+        if !candidates.is_empty() {
+            candidates.sort_by(|a, b| b.1.cmp(&a.1));
+
+            for (base, votes) in candidates {
+                if self.is_candidate(base) && self.verify(mem, base) {
+                    return Ok(self.solution(base, votes + signatures));
+                }
+            }
+        } else if signatures > 0 {
+            let (start, end) = self.range.ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                    .log_error("a search range is required when only signatures are given")
+            })?;
+
+            // first base inside of the range with an aligned slide
+            let rem = start.wrapping_sub(self.link_base).to_umem() % self.alignment;
+            let mut base = start
+                .to_umem()
+                .checked_add((self.alignment - rem) % self.alignment);
+
+            while let Some(addr) = base.map(Address::from).filter(|&b| b < end) {
+                if self.verify(mem, addr) {
+                    return Ok(self.solution(addr, signatures));
+                }
+                base = addr.to_umem().checked_add(self.alignment);
+            }
+        } else {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error("no kaslr anchors were given"));
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_info("no base address satisfies all kaslr anchors"))
+    }
+
+    fn solution(&self, base: Address, matched: usize) -> KaslrSolution {
+        KaslrSolution {
+            base,
+            slide: base.wrapping_sub(self.link_base).to_umem() as imem,
+            matched,
+        }
+    }
+
+    fn is_candidate(&self, base: Address) -> bool {
+        let aligned = base.wrapping_sub(self.link_base).to_umem() % self.alignment == 0;
+        let in_range = self
+            .range
+            .map_or(true, |(start, end)| base >= start && base < end);
+        aligned && in_range
+    }
+
+    /// Checks whether all signatures match if the image was loaded at `base`.
+    fn verify(&self, mem: &mut impl MemoryView, base: Address) -> bool {
+        let mut buf = vec![];
+
+        self.anchors.iter().all(|anchor| match anchor {
+            KaslrAnchor::Signature {
+                link_address,
+                pattern,
+            } => {
+                buf.resize(pattern.len(), 0);
+                let addr = base.wrapping_add(link_address.wrapping_sub(self.link_base));
+                mem.read_raw_into(addr, &mut buf).is_ok()
+                    && buf
+                        .iter()
+                        .zip(pattern.iter())
+                        .all(|(b, p)| p.map_or(true, |p| p == *b))
+            }
+            KaslrAnchor::Symbol { .. } => true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn symbol_votes() {
+        let mut buf = vec![0u8; 0x4000];
+        buf[0x2010..0x2014].copy_from_slice(&[1, 2, 3, 4]);
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let link_base = Address::from(0x1000_0000u64);
+        let solver = KaslrSolver::new(link_base)
+            // one outlier symbol disagrees with the other two
+            .anchors(vec![
+                KaslrAnchor::symbol(link_base + 0x100usize, base + 0x1100usize),
+                KaslrAnchor::symbol(link_base + 0x200usize, base + 0x2200usize),
+                KaslrAnchor::symbol(link_base + 0x300usize, base + 0x2300usize),
+            ])
+            .anchor(KaslrAnchor::masked_signature(
+                link_base + 0x10usize,
+                &[Some(1), None, Some(3)],
+            ));
+
+        let solution = solver.solve(&mut proc).unwrap();
+        assert_eq!(solution.base, base + 0x2000usize);
+        assert_eq!(solution.matched, 3);
+
+        // the signature rules out the slide of the outlier
+        let solver = KaslrSolver::new(link_base)
+            .anchor(KaslrAnchor::symbol(link_base, base + 0x1000usize))
+            .anchor(KaslrAnchor::signature(link_base + 0x10usize, &[1, 2]));
+        assert!(solver.solve(&mut proc).is_err());
+    }
+}
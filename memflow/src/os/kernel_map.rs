@@ -0,0 +1,307 @@
+//! Labelling of the kernel virtual address space.
+//!
+//! Results of kernel analysis are mostly raw addresses. Knowing which part of the kernel address
+//! space an address falls into (a pool, the page tables, session space or a driver image) is
+//! often the quickest way to tell whether a result makes sense.
+//!
+//! A [`KernelLayout`] describes the fixed regions of a kernel address space. Older kernels place
+//! these regions at constant addresses per build, which are provided by
+//! [`KernelLayout::windows_x64`]. Newer kernels randomize them, OS layers then read the actual
+//! bounds from kernel variables and add them with [`KernelLayout::region`].
+//!
+//! A [`KernelMemoryMap`] combines the layout with the kernel image and the loaded driver images
+//! and answers which region contains a given address. It is usually obtained through
+//! [`Os::kernel_memory_map`](super::Os::kernel_memory_map).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::os::kernel_map::{KernelLayout, KernelMemoryMap, KernelRegionKind};
+//! use memflow::types::Address;
+//!
+//! // Windows 7 SP1
+//! let map = KernelMemoryMap::new(KernelLayout::windows_x64(7601).into_regions());
+//!
+//! let region = map.lookup(Address::from(0xfffff8a0_00123000u64)).unwrap();
+//! assert_eq!(region.kind, KernelRegionKind::PagedPool);
+//! assert_eq!(
+//!     map.describe(Address::from(0xfffff8a0_00123000u64)),
+//!     "PagedPool+0x123000"
+//! );
+//! ```
+
+use std::prelude::v1::*;
+
+use std::fmt;
+
+use crate::types::{umem, Address};
+
+/// Kind of a kernel address space region.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum KernelRegionKind {
+    /// The kernel image itself
+    KernelImage,
+    /// A loaded driver or kernel module image
+    DriverImage,
+    /// Pageable pool allocations
+    PagedPool,
+    /// Non-pageable pool allocations
+    NonPagedPool,
+    /// Recursively mapped page tables
+    PteSpace,
+    /// Per-process mappings of the memory manager
+    Hyperspace,
+    /// Per-session mappings (win32k, session pool)
+    SessionSpace,
+    /// Dynamically mapped system PTEs (stacks, MDL mappings)
+    SystemPtes,
+    /// Mapped views of the file system cache
+    SystemCache,
+    /// Dynamically allocated kernel address space
+    DynamicKernelVa,
+    /// The page frame number database
+    PfnDatabase,
+    /// Page shared with user mode (e.g. `KUSER_SHARED_DATA`)
+    SharedUserData,
+    /// Region defined by the OS layer without a more specific kind
+    Other,
+}
+
+impl fmt::Display for KernelRegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            KernelRegionKind::KernelImage => "KernelImage",
+            KernelRegionKind::DriverImage => "DriverImage",
+            KernelRegionKind::PagedPool => "PagedPool",
+            KernelRegionKind::NonPagedPool => "NonPagedPool",
+            KernelRegionKind::PteSpace => "PteSpace",
+            KernelRegionKind::Hyperspace => "Hyperspace",
+            KernelRegionKind::SessionSpace => "SessionSpace",
+            KernelRegionKind::SystemPtes => "SystemPtes",
+            KernelRegionKind::SystemCache => "SystemCache",
+            KernelRegionKind::DynamicKernelVa => "DynamicKernelVa",
+            KernelRegionKind::PfnDatabase => "PfnDatabase",
+            KernelRegionKind::SharedUserData => "SharedUserData",
+            KernelRegionKind::Other => "Other",
+        };
+        f.pad(name)
+    }
+}
+
+/// A labelled region of the kernel address space.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KernelRegion {
+    /// Kind of the region
+    pub kind: KernelRegionKind,
+    /// Start of the region
+    pub base: Address,
+    /// Size of the region in bytes
+    pub size: umem,
+    /// Label of the region, the module name for images and the kind otherwise
+    pub name: String,
+}
+
+impl KernelRegion {
+    /// Creates a region labelled with its kind.
+    pub fn new(kind: KernelRegionKind, base: Address, size: umem) -> Self {
+        Self {
+            kind,
+            base,
+            size,
+            name: kind.to_string(),
+        }
+    }
+
+    /// Creates a region with a custom label.
+    pub fn with_name(kind: KernelRegionKind, base: Address, size: umem, name: &str) -> Self {
+        Self {
+            kind,
+            base,
+            size,
+            name: name.to_string(),
+        }
+    }
+
+    /// Returns true if `address` is part of the region.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.base && address.to_umem() - self.base.to_umem() < self.size
+    }
+}
+
+/// Fixed regions of a kernel address space.
+#[derive(Clone, Debug, Default)]
+pub struct KernelLayout {
+    regions: Vec<KernelRegion>,
+}
+
+impl KernelLayout {
+    /// Creates an empty layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the known fixed layout of the given Windows x64 build.
+    ///
+    /// Builds up to Windows 8 (9200) use the 8 TB layout with constant region addresses. Up to
+    /// Windows 10 1511 (10586) the page tables and hyperspace are still at constant addresses.
+    /// Starting with Windows 10 1607 (14393) all regions are randomized and only the shared
+    /// user data page remains, the OS layer has to add the remaining regions itself.
+    pub fn windows_x64(build: u32) -> Self {
+        use KernelRegionKind::*;
+
+        let mut layout = Self::new().region(SharedUserData, 0xfffff780_00000000u64.into(), 0x1000);
+
+        if build < 14393 {
+            layout = layout
+                .region(PteSpace, 0xfffff680_00000000u64.into(), 0x80_00000000)
+                .region(Hyperspace, 0xfffff700_00000000u64.into(), 0x80_00000000);
+        }
+
+        if build <= 9200 {
+            layout = layout
+                .region(SystemPtes, 0xfffff880_00000000u64.into(), 0x20_00000000)
+                .region(PagedPool, 0xfffff8a0_00000000u64.into(), 0x20_00000000)
+                .region(SessionSpace, 0xfffff900_00000000u64.into(), 0x80_00000000)
+                .region(
+                    DynamicKernelVa,
+                    0xfffff980_00000000u64.into(),
+                    0x100_00000000,
+                )
+                // the pfn database and the non-paged pool share the remainder of the address
+                // space, their boundary depends on the amount of physical memory
+                .region(
+                    PfnDatabase,
+                    0xfffffa80_00000000u64.into(),
+                    0x580_00000000 - 0x40_0000,
+                );
+        }
+
+        layout
+    }
+
+    /// Adds a region to the layout.
+    pub fn region(mut self, kind: KernelRegionKind, base: Address, size: umem) -> Self {
+        self.regions.push(KernelRegion::new(kind, base, size));
+        self
+    }
+
+    /// Adds or replaces the region of the given kind.
+    ///
+    /// This is used to refine the constant layout with bounds read from the kernel, e.g. the
+    /// actual end of the pfn database and start of the non-paged pool.
+    pub fn set_region(mut self, kind: KernelRegionKind, base: Address, size: umem) -> Self {
+        self.regions.retain(|r| r.kind != kind);
+        self.region(kind, base, size)
+    }
+
+    /// Returns the regions of the layout.
+    pub fn regions(&self) -> &[KernelRegion] {
+        &self.regions
+    }
+
+    /// Consumes the layout and returns its regions.
+    pub fn into_regions(self) -> Vec<KernelRegion> {
+        self.regions
+    }
+}
+
+/// Labelled map of the kernel address space.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KernelMemoryMap {
+    regions: Vec<KernelRegion>,
+}
+
+impl KernelMemoryMap {
+    /// Creates a map from the given regions.
+    pub fn new(regions: Vec<KernelRegion>) -> Self {
+        let mut map = Self { regions };
+        map.sort();
+        map
+    }
+
+    /// Adds a region to the map.
+    pub fn insert(&mut self, region: KernelRegion) {
+        self.regions.push(region);
+        self.sort();
+    }
+
+    /// Returns all regions sorted by their base address.
+    pub fn regions(&self) -> &[KernelRegion] {
+        &self.regions
+    }
+
+    /// Returns all regions containing `address`, sorted by their base address.
+    pub fn regions_at(&self, address: Address) -> impl Iterator<Item = &KernelRegion> + '_ {
+        self.regions
+            .iter()
+            .take_while(move |r| r.base <= address)
+            .filter(move |r| r.contains(address))
+    }
+
+    /// Returns the most specific region containing `address`.
+    ///
+    /// Regions may be nested, e.g. driver images are mapped inside of the system PTE region. In
+    /// that case the smallest region is returned.
+    pub fn lookup(&self, address: Address) -> Option<&KernelRegion> {
+        self.regions_at(address).min_by_key(|r| r.size)
+    }
+
+    /// Formats `address` as `region+0x1234`, or as a plain address if it is not part of any
+    /// region.
+    pub fn describe(&self, address: Address) -> String {
+        match self.lookup(address) {
+            Some(region) => format!("{}+{:#x}", region.name, address - region.base),
+            None => format!("{:x}", address),
+        }
+    }
+
+    fn sort(&mut self) {
+        self.regions
+            .sort_by(|a, b| a.base.cmp(&b.base).then(b.size.cmp(&a.size)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_regions() {
+        let mut map = KernelMemoryMap::new(KernelLayout::windows_x64(7601).into_regions());
+        map.insert(KernelRegion::with_name(
+            KernelRegionKind::DriverImage,
+            0xfffff880_01000000u64.into(),
+            0x20000,
+            "tcpip.sys",
+        ));
+
+        let addr = Address::from(0xfffff880_01000123u64);
+        assert_eq!(map.regions_at(addr).count(), 2);
+        assert_eq!(map.lookup(addr).unwrap().name, "tcpip.sys");
+        assert_eq!(map.describe(addr), "tcpip.sys+0x123");
+        assert_eq!(
+            map.lookup(0xfffff880_01100000u64.into()).unwrap().kind,
+            KernelRegionKind::SystemPtes
+        );
+        assert!(map.lookup(0x1000.into()).is_none());
+    }
+
+    #[test]
+    fn randomized_layout() {
+        let layout = KernelLayout::windows_x64(19045).set_region(
+            KernelRegionKind::PteSpace,
+            0xffffa580_00000000u64.into(),
+            0x80_00000000,
+        );
+        let map = KernelMemoryMap::new(layout.into_regions());
+
+        assert!(map.lookup(0xfffff8a0_00000000u64.into()).is_none());
+        assert_eq!(
+            map.lookup(0xffffa5d0_00000000u64.into()).unwrap().kind,
+            KernelRegionKind::PteSpace
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Race aware walkers for circular doubly linked kernel lists.
+//!
+//! Kernels link most of their objects (processes, threads, modules, ...) in circular doubly
+//! linked lists, `LIST_ENTRY` on Windows and `list_head` on Linux. On a live system these lists
+//! are modified concurrently under a guarded mutex, pushlock or spinlock which memflow can not
+//! acquire. A walk that overlaps with an insertion or removal may therefore observe a half
+//! updated list and follow a pointer into an entry that is being unlinked or already freed.
+//!
+//! [`ListWalker`] detects such a state by checking that the `Blink` of every visited entry
+//! points back to the entry it was reached from. If the list is inconsistent it is walked again
+//! a bounded number of times. If no attempt observes a consistent list, the longest partial
+//! result is returned and flagged as racy instead of failing the whole enumeration.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::list::ListWalker;
+//!
+//! fn process_list(
+//!     kernel: &mut impl MemoryView,
+//!     arch: ArchitectureObj,
+//!     list_head: Address,
+//!     links_offset: usize,
+//! ) -> Result<Vec<Address>> {
+//!     let walk = ListWalker::new(arch).retries(5).walk(kernel, list_head)?;
+//!     if walk.racy {
+//!         println!("process list was modified during the walk, the result is incomplete");
+//!     }
+//!     Ok(walk.containers(links_offset).collect())
+//! }
+//! # use memflow::dummy::corpus::TestImageBuilder;
+//! # use memflow::architecture::x86::x64;
+//! # let image = TestImageBuilder::windows().process(4, "System").build();
+//! # let processes = process_list(
+//! #     &mut image.kernel_view(),
+//! #     x64::ARCH,
+//! #     image.process_list_head,
+//! #     image.layout.links,
+//! # ).unwrap();
+//! # assert_eq!(processes, vec![image.processes[0].address]);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Maximum number of entries walked, protecting against corrupted or cyclic lists.
+pub const DEFAULT_MAX_ENTRIES: usize = 0x10000;
+
+/// Number of times an inconsistent list is walked again.
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// Describes why a list walk was considered inconsistent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ListInconsistency {
+    /// The `Blink` of `entry` does not point back to the entry it was reached from.
+    BrokenBacklink {
+        entry: Address,
+        expected: Address,
+        found: Address,
+    },
+    /// The entry at `entry` could not be read or is a null pointer.
+    Unreadable { entry: Address },
+    /// The list did not lead back to its head within the maximum number of entries.
+    TooLong,
+}
+
+/// The result of a list walk.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ListWalk {
+    /// Addresses of the list entries in list order, excluding the list head
+    ///
+    /// If the walk is racy this only contains the entries up to the first inconsistency.
+    pub entries: Vec<Address>,
+    /// True if no attempt observed a consistent list
+    pub racy: bool,
+    /// Inconsistency observed by the attempt the entries were taken from
+    pub inconsistency: Option<ListInconsistency>,
+    /// Number of times the list was walked
+    pub attempts: usize,
+}
+
+impl ListWalk {
+    /// Returns the addresses of the structures containing the entries.
+    ///
+    /// `links_offset` is the offset of the list entry inside of the containing structure, this
+    /// is the equivalent of `CONTAINING_RECORD` / `container_of`.
+    pub fn containers(&self, links_offset: usize) -> impl Iterator<Item = Address> + '_ {
+        self.entries.iter().map(move |&entry| entry - links_offset)
+    }
+}
+
+/// Walks circular doubly linked lists and validates their back links.
+#[derive(Clone, Copy, Debug)]
+pub struct ListWalker {
+    arch: ArchitectureObj,
+    max_entries: usize,
+    retries: usize,
+}
+
+impl ListWalker {
+    /// Constructs a new walker for lists of the given architecture.
+    pub fn new(arch: ArchitectureObj) -> Self {
+        Self {
+            arch,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Sets the maximum number of entries walked.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets the number of times an inconsistent list is walked again.
+    ///
+    /// A value of 0 walks the list exactly once.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Walks the list starting at the list head `head`.
+    ///
+    /// Only fails if the list head itself can not be read. All other inconsistencies are
+    /// retried and eventually reported through [`ListWalk::racy`].
+    pub fn walk(&self, mem: &mut impl MemoryView, head: Address) -> Result<ListWalk> {
+        let mut best: Option<ListWalk> = None;
+
+        for attempt in 1..=(self.retries + 1) {
+            let (entries, inconsistency) = self.walk_once(mem, head)?;
+
+            if inconsistency.is_none() {
+                return Ok(ListWalk {
+                    entries,
+                    racy: false,
+                    inconsistency: None,
+                    attempts: attempt,
+                });
+            }
+
+            if best
+                .as_ref()
+                .map_or(true, |b| entries.len() > b.entries.len())
+            {
+                best = Some(ListWalk {
+                    entries,
+                    racy: true,
+                    inconsistency,
+                    attempts: 0,
+                });
+            }
+        }
+
+        let mut walk = best.unwrap_or_default();
+        walk.attempts = self.retries + 1;
+        Ok(walk)
+    }
+
+    /// Walks the list once and returns the entries up to the first inconsistency.
+    fn walk_once(
+        &self,
+        mem: &mut impl MemoryView,
+        head: Address,
+    ) -> Result<(Vec<Address>, Option<ListInconsistency>)> {
+        let (head_flink, head_blink) = self.read_entry(mem, head).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                .log_debug("unable to read list head")
+        })?;
+
+        let mut entries = vec![];
+        let mut prev = head;
+        let mut entry = head_flink;
+
+        while entry != head {
+            if entries.len() >= self.max_entries {
+                return Ok((entries, Some(ListInconsistency::TooLong)));
+            }
+
+            let links = if entry.is_null() {
+                None
+            } else {
+                self.read_entry(mem, entry)
+            };
+            let (flink, blink) = match links {
+                Some(links) => links,
+                None => return Ok((entries, Some(ListInconsistency::Unreadable { entry }))),
+            };
+
+            if blink != prev {
+                return Ok((
+                    entries,
+                    Some(ListInconsistency::BrokenBacklink {
+                        entry,
+                        expected: prev,
+                        found: blink,
+                    }),
+                ));
+            }
+
+            entries.push(entry);
+            prev = entry;
+            entry = flink;
+        }
+
+        // the walk is only complete if the head links back to the last entry
+        if head_blink != prev {
+            return Ok((
+                entries,
+                Some(ListInconsistency::BrokenBacklink {
+                    entry: head,
+                    expected: prev,
+                    found: head_blink,
+                }),
+            ));
+        }
+
+        Ok((entries, None))
+    }
+
+    /// Reads the `Flink` and `Blink` pointers of the entry at `entry`.
+    fn read_entry(&self, mem: &mut impl MemoryView, entry: Address) -> Option<(Address, Address)> {
+        let flink = mem.read_addr_arch(self.arch, entry).data_part().ok()?;
+        let blink = mem
+            .read_addr_arch(self.arch, entry + self.arch.size_addr())
+            .data_part()
+            .ok()?;
+        Some((flink, blink))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::corpus::TestImageBuilder;
+
+    #[test]
+    fn consistent_list() {
+        let image = TestImageBuilder::linux()
+            .process(1, "systemd")
+            .process(2, "kthreadd")
+            .process(300, "sshd")
+            .build();
+
+        let walk = ListWalker::new(x64::ARCH)
+            .walk(&mut image.kernel_view(), image.process_list_head)
+            .unwrap();
+
+        assert!(!walk.racy);
+        assert_eq!(walk.attempts, 1);
+        assert_eq!(
+            walk.containers(image.layout.links).collect::<Vec<_>>(),
+            image
+                .processes
+                .iter()
+                .map(|p| p.address)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn broken_backlink() {
+        let image = TestImageBuilder::windows()
+            .process(4, "System")
+            .process(100, "smss.exe")
+            .process(200, "csrss.exe")
+            .build();
+        let mut kernel = image.kernel_view();
+
+        // simulate an insertion that has only updated the Flink of its predecessor so far
+        let second = image.processes[1].address + image.layout.links;
+        kernel.write(second + 8usize, &0xdead_0000u64).unwrap();
+
+        let walk = ListWalker::new(x64::ARCH)
+            .retries(2)
+            .walk(&mut kernel, image.process_list_head)
+            .unwrap();
+
+        assert!(walk.racy);
+        assert_eq!(walk.attempts, 3);
+        assert_eq!(
+            walk.entries,
+            vec![image.processes[0].address + image.layout.links]
+        );
+        assert_eq!(
+            walk.inconsistency,
+            Some(ListInconsistency::BrokenBacklink {
+                entry: second,
+                expected: image.processes[0].address + image.layout.links,
+                found: Address::from(0xdead_0000u64),
+            })
+        );
+    }
+}
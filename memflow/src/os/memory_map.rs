@@ -0,0 +1,309 @@
+//! Consolidated memory map of a process.
+//!
+//! The mapped memory of a process is reported as plain page ranges with their protection. A
+//! [`MemoryMapReport`] attributes these ranges to their owners, the same way VMMap does: module
+//! images, heaps, thread stacks, TEBs and other well known structures. Regions are split at the
+//! boundaries of every annotation, so each region has exactly one owner.
+//!
+//! The report is usually obtained through
+//! [`Process::memory_map_report`](super::Process::memory_map_report), which annotates module
+//! images and the environment block. OS layers which are able to enumerate heaps and threads
+//! annotate those as well, callers can add their own findings with
+//! [`MemoryMapReport::annotate`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::cglue::CTup3;
+//! use memflow::os::memory_map::{MemoryMapReport, RegionKind};
+//! use memflow::types::{Address, PageType};
+//!
+//! let mut report = MemoryMapReport::new(vec![
+//!     CTup3(Address::from(0x10000), 0x4000, PageType::WRITEABLE),
+//!     CTup3(Address::from(0x400000), 0x3000, PageType::READ_ONLY),
+//! ]);
+//! report.annotate(RegionKind::Image, Address::from(0x400000), 0x3000, "notepad.exe");
+//! report.annotate(RegionKind::Stack, Address::from(0x12000), 0x2000, "Thread 1234");
+//!
+//! assert_eq!(report.regions().len(), 3);
+//! assert_eq!(
+//!     report.describe(Address::from(0x12010)),
+//!     "Stack (Thread 1234)+0x10"
+//! );
+//!
+//! // prints a VMMap like table of all regions
+//! println!("{}", report);
+//! ```
+
+use std::prelude::v1::*;
+
+use std::fmt;
+
+use crate::cglue::CTup3;
+use crate::mem::MemoryRange;
+use crate::types::{umem, Address, PageType};
+
+/// Kind of the owner of a process memory region.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum RegionKind {
+    /// An executable image (the main binary or a loaded module)
+    Image,
+    /// A heap managed by the process
+    Heap,
+    /// The stack of a thread
+    Stack,
+    /// The thread environment block of a thread
+    Teb,
+    /// The process environment block
+    Peb,
+    /// The environment variables of the process
+    Environment,
+    /// A mapped file or a shared section
+    Mapped,
+    /// Private memory without a known owner
+    Private,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RegionKind::Image => "Image",
+            RegionKind::Heap => "Heap",
+            RegionKind::Stack => "Stack",
+            RegionKind::Teb => "Teb",
+            RegionKind::Peb => "Peb",
+            RegionKind::Environment => "Environment",
+            RegionKind::Mapped => "Mapped",
+            RegionKind::Private => "Private",
+        };
+        f.pad(name)
+    }
+}
+
+/// A region of a process memory map with uniform protection and owner.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct MemoryRegion {
+    /// Start of the region
+    pub base: Address,
+    /// Size of the region in bytes
+    pub size: umem,
+    /// Protection of the pages in the region
+    pub page_type: PageType,
+    /// Kind of the owner of the region
+    pub kind: RegionKind,
+    /// Details about the owner, e.g. the module name or the owning thread
+    pub details: String,
+}
+
+impl MemoryRegion {
+    /// Returns true if `address` is part of the region.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.base && address.to_umem() - self.base.to_umem() < self.size
+    }
+
+    /// Returns the protection of the region in the `rwx` notation, or `???` if it is not known.
+    pub fn protection(&self) -> String {
+        if self.page_type.contains(PageType::UNKNOWN) {
+            return "???".to_string();
+        }
+
+        let write = if self.page_type.contains(PageType::WRITEABLE) {
+            'w'
+        } else {
+            '-'
+        };
+        let exec = if self.page_type.contains(PageType::NOEXEC) {
+            '-'
+        } else {
+            'x'
+        };
+        format!("r{}{}", write, exec)
+    }
+
+    /// Returns the label of the region, the kind followed by its details.
+    pub fn label(&self) -> String {
+        if self.details.is_empty() {
+            self.kind.to_string()
+        } else {
+            format!("{} ({})", self.kind, self.details)
+        }
+    }
+
+    fn slice(&self, start: umem, end: umem) -> Self {
+        Self {
+            base: Address::from(start),
+            size: end - start,
+            page_type: self.page_type,
+            kind: self.kind,
+            details: self.details.clone(),
+        }
+    }
+}
+
+/// Memory map of a process with owner attribution.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct MemoryMapReport {
+    regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMapReport {
+    /// Creates a report from the mapped memory ranges of a process.
+    ///
+    /// All regions are initially reported as [`RegionKind::Private`].
+    pub fn new(ranges: Vec<MemoryRange>) -> Self {
+        let mut regions = ranges
+            .into_iter()
+            .map(|CTup3(base, size, page_type)| MemoryRegion {
+                base,
+                size,
+                page_type,
+                kind: RegionKind::Private,
+                details: String::new(),
+            })
+            .collect::<Vec<_>>();
+        regions.sort_by_key(|r| r.base);
+        Self { regions }
+    }
+
+    /// Attributes the memory from `base` to `base + size` to the given owner.
+    ///
+    /// Regions that are only partially covered are split, unmapped parts of the range are
+    /// ignored. Later annotations take precedence over earlier ones.
+    pub fn annotate(&mut self, kind: RegionKind, base: Address, size: umem, details: &str) {
+        let (start, end) = (base.to_umem(), base.to_umem().saturating_add(size));
+
+        let mut regions = Vec::with_capacity(self.regions.len() + 2);
+        for region in self.regions.drain(..) {
+            let region_start = region.base.to_umem();
+            let region_end = region_start + region.size;
+
+            if region_end <= start || region_start >= end {
+                regions.push(region);
+                continue;
+            }
+
+            if region_start < start {
+                regions.push(region.slice(region_start, start));
+            }
+
+            let mut owned = region.slice(region_start.max(start), region_end.min(end));
+            owned.kind = kind;
+            owned.details = details.to_string();
+            regions.push(owned);
+
+            if region_end > end {
+                regions.push(region.slice(end, region_end));
+            }
+        }
+
+        self.regions = regions;
+    }
+
+    /// Returns all regions sorted by their base address.
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    /// Returns the region containing `address`.
+    pub fn lookup(&self, address: Address) -> Option<&MemoryRegion> {
+        let idx = self
+            .regions
+            .partition_point(|r| r.base <= address)
+            .checked_sub(1)?;
+        Some(&self.regions[idx]).filter(|r| r.contains(address))
+    }
+
+    /// Formats `address` as `Kind (details)+0x1234`, or as a plain address if it is not mapped.
+    pub fn describe(&self, address: Address) -> String {
+        match self.lookup(address) {
+            Some(region) => format!("{}+{:#x}", region.label(), address - region.base),
+            None => format!("{:x}", address),
+        }
+    }
+
+    /// Returns the total size of all regions of the given kind.
+    pub fn total_size(&self, kind: RegionKind) -> umem {
+        self.regions
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(|r| r.size)
+            .sum()
+    }
+}
+
+impl fmt::Display for MemoryMapReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>18} {:>12} {:<4} {:<12} Details",
+            "Address", "Size", "Prot", "Type"
+        )?;
+        for region in &self.regions {
+            writeln!(
+                f,
+                "{:>18x} {:>12x} {:<4} {:<12} {}",
+                region.base.to_umem(),
+                region.size,
+                region.protection(),
+                region.kind,
+                region.details
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> MemoryMapReport {
+        MemoryMapReport::new(vec![
+            CTup3(Address::from(0x400000), 0x1000, PageType::READ_ONLY),
+            CTup3(
+                Address::from(0x401000),
+                0x2000,
+                PageType::READ_ONLY.noexec(false),
+            ),
+            CTup3(Address::from(0x10000), 0x8000, PageType::WRITEABLE),
+        ])
+    }
+
+    #[test]
+    fn annotate_splits_regions() {
+        let mut report = report();
+        report.annotate(RegionKind::Image, Address::from(0x400000), 0x3000, "a.exe");
+        report.annotate(RegionKind::Heap, Address::from(0x12000), 0x2000, "");
+
+        let regions = report.regions();
+        assert_eq!(regions.len(), 5);
+        assert_eq!(regions[0].kind, RegionKind::Private);
+        assert_eq!(regions[0].size, 0x2000);
+        assert_eq!(regions[1].kind, RegionKind::Heap);
+        assert_eq!(regions[1].base, Address::from(0x12000));
+        assert_eq!(regions[2].kind, RegionKind::Private);
+        assert_eq!(regions[2].size, 0x4000);
+        assert_eq!(regions[4].protection(), "r-x");
+        assert_eq!(report.total_size(RegionKind::Image), 0x3000);
+    }
+
+    #[test]
+    fn lookup_and_describe() {
+        let mut report = report();
+        report.annotate(RegionKind::Image, Address::from(0x400000), 0x3000, "a.exe");
+
+        assert_eq!(
+            report.lookup(Address::from(0x401234)).unwrap().details,
+            "a.exe"
+        );
+        assert!(report.lookup(Address::from(0x18000)).is_none());
+        assert_eq!(
+            report.describe(Address::from(0x400010)),
+            "Image (a.exe)+0x10"
+        );
+        assert_eq!(report.describe(Address::from(0x20000)), "20000");
+    }
+}
@@ -15,22 +15,91 @@
 //! functions. It might be wise to implement helpers for exported functions, memory protection
 //! flags, and other things concerned with individual modules.
 
+pub mod annotations;
+pub mod consistency;
+#[cfg(feature = "unsafe_writes")]
+pub mod control;
+pub mod handle;
+pub mod kallsyms;
+pub mod kaslr;
+pub mod kernel_map;
 pub mod keyboard;
+pub mod list;
+pub mod memory_map;
 pub mod module;
+#[cfg(all(feature = "native", any(target_os = "linux", windows)))]
+pub mod native;
+pub mod percpu;
 pub mod process;
+pub mod report;
+pub mod resources;
 pub mod root;
+pub mod slab;
+pub mod structs;
+pub mod symbols;
+pub mod syscalls;
+pub mod task_layout;
+pub mod teb;
 pub mod util;
+pub mod vma;
+pub mod watch;
+
+#[cfg(feature = "unsafe_writes")]
+pub use control::{suspended, ProcessControl, SuspendedError};
+
+pub use annotations::{Annotation, AnnotationStore};
+
+pub use consistency::{
+    read_consistent, with_consistency, Consistency, ConsistentRead, EnumerationOptions,
+};
+
+pub use handle::ProcessHandle;
+
+pub use kallsyms::{read_kallsyms, read_ksymtab, KernelSymbol};
+
+pub use kaslr::{KaslrAnchor, KaslrSolution, KaslrSolver};
+
+pub use kernel_map::{KernelLayout, KernelMemoryMap, KernelRegion, KernelRegionKind};
 
 pub use keyboard::{Keyboard, KeyboardState, OsKeyboard};
 
+pub use list::{ListInconsistency, ListWalk, ListWalker};
+
+pub use memory_map::{MemoryMapReport, MemoryRegion, RegionKind};
+
 pub use module::{
-    ExportCallback, ExportInfo, ImportCallback, ImportInfo, ModuleAddressCallback,
-    ModuleAddressInfo, ModuleInfo, ModuleInfoCallback, SectionCallback, SectionInfo,
+    format_address, ExportCallback, ExportInfo, ImportCallback, ImportInfo, ModuleAddressCallback,
+    ModuleAddressInfo, ModuleInfo, ModuleInfoCallback, SectionCallback, SectionData, SectionInfo,
+};
+
+#[cfg(all(feature = "native", any(target_os = "linux", windows)))]
+pub use native::{NativeOs, NativeProcess};
+
+pub use percpu::PerCpuOffsets;
+
+pub use process::{EnvironmentBlock, Pid, Process, ProcessInfo, ProcessInfoCallback, ProcessState};
+
+pub use resources::{ModuleVersion, VersionInfo};
+
+pub use root::{
+    CpuThreadInfo, CpuThreadInfoCallback, Os, OsInfo, OsSecurityFeatures, OsTime, OsVersion,
 };
 
-pub use process::{Pid, Process, ProcessInfo, ProcessInfoCallback, ProcessState};
+pub use slab::{SlabCache, SlabInfo, SlabLayout, SlabObject, SlabReader, SlabState};
+
+pub use structs::{FieldLayout, StructLayout, StructReader, TypeDatabase};
+
+pub use symbols::{Symbol, SymbolIndex};
+
+pub use syscalls::{SyscallEntry, SyscallTable};
+
+pub use task_layout::{TaskLayout, TaskLayoutScanner};
+
+pub use teb::{TebInfo, TebLayout, TebReader};
+
+pub use vma::{MappingKind, VmaInfo, VmaLayout, VmaReader};
 
-pub use root::{Os, OsInfo};
+pub use watch::TargetWatch;
 
 use crate::types::Address;
 
@@ -1,6 +1,8 @@
 //! Describes modules
 
 use crate::prelude::v1::*;
+use std::fmt::Write;
+use std::prelude::v1::*;
 
 /// Module information structure
 #[repr(C)]
@@ -44,6 +46,88 @@ pub struct ModuleInfo {
     pub arch: ArchitectureIdent,
 }
 
+impl ModuleInfo {
+    /// Returns true if `address` lies within the bounds of this module.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.base && ((address - self.base) as umem) < self.size
+    }
+
+    /// Returns the section of this module containing `address`.
+    pub fn section_by_addr(
+        &self,
+        process: &mut impl Process,
+        address: Address,
+    ) -> Result<SectionInfo> {
+        process
+            .module_section_list(self)?
+            .into_iter()
+            .find(|s| s.contains(address))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_debug("address is not part of any section")
+            })
+    }
+
+    /// Reads the contents of the section `name` of this module.
+    ///
+    /// Sections are often only partially resident, e.g. because parts of them were never
+    /// touched or have been paged out. Such sections are still returned: pages that can not be
+    /// read are zero-filled and reported in [`SectionData::missing`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    ///
+    /// fn dump_text(process: &mut (impl Process + MemoryView), module: &ModuleInfo) -> Result<()> {
+    ///     let text = module.read_section(process, ".text")?;
+    ///     if !text.is_complete() {
+    ///         println!("{} bytes of .text are not resident", text.missing_bytes());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let module = proc.primary_module().unwrap();
+    /// # assert!(dump_text(&mut proc, &module).is_err());
+    /// ```
+    pub fn read_section(
+        &self,
+        process: &mut (impl Process + MemoryView),
+        name: &str,
+    ) -> Result<SectionData> {
+        let section = process.module_section_by_name(self, name)?;
+        SectionData::read(process, section)
+    }
+
+    /// Reads the version information resource of the module.
+    ///
+    /// The resource is parsed from the mapped image, see [`resources`](super::resources) for
+    /// details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    ///
+    /// fn print_version(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<()> {
+    ///     let info = module.version_info(mem)?;
+    ///     if let Some(version) = info.file_version {
+    ///         println!("{} {}", module.name, version);
+    ///     }
+    ///     println!("{}", info.company_name().unwrap_or("unknown company"));
+    ///     Ok(())
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let module = proc.primary_module().unwrap();
+    /// # assert!(print_version(&mut proc, &module).is_err());
+    /// ```
+    pub fn version_info(&self, mem: &mut impl MemoryView) -> Result<VersionInfo> {
+        super::resources::read_version_info(mem, self.base)
+    }
+}
+
 pub type ModuleInfoCallback<'a> = OpaqueCallback<'a, ModuleInfo>;
 
 /// Pair of address and architecture used for callbacks
@@ -100,4 +184,360 @@ pub struct SectionInfo {
     pub size: umem,
 }
 
+impl SectionInfo {
+    /// Returns true if `address` lies within the bounds of this section.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.base && ((address - self.base) as umem) < self.size
+    }
+}
+
 pub type SectionCallback<'a> = OpaqueCallback<'a, SectionInfo>;
+
+/// Contents of a section read from memory.
+#[derive(Clone, Debug)]
+pub struct SectionData {
+    /// The section that was read
+    pub section: SectionInfo,
+    /// Contents of the section, parts that could not be read are zero-filled
+    pub data: Vec<u8>,
+    /// Address and length of all ranges that could not be read
+    pub missing: Vec<(Address, umem)>,
+}
+
+impl SectionData {
+    /// Reads `section` page by page, skipping pages that can not be read.
+    pub fn read(mem: &mut impl MemoryView, section: SectionInfo) -> Result<Self> {
+        let page_size = size::kb(4);
+        let mut data = vec![0u8; section.size as usize];
+
+        // split the section along page boundaries so a missing page does not affect the others
+        let first = page_size - (section.base.to_umem() as usize % page_size);
+        let mut chunks = vec![];
+        let (mut addr, mut rest) = (section.base, data.as_mut_slice());
+        while !rest.is_empty() {
+            let len = core::cmp::min(
+                rest.len(),
+                if chunks.is_empty() { first } else { page_size },
+            );
+            let (chunk, tail) = rest.split_at_mut(len);
+            chunks.push(CTup2(addr, chunk.into()));
+            addr += len;
+            rest = tail;
+        }
+
+        let results = mem.read_raw_list_chunks(&mut chunks).data_part()?;
+
+        let mut missing: Vec<(Address, umem)> = vec![];
+        for err in results.into_iter().filter_map(|r| r.err()) {
+            match missing.last_mut() {
+                Some((start, len)) if *start + *len == err.address => *len += err.len,
+                _ => missing.push((err.address, err.len)),
+            }
+        }
+
+        Ok(Self {
+            section,
+            data,
+            missing,
+        })
+    }
+
+    /// Returns true if the entire section could be read.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Returns the number of bytes that could not be read.
+    pub fn missing_bytes(&self) -> umem {
+        self.missing.iter().map(|(_, len)| len).sum()
+    }
+
+    /// Returns true if the byte at `address` was read successfully.
+    pub fn is_resident(&self, address: Address) -> bool {
+        self.section.contains(address)
+            && !self
+                .missing
+                .iter()
+                .any(|&(start, len)| address >= start && ((address - start) as umem) < len)
+    }
+}
+
+/// Formats `address` in the symbolized `module!export+0x1234` notation.
+///
+/// The closest export at or below `address` is picked from `exports`. If there is no such export
+/// the address is formatted relative to the module base as `module+0x1234`. Addresses without a
+/// containing module are formatted as plain hex numbers.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+/// use memflow::os::format_address;
+///
+/// let module = ModuleInfo {
+///     address: Address::null(),
+///     parent_process: Address::null(),
+///     base: Address::from(0x1000),
+///     size: 0x1000,
+///     name: "ntdll.dll".into(),
+///     path: "".into(),
+///     arch: ArchitectureIdent::X86(64, false),
+/// };
+/// let exports = [ExportInfo { name: "NtClose".into(), offset: 0x100 }];
+///
+/// assert_eq!(format_address(Address::from(0x1110), Some(&module), &exports), "ntdll.dll!NtClose+0x10");
+/// assert_eq!(format_address(Address::from(0x1010), Some(&module), &exports), "ntdll.dll+0x10");
+/// assert_eq!(format_address(Address::from(0x3000), None, &[]), "0x3000");
+/// ```
+pub fn format_address(
+    address: Address,
+    module: Option<&ModuleInfo>,
+    exports: &[ExportInfo],
+) -> String {
+    let mut ret = String::new();
+
+    let module = match module {
+        Some(module) if module.contains(address) => module,
+        _ => {
+            write!(ret, "{:#x}", address.to_umem()).ok();
+            return ret;
+        }
+    };
+
+    let offset = (address - module.base) as umem;
+
+    let export = exports
+        .iter()
+        .filter(|e| e.offset <= offset)
+        .max_by_key(|e| e.offset);
+
+    match export {
+        Some(export) => write_symbol(
+            &mut ret,
+            module,
+            Some(export.name.as_ref()),
+            offset - export.offset,
+        ),
+        None => write_symbol(&mut ret, module, None, offset),
+    }
+
+    ret
+}
+
+pub(crate) fn write_symbol(
+    out: &mut String,
+    module: &ModuleInfo,
+    export: Option<&str>,
+    offset: umem,
+) {
+    match export {
+        Some(export) => write!(out, "{}!{}", module.name, export).ok(),
+        None => write!(out, "{}", module.name).ok(),
+    };
+
+    if offset != 0 {
+        write!(out, "+{:#x}", offset).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+
+    /// Builds a minimal mapped PE32+ image with a `.text` section at RVA `0x1000` and a `.data`
+    /// section at RVA `0x2000`.
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x3000];
+        let put = |image: &mut Vec<u8>, off: usize, data: &[u8]| {
+            image[off..off + data.len()].copy_from_slice(data)
+        };
+
+        put(&mut image, 0, b"MZ");
+        put(&mut image, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut image, 0x40, b"PE\0\0");
+
+        // file header
+        put(&mut image, 0x44, &0x8664u16.to_le_bytes());
+        put(&mut image, 0x46, &2u16.to_le_bytes());
+        put(&mut image, 0x54, &0xf0u16.to_le_bytes());
+        put(&mut image, 0x56, &0x22u16.to_le_bytes());
+
+        // optional header
+        let opt = 0x58;
+        put(&mut image, opt, &0x20bu16.to_le_bytes());
+        put(&mut image, opt + 24, &0x140000000u64.to_le_bytes());
+        put(&mut image, opt + 32, &0x1000u32.to_le_bytes());
+        put(&mut image, opt + 36, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 56, &0x3000u32.to_le_bytes());
+        put(&mut image, opt + 60, &0x200u32.to_le_bytes());
+        put(&mut image, opt + 108, &16u32.to_le_bytes());
+
+        // section table
+        let sec = opt + 0xf0;
+        for (i, (name, rva)) in [(b".text\0\0\0", 0x1000u32), (b".data\0\0\0", 0x2000)]
+            .iter()
+            .enumerate()
+        {
+            let sec = sec + i * 40;
+            put(&mut image, sec, *name);
+            put(&mut image, sec + 8, &0x100u32.to_le_bytes());
+            put(&mut image, sec + 12, &rva.to_le_bytes());
+            put(&mut image, sec + 16, &0x200u32.to_le_bytes());
+            put(
+                &mut image,
+                sec + 20,
+                &(0x200 * (i as u32 + 1)).to_le_bytes(),
+            );
+        }
+
+        put(&mut image, 0x2000, b"data");
+
+        image
+    }
+
+    fn module(base: Address, size: umem) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base,
+            size,
+            name: "test.dll".into(),
+            path: "".into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    #[test]
+    fn section_by_addr() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &image());
+        let module = module(proc.info().address, 0x3000);
+
+        let text = module
+            .section_by_addr(&mut proc, module.base + 0x10ffusize)
+            .unwrap();
+        assert_eq!(text.name.as_ref(), ".text");
+        assert_eq!(text.base, module.base + 0x1000usize);
+        assert_eq!(text.size, 0x100);
+
+        let data = module
+            .section_by_addr(&mut proc, module.base + 0x2000usize)
+            .unwrap();
+        assert_eq!(data.name.as_ref(), ".data");
+
+        // the headers and the gaps between sections are not part of any section
+        for offset in [0usize, 0x1100, 0x2100] {
+            assert_eq!(
+                module
+                    .section_by_addr(&mut proc, module.base + offset)
+                    .unwrap_err()
+                    .1,
+                ErrorKind::NotFound
+            );
+        }
+    }
+
+    #[test]
+    fn read_section() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &image());
+        let module = module(proc.info().address, 0x3000);
+
+        let data = module.read_section(&mut proc, ".data").unwrap();
+        assert!(data.is_complete());
+        assert_eq!(data.data.len(), 0x100);
+        assert_eq!(&data.data[..4], b"data");
+        assert!(data.is_resident(module.base + 0x20ffusize));
+        assert!(!data.is_resident(module.base + 0x2100usize));
+    }
+
+    #[test]
+    fn section_with_missing_pages() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        view.write_raw(Address::from(0x1000u64), &[0xaa; 0x4000])
+            .unwrap();
+
+        // the pages at 0x2000 and 0x3000 are not mapped
+        let mut mem_map = MemoryMap::new();
+        mem_map.push_range(Address::null(), 0x2000u64.into(), Address::null());
+        mem_map.push_range(0x4000u64.into(), 0x6000u64.into(), 0x4000u64.into());
+        let mut view = view.into_remap_view(mem_map);
+
+        // the section neither starts nor ends on a page boundary
+        let section = SectionInfo {
+            name: ".text".into(),
+            base: Address::from(0x1800u64),
+            size: 0x3000,
+        };
+        let data = SectionData::read(&mut view, section).unwrap();
+
+        // the failed pages are merged into a single range and zero-filled
+        assert!(!data.is_complete());
+        assert_eq!(data.missing, vec![(Address::from(0x2000u64), 0x2000)]);
+        assert_eq!(data.missing_bytes(), 0x2000);
+        assert!(data.data[..0x800].iter().all(|&b| b == 0xaa));
+        assert!(data.data[0x800..0x2800].iter().all(|&b| b == 0));
+        assert!(data.data[0x2800..].iter().all(|&b| b == 0xaa));
+
+        for (addr, resident) in [
+            (0x17ffu64, false),
+            (0x1800, true),
+            (0x1fff, true),
+            (0x2000, false),
+            (0x3fff, false),
+            (0x4000, true),
+            (0x47ff, true),
+            (0x4800, false),
+        ] {
+            assert_eq!(
+                data.is_resident(Address::from(addr)),
+                resident,
+                "{:x}",
+                addr
+            );
+        }
+    }
+
+    #[test]
+    fn contains() {
+        let module = module(Address::from(0x1000u64), 0x1000);
+
+        assert!(!module.contains(Address::from(0xfffu64)));
+        assert!(module.contains(Address::from(0x1000u64)));
+        assert!(module.contains(Address::from(0x1fffu64)));
+        assert!(!module.contains(Address::from(0x2000u64)));
+    }
+
+    #[test]
+    fn format_address() {
+        let module = module(Address::from(0x1000u64), 0x1000);
+        let exports = [
+            ExportInfo {
+                name: "second".into(),
+                offset: 0x200,
+            },
+            ExportInfo {
+                name: "first".into(),
+                offset: 0x100,
+            },
+        ];
+        let format = |addr: u64, module: Option<&ModuleInfo>| {
+            super::format_address(Address::from(addr), module, &exports)
+        };
+
+        // exact export matches have no offset, otherwise the closest export below is used
+        assert_eq!(format(0x1100, Some(&module)), "test.dll!first");
+        assert_eq!(format(0x11ff, Some(&module)), "test.dll!first+0xff");
+        assert_eq!(format(0x1200, Some(&module)), "test.dll!second");
+        assert_eq!(format(0x1fff, Some(&module)), "test.dll!second+0xdff");
+
+        // addresses below the first export are relative to the module base
+        assert_eq!(format(0x1000, Some(&module)), "test.dll");
+        assert_eq!(format(0x10ff, Some(&module)), "test.dll+0xff");
+
+        // addresses outside of the module are not symbolized
+        assert_eq!(format(0x2000, Some(&module)), "0x2000");
+        assert_eq!(format(0xfff, Some(&module)), "0xfff");
+        assert_eq!(format(0x1100, None), "0x1100");
+    }
+}
@@ -0,0 +1,1015 @@
+//! OS layer for the processes of the host system.
+//!
+//! [`NativeOs`] lists the processes of the machine memflow runs on and opens them as
+//! [`NativeProcess`], which implements [`Process`] and [`MemoryView`] on top of the memory
+//! access APIs of the host: `process_vm_readv`/`process_vm_writev` on Linux, and
+//! `ReadProcessMemory`/`WriteProcessMemory` on Windows. No hardware or virtual machine is
+//! required, which makes it useful for developing and testing tools against local processes.
+//!
+//! Process addresses are the process ids, module addresses are the base addresses of the
+//! modules. The kernel is not accessible, so no kernel modules are listed.
+//!
+//! # Remarks
+//!
+//! Accessing other processes requires the usual debugging privileges: on Linux the caller must
+//! be allowed to ptrace the target (see `/proc/sys/kernel/yama/ptrace_scope`), on Windows the
+//! target must be openable with `PROCESS_VM_READ`. Command lines of Windows processes are not
+//! retrieved.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::os::native::NativeOs;
+//! use memflow::prelude::v1::*;
+//!
+//! let mut os = NativeOs::new().unwrap();
+//! for info in os.process_info_list().unwrap() {
+//!     println!("{} {}", info.pid, info.name);
+//! }
+//!
+//! let mut proc = os.into_process_by_name("bash").unwrap();
+//! let module = proc.primary_module().unwrap();
+//! let header = proc.read::<[u8; 4]>(module.base).data_part().unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::sync::Arc;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::{opt_call, MemoryView, MemoryViewMetadata};
+use crate::os::{process::*, root::*, *};
+use crate::types::{imem, size, umem, util::GapRemover, Address, PageType};
+
+use crate::cglue::*;
+
+/// Returns the architecture of the host.
+fn host_arch() -> ArchitectureIdent {
+    if cfg!(target_arch = "x86_64") {
+        ArchitectureIdent::X86(64, false)
+    } else if cfg!(target_arch = "x86") {
+        ArchitectureIdent::X86(32, false)
+    } else if cfg!(target_arch = "aarch64") {
+        ArchitectureIdent::AArch64(size::kb(4))
+    } else {
+        ArchitectureIdent::Unknown(0)
+    }
+}
+
+/// A memory region of a process.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Region {
+    base: Address,
+    size: umem,
+    page_type: PageType,
+    /// Path of the mapped file, empty for anonymous memory
+    path: String,
+}
+
+/// Groups the file backed regions of a process into modules, in order of their first mapping.
+fn modules_from_regions(
+    regions: &[Region],
+    process: Address,
+    arch: ArchitectureIdent,
+) -> Vec<ModuleInfo> {
+    let mut ret: Vec<ModuleInfo> = vec![];
+    // anonymous and special mappings like `[heap]` are skipped
+    for region in regions
+        .iter()
+        .filter(|r| !r.path.is_empty() && !r.path.starts_with('['))
+    {
+        let end = region.base + region.size;
+        if let Some(module) = ret.iter_mut().find(|m| m.path.as_ref() == region.path) {
+            if end > module.base + module.size {
+                module.size = end - module.base;
+            }
+            continue;
+        }
+
+        let name = region
+            .path
+            .rsplit(|c| c == '/' || c == '\\')
+            .next()
+            .unwrap_or_default();
+        ret.push(ModuleInfo {
+            address: region.base,
+            parent_process: process,
+            base: region.base,
+            size: region.size,
+            name: name.into(),
+            path: region.path.as_str().into(),
+            arch,
+        });
+    }
+    ret
+}
+
+/// The host system.
+#[derive(Clone)]
+pub struct NativeOs {
+    info: OsInfo,
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(NativeOs, crate::plugins::OsInstance, {});
+
+impl NativeOs {
+    /// Creates the OS layer for the host system.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            info: OsInfo {
+                base: Address::NULL,
+                size: 0,
+                arch: host_arch(),
+                version: sys::os_version(),
+            },
+        })
+    }
+
+    /// Opens the process memflow is running in.
+    pub fn current_process() -> Result<NativeProcess> {
+        Self::new()?.into_process_by_pid(std::process::id())
+    }
+}
+
+impl Os for NativeOs {
+    type ProcessType<'a> = NativeProcess;
+    type IntoProcessType = NativeProcess;
+
+    /// Walks the process list, the address of a process is its id
+    fn process_address_list_callback(&mut self, mut callback: AddressCallback) -> Result<()> {
+        sys::pids()?
+            .into_iter()
+            .take_while(|&pid| callback.call(Address::from(pid as umem)))
+            .for_each(|_| {});
+        Ok(())
+    }
+
+    fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo> {
+        sys::process_info(address.to_umem() as Pid, self.info.arch)
+    }
+
+    fn process_by_info(&mut self, info: ProcessInfo) -> Result<Self::ProcessType<'_>> {
+        NativeProcess::with_info(info)
+    }
+
+    fn into_process_by_info(self, info: ProcessInfo) -> Result<Self::IntoProcessType> {
+        NativeProcess::with_info(info)
+    }
+
+    /// Kernel modules are not accessible from user mode
+    fn module_address_list_callback(&mut self, _callback: AddressCallback) -> Result<()> {
+        Ok(())
+    }
+
+    fn module_by_address(&mut self, _address: Address) -> Result<ModuleInfo> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn primary_module_address(&mut self) -> Result<Address> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        _info: &ModuleInfo,
+        _callback: ImportCallback,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn module_export_list_callback(
+        &mut self,
+        _info: &ModuleInfo,
+        _callback: ExportCallback,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        _info: &ModuleInfo,
+        _callback: SectionCallback,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn info(&self) -> &OsInfo {
+        &self.info
+    }
+}
+
+/// A process of the host system.
+#[derive(Clone)]
+pub struct NativeProcess {
+    info: ProcessInfo,
+    handle: Arc<sys::Handle>,
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(NativeProcess, crate::plugins::ProcessInstance, {});
+#[cfg(feature = "plugins")]
+cglue_impl_group!(NativeProcess, crate::plugins::IntoProcessInstance, {});
+
+impl NativeProcess {
+    fn with_info(info: ProcessInfo) -> Result<Self> {
+        Ok(Self {
+            handle: Arc::new(sys::Handle::open(info.pid)?),
+            info,
+        })
+    }
+
+    fn modules(&self) -> Result<Vec<ModuleInfo>> {
+        sys::modules(&self.handle, &self.info)
+    }
+}
+
+impl Process for NativeProcess {
+    fn state(&mut self) -> ProcessState {
+        self.handle.state()
+    }
+
+    fn module_address_list_callback(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+        callback: ModuleAddressCallback,
+    ) -> Result<()> {
+        self.modules()?
+            .into_iter()
+            .filter(|m| target_arch.is_none() || Some(&m.arch) == target_arch)
+            .map(|m| ModuleAddressInfo {
+                address: m.address,
+                arch: m.arch,
+            })
+            .feed_into(callback);
+        Ok(())
+    }
+
+    fn module_by_address(
+        &mut self,
+        address: Address,
+        architecture: ArchitectureIdent,
+    ) -> Result<ModuleInfo> {
+        self.modules()?
+            .into_iter()
+            .find(|m| m.address == address && m.arch == architecture)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// Retrieves the module of the process executable
+    fn primary_module_address(&mut self) -> Result<Address> {
+        let modules = self.modules()?;
+        modules
+            .iter()
+            .find(|m| m.path.as_ref() == self.info.path.as_ref())
+            .or_else(|| modules.first())
+            .map(|m| m.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ImportCallback,
+    ) -> Result<()> {
+        crate::os::util::module_import_list_callback(self, info, callback)
+    }
+
+    fn module_export_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ExportCallback,
+    ) -> Result<()> {
+        crate::os::util::module_export_list_callback(self, info, callback)
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: SectionCallback,
+    ) -> Result<()> {
+        crate::os::util::module_section_list_callback(self, info, callback)
+    }
+
+    fn info(&self) -> &ProcessInfo {
+        &self.info
+    }
+
+    fn mapped_mem_range(
+        &mut self,
+        gap_size: imem,
+        start: Address,
+        end: Address,
+        out: MemoryRangeCallback,
+    ) {
+        let regions = sys::regions(&self.handle).unwrap_or_default();
+        GapRemover::new(out, gap_size, start, end).extend(
+            regions
+                .into_iter()
+                .map(|r| CTup3(r.base, r.size, r.page_type)),
+        )
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl MemoryView for NativeProcess {
+    fn read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.handle.read(addr, &mut buf) {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, buf) in inp {
+            if self.handle.write(addr, &buf) {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let arch_bits = match self.info.proc_arch {
+            ArchitectureIdent::X86(bits, _) => bits,
+            _ => 64,
+        };
+        let max_address = if arch_bits == 32 {
+            Address::from(0xffff_ffffu64)
+        } else {
+            Address::from(usize::MAX as umem)
+        };
+        MemoryViewMetadata {
+            max_address,
+            real_size: max_address.to_umem().saturating_add(1),
+            readonly: false,
+            little_endian: cfg!(target_endian = "little"),
+            arch_bits,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::prelude::v1::*;
+
+    use std::fs;
+    use std::os::raw::{c_int, c_ulong, c_void};
+
+    use super::{modules_from_regions, Region};
+    use crate::architecture::ArchitectureIdent;
+    use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+    use crate::os::{ModuleInfo, OsVersion, Pid, ProcessInfo, ProcessState};
+    use crate::types::{umem, Address, PageType};
+
+    #[repr(C)]
+    struct IoVec {
+        base: *mut c_void,
+        len: usize,
+    }
+
+    extern "C" {
+        fn process_vm_readv(
+            pid: c_int,
+            local_iov: *const IoVec,
+            liovcnt: c_ulong,
+            remote_iov: *const IoVec,
+            riovcnt: c_ulong,
+            flags: c_ulong,
+        ) -> isize;
+        fn process_vm_writev(
+            pid: c_int,
+            local_iov: *const IoVec,
+            liovcnt: c_ulong,
+            remote_iov: *const IoVec,
+            riovcnt: c_ulong,
+            flags: c_ulong,
+        ) -> isize;
+    }
+
+    pub fn os_version() -> OsVersion {
+        // e.g. `6.1.0-18-amd64`
+        let release = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+        let mut parts = release
+            .trim()
+            .split(|c: char| !c.is_ascii_digit())
+            .map(|p| p.parse::<u32>().unwrap_or_default());
+        OsVersion {
+            major: parts.next().unwrap_or_default(),
+            minor: parts.next().unwrap_or_default(),
+            build: parts.next().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn pids() -> Result<Vec<Pid>> {
+        let entries = fs::read_dir("/proc").map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir).log_error(err)
+        })?;
+        let mut ret = entries
+            .flatten()
+            .filter_map(|e| e.file_name().to_str()?.parse::<Pid>().ok())
+            .collect::<Vec<_>>();
+        ret.sort_unstable();
+        Ok(ret)
+    }
+
+    /// Parses the state of `/proc/<pid>/stat`.
+    pub fn parse_stat(stat: &str) -> ProcessState {
+        // the name in parentheses may contain spaces, fields are counted after it
+        let fields = match stat.rfind(')') {
+            Some(pos) => stat[pos + 1..].split_whitespace().collect::<Vec<_>>(),
+            None => return ProcessState::Unknown,
+        };
+        match fields.first() {
+            Some(&"Z") | Some(&"X") | Some(&"x") => fields
+                .get(49)
+                .and_then(|code| code.parse::<i32>().ok())
+                .map(|code| ProcessState::Dead((code >> 8) & 0xff))
+                .unwrap_or(ProcessState::Unknown),
+            Some(_) => ProcessState::Alive,
+            None => ProcessState::Unknown,
+        }
+    }
+
+    pub fn process_info(pid: Pid, sys_arch: ArchitectureIdent) -> Result<ProcessInfo> {
+        let dir = format!("/proc/{}", pid);
+        let stat = fs::read_to_string(format!("{}/stat", dir)).map_err(|_| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound)
+                .log_trace(format!("process {} not found", pid))
+        })?;
+
+        let name = fs::read_to_string(format!("{}/comm", dir)).unwrap_or_default();
+        let path = fs::read_link(format!("{}/exe", dir))
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let command_line = fs::read(format!("{}/cmdline", dir))
+            .map(|c| {
+                c.split(|&b| b == 0)
+                    .filter(|a| !a.is_empty())
+                    .map(String::from_utf8_lossy)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+
+        // the elf class tells 32 bit processes apart
+        let proc_arch = match (sys_arch, fs::read(format!("{}/exe", dir))) {
+            (ArchitectureIdent::X86(64, _), Ok(elf)) if elf.get(4) == Some(&1) => {
+                ArchitectureIdent::X86(32, false)
+            }
+            _ => sys_arch,
+        };
+
+        Ok(ProcessInfo {
+            address: Address::from(pid as umem),
+            pid,
+            state: parse_stat(&stat),
+            name: name.trim_end().into(),
+            path: path.as_str().into(),
+            command_line: command_line.as_str().into(),
+            sys_arch,
+            proc_arch,
+        })
+    }
+
+    /// Parses the lines of `/proc/<pid>/maps`.
+    pub(super) fn parse_maps(maps: &str) -> Vec<Region> {
+        maps.lines()
+            .filter_map(|line| {
+                // start-end perms offset dev inode [path]
+                let mut fields = line.splitn(6, ' ');
+                let (start, end) = fields.next()?.split_once('-')?;
+                let perms = fields.next()?.as_bytes();
+                let path = fields.nth(3).unwrap_or_default().trim_start();
+
+                let start = u64::from_str_radix(start, 16).ok()?;
+                let end = u64::from_str_radix(end, 16).ok()?;
+                if perms.first() != Some(&b'r') {
+                    return None;
+                }
+
+                let mut page_type = PageType::USER;
+                page_type |= if perms.get(1) == Some(&b'w') {
+                    PageType::WRITEABLE
+                } else {
+                    PageType::READ_ONLY
+                };
+                page_type |= if perms.get(2) == Some(&b'x') {
+                    PageType::EXECUTABLE
+                } else {
+                    PageType::NOEXEC
+                };
+
+                Some(Region {
+                    base: Address::from(start),
+                    size: (end - start) as umem,
+                    page_type,
+                    path: path.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    pub(super) fn regions(handle: &Handle) -> Result<Vec<Region>> {
+        fs::read_to_string(format!("/proc/{}/maps", handle.pid))
+            .map(|maps| parse_maps(&maps))
+            .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(err))
+    }
+
+    pub fn modules(handle: &Handle, info: &ProcessInfo) -> Result<Vec<ModuleInfo>> {
+        Ok(modules_from_regions(
+            &regions(handle)?,
+            info.address,
+            info.proc_arch,
+        ))
+    }
+
+    /// Processes are accessed by their id, there is nothing to close.
+    pub struct Handle {
+        pid: Pid,
+    }
+
+    impl Handle {
+        pub fn open(pid: Pid) -> Result<Self> {
+            Ok(Self { pid })
+        }
+
+        pub fn state(&self) -> ProcessState {
+            fs::read_to_string(format!("/proc/{}/stat", self.pid))
+                .map(|stat| parse_stat(&stat))
+                .unwrap_or(ProcessState::Dead(0))
+        }
+
+        pub fn read(&self, addr: Address, buf: &mut [u8]) -> bool {
+            if buf.is_empty() {
+                return true;
+            }
+            let local = IoVec {
+                base: buf.as_mut_ptr() as *mut c_void,
+                len: buf.len(),
+            };
+            let remote = IoVec {
+                base: addr.to_umem() as usize as *mut c_void,
+                len: buf.len(),
+            };
+            let ret = unsafe { process_vm_readv(self.pid as c_int, &local, 1, &remote, 1, 0) };
+            ret == buf.len() as isize
+        }
+
+        pub fn write(&self, addr: Address, buf: &[u8]) -> bool {
+            if buf.is_empty() {
+                return true;
+            }
+            let local = IoVec {
+                base: buf.as_ptr() as *mut c_void,
+                len: buf.len(),
+            };
+            let remote = IoVec {
+                base: addr.to_umem() as usize as *mut c_void,
+                len: buf.len(),
+            };
+            let ret = unsafe { process_vm_writev(self.pid as c_int, &local, 1, &remote, 1, 0) };
+            ret == buf.len() as isize
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::prelude::v1::*;
+
+    use std::ffi::c_void;
+
+    use super::{modules_from_regions, Region};
+    use crate::architecture::ArchitectureIdent;
+    use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+    use crate::os::{ModuleInfo, OsVersion, Pid, ProcessInfo, ProcessState};
+    use crate::types::{umem, Address, PageType};
+
+    const PROCESS_VM_OPERATION: u32 = 0x0008;
+    const PROCESS_VM_READ: u32 = 0x0010;
+    const PROCESS_VM_WRITE: u32 = 0x0020;
+    const PROCESS_QUERY_INFORMATION: u32 = 0x0400;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+
+    const TH32CS_SNAPPROCESS: u32 = 0x2;
+    const TH32CS_SNAPMODULE: u32 = 0x8;
+    const TH32CS_SNAPMODULE32: u32 = 0x10;
+    const INVALID_HANDLE_VALUE: *mut c_void = !0 as *mut c_void;
+
+    const STILL_ACTIVE: u32 = 259;
+    const MEM_COMMIT: u32 = 0x1000;
+    const PAGE_NOACCESS: u32 = 0x01;
+    const PAGE_GUARD: u32 = 0x100;
+    /// `PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY`
+    const PAGE_WRITEABLE: u32 = 0x04 | 0x08 | 0x40 | 0x80;
+    /// `PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY`
+    const PAGE_EXECUTABLE: u32 = 0x10 | 0x20 | 0x40 | 0x80;
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct ProcessEntry32W {
+        size: u32,
+        usage: u32,
+        process_id: u32,
+        default_heap_id: usize,
+        module_id: u32,
+        threads: u32,
+        parent_process_id: u32,
+        pri_class_base: i32,
+        flags: u32,
+        exe_file: [u16; 260],
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct ModuleEntry32W {
+        size: u32,
+        module_id: u32,
+        process_id: u32,
+        glbl_usage: u32,
+        proc_usage: u32,
+        base_addr: *mut u8,
+        base_size: u32,
+        module: *mut c_void,
+        module_name: [u16; 256],
+        exe_path: [u16; 260],
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct MemoryBasicInformation {
+        base_address: *mut c_void,
+        allocation_base: *mut c_void,
+        allocation_protect: u32,
+        #[cfg(target_pointer_width = "64")]
+        partition_id: u16,
+        region_size: usize,
+        state: u32,
+        protect: u32,
+        type_: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+        fn GetExitCodeProcess(handle: *mut c_void, code: *mut u32) -> i32;
+        fn IsWow64Process(handle: *mut c_void, wow64: *mut i32) -> i32;
+        fn QueryFullProcessImageNameW(
+            handle: *mut c_void,
+            flags: u32,
+            name: *mut u16,
+            size: *mut u32,
+        ) -> i32;
+        fn ReadProcessMemory(
+            handle: *mut c_void,
+            base: *const c_void,
+            buf: *mut c_void,
+            size: usize,
+            read: *mut usize,
+        ) -> i32;
+        fn WriteProcessMemory(
+            handle: *mut c_void,
+            base: *mut c_void,
+            buf: *const c_void,
+            size: usize,
+            written: *mut usize,
+        ) -> i32;
+        fn VirtualQueryEx(
+            handle: *mut c_void,
+            addr: *const c_void,
+            info: *mut MemoryBasicInformation,
+            len: usize,
+        ) -> usize;
+        fn CreateToolhelp32Snapshot(flags: u32, pid: u32) -> *mut c_void;
+        fn Process32FirstW(snapshot: *mut c_void, entry: *mut ProcessEntry32W) -> i32;
+        fn Process32NextW(snapshot: *mut c_void, entry: *mut ProcessEntry32W) -> i32;
+        fn Module32FirstW(snapshot: *mut c_void, entry: *mut ModuleEntry32W) -> i32;
+        fn Module32NextW(snapshot: *mut c_void, entry: *mut ModuleEntry32W) -> i32;
+    }
+
+    fn from_wide(s: &[u16]) -> String {
+        let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+        String::from_utf16_lossy(&s[..len])
+    }
+
+    /// Toolhelp snapshot, closed on drop.
+    struct Snapshot(*mut c_void);
+
+    impl Snapshot {
+        fn new(flags: u32, pid: Pid) -> Result<Self> {
+            let handle = unsafe { CreateToolhelp32Snapshot(flags, pid) };
+            if handle == INVALID_HANDLE_VALUE {
+                Err(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+                    .log_error("CreateToolhelp32Snapshot failed"))
+            } else {
+                Ok(Self(handle))
+            }
+        }
+    }
+
+    impl Drop for Snapshot {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    fn process_entries() -> Result<Vec<ProcessEntry32W>> {
+        let snapshot = Snapshot::new(TH32CS_SNAPPROCESS, 0)?;
+        let mut ret = vec![];
+        let mut entry: ProcessEntry32W = unsafe { std::mem::zeroed() };
+        entry.size = std::mem::size_of::<ProcessEntry32W>() as u32;
+        let mut ok = unsafe { Process32FirstW(snapshot.0, &mut entry) };
+        while ok != 0 {
+            ret.push(entry);
+            ok = unsafe { Process32NextW(snapshot.0, &mut entry) };
+        }
+        Ok(ret)
+    }
+
+    pub fn os_version() -> OsVersion {
+        OsVersion::default()
+    }
+
+    pub fn pids() -> Result<Vec<Pid>> {
+        Ok(process_entries()?
+            .into_iter()
+            .map(|e| e.process_id)
+            .collect())
+    }
+
+    pub fn process_info(pid: Pid, sys_arch: ArchitectureIdent) -> Result<ProcessInfo> {
+        let entry = process_entries()?
+            .into_iter()
+            .find(|e| e.process_id == pid)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound)
+                    .log_trace(format!("process {} not found", pid))
+            })?;
+
+        let (path, state, proc_arch) = match Handle::open(pid) {
+            Ok(handle) => {
+                let mut name = [0u16; 1024];
+                let mut size = name.len() as u32;
+                let path = if unsafe {
+                    QueryFullProcessImageNameW(handle.0, 0, name.as_mut_ptr(), &mut size)
+                } != 0
+                {
+                    from_wide(&name[..size as usize])
+                } else {
+                    String::new()
+                };
+
+                let mut wow64 = 0;
+                let proc_arch =
+                    if unsafe { IsWow64Process(handle.0, &mut wow64) } != 0 && wow64 != 0 {
+                        ArchitectureIdent::X86(32, false)
+                    } else {
+                        sys_arch
+                    };
+
+                (path, handle.state(), proc_arch)
+            }
+            Err(_) => (String::new(), ProcessState::Unknown, sys_arch),
+        };
+
+        Ok(ProcessInfo {
+            address: Address::from(pid as umem),
+            pid,
+            state,
+            name: from_wide(&entry.exe_file).as_str().into(),
+            path: path.as_str().into(),
+            command_line: "".into(),
+            sys_arch,
+            proc_arch,
+        })
+    }
+
+    pub(super) fn regions(handle: &Handle) -> Result<Vec<Region>> {
+        let mut ret = vec![];
+        let mut addr = 0usize;
+        loop {
+            let mut info: MemoryBasicInformation = unsafe { std::mem::zeroed() };
+            let len = unsafe {
+                VirtualQueryEx(
+                    handle.0,
+                    addr as *const c_void,
+                    &mut info,
+                    std::mem::size_of::<MemoryBasicInformation>(),
+                )
+            };
+            if len == 0 {
+                break;
+            }
+
+            let protect = info.protect;
+            if info.state == MEM_COMMIT && protect & (PAGE_NOACCESS | PAGE_GUARD) == 0 {
+                let mut page_type = PageType::USER;
+                page_type |= if protect & PAGE_WRITEABLE != 0 {
+                    PageType::WRITEABLE
+                } else {
+                    PageType::READ_ONLY
+                };
+                page_type |= if protect & PAGE_EXECUTABLE != 0 {
+                    PageType::EXECUTABLE
+                } else {
+                    PageType::NOEXEC
+                };
+                ret.push(Region {
+                    base: Address::from(info.base_address as umem),
+                    size: info.region_size as umem,
+                    page_type,
+                    path: String::new(),
+                });
+            }
+
+            addr = match (info.base_address as usize).checked_add(info.region_size) {
+                Some(next) if next > addr => next,
+                _ => break,
+            };
+        }
+        Ok(ret)
+    }
+
+    pub fn modules(_handle: &Handle, info: &ProcessInfo) -> Result<Vec<ModuleInfo>> {
+        let snapshot = Snapshot::new(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, info.pid)?;
+
+        let mut regions = vec![];
+        let mut entry: ModuleEntry32W = unsafe { std::mem::zeroed() };
+        entry.size = std::mem::size_of::<ModuleEntry32W>() as u32;
+        let mut ok = unsafe { Module32FirstW(snapshot.0, &mut entry) };
+        while ok != 0 {
+            regions.push(Region {
+                base: Address::from(entry.base_addr as umem),
+                size: entry.base_size as umem,
+                page_type: PageType::UNKNOWN,
+                path: from_wide(&entry.exe_path),
+            });
+            ok = unsafe { Module32NextW(snapshot.0, &mut entry) };
+        }
+
+        Ok(modules_from_regions(&regions, info.address, info.proc_arch))
+    }
+
+    /// Process handle, closed on drop.
+    pub struct Handle(*mut c_void);
+
+    // process handles can be used from any thread
+    unsafe impl Send for Handle {}
+    unsafe impl Sync for Handle {}
+
+    impl Handle {
+        pub fn open(pid: Pid) -> Result<Self> {
+            let full = PROCESS_VM_OPERATION
+                | PROCESS_VM_READ
+                | PROCESS_VM_WRITE
+                | PROCESS_QUERY_INFORMATION;
+            let mut handle = unsafe { OpenProcess(full, 0, pid) };
+            if handle.is_null() {
+                // fall back to read only access
+                handle = unsafe {
+                    OpenProcess(PROCESS_VM_READ | PROCESS_QUERY_LIMITED_INFORMATION, 0, pid)
+                };
+            }
+
+            if handle.is_null() {
+                Err(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound)
+                    .log_error(format!("unable to open process {}", pid)))
+            } else {
+                Ok(Self(handle))
+            }
+        }
+
+        pub fn state(&self) -> ProcessState {
+            let mut code = 0;
+            if unsafe { GetExitCodeProcess(self.0, &mut code) } == 0 {
+                ProcessState::Unknown
+            } else if code == STILL_ACTIVE {
+                ProcessState::Alive
+            } else {
+                ProcessState::Dead(code as i32)
+            }
+        }
+
+        pub fn read(&self, addr: Address, buf: &mut [u8]) -> bool {
+            let mut read = 0;
+            buf.is_empty()
+                || unsafe {
+                    ReadProcessMemory(
+                        self.0,
+                        addr.to_umem() as usize as *const c_void,
+                        buf.as_mut_ptr() as *mut c_void,
+                        buf.len(),
+                        &mut read,
+                    )
+                } != 0
+                    && read == buf.len()
+        }
+
+        pub fn write(&self, addr: Address, buf: &[u8]) -> bool {
+            let mut written = 0;
+            buf.is_empty()
+                || unsafe {
+                    WriteProcessMemory(
+                        self.0,
+                        addr.to_umem() as usize as *mut c_void,
+                        buf.as_ptr() as *const c_void,
+                        buf.len(),
+                        &mut written,
+                    )
+                } != 0
+                    && written == buf.len()
+        }
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    #[test]
+    fn parse_maps() {
+        let maps = "\
+55d0c5a00000-55d0c5a2c000 r--p 00000000 fd:01 1835017                    /usr/bin/bash
+55d0c5a2c000-55d0c5af3000 r-xp 0002c000 fd:01 1835017                    /usr/bin/bash
+55d0c5b26000-55d0c5b2a000 rw-p 00125000 fd:01 1835017                    /usr/bin/bash
+55d0c5b2a000-55d0c5b35000 rw-p 00000000 00:00 0
+55d0c6e1e000-55d0c6f9b000 rw-p 00000000 00:00 0                          [heap]
+7f1e2b400000-7f1e2b428000 r--p 00000000 fd:01 1838293                    /usr/lib/libc.so.6
+7ffd1b7fd000-7ffd1b7ff000 ---p 00000000 00:00 0
+";
+        let regions = sys::parse_maps(maps);
+        assert_eq!(regions.len(), 6);
+        assert_eq!(regions[1].base, Address::from(0x55d0_c5a2_c000u64));
+        assert!(regions[1].page_type.contains(PageType::EXECUTABLE));
+        assert!(regions[2].page_type.contains(PageType::WRITEABLE));
+        assert_eq!(regions[4].path, "[heap]");
+
+        let arch = ArchitectureIdent::X86(64, false);
+        let modules = modules_from_regions(&regions, Address::from(1234u64), arch);
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[0].name.as_ref(), "bash");
+        assert_eq!(modules[0].base, Address::from(0x55d0_c5a0_0000u64));
+        assert_eq!(modules[0].size, 0x12a000);
+        assert_eq!(modules[1].path.as_ref(), "/usr/lib/libc.so.6");
+    }
+
+    #[test]
+    fn parse_stat() {
+        assert_eq!(
+            sys::parse_stat("42 (a (weird) name) S 1 42 42 0 -1"),
+            ProcessState::Alive
+        );
+        let zombie = format!("42 (name) Z {}256", "0 ".repeat(48));
+        assert_eq!(sys::parse_stat(&zombie), ProcessState::Dead(1));
+    }
+
+    #[test]
+    fn current_process() {
+        let mut proc = NativeOs::current_process().unwrap();
+        assert_eq!(proc.info().pid, std::process::id());
+        assert!(proc.state().is_alive());
+        assert!(proc.primary_module().is_ok());
+
+        let mut value = 0x1122_3344_5566_7788u64;
+        let addr = Address::from(&mut value as *mut u64 as usize as umem);
+        assert_eq!(proc.read::<u64>(addr).unwrap(), 0x1122_3344_5566_7788);
+
+        proc.write(addr, &0xdead_beefu64).unwrap();
+        assert_eq!(unsafe { std::ptr::read_volatile(&value) }, 0xdead_beef);
+
+        assert!(proc.read::<u64>(Address::NULL).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Access to per-CPU variables of Linux kernels.
+//!
+//! Linux keeps a separate copy of every per-CPU variable (e.g. `current_task` or `runqueues`) for
+//! each CPU. The address of the copy of a CPU is the address of the variable in the per-CPU
+//! section plus the entry of that CPU in the `__per_cpu_offset` array.
+//!
+//! [`PerCpuOffsets`] reads that array once and resolves per-CPU variables for every CPU. The
+//! addresses of `__per_cpu_offset` and of the variables themselves can be obtained through
+//! [`read_kallsyms`](super::read_kallsyms).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::percpu::PerCpuOffsets;
+//!
+//! fn current_tasks(
+//!     mem: &mut impl MemoryView,
+//!     per_cpu_offset: Address,
+//!     current_task: Address,
+//! ) -> Result<Vec<Address>> {
+//!     let offsets = PerCpuOffsets::read(mem, per_cpu_offset, 64)?;
+//!     let tasks = offsets.read_all::<u64>(mem, current_task)?;
+//!     Ok(tasks.into_iter().map(Address::from).collect())
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # let mut mem = DummyMemory::new(size::mb(1));
+//! # mem.phys_write(0x1000.into(), &[0x10000u64, 0x20000]).unwrap();
+//! # mem.phys_write(0x10008.into(), &0xaaaau64).unwrap();
+//! # mem.phys_write(0x20008.into(), &0xbbbbu64).unwrap();
+//! # let tasks = current_tasks(&mut mem.phys_view(), 0x1000.into(), 0x8.into()).unwrap();
+//! # assert_eq!(tasks, vec![Address::from(0xaaaau64), Address::from(0xbbbbu64)]);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::dataview::Pod;
+use crate::error::{PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Per-CPU offsets of a Linux kernel, read from `__per_cpu_offset`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PerCpuOffsets {
+    offsets: Vec<umem>,
+}
+
+impl PerCpuOffsets {
+    /// Creates the offsets from a list of per-CPU offsets, indexed by CPU number.
+    pub fn new(offsets: Vec<umem>) -> Self {
+        Self { offsets }
+    }
+
+    /// Reads up to `nr_cpu_ids` 64-bit entries of the `__per_cpu_offset` array at `addr`.
+    ///
+    /// The array is sized for the maximum number of CPUs the kernel supports, while only the
+    /// entries of possible CPUs are set. Reading stops at the first unset (zero) entry following
+    /// the boot CPU.
+    pub fn read(mem: &mut impl MemoryView, addr: Address, nr_cpu_ids: usize) -> Result<Self> {
+        let mut offsets = vec![0u64; nr_cpu_ids];
+        mem.read_into(addr, &mut offsets[..]).data()?;
+
+        let count = offsets
+            .iter()
+            .skip(1)
+            .position(|&o| o == 0)
+            .map_or(offsets.len(), |n| n + 1);
+        offsets.truncate(count);
+
+        Ok(Self::new(offsets.into_iter().map(|o| o as umem).collect()))
+    }
+
+    /// Returns the number of CPUs.
+    pub fn cpu_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns the offsets of all CPUs.
+    pub fn offsets(&self) -> &[umem] {
+        &self.offsets
+    }
+
+    /// Returns the address of the copy of the per-CPU variable at `var` that belongs to `cpu`.
+    pub fn address(&self, var: Address, cpu: usize) -> Option<Address> {
+        self.offsets
+            .get(cpu)
+            .map(|&offset| var.wrapping_add(offset.into()))
+    }
+
+    /// Returns the addresses of the copies of the per-CPU variable at `var` of all CPUs.
+    pub fn addresses(&self, var: Address) -> impl Iterator<Item = Address> + '_ {
+        self.offsets
+            .iter()
+            .map(move |&offset| var.wrapping_add(offset.into()))
+    }
+
+    /// Reads the copy of the per-CPU variable at `var` that belongs to `cpu`.
+    ///
+    /// Returns `None` if the cpu does not exist.
+    pub fn read_var<T: Pod + Sized>(
+        &self,
+        mem: &mut impl MemoryView,
+        var: Address,
+        cpu: usize,
+    ) -> Option<Result<T>> {
+        self.address(var, cpu).map(|addr| mem.read(addr).data())
+    }
+
+    /// Reads the copies of the per-CPU variable at `var` of all CPUs, ordered by CPU number.
+    pub fn read_all<T: Pod + Sized>(
+        &self,
+        mem: &mut impl MemoryView,
+        var: Address,
+    ) -> Result<Vec<T>> {
+        self.addresses(var)
+            .map(|addr| mem.read(addr).data())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[test]
+    fn unset_cpus() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x1000.into(), &[0x10000u64, 0x20000, 0, 0])
+            .unwrap();
+        mem.phys_write(0x20010.into(), &7u32).unwrap();
+
+        let mut view = mem.phys_view();
+        let offsets = PerCpuOffsets::read(&mut view, 0x1000.into(), 4).unwrap();
+
+        assert_eq!(offsets.cpu_count(), 2);
+        assert_eq!(offsets.address(0x10.into(), 1), Some(0x20010u64.into()));
+        assert_eq!(offsets.address(0x10.into(), 2), None);
+        assert_eq!(
+            offsets
+                .read_var::<u32>(&mut view, 0x10.into(), 1)
+                .unwrap()
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            offsets.read_all::<u32>(&mut view, 0x10.into()).unwrap(),
+            vec![0, 7]
+        );
+    }
+}
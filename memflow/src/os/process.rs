@@ -1,5 +1,6 @@
 //! Describes process context
 
+use super::consistency::{with_consistency, Consistency, EnumerationOptions};
 use super::{
     ExportCallback, ExportInfo, ImportCallback, ImportInfo, ModuleAddressInfo, ModuleInfo,
     ModuleInfoCallback, SectionCallback, SectionInfo,
@@ -96,6 +97,38 @@ pub trait Process: Send {
             .module_address_list_callback(target_arch, inner_callback.into())
     }
 
+    /// Retrieves the result of reading every module of the process
+    ///
+    /// Unlike [`module_list_arch`](Self::module_list_arch), which skips modules that could not be
+    /// read, this returns the address of every module structure along with the result of
+    /// reading it, in list order. If any module failed, all results are returned as a
+    /// [`PartialError::PartialVirtualRead`].
+    ///
+    /// # Arguments
+    /// * `target_arch` - sets which architecture to retrieve the modules for (if emulated). Choose
+    /// between `Some(ProcessInfo::sys_arch())`, and `Some(ProcessInfo::proc_arch())`. `None` for all.
+    #[skip_func]
+    fn module_list_partial(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+    ) -> PartialResult<Vec<(Address, Result<ModuleInfo>)>> {
+        let mut addrs = vec![];
+        self.module_address_list_callback(target_arch, (&mut addrs).into())?;
+
+        let results = addrs
+            .into_iter()
+            .map(|ModuleAddressInfo { address, arch }| {
+                (address, self.module_by_address(address, arch))
+            })
+            .collect::<Vec<_>>();
+
+        if results.iter().any(|(_, res)| res.is_err()) {
+            Err(PartialError::PartialVirtualRead(results))
+        } else {
+            Ok(results)
+        }
+    }
+
     /// Retrieves a module by its structure address and architecture
     ///
     /// # Arguments
@@ -165,6 +198,48 @@ pub trait Process: Send {
         self.module_list_arch(None)
     }
 
+    /// Retrieves a module list for the process with the given enumeration options
+    ///
+    /// With [`Consistency::BestEffort`] this is equivalent to
+    /// [`module_list_arch`](Self::module_list_arch). Otherwise the module address list is read
+    /// again after reading every module, and the enumeration is repeated if it changed.
+    ///
+    /// # Arguments
+    /// * `target_arch` - sets which architecture to retrieve the modules for (if emulated). Choose
+    /// between `Some(ProcessInfo::sys_arch())`, and `Some(ProcessInfo::proc_arch())`. `None` for all.
+    /// * `options` - how concurrent modifications of the module list are dealt with
+    #[skip_func]
+    fn module_list_with(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+        options: EnumerationOptions,
+    ) -> Result<Vec<ModuleInfo>> {
+        if options.consistency == Consistency::BestEffort {
+            return self.module_list_arch(target_arch);
+        }
+
+        with_consistency(
+            self,
+            options.consistency,
+            |proc| {
+                let mut addrs = vec![];
+                let callback = &mut |info: ModuleAddressInfo| {
+                    addrs.push((info.address, info.arch));
+                    true
+                };
+                proc.module_address_list_callback(target_arch, callback.into())?;
+                Ok(addrs)
+            },
+            |proc, addrs| {
+                Ok(addrs
+                    .iter()
+                    .filter_map(|&(address, arch)| proc.module_by_address(address, arch).ok())
+                    .collect())
+            },
+        )
+        .map(|read| read.value)
+    }
+
     /// Retrieves address of the primary module structure of the process
     ///
     /// This will generally be for the initial executable that was run
@@ -268,6 +343,38 @@ pub trait Process: Send {
         ret
     }
 
+    /// Formats `address` as `module!export+0x1234`
+    ///
+    /// The address is resolved using the bounds of the process modules and their export tables.
+    /// See [`format_address`](super::module::format_address) for details on the output format.
+    #[skip_func]
+    fn format_address(&mut self, address: Address) -> String {
+        let module = self
+            .module_list()
+            .ok()
+            .and_then(|modules| modules.into_iter().find(|m| m.contains(address)));
+
+        match module {
+            Some(module) => {
+                let exports = self.module_export_list(&module).unwrap_or_default();
+                super::module::format_address(address, Some(&module), &exports)
+            }
+            None => super::module::format_address(address, None, &[]),
+        }
+    }
+
+    /// Retrieves the location of the environment block of the process
+    ///
+    /// On Windows this is the `Environment` of the process parameters referenced by the PEB, on
+    /// Linux the `environ` area on the initial stack. OS layers that can not locate the block
+    /// return an error of kind [`ErrorKind::UnsupportedOptionalFeature`].
+    fn environment_block(&mut self) -> Result<EnvironmentBlock> {
+        Err(Error(
+            ErrorOrigin::OsLayer,
+            ErrorKind::UnsupportedOptionalFeature,
+        ))
+    }
+
     /// Retrieves the process info
     fn info(&self) -> &ProcessInfo;
 
@@ -301,6 +408,61 @@ pub trait Process: Send {
         self.mapped_mem(gap_size, (&mut out).into());
         out
     }
+
+    /// Retrieves a consolidated memory map of the process with owner attribution
+    ///
+    /// The map consists of the mapped memory of the process, annotated with the module images
+    /// and the environment block. OS layers that are able to locate heaps, thread stacks and
+    /// TEBs override this function to annotate them as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// use memflow::os::memory_map::RegionKind;
+    /// # use memflow::dummy::DummyOs;
+    ///
+    /// fn print_memory_map(proc: &mut impl Process) -> Result<()> {
+    ///     let report = proc.memory_map_report()?;
+    ///     println!("{}", report);
+    ///     println!("images: {:#x} bytes", report.total_size(RegionKind::Image));
+    ///     Ok(())
+    /// }
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # print_memory_map(&mut proc).unwrap();
+    /// ```
+    #[skip_func]
+    fn memory_map_report(&mut self) -> Result<super::memory_map::MemoryMapReport> {
+        use super::memory_map::{MemoryMapReport, RegionKind};
+
+        let mut report = MemoryMapReport::new(self.mapped_mem_vec(0));
+
+        for module in self.module_list()? {
+            report.annotate(RegionKind::Image, module.base, module.size, &module.name);
+        }
+
+        if let Ok(env) = self.environment_block() {
+            report.annotate(RegionKind::Environment, env.address, env.size, "");
+        }
+
+        Ok(report)
+    }
+}
+
+/// Location of the environment variables of a process
+///
+/// The block consists of null terminated `NAME=VALUE` entries, followed by an empty entry.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct EnvironmentBlock {
+    /// Address of the first entry
+    pub address: Address,
+    /// Size of the block in bytes
+    pub size: umem,
+    /// True if the entries are encoded as UTF-16, false for UTF-8
+    pub wide: bool,
 }
 
 /// Process information structure
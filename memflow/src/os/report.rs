@@ -0,0 +1,277 @@
+//! Versioned serialization of OS information and triage reports.
+//!
+//! All OS information types ([`ProcessInfo`](super::ProcessInfo),
+//! [`ModuleInfo`](super::ModuleInfo), [`SectionInfo`](super::SectionInfo), ...) implement
+//! `Serialize` and `Deserialize` when the `serde` feature is enabled. Their field names match the
+//! Rust field names and are considered part of the public API.
+//!
+//! Tools that persist reports should wrap the data in a [`Report`], which records the
+//! [`REPORT_VERSION`] the data was written with. The version is bumped whenever a field of one of
+//! the info types is renamed, removed or changes its meaning.
+//!
+//! [`triage`] gathers processes, their modules, kernel modules and suspicious findings of an OS
+//! into a single [`TriageReport`] in one call, which is useful as a forensic baseline.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::report::Report;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn report(proc: &mut impl Process) -> Result<Report<Vec<ModuleInfo>>> {
+//!     Ok(Report::new(proc.module_list()?))
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let report = report(&mut proc).unwrap();
+//! # assert!(report.into_data().is_ok());
+//! ```
+
+use std::prelude::v1::*;
+
+use super::{ModuleInfo, Os, OsInfo, Pid, Process, ProcessInfo};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+/// Current version of the serialized OS information layout.
+pub const REPORT_VERSION: u32 = 1;
+
+/// Versioned envelope around serialized OS information.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Report<T> {
+    /// Layout version the data was written with
+    pub version: u32,
+    /// Report contents
+    pub data: T,
+}
+
+impl<T> Report<T> {
+    /// Wraps `data` in a report of the current version.
+    pub fn new(data: T) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            data,
+        }
+    }
+
+    /// Returns the report contents if the report has been written with the current version.
+    pub fn into_data(self) -> Result<T> {
+        if self.version == REPORT_VERSION {
+            Ok(self.data)
+        } else {
+            Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::VersionMismatch).log_warn(format_args!(
+                    "report version {} does not match the supported version {}",
+                    self.version, REPORT_VERSION
+                )),
+            )
+        }
+    }
+}
+
+/// Sections to include in a [`TriageReport`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TriageConfig {
+    /// Collect the process list
+    pub processes: bool,
+    /// Collect the module list of every process (requires `processes`)
+    pub modules: bool,
+    /// Collect the kernel module list
+    pub kernel_modules: bool,
+    /// Run consistency checks on the collected data and report suspicious findings
+    pub findings: bool,
+}
+
+impl Default for TriageConfig {
+    fn default() -> Self {
+        Self {
+            processes: true,
+            modules: true,
+            kernel_modules: true,
+            findings: true,
+        }
+    }
+}
+
+/// Process entry of a [`TriageReport`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProcessReport {
+    /// Process information
+    pub info: ProcessInfo,
+    /// Modules loaded into the process, empty if modules were not collected
+    pub modules: Vec<ModuleInfo>,
+}
+
+/// Severity of a [`Finding`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Severity {
+    /// Data that could not be collected
+    Info,
+    /// Inconsistency that may indicate tampering
+    Suspicious,
+}
+
+/// Noteworthy observation made while collecting a [`TriageReport`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Finding {
+    /// Severity of the finding
+    pub severity: Severity,
+    /// Process the finding relates to, if any
+    pub pid: Option<Pid>,
+    /// Human readable description
+    pub description: String,
+}
+
+/// Structured snapshot of the state of an OS.
+///
+/// # Remarks
+///
+/// Network connections, handles and other OS specific objects are not exposed through the
+/// generic [`Os`] trait and are therefore not part of the report.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TriageReport {
+    /// Information about the OS
+    pub os: OsInfo,
+    /// All processes of the OS
+    pub processes: Vec<ProcessReport>,
+    /// All kernel modules (drivers) of the OS
+    pub kernel_modules: Vec<ModuleInfo>,
+    /// Findings of the consistency checks
+    pub findings: Vec<Finding>,
+}
+
+/// Collects a [`TriageReport`] of `os` with the sections selected in `config`.
+///
+/// Failures to collect individual processes or module lists do not abort the report, they are
+/// recorded as [`Severity::Info`] findings instead.
+pub fn triage<O: Os>(os: &mut O, config: &TriageConfig) -> Result<Report<TriageReport>> {
+    let mut findings = vec![];
+    let mut processes = vec![];
+
+    if config.processes {
+        for info in os.process_info_list()? {
+            let modules = if config.modules {
+                match os
+                    .process_by_info(info.clone())
+                    .and_then(|mut p| p.module_list())
+                {
+                    Ok(modules) => modules,
+                    Err(err) => {
+                        findings.push(Finding {
+                            severity: Severity::Info,
+                            pid: Some(info.pid),
+                            description: format!("unable to read module list: {}", err),
+                        });
+                        vec![]
+                    }
+                }
+            } else {
+                vec![]
+            };
+
+            processes.push(ProcessReport { info, modules });
+        }
+    }
+
+    let kernel_modules = if config.kernel_modules {
+        match os.module_list() {
+            Ok(modules) => modules,
+            Err(err) => {
+                findings.push(Finding {
+                    severity: Severity::Info,
+                    pid: None,
+                    description: format!("unable to read kernel module list: {}", err),
+                });
+                vec![]
+            }
+        }
+    } else {
+        vec![]
+    };
+
+    if config.findings {
+        check_processes(&processes, &mut findings);
+        check_modules(None, &kernel_modules, &mut findings);
+        for process in processes.iter() {
+            check_modules(Some(process.info.pid), &process.modules, &mut findings);
+        }
+    }
+
+    Ok(Report::new(TriageReport {
+        os: os.info().clone(),
+        processes,
+        kernel_modules,
+        findings,
+    }))
+}
+
+fn check_processes(processes: &[ProcessReport], findings: &mut Vec<Finding>) {
+    let mut pids = processes.iter().map(|p| p.info.pid).collect::<Vec<_>>();
+    pids.sort_unstable();
+
+    for pid in pids.windows(2).filter(|w| w[0] == w[1]).map(|w| w[0]) {
+        findings.push(Finding {
+            severity: Severity::Suspicious,
+            pid: Some(pid),
+            description: "process id is used by multiple processes".to_string(),
+        });
+    }
+
+    for process in processes.iter().filter(|p| p.info.name.as_ref().is_empty()) {
+        findings.push(Finding {
+            severity: Severity::Suspicious,
+            pid: Some(process.info.pid),
+            description: "process has no name".to_string(),
+        });
+    }
+}
+
+fn check_modules(pid: Option<Pid>, modules: &[ModuleInfo], findings: &mut Vec<Finding>) {
+    let mut sorted = modules.iter().collect::<Vec<_>>();
+    sorted.sort_unstable_by_key(|m| m.base);
+
+    for w in sorted.windows(2) {
+        if w[0].contains(w[1].base) {
+            findings.push(Finding {
+                severity: Severity::Suspicious,
+                pid,
+                description: format!("module {} overlaps module {}", w[1].name, w[0].name),
+            });
+        }
+    }
+
+    for module in modules.iter().filter(|m| m.name.as_ref().is_empty()) {
+        findings.push(Finding {
+            severity: Severity::Suspicious,
+            pid,
+            description: format!("unnamed module at {}", module.base),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn triage_dummy() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(64)));
+        os.alloc_process(size::mb(1), &[]);
+        os.alloc_process(size::mb(1), &[]);
+
+        let report = triage(&mut os, &TriageConfig::default())
+            .unwrap()
+            .into_data()
+            .unwrap();
+
+        assert_eq!(report.processes.len(), 2);
+        assert!(report.findings.is_empty());
+    }
+}
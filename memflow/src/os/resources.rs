@@ -0,0 +1,431 @@
+//! Parsing of PE resources from loaded images.
+//!
+//! The resource directory of a loaded PE image is mapped along with the rest of the image, so
+//! resources can be read without access to the file on disk. This module locates resources in
+//! the directory tree and decodes the `VS_VERSIONINFO` resource, which holds the file and
+//! product versions as well as descriptive strings like the company and product name.
+//!
+//! Version information is usually obtained through
+//! [`ModuleInfo::version_info`](super::ModuleInfo::version_info).
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Resource type of version information (`RT_VERSION`).
+pub const RT_VERSION: u32 = 16;
+
+/// Maximum size of a resource that is read, protecting against corrupted directories.
+pub const MAX_RESOURCE_SIZE: u32 = 0x100000;
+
+/// Signature of a `VS_FIXEDFILEINFO` structure.
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xfeef04bd;
+
+/// Four part version number of a module.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ModuleVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub build: u16,
+    pub revision: u16,
+}
+
+impl ModuleVersion {
+    /// Creates a version from the most and least significant halves stored in
+    /// `VS_FIXEDFILEINFO`.
+    pub fn from_parts(ms: u32, ls: u32) -> Self {
+        Self {
+            major: (ms >> 16) as u16,
+            minor: ms as u16,
+            build: (ls >> 16) as u16,
+            revision: ls as u16,
+        }
+    }
+}
+
+impl fmt::Display for ModuleVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.build, self.revision
+        )
+    }
+}
+
+/// Decoded `VS_VERSIONINFO` resource.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct VersionInfo {
+    /// Binary file version
+    pub file_version: Option<ModuleVersion>,
+    /// Binary product version
+    pub product_version: Option<ModuleVersion>,
+    /// Language and code page of the string table, e.g. `040904b0`
+    pub language: String,
+    /// Key value pairs of the first string table, e.g. `CompanyName`
+    pub strings: Vec<(String, String)>,
+}
+
+impl VersionInfo {
+    /// Decodes a `VS_VERSIONINFO` resource.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let root = Block::parse(data, 0)
+            .filter(|b| b.key == "VS_VERSION_INFO")
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                    .log_debug("invalid version info resource")
+            })?;
+
+        let mut ret = Self::default();
+
+        if root.value_len >= 52 {
+            let fixed = &data[root.value..root.value + 52];
+            let field = |i: usize| u32::from_le_bytes(fixed[i * 4..i * 4 + 4].try_into().unwrap());
+            if field(0) == FIXED_FILE_INFO_SIGNATURE {
+                ret.file_version = Some(ModuleVersion::from_parts(field(2), field(3)));
+                ret.product_version = Some(ModuleVersion::from_parts(field(4), field(5)));
+            }
+        }
+
+        let string_table = root
+            .children(data)
+            .find(|b| b.key == "StringFileInfo")
+            .and_then(|b| b.children(data).next());
+
+        if let Some(table) = string_table {
+            ret.language = table.key.clone();
+            ret.strings = table
+                .children(data)
+                .map(|s| {
+                    // the value length of strings is given in characters
+                    let len = std::cmp::min(s.value_len * 2, s.end.saturating_sub(s.value));
+                    let value = read_utf16(&data[s.value..s.value + len]);
+                    (s.key, value)
+                })
+                .collect();
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the value of the string `key`, e.g. `FileDescription` or `OriginalFilename`.
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the `CompanyName` string.
+    pub fn company_name(&self) -> Option<&str> {
+        self.string("CompanyName")
+    }
+
+    /// Returns the `ProductName` string.
+    pub fn product_name(&self) -> Option<&str> {
+        self.string("ProductName")
+    }
+
+    /// Returns the `FileDescription` string.
+    pub fn file_description(&self) -> Option<&str> {
+        self.string("FileDescription")
+    }
+}
+
+/// Node of the `VS_VERSIONINFO` block tree.
+struct Block {
+    key: String,
+    /// Length of the value, in bytes for binary and in characters for text values
+    value_len: usize,
+    /// Offset of the value
+    value: usize,
+    /// Offset of the first child
+    children: usize,
+    /// Offset after the end of the block
+    end: usize,
+}
+
+impl Block {
+    fn parse(data: &[u8], offset: usize) -> Option<Self> {
+        let header = data.get(offset..offset + 6)?;
+        let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let value_len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let text = u16::from_le_bytes([header[4], header[5]]) == 1;
+
+        let end = offset + len;
+        if len < 6 || end > data.len() {
+            return None;
+        }
+
+        let key_start = offset + 6;
+        let key_len = data[key_start..end]
+            .chunks_exact(2)
+            .position(|c| c == [0, 0])?;
+        let key = read_utf16(&data[key_start..key_start + key_len * 2]);
+
+        let value = align4(key_start + key_len * 2 + 2);
+        let value_bytes = if text { value_len * 2 } else { value_len };
+        let children = std::cmp::min(align4(value + value_bytes), end);
+
+        Some(Self {
+            key,
+            value_len,
+            value: std::cmp::min(value, end),
+            children,
+            end,
+        })
+    }
+
+    fn children<'a>(&self, data: &'a [u8]) -> impl Iterator<Item = Block> + 'a {
+        let end = self.end;
+        let mut offset = self.children;
+        std::iter::from_fn(move || {
+            if offset >= end {
+                return None;
+            }
+            let block = Block::parse(&data[..end], offset)?;
+            offset = align4(block.end);
+            Some(block)
+        })
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn read_utf16(data: &[u8]) -> String {
+    let chars = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars)
+}
+
+/// Returns the address and size of the first resource of type `type_id` in the PE image
+/// loaded at `base`.
+pub fn find_resource(
+    mem: &mut impl MemoryView,
+    base: Address,
+    type_id: u32,
+) -> Result<(Address, umem)> {
+    let not_found = || {
+        Error(ErrorOrigin::OsLayer, ErrorKind::NotFound).log_debug("resource not found in image")
+    };
+
+    let root = resource_directory(mem, base)?.ok_or_else(not_found)?;
+
+    // the tree has three levels: type, name and language
+    let mut dir = root;
+    let mut id = Some(type_id);
+    for _ in 0..3 {
+        let entry = directory_entry(mem, base + dir, id)?.ok_or_else(not_found)?;
+        if entry & 0x8000_0000 == 0 {
+            // a data entry, the tree ended early
+            return data_entry(mem, base, root + entry);
+        }
+        dir = root + (entry & 0x7fff_ffff);
+        id = None;
+    }
+
+    // the language level of a malformed tree may contain another directory
+    let entry = directory_entry(mem, base + dir, None)?.ok_or_else(not_found)?;
+    if entry & 0x8000_0000 != 0 {
+        return Err(not_found());
+    }
+    data_entry(mem, base, root + entry)
+}
+
+/// Reads the version information resource of the PE image loaded at `base`.
+pub fn read_version_info(mem: &mut impl MemoryView, base: Address) -> Result<VersionInfo> {
+    let (addr, size) = find_resource(mem, base, RT_VERSION)?;
+    let data = mem.read_raw(addr, size as usize).data_part()?;
+    VersionInfo::parse(&data)
+}
+
+/// Returns the RVA of the resource directory of the image at `base`.
+fn resource_directory(mem: &mut impl MemoryView, base: Address) -> Result<Option<u32>> {
+    if mem.read::<[u8; 2]>(base).data_part()? != *b"MZ" {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+            .log_debug("image does not start with a dos header"));
+    }
+
+    let nt = base + mem.read::<u32>(base + 0x3c_usize).data_part()? as umem;
+    if mem.read::<[u8; 4]>(nt).data_part()? != *b"PE\0\0" {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+            .log_debug("invalid pe signature"));
+    }
+
+    let opt = nt + 0x18_usize;
+    let data_dirs = match mem.read::<u16>(opt).data_part()? {
+        0x10b => opt + 96_usize,
+        0x20b => opt + 112_usize,
+        _ => {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("invalid optional header magic"))
+        }
+    };
+
+    // IMAGE_DIRECTORY_ENTRY_RESOURCE
+    let rva = mem.read::<u32>(data_dirs + 2 * 8_usize).data_part()?;
+    Ok(if rva == 0 { None } else { Some(rva) })
+}
+
+/// Returns the `OffsetToData` of the entry `id` of the directory at `dir`, or of its first
+/// entry if `id` is `None`.
+fn directory_entry(
+    mem: &mut impl MemoryView,
+    dir: Address,
+    id: Option<u32>,
+) -> Result<Option<u32>> {
+    let header = mem.read::<[u16; 8]>(dir).data_part()?;
+    let count = header[6] as usize + header[7] as usize;
+
+    let mut entries = vec![0u32; count * 2];
+    mem.read_into(dir + 16_usize, entries.as_mut_slice())
+        .data_part()?;
+
+    Ok(entries
+        .chunks_exact(2)
+        .find(|e| id.map(|id| e[0] == id).unwrap_or(true))
+        .map(|e| e[1]))
+}
+
+/// Reads an `IMAGE_RESOURCE_DATA_ENTRY`.
+fn data_entry(mem: &mut impl MemoryView, base: Address, entry: u32) -> Result<(Address, umem)> {
+    let [rva, size] = mem.read::<[u32; 2]>(base + entry as umem).data_part()?;
+    if size > MAX_RESOURCE_SIZE {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+            .log_debug("resource exceeds the maximum size"));
+    }
+    Ok((base + rva as umem, size as umem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    /// Encodes a block of the version info tree.
+    fn block(key: &str, value: &[u8], value_len: u16, text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut ret = vec![0u8; 6];
+        ret[2..4].copy_from_slice(&value_len.to_le_bytes());
+        ret[4..6].copy_from_slice(&(text as u16).to_le_bytes());
+        ret.extend(key.encode_utf16().chain(Some(0)).flat_map(u16::to_le_bytes));
+        ret.resize(align4(ret.len()), 0);
+        ret.extend_from_slice(value);
+        for child in children {
+            ret.resize(align4(ret.len()), 0);
+            ret.extend_from_slice(child);
+        }
+        let len = ret.len() as u16;
+        ret[0..2].copy_from_slice(&len.to_le_bytes());
+        ret
+    }
+
+    fn string(key: &str, value: &str) -> Vec<u8> {
+        let chars = value.encode_utf16().chain(Some(0)).collect::<Vec<_>>();
+        let bytes = chars
+            .iter()
+            .copied()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        block(key, &bytes, chars.len() as u16, true, &[])
+    }
+
+    fn version_info() -> Vec<u8> {
+        let mut fixed = vec![];
+        for v in [
+            FIXED_FILE_INFO_SIGNATURE,
+            0x10000,
+            0xa0000,
+            0x4a610001,
+            0xa0000,
+            0x4a610000,
+        ] {
+            fixed.extend_from_slice(&v.to_le_bytes());
+        }
+        fixed.resize(52, 0);
+
+        let table = block(
+            "040904b0",
+            &[],
+            0,
+            true,
+            &[
+                string("CompanyName", "Microsoft Corporation"),
+                string("FileDescription", "Notepad"),
+            ],
+        );
+        let strings = block("StringFileInfo", &[], 0, true, &[table]);
+        block("VS_VERSION_INFO", &fixed, 52, false, &[strings])
+    }
+
+    #[test]
+    fn parse() {
+        let info = VersionInfo::parse(&version_info()).unwrap();
+
+        assert_eq!(info.file_version.unwrap().to_string(), "10.0.19041.1");
+        assert_eq!(info.product_version.unwrap().to_string(), "10.0.19041.0");
+        assert_eq!(info.language, "040904b0");
+        assert_eq!(info.company_name(), Some("Microsoft Corporation"));
+        assert_eq!(info.file_description(), Some("Notepad"));
+        assert_eq!(info.product_name(), None);
+    }
+
+    #[test]
+    fn resource_tree() {
+        let data = version_info();
+
+        let mut image = vec![0u8; 0x2000];
+        let put = |image: &mut Vec<u8>, off: usize, data: &[u8]| {
+            image[off..off + data.len()].copy_from_slice(data)
+        };
+        put(&mut image, 0, b"MZ");
+        put(&mut image, 0x3c, &0x80u32.to_le_bytes());
+        put(&mut image, 0x80, b"PE\0\0");
+        put(&mut image, 0x98, &0x20bu16.to_le_bytes());
+        put(&mut image, 0x98 + 112 + 16, &0x1000u32.to_le_bytes());
+
+        // type, name and language directories with a single id entry each
+        let res = 0x1000;
+        for (i, (id, next)) in [
+            (RT_VERSION, 0x8000_0018u32),
+            (1, 0x8000_0030),
+            (0x409, 0x48),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let dir = res + i * 0x18;
+            put(&mut image, dir + 14, &1u16.to_le_bytes());
+            put(&mut image, dir + 16, &id.to_le_bytes());
+            put(&mut image, dir + 20, &next.to_le_bytes());
+        }
+        put(&mut image, res + 0x48, &0x1100u32.to_le_bytes());
+        put(&mut image, res + 0x4c, &(data.len() as u32).to_le_bytes());
+        put(&mut image, 0x1100, &data);
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &image);
+        let base = proc.info().address;
+
+        let (addr, size) = find_resource(&mut proc, base, RT_VERSION).unwrap();
+        assert_eq!(addr, base + 0x1100_usize);
+        assert_eq!(size, data.len() as umem);
+
+        let info = read_version_info(&mut proc, base).unwrap();
+        assert_eq!(info.company_name(), Some("Microsoft Corporation"));
+
+        assert!(find_resource(&mut proc, base, 3).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Describes the root of the Operating System
 
+use super::consistency::{with_consistency, Consistency, EnumerationOptions};
 use super::process::*;
 use super::{AddressCallback, ProcessInfo, ProcessInfoCallback};
 
@@ -67,6 +68,52 @@ pub trait Os: Send {
         Ok(ret)
     }
 
+    /// Retrieves the result of reading every process in the process list
+    ///
+    /// Unlike [`process_info_list`](Self::process_info_list), which skips processes that could
+    /// not be read, this returns the address of every process structure along with the result of
+    /// reading it, in list order. If any process failed, all results are returned as a
+    /// [`PartialError::PartialVirtualRead`].
+    #[skip_func]
+    fn process_info_list_partial(&mut self) -> PartialResult<Vec<(Address, Result<ProcessInfo>)>> {
+        let results = self
+            .process_address_list()?
+            .into_iter()
+            .map(|addr| (addr, self.process_info_by_address(addr)))
+            .collect::<Vec<_>>();
+
+        if results.iter().any(|(_, res)| res.is_err()) {
+            Err(PartialError::PartialVirtualRead(results))
+        } else {
+            Ok(results)
+        }
+    }
+
+    /// Retrieves a process list with the given enumeration options
+    ///
+    /// With [`Consistency::BestEffort`] this is equivalent to
+    /// [`process_info_list`](Self::process_info_list). Otherwise the process address list is read
+    /// again after reading every process, and the enumeration is repeated if it changed.
+    #[skip_func]
+    fn process_info_list_with(&mut self, options: EnumerationOptions) -> Result<Vec<ProcessInfo>> {
+        if options.consistency == Consistency::BestEffort {
+            return self.process_info_list();
+        }
+
+        with_consistency(
+            self,
+            options.consistency,
+            |os| os.process_address_list(),
+            |os, addrs| {
+                Ok(addrs
+                    .iter()
+                    .filter_map(|&addr| os.process_info_by_address(addr).ok())
+                    .collect())
+            },
+        )
+        .map(|read| read.value)
+    }
+
     /// Find process information by its internal address
     fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo>;
 
@@ -236,6 +283,26 @@ pub trait Os: Send {
     /// * `address` - address where module's information resides in
     fn module_by_address(&mut self, address: Address) -> Result<ModuleInfo>;
 
+    /// Retrieves the result of reading every module in the OS module list
+    ///
+    /// See [`process_info_list_partial`](Self::process_info_list_partial) for details.
+    #[skip_func]
+    fn module_list_partial(&mut self) -> PartialResult<Vec<(Address, Result<ModuleInfo>)>> {
+        let mut addrs = vec![];
+        self.module_address_list_callback((&mut addrs).into())?;
+
+        let results = addrs
+            .into_iter()
+            .map(|addr| (addr, self.module_by_address(addr)))
+            .collect::<Vec<_>>();
+
+        if results.iter().any(|(_, res)| res.is_err()) {
+            Err(PartialError::PartialVirtualRead(results))
+        } else {
+            Ok(results)
+        }
+    }
+
     /// Finds a OS module by its name
     ///
     /// This function can be useful for quickly accessing a specific module
@@ -261,6 +328,33 @@ pub trait Os: Send {
         Ok(ret)
     }
 
+    /// Retrieves a module list for the OS with the given enumeration options
+    ///
+    /// See [`process_info_list_with`](Self::process_info_list_with) for details.
+    #[skip_func]
+    fn module_list_with(&mut self, options: EnumerationOptions) -> Result<Vec<ModuleInfo>> {
+        if options.consistency == Consistency::BestEffort {
+            return self.module_list();
+        }
+
+        with_consistency(
+            self,
+            options.consistency,
+            |os| {
+                let mut addrs = vec![];
+                os.module_address_list_callback((&mut addrs).into())?;
+                Ok(addrs)
+            },
+            |os, addrs| {
+                Ok(addrs
+                    .iter()
+                    .filter_map(|&addr| os.module_by_address(addr).ok())
+                    .collect())
+            },
+        )
+        .map(|read| read.value)
+    }
+
     /// Retrieves address of the primary module of the OS
     ///
     /// This will generally be for the main kernel process/module
@@ -364,6 +458,111 @@ pub trait Os: Send {
         ret
     }
 
+    /// Formats `address` as `module!export+0x1234`
+    ///
+    /// The address is resolved using the bounds of the OS modules and their export tables.
+    /// See [`format_address`](super::module::format_address) for details on the output format.
+    #[skip_func]
+    fn format_address(&mut self, address: Address) -> String {
+        let module = self
+            .module_list()
+            .ok()
+            .and_then(|modules| modules.into_iter().find(|m| m.contains(address)));
+
+        match module {
+            Some(module) => {
+                let exports = self.module_export_list(&module).unwrap_or_default();
+                super::module::format_address(address, Some(&module), &exports)
+            }
+            None => super::module::format_address(address, None, &[]),
+        }
+    }
+
+    /// Walks the threads that are currently executing and calls the provided callback for each
+    ///
+    /// One entry is reported per CPU, in ascending order of the CPU number. This is the
+    /// `CurrentThread` of each processor control block on Windows, and the per-CPU `current`
+    /// task on Linux.
+    ///
+    /// By default this returns an error, since not every OS layer is able to provide it.
+    ///
+    /// # Arguments
+    /// * `callback` - where to pass each thread to. This is an opaque callback.
+    fn current_thread_list_callback(&mut self, _callback: CpuThreadInfoCallback) -> Result<()> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves the threads that are currently executing, one per CPU
+    #[skip_func]
+    fn current_threads(&mut self) -> Result<Vec<CpuThreadInfo>> {
+        let mut ret = vec![];
+        self.current_thread_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Retrieves the current wall-clock time, uptime and time zone of the target
+    ///
+    /// This allows timestamping events in target time rather than host time. Windows layers
+    /// read it from `KUSER_SHARED_DATA`, Linux layers from the timekeeper and `jiffies`.
+    ///
+    /// By default this returns an error, since not every OS layer is able to provide it.
+    fn system_time(&mut self) -> Result<OsTime> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves the fixed regions of the kernel address space
+    ///
+    /// OS layers implement this by combining the constant layout of the running build with
+    /// region bounds read from kernel variables.
+    ///
+    /// By default this returns an error, since not every OS layer is able to provide it.
+    #[skip_func]
+    fn kernel_layout(&mut self) -> Result<super::kernel_map::KernelLayout> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves a labelled map of the kernel address space
+    ///
+    /// The map consists of the regions of [`kernel_layout`](Os::kernel_layout), if available,
+    /// the kernel image and all loaded driver images.
+    #[skip_func]
+    fn kernel_memory_map(&mut self) -> Result<super::kernel_map::KernelMemoryMap> {
+        use super::kernel_map::{KernelMemoryMap, KernelRegion, KernelRegionKind};
+
+        let mut regions = self
+            .kernel_layout()
+            .map(|l| l.into_regions())
+            .unwrap_or_default();
+
+        let (kernel_base, kernel_size) = (self.info().base, self.info().size);
+
+        let mut kernel_found = false;
+        for module in self.module_list()? {
+            let kind = if module.base == kernel_base {
+                kernel_found = true;
+                KernelRegionKind::KernelImage
+            } else {
+                KernelRegionKind::DriverImage
+            };
+            regions.push(KernelRegion::with_name(
+                kind,
+                module.base,
+                module.size,
+                &module.name,
+            ));
+        }
+
+        if !kernel_found && !kernel_base.is_null() && kernel_size > 0 {
+            regions.push(KernelRegion::new(
+                KernelRegionKind::KernelImage,
+                kernel_base,
+                kernel_size,
+            ));
+        }
+
+        Ok(KernelMemoryMap::new(regions))
+    }
+
     /// Retrieves the OS info
     fn info(&self) -> &OsInfo;
 }
@@ -384,4 +583,138 @@ pub struct OsInfo {
     pub size: umem,
     /// System architecture
     pub arch: ArchitectureIdent,
+    /// Detailed version information, all fields are zero/empty if unknown
+    pub version: OsVersion,
+}
+
+/// Detailed version information about the OS
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct OsVersion {
+    /// Major version (e.g. 10 on Windows 10/11, 6 on Linux 6.1)
+    pub major: u32,
+    /// Minor version
+    pub minor: u32,
+    /// Build number (e.g. 22621 on Windows), or the patch level on Linux
+    pub build: u32,
+    /// Update revision of the build (e.g. the UBR on Windows)
+    ///
+    /// This changes with every cumulative update or hotpatch that is installed.
+    pub revision: u32,
+    /// Edition or distribution name (e.g. `Professional`, `ServerDatacenter`), may be empty
+    pub edition: ReprCString,
+    /// Security features detected as enabled
+    pub security: OsSecurityFeatures,
+}
+
+impl Default for OsVersion {
+    fn default() -> Self {
+        Self {
+            major: 0,
+            minor: 0,
+            build: 0,
+            revision: 0,
+            edition: "".into(),
+            security: OsSecurityFeatures::NONE,
+        }
+    }
+}
+
+bitflags! {
+    /// Security features of the OS.
+    ///
+    /// Features that are not set are either disabled or could not be determined by the OS layer.
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+    pub struct OsSecurityFeatures: u32 {
+        /// No security features were detected.
+        const NONE = 0;
+        /// The system was booted with secure boot.
+        const SECURE_BOOT = 0b0000_0001;
+        /// Virtualization based security is running, parts of memory are owned by a secure kernel.
+        const VBS = 0b0000_0010;
+        /// Hypervisor protected code integrity (memory integrity) is enforced.
+        const HVCI = 0b0000_0100;
+        /// The kernel is loaded at a randomized address.
+        const KASLR = 0b0000_1000;
+        /// Hotpatches can be applied to the running kernel (e.g. livepatch on Linux).
+        const HOTPATCH = 0b0001_0000;
+    }
+}
+
+/// Time information of the target
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct OsTime {
+    /// Wall-clock time in nanoseconds since the unix epoch (UTC)
+    pub time_ns: u64,
+    /// Time since boot in nanoseconds
+    pub uptime_ns: u64,
+    /// Offset of the local time zone to UTC in seconds, positive east of UTC
+    pub utc_offset: i32,
 }
+
+impl OsTime {
+    /// Difference between the Windows (1601-01-01) and unix (1970-01-01) epoch in 100ns units.
+    const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+
+    /// Creates the time information from the Windows representation.
+    ///
+    /// All values are in 100ns units, like the `SystemTime`, `InterruptTime` and `TimeZoneBias`
+    /// fields of `KUSER_SHARED_DATA`. The bias is the difference between UTC and local time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::os::OsTime;
+    ///
+    /// // 2021-01-01 00:00:00 UTC, 10 seconds after boot, UTC+1
+    /// let time = OsTime::from_filetime(132_539_328_000_000_000, 100_000_000, -36_000_000_000);
+    ///
+    /// assert_eq!(time.time_ns, 1_609_459_200_000_000_000);
+    /// assert_eq!(time.uptime_ns, 10_000_000_000);
+    /// assert_eq!(time.utc_offset, 3600);
+    /// ```
+    pub fn from_filetime(system_time: u64, interrupt_time: u64, time_zone_bias: i64) -> Self {
+        Self {
+            time_ns: system_time
+                .saturating_sub(Self::FILETIME_UNIX_EPOCH)
+                .saturating_mul(100),
+            uptime_ns: interrupt_time.saturating_mul(100),
+            utc_offset: (-time_zone_bias / 10_000_000) as i32,
+        }
+    }
+
+    /// Returns the wall-clock time as a [`SystemTime`](std::time::SystemTime).
+    #[cfg(feature = "std")]
+    pub fn to_system_time(&self) -> ::std::time::SystemTime {
+        ::std::time::UNIX_EPOCH + ::std::time::Duration::from_nanos(self.time_ns)
+    }
+}
+
+/// Thread executing on a single CPU at the time of the query
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct CpuThreadInfo {
+    /// Number of the CPU
+    pub cpu: u32,
+    /// Address of the thread structure (e.g. `ETHREAD` on Windows, `task_struct` on Linux)
+    pub thread: Address,
+    /// ID of the thread
+    pub tid: u32,
+    /// Address of the owning process structure, usable with [`Os::process_info_by_address`]
+    ///
+    /// This is [`Address::INVALID`] if the CPU is idle and no process owns the thread.
+    pub process: Address,
+    /// ID of the owning process
+    pub pid: Pid,
+}
+
+pub type CpuThreadInfoCallback<'a> = OpaqueCallback<'a, CpuThreadInfo>;
@@ -0,0 +1,692 @@
+//! Introspection of the Linux SLUB allocator.
+//!
+//! Most small kernel objects (`task_struct`, `file`, `cred`, socket buffers, ...) are allocated
+//! from slab caches. Every cache is described by a `kmem_cache` and linked into the global
+//! `slab_caches` list. A cache hands out objects of a fixed size from slabs, which are runs of
+//! `2^order` pages. Slabs are tracked in three places:
+//!
+//! * the active slab of every CPU (`kmem_cache::cpu_slab`)
+//! * the partial list of every NUMA node (`kmem_cache_node::partial`)
+//! * the full list of every NUMA node (`kmem_cache_node::full`), only with `CONFIG_SLUB_DEBUG`
+//!
+//! [`SlabReader`] enumerates caches, their slabs and the objects within those slabs. Objects
+//! linked into a freelist are reported as free, all others as allocated. Free pointers of
+//! kernels built with `CONFIG_SLAB_FREELIST_HARDENED` are decoded if the layout contains the
+//! offset of `kmem_cache::random`.
+//!
+//! [`SlabReader::find_object`] does the reverse and attributes an arbitrary address to the slab
+//! object containing it, similar to [`find_pool_allocation`](crate::analysis::find_pool_allocation)
+//! for the Windows pool.
+//!
+//! As with the other Linux helpers the structure offsets have to be provided through a
+//! [`SlabLayout`], `vmemmap_base` and `page_offset_base` can be resolved through
+//! [`read_kallsyms`](super::read_kallsyms).
+//!
+//! # Remarks
+//!
+//! Full slabs are only tracked by debug kernels, objects in full slabs of other kernels can only
+//! be found through [`SlabReader::find_object`]. Slabs on the per-CPU partial lists and caches of
+//! the SLAB and SLOB allocators are not supported.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::slab::{SlabLayout, SlabReader};
+//!
+//! fn count_tasks(
+//!     kernel: &mut impl MemoryView,
+//!     reader: &SlabReader,
+//!     slab_caches: Address,
+//! ) -> Result<usize> {
+//!     let cache = reader.find_cache(kernel, slab_caches, "task_struct")?;
+//!     let objects = reader.cache_objects(kernel, &cache, None)?;
+//!     Ok(objects.iter().filter(|o| !o.free).count())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let reader = SlabReader::new(x64::ARCH, SlabLayout::default(), Address::null(), Address::null());
+//! # assert!(count_tasks(&mut proc, &reader, Address::null()).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use super::list::ListWalker;
+use super::percpu::PerCpuOffsets;
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Maximum length of a cache name.
+const MAX_NAME_LEN: usize = 64;
+
+/// Maximum number of objects per slab, protecting against corrupted caches.
+pub const MAX_OBJECTS_PER_SLAB: u32 = 0x8000;
+
+/// Default number of NUMA nodes whose slab lists are walked.
+pub const DEFAULT_NODES: usize = 1;
+
+/// Offsets of the kernel structures required for slab introspection.
+///
+/// Kernels before 5.17 use `struct page` for slabs, the `slab_*` fields refer to the
+/// corresponding members of `struct page` there.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SlabLayout {
+    /// Offset of `kmem_cache::list`
+    pub cache_list: usize,
+    /// Offset of `kmem_cache::name`
+    pub cache_name: usize,
+    /// Offset of `kmem_cache::object_size`
+    pub cache_object_size: usize,
+    /// Offset of `kmem_cache::size`
+    pub cache_size: usize,
+    /// Offset of `kmem_cache::offset`
+    pub cache_offset: usize,
+    /// Offset of `kmem_cache::oo`
+    pub cache_oo: usize,
+    /// Offset of `kmem_cache::cpu_slab`
+    pub cache_cpu_slab: usize,
+    /// Offset of `kmem_cache::node`
+    pub cache_node: usize,
+    /// Offset of `kmem_cache::random`, only present with `CONFIG_SLAB_FREELIST_HARDENED`
+    pub cache_random: Option<usize>,
+    /// True if hardened free pointers are mixed with the byte swapped pointer location (5.7+)
+    pub freelist_swab: bool,
+    /// Offset of `kmem_cache_cpu::freelist`
+    pub cpu_freelist: usize,
+    /// Offset of `kmem_cache_cpu::slab` (`kmem_cache_cpu::page` before 5.17)
+    pub cpu_slab: usize,
+    /// Offset of `kmem_cache_node::partial`
+    pub node_partial: usize,
+    /// Offset of `kmem_cache_node::full`, only present with `CONFIG_SLUB_DEBUG`
+    pub node_full: Option<usize>,
+    /// Offset of `slab::slab_list`
+    pub slab_list: usize,
+    /// Offset of `slab::freelist`
+    pub slab_freelist: usize,
+    /// Offset of `slab::counters`
+    pub slab_counters: usize,
+    /// Offset of `slab::slab_cache`
+    pub slab_cache: usize,
+    /// Offset of `page::compound_head`
+    pub page_compound_head: usize,
+    /// Size of `struct page`
+    pub page_size: usize,
+}
+
+/// A slab cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SlabCache {
+    /// Address of the `kmem_cache`
+    pub address: Address,
+    /// Name of the cache
+    pub name: String,
+    /// Size of the objects as requested by the user of the cache
+    pub object_size: u32,
+    /// Distance between two objects, including metadata and alignment
+    pub size: u32,
+    /// Offset of the free pointer within free objects
+    pub free_offset: u32,
+    /// Number of objects in a slab
+    pub objects_per_slab: u32,
+    /// Order of the slab size in pages
+    pub order: u32,
+    /// Key used to obfuscate free pointers, 0 if free pointers are not hardened
+    pub random: umem,
+}
+
+/// Where a slab is tracked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum SlabState {
+    /// The active slab of the given CPU
+    Cpu(usize),
+    /// A slab on the partial list of a node
+    Partial,
+    /// A slab on the full list of a node
+    Full,
+}
+
+/// A single slab of a cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SlabInfo {
+    /// Address of the `struct slab` (or `struct page`)
+    pub slab: Address,
+    /// Address of the first object
+    pub address: Address,
+    /// Number of objects in the slab
+    pub objects: u32,
+    /// Number of allocated objects as tracked by the slab
+    ///
+    /// For active CPU slabs this includes the objects on the lockless per-CPU freelist.
+    pub inuse: u32,
+    /// Where the slab was found
+    pub state: SlabState,
+    /// Head of the lockless freelist of the CPU owning the slab
+    pub cpu_freelist: Option<Address>,
+}
+
+/// An object of a slab cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SlabObject {
+    /// Start of the object
+    pub address: Address,
+    /// Address of the `kmem_cache` the object belongs to
+    pub cache: Address,
+    /// Address of the `struct slab` (or `struct page`) containing the object
+    pub slab: Address,
+    /// True if the object is linked into a freelist, its contents may still be intact
+    pub free: bool,
+}
+
+/// Enumerates the slab caches of a Linux kernel and their objects.
+#[derive(Clone, Copy, Debug)]
+pub struct SlabReader {
+    arch: ArchitectureObj,
+    layout: SlabLayout,
+    vmemmap_base: Address,
+    page_offset_base: Address,
+    nodes: usize,
+}
+
+impl SlabReader {
+    /// Constructs a new reader.
+    ///
+    /// `vmemmap_base` is the start of the `struct page` array and `page_offset_base` the start of
+    /// the direct mapping of physical memory. Both are randomized with `CONFIG_RANDOMIZE_MEMORY`.
+    pub fn new(
+        arch: ArchitectureObj,
+        layout: SlabLayout,
+        vmemmap_base: Address,
+        page_offset_base: Address,
+    ) -> Self {
+        Self {
+            arch,
+            layout,
+            vmemmap_base,
+            page_offset_base,
+            nodes: DEFAULT_NODES,
+        }
+    }
+
+    /// Sets the number of NUMA nodes (`nr_node_ids`) whose slab lists are walked.
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Reads all caches linked into the `slab_caches` list at `slab_caches`.
+    ///
+    /// Caches that can not be read are skipped.
+    pub fn caches(
+        &self,
+        mem: &mut impl MemoryView,
+        slab_caches: Address,
+    ) -> Result<Vec<SlabCache>> {
+        let walk = ListWalker::new(self.arch).walk(mem, slab_caches)?;
+        Ok(walk
+            .containers(self.layout.cache_list)
+            .filter_map(|cache| self.read_cache(mem, cache).ok())
+            .collect())
+    }
+
+    /// Returns the cache with the given name.
+    pub fn find_cache(
+        &self,
+        mem: &mut impl MemoryView,
+        slab_caches: Address,
+        name: &str,
+    ) -> Result<SlabCache> {
+        self.caches(mem, slab_caches)?
+            .into_iter()
+            .find(|cache| cache.name == name)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound).log_debug("slab cache not found")
+            })
+    }
+
+    /// Reads the `kmem_cache` at `cache`.
+    pub fn read_cache(&self, mem: &mut impl MemoryView, cache: Address) -> Result<SlabCache> {
+        let name_ptr = self.read_ptr(mem, cache + self.layout.cache_name)?;
+        let name = mem.read_char_string_n(name_ptr, MAX_NAME_LEN).data_part()?;
+
+        let object_size = mem
+            .read::<u32>(cache + self.layout.cache_object_size)
+            .data_part()?;
+        let size = mem
+            .read::<u32>(cache + self.layout.cache_size)
+            .data_part()?;
+        let free_offset = mem
+            .read::<u32>(cache + self.layout.cache_offset)
+            .data_part()?;
+        let oo = mem.read::<u32>(cache + self.layout.cache_oo).data_part()?;
+        let random = match self.layout.cache_random {
+            Some(random) => self.read_ptr(mem, cache + random)?.to_umem(),
+            None => 0,
+        };
+
+        let objects_per_slab = oo & 0xffff;
+        if size == 0 || objects_per_slab > MAX_OBJECTS_PER_SLAB {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("slab cache contains an invalid object layout"));
+        }
+
+        Ok(SlabCache {
+            address: cache,
+            name,
+            object_size,
+            size,
+            free_offset,
+            objects_per_slab,
+            order: oo >> 16,
+            random,
+        })
+    }
+
+    /// Returns the slabs of `cache`.
+    ///
+    /// Active CPU slabs are only returned if `percpu` is given. Lists that can not be read are
+    /// skipped.
+    pub fn slabs(
+        &self,
+        mem: &mut impl MemoryView,
+        cache: &SlabCache,
+        percpu: Option<&PerCpuOffsets>,
+    ) -> Result<Vec<SlabInfo>> {
+        let mut slabs = vec![];
+
+        if let Some(percpu) = percpu {
+            let cpu_slab = self.read_ptr(mem, cache.address + self.layout.cache_cpu_slab)?;
+            for (cpu, cpu_cache) in percpu.addresses(cpu_slab).enumerate() {
+                let slab = match self.read_ptr(mem, cpu_cache + self.layout.cpu_slab) {
+                    Ok(slab) if !slab.is_null() => slab,
+                    _ => continue,
+                };
+                let freelist = self.read_ptr(mem, cpu_cache + self.layout.cpu_freelist)?;
+                if let Ok(mut info) = self.read_slab(mem, slab, SlabState::Cpu(cpu)) {
+                    info.cpu_freelist = Some(freelist);
+                    slabs.push(info);
+                }
+            }
+        }
+
+        let walker = ListWalker::new(self.arch);
+        for i in 0..self.nodes {
+            let node = match self.read_ptr(
+                mem,
+                cache.address + self.layout.cache_node + i * self.arch.size_addr(),
+            ) {
+                Ok(node) if !node.is_null() => node,
+                _ => continue,
+            };
+
+            let lists = Some((self.layout.node_partial, SlabState::Partial))
+                .into_iter()
+                .chain(self.layout.node_full.map(|full| (full, SlabState::Full)));
+            for (offset, state) in lists {
+                let walk = match walker.walk(mem, node + offset) {
+                    Ok(walk) => walk,
+                    Err(_) => continue,
+                };
+                for slab in walk.containers(self.layout.slab_list) {
+                    if let Ok(info) = self.read_slab(mem, slab, state) {
+                        slabs.push(info);
+                    }
+                }
+            }
+        }
+
+        Ok(slabs)
+    }
+
+    /// Returns all objects of `slab`.
+    ///
+    /// Objects reachable from the freelist of the slab or the freelist of the CPU owning it are
+    /// reported as free. A freelist that points outside of the slab is cut off at that point.
+    pub fn objects(
+        &self,
+        mem: &mut impl MemoryView,
+        cache: &SlabCache,
+        slab: &SlabInfo,
+    ) -> Result<Vec<SlabObject>> {
+        let mut free = vec![false; slab.objects.min(MAX_OBJECTS_PER_SLAB) as usize];
+
+        let slab_freelist = self.read_ptr(mem, slab.slab + self.layout.slab_freelist)?;
+        for head in Some(slab_freelist).into_iter().chain(slab.cpu_freelist) {
+            self.mark_free(mem, cache, slab.address, head, &mut free);
+        }
+
+        Ok(free
+            .into_iter()
+            .enumerate()
+            .map(|(i, free)| SlabObject {
+                address: slab.address + i as umem * cache.size as umem,
+                cache: cache.address,
+                slab: slab.slab,
+                free,
+            })
+            .collect())
+    }
+
+    /// Returns the objects of all slabs of `cache`, see [`slabs`](Self::slabs) and
+    /// [`objects`](Self::objects).
+    pub fn cache_objects(
+        &self,
+        mem: &mut impl MemoryView,
+        cache: &SlabCache,
+        percpu: Option<&PerCpuOffsets>,
+    ) -> Result<Vec<SlabObject>> {
+        let mut ret = vec![];
+        for slab in self.slabs(mem, cache, percpu)? {
+            if let Ok(objects) = self.objects(mem, cache, &slab) {
+                ret.extend(objects);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the slab object containing `address`.
+    ///
+    /// The address has to be part of the direct mapping. Only the freelist of the slab itself is
+    /// consulted, objects on the freelist of a CPU are reported as allocated. Addresses outside
+    /// of slab pages or in the padding at the end of a slab are reported as
+    /// [`ErrorKind::NotFound`].
+    pub fn find_object(&self, mem: &mut impl MemoryView, address: Address) -> Result<SlabObject> {
+        if address < self.page_offset_base {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("address is not part of the direct mapping"));
+        }
+
+        let pfn = (address - self.page_offset_base) as umem / self.arch.page_size() as umem;
+        let mut slab = self.vmemmap_base + pfn * self.layout.page_size as umem;
+
+        // tail pages of compound pages point to their head page with the lowest bit set
+        let head = self.read_ptr(mem, slab + self.layout.page_compound_head)?;
+        if head.to_umem() & 1 != 0 {
+            slab = Address::from(head.to_umem() - 1);
+        }
+
+        let cache = self.read_ptr(mem, slab + self.layout.slab_cache)?;
+        if cache.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("address is not part of a slab"));
+        }
+        let cache = self.read_cache(mem, cache)?;
+        // the list the slab is tracked on is not known here
+        let info = self.read_slab(mem, slab, SlabState::Partial)?;
+
+        let index = (address - info.address) as umem / cache.size as umem;
+        if index >= info.objects as umem {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("address is part of the slab padding"));
+        }
+
+        Ok(self.objects(mem, &cache, &info)?[index as usize])
+    }
+
+    /// Reads the `struct slab` at `slab`.
+    fn read_slab(
+        &self,
+        mem: &mut impl MemoryView,
+        slab: Address,
+        state: SlabState,
+    ) -> Result<SlabInfo> {
+        let counters = mem
+            .read::<u32>(slab + self.layout.slab_counters)
+            .data_part()?;
+        let objects = (counters >> 16) & 0x7fff;
+
+        if slab < self.vmemmap_base || self.layout.page_size == 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("slab is not part of the vmemmap"));
+        }
+        let pfn = (slab - self.vmemmap_base) as umem / self.layout.page_size as umem;
+
+        Ok(SlabInfo {
+            slab,
+            address: self.page_offset_base + pfn * self.arch.page_size() as umem,
+            objects,
+            inuse: counters & 0xffff,
+            state,
+            cpu_freelist: None,
+        })
+    }
+
+    /// Follows the freelist starting at `head` and marks all visited objects as free.
+    fn mark_free(
+        &self,
+        mem: &mut impl MemoryView,
+        cache: &SlabCache,
+        start: Address,
+        head: Address,
+        free: &mut [bool],
+    ) {
+        let size = cache.size as umem;
+        let mut object = head;
+
+        while !object.is_null() && object >= start {
+            let offset = (object - start) as umem;
+            let index = (offset / size) as usize;
+            if offset % size != 0 || index >= free.len() || free[index] {
+                break;
+            }
+            free[index] = true;
+
+            let ptr_addr = object + cache.free_offset as umem;
+            object = match self.read_ptr(mem, ptr_addr) {
+                Ok(ptr) => self.decode_free_pointer(cache, ptr, ptr_addr),
+                Err(_) => break,
+            };
+        }
+    }
+
+    /// Decodes a hardened free pointer read from `ptr_addr`.
+    fn decode_free_pointer(&self, cache: &SlabCache, ptr: Address, ptr_addr: Address) -> Address {
+        if self.layout.cache_random.is_none() {
+            return ptr;
+        }
+
+        let location = if !self.layout.freelist_swab {
+            ptr_addr.to_umem()
+        } else if self.arch.size_addr() == 8 {
+            (ptr_addr.to_umem() as u64).swap_bytes() as umem
+        } else {
+            (ptr_addr.to_umem() as u32).swap_bytes() as umem
+        };
+
+        Address::from(ptr.to_umem() ^ cache.random ^ location)
+    }
+
+    fn read_ptr(&self, mem: &mut impl MemoryView, addr: Address) -> Result<Address> {
+        mem.read_addr_arch(self.arch, addr).data_part()
+    }
+}
+
+impl SlabCache {
+    /// Returns the size of a slab in bytes.
+    pub fn slab_size(&self, page_size: usize) -> umem {
+        (page_size as umem) << self.order
+    }
+
+    /// Reads the object at `address` into a buffer of `object_size` bytes.
+    pub fn read_object(&self, mem: &mut impl MemoryView, address: Address) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.object_size as usize];
+        mem.read_raw_into(address, &mut buf).data_part()?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const LAYOUT: SlabLayout = SlabLayout {
+        cache_list: 0x60,
+        cache_name: 0x58,
+        cache_object_size: 0x1c,
+        cache_size: 0x18,
+        cache_offset: 0x20,
+        cache_oo: 0x28,
+        cache_cpu_slab: 0x0,
+        cache_node: 0x100,
+        cache_random: None,
+        freelist_swab: true,
+        cpu_freelist: 0x0,
+        cpu_slab: 0x10,
+        node_partial: 0x10,
+        node_full: None,
+        slab_list: 0x8,
+        slab_freelist: 0x20,
+        slab_counters: 0x28,
+        slab_cache: 0x18,
+        page_compound_head: 0x8,
+        page_size: 0x40,
+    };
+
+    const VMEMMAP: u64 = 0x8_0000;
+    const DIRECT_MAP: u64 = 0x10_0000;
+    const SLAB_CACHES: u64 = 0x500;
+    const CACHE: u64 = 0x1000;
+    const NODE: u64 = 0x2000;
+    const SLAB_PFN: u64 = 3;
+    const SLAB: u64 = VMEMMAP + SLAB_PFN * 0x40;
+    const SLAB_START: u64 = DIRECT_MAP + SLAB_PFN * 0x1000;
+
+    fn link(mem: &mut impl MemoryView, entry: u64, next: u64, prev: u64) {
+        mem.write(Address::from(entry), &next).unwrap();
+        mem.write(Address::from(entry + 8), &prev).unwrap();
+    }
+
+    fn build_mem() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(2));
+        let mut view = mem.phys_view();
+
+        // kmalloc-64 cache with 64 objects per order 0 slab
+        let list = CACHE + LAYOUT.cache_list as u64;
+        link(&mut view, SLAB_CACHES, list, list);
+        link(&mut view, list, SLAB_CACHES, SLAB_CACHES);
+        view.write(Address::from(CACHE + 0x58), &0x1800u64).unwrap();
+        view.write_raw(Address::from(0x1800u64), b"kmalloc-64\0")
+            .unwrap();
+        view.write(Address::from(CACHE + 0x18), &64u32).unwrap();
+        view.write(Address::from(CACHE + 0x1c), &64u32).unwrap();
+        view.write(Address::from(CACHE + 0x20), &32u32).unwrap();
+        view.write(Address::from(CACHE + 0x28), &64u32).unwrap();
+        view.write(Address::from(CACHE + 0x100), &NODE).unwrap();
+
+        // a single partial slab with objects 5 and 9 free
+        let partial = NODE + LAYOUT.node_partial as u64;
+        let slab_list = SLAB + LAYOUT.slab_list as u64;
+        link(&mut view, partial, slab_list, slab_list);
+        link(&mut view, slab_list, partial, partial);
+        view.write(Address::from(SLAB + 0x18), &CACHE).unwrap();
+        view.write(Address::from(SLAB + 0x20), &(SLAB_START + 5 * 64))
+            .unwrap();
+        view.write(Address::from(SLAB + 0x28), &(62u32 | (64 << 16)))
+            .unwrap();
+        view.write(
+            Address::from(SLAB_START + 5 * 64 + 32),
+            &(SLAB_START + 9 * 64),
+        )
+        .unwrap();
+
+        mem
+    }
+
+    fn reader() -> SlabReader {
+        SlabReader::new(x64::ARCH, LAYOUT, VMEMMAP.into(), DIRECT_MAP.into())
+    }
+
+    #[test]
+    fn enumerate_objects() {
+        let mut mem = build_mem();
+        let mut view = mem.phys_view();
+        let reader = reader();
+
+        let cache = reader
+            .find_cache(&mut view, SLAB_CACHES.into(), "kmalloc-64")
+            .unwrap();
+        assert_eq!(cache.objects_per_slab, 64);
+        assert_eq!(cache.slab_size(size::kb(4)), 0x1000);
+
+        let slabs = reader.slabs(&mut view, &cache, None).unwrap();
+        assert_eq!(slabs.len(), 1);
+        assert_eq!(slabs[0].address, Address::from(SLAB_START));
+        assert_eq!(slabs[0].inuse, 62);
+
+        let objects = reader.cache_objects(&mut view, &cache, None).unwrap();
+        assert_eq!(objects.len(), 64);
+        assert_eq!(
+            objects
+                .iter()
+                .filter(|o| o.free)
+                .map(|o| o.address)
+                .collect::<Vec<_>>(),
+            vec![
+                Address::from(SLAB_START + 5 * 64),
+                Address::from(SLAB_START + 9 * 64)
+            ]
+        );
+    }
+
+    #[test]
+    fn find_object() {
+        let mut mem = build_mem();
+        let mut view = mem.phys_view();
+        let reader = reader();
+
+        let object = reader
+            .find_object(&mut view, Address::from(SLAB_START + 7 * 64 + 3))
+            .unwrap();
+        assert_eq!(object.address, Address::from(SLAB_START + 7 * 64));
+        assert_eq!(object.cache, Address::from(CACHE));
+        assert!(!object.free);
+
+        let object = reader
+            .find_object(&mut view, Address::from(SLAB_START + 9 * 64))
+            .unwrap();
+        assert!(object.free);
+
+        assert!(reader
+            .find_object(&mut view, Address::from(DIRECT_MAP + 0x5000))
+            .is_err());
+    }
+
+    #[test]
+    fn hardened_freelist() {
+        let layout = SlabLayout {
+            cache_random: Some(0x30),
+            ..LAYOUT
+        };
+        let reader = SlabReader::new(x64::ARCH, layout, VMEMMAP.into(), DIRECT_MAP.into());
+        let cache = SlabCache {
+            address: CACHE.into(),
+            name: "cred_jar".into(),
+            object_size: 0xa8,
+            size: 0xc0,
+            free_offset: 0x60,
+            objects_per_slab: 21,
+            order: 0,
+            random: 0x1234_5678_9abc_def0,
+        };
+
+        let ptr_addr = Address::from(0xffff_8880_0100_0060u64);
+        let next = 0xffff_8880_0100_00c0u64;
+        let encoded = next ^ 0x1234_5678_9abc_def0 ^ 0xffff_8880_0100_0060u64.swap_bytes();
+
+        assert_eq!(
+            reader.decode_free_pointer(&cache, Address::from(encoded), ptr_addr),
+            Address::from(next)
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Runtime structure layouts and typed field access.
+//!
+//! OS layers usually access kernel structures like `_EPROCESS` or `_PEB` through offsets that are
+//! hardcoded per build. The [`TypeDatabase`] instead holds the layouts of structures as data, so
+//! they can be filled from the type information of the running kernel (e.g. PDB or ISF files)
+//! once and then be used to read any field by its name.
+//!
+//! A [`StructReader`] is obtained through [`TypeDatabase::struct_reader`] and translates
+//! field names into offsets and typed reads. Fields of embedded structures are addressed with a
+//! dotted path, e.g. `Pcb.DirectoryTableBase`.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::structs::{StructLayout, TypeDatabase};
+//!
+//! fn process_info(
+//!     kernel: &TypeDatabase,
+//!     mem: &mut impl MemoryView,
+//!     eprocess: Address,
+//! ) -> Result<(u64, Address)> {
+//!     let reader = kernel.struct_reader("_EPROCESS")?;
+//!     let pid: u64 = reader.read(mem, eprocess, "UniqueProcessId")?;
+//!     let dtb: u64 = reader.read(mem, eprocess, "Pcb.DirectoryTableBase")?;
+//!     Ok((pid, Address::from(dtb)))
+//! }
+//!
+//! let mut kernel = TypeDatabase::new();
+//! kernel.insert(StructLayout::new("_KPROCESS", 0x438).field("DirectoryTableBase", 0x28, 8));
+//! kernel.insert(
+//!     StructLayout::new("_EPROCESS", 0xa40)
+//!         .embedded("Pcb", 0x0, "_KPROCESS")
+//!         .field("UniqueProcessId", 0x440, 8),
+//! );
+//! # use memflow::dummy::DummyMemory;
+//! # let mut mem = DummyMemory::new(size::mb(1));
+//! # mem.phys_write(0x1028.into(), &0x1ab000u64).unwrap();
+//! # mem.phys_write(0x1440.into(), &4u64).unwrap();
+//! # let info = process_info(&kernel, &mut mem.phys_view(), 0x1000.into()).unwrap();
+//! # assert_eq!(info, (4, Address::from(0x1ab000u64)));
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use crate::architecture::ArchitectureObj;
+use crate::dataview::Pod;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Layout of a single field of a structure.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FieldLayout {
+    /// Offset of the field from the start of the structure
+    pub offset: usize,
+    /// Size of the field in bytes
+    pub size: usize,
+    /// Position and length in bits of a bitfield within the field
+    pub bits: Option<(u8, u8)>,
+    /// Name of the structure type of an embedded structure
+    pub type_name: Option<String>,
+}
+
+/// Layout of a structure, mapping field names to their offsets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct StructLayout {
+    pub name: String,
+    pub size: usize,
+    fields: BTreeMap<String, FieldLayout>,
+}
+
+impl StructLayout {
+    /// Creates a new structure layout without any fields.
+    pub fn new(name: &str, size: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            size,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a plain field of `size` bytes at `offset`.
+    pub fn field(self, name: &str, offset: usize, size: usize) -> Self {
+        self.with_field(
+            name,
+            FieldLayout {
+                offset,
+                size,
+                bits: None,
+                type_name: None,
+            },
+        )
+    }
+
+    /// Adds a bitfield of `length` bits starting at bit `position` of the `size` bytes at `offset`.
+    pub fn bitfield(
+        self,
+        name: &str,
+        offset: usize,
+        size: usize,
+        position: u8,
+        length: u8,
+    ) -> Self {
+        self.with_field(
+            name,
+            FieldLayout {
+                offset,
+                size,
+                bits: Some((position, length)),
+                type_name: None,
+            },
+        )
+    }
+
+    /// Adds an embedded structure of type `type_name` at `offset`.
+    ///
+    /// The size of the field is taken from the layout of `type_name` when it is accessed.
+    pub fn embedded(self, name: &str, offset: usize, type_name: &str) -> Self {
+        self.with_field(
+            name,
+            FieldLayout {
+                offset,
+                size: 0,
+                bits: None,
+                type_name: Some(type_name.to_string()),
+            },
+        )
+    }
+
+    /// Adds a field with an arbitrary layout.
+    pub fn with_field(mut self, name: &str, field: FieldLayout) -> Self {
+        self.fields.insert(name.to_string(), field);
+        self
+    }
+
+    /// Returns the layout of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.get(name)
+    }
+
+    /// Returns all fields of the structure sorted by their name.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &FieldLayout)> {
+        self.fields
+            .iter()
+            .map(|(name, field)| (name.as_str(), field))
+    }
+}
+
+/// A collection of structure layouts of a single kernel build.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TypeDatabase {
+    structs: BTreeMap<String, StructLayout>,
+}
+
+impl TypeDatabase {
+    /// Creates an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the layout of a structure, replacing a previous layout of the same name.
+    pub fn insert(&mut self, layout: StructLayout) {
+        self.structs.insert(layout.name.clone(), layout);
+    }
+
+    /// Returns the layout of the structure `name`.
+    pub fn get(&self, name: &str) -> Option<&StructLayout> {
+        self.structs.get(name)
+    }
+
+    /// Returns the names of all structures in the database.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.structs.keys().map(String::as_str)
+    }
+
+    /// Returns a reader for fields of the structure `name`.
+    pub fn struct_reader(&self, name: &str) -> Result<StructReader<'_>> {
+        let layout = self.get(name).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound).log_debug("unknown structure type")
+        })?;
+        Ok(StructReader { db: self, layout })
+    }
+}
+
+/// Typed access to the fields of a structure, see the [module level documentation](self).
+#[derive(Clone, Copy, Debug)]
+pub struct StructReader<'a> {
+    db: &'a TypeDatabase,
+    layout: &'a StructLayout,
+}
+
+impl<'a> StructReader<'a> {
+    /// Returns the layout of the structure.
+    pub fn layout(&self) -> &'a StructLayout {
+        self.layout
+    }
+
+    /// Returns the size of the structure.
+    pub fn size(&self) -> usize {
+        self.layout.size
+    }
+
+    /// Resolves a possibly dotted field path into its absolute offset and layout.
+    pub fn resolve(&self, path: &str) -> Result<(usize, &'a FieldLayout)> {
+        let mut layout = self.layout;
+        let mut offset = 0;
+        let mut parts = path.split('.').peekable();
+
+        while let Some(name) = parts.next() {
+            let field = layout.get(name).ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Offset).log_debug("unknown structure field")
+            })?;
+            offset += field.offset;
+
+            if parts.peek().is_none() {
+                return Ok((offset, field));
+            }
+
+            layout = field
+                .type_name
+                .as_deref()
+                .and_then(|name| self.db.get(name))
+                .ok_or_else(|| {
+                    Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                        .log_debug("structure field is not an embedded structure")
+                })?;
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+            .log_debug("empty structure field path"))
+    }
+
+    /// Returns the offset of the field `path` from the start of the structure.
+    pub fn offset(&self, path: &str) -> Result<usize> {
+        self.resolve(path).map(|(offset, _)| offset)
+    }
+
+    /// Returns the address of the field `path` of the structure at `base`.
+    pub fn field_address(&self, base: Address, path: &str) -> Result<Address> {
+        self.offset(path).map(|offset| base + offset)
+    }
+
+    /// Reads the field `path` of the structure at `base`.
+    ///
+    /// Fails if `T` is larger than the field.
+    pub fn read<T: Pod + Sized>(
+        &self,
+        mem: &mut impl MemoryView,
+        base: Address,
+        path: &str,
+    ) -> Result<T> {
+        let (offset, field) = self.resolve(path)?;
+        if field.type_name.is_none() && std::mem::size_of::<T>() > field.size {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("type is larger than the structure field"));
+        }
+        mem.read(base + offset).data_part()
+    }
+
+    /// Reads the pointer field `path` of the structure at `base`.
+    pub fn read_addr(
+        &self,
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        base: Address,
+        path: &str,
+    ) -> Result<Address> {
+        let (offset, field) = self.resolve(path)?;
+        if arch.size_addr() > field.size {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("pointer is larger than the structure field"));
+        }
+        mem.read_addr_arch(arch, base + offset).data_part()
+    }
+
+    /// Reads the field `path` of the structure at `base` as an unsigned integer.
+    ///
+    /// Fields of up to 8 bytes are supported. Bitfields are shifted and masked accordingly.
+    pub fn read_value(&self, mem: &mut impl MemoryView, base: Address, path: &str) -> Result<u64> {
+        let (offset, field) = self.resolve(path)?;
+        if field.size == 0 || field.size > 8 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug("structure field is not an integer"));
+        }
+
+        let mut buf = [0u8; 8];
+        mem.read_raw_into(base + offset, &mut buf[..field.size])
+            .data_part()?;
+        let value = u64::from_le_bytes(buf);
+
+        Ok(match field.bits {
+            Some((position, length)) if length < 64 => (value >> position) & ((1 << length) - 1),
+            Some((position, _)) => value >> position,
+            None => value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    fn database() -> TypeDatabase {
+        let mut db = TypeDatabase::new();
+        db.insert(
+            StructLayout::new("_MMPFN", 0x30)
+                .field("u3", 0x22, 2)
+                .bitfield("PageLocation", 0x22, 2, 0, 3)
+                .bitfield("ReadInProgress", 0x22, 2, 3, 1),
+        );
+        db.insert(StructLayout::new("_INNER", 0x10).field("Value", 0x8, 4));
+        db.insert(
+            StructLayout::new("_OUTER", 0x40)
+                .field("Flags", 0x0, 4)
+                .embedded("Inner", 0x20, "_INNER"),
+        );
+        db
+    }
+
+    #[test]
+    fn nested_fields() {
+        let db = database();
+        let reader = db.struct_reader("_OUTER").unwrap();
+
+        assert_eq!(reader.offset("Inner.Value").unwrap(), 0x28);
+        assert!(reader.offset("Inner.Missing").is_err());
+        assert!(reader.offset("Flags.Value").is_err());
+        assert!(db.struct_reader("_MISSING").is_err());
+
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x1028.into(), &0x1234u32).unwrap();
+
+        let mut view = mem.phys_view();
+        let value: u32 = reader
+            .read(&mut view, 0x1000.into(), "Inner.Value")
+            .unwrap();
+        assert_eq!(value, 0x1234);
+        assert!(reader
+            .read::<u64>(&mut view, 0x1000.into(), "Inner.Value")
+            .is_err());
+    }
+
+    #[test]
+    fn bitfields() {
+        let db = database();
+        let reader = db.struct_reader("_MMPFN").unwrap();
+
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x22.into(), &0b1010_1110u16).unwrap();
+
+        let mut view = mem.phys_view();
+        assert_eq!(
+            reader.read_value(&mut view, 0.into(), "u3").unwrap(),
+            0b1010_1110
+        );
+        assert_eq!(
+            reader
+                .read_value(&mut view, 0.into(), "PageLocation")
+                .unwrap(),
+            0b110
+        );
+        assert_eq!(
+            reader
+                .read_value(&mut view, 0.into(), "ReadInProgress")
+                .unwrap(),
+            1
+        );
+    }
+}
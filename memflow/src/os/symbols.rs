@@ -0,0 +1,319 @@
+//! Symbol index for fast address and name lookups.
+//!
+//! Resolving addresses through [`Process::format_address`] walks the module list and export
+//! tables on every call. The [`SymbolIndex`] collects the same information once and answers
+//! address → symbol and name → address queries in logarithmic time.
+//!
+//! The index does not track changes of the target by itself. Call
+//! [`SymbolIndex::refresh_process`] (or [`SymbolIndex::refresh_os`]) periodically to rebuild it
+//! whenever the module list changed.
+//!
+//! # Examples:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::symbols::SymbolIndex;
+//! # use memflow::dummy::DummyOs;
+//!
+//! fn print_symbol(proc: &mut impl Process, addr: Address) -> Result<()> {
+//!     let mut index = SymbolIndex::from_process(proc)?;
+//!
+//!     // rebuilds the index only if the module list changed
+//!     index.refresh_process(proc)?;
+//!
+//!     println!("{}", index.format_address(addr));
+//!
+//!     Ok(())
+//! }
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address;
+//! # print_symbol(&mut proc, addr).unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+
+use super::module::{format_address, write_symbol};
+use super::{ExportInfo, ModuleInfo, Os, Process};
+use crate::error::Result;
+use crate::types::{umem, Address};
+
+/// Single exported symbol contained in a [`SymbolIndex`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Symbol {
+    /// Name of the symbol
+    pub name: String,
+    /// Absolute address of the symbol
+    pub address: Address,
+    /// Index of the containing module in [`SymbolIndex::modules`]
+    pub module: usize,
+}
+
+/// Sorted lookup tables of modules and their exports.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolIndex {
+    modules: Vec<ModuleInfo>,
+    symbols: Vec<Symbol>,
+    names: BTreeMap<String, Vec<usize>>,
+}
+
+impl SymbolIndex {
+    /// Builds a new index from a list of modules and their exports.
+    ///
+    /// Exports that do not point into their module (e.g. forwarded exports) are skipped.
+    pub fn new(modules: impl IntoIterator<Item = (ModuleInfo, Vec<ExportInfo>)>) -> Self {
+        let mut modules = modules.into_iter().collect::<Vec<_>>();
+        modules.sort_by_key(|(m, _)| m.base);
+
+        let mut symbols = vec![];
+        for (idx, (module, exports)) in modules.iter().enumerate() {
+            symbols.extend(
+                exports
+                    .iter()
+                    .filter(|e| e.offset < module.size)
+                    .map(|e| Symbol {
+                        name: e.name.to_string(),
+                        address: module.base + e.offset,
+                        module: idx,
+                    }),
+            );
+        }
+        symbols.sort_by_key(|s| s.address);
+
+        let mut names: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (idx, symbol) in symbols.iter().enumerate() {
+            names.entry(symbol.name.clone()).or_default().push(idx);
+        }
+
+        Self {
+            modules: modules.into_iter().map(|(m, _)| m).collect(),
+            symbols,
+            names,
+        }
+    }
+
+    /// Builds the index from all modules of a process.
+    ///
+    /// Modules whose export table can not be read are indexed without any symbols.
+    pub fn from_process(proc: &mut impl Process) -> Result<Self> {
+        let modules = proc.module_list()?;
+        Ok(Self::new(modules.into_iter().map(|m| {
+            let exports = proc.module_export_list(&m).unwrap_or_default();
+            (m, exports)
+        })))
+    }
+
+    /// Builds the index from all kernel modules of an OS.
+    ///
+    /// Modules whose export table can not be read are indexed without any symbols.
+    pub fn from_os(os: &mut impl Os) -> Result<Self> {
+        let modules = os.module_list()?;
+        Ok(Self::new(modules.into_iter().map(|m| {
+            let exports = os.module_export_list(&m).unwrap_or_default();
+            (m, exports)
+        })))
+    }
+
+    /// Rebuilds the index if the module list of the process changed.
+    ///
+    /// Returns true if the index has been rebuilt.
+    pub fn refresh_process(&mut self, proc: &mut impl Process) -> Result<bool> {
+        let modules = proc.module_list()?;
+        if self.is_current(&modules) {
+            return Ok(false);
+        }
+
+        *self = Self::new(modules.into_iter().map(|m| {
+            let exports = proc.module_export_list(&m).unwrap_or_default();
+            (m, exports)
+        }));
+        Ok(true)
+    }
+
+    /// Rebuilds the index if the kernel module list of the OS changed.
+    ///
+    /// Returns true if the index has been rebuilt.
+    pub fn refresh_os(&mut self, os: &mut impl Os) -> Result<bool> {
+        let modules = os.module_list()?;
+        if self.is_current(&modules) {
+            return Ok(false);
+        }
+
+        *self = Self::new(modules.into_iter().map(|m| {
+            let exports = os.module_export_list(&m).unwrap_or_default();
+            (m, exports)
+        }));
+        Ok(true)
+    }
+
+    /// Returns true if the index was built from the given module list.
+    ///
+    /// Modules are compared by their base address, size and name.
+    pub fn is_current(&self, modules: &[ModuleInfo]) -> bool {
+        modules.len() == self.modules.len()
+            && modules.iter().all(|m| {
+                self.module_by_address(m.base).map_or(false, |i| {
+                    i.base == m.base && i.size == m.size && i.name.as_ref() == m.name.as_ref()
+                })
+            })
+    }
+
+    /// Returns all indexed modules sorted by their base address.
+    pub fn modules(&self) -> &[ModuleInfo] {
+        &self.modules
+    }
+
+    /// Returns all indexed symbols sorted by their address.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Returns the module containing `address`.
+    pub fn module_by_address(&self, address: Address) -> Option<&ModuleInfo> {
+        let idx = self.module_idx(address)?;
+        Some(&self.modules[idx])
+    }
+
+    /// Returns the closest symbol at or below `address` inside the same module, as well as the
+    /// offset of `address` from that symbol.
+    pub fn symbol_by_address(&self, address: Address) -> Option<(&Symbol, umem)> {
+        let module = self.module_idx(address)?;
+
+        let idx = self.symbols.partition_point(|s| s.address <= address);
+        let symbol = self.symbols[..idx].last()?;
+
+        if symbol.module == module {
+            Some((symbol, (address - symbol.address) as umem))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the address of the symbol with the given name.
+    ///
+    /// The name is either a plain export name, or qualified with the module name in the form of
+    /// `module!export`. Module names are compared case insensitively. If an unqualified name is
+    /// exported by multiple modules, the symbol with the lowest address is returned.
+    pub fn address_by_name(&self, name: &str) -> Option<Address> {
+        let (module, name) = match name.split_once('!') {
+            Some((module, name)) => (Some(module), name),
+            None => (None, name),
+        };
+
+        self.names
+            .get(name)?
+            .iter()
+            .map(|&idx| &self.symbols[idx])
+            .find(|s| {
+                module.map_or(true, |module| {
+                    self.modules[s.module]
+                        .name
+                        .as_ref()
+                        .eq_ignore_ascii_case(module)
+                })
+            })
+            .map(|s| s.address)
+    }
+
+    /// Formats `address` as `module!export+0x1234`.
+    ///
+    /// The output is equivalent to [`format_address`](super::module::format_address).
+    pub fn format_address(&self, address: Address) -> String {
+        let module = match self.module_by_address(address) {
+            Some(module) => module,
+            None => return format_address(address, None, &[]),
+        };
+
+        let mut ret = String::new();
+        match self.symbol_by_address(address) {
+            Some((symbol, offset)) => write_symbol(&mut ret, module, Some(&symbol.name), offset),
+            None => write_symbol(&mut ret, module, None, (address - module.base) as umem),
+        }
+        ret
+    }
+
+    fn module_idx(&self, address: Address) -> Option<usize> {
+        let idx = self
+            .modules
+            .partition_point(|m| m.base <= address)
+            .checked_sub(1)?;
+
+        if self.modules[idx].contains(address) {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::ArchitectureIdent;
+
+    fn module(name: &str, base: u64, size: umem) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: base.into(),
+            size,
+            name: name.into(),
+            path: name.into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    fn export(name: &str, offset: umem) -> ExportInfo {
+        ExportInfo {
+            name: name.into(),
+            offset,
+        }
+    }
+
+    fn index() -> SymbolIndex {
+        SymbolIndex::new(vec![
+            (
+                module("b.dll", 0x20000, 0x1000),
+                vec![export("Close", 0x200), export("Open", 0x100)],
+            ),
+            (
+                module("a.dll", 0x10000, 0x1000),
+                vec![export("Open", 0x300), export("Forwarded", 0x5000)],
+            ),
+        ])
+    }
+
+    #[test]
+    fn lookup_address() {
+        let index = index();
+
+        assert_eq!(index.format_address(0x20210u64.into()), "b.dll!Close+0x10");
+        assert_eq!(index.format_address(0x20100u64.into()), "b.dll!Open");
+        assert_eq!(index.format_address(0x20010u64.into()), "b.dll+0x10");
+        assert_eq!(index.format_address(0x10010u64.into()), "a.dll+0x10");
+        assert_eq!(index.format_address(0x18000u64.into()), "0x18000");
+        assert!(index.symbol_by_address(0x20010u64.into()).is_none());
+    }
+
+    #[test]
+    fn lookup_name() {
+        let index = index();
+
+        assert_eq!(index.address_by_name("Open"), Some(0x10300u64.into()));
+        assert_eq!(index.address_by_name("B.DLL!Open"), Some(0x20100u64.into()));
+        assert_eq!(index.address_by_name("a.dll!Close"), None);
+        assert_eq!(index.address_by_name("Forwarded"), None);
+    }
+
+    #[test]
+    fn staleness() {
+        let index = index();
+        let mut modules = index.modules().to_vec();
+
+        assert!(index.is_current(&modules));
+
+        modules[0].size = 0x2000;
+        assert!(!index.is_current(&modules));
+    }
+}
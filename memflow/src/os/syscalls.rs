@@ -0,0 +1,300 @@
+//! Per-build system call tables.
+//!
+//! System call numbers are not stable, they change with every Windows build and depend on the
+//! configuration of a Linux kernel. Tools correlating traces (e.g. the `eax`/`rax` value of a
+//! `syscall` instruction) with memory need the mapping of the exact running build.
+//!
+//! A [`SyscallTable`] can be extracted from three sources:
+//!
+//! * [`SyscallTable::from_ntdll`] decodes the system call stubs exported by the `ntdll.dll`
+//!   of a process. Stubs which have been hooked are numbered by their position among all
+//!   stubs, which matches the numbering of recent Windows builds.
+//! * [`SyscallTable::from_service_table`] reads the kernel service table (`nt!KiServiceTable`),
+//!   which yields the kernel side handlers of the system calls.
+//! * [`SyscallTable::from_linux_table`] reads the Linux `sys_call_table`.
+//!
+//! Names of kernel side handlers are resolved through a caller provided function, e.g. a lookup
+//! in a [`SymbolIndex`](super::SymbolIndex) or in the symbols returned by
+//! [`read_kallsyms`](super::read_kallsyms).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::syscalls::SyscallTable;
+//!
+//! fn print_syscall(proc: &mut (impl Process + MemoryView), number: u32) -> Result<()> {
+//!     let ntdll = proc.module_by_name("ntdll.dll")?;
+//!     let table = SyscallTable::from_ntdll(proc, &ntdll)?;
+//!
+//!     match table.by_number(number) {
+//!         Some(entry) => println!("syscall {:#x} is {}", number, entry.name),
+//!         None => println!("syscall {:#x} is unknown", number),
+//!     }
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # assert!(print_syscall(&mut proc, 0x55).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use super::{ModuleInfo, Process};
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Maximum number of entries read from a kernel system call table.
+pub const MAX_SYSCALLS: u32 = 0x1000;
+
+/// A single system call.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SyscallEntry {
+    /// Number of the system call
+    pub number: u32,
+    /// Name of the system call, empty if it could not be resolved
+    pub name: String,
+    /// Address of the user mode stub or the kernel mode handler
+    pub address: Address,
+}
+
+/// Mapping of system call numbers to functions of a specific build.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SyscallTable {
+    entries: BTreeMap<u32, SyscallEntry>,
+}
+
+impl SyscallTable {
+    /// Creates a table from the given entries.
+    pub fn new(entries: impl IntoIterator<Item = SyscallEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().map(|e| (e.number, e)).collect(),
+        }
+    }
+
+    /// Extracts the system call numbers from the stubs exported by `ntdll`.
+    ///
+    /// The names are reported with the `Nt` prefix. Fails with `ErrorKind::NotFound` if the
+    /// module does not export any system call stubs.
+    pub fn from_ntdll(proc: &mut (impl Process + MemoryView), ntdll: &ModuleInfo) -> Result<Self> {
+        // every system call is exported both as Nt* and Zw*, but only Zw* is unique to stubs
+        let mut stubs = proc
+            .module_export_list(ntdll)?
+            .into_iter()
+            .filter(|e| e.name.starts_with("Zw"))
+            .map(|e| (ntdll.base + e.offset, format!("Nt{}", &e.name[2..])))
+            .collect::<Vec<_>>();
+        stubs.sort_by_key(|(address, _)| *address);
+        stubs.dedup_by_key(|(address, _)| *address);
+
+        if stubs.is_empty() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("module does not export any system call stubs"));
+        }
+
+        let mut entries = vec![];
+        for (idx, (address, name)) in stubs.into_iter().enumerate() {
+            let number = proc
+                .read_raw(address, 8)
+                .data_part()
+                .ok()
+                .and_then(|stub| decode_stub(&stub))
+                .unwrap_or(idx as u32);
+
+            entries.push(SyscallEntry {
+                number,
+                name,
+                address,
+            });
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    /// Reads `count` entries of the Windows kernel service table at `table`.
+    ///
+    /// On x64 the table consists of 32-bit offsets relative to the table, shifted left by 4
+    /// bits. On x86 it consists of absolute addresses. `resolve` is used to look up the names
+    /// of the handlers.
+    pub fn from_service_table(
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        table: Address,
+        count: u32,
+        mut resolve: impl FnMut(Address) -> Option<String>,
+    ) -> Result<Self> {
+        let count = std::cmp::min(count, MAX_SYSCALLS);
+        let buf = mem.read_raw(table, count as usize * 4).data_part()?;
+
+        let entries = buf
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(number, entry)| {
+                let entry = u32::from_le_bytes(entry.try_into().unwrap());
+                let address = if arch.bits() == 64 {
+                    let offset = (entry as i32 >> 4) as i64;
+                    Address::from((table.to_umem() as i64).wrapping_add(offset) as u64)
+                } else {
+                    Address::from(entry)
+                };
+                SyscallEntry {
+                    number: number as u32,
+                    name: resolve(address).unwrap_or_default(),
+                    address,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self::new(entries))
+    }
+
+    /// Reads `count` entries of the Linux `sys_call_table` at `table`.
+    ///
+    /// `resolve` is used to look up the names of the handlers. Entries pointing to
+    /// `sys_ni_syscall` denote unimplemented system calls and are skipped.
+    pub fn from_linux_table(
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        table: Address,
+        count: u32,
+        mut resolve: impl FnMut(Address) -> Option<String>,
+    ) -> Result<Self> {
+        let count = std::cmp::min(count, MAX_SYSCALLS);
+
+        let mut entries = vec![];
+        for number in 0..count {
+            let address = mem
+                .read_addr_arch(arch, table + number as umem * arch.size_addr() as umem)
+                .data_part()?;
+            let name = resolve(address).unwrap_or_default();
+            if name == "sys_ni_syscall" {
+                continue;
+            }
+            entries.push(SyscallEntry {
+                number,
+                name,
+                address,
+            });
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    /// Returns the system call with the given number.
+    pub fn by_number(&self, number: u32) -> Option<&SyscallEntry> {
+        self.entries.get(&number)
+    }
+
+    /// Returns the system call with the given name.
+    pub fn by_name(&self, name: &str) -> Option<&SyscallEntry> {
+        self.entries.values().find(|e| e.name == name)
+    }
+
+    /// Returns all system calls sorted by their number.
+    pub fn entries(&self) -> impl Iterator<Item = &SyscallEntry> + '_ {
+        self.entries.values()
+    }
+
+    /// Returns the number of system calls in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Decodes the system call number of an `ntdll` stub.
+///
+/// Recognizes the x64 stub (`mov r10, rcx; mov eax, imm32`) and the x86 stub
+/// (`mov eax, imm32`). Returns `None` if the stub has been modified, e.g. by a hook.
+pub fn decode_stub(stub: &[u8]) -> Option<u32> {
+    match stub {
+        [0x4c, 0x8b, 0xd1, 0xb8, a, b, c, d, ..] | [0xb8, a, b, c, d, ..] => {
+            Some(u32::from_le_bytes([*a, *b, *c, *d]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[test]
+    fn decode_stubs() {
+        assert_eq!(
+            decode_stub(&[0x4c, 0x8b, 0xd1, 0xb8, 0x55, 0x00, 0x00, 0x00]),
+            Some(0x55)
+        );
+        assert_eq!(decode_stub(&[0xb8, 0x26, 0x01, 0x00, 0x00]), Some(0x126));
+        assert_eq!(decode_stub(&[0xe9, 0x00, 0x10, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn service_table_x64() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        let table = Address::from(0x20000u64);
+        let handlers = [0x21000u32, 0x1f000];
+        for (i, handler) in handlers.iter().enumerate() {
+            let offset = (*handler as i32 - 0x20000) << 4;
+            view.write(table + i * 4, &offset).unwrap();
+        }
+
+        let syscalls = SyscallTable::from_service_table(&mut view, x64::ARCH, table, 2, |addr| {
+            match addr.to_umem() {
+                0x21000 => Some("NtAccessCheck".to_string()),
+                _ => None,
+            }
+        })
+        .unwrap();
+
+        assert_eq!(syscalls.len(), 2);
+        assert_eq!(
+            syscalls.by_name("NtAccessCheck").unwrap().address,
+            Address::from(0x21000u64)
+        );
+        assert_eq!(
+            syscalls.by_number(1).unwrap().address,
+            Address::from(0x1f000u64)
+        );
+    }
+
+    #[test]
+    fn linux_table() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        let table = Address::from(0x30000u64);
+        view.write(table, &0x1000u64).unwrap();
+        view.write(table + 8usize, &0x2000u64).unwrap();
+        view.write(table + 16usize, &0x1000u64).unwrap();
+
+        let syscalls = SyscallTable::from_linux_table(&mut view, x64::ARCH, table, 3, |addr| {
+            match addr.to_umem() {
+                0x1000 => Some("__x64_sys_read".to_string()),
+                _ => Some("sys_ni_syscall".to_string()),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(syscalls.len(), 2);
+        assert!(syscalls.by_number(1).is_none());
+        assert_eq!(syscalls.by_number(2).unwrap().name, "__x64_sys_read");
+    }
+}
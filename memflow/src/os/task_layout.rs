@@ -0,0 +1,386 @@
+//! Heuristic discovery of `task_struct` field offsets on Linux.
+//!
+//! Kernels built with structure layout randomization (`CONFIG_RANDSTRUCT`) shuffle the fields of
+//! `task_struct` with a per-build seed, so offsets taken from a different build of the same
+//! version are useless. Without debug information of the exact build the most important fields
+//! can still be located by the values they hold in the first tasks of the system:
+//!
+//! - `comm` of `init_task` holds the name `swapper` (or `swapper/0`)
+//! - `tasks` is a `list_head` linking all thread group leaders, starting with `init_task`, every
+//!   linked task has a printable `comm`
+//! - `pid` is 0 for `init_task` and 1 for the next task in the list, all pids are unique
+//! - `mm` is null for `init_task` and kernel threads like `kthreadd` (pid 2), but set to a unique
+//!   pointer for every user process
+//!
+//! [`TaskLayoutScanner`] applies these signatures to the memory of `init_task`, which can be
+//! resolved through [`read_kallsyms`](super::read_kallsyms). Fields that can not be located
+//! unambiguously are reported as `None`, so a randomized kernel can at least be partially
+//! introspected.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::task_layout::TaskLayoutScanner;
+//!
+//! fn list_tasks(kernel: &mut impl MemoryView, arch: ArchitectureObj, init_task: Address) -> Result<()> {
+//!     let layout = TaskLayoutScanner::new(arch).discover(kernel, init_task)?;
+//!
+//!     for task in layout.tasks(kernel, arch, init_task)? {
+//!         let comm: [u8; 16] = kernel.read(task + layout.comm).data_part()?;
+//!         match layout.pid {
+//!             Some(pid) => println!("{} {:?}", kernel.read::<u32>(task + pid).data_part()?, comm),
+//!             None => println!("? {:?}", comm),
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::architecture::x86::x64;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let base = proc.info().address;
+//! # assert!(list_tasks(&mut proc, x64::ARCH, base).is_err());
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use super::list::ListWalker;
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::Address;
+
+/// Number of bytes of every task that are searched for fields.
+pub const DEFAULT_SCAN_SIZE: usize = 0x3000;
+
+/// Maximum number of tasks inspected per candidate list.
+pub const DEFAULT_MAX_TASKS: usize = 0x1000;
+
+/// Highest pid the kernel can allocate (`PID_MAX_LIMIT` on 64-bit systems).
+const PID_MAX_LIMIT: u32 = 0x40_0000;
+
+/// Length of the `comm` field of a task.
+const TASK_COMM_LEN: usize = 16;
+
+/// Offsets of `task_struct` fields discovered through heuristics.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TaskLayout {
+    /// Offset of the `tasks` list head
+    pub tasks: usize,
+    /// Offset of the `comm` name
+    pub comm: usize,
+    /// Offset of the `pid`
+    ///
+    /// The `tgid` field holds the same values for all tasks in the `tasks` list, so this might
+    /// also be the offset of `tgid`.
+    pub pid: Option<usize>,
+    /// Offset of the `mm` pointer
+    pub mm: Option<usize>,
+}
+
+impl TaskLayout {
+    /// Returns the addresses of all tasks linked through `tasks`, starting with `init_task`.
+    pub fn tasks(
+        &self,
+        mem: &mut impl MemoryView,
+        arch: ArchitectureObj,
+        init_task: Address,
+    ) -> Result<Vec<Address>> {
+        let walk = ListWalker::new(arch).walk(mem, init_task + self.tasks)?;
+        Ok(std::iter::once(init_task)
+            .chain(walk.containers(self.tasks))
+            .collect())
+    }
+}
+
+/// Locates the fields of `task_struct` on kernels with randomized structure layouts.
+#[derive(Clone, Copy, Debug)]
+pub struct TaskLayoutScanner {
+    arch: ArchitectureObj,
+    scan_size: usize,
+    max_tasks: usize,
+}
+
+impl TaskLayoutScanner {
+    /// Constructs a new scanner for kernels of the given architecture.
+    pub fn new(arch: ArchitectureObj) -> Self {
+        Self {
+            arch,
+            scan_size: DEFAULT_SCAN_SIZE,
+            max_tasks: DEFAULT_MAX_TASKS,
+        }
+    }
+
+    /// Sets the number of bytes of every task that are searched for fields.
+    ///
+    /// This should be at least the size of `task_struct` of the target kernel.
+    pub fn scan_size(mut self, scan_size: usize) -> Self {
+        self.scan_size = scan_size;
+        self
+    }
+
+    /// Sets the maximum number of tasks inspected per candidate list.
+    pub fn max_tasks(mut self, max_tasks: usize) -> Self {
+        self.max_tasks = max_tasks;
+        self
+    }
+
+    /// Discovers the layout of `task_struct` from the task at `init_task`.
+    ///
+    /// Fails if `comm` or `tasks` can not be located, the remaining fields are optional.
+    pub fn discover(&self, mem: &mut impl MemoryView, init_task: Address) -> Result<TaskLayout> {
+        let init = mem.read_raw(init_task, self.scan_size).data_part()?;
+
+        let comm = find_comm(&init).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_info("unable to locate the comm of init_task")
+        })?;
+
+        let (tasks, list) = self
+            .find_tasks(mem, init_task, &init, comm)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                    .log_info("unable to locate the task list of init_task")
+            })?;
+
+        let mut bufs = vec![init];
+        for &task in list.iter() {
+            bufs.push(mem.read_raw(task, self.scan_size).data_part()?);
+        }
+
+        let pid = find_pid(&bufs);
+        let mm = pid.and_then(|pid| self.find_mm(&bufs, pid));
+
+        Ok(TaskLayout {
+            tasks,
+            comm,
+            pid,
+            mm,
+        })
+    }
+
+    /// Finds the `list_head` linking the most tasks with a valid `comm`.
+    fn find_tasks(
+        &self,
+        mem: &mut impl MemoryView,
+        init_task: Address,
+        init: &[u8],
+        comm: usize,
+    ) -> Option<(usize, Vec<Address>)> {
+        let ptr = self.arch.size_addr();
+        let walker = ListWalker::new(self.arch)
+            .retries(1)
+            .max_entries(self.max_tasks);
+
+        let mut best: Option<(usize, Vec<Address>)> = None;
+
+        for offset in (0..init.len().saturating_sub(2 * ptr)).step_by(ptr) {
+            let head = init_task + offset;
+            let next = read_ptr(init, offset, ptr);
+            let prev = read_ptr(init, offset + ptr, ptr);
+
+            // empty or invalid lists can not be the task list
+            if next.is_null() || prev.is_null() || next == head {
+                continue;
+            }
+
+            let walk = match walker.walk(mem, head) {
+                Ok(walk) if !walk.racy => walk,
+                _ => continue,
+            };
+
+            let tasks = walk.containers(offset).collect::<Vec<_>>();
+            if best
+                .as_ref()
+                .map_or(false, |(_, best)| best.len() >= tasks.len())
+            {
+                continue;
+            }
+
+            let valid = tasks.iter().all(|&task| {
+                mem.read::<[u8; TASK_COMM_LEN]>(task + comm)
+                    .data_part()
+                    .map_or(false, |name| is_valid_comm(&name))
+            });
+
+            if valid {
+                best = Some((offset, tasks));
+            }
+        }
+
+        best
+    }
+
+    /// Finds the `mm` pointer, it is null for `init_task` and kernel threads and unique otherwise.
+    fn find_mm(&self, bufs: &[Vec<u8>], pid: usize) -> Option<usize> {
+        let ptr = self.arch.size_addr();
+        let len = bufs.iter().map(Vec::len).min()?;
+
+        let pids = bufs
+            .iter()
+            .map(|buf| read_u32(buf, pid))
+            .collect::<Vec<_>>();
+        let kthreadd = pids.iter().position(|&pid| pid == 2);
+
+        let mut candidates = (0..len.saturating_sub(ptr)).step_by(ptr).filter(|&offset| {
+            let values = bufs
+                .iter()
+                .map(|buf| read_ptr(buf, offset, ptr))
+                .collect::<Vec<_>>();
+
+            let user = values[1..].iter().filter(|v| !v.is_null()).count();
+            values[0].is_null()
+                && !values[1].is_null()
+                && kthreadd.map_or(true, |i| values[i].is_null())
+                && all_unique(values[1..].iter().filter(|v| !v.is_null()))
+                && user > 0
+        });
+
+        // only report the field if it is unambiguous
+        match (candidates.next(), candidates.next()) {
+            (Some(offset), None) => Some(offset),
+            _ => None,
+        }
+    }
+}
+
+/// Finds `swapper` or `swapper/0` in the memory of `init_task`.
+fn find_comm(init: &[u8]) -> Option<usize> {
+    const SWAPPER: &[u8] = b"swapper";
+
+    init.windows(SWAPPER.len() + 1)
+        .position(|w| w.starts_with(SWAPPER) && (w[SWAPPER.len()] == 0 || w[SWAPPER.len()] == b'/'))
+        .filter(|&offset| offset + TASK_COMM_LEN <= init.len())
+}
+
+/// Finds the `pid`, it is 0 for `init_task`, 1 for the first task and unique for all tasks.
+fn find_pid(bufs: &[Vec<u8>]) -> Option<usize> {
+    if bufs.len() < 2 {
+        return None;
+    }
+
+    let len = bufs.iter().map(Vec::len).min()?;
+
+    (0..len.saturating_sub(4)).step_by(4).find(|&offset| {
+        let pids = bufs
+            .iter()
+            .map(|buf| read_u32(buf, offset))
+            .collect::<Vec<_>>();
+
+        pids[0] == 0
+            && pids[1] == 1
+            && pids[1..].iter().all(|&pid| pid > 0 && pid < PID_MAX_LIMIT)
+            && all_unique(pids.iter())
+    })
+}
+
+/// Checks if `name` is a nul terminated, non-empty and printable task name.
+fn is_valid_comm(name: &[u8]) -> bool {
+    let len = match name.iter().position(|&c| c == 0) {
+        Some(len) if len > 0 => len,
+        _ => return false,
+    };
+    name[..len].iter().all(|&c| (0x20..0x7f).contains(&c))
+}
+
+fn all_unique<T: Ord>(values: impl Iterator<Item = T>) -> bool {
+    let mut values = values.collect::<Vec<_>>();
+    let len = values.len();
+    values.sort_unstable();
+    values.dedup();
+    values.len() == len
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_ptr(buf: &[u8], offset: usize, size: usize) -> Address {
+    if size == 4 {
+        Address::from(read_u32(buf, offset))
+    } else {
+        Address::from(u64::from_le_bytes(
+            buf[offset..offset + 8].try_into().unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const TASK_SIZE: usize = 0x1000;
+
+    const TASKS: usize = 0x5a8;
+    const COMM: usize = 0x9e0;
+    const PID: usize = 0x2c4;
+    const TGID: usize = 0x7f0;
+    const MM: usize = 0x118;
+    const ACTIVE_MM: usize = 0x120;
+    const CHILDREN: usize = 0x300;
+
+    #[test]
+    fn randomized_layout() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let tasks = [
+            (0, "swapper/0", false),
+            (1, "systemd", true),
+            (2, "kthreadd", false),
+            (342, "bash", true),
+        ];
+        let task_addr = |i: usize| Address::from(0x10000 + i * TASK_SIZE);
+
+        for (i, &(pid, comm, user)) in tasks.iter().enumerate() {
+            let task = task_addr(i);
+            let next = task_addr((i + 1) % tasks.len()) + TASKS;
+            let prev = task_addr((i + tasks.len() - 1) % tasks.len()) + TASKS;
+
+            mem.phys_write(
+                (task + TASKS).into(),
+                &[next.to_umem() as u64, prev.to_umem() as u64],
+            )
+            .unwrap();
+            mem.phys_write((task + PID).into(), &(pid as u32)).unwrap();
+            mem.phys_write((task + TGID).into(), &(pid as u32)).unwrap();
+            mem.phys_write((task + COMM).into(), comm.as_bytes())
+                .unwrap();
+
+            let mm = if user { 0x80000 + i as u64 * 0x1000 } else { 0 };
+            mem.phys_write((task + MM).into(), &mm).unwrap();
+            mem.phys_write((task + ACTIVE_MM).into(), &0x7f000u64)
+                .unwrap();
+
+            // an empty children list points to itself
+            let children = (task + CHILDREN).to_umem() as u64;
+            mem.phys_write((task + CHILDREN).into(), &[children, children])
+                .unwrap();
+        }
+
+        let mut view = mem.phys_view();
+        let layout = TaskLayoutScanner::new(x64::ARCH)
+            .scan_size(TASK_SIZE)
+            .discover(&mut view, task_addr(0))
+            .unwrap();
+
+        assert_eq!(
+            layout,
+            TaskLayout {
+                tasks: TASKS,
+                comm: COMM,
+                pid: Some(PID),
+                mm: Some(MM),
+            }
+        );
+
+        let found = layout.tasks(&mut view, x64::ARCH, task_addr(0)).unwrap();
+        assert_eq!(found, (0..tasks.len()).map(task_addr).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,356 @@
+//! Parsing of Windows thread environment blocks.
+//!
+//! Every user mode thread on Windows has a thread environment block (TEB) which starts with the
+//! `NT_TIB`. It contains the bounds of the thread stack, the thread local storage slots, the last
+//! error value and the fiber data of the thread. Locating the stacks is the first step of
+//! scanning them for pointers or unwinding them.
+//!
+//! The layout of the fields used here has been stable since Windows XP, the known offsets are
+//! provided by [`TebLayout::windows_x64`] and [`TebLayout::windows_x86`].
+//!
+//! # Remarks
+//!
+//! WoW64 threads have both a 64-bit and a 32-bit TEB. The 32-bit TEB is located `0x2000` bytes
+//! after the 64-bit TEB and has to be read with the x86 architecture and layout.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::teb::TebReader;
+//!
+//! fn print_stack(mem: &mut impl MemoryView, teb: Address) -> Result<()> {
+//!     let teb = TebReader::new(x86::x64::ARCH).read(mem, teb)?;
+//!     println!(
+//!         "thread {} stack: {:x} - {:x}",
+//!         teb.tid, teb.stack_limit, teb.stack_base
+//!     );
+//!     Ok(())
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # let mut mem = DummyMemory::new(size::mb(1));
+//! # let mut view = mem.phys_view();
+//! # view.write(Address::from(0x1008), &0x20000u64).unwrap();
+//! # view.write(Address::from(0x1010), &0x1c000u64).unwrap();
+//! # view.write(Address::from(0x1030), &0x1000u64).unwrap();
+//! # print_stack(&mut view, Address::from(0x1000)).unwrap();
+//! ```
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use super::memory_map::{MemoryMapReport, RegionKind};
+use super::Pid;
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Number of TLS slots stored directly in the TEB.
+pub const TLS_MINIMUM_AVAILABLE: usize = 64;
+
+/// Number of TLS slots stored in the separately allocated expansion array.
+pub const TLS_EXPANSION_SLOTS: usize = 1024;
+
+/// Offsets of the fields of a thread environment block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TebLayout {
+    /// Offset of `NT_TIB::StackBase`
+    pub stack_base: usize,
+    /// Offset of `NT_TIB::StackLimit`
+    pub stack_limit: usize,
+    /// Offset of `NT_TIB::FiberData`
+    pub fiber_data: usize,
+    /// Offset of `NT_TIB::Self`
+    pub self_ptr: usize,
+    /// Offset of `ClientId.UniqueProcess`
+    pub pid: usize,
+    /// Offset of `ClientId.UniqueThread`
+    pub tid: usize,
+    /// Offset of `ThreadLocalStoragePointer`
+    pub tls_pointer: usize,
+    /// Offset of `ProcessEnvironmentBlock`
+    pub peb: usize,
+    /// Offset of `LastErrorValue`
+    pub last_error: usize,
+    /// Offset of `DeallocationStack`
+    pub deallocation_stack: usize,
+    /// Offset of `TlsSlots`
+    pub tls_slots: usize,
+    /// Offset of `TlsExpansionSlots`
+    pub tls_expansion_slots: usize,
+}
+
+impl TebLayout {
+    /// Returns the layout of a 64-bit TEB.
+    pub const fn windows_x64() -> Self {
+        Self {
+            stack_base: 0x8,
+            stack_limit: 0x10,
+            fiber_data: 0x20,
+            self_ptr: 0x30,
+            pid: 0x40,
+            tid: 0x48,
+            tls_pointer: 0x58,
+            peb: 0x60,
+            last_error: 0x68,
+            deallocation_stack: 0x1478,
+            tls_slots: 0x1480,
+            tls_expansion_slots: 0x1780,
+        }
+    }
+
+    /// Returns the layout of a 32-bit TEB.
+    pub const fn windows_x86() -> Self {
+        Self {
+            stack_base: 0x4,
+            stack_limit: 0x8,
+            fiber_data: 0x10,
+            self_ptr: 0x18,
+            pid: 0x20,
+            tid: 0x24,
+            tls_pointer: 0x2c,
+            peb: 0x30,
+            last_error: 0x34,
+            deallocation_stack: 0xe0c,
+            tls_slots: 0xe10,
+            tls_expansion_slots: 0xf94,
+        }
+    }
+}
+
+/// The parsed contents of a thread environment block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TebInfo {
+    /// Address of the TEB
+    pub address: Address,
+    /// Upper bound of the committed stack, the stack grows down from here
+    pub stack_base: Address,
+    /// Lower bound of the committed stack
+    pub stack_limit: Address,
+    /// Lower bound of the reserved stack
+    pub deallocation_stack: Address,
+    /// Fiber data of the thread, or its `Version` field if the thread is not a fiber
+    pub fiber_data: Address,
+    /// Pointer to the array of static TLS blocks of the loaded modules
+    pub tls_pointer: Address,
+    /// Address of the process environment block
+    pub peb: Address,
+    /// ID of the owning process
+    pub pid: Pid,
+    /// ID of the thread
+    pub tid: u32,
+    /// Value returned by `GetLastError` in the context of the thread
+    pub last_error: u32,
+    /// The dynamic TLS slots stored in the TEB
+    pub tls_slots: Vec<Address>,
+    /// Address of the expansion array of TLS slots, null if it has not been allocated
+    pub tls_expansion_slots: Address,
+}
+
+impl TebInfo {
+    /// Returns the reserved stack range from its lower bound to the stack base.
+    ///
+    /// Only the range from [`stack_limit`](Self::stack_limit) to the stack base is committed,
+    /// the remainder is reserved and usually not mapped.
+    pub fn stack_range(&self) -> (Address, Address) {
+        let start = if self.deallocation_stack.is_null() {
+            self.stack_limit
+        } else {
+            self.deallocation_stack
+        };
+        (start, self.stack_base)
+    }
+
+    /// Returns true if `address` lies within the committed part of the stack.
+    pub fn stack_contains(&self, address: Address) -> bool {
+        address >= self.stack_limit && address < self.stack_base
+    }
+
+    /// Annotates the TEB and the stack of the thread in a memory map.
+    pub fn annotate(&self, report: &mut MemoryMapReport, teb_size: umem) {
+        let details = format!("Thread {}", self.tid);
+        let (start, end) = self.stack_range();
+        if end > start {
+            report.annotate(RegionKind::Stack, start, (end - start) as umem, &details);
+        }
+        report.annotate(RegionKind::Teb, self.address, teb_size, &details);
+    }
+}
+
+/// Reads thread environment blocks of a given architecture.
+#[derive(Clone, Copy, Debug)]
+pub struct TebReader {
+    arch: ArchitectureObj,
+    layout: TebLayout,
+}
+
+impl TebReader {
+    /// Constructs a new reader with the known layout for the bitness of `arch`.
+    pub fn new(arch: ArchitectureObj) -> Self {
+        let layout = if arch.bits() == 64 {
+            TebLayout::windows_x64()
+        } else {
+            TebLayout::windows_x86()
+        };
+        Self { arch, layout }
+    }
+
+    /// Overrides the layout of the TEB.
+    pub fn layout(mut self, layout: TebLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Reads the TEB at `teb`.
+    ///
+    /// Fails with `ErrorKind::Offset` if the `Self` pointer of the TEB does not point back to
+    /// `teb`, which indicates that the address is not a TEB or the layout does not match.
+    pub fn read(&self, mem: &mut impl MemoryView, teb: Address) -> Result<TebInfo> {
+        let layout = &self.layout;
+
+        let self_ptr = self.read_ptr(mem, teb + layout.self_ptr)?;
+        if self_ptr != teb {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_debug("self pointer of the teb does not match its address"));
+        }
+
+        let slots_size = TLS_MINIMUM_AVAILABLE * self.arch.size_addr();
+        let slots = mem
+            .read_raw(teb + layout.tls_slots, slots_size)
+            .data_part()?;
+        let tls_slots = slots
+            .chunks_exact(self.arch.size_addr())
+            .map(|slot| match *slot {
+                [a, b, c, d] => Address::from(u32::from_le_bytes([a, b, c, d])),
+                _ => Address::from(u64::from_le_bytes(slot.try_into().unwrap_or_default())),
+            })
+            .collect();
+
+        Ok(TebInfo {
+            address: teb,
+            stack_base: self.read_ptr(mem, teb + layout.stack_base)?,
+            stack_limit: self.read_ptr(mem, teb + layout.stack_limit)?,
+            deallocation_stack: self.read_ptr(mem, teb + layout.deallocation_stack)?,
+            fiber_data: self.read_ptr(mem, teb + layout.fiber_data)?,
+            tls_pointer: self.read_ptr(mem, teb + layout.tls_pointer)?,
+            peb: self.read_ptr(mem, teb + layout.peb)?,
+            pid: mem.read::<u32>(teb + layout.pid).data_part()?,
+            tid: mem.read::<u32>(teb + layout.tid).data_part()?,
+            last_error: mem.read::<u32>(teb + layout.last_error).data_part()?,
+            tls_slots,
+            tls_expansion_slots: self.read_ptr(mem, teb + layout.tls_expansion_slots)?,
+        })
+    }
+
+    /// Reads the value of the TLS slot `index` of the thread.
+    ///
+    /// Indices above [`TLS_MINIMUM_AVAILABLE`] are read from the expansion array, which yields
+    /// a null value if the thread did not allocate it yet.
+    pub fn tls_value(
+        &self,
+        mem: &mut impl MemoryView,
+        teb: &TebInfo,
+        index: usize,
+    ) -> Result<Address> {
+        if let Some(&value) = teb.tls_slots.get(index) {
+            return Ok(value);
+        }
+
+        let index = index - TLS_MINIMUM_AVAILABLE;
+        if index >= TLS_EXPANSION_SLOTS {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                .log_debug("tls index out of bounds"));
+        }
+
+        if teb.tls_expansion_slots.is_null() {
+            return Ok(Address::null());
+        }
+
+        self.read_ptr(mem, teb.tls_expansion_slots + index * self.arch.size_addr())
+    }
+
+    fn read_ptr(&self, mem: &mut impl MemoryView, addr: Address) -> Result<Address> {
+        mem.read_addr_arch(self.arch, addr).data_part()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::{x32, x64};
+    use crate::cglue::CTup3;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::{size, PageType};
+
+    #[test]
+    fn read_teb64() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+        let teb = Address::from(0x10000u64);
+        let layout = TebLayout::windows_x64();
+
+        view.write(teb + layout.stack_base, &0x80000u64).unwrap();
+        view.write(teb + layout.stack_limit, &0x7c000u64).unwrap();
+        view.write(teb + layout.self_ptr, &0x10000u64).unwrap();
+        view.write(teb + layout.tid, &1234u32).unwrap();
+        view.write(teb + layout.last_error, &5u32).unwrap();
+        view.write(teb + layout.deallocation_stack, &0x40000u64)
+            .unwrap();
+        view.write(teb + layout.tls_slots + 3 * 8, &0xabcdu64)
+            .unwrap();
+        view.write(teb + layout.tls_expansion_slots, &0x20000u64)
+            .unwrap();
+        view.write(Address::from(0x20000u64 + 2 * 8), &0x1337u64)
+            .unwrap();
+
+        let reader = TebReader::new(x64::ARCH);
+        let info = reader.read(&mut view, teb).unwrap();
+
+        assert_eq!(info.tid, 1234);
+        assert_eq!(info.last_error, 5);
+        assert_eq!(
+            info.stack_range(),
+            (Address::from(0x40000u64), Address::from(0x80000u64))
+        );
+        assert!(info.stack_contains(Address::from(0x7d000u64)));
+        assert_eq!(info.tls_slots.len(), TLS_MINIMUM_AVAILABLE);
+        assert_eq!(
+            reader.tls_value(&mut view, &info, 3).unwrap(),
+            Address::from(0xabcdu64)
+        );
+        assert_eq!(
+            reader.tls_value(&mut view, &info, 66).unwrap(),
+            Address::from(0x1337u64)
+        );
+
+        let mut report = MemoryMapReport::new(vec![
+            CTup3(Address::from(0x10000u64), 0x2000, PageType::WRITEABLE),
+            CTup3(Address::from(0x7c000u64), 0x4000, PageType::WRITEABLE),
+        ]);
+        info.annotate(&mut report, 0x2000);
+        assert_eq!(
+            report.describe(Address::from(0x7d000u64)),
+            "Stack (Thread 1234)+0x1000"
+        );
+        assert_eq!(
+            report.describe(Address::from(0x10010u64)),
+            "Teb (Thread 1234)+0x10"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_teb() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        let err = TebReader::new(x32::ARCH)
+            .read(&mut view, Address::from(0x10000u64))
+            .unwrap_err();
+        assert_eq!(err.1, ErrorKind::Offset);
+    }
+}
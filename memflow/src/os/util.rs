@@ -371,9 +371,10 @@ pub fn section_list_callback(
         if let Ok(pe) = pelite::PeView::from_bytes(module_image) {
             let iter = pe.section_headers().iter().filter_map(|sh| {
                 sh.name().ok().map(|name| {
+                    let range = sh.virtual_range();
                     (
-                        sh.virtual_range().start as umem,
-                        sh.virtual_range().end as umem,
+                        range.start as umem,
+                        (range.end - range.start) as umem,
                         name.into(),
                     )
                 })
@@ -0,0 +1,348 @@
+//! Classification of Linux and Android memory mappings.
+//!
+//! The address space of a Linux process is described by a set of `vm_area_struct`s. Besides
+//! anonymous memory and regular file mappings, Android processes map a number of special files
+//! which are essential for understanding the process:
+//! * `ashmem` regions used for shared memory between apps and system services
+//! * the `binder` transaction buffer used for inter process communication
+//! * `dmabuf` buffers shared with graphics, camera and media hardware
+//!
+//! [`VmaReader`] reads the `vm_area_struct`s of a process, resolves the path of the backing file
+//! by walking the `d_parent` chain of its dentry and classifies the mapping accordingly.
+//!
+//! As structure offsets differ between kernel versions and configurations they have to be
+//! provided through a [`VmaLayout`], e.g. from a [`TypeDatabase`](super::TypeDatabase) filled
+//! from the kernel debug information.
+//!
+//! # Remarks
+//!
+//! The resolved path is relative to the root of the filesystem the file resides on, mount
+//! points are not followed. Kernels starting with version 6.1 store the mappings in a maple
+//! tree instead of a linked list, in that case the `vm_area_struct` addresses have to be
+//! gathered by the caller and passed to [`VmaReader::read_vma`] individually.
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{Address, PageType};
+
+/// Default maximum number of path components resolved for a backing file.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Maximum length of a single path component.
+const MAX_NAME_LEN: usize = 256;
+
+/// Maximum number of mappings walked, protecting against corrupted or cyclic lists.
+const MAX_VMAS: usize = 0x10000;
+
+const VM_READ: u64 = 0x1;
+const VM_WRITE: u64 = 0x2;
+const VM_EXEC: u64 = 0x4;
+const VM_SHARED: u64 = 0x8;
+
+/// Offsets of the kernel structures required for reading memory mappings.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct VmaLayout {
+    /// Offset of `vm_area_struct::vm_start`
+    pub vm_start: usize,
+    /// Offset of `vm_area_struct::vm_end`
+    pub vm_end: usize,
+    /// Offset of `vm_area_struct::vm_next`, not present on kernels using a maple tree
+    pub vm_next: Option<usize>,
+    /// Offset of `vm_area_struct::vm_flags`
+    pub vm_flags: usize,
+    /// Offset of `vm_area_struct::vm_file`
+    pub vm_file: usize,
+    /// Offset of `file::f_path.dentry`
+    pub file_dentry: usize,
+    /// Offset of `dentry::d_parent`
+    pub dentry_parent: usize,
+    /// Offset of `dentry::d_name.name`
+    pub dentry_name: usize,
+}
+
+/// The kind of memory backing a mapping.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum MappingKind {
+    /// Anonymous memory without a backing file (heap, stacks, ...)
+    Anonymous,
+    /// A regular file mapping
+    File,
+    /// An Android shared memory region
+    Ashmem,
+    /// The binder transaction buffer (binder, hwbinder, vndbinder)
+    Binder,
+    /// A buffer shared with hardware through the dma-buf framework
+    DmaBuf,
+}
+
+impl MappingKind {
+    /// Classifies a mapping by the path of its backing file.
+    pub fn from_path(path: Option<&str>) -> Self {
+        match path {
+            None => MappingKind::Anonymous,
+            Some(path) if path.contains("ashmem") => MappingKind::Ashmem,
+            Some(path) if path.contains("binder") => MappingKind::Binder,
+            Some(path) if path.contains("dmabuf") => MappingKind::DmaBuf,
+            Some(_) => MappingKind::File,
+        }
+    }
+}
+
+/// Information about a single memory mapping.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct VmaInfo {
+    /// Address of the `vm_area_struct`
+    pub address: Address,
+    /// First address of the mapping
+    pub start: Address,
+    /// Address right after the end of the mapping
+    pub end: Address,
+    /// The raw `vm_flags` of the mapping
+    pub flags: u64,
+    /// The kind of memory backing the mapping
+    pub kind: MappingKind,
+    /// Path of the backing file, if the mapping is backed by a file
+    pub path: Option<String>,
+}
+
+impl VmaInfo {
+    /// Returns the size of the mapping in bytes.
+    pub fn size(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    /// Returns true if the mapping is readable.
+    pub fn readable(&self) -> bool {
+        self.flags & VM_READ != 0
+    }
+
+    /// Returns true if the mapping is writeable.
+    pub fn writeable(&self) -> bool {
+        self.flags & VM_WRITE != 0
+    }
+
+    /// Returns true if the mapping is executable.
+    pub fn executable(&self) -> bool {
+        self.flags & VM_EXEC != 0
+    }
+
+    /// Returns true if the mapping is shared with other processes.
+    pub fn shared(&self) -> bool {
+        self.flags & VM_SHARED != 0
+    }
+
+    /// Returns the protection of the mapping as a [`PageType`].
+    pub fn page_type(&self) -> PageType {
+        PageType::USER
+            .write(self.writeable())
+            .noexec(!self.executable())
+    }
+}
+
+/// Reads and classifies the memory mappings of a Linux process.
+#[derive(Clone, Copy, Debug)]
+pub struct VmaReader {
+    arch: ArchitectureObj,
+    layout: VmaLayout,
+    max_depth: usize,
+}
+
+impl VmaReader {
+    /// Constructs a new reader for the given architecture and structure layout.
+    pub fn new(arch: ArchitectureObj, layout: VmaLayout) -> Self {
+        Self {
+            arch,
+            layout,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Sets the maximum number of path components resolved for a backing file.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Reads and classifies the `vm_area_struct` at `vma`.
+    ///
+    /// A backing file whose path can not be resolved results in an empty path, the mapping is
+    /// still reported as a file mapping in that case.
+    pub fn read_vma(&self, mem: &mut impl MemoryView, vma: Address) -> Result<VmaInfo> {
+        let start = self.read_ptr(mem, vma + self.layout.vm_start)?;
+        let end = self.read_ptr(mem, vma + self.layout.vm_end)?;
+        let flags = self.read_ptr(mem, vma + self.layout.vm_flags)?.to_umem() as u64;
+        let file = self.read_ptr(mem, vma + self.layout.vm_file)?;
+
+        let path = if file.is_null() {
+            None
+        } else {
+            Some(self.file_path(mem, file).unwrap_or_default())
+        };
+
+        Ok(VmaInfo {
+            address: vma,
+            start,
+            end,
+            flags,
+            kind: MappingKind::from_path(path.as_deref()),
+            path,
+        })
+    }
+
+    /// Reads all mappings linked through `vm_next`, starting at `first_vma` (`mm_struct::mmap`).
+    ///
+    /// Fails with `ErrorKind::NotSupported` if the layout does not contain `vm_next`.
+    pub fn walk_list(&self, mem: &mut impl MemoryView, first_vma: Address) -> Result<Vec<VmaInfo>> {
+        let vm_next = self.layout.vm_next.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+                .log_debug("vm_next is not available for this kernel")
+        })?;
+
+        let mut vmas = vec![];
+        let mut vma = first_vma;
+        while !vma.is_null() && vmas.len() < MAX_VMAS {
+            vmas.push(self.read_vma(mem, vma)?);
+            vma = self.read_ptr(mem, vma + vm_next)?;
+        }
+
+        Ok(vmas)
+    }
+
+    /// Resolves the path of the `struct file` at `file` by walking its dentry chain.
+    pub fn file_path(&self, mem: &mut impl MemoryView, file: Address) -> Result<String> {
+        let mut dentry = self.read_ptr(mem, file + self.layout.file_dentry)?;
+        if dentry.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("file does not have a dentry"));
+        }
+
+        let mut components = vec![];
+        for _ in 0..self.max_depth {
+            let name_ptr = self.read_ptr(mem, dentry + self.layout.dentry_name)?;
+            let name = mem.read_char_string_n(name_ptr, MAX_NAME_LEN).data_part()?;
+
+            let parent = self.read_ptr(mem, dentry + self.layout.dentry_parent)?;
+            // the root dentry of a filesystem is its own parent
+            if parent == dentry || parent.is_null() {
+                if name != "/" {
+                    components.push(name);
+                }
+                break;
+            }
+
+            components.push(name);
+            dentry = parent;
+        }
+
+        let path = components
+            .iter()
+            .rev()
+            .fold(String::new(), |path, name| path + "/" + name);
+        Ok(path)
+    }
+
+    fn read_ptr(&self, mem: &mut impl MemoryView, addr: Address) -> Result<Address> {
+        mem.read_addr_arch(self.arch, addr).data_part()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const LAYOUT: VmaLayout = VmaLayout {
+        vm_start: 0x0,
+        vm_end: 0x8,
+        vm_next: Some(0x10),
+        vm_flags: 0x20,
+        vm_file: 0x28,
+        file_dentry: 0x18,
+        dentry_parent: 0x8,
+        dentry_name: 0x20,
+    };
+
+    fn write_dentry(
+        mem: &mut impl MemoryView,
+        dentry: u64,
+        parent: u64,
+        name_ptr: u64,
+        name: &str,
+    ) {
+        mem.write(Address::from(dentry + 0x8), &parent).unwrap();
+        mem.write(Address::from(dentry + 0x20), &name_ptr).unwrap();
+        mem.write_raw(Address::from(name_ptr), name.as_bytes())
+            .unwrap();
+        mem.write(Address::from(name_ptr + name.len() as u64), &0u8)
+            .unwrap();
+    }
+
+    #[test]
+    fn classify_binder_mapping() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        // dentries of "/dev/binder"
+        write_dentry(&mut view, 0x1000, 0x1000, 0x1800, "/");
+        write_dentry(&mut view, 0x2000, 0x1000, 0x2800, "dev");
+        write_dentry(&mut view, 0x3000, 0x2000, 0x3800, "binder");
+        view.write(Address::from(0x4018u64), &0x3000u64).unwrap();
+
+        // anonymous rw mapping followed by the binder mapping
+        view.write(Address::from(0x5000u64), &0x7000_0000u64)
+            .unwrap();
+        view.write(Address::from(0x5008u64), &0x7000_4000u64)
+            .unwrap();
+        view.write(Address::from(0x5010u64), &0x6000u64).unwrap();
+        view.write(Address::from(0x5020u64), &(VM_READ | VM_WRITE))
+            .unwrap();
+
+        view.write(Address::from(0x6000u64), &0x7100_0000u64)
+            .unwrap();
+        view.write(Address::from(0x6008u64), &0x7110_0000u64)
+            .unwrap();
+        view.write(Address::from(0x6020u64), &VM_READ).unwrap();
+        view.write(Address::from(0x6028u64), &0x4000u64).unwrap();
+
+        let vmas = VmaReader::new(x64::ARCH, LAYOUT)
+            .walk_list(&mut view, Address::from(0x5000u64))
+            .unwrap();
+
+        assert_eq!(vmas.len(), 2);
+        assert_eq!(vmas[0].kind, MappingKind::Anonymous);
+        assert_eq!(vmas[0].size(), 0x4000);
+        assert!(vmas[0].writeable());
+        assert_eq!(vmas[1].kind, MappingKind::Binder);
+        assert_eq!(vmas[1].path.as_deref(), Some("/dev/binder"));
+        assert!(!vmas[1].writeable());
+    }
+
+    #[test]
+    fn classify_paths() {
+        assert_eq!(
+            MappingKind::from_path(Some("/dev/ashmem/dalvik-main space")),
+            MappingKind::Ashmem
+        );
+        assert_eq!(
+            MappingKind::from_path(Some("/dev/hwbinder")),
+            MappingKind::Binder
+        );
+        assert_eq!(
+            MappingKind::from_path(Some("/dmabuf:")),
+            MappingKind::DmaBuf
+        );
+        assert_eq!(
+            MappingKind::from_path(Some("/system/lib64/libc.so")),
+            MappingKind::File
+        );
+    }
+}
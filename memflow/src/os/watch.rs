@@ -0,0 +1,230 @@
+//! Detection of target reboots and kernel updates.
+//!
+//! OS layers locate their kernel structures once during initialization. When the target reboots
+//! or installs a kernel update while memflow is attached, all of these structures are gone, but
+//! reads still succeed and silently return garbage.
+//!
+//! The [`TargetWatch`] remembers a few anchors of the target at initialization time: the kernel
+//! base and version from the [`OsInfo`], and arbitrary byte ranges like the kernel image header
+//! or the kernel half of the top level page table. Validating these periodically detects a
+//! changed target, which is reported as an error of kind [`ErrorKind::TargetChanged`], so the
+//! caller can reinitialize the OS layer.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::os::watch::TargetWatch;
+//!
+//! fn watch(os: &mut (impl Os + MemoryView), dtb: Address, phys: &mut impl MemoryView) -> Result<()> {
+//!     let info = os.info().clone();
+//!
+//!     let mut watch = TargetWatch::new(&info)
+//!         // the kernel image header is overwritten once a different kernel is loaded
+//!         .anchor(os, info.base, 0x200)?
+//!         // the kernel half of the top level page table changes with every boot
+//!         .anchor(phys, dtb + 0x800usize, 0x800)?;
+//!
+//!     // later on
+//!     match watch.check(&info, os).and_then(|_| watch.check_anchor(1, phys)) {
+//!         Err(Error(_, ErrorKind::TargetChanged)) => println!("target changed, reinitializing"),
+//!         res => res?,
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::OsInfo;
+use crate::types::{umem, Address};
+
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Detects reboots and kernel updates of the target by validating previously captured anchors.
+#[derive(Clone, Debug)]
+pub struct TargetWatch {
+    base: Address,
+    size: umem,
+    build: u32,
+    revision: u32,
+    anchors: Vec<(Address, Vec<u8>)>,
+    #[cfg(feature = "std")]
+    interval: Option<Duration>,
+    #[cfg(feature = "std")]
+    last_check: Option<Instant>,
+}
+
+impl TargetWatch {
+    /// Creates a new watch remembering the kernel base and version of `info`.
+    pub fn new(info: &OsInfo) -> Self {
+        Self {
+            base: info.base,
+            size: info.size,
+            build: info.version.build,
+            revision: info.version.revision,
+            anchors: vec![],
+            #[cfg(feature = "std")]
+            interval: None,
+            #[cfg(feature = "std")]
+            last_check: None,
+        }
+    }
+
+    /// Captures `len` bytes at `addr` as an anchor.
+    ///
+    /// The bytes are read from `mem` right away and have to stay the same for the lifetime of
+    /// the target. Anchors are identified by the order they were added in.
+    pub fn anchor(mut self, mem: &mut impl MemoryView, addr: Address, len: usize) -> Result<Self> {
+        let bytes = mem.read_raw(addr, len).data()?;
+        self.anchors.push((addr, bytes));
+        Ok(self)
+    }
+
+    /// Sets the minimum time between two checks performed by [`poll`](Self::poll).
+    #[cfg(feature = "std")]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Returns all anchors as `(address, bytes)` pairs.
+    pub fn anchors(&self) -> &[(Address, Vec<u8>)] {
+        &self.anchors
+    }
+
+    /// Validates the kernel base, version and all anchors.
+    ///
+    /// All anchors are read through `mem`. Anchors captured through a different memory view
+    /// can be validated separately using [`check_anchor`](Self::check_anchor).
+    ///
+    /// An anchor that cannot be read anymore is treated as changed.
+    pub fn check(&mut self, info: &OsInfo, mem: &mut impl MemoryView) -> Result<()> {
+        self.check_info(info)?;
+        (0..self.anchors.len()).try_for_each(|idx| self.check_anchor(idx, mem))
+    }
+
+    /// Validates the kernel base and version of `info`.
+    ///
+    /// This is useful for OS layers that re-derive their info block, without having to read any
+    /// anchors.
+    pub fn check_info(&mut self, info: &OsInfo) -> Result<()> {
+        #[cfg(feature = "std")]
+        {
+            self.last_check = Some(Instant::now());
+        }
+
+        if info.base != self.base
+            || info.size != self.size
+            || info.version.build != self.build
+            || info.version.revision != self.revision
+        {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::TargetChanged).log_info(format!(
+                    "kernel changed from {:x} (build {}.{}) to {:x} (build {}.{})",
+                    self.base,
+                    self.build,
+                    self.revision,
+                    info.base,
+                    info.version.build,
+                    info.version.revision
+                )),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Validates a single anchor by reading it through `mem`.
+    ///
+    /// Returns an error of kind [`ErrorKind::NotFound`] if there is no anchor with index `idx`.
+    pub fn check_anchor(&self, idx: usize, mem: &mut impl MemoryView) -> Result<()> {
+        let (addr, bytes) = self
+            .anchors
+            .get(idx)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))?;
+
+        match mem.read_raw(*addr, bytes.len()).data() {
+            Ok(cur) if &cur == bytes => Ok(()),
+            _ => Err(Error(ErrorOrigin::OsLayer, ErrorKind::TargetChanged)
+                .log_info(format!("anchor at {:x} changed", addr))),
+        }
+    }
+
+    /// Validates the target through [`check`](Self::check) if the interval elapsed.
+    ///
+    /// Returns `true` if a check was performed. Without an interval every call performs a check.
+    #[cfg(feature = "std")]
+    pub fn poll(&mut self, info: &OsInfo, mem: &mut impl MemoryView) -> Result<bool> {
+        let due = match (self.interval, self.last_check) {
+            (Some(interval), Some(last)) => last.elapsed() >= interval,
+            _ => true,
+        };
+
+        if due {
+            self.check(info, mem)?;
+        }
+
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::ArchitectureIdent;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::os::OsVersion;
+    use crate::types::size;
+
+    fn info() -> OsInfo {
+        OsInfo {
+            base: 0x10000.into(),
+            size: size::kb(64) as umem,
+            arch: ArchitectureIdent::X86(64, false),
+            version: OsVersion {
+                build: 22621,
+                ..OsVersion::default()
+            },
+        }
+    }
+
+    #[test]
+    fn detect_change() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x10000.into(), b"MZ\x90\x00").unwrap();
+
+        let mut info = info();
+        let mut watch = TargetWatch::new(&info)
+            .anchor(&mut mem.phys_view(), info.base, 4)
+            .unwrap();
+        watch.check(&info, &mut mem.phys_view()).unwrap();
+
+        mem.phys_write(0x10000.into(), &[0u8; 4]).unwrap();
+        assert_eq!(
+            watch.check(&info, &mut mem.phys_view()).unwrap_err().1,
+            ErrorKind::TargetChanged
+        );
+
+        info.version.build += 1;
+        assert_eq!(
+            watch.check_info(&info).unwrap_err().1,
+            ErrorKind::TargetChanged
+        );
+    }
+
+    #[test]
+    fn poll_interval() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let info = info();
+        let mut watch = TargetWatch::new(&info).interval(Duration::from_secs(3600));
+
+        assert!(watch.poll(&info, &mut mem.phys_view()).unwrap());
+        assert!(!watch.poll(&info, &mut mem.phys_view()).unwrap());
+    }
+}
@@ -22,6 +22,9 @@ pub type MuConnectorInstanceArcBox<'a> = std::mem::MaybeUninit<ConnectorInstance
 
 /// This creates a cglue plugin instance from the given [`PhysicalMemory`] object.
 /// This also configures caching based on the provided input `args`.
+///
+/// Connectors declaring read constraints in their [`PhysicalMemoryMetadata`] are wrapped in a
+/// [`ConstrainedPhysicalMemory`] before any other middleware is applied.
 pub fn create_instance<T: Send + 'static + PhysicalMemory>(
     conn: T,
     lib: LibArc,
@@ -29,6 +32,51 @@ pub fn create_instance<T: Send + 'static + PhysicalMemory>(
     no_default_cache: bool,
 ) -> ConnectorInstanceArcBox<'static>
 // TODO: get rid of these trait bounds
+where
+    (T, LibArc): Into<ConnectorInstanceBaseArcBox<'static, T, c_void>>,
+    (
+        CachedPhysicalMemory<'static, T, TimedCacheValidator>,
+        LibArc,
+    ): Into<
+        ConnectorInstanceBaseArcBox<
+            'static,
+            CachedPhysicalMemory<'static, T, TimedCacheValidator>,
+            c_void,
+        >,
+    >,
+    (ConstrainedPhysicalMemory<T>, LibArc):
+        Into<ConnectorInstanceBaseArcBox<'static, ConstrainedPhysicalMemory<T>, c_void>>,
+    (
+        CachedPhysicalMemory<'static, ConstrainedPhysicalMemory<T>, TimedCacheValidator>,
+        LibArc,
+    ): Into<
+        ConnectorInstanceBaseArcBox<
+            'static,
+            CachedPhysicalMemory<'static, ConstrainedPhysicalMemory<T>, TimedCacheValidator>,
+            c_void,
+        >,
+    >,
+{
+    let constrained = ConstrainedPhysicalMemory::new(conn);
+    if constrained.is_constrained() {
+        info!(
+            "Inserting `ConstrainedPhysicalMemory` middleware with max_read_size={}, alignment={}",
+            constrained.max_read_size(),
+            constrained.alignment()
+        );
+        insert_middleware(constrained, lib, args, no_default_cache)
+    } else {
+        insert_middleware(constrained.into_inner(), lib, args, no_default_cache)
+    }
+}
+
+/// Applies all middleware requested in `args` to `conn`.
+fn insert_middleware<T: Send + 'static + PhysicalMemory>(
+    conn: T,
+    lib: LibArc,
+    args: &ConnectorArgs,
+    no_default_cache: bool,
+) -> ConnectorInstanceArcBox<'static>
 where
     (T, LibArc): Into<ConnectorInstanceBaseArcBox<'static, T, c_void>>,
     (
@@ -87,6 +135,36 @@ where
         conn
     };
 
+    let conn =
+        if args.middleware_args.throttle_bytes > 0 || args.middleware_args.throttle_requests > 0 {
+            info!(
+                "Inserting `ThrottledMemory` middleware with bytes/s={}, requests/s={}",
+                args.middleware_args.throttle_bytes, args.middleware_args.throttle_requests
+            );
+
+            let conn = ThrottledMemory::builder(conn)
+                .bytes_per_sec(args.middleware_args.throttle_bytes)
+                .requests_per_sec(args.middleware_args.throttle_requests)
+                .build()
+                .unwrap();
+            group_obj!((conn, lib.clone()) as ConnectorInstance)
+        } else {
+            conn
+        };
+
+    let conn = if args.middleware_args.trace_slow > 0 {
+        info!(
+            "Inserting `TracedPhysicalMemory` middleware with threshold={}ms",
+            args.middleware_args.trace_slow
+        );
+
+        let conn =
+            TracedPhysicalMemory::new(conn, Duration::from_millis(args.middleware_args.trace_slow));
+        group_obj!((conn, lib.clone()) as ConnectorInstance)
+    } else {
+        conn
+    };
+
     if args.middleware_args.metrics {
         info!("Inserting `PhysicalMemoryMetrics` middleware",);
         let conn = PhysicalMemoryMetrics::new(conn);
@@ -109,7 +187,13 @@ pub struct ConnectorMiddlewareArgs {
 
     pub delay: u64,
 
+    pub throttle_bytes: u64,
+    pub throttle_requests: u64,
+
     pub metrics: bool,
+
+    /// Trace all accesses slower than this many milliseconds, 0 disables tracing.
+    pub trace_slow: u64,
 }
 
 impl ConnectorMiddlewareArgs {
@@ -139,10 +223,24 @@ impl ConnectorMiddlewareArgs {
         self
     }
 
+    pub fn throttle_bytes(mut self, bytes_per_sec: u64) -> Self {
+        self.throttle_bytes = bytes_per_sec;
+        self
+    }
+    pub fn throttle_requests(mut self, requests_per_sec: u64) -> Self {
+        self.throttle_requests = requests_per_sec;
+        self
+    }
+
     pub fn metrics(mut self, metrics: bool) -> Self {
         self.metrics = metrics;
         self
     }
+
+    pub fn trace_slow(mut self, threshold_ms: u64) -> Self {
+        self.trace_slow = threshold_ms;
+        self
+    }
 }
 
 impl std::str::FromStr for ConnectorMiddlewareArgs {
@@ -208,11 +306,38 @@ impl std::str::FromStr for ConnectorMiddlewareArgs {
                     .log_error("Failed to parse delay configuration")
             })?;
 
+        let throttle_bytes = args
+            .get("throttle_bytes")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse throttle_bytes configuration")
+            })?;
+
+        let throttle_requests = args
+            .get("throttle_requests")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse throttle_requests configuration")
+            })?;
+
         let metrics = args
             .get("metrics")
             .map(|s| s.to_lowercase() == "true" || s == "1")
             .unwrap_or_default();
 
+        let trace_slow = args
+            .get("trace_slow")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse trace_slow configuration")
+            })?;
+
         Ok(Self {
             cache: cache.into(),
             cache_size,
@@ -221,7 +346,12 @@ impl std::str::FromStr for ConnectorMiddlewareArgs {
 
             delay,
 
+            throttle_bytes,
+            throttle_requests,
+
             metrics,
+
+            trace_slow,
         })
     }
 }
@@ -392,6 +522,18 @@ mod tests {
         assert_eq!(args.middleware_args.cache_page_size, 0x1000);
     }
 
+    #[test]
+    pub fn connector_args_trace_slow() {
+        let args: ConnectorArgs = "target::metrics=1,trace_slow=50"
+            .parse()
+            .expect("unable to parse args");
+        assert!(args.middleware_args.metrics);
+        assert_eq!(args.middleware_args.trace_slow, 50);
+
+        let args: ConnectorArgs = "target::trace_slow=abc".parse().unwrap_or_default();
+        assert_eq!(args.middleware_args.trace_slow, 0);
+    }
+
     #[test]
     pub fn connector_args_url() {
         let args: ConnectorArgs = ":device=\"RAWUDP://ip=127.0.0.1:8080\":"
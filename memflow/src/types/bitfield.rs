@@ -0,0 +1,330 @@
+/*!
+Bitfield types for commonly parsed on-target structures.
+
+This module contains types for page table entries, PE characteristics and memory protection
+masks with named accessors, so code parsing these structures does not have to deal with raw bit
+indices. They are used internally by the page walker and can be embedded in user defined
+structures as they all implement `Pod`.
+*/
+
+use super::{Address, PageType};
+use crate::dataview::Pod;
+
+/// A page table entry of the x86 architecture.
+///
+/// The layout is shared between 32-bit PAE and 64-bit page tables. Non-PAE 32-bit page table
+/// entries are a subset of it, the upper half is simply zero.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::types::bitfield::X86Pte;
+///
+/// let pte = X86Pte(0x8000_0000_1234_5067);
+/// assert!(pte.present());
+/// assert!(pte.writeable());
+/// assert!(pte.user());
+/// assert!(pte.nx());
+/// assert_eq!(pte.page_frame(), 0x1234_5000);
+/// ```
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct X86Pte(pub u64);
+
+unsafe impl Pod for X86Pte {}
+
+impl X86Pte {
+    /// Mask of the physical address bits of the entry.
+    pub const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    const fn bit(self, idx: u8) -> bool {
+        (self.0 & (1 << idx)) != 0
+    }
+
+    /// The entry maps a page or references a page table.
+    pub const fn present(self) -> bool {
+        self.bit(0)
+    }
+
+    /// Writes are allowed to the region mapped by the entry.
+    pub const fn writeable(self) -> bool {
+        self.bit(1)
+    }
+
+    /// User mode accesses are allowed to the region mapped by the entry.
+    pub const fn user(self) -> bool {
+        self.bit(2)
+    }
+
+    /// The region mapped by the entry uses write-through caching.
+    pub const fn write_through(self) -> bool {
+        self.bit(3)
+    }
+
+    /// Caching is disabled for the region mapped by the entry.
+    pub const fn cache_disable(self) -> bool {
+        self.bit(4)
+    }
+
+    /// The entry has been used for a translation.
+    pub const fn accessed(self) -> bool {
+        self.bit(5)
+    }
+
+    /// The page mapped by the entry has been written to.
+    ///
+    /// Only valid for entries mapping a page.
+    pub const fn dirty(self) -> bool {
+        self.bit(6)
+    }
+
+    /// The entry maps a large page instead of referencing a page table.
+    ///
+    /// Only valid for entries above the lowest level.
+    pub const fn large_page(self) -> bool {
+        self.bit(7)
+    }
+
+    /// The translation is global and not flushed on address space switches.
+    pub const fn global(self) -> bool {
+        self.bit(8)
+    }
+
+    /// Instruction fetches are not allowed from the region mapped by the entry.
+    pub const fn nx(self) -> bool {
+        self.bit(63)
+    }
+
+    /// Returns the physical address referenced by the entry.
+    ///
+    /// For large pages the low bits of the result are not cleared and have to be masked
+    /// according to the page size.
+    pub const fn page_frame(self) -> u64 {
+        self.0 & Self::ADDRESS_MASK
+    }
+}
+
+impl From<u64> for X86Pte {
+    fn from(pte: u64) -> Self {
+        Self(pte)
+    }
+}
+
+impl From<Address> for X86Pte {
+    fn from(pte: Address) -> Self {
+        Self(pte.to_umem() as u64)
+    }
+}
+
+impl From<X86Pte> for u64 {
+    fn from(pte: X86Pte) -> Self {
+        pte.0
+    }
+}
+
+bitflags! {
+    /// Characteristics of a PE/COFF image, as stored in its file header.
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+    pub struct PeCharacteristics: u16 {
+        /// The image does not contain base relocations and has to be loaded at its preferred base.
+        const RELOCS_STRIPPED = 0x0001;
+        /// The image is valid and can be run.
+        const EXECUTABLE_IMAGE = 0x0002;
+        /// COFF line numbers have been removed.
+        const LINE_NUMS_STRIPPED = 0x0004;
+        /// COFF symbol table entries for local symbols have been removed.
+        const LOCAL_SYMS_STRIPPED = 0x0008;
+        /// Obsolete. Aggressively trim the working set.
+        const AGGRESSIVE_WS_TRIM = 0x0010;
+        /// The application can handle addresses larger than 2 GB.
+        const LARGE_ADDRESS_AWARE = 0x0020;
+        /// Obsolete. Little endian byte order.
+        const BYTES_REVERSED_LO = 0x0080;
+        /// The machine is based on a 32-bit word architecture.
+        const MACHINE_32BIT = 0x0100;
+        /// Debugging information has been removed from the image.
+        const DEBUG_STRIPPED = 0x0200;
+        /// Copy the image to the swap file if it is located on removable media.
+        const REMOVABLE_RUN_FROM_SWAP = 0x0400;
+        /// Copy the image to the swap file if it is located on the network.
+        const NET_RUN_FROM_SWAP = 0x0800;
+        /// The image is a system file, for example a driver.
+        const SYSTEM = 0x1000;
+        /// The image is a dynamic-link library.
+        const DLL = 0x2000;
+        /// The image should only be run on a uniprocessor machine.
+        const UP_SYSTEM_ONLY = 0x4000;
+        /// Obsolete. Big endian byte order.
+        const BYTES_REVERSED_HI = 0x8000;
+    }
+}
+
+unsafe impl Pod for PeCharacteristics {}
+
+bitflags! {
+    /// Characteristics of a section of a PE/COFF image, as stored in its section header.
+    ///
+    /// The section alignment encoded in bits 20 to 23 is not part of these flags.
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+    pub struct PeSectionCharacteristics: u32 {
+        /// The section should not be padded to the next boundary.
+        const TYPE_NO_PAD = 0x0000_0008;
+        /// The section contains executable code.
+        const CNT_CODE = 0x0000_0020;
+        /// The section contains initialized data.
+        const CNT_INITIALIZED_DATA = 0x0000_0040;
+        /// The section contains uninitialized data.
+        const CNT_UNINITIALIZED_DATA = 0x0000_0080;
+        /// The section contains comments or other information.
+        const LNK_INFO = 0x0000_0200;
+        /// The section will not become part of the image.
+        const LNK_REMOVE = 0x0000_0800;
+        /// The section contains COMDAT data.
+        const LNK_COMDAT = 0x0000_1000;
+        /// The section contains data referenced through the global pointer.
+        const GPREL = 0x0000_8000;
+        /// The section contains extended relocations.
+        const LNK_NRELOC_OVFL = 0x0100_0000;
+        /// The section can be discarded as needed.
+        const MEM_DISCARDABLE = 0x0200_0000;
+        /// The section cannot be cached.
+        const MEM_NOT_CACHED = 0x0400_0000;
+        /// The section is not pageable.
+        const MEM_NOT_PAGED = 0x0800_0000;
+        /// The section can be shared in memory.
+        const MEM_SHARED = 0x1000_0000;
+        /// The section can be executed as code.
+        const MEM_EXECUTE = 0x2000_0000;
+        /// The section can be read.
+        const MEM_READ = 0x4000_0000;
+        /// The section can be written to.
+        const MEM_WRITE = 0x8000_0000;
+    }
+}
+
+unsafe impl Pod for PeSectionCharacteristics {}
+
+impl PeSectionCharacteristics {
+    /// Returns the page type of memory mapping the section.
+    pub fn page_type(self) -> PageType {
+        PageType::default()
+            .write(self.contains(Self::MEM_WRITE))
+            .noexec(!self.contains(Self::MEM_EXECUTE))
+    }
+}
+
+bitflags! {
+    /// Memory protection mask of a virtual memory region on Windows (`PAGE_*` constants).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::bitfield::PageProtection;
+    ///
+    /// let prot = PageProtection::EXECUTE_READ | PageProtection::GUARD;
+    /// assert!(prot.readable());
+    /// assert!(!prot.writeable());
+    /// assert!(prot.executable());
+    /// ```
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+    pub struct PageProtection: u32 {
+        /// All access to the region is disabled.
+        const NOACCESS = 0x0001;
+        /// The region can be read.
+        const READONLY = 0x0002;
+        /// The region can be read and written to.
+        const READWRITE = 0x0004;
+        /// The region can be read, writes create a private copy of the page.
+        const WRITECOPY = 0x0008;
+        /// The region can be executed.
+        const EXECUTE = 0x0010;
+        /// The region can be read and executed.
+        const EXECUTE_READ = 0x0020;
+        /// The region can be read, written to and executed.
+        const EXECUTE_READWRITE = 0x0040;
+        /// The region can be read and executed, writes create a private copy of the page.
+        const EXECUTE_WRITECOPY = 0x0080;
+        /// The first access to the region raises a guard page exception.
+        const GUARD = 0x0100;
+        /// The region is not cached.
+        const NOCACHE = 0x0200;
+        /// The region uses write-combined caching.
+        const WRITECOMBINE = 0x0400;
+    }
+}
+
+unsafe impl Pod for PageProtection {}
+
+impl PageProtection {
+    /// Returns true if the region can be read.
+    pub fn readable(self) -> bool {
+        self.intersects(
+            Self::READONLY
+                | Self::READWRITE
+                | Self::WRITECOPY
+                | Self::EXECUTE_READ
+                | Self::EXECUTE_READWRITE
+                | Self::EXECUTE_WRITECOPY,
+        )
+    }
+
+    /// Returns true if the region can be written to, including copy-on-write regions.
+    pub fn writeable(self) -> bool {
+        self.intersects(
+            Self::READWRITE | Self::WRITECOPY | Self::EXECUTE_READWRITE | Self::EXECUTE_WRITECOPY,
+        )
+    }
+
+    /// Returns true if the region can be executed.
+    pub fn executable(self) -> bool {
+        self.intersects(
+            Self::EXECUTE | Self::EXECUTE_READ | Self::EXECUTE_READWRITE | Self::EXECUTE_WRITECOPY,
+        )
+    }
+
+    /// Returns the page type of memory with this protection.
+    pub fn page_type(self) -> PageType {
+        PageType::default()
+            .write(self.writeable())
+            .noexec(!self.executable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pte_flags() {
+        let pte = X86Pte::from(Address::from(0x1234_5083u64));
+        assert!(pte.present());
+        assert!(pte.writeable());
+        assert!(!pte.user());
+        assert!(pte.large_page());
+        assert!(!pte.nx());
+        assert_eq!(pte.page_frame(), 0x1234_5000);
+    }
+
+    #[test]
+    fn section_page_type() {
+        let text = PeSectionCharacteristics::from_bits_truncate(0x6050_0020);
+        assert_eq!(
+            text,
+            PeSectionCharacteristics::CNT_CODE
+                | PeSectionCharacteristics::MEM_EXECUTE
+                | PeSectionCharacteristics::MEM_READ
+        );
+        assert_eq!(text.page_type(), PageType::READ_ONLY | PageType::EXECUTABLE);
+
+        let data = PeSectionCharacteristics::MEM_READ | PeSectionCharacteristics::MEM_WRITE;
+        assert_eq!(data.page_type(), PageType::WRITEABLE | PageType::NOEXEC);
+    }
+}
@@ -3,6 +3,11 @@ pub mod timed_validator;
 
 pub mod count_validator;
 
+pub mod stats;
+
+#[cfg(feature = "std")]
+pub mod persist;
+
 #[cfg(feature = "std")]
 #[doc(hidden)]
 pub use timed_validator::*;
@@ -10,6 +15,10 @@ pub use timed_validator::*;
 #[doc(hidden)]
 pub use count_validator::*;
 
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use persist::*;
+
 #[cfg(feature = "std")]
 pub type DefaultCacheValidator = TimedCacheValidator;
 #[cfg(not(feature = "std"))]
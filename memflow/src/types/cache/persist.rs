@@ -0,0 +1,184 @@
+//! Persistence of cache contents between sessions.
+//!
+//! Caches take a while to warm up, since every page table and every frequently accessed page has
+//! to be fetched from the target first. When a tool is restarted against a target that did not
+//! change in between (a memory snapshot or a paused virtual machine), the previous contents of the
+//! caches are still accurate and can be loaded from disk instead.
+//!
+//! Cache files start with a header recording the kind of cache, a format version, an identity
+//! string of the target and the time the file was written. A file is only loaded if all of them
+//! match the expectations of the caller:
+//!
+//! - The identity is chosen by the caller and should uniquely identify the state of the target,
+//!   e.g. the path and modification time of a snapshot or the id of a paused VM. A mismatch is
+//!   reported as [`ErrorKind::TargetChanged`].
+//! - Files older than the given maximum age are rejected with [`ErrorKind::TargetChanged`] as well.
+//!
+//! Loaded entries are handed to the cache validator like freshly read ones, so they expire
+//! according to the validator of the cache they are loaded into.
+
+use ::std::fs::File;
+use ::std::io::{BufReader, BufWriter, Read, Write};
+use ::std::path::Path;
+use ::std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+/// Version of the cache file format.
+const CACHE_FILE_VERSION: u32 = 1;
+
+/// Validity metadata stored in front of every cache file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheFileHeader {
+    /// Kind of cache stored in the file
+    pub kind: [u8; 4],
+    /// Identity of the target the cache was saved from
+    pub identity: String,
+    /// Unix timestamp of the time the file was written
+    pub created: u64,
+    /// Size of a single entry in bytes
+    pub entry_size: u64,
+    /// Number of entries following the header
+    pub entry_count: u64,
+}
+
+impl CacheFileHeader {
+    /// Creates a header for a file written now.
+    pub fn new(kind: [u8; 4], identity: &str, entry_size: u64, entry_count: u64) -> Self {
+        Self {
+            kind,
+            identity: identity.to_string(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            entry_size,
+            entry_count,
+        }
+    }
+
+    /// Returns the time elapsed since the file was written.
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(self.created))
+            .unwrap_or_default()
+    }
+
+    /// Writes the header to `w`.
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        let identity = self.identity.as_bytes();
+
+        w.write_all(&self.kind)
+            .and_then(|_| w.write_all(&CACHE_FILE_VERSION.to_le_bytes()))
+            .and_then(|_| w.write_all(&(identity.len() as u32).to_le_bytes()))
+            .and_then(|_| w.write_all(identity))
+            .and_then(|_| w.write_all(&self.created.to_le_bytes()))
+            .and_then(|_| w.write_all(&self.entry_size.to_le_bytes()))
+            .and_then(|_| w.write_all(&self.entry_count.to_le_bytes()))
+            .map_err(write_error)
+    }
+
+    /// Reads a header from `r` and checks it against the expected kind, identity and age.
+    pub fn read(
+        r: &mut impl Read,
+        kind: [u8; 4],
+        identity: &str,
+        max_age: Duration,
+    ) -> Result<Self> {
+        let mut file_kind = [0u8; 4];
+        r.read_exact(&mut file_kind).map_err(read_error)?;
+        if file_kind != kind {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::UnableToReadFile)
+                .log_error("file does not contain a cache of the expected kind"));
+        }
+
+        if read_u32(r)? != CACHE_FILE_VERSION {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::VersionMismatch)
+                .log_error("unsupported cache file version"));
+        }
+
+        let mut file_identity = vec![0u8; read_u32(r)? as usize];
+        r.read_exact(&mut file_identity).map_err(read_error)?;
+        if file_identity != identity.as_bytes() {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::TargetChanged)
+                .log_info("cache file was saved from a different target"));
+        }
+
+        let header = Self {
+            kind,
+            identity: identity.to_string(),
+            created: read_u64(r)?,
+            entry_size: read_u64(r)?,
+            entry_count: read_u64(r)?,
+        };
+
+        if header.age() > max_age {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::TargetChanged)
+                .log_info("cache file has expired"));
+        }
+
+        Ok(header)
+    }
+}
+
+/// Creates the cache file at `path` for writing.
+pub fn create_cache_file(path: impl AsRef<Path>) -> Result<BufWriter<File>> {
+    File::create(path).map(BufWriter::new).map_err(write_error)
+}
+
+/// Opens the cache file at `path` for reading.
+pub fn open_cache_file(path: impl AsRef<Path>) -> Result<BufReader<File>> {
+    File::open(path).map(BufReader::new).map_err(read_error)
+}
+
+pub(crate) fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf).map_err(read_error)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf).map_err(read_error)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_error(err: ::std::io::Error) -> Error {
+    Error(ErrorOrigin::Cache, ErrorKind::UnableToReadFile).log_error(err)
+}
+
+pub(crate) fn write_error(err: ::std::io::Error) -> Error {
+    Error(ErrorOrigin::Cache, ErrorKind::UnableToWriteFile).log_error(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let header = CacheFileHeader::new(*b"TEST", "snapshot.raw", 0x1000, 3);
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+
+        let day = Duration::from_secs(86400);
+        assert_eq!(
+            CacheFileHeader::read(&mut buf.as_slice(), *b"TEST", "snapshot.raw", day).unwrap(),
+            header
+        );
+        assert_eq!(
+            CacheFileHeader::read(&mut buf.as_slice(), *b"TEST", "other.raw", day)
+                .unwrap_err()
+                .1,
+            ErrorKind::TargetChanged
+        );
+        assert_eq!(
+            CacheFileHeader::read(&mut buf.as_slice(), *b"PAGE", "snapshot.raw", day)
+                .unwrap_err()
+                .1,
+            ErrorKind::UnableToReadFile
+        );
+    }
+}
@@ -0,0 +1,138 @@
+/*!
+Optional collection of cache statistics.
+
+The collector is disabled by default. Once enabled via [`enable`] the page caches and
+translation caches count their hits and misses. A [`snapshot`] of the counters can be queried at
+any time, e.g. to tune the cache sizes of a connector.
+
+Failures of the individual layers are collected separately by [`error::stats`](crate::error::stats).
+
+The counters are process wide and shared by all caches. While the collector is disabled recording
+a statistic is a single relaxed atomic load.
+
+# Examples
+```
+use memflow::types::cache::stats;
+
+stats::enable();
+
+// ... read memory through a cached connector
+
+if let Some(ratio) = stats::snapshot().page_cache_miss_ratio() {
+    println!("page cache miss ratio: {:.2}", ratio);
+}
+
+stats::disable();
+```
+*/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// targets without 64 bit atomics fall back to pointer sized counters
+#[cfg(target_has_atomic = "64")]
+type Counter = std::sync::atomic::AtomicU64;
+#[cfg(not(target_has_atomic = "64"))]
+type Counter = std::sync::atomic::AtomicUsize;
+
+/// The individual statistics recorded by the collector.
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CacheStat {
+    /// A read could be served from a page cache.
+    PageCacheHit,
+    /// A read had to be fetched from the connector by a page cache.
+    PageCacheMiss,
+    /// A translation could be served from a translation cache.
+    TlbHit,
+    /// A translation had to be resolved by walking the page tables.
+    TlbMiss,
+}
+
+const STAT_COUNT: usize = CacheStat::TlbMiss as usize + 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static COUNTERS: [Counter; STAT_COUNT] = [
+    Counter::new(0),
+    Counter::new(0),
+    Counter::new(0),
+    Counter::new(0),
+];
+
+/// Snapshot of all statistics recorded since the collector was last reset.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CacheStats {
+    pub page_cache_hits: u64,
+    pub page_cache_misses: u64,
+    pub tlb_hits: u64,
+    pub tlb_misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of page cache lookups that missed, if there were any lookups.
+    pub fn page_cache_miss_ratio(&self) -> Option<f64> {
+        miss_ratio(self.page_cache_hits, self.page_cache_misses)
+    }
+
+    /// Returns the fraction of translation cache lookups that missed, if there were any lookups.
+    pub fn tlb_miss_ratio(&self) -> Option<f64> {
+        miss_ratio(self.tlb_hits, self.tlb_misses)
+    }
+}
+
+fn miss_ratio(hits: u64, misses: u64) -> Option<f64> {
+    let total = hits + misses;
+    if total > 0 {
+        Some(misses as f64 / total as f64)
+    } else {
+        None
+    }
+}
+
+/// Enables the collector.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Disables the collector, already recorded statistics are kept.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns true if the collector is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Resets all statistics to zero.
+pub fn reset() {
+    COUNTERS
+        .iter()
+        .for_each(|counter| counter.store(0, Ordering::Relaxed));
+}
+
+/// Returns the current value of a single statistic.
+#[allow(clippy::unnecessary_cast)]
+pub fn get(stat: CacheStat) -> u64 {
+    COUNTERS[stat as usize].load(Ordering::Relaxed) as u64
+}
+
+/// Returns a snapshot of all statistics.
+pub fn snapshot() -> CacheStats {
+    CacheStats {
+        page_cache_hits: get(CacheStat::PageCacheHit),
+        page_cache_misses: get(CacheStat::PageCacheMiss),
+        tlb_hits: get(CacheStat::TlbHit),
+        tlb_misses: get(CacheStat::TlbMiss),
+    }
+}
+
+/// Adds `count` to a statistic if the collector is enabled.
+#[inline]
+#[allow(clippy::unnecessary_cast)]
+pub fn record(stat: CacheStat, count: u64) {
+    if count > 0 && is_enabled() {
+        COUNTERS[stat as usize].fetch_add(count as _, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,173 @@
+/*!
+Integer types with a fixed byte order.
+
+These wrappers store an integer in the byte order of the target and convert it to the byte order
+of the host on access. This allows structs describing on-target data of a specific byte order to
+be declared naturally and read with the existing helpers, without having to swap each field
+manually.
+
+Each wrapper has the same size and alignment as the underlying integer, so the layout of a
+`#[repr(C)]` struct does not change when its fields are replaced by these wrappers.
+
+# Examples
+
+```
+use memflow::prelude::v1::*;
+use memflow::types::endian::{U16Be, U32Be};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod)]
+struct Header {
+    magic: U32Be,
+    version: U16Be,
+    flags: U16Be,
+}
+
+fn read_header(mem: &mut impl MemoryView, addr: Address) -> Result<Header> {
+    mem.read(addr).data()
+}
+# use memflow::dummy::DummyMemory;
+# let mut mem = DummyMemory::new(size::mb(1));
+# mem.phys_write(0x1000.into(), &[0xfeu8, 0xed, 0xfa, 0xce, 0, 2, 0, 1]).unwrap();
+# let header = read_header(&mut mem.phys_view(), 0x1000.into()).unwrap();
+# assert_eq!(header.magic.get(), 0xfeedface);
+# assert_eq!(header.version.get(), 2);
+# assert_eq!(header.flags.get(), 1);
+```
+*/
+
+use crate::dataview::Pod;
+
+use std::fmt;
+
+macro_rules! endian_int {
+    ($name:ident, $int:ty, $from:ident, $to:ident, $order:literal) => {
+        #[doc = concat!("A `", stringify!($int), "` stored in ", $order, " byte order.")]
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Default, Eq, PartialEq, Hash)]
+        pub struct $name($int);
+
+        unsafe impl Pod for $name {}
+
+        impl $name {
+            /// Creates a new value from an integer in host byte order.
+            pub const fn new(value: $int) -> Self {
+                Self(value.$to())
+            }
+
+            /// Returns the value in host byte order.
+            pub const fn get(self) -> $int {
+                <$int>::$from(self.0)
+            }
+
+            /// Sets the value from an integer in host byte order.
+            pub fn set(&mut self, value: $int) {
+                self.0 = value.$to();
+            }
+
+            /// Returns the value as it is stored on the target.
+            pub const fn raw(self) -> $int {
+                self.0
+            }
+        }
+
+        impl From<$int> for $name {
+            fn from(value: $int) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for $int {
+            fn from(value: $name) -> Self {
+                value.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.get(), f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.get(), f)
+            }
+        }
+
+        impl fmt::LowerHex for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::LowerHex::fmt(&self.get(), f)
+            }
+        }
+
+        impl fmt::UpperHex for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::UpperHex::fmt(&self.get(), f)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl ::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                ::serde::Serialize::serialize(&self.get(), serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <$int as ::serde::Deserialize>::deserialize(deserializer).map(Self::new)
+            }
+        }
+    };
+}
+
+endian_int!(U16Be, u16, from_be, to_be, "big endian");
+endian_int!(U32Be, u32, from_be, to_be, "big endian");
+endian_int!(U64Be, u64, from_be, to_be, "big endian");
+endian_int!(I16Be, i16, from_be, to_be, "big endian");
+endian_int!(I32Be, i32, from_be, to_be, "big endian");
+endian_int!(I64Be, i64, from_be, to_be, "big endian");
+
+endian_int!(U16Le, u16, from_le, to_le, "little endian");
+endian_int!(U32Le, u32, from_le, to_le, "little endian");
+endian_int!(U64Le, u64, from_le, to_le, "little endian");
+endian_int!(I16Le, i16, from_le, to_le, "little endian");
+endian_int!(I32Le, i32, from_le, to_le, "little endian");
+endian_int!(I64Le, i64, from_le, to_le, "little endian");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataview::PodMethods;
+
+    #[test]
+    fn byte_order() {
+        let be = U32Be::new(0x1122_3344);
+        let le = U32Le::new(0x1122_3344);
+
+        assert_eq!(be.as_bytes(), &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(le.as_bytes(), &[0x44, 0x33, 0x22, 0x11]);
+        assert_eq!(be.get(), le.get());
+        assert_eq!(u32::from(be), 0x1122_3344);
+        assert_eq!(format!("{:x}", be), "11223344");
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(std::mem::size_of::<U64Be>(), 8);
+        assert_eq!(std::mem::align_of::<U64Be>(), std::mem::align_of::<u64>());
+
+        let mut value = I16Be::default();
+        value.set(-2);
+        assert_eq!(value.get(), -2);
+        assert_eq!(value.raw(), (-2i16).to_be());
+    }
+}
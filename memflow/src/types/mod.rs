@@ -24,6 +24,14 @@ pub use pointer::{Pointer, Pointer32, Pointer64};
 pub mod byte_swap;
 pub use byte_swap::ByteSwap;
 
+pub mod bitfield;
+pub use bitfield::{PageProtection, PeCharacteristics, PeSectionCharacteristics, X86Pte};
+
+pub mod endian;
+pub use endian::{
+    I16Be, I16Le, I32Be, I32Le, I64Be, I64Le, U16Be, U16Le, U32Be, U32Le, U64Be, U64Le,
+};
+
 pub mod cache;
 pub use cache::{CacheValidator, DefaultCacheValidator};
 
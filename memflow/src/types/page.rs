@@ -9,7 +9,7 @@ bitflags! {
     #[repr(transparent)]
     #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
     #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
-    pub struct PageType: u8 {
+    pub struct PageType: u16 {
         /// The page explicitly has no flags.
         const NONE = 0b0000_0000;
         /// The page type is not known.
@@ -22,10 +22,20 @@ bitflags! {
         const READ_ONLY = 0b0000_1000;
         /// The page is not executable.
         const NOEXEC = 0b0001_0000;
+        /// The page is owned by a secure kernel (e.g. VTL1 under Windows VBS) and its contents
+        /// can not be accessed from the normal world.
+        const SECURE = 0b0010_0000;
+        /// The page is accessible from user mode.
+        const USER = 0b0100_0000;
+        /// The page is only accessible from supervisor (kernel) mode.
+        const SUPERVISOR = 0b1000_0000;
+        /// The page is executable.
+        const EXECUTABLE = 0b0001_0000_0000;
     }
 }
 
 impl PageType {
+    /// Marks the page as writeable, or as read only if `flag` is false.
     pub fn write(mut self, flag: bool) -> Self {
         self &= !(PageType::WRITEABLE | PageType::READ_ONLY | PageType::UNKNOWN);
         if flag {
@@ -35,15 +45,37 @@ impl PageType {
         }
     }
 
+    /// Marks the page as non-executable, or as executable if `flag` is false.
     pub fn noexec(mut self, flag: bool) -> Self {
-        self &= !(PageType::NOEXEC);
+        self &= !(PageType::NOEXEC | PageType::EXECUTABLE);
         if flag {
             self | PageType::NOEXEC
+        } else {
+            self | PageType::EXECUTABLE
+        }
+    }
+
+    /// Marks the page as accessible from user mode, or as supervisor only if `flag` is false.
+    pub fn user(mut self, flag: bool) -> Self {
+        self &= !(PageType::USER | PageType::SUPERVISOR);
+        if flag {
+            self | PageType::USER
+        } else {
+            self | PageType::SUPERVISOR
+        }
+    }
+
+    /// Marks the page as owned by a secure kernel, or clears the mark if `flag` is false.
+    pub fn secure(mut self, flag: bool) -> Self {
+        self &= !(PageType::SECURE);
+        if flag {
+            self | PageType::SECURE
         } else {
             self
         }
     }
 
+    /// Marks the page as containing page table entries, or clears the mark if `flag` is false.
     pub fn page_table(mut self, flag: bool) -> Self {
         self &= !(PageType::PAGE_TABLE | PageType::UNKNOWN);
         if flag {
@@ -54,6 +86,45 @@ impl PageType {
     }
 }
 
+impl PageType {
+    /// Flags describing which privilege level is able to access a page and how.
+    pub const ACCESS: PageType = PageType::USER
+        .union(PageType::SUPERVISOR)
+        .union(PageType::EXECUTABLE);
+
+    /// Checks whether this page type is allowed by the given `mask`.
+    ///
+    /// A page type is allowed if all of its flags are contained in the mask. The [`ACCESS`]
+    /// flags are only taken into account if the mask contains at least one of them, so masks
+    /// that predate these flags keep matching the same pages.
+    ///
+    /// [`ACCESS`]: Self::ACCESS
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::PageType;
+    ///
+    /// let code = PageType::READ_ONLY | PageType::USER | PageType::EXECUTABLE;
+    /// let kernel = PageType::READ_ONLY | PageType::SUPERVISOR | PageType::EXECUTABLE;
+    ///
+    /// assert!(code.matches_mask(PageType::READ_ONLY));
+    /// assert!(kernel.matches_mask(PageType::READ_ONLY));
+    ///
+    /// // only executable user pages
+    /// let mask = PageType::READ_ONLY | PageType::WRITEABLE | PageType::USER | PageType::EXECUTABLE;
+    /// assert!(code.matches_mask(mask));
+    /// assert!(!kernel.matches_mask(mask));
+    /// ```
+    pub fn matches_mask(self, mask: PageType) -> bool {
+        if mask.intersects(Self::ACCESS) {
+            mask.contains(self)
+        } else {
+            (mask | Self::ACCESS).contains(self)
+        }
+    }
+}
+
 impl Default for PageType {
     fn default() -> Self {
         PageType::UNKNOWN
@@ -94,3 +165,63 @@ impl Page {
         self.page_base.is_valid() && self.page_size != 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noexec() {
+        let page = PageType::READ_ONLY.noexec(false);
+        assert_eq!(page, PageType::READ_ONLY | PageType::EXECUTABLE);
+
+        let page = page.noexec(true);
+        assert_eq!(page, PageType::READ_ONLY | PageType::NOEXEC);
+
+        assert_eq!(
+            page.noexec(false),
+            PageType::READ_ONLY | PageType::EXECUTABLE
+        );
+    }
+
+    #[test]
+    fn user() {
+        let page = PageType::WRITEABLE.user(true);
+        assert_eq!(page, PageType::WRITEABLE | PageType::USER);
+
+        let page = page.user(false);
+        assert_eq!(page, PageType::WRITEABLE | PageType::SUPERVISOR);
+
+        assert_eq!(page.user(true), PageType::WRITEABLE | PageType::USER);
+    }
+
+    #[test]
+    fn matches_mask() {
+        let user_code = PageType::READ_ONLY | PageType::USER | PageType::EXECUTABLE;
+        let kernel_data = PageType::WRITEABLE | PageType::SUPERVISOR | PageType::NOEXEC;
+
+        // masks without access flags ignore them
+        assert!(user_code.matches_mask(PageType::READ_ONLY));
+        assert!(!user_code.matches_mask(PageType::WRITEABLE));
+        assert!(kernel_data.matches_mask(PageType::WRITEABLE | PageType::NOEXEC));
+        assert!(!kernel_data.matches_mask(PageType::WRITEABLE));
+
+        // masks with access flags require all flags of the page
+        let user = PageType::READ_ONLY
+            | PageType::WRITEABLE
+            | PageType::NOEXEC
+            | PageType::USER
+            | PageType::EXECUTABLE;
+        assert!(user_code.matches_mask(user));
+        assert!(!kernel_data.matches_mask(user));
+        assert!(!user_code.matches_mask(PageType::READ_ONLY | PageType::USER));
+
+        let supervisor = PageType::WRITEABLE | PageType::NOEXEC | PageType::SUPERVISOR;
+        assert!(kernel_data.matches_mask(supervisor));
+        assert!(!user_code.matches_mask(supervisor));
+
+        // pages without access flags match any mask containing their other flags
+        assert!(PageType::READ_ONLY.matches_mask(PageType::READ_ONLY | PageType::USER));
+        assert!(PageType::PAGE_TABLE.matches_mask(PageType::PAGE_TABLE));
+    }
+}